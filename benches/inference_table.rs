@@ -0,0 +1,181 @@
+//! Benchmarks of the inference table, and tests asserting on the
+//! number of heap allocations it performs. These live in their own
+//! target so that the counting allocator they install does not slow
+//! down the rest of the test suite.
+
+#![feature(test)]
+
+extern crate chalk;
+extern crate test;
+
+use chalk::ir::{ApplicationTy, Environment, Parameter, ParameterKind, Substitution, Ty, TypeName,
+                UniverseIndex, SUBST_INLINE_PARAMETERS};
+use chalk::solve::InferenceTable;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use test::Bencher;
+
+/// Wraps the system allocator so that tests can assert on the number
+/// of heap allocations performed by an operation. Counts are kept per
+/// thread, so tests running in parallel do not disturb one another.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = Cell::new(0);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of heap allocations performed by `op` on the
+/// current thread.
+fn count_allocations<R>(op: impl FnOnce() -> R) -> usize {
+    let before = ALLOCATIONS.with(|count| count.get());
+    let result = op();
+    let after = ALLOCATIONS.with(|count| count.get());
+    drop(result);
+    after - before
+}
+
+#[test]
+fn small_substitutions_do_not_allocate() {
+    let allocations = count_allocations(|| {
+        let subst: Substitution = (0..SUBST_INLINE_PARAMETERS)
+            .map(|index| ParameterKind::Ty(Ty::Var(index)))
+            .collect();
+        (subst.clone(), subst)
+    });
+    assert_eq!(allocations, 0);
+
+    // One more parameter than fits inline spills onto the heap, once
+    // for the original and once for the clone.
+    let allocations = count_allocations(|| {
+        let subst: Substitution = (0..SUBST_INLINE_PARAMETERS + 1)
+            .map(|index| ParameterKind::Ty(Ty::Var(index)))
+            .collect();
+        (subst.clone(), subst)
+    });
+    assert_eq!(allocations, 2);
+}
+
+/// Builds `[[...[leaf]...]]`, nested `depth` times.
+fn deep_ty(depth: usize, leaf: Ty) -> Ty {
+    (0..depth).fold(leaf, |ty, _| {
+        Ty::Apply(ApplicationTy::new(TypeName::Slice, vec![ParameterKind::Ty(ty)]))
+    })
+}
+
+/// Builds `(P0, ..., Pn)` from the given parameters.
+fn wide_ty(parameters: Vec<Parameter>) -> Ty {
+    Ty::Apply(ApplicationTy::new(TypeName::Tuple(parameters.len()), parameters))
+}
+
+fn unit_ty() -> Ty {
+    wide_ty(vec![])
+}
+
+#[test]
+fn unify_allocations() {
+    let environment = &Environment::new();
+    let ground = deep_ty(10, unit_ty());
+
+    // Unifying two identical ground types only walks them. (The first
+    // unification warms up the table's internal buffers.)
+    let mut table = InferenceTable::new();
+    table.unify_tys(environment, &ground, &ground).unwrap();
+    let allocations = count_allocations(|| table.unify_tys(environment, &ground, &ground).unwrap());
+    assert_eq!(allocations, 0);
+
+    // Binding a variable to a ground type copies that type once (one
+    // allocation per level of nesting), plus at most one allocation
+    // for the undo log.
+    let mut table = InferenceTable::new();
+    let var = table.new_ty_variable(UniverseIndex::ROOT);
+    let allocations = count_allocations(|| table.unify_tys(environment, &var, &ground).unwrap());
+    assert!(allocations <= 11, "{} allocations", allocations);
+}
+
+/// Stands in for solving one goal of a batch: binds `width` fresh
+/// variables to ground types.
+fn bind_fresh_variables(table: &mut InferenceTable, width: usize) {
+    let environment = &Environment::new();
+    let ground = wide_ty(vec![ParameterKind::Ty(unit_ty()); width]);
+    let vars = wide_ty(
+        (0..width)
+            .map(|_| ParameterKind::Ty(table.new_ty_variable(UniverseIndex::ROOT)))
+            .collect(),
+    );
+    table.unify_tys(environment, &vars, &ground).unwrap();
+}
+
+#[test]
+fn cleared_table_allocations() {
+    let fresh = count_allocations(|| bind_fresh_variables(&mut InferenceTable::new(), 100));
+
+    // A cleared table keeps the storage for its variables, so after a
+    // couple of goals only the types themselves are allocated.
+    let mut table = InferenceTable::new();
+    for _ in 0..2 {
+        table.clear();
+        bind_fresh_variables(&mut table, 100);
+    }
+    let cleared = count_allocations(|| {
+        table.clear();
+        bind_fresh_variables(&mut table, 100)
+    });
+    assert!(cleared < fresh, "{} allocations, {} for a fresh table", cleared, fresh);
+}
+
+#[bench]
+fn unify_deep_tys(b: &mut Bencher) {
+    let environment = &Environment::new();
+    let ty = deep_ty(100, unit_ty());
+    let mut table = InferenceTable::new();
+    b.iter(|| table.unify_tys(environment, &ty, &ty).unwrap());
+}
+
+#[bench]
+fn unify_var_with_deep_ty(b: &mut Bencher) {
+    let environment = &Environment::new();
+    let ty = deep_ty(100, unit_ty());
+    b.iter(|| {
+        let mut table = InferenceTable::new();
+        let var = table.new_ty_variable(UniverseIndex::ROOT);
+        table.unify_tys(environment, &var, &ty).unwrap()
+    });
+}
+
+#[bench]
+fn bind_variables_in_cleared_table(b: &mut Bencher) {
+    let mut table = InferenceTable::new();
+    b.iter(|| {
+        table.clear();
+        bind_fresh_variables(&mut table, 100)
+    });
+}
+
+#[bench]
+fn unify_wide_tys(b: &mut Bencher) {
+    let environment = &Environment::new();
+    let ground = wide_ty(vec![ParameterKind::Ty(unit_ty()); 100]);
+    b.iter(|| {
+        let mut table = InferenceTable::new();
+        let vars = wide_ty(
+            (0..100)
+                .map(|_| ParameterKind::Ty(table.new_ty_variable(UniverseIndex::ROOT)))
+                .collect(),
+        );
+        table.unify_tys(environment, &vars, &ground).unwrap()
+    });
+}
@@ -0,0 +1,135 @@
+//! A store for answers that skips those subsumed by answers it already
+//! holds. The tables of the SLG solver keep their answers in one, and
+//! so can anything else that accumulates canonical answers -- e.g., a
+//! cache of the solutions of root goals -- rather than managing them
+//! in a way of its own.
+
+use fxhash::FxHashMap;
+use std::hash::Hash;
+use std::slice;
+
+/// An answer that can be kept in an `AnswerStore`.
+pub trait StoredAnswer {
+    /// Answers are only compared for subsumption with answers with the
+    /// same key; for the answers of a table, the canonical substitution.
+    type Key: Clone + Eq + Hash;
+
+    fn key(&self) -> &Self::Key;
+
+    /// Whether this answer makes `other`, which has the same key,
+    /// redundant. Should be reflexive and transitive.
+    fn subsumes(&self, other: &Self) -> bool;
+}
+
+/// The answers found so far, in the order they were found. Answers are
+/// identified by their index in that order, which never changes.
+#[derive(Clone, Debug)]
+pub struct AnswerStore<A: StoredAnswer> {
+    answers: Vec<A>,
+
+    /// For each key, the indices of the answers with that key that no
+    /// later answer subsumes. Answers subsumed after they were inserted
+    /// are dropped from here, but kept in `answers`, so that indices
+    /// handed out before remain valid.
+    minimal: FxHashMap<A::Key, Vec<usize>>,
+}
+
+impl<A: StoredAnswer> AnswerStore<A> {
+    pub fn new() -> Self {
+        AnswerStore {
+            answers: Vec::new(),
+            minimal: FxHashMap::default(),
+        }
+    }
+
+    /// Adds `answer`, unless some answer with the same key already
+    /// subsumes it; answers that `answer` subsumes stop being returned
+    /// by `lookup`. Returns true if `answer` was added.
+    pub fn insert(&mut self, answer: A) -> bool {
+        let index = self.answers.len();
+        {
+            let answers = &self.answers;
+            let minimal = self.minimal
+                .entry(answer.key().clone())
+                .or_insert_with(Vec::new);
+            if minimal.iter().any(|&i| answers[i].subsumes(&answer)) {
+                return false;
+            }
+            minimal.retain(|&i| !answer.subsumes(&answers[i]));
+            minimal.push(index);
+        }
+        self.answers.push(answer);
+        true
+    }
+
+    /// The answer with the given index, if there are that many.
+    pub fn get(&self, index: usize) -> Option<&A> {
+        self.answers.get(index)
+    }
+
+    /// Every answer added, in order, including those subsumed since.
+    pub fn answers(&self) -> &[A] {
+        &self.answers
+    }
+
+    pub fn iter(&self) -> slice::Iter<A> {
+        self.answers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.answers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.answers.is_empty()
+    }
+
+    /// Whether any answer with the key `key` was added.
+    pub fn contains_key(&self, key: &A::Key) -> bool {
+        self.minimal.contains_key(key)
+    }
+
+    /// The answers with the key `key` that are not subsumed, in the
+    /// order they were added.
+    pub fn lookup(&self, key: &A::Key) -> Vec<&A> {
+        match self.minimal.get(key) {
+            Some(indices) => indices.iter().map(|&i| &self.answers[i]).collect(),
+            None => vec![],
+        }
+    }
+
+    /// The answers that are not subsumed and whose key generalizes
+    /// `key`, in the order they were added. What it means for one key
+    /// to generalize another is up to `generalizes`, which is called
+    /// with each key of the store and `key`, in that order; typically,
+    /// that some substitution of the variables of the first yields the
+    /// second.
+    pub fn lookup_generalizing(
+        &self,
+        key: &A::Key,
+        mut generalizes: impl FnMut(&A::Key, &A::Key) -> bool,
+    ) -> Vec<&A> {
+        let mut indices: Vec<usize> = self.minimal
+            .iter()
+            .filter(|&(stored_key, _)| generalizes(stored_key, key))
+            .flat_map(|(_, indices)| indices.iter().cloned())
+            .collect();
+        indices.sort();
+        indices.into_iter().map(|i| &self.answers[i]).collect()
+    }
+}
+
+impl<A: StoredAnswer> Default for AnswerStore<A> {
+    fn default() -> Self {
+        AnswerStore::new()
+    }
+}
+
+impl<'a, A: StoredAnswer> IntoIterator for &'a AnswerStore<A> {
+    type Item = &'a A;
+    type IntoIter = slice::Iter<'a, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -1,6 +1,6 @@
 use crate::fallible::Fallible;
 use crate::hh::HhGoal;
-use crate::{DelayedLiteral, ExClause, SimplifiedAnswer};
+use crate::{DelayedLiteral, ExClause, Literal, MixedCyclePolicy, SimplifiedAnswer};
 use std::fmt::Debug;
 use std::hash::Hash;
 
@@ -125,6 +125,37 @@ pub trait ContextOps<C: Context>: Sized + Clone + Debug + AggregateOps<C> {
     /// True if this is a coinductive goal -- e.g., proving an auto trait.
     fn is_coinductive(&self, goal: &C::UCanonicalGoalInEnvironment) -> bool;
 
+    /// How to resolve cycles that mix coinductive and inductive goals.
+    fn mixed_cycle_policy(&self) -> MixedCyclePolicy {
+        MixedCyclePolicy::default()
+    }
+
+    /// True if a table should be considered complete as soon as it
+    /// finds a "trivial" answer -- one with an identity substitution,
+    /// no region constraints and no delayed literals. Such an answer
+    /// subsumes any other answer the table could find, so this only
+    /// affects performance, not which goals are provable; disabling
+    /// it is useful to check that this is so.
+    fn trivial_answer_cut(&self) -> bool {
+        true
+    }
+
+    /// True if the derivation of each answer should be recorded, so
+    /// that it can be retrieved with `Forest::proof_tree`. This costs
+    /// time and memory, so it is only meant for debugging.
+    fn record_proofs(&self) -> bool {
+        false
+    }
+
+    /// The most answers to enumerate for the table with the given
+    /// goal, if limited. Once a table has that many answers, its
+    /// remaining strands are dropped, and it gets one more answer --
+    /// the ambiguous `identity_answer` -- standing for any answers it
+    /// would have found next.
+    fn max_answers(&self, _goal: &C::UCanonicalGoalInEnvironment) -> Option<usize> {
+        None
+    }
+
     /// Create an inference table for processing a new goal and instantiate that goal
     /// in that context, returning "all the pieces".
     ///
@@ -166,6 +197,10 @@ pub trait ContextOps<C: Context>: Sized + Clone + Debug + AggregateOps<C> {
     fn canonical(u_canon: &C::UCanonicalGoalInEnvironment) -> &C::CanonicalGoalInEnvironment;
     fn is_trivial_substitution(u_canon: &C::UCanonicalGoalInEnvironment,
                                canonical_subst: &C::CanonicalConstrainedSubst) -> bool;
+
+    /// The answer to `u_canon` that maps each of its variables to
+    /// itself, with no region constraints: the most general answer.
+    fn identity_answer(u_canon: &C::UCanonicalGoalInEnvironment) -> C::CanonicalConstrainedSubst;
     fn num_universes(&C::UCanonicalGoalInEnvironment) -> usize;
 
     /// Convert a goal G *from* the canonical universes *into* our
@@ -244,12 +279,15 @@ pub trait InferenceTable<C: Context, I: Context>:
     /// goals.
     fn into_hh_goal(&mut self, goal: I::Goal) -> HhGoal<I>;
 
+    /// Extends `env` with `clauses`. Returns `None` if the resulting
+    /// environment would be too large, in which case the goal that
+    /// required it is treated as one we cannot prove.
     // Used by: simplify
     fn add_clauses(
         &mut self,
         env: &I::Environment,
         clauses: I::ProgramClauses,
-    ) -> I::Environment;
+    ) -> Option<I::Environment>;
 }
 
 /// Methods for unifying and manipulating terms and binders.
@@ -311,9 +349,25 @@ pub trait UnificationOps<C: Context, I: Context> {
         b: &I::Parameter,
     ) -> Fallible<I::UnificationResult>;
 
+    // Used by: simplify
+    fn outlives_parameters(
+        &mut self,
+        environment: &I::Environment,
+        a: &I::Parameter,
+        b: &I::Parameter,
+    ) -> Fallible<I::UnificationResult>;
+
     /// Add the residual subgoals as new subgoals of the ex-clause.
     /// Also add region constraints.
     fn into_ex_clause(&mut self, result: I::UnificationResult, ex_clause: &mut ExClause<I>);
+
+    // Used by: logic
+    /// Chooses which of the pending `subgoals` of an ex-clause (of
+    /// which there is at least one) to pursue next, returning its
+    /// index. By default, this is always the last one.
+    fn select_subgoal(&mut self, subgoals: &[Literal<I>]) -> usize {
+        subgoals.len() - 1
+    }
 }
 
 /// "Truncation" (called "abstraction" in the papers referenced below)
@@ -1,10 +1,13 @@
-use crate::{DepthFirstNumber, SimplifiedAnswer, TableIndex};
+use crate::{CannotProveReason, CycleKind, CycleReport, DelayedLiteral, DelayedLiteralSet,
+            DepthFirstNumber, ForestStats, LimitKind, LimitReport, ProofNode, SimplifiedAnswer,
+            TableIndex};
 use crate::context::prelude::*;
 use crate::context::AnswerStream;
 use crate::logic::RootSearchFail;
 use crate::stack::{Stack, StackIndex};
 use crate::tables::Tables;
 use crate::table::{Answer, AnswerIndex};
+use fxhash::FxHashSet;
 
 pub struct Forest<C: Context, CO: ContextOps<C>> {
     #[allow(dead_code)]
@@ -12,6 +15,16 @@ pub struct Forest<C: Context, CO: ContextOps<C>> {
     crate tables: Tables<C>,
     crate stack: Stack,
 
+    /// Cycles encountered so far, in the order they were first
+    /// detected; see `cycles`.
+    cycles: Vec<CycleReport<C>>,
+
+    /// Limits hit so far, in the order they were first hit; see
+    /// `limits`.
+    limits: Vec<LimitReport<C>>,
+
+    crate stats: ForestStats,
+
     dfn: DepthFirstNumber,
 }
 
@@ -21,6 +34,9 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             context,
             tables: Tables::new(),
             stack: Stack::default(),
+            cycles: vec![],
+            limits: vec![],
+            stats: ForestStats::default(),
             dfn: DepthFirstNumber::MIN,
         }
     }
@@ -62,9 +78,15 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     /// iterator. Each time you invoke `next`, it will do the work to
     /// extract one more answer. These answers are cached in between
     /// invocations. Invoking `next` fewer times is preferable =)
+    ///
+    /// `should_continue` is consulted each time a quantum of work is
+    /// exceeded; once it returns false, the iterator stops early and
+    /// sets `cut_short`.
     fn iter_answers<'f>(
         &'f mut self,
         goal: &C::UCanonicalGoalInEnvironment,
+        should_continue: &'f mut dyn FnMut() -> bool,
+        cut_short: &'f mut bool,
     ) -> impl AnswerStream<C> + 'f {
         let table = self.get_or_create_table_for_ucanonical_goal(goal.clone());
         let answer = AnswerIndex::ZERO;
@@ -72,6 +94,25 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             forest: self,
             table,
             answer,
+            should_continue,
+            cut_short,
+        }
+    }
+
+    /// Returns the answers to `goal` one by one, doing the work to find
+    /// each only when it is asked for. Unlike `solve`, the answers are
+    /// not aggregated into a single solution, so this can enumerate
+    /// every way in which the goal holds. As with `solve`, the answers
+    /// found are cached for future attempts.
+    pub fn answers<'f>(
+        &'f mut self,
+        goal: &C::UCanonicalGoalInEnvironment,
+    ) -> ForestAnswers<'f, C, CO> {
+        let table = self.get_or_create_table_for_ucanonical_goal(goal.clone());
+        ForestAnswers {
+            forest: self,
+            table,
+            answer: AnswerIndex::ZERO,
         }
     }
 
@@ -79,7 +120,27 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     /// as much work towards `goal` as it has to (and that works is
     /// cached for future attempts).
     pub fn solve(&mut self, goal: &C::UCanonicalGoalInEnvironment) -> Option<C::Solution> {
-        self.context.clone().make_solution(CO::canonical(&goal), self.iter_answers(goal))
+        self.solve_with_limit(goal, || true).0
+    }
+
+    /// Like `solve`, but consults `should_continue` each time a
+    /// quantum of work is exceeded, and gives up once it returns
+    /// false. Returns the solution along with whether the search was
+    /// cut short. If it was, the solution is based only on the answers
+    /// found up to that point -- in particular, it may be `None` even
+    /// though the goal is provable. The work done so far is kept, so
+    /// solving the goal again picks up where this left off.
+    pub fn solve_with_limit(
+        &mut self,
+        goal: &C::UCanonicalGoalInEnvironment,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> (Option<C::Solution>, bool) {
+        let mut cut_short = false;
+        let solution = self.context.clone().make_solution(
+            CO::canonical(&goal),
+            self.iter_answers(goal, &mut should_continue, &mut cut_short),
+        );
+        (solution, cut_short)
     }
 
     /// True if all the tables on the stack starting from `depth` and
@@ -115,6 +176,180 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         })
     }
 
+    /// Returns the goal of every table created so far, in the order
+    /// the tables were created, along with the answers found for each.
+    /// Together these record what the solver proved along the way,
+    /// which is useful for comparing one run against another.
+    pub fn tables_and_answers(
+        &self,
+    ) -> Vec<(C::UCanonicalGoalInEnvironment, Vec<SimplifiedAnswer<C>>)> {
+        (&self.tables)
+            .into_iter()
+            .map(|table| {
+                let answers = table
+                    .answers()
+                    .iter()
+                    .map(|answer| self.simplified_answer(answer))
+                    .collect();
+                (table.table_goal.clone(), answers)
+            })
+            .collect()
+    }
+
+    /// Returns the goal of every table created so far, in the order
+    /// the tables were created.
+    pub fn table_goals<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = &'a C::UCanonicalGoalInEnvironment> + 'a {
+        (&self.tables).into_iter().map(|table| &table.table_goal)
+    }
+
+    fn simplified_answer(&self, answer: &Answer<C>) -> SimplifiedAnswer<C> {
+        SimplifiedAnswer {
+            subst: answer.subst.clone(),
+            ambiguous: !answer.delayed_literals.is_empty(),
+            cannot_prove: self.cannot_prove_reason(
+                &answer.delayed_literals,
+                &mut FxHashSet::default(),
+            ),
+        }
+    }
+
+    /// Returns the most significant reason that an answer blocked on
+    /// `delayed_literals` cannot be proven. Positive delayed literals
+    /// are followed to the answers they refer to, since those are
+    /// ambiguous in turn; `visited` guards against following a cycle
+    /// of them forever.
+    fn cannot_prove_reason(
+        &self,
+        delayed_literals: &DelayedLiteralSet<C>,
+        visited: &mut FxHashSet<TableIndex>,
+    ) -> Option<CannotProveReason> {
+        let mut reason = None;
+        for delayed_literal in &delayed_literals.delayed_literals {
+            let literal_reason = match delayed_literal {
+                DelayedLiteral::CannotProve(literal_reason) => Some(*literal_reason),
+                DelayedLiteral::Negative(_) => None,
+                DelayedLiteral::Positive(table, subst) => {
+                    if !visited.insert(*table) {
+                        continue;
+                    }
+                    let mut table_reason = None;
+                    for answer in self.tables[*table].answers() {
+                        if answer.subst == *subst {
+                            table_reason = table_reason
+                                .max(self.cannot_prove_reason(&answer.delayed_literals, visited));
+                        }
+                    }
+                    table_reason
+                }
+            };
+            reason = reason.max(literal_reason);
+        }
+        reason
+    }
+
+    /// The derivation of the `answer`th answer to `goal`, if a table
+    /// for `goal` has that many answers. Derivations are only recorded
+    /// while `ContextOps::record_proofs` is set; for answers found
+    /// otherwise, the tree consists of the root alone.
+    pub fn proof_tree(
+        &self,
+        goal: &C::UCanonicalGoalInEnvironment,
+        answer: usize,
+    ) -> Option<ProofNode<C>> {
+        let table = self.tables.index_of(goal)?;
+        self.proof_node(table, answer)
+    }
+
+    /// Each answer only uses answers found before it, so this always
+    /// terminates.
+    fn proof_node(&self, table: TableIndex, answer: usize) -> Option<ProofNode<C>> {
+        let table_ref = &self.tables[table];
+        let simplified = self.simplified_answer(table_ref.answer(AnswerIndex::from(answer))?);
+        let (clause, children) = match table_ref.answer_proof(answer) {
+            Some(proof) => (
+                proof.clause,
+                proof
+                    .subgoal_answers
+                    .iter()
+                    .filter_map(|&(table, answer)| self.proof_node(table, answer))
+                    .collect(),
+            ),
+            None => (None, vec![]),
+        };
+        Some(ProofNode {
+            table_goal: table_ref.table_goal.clone(),
+            answer: simplified,
+            clause,
+            children,
+        })
+    }
+
+    /// Returns the cycles encountered by this forest so far, and
+    /// whether each was treated inductively or coinductively. Since
+    /// work is cached across calls to `solve`, this covers every goal
+    /// solved with this forest. Each distinct cycle is reported once.
+    pub fn cycles(&self) -> &[CycleReport<C>] {
+        &self.cycles
+    }
+
+    /// Returns the limits on the size of terms that were hit by this
+    /// forest so far, and for which tables. Like `cycles`, this covers
+    /// every goal solved with this forest, and each distinct report is
+    /// made once.
+    pub fn limits(&self) -> &[LimitReport<C>] {
+        &self.limits
+    }
+
+    /// Returns statistics about the work done by this forest so far,
+    /// across all calls to `solve`.
+    pub fn stats(&self) -> ForestStats {
+        self.stats
+    }
+
+    /// Records that the table at `depth` was re-entered while still
+    /// on the stack.
+    pub(super) fn record_cycle(&mut self, depth: StackIndex, kind: CycleKind) {
+        let table_goal = &self.tables[self.stack[depth].table].table_goal;
+        if self.cycles
+            .iter()
+            .any(|report| report.kind == kind && report.table_goal == *table_goal)
+        {
+            return;
+        }
+        self.cycles.push(CycleReport {
+            table_goal: table_goal.clone(),
+            kind,
+        });
+    }
+
+    /// Records that the table at `depth` hit a limit.
+    pub(super) fn record_limit(&mut self, depth: StackIndex, kind: LimitKind) {
+        let table_goal = &self.tables[self.stack[depth].table].table_goal;
+        if self.limits
+            .iter()
+            .any(|report| report.kind == kind && report.table_goal == *table_goal)
+        {
+            return;
+        }
+        self.limits.push(LimitReport {
+            table_goal: table_goal.clone(),
+            kind,
+        });
+    }
+
+    /// True if at least one of the tables on the stack starting from
+    /// `depth` is coinductive. If `top_of_stack_is_coinductive_from`
+    /// is false, this indicates that a cycle starting at `depth`
+    /// mixes coinductive and inductive goals.
+    pub(super) fn top_of_stack_is_mixed_from(&self, depth: StackIndex) -> bool {
+        self.stack.top_of_stack_from(depth).any(|d| {
+            let table = self.stack[d].table;
+            self.tables[table].coinductive_goal
+        })
+    }
+
     /// Useful for testing.
     pub fn num_cached_answers_for_goal(&mut self, goal: &C::UCanonicalGoalInEnvironment) -> usize {
         let table = self.get_or_create_table_for_ucanonical_goal(goal.clone());
@@ -122,10 +357,41 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     }
 }
 
+/// The answers to a goal, as returned by `Forest::answers`.
+pub struct ForestAnswers<'forest, C: Context + 'forest, CO: ContextOps<C> + 'forest> {
+    forest: &'forest mut Forest<C, CO>,
+    table: TableIndex,
+    answer: AnswerIndex,
+}
+
+impl<'forest, C, CO: ContextOps<C>> Iterator for ForestAnswers<'forest, C, CO>
+where
+    C: Context,
+{
+    type Item = SimplifiedAnswer<C>;
+
+    fn next(&mut self) -> Option<SimplifiedAnswer<C>> {
+        loop {
+            match self.forest.ensure_root_answer(self.table, self.answer) {
+                Ok(()) => {
+                    let answer = self.forest.answer(self.table, self.answer);
+                    let answer = self.forest.simplified_answer(answer);
+                    self.answer.increment();
+                    return Some(answer);
+                }
+                Err(RootSearchFail::NoMoreSolutions) => return None,
+                Err(RootSearchFail::QuantumExceeded) => continue,
+            }
+        }
+    }
+}
+
 struct ForestSolver<'forest, C: Context + 'forest, CO: ContextOps<C> + 'forest> {
     forest: &'forest mut Forest<C, CO>,
     table: TableIndex,
     answer: AnswerIndex,
+    should_continue: &'forest mut dyn FnMut() -> bool,
+    cut_short: &'forest mut bool,
 }
 
 impl<'forest, C, CO: ContextOps<C>> AnswerStream<C> for ForestSolver<'forest, C, CO>
@@ -133,6 +399,10 @@ where
     C: Context,
 {
     fn peek_answer(&mut self) -> Option<SimplifiedAnswer<C>> {
+        if *self.cut_short {
+            return None;
+        }
+
         loop {
             match self.forest.ensure_root_answer(self.table, self.answer) {
                 Ok(()) => {
@@ -148,19 +418,19 @@ where
                     // when we could have given a concrete yes/no
                     // answer).
 
-                    let simplified_answer = SimplifiedAnswer {
-                        subst: answer.subst.clone(),
-                        ambiguous: !answer.delayed_literals.is_empty(),
-                    };
-
-                    return Some(simplified_answer);
+                    return Some(self.forest.simplified_answer(answer));
                 }
 
                 Err(RootSearchFail::NoMoreSolutions) => {
                     return None;
                 }
 
-                Err(RootSearchFail::QuantumExceeded) => {}
+                Err(RootSearchFail::QuantumExceeded) => {
+                    if !(self.should_continue)() {
+                        *self.cut_short = true;
+                        return None;
+                    }
+                }
             }
         }
     }
@@ -11,6 +11,11 @@ pub enum HhGoal<C: Context> {
     And(C::Goal, C::Goal),
     Not(C::Goal),
     Unify(C::Parameter, C::Parameter),
+
+    /// The first parameter outlives the second, which is a lifetime.
+    /// Like `Unify`, this is left to the inference table, which may
+    /// produce region constraints.
+    Outlives(C::Parameter, C::Parameter),
     DomainGoal(C::DomainGoal),
 
     /// Indicates something that cannot be proven to be true or false
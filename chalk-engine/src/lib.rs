@@ -71,6 +71,7 @@ use fxhash::FxHashSet;
 use std::cmp::min;
 use std::usize;
 
+pub mod answers;
 pub mod context;
 pub mod fallible;
 pub mod forest;
@@ -144,19 +145,157 @@ pub struct SimplifiedAnswer<C: Context> {
     /// either because the size of the answer exceeded `max_size` or
     /// because of a negative loop (e.g., `P :- not { P }`).
     pub ambiguous: bool,
+
+    /// If the answer is ambiguous, the most significant reason that
+    /// it, or an answer it depends on, could not be proven. This is
+    /// `None` if the answer is blocked only on a negative loop.
+    pub cannot_prove: Option<CannotProveReason>,
+}
+
+/// How an answer was derived, as recorded when
+/// `ContextOps::record_proofs` is set: the program clause that the
+/// strand which produced it started from, and the answers to its
+/// positive subgoals that it used. Following those answers' own proofs
+/// in turn yields the derivation tree; see `Forest::proof_tree`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AnswerProof {
+    /// The index of the clause among those that
+    /// `UnificationOps::program_clauses` returned for the goal of the
+    /// table, or `None` if that goal was simplified rather than being
+    /// a domain goal.
+    pub clause: Option<usize>,
+
+    /// The answers to subgoals that were used, as the index of the
+    /// table of the subgoal and of the answer within it, in the order
+    /// they were used. Subgoals that held coinductively, or that were
+    /// negative, used no answer, and so are not included.
+    pub subgoal_answers: Vec<(TableIndex, usize)>,
 }
 
-#[derive(Debug)]
-struct DelayedLiteralSets<C: Context>(InnerDelayedLiteralSets<C>);
+/// A node of the derivation tree of an answer; see `Forest::proof_tree`.
+#[derive(Clone, Debug)]
+pub struct ProofNode<C: Context> {
+    pub table_goal: C::UCanonicalGoalInEnvironment,
+    pub answer: SimplifiedAnswer<C>,
+
+    /// See `AnswerProof::clause`; also `None` if no proof was recorded
+    /// for the answer, e.g. because it stands in for the answers past
+    /// `ContextOps::max_answers`.
+    pub clause: Option<usize>,
+
+    /// The derivations of the answers to subgoals that were used.
+    pub children: Vec<ProofNode<C>>,
+}
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-enum InnerDelayedLiteralSets<C: Context> {
-    /// Corresponds to a single, empty set.
-    None,
+/// Records a cycle that the solver encountered while answering a
+/// query: some table requested an answer from itself while it was
+/// still on the stack. `table_goal` is the goal of the table at the
+/// head of the cycle.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CycleReport<C: Context> {
+    pub table_goal: C::UCanonicalGoalInEnvironment,
+    pub kind: CycleKind,
+}
+
+/// Counts of notable events while solving, for understanding the
+/// solver's performance; see `Forest::stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ForestStats {
+    /// The number of tables that were completed early because they
+    /// found a trivial answer (see `ContextOps::trivial_answer_cut`).
+    pub trivial_answer_cuts: usize,
+
+    /// The number of pending strands discarded by those cuts.
+    pub strands_cut: usize,
+}
+
+/// Records that a limit on the size of terms was hit while working
+/// on the table for `table_goal`, so that some answers to it are
+/// approximations. Raising the limit may make them precise.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LimitReport<C: Context> {
+    pub table_goal: C::UCanonicalGoalInEnvironment,
+    pub kind: LimitKind,
+}
 
-    /// Some (non-zero) number of non-empty sets.
-    /// Must be a set of sets, but HashSets are not Hash so we manually ensure uniqueness.
-    Some(Vec<DelayedLiteralSet<C>>),
+/// Which limit was hit; see `LimitReport`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LimitKind {
+    /// A subgoal of the table was too large (see
+    /// `TruncateOps::truncate_goal`). For a positive subgoal, a
+    /// truncated, more general subgoal was solved instead; a negative
+    /// one could not be solved at all.
+    SubgoalSize,
+
+    /// An answer to the table was too large (see
+    /// `TruncateOps::truncate_answer`), and was replaced with a
+    /// truncated, ambiguous one.
+    AnswerSize,
+}
+
+/// How a cycle was treated by the solver.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CycleKind {
+    /// Every goal participating in the cycle was coinductive (e.g.,
+    /// an auto trait), so the cyclic request was considered true.
+    Coinductive,
+
+    /// At least one goal participating in the cycle was inductive,
+    /// so the cyclic request contributed no answers.
+    Inductive,
+
+    /// The cycle mixed coinductive and inductive goals and, per
+    /// `MixedCyclePolicy::Ambiguous`, the cyclic request was
+    /// considered neither true nor false.
+    Ambiguous,
+}
+
+/// Controls how the solver resolves a cycle that passes through both
+/// coinductive goals (e.g., auto traits) and inductive goals. Cycles
+/// made up entirely of coinductive goals are always accepted, and
+/// cycles made up entirely of inductive goals never are; this policy
+/// only matters for the cases in between.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MixedCyclePolicy {
+    /// Treat the cycle as inductive, so that it contributes no
+    /// answers. Goals that can only be proven via such a cycle are
+    /// rejected. This is the default.
+    Reject,
+
+    /// Treat the cyclic request as ambiguous: it is neither accepted
+    /// nor rejected, and any answer that depends on it is reported as
+    /// ambiguous.
+    Ambiguous,
+
+    /// Experimental: judge the cycle by the goal at its head. If the
+    /// goal that (indirectly) depends on itself is coinductive, the
+    /// cycle is accepted coinductively, even if inductive goals
+    /// appear in between; otherwise it is treated inductively.
+    Stratified,
+}
+
+impl Default for MixedCyclePolicy {
+    fn default() -> Self {
+        MixedCyclePolicy::Reject
+    }
+}
+
+/// Why a `DelayedLiteral::CannotProve` was inserted. Variants are
+/// ordered from least to most significant, so that when an answer
+/// is blocked for several reasons, the maximum is the one to report.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CannotProveReason {
+    /// A cycle mixing coinductive and inductive goals was treated as
+    /// ambiguous, per `MixedCyclePolicy::Ambiguous`.
+    MixedCycle,
+
+    /// A negative goal could not be solved because it still
+    /// contained unresolved inference variables.
+    Floundered,
+
+    /// A goal or answer exceeded the maximum size and had to be
+    /// truncated.
+    Overflow,
 }
 
 /// A set of delayed literals.
@@ -177,8 +316,9 @@ struct DelayedLiteralSet<C: Context> {
 #[derive(Clone, Debug)]
 pub enum DelayedLiteral<C: Context> {
     /// Something which can never be proven nor disproven. Inserted
-    /// when truncation triggers; doesn't arise normally.
-    CannotProve(()),
+    /// when truncation triggers, when a negative goal flounders, or
+    /// for ambiguous mixed cycles; doesn't arise normally.
+    CannotProve(CannotProveReason),
 
     /// We are blocked on a negative literal `~G`, where `G` is the
     /// goal of the given table. Because negative goals must always be
@@ -237,41 +377,6 @@ struct Minimums {
     negative: DepthFirstNumber,
 }
 
-impl<C: Context> DelayedLiteralSets<C> {
-    fn singleton(set: DelayedLiteralSet<C>) -> Self {
-        if set.is_empty() {
-            DelayedLiteralSets(InnerDelayedLiteralSets::None)
-        } else {
-            DelayedLiteralSets(InnerDelayedLiteralSets::Some(vec![set]))
-        }
-    }
-
-    /// Inserts the set if it is minimal in the family.
-    /// Returns true iff the set was inserted.
-    fn insert_if_minimal(&mut self, set: &DelayedLiteralSet<C>) -> bool {
-        match self.0 {
-            // The empty set is always minimal.
-            InnerDelayedLiteralSets::None => false,
-            // Are we inserting an empty set?
-            InnerDelayedLiteralSets::Some(_) if set.is_empty() => {
-                self.0 = InnerDelayedLiteralSets::None;
-                true
-            }
-            InnerDelayedLiteralSets::Some(ref mut sets) => {
-                // Look for a subset.
-                if sets.iter().any(|set| set.is_subset(&set)) {
-                    false
-                } else {
-                    // No subset therefore `set` is minimal, discard supersets and insert.
-                    sets.retain(|set| !set.is_subset(set));
-                    sets.push(set.clone());
-                    true
-                }
-            }
-        }
-    }
-}
-
 impl<C: Context> DelayedLiteralSet<C> {
     fn is_empty(&self) -> bool {
         self.delayed_literals.is_empty()
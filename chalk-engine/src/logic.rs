@@ -1,5 +1,5 @@
-use crate::{DelayedLiteral, DelayedLiteralSet, DepthFirstNumber, ExClause, Literal, Minimums,
-            TableIndex};
+use crate::{AnswerProof, CannotProveReason, CycleKind, DelayedLiteral, DelayedLiteralSet,
+            DepthFirstNumber, ExClause, LimitKind, Literal, Minimums, MixedCyclePolicy, TableIndex};
 use crate::fallible::NoSolution;
 use crate::context::{WithInstantiatedExClause, WithInstantiatedUCanonicalGoal, prelude::*};
 use crate::forest::Forest;
@@ -77,6 +77,10 @@ pub(super) enum StrandFail<C: Context> {
 enum EnsureSuccess {
     AnswerAvailable,
     Coinductive,
+
+    /// A cycle mixing coinductive and inductive goals that, per
+    /// `MixedCyclePolicy::Ambiguous`, is neither true nor false.
+    Ambiguous,
 }
 
 impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
@@ -98,7 +102,9 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
 
             // Things involving cycles should be impossible since our
             // stack was empty on entry:
-            Ok(EnsureSuccess::Coinductive) | Err(RecursiveSearchFail::Cycle(..)) => {
+            Ok(EnsureSuccess::Coinductive)
+            | Ok(EnsureSuccess::Ambiguous)
+            | Err(RecursiveSearchFail::Cycle(..)) => {
                 panic!("ensure_root_answer: nothing on the stack but cyclic result")
             }
         }
@@ -129,6 +135,9 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     ///   request of a coinductive goal and is thus considered true;
     ///   in this case, the answer is not cached in the table (it is
     ///   only true in this cyclic context).
+    /// - `EnsureSuccess::Ambiguous` means that this was a cyclic
+    ///   request that mixed coinductive and inductive goals, and the
+    ///   `MixedCyclePolicy` asked for such cycles to be ambiguous.
     ///
     /// This function first attempts to fetch answer that is cached in
     /// the table. If none is found, then we will if the table is on
@@ -166,9 +175,27 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             info!("ensure_answer: cycle detected at depth {:?}", depth);
 
             if self.top_of_stack_is_coinductive_from(depth) {
+                self.record_cycle(depth, CycleKind::Coinductive);
                 return Ok(EnsureSuccess::Coinductive);
             }
 
+            if self.top_of_stack_is_mixed_from(depth) {
+                match self.context.mixed_cycle_policy() {
+                    MixedCyclePolicy::Reject => {}
+                    MixedCyclePolicy::Ambiguous => {
+                        self.record_cycle(depth, CycleKind::Ambiguous);
+                        return Ok(EnsureSuccess::Ambiguous);
+                    }
+                    MixedCyclePolicy::Stratified => {
+                        if self.tables[table].coinductive_goal {
+                            self.record_cycle(depth, CycleKind::Coinductive);
+                            return Ok(EnsureSuccess::Coinductive);
+                        }
+                    }
+                }
+            }
+
+            self.record_cycle(depth, CycleKind::Inductive);
             return Err(RecursiveSearchFail::Cycle(Minimums {
                 positive: self.stack[depth].dfn,
                 negative: DepthFirstNumber::MAX,
@@ -272,6 +299,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         let CanonicalStrand {
             canonical_ex_clause,
             selected_subgoal,
+            proof,
         } = canonical_strand;
         return context.instantiate_ex_clause(
             num_universes,
@@ -279,6 +307,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             With {
                 op,
                 selected_subgoal: selected_subgoal.clone(),
+                proof: proof.clone(),
                 ops: PhantomData,
             },
         );
@@ -286,6 +315,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         struct With<C: Context, CO: ContextOps<C>, OP: WithInstantiatedStrand<C, CO>> {
             op: OP,
             selected_subgoal: Option<SelectedSubgoal<C>>,
+            proof: Option<AnswerProof>,
             ops: PhantomData<CO>,
         }
 
@@ -302,6 +332,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     infer,
                     ex_clause,
                     selected_subgoal: self.selected_subgoal.clone(),
+                    proof: self.proof,
                 })
             }
         }
@@ -312,19 +343,22 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             infer,
             ex_clause,
             selected_subgoal,
+            proof,
         } = strand;
-        Self::canonicalize_strand_from(&mut *infer, &ex_clause, selected_subgoal)
+        Self::canonicalize_strand_from(&mut *infer, &ex_clause, selected_subgoal, proof)
     }
 
     fn canonicalize_strand_from<I: Context>(
         infer: &mut dyn InferenceTable<C, I>,
         ex_clause: &ExClause<I>,
         selected_subgoal: Option<SelectedSubgoal<C>>,
+        proof: Option<AnswerProof>,
     ) -> CanonicalStrand<C> {
         let canonical_ex_clause = infer.canonicalize_ex_clause(&ex_clause);
         CanonicalStrand {
             canonical_ex_clause,
             selected_subgoal,
+            proof,
         }
     }
 
@@ -381,6 +415,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             let CanonicalStrand {
                 canonical_ex_clause,
                 selected_subgoal,
+                proof: _,
             } = strand;
             let selected_subgoal = selected_subgoal.unwrap_or_else(|| {
                 panic!(
@@ -483,21 +518,21 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 return self.pursue_answer(depth, strand);
             }
 
-            // For now, we always pick the last subgoal in the
-            // list.
-            //
-            // FIXME(rust-lang-nursery/chalk#80) -- we should be more
-            // selective. For example, we don't want to pick a
-            // negative literal that will flounder, and we don't want
-            // to pick things like `?T: Sized` if we can help it.
-            let subgoal_index = strand.ex_clause.subgoals.len() - 1;
+            // The context decides which subgoal to pick; by default,
+            // this is the last one in the list. It may be more
+            // selective (rust-lang-nursery/chalk#80): for example, we
+            // don't want to pick a negative literal that will
+            // flounder, and we don't want to pick things like `?T:
+            // Sized` if we can help it.
+            let subgoal_index = strand.infer.select_subgoal(&strand.ex_clause.subgoals);
 
             // Get or create table for this subgoal.
             match self.get_or_create_table_for_subgoal(
+                depth,
                 &mut *strand.infer,
                 &strand.ex_clause.subgoals[subgoal_index],
             ) {
-                Some((subgoal_table, universe_map)) => {
+                Ok((subgoal_table, universe_map)) => {
                     strand.selected_subgoal = Some(SelectedSubgoal {
                         subgoal_index,
                         subgoal_table,
@@ -506,7 +541,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     });
                 }
 
-                None => {
+                Err(reason) => {
                     // If we failed to create a table for the subgoal,
                     // then the execution has "floundered" (cannot yield
                     // a complete result). We choose to handle this by
@@ -517,7 +552,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     strand
                         .ex_clause
                         .delayed_literals
-                        .push(DelayedLiteral::CannotProve(()));
+                        .push(DelayedLiteral::CannotProve(reason));
                 }
             }
         }
@@ -554,6 +589,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     subgoals,
                 },
             selected_subgoal: _,
+            proof,
         } = strand;
         assert!(subgoals.is_empty());
 
@@ -631,15 +667,47 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         // of proving things from the environment (though the latter
         // is a *bit* suspect; e.g., those things in the environment
         // must be backed by an impl *eventually*).
+        //
+        // The cut can be disabled via `ContextOps::trivial_answer_cut`,
+        // which is useful for checking that it does not change the
+        // results; `ForestStats` records how often it fires.
         let is_trivial_answer = {
-            answer.delayed_literals.is_empty()
+            self.context.trivial_answer_cut()
+                && answer.delayed_literals.is_empty()
                 && CO::is_trivial_substitution(&self.tables[table].table_goal, &answer.subst)
                 && CO::empty_constraints(&answer.subst)
         };
 
-        if self.tables[table].push_answer(answer) {
+        // Past the limit on the number of answers (if any), give up on
+        // enumerating new ones: the ambiguous identity answer subsumes
+        // any answer that is left, so callers depending on those become
+        // ambiguous, rather than missing them.
+        let max_answers = self.context.max_answers(&self.tables[table].table_goal);
+        let past_max_answers = max_answers.map_or(false, |max| {
+            self.tables[table].num_cached_answers() >= max
+                && !self.tables[table].has_answer_with_subst(&answer.subst)
+        });
+        if past_max_answers {
+            let strands = self.tables[table].take_strands();
+            self.stats.strands_cut += strands.len();
+            let mut delayed_literals = FxHashSet::default();
+            delayed_literals.insert(DelayedLiteral::CannotProve(CannotProveReason::Overflow));
+            let answer = Answer {
+                subst: CO::identity_answer(&self.tables[table].table_goal),
+                delayed_literals: DelayedLiteralSet { delayed_literals },
+            };
+            return if self.tables[table].push_answer(answer, None) {
+                Ok(())
+            } else {
+                Err(StrandFail::NoSolution)
+            };
+        }
+
+        if self.tables[table].push_answer(answer, proof) {
             if is_trivial_answer {
-                self.tables[table].take_strands();
+                let strands = self.tables[table].take_strands();
+                self.stats.trivial_answer_cuts += 1;
+                self.stats.strands_cut += strands.len();
             }
 
             Ok(())
@@ -664,15 +732,16 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     /// Resolution* steps.
     fn get_or_create_table_for_subgoal<I: Context>(
         &mut self,
+        depth: StackIndex,
         infer: &mut dyn InferenceTable<C, I>,
         subgoal: &Literal<I>,
-    ) -> Option<(TableIndex, C::UniverseMap)> {
+    ) -> Result<(TableIndex, C::UniverseMap), CannotProveReason> {
         debug_heading!("get_or_create_table_for_subgoal(subgoal={:?})", subgoal);
 
         // Subgoal abstraction:
         let canonical_subgoal = match subgoal {
-            Literal::Positive(subgoal) => self.abstract_positive_literal(infer, subgoal),
-            Literal::Negative(subgoal) => self.abstract_negative_literal(infer, subgoal)?,
+            Literal::Positive(subgoal) => self.abstract_positive_literal(depth, infer, subgoal),
+            Literal::Negative(subgoal) => self.abstract_negative_literal(depth, infer, subgoal)?,
         };
 
         debug!("canonical_subgoal={:?}", canonical_subgoal);
@@ -681,7 +750,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
 
         let table = self.get_or_create_table_for_ucanonical_goal(ucanonical_subgoal);
 
-        Some((table, universe_map))
+        Ok((table, universe_map))
     }
 
     /// Given a u-canonical goal, searches for an existing table. If
@@ -762,11 +831,12 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         environment: I::Environment,
         goal: I::Goal,
     ) {
+        let record_proofs = self.context.record_proofs();
         let table_ref = &mut self.tables[table];
         match infer.into_hh_goal(goal) {
             HhGoal::DomainGoal(domain_goal) => {
                 let clauses = infer.program_clauses(&environment, &domain_goal);
-                for clause in clauses {
+                for (index, clause) in clauses.into_iter().enumerate() {
                     debug!("program clause = {:#?}", clause);
                     if let Ok(resolvent) =
                         infer.resolvent_clause(&environment, &domain_goal, &subst, &clause)
@@ -775,6 +845,14 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                         table_ref.push_strand(CanonicalStrand {
                             canonical_ex_clause: resolvent,
                             selected_subgoal: None,
+                            proof: if record_proofs {
+                                Some(AnswerProof {
+                                    clause: Some(index),
+                                    subgoal_answers: vec![],
+                                })
+                            } else {
+                                None
+                            },
                         });
                     }
                 }
@@ -800,6 +878,11 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                         infer,
                         ex_clause,
                         selected_subgoal: None,
+                        proof: if record_proofs {
+                            Some(AnswerProof::default())
+                        } else {
+                            None
+                        },
                     }));
                 }
             }
@@ -816,6 +899,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     /// This technique is described in the SA paper.
     fn abstract_positive_literal<I: Context>(
         &mut self,
+        depth: StackIndex,
         infer: &mut dyn InferenceTable<C, I>,
         subgoal: &I::GoalInEnvironment,
     ) -> C::CanonicalGoalInEnvironment {
@@ -846,6 +930,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             None => infer.canonicalize_goal(subgoal),
             Some(truncated_subgoal) => {
                 debug!("truncated={:?}", truncated_subgoal);
+                self.record_limit(depth, LimitKind::SubgoalSize);
                 infer.canonicalize_goal(&truncated_subgoal)
             }
         }
@@ -860,9 +945,10 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     /// said to "flounder").
     fn abstract_negative_literal<I: Context>(
         &mut self,
+        depth: StackIndex,
         infer: &mut dyn InferenceTable<C, I>,
         subgoal: &I::GoalInEnvironment,
-    ) -> Option<C::CanonicalGoalInEnvironment> {
+    ) -> Result<C::CanonicalGoalInEnvironment, CannotProveReason> {
         // First, we have to check that the selected negative literal
         // is ground, and invert any universally quantified variables.
         //
@@ -900,7 +986,9 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         // could instead generate an (imprecise) result). As you can
         // see a bit later, we also diverge in some other aspects that
         // affect completeness when it comes to subgoal abstraction.
-        let inverted_subgoal = infer.invert_goal(subgoal)?;
+        let inverted_subgoal = infer
+            .invert_goal(subgoal)
+            .ok_or(CannotProveReason::Floundered)?;
 
         // DIVERGENCE
         //
@@ -954,8 +1042,11 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         // prior paragraph above.) I just didn't feel like dealing
         // with it yet.
         match infer.truncate_goal(&inverted_subgoal) {
-            Some(_) => None,
-            None => Some(infer.canonicalize_goal(&inverted_subgoal)),
+            Some(_) => {
+                self.record_limit(depth, LimitKind::SubgoalSize);
+                Err(CannotProveReason::Overflow)
+            }
+            None => Ok(infer.canonicalize_goal(&inverted_subgoal)),
         }
     }
 
@@ -994,22 +1085,48 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 // recursively requested an answer for itself. That
                 // means that our subgoal is unconditionally true, so
                 // we can drop it and pursue the next thing.
-                assert!(
-                    self.tables[table].coinductive_goal
-                        && self.tables[subgoal_table].coinductive_goal
+                //
+                // (Under `MixedCyclePolicy::Stratified`, the current
+                // table need not itself be coinductive.)
+                assert!(self.tables[subgoal_table].coinductive_goal);
+                let Strand {
+                    infer,
+                    mut ex_clause,
+                    selected_subgoal: _,
+                    proof,
+                } = strand;
+                ex_clause.subgoals.remove(subgoal_index);
+                return self.pursue_strand_recursively(
+                    depth,
+                    Strand {
+                        infer,
+                        ex_clause,
+                        selected_subgoal: None,
+                        proof,
+                    },
                 );
+            }
+            Ok(EnsureSuccess::Ambiguous) => {
+                // A mixed cycle that we were asked to treat as
+                // ambiguous. Drop the subgoal, but record that
+                // whatever answer we produce cannot be proven.
                 let Strand {
                     infer,
                     mut ex_clause,
                     selected_subgoal: _,
+                    proof,
                 } = strand;
                 ex_clause.subgoals.remove(subgoal_index);
+                ex_clause
+                    .delayed_literals
+                    .push(DelayedLiteral::CannotProve(CannotProveReason::MixedCycle));
                 return self.pursue_strand_recursively(
                     depth,
                     Strand {
                         infer,
                         ex_clause,
                         selected_subgoal: None,
+                        proof,
                     },
                 );
             }
@@ -1042,6 +1159,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             infer,
             mut ex_clause,
             selected_subgoal: _,
+            mut proof,
         } = strand;
         let subgoal = match ex_clause.subgoals.remove(subgoal_index) {
             Literal::Positive(g) => g,
@@ -1072,7 +1190,11 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 }
 
                 // Apply answer abstraction.
-                let ex_clause = self.truncate_returned(ex_clause, &mut *infer);
+                let ex_clause = self.truncate_returned(depth, ex_clause, &mut *infer);
+
+                if let Some(proof) = &mut proof {
+                    proof.subgoal_answers.push((subgoal_table, answer_index.value));
+                }
 
                 self.pursue_strand_recursively(
                     depth,
@@ -1080,6 +1202,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                         infer,
                         ex_clause,
                         selected_subgoal: None,
+                        proof,
                     },
                 )
             }
@@ -1098,7 +1221,8 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     /// a positive edge (the SLG POSITIVE RETURN operation). Truncates
     /// the resolvent (or factor) if it has grown too large.
     fn truncate_returned<I: Context>(
-        &self,
+        &mut self,
+        depth: StackIndex,
         ex_clause: ExClause<I>,
         infer: &mut dyn InferenceTable<C, I>,
     ) -> ExClause<I> {
@@ -1128,6 +1252,8 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
 
             // Resolvent got too large. Have to introduce approximation.
             Some(truncated_subst) => {
+                self.record_limit(depth, LimitKind::AnswerSize);
+
                 // DIVERGENCE
                 //
                 // In RR, `self.delayed_literals` would be
@@ -1151,7 +1277,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
 
                 ExClause {
                     subst: truncated_subst,
-                    delayed_literals: vec![DelayedLiteral::CannotProve(())],
+                    delayed_literals: vec![DelayedLiteral::CannotProve(CannotProveReason::Overflow)],
                     constraints: vec![],
                     subgoals: vec![],
                 }
@@ -1185,6 +1311,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             &mut *strand.infer,
             &strand.ex_clause,
             Some(selected_subgoal),
+            strand.proof.clone(),
         ));
     }
 
@@ -1249,6 +1376,13 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 return Err(StrandFail::NoSolution);
             }
 
+            Ok(EnsureSuccess::Ambiguous) => {
+                // A mixed cycle that we were asked to treat as
+                // ambiguous: the subgoal is neither true nor false,
+                // so neither is its negation.
+                delayed_literal = Some(DelayedLiteral::CannotProve(CannotProveReason::MixedCycle));
+            }
+
             Err(RecursiveSearchFail::Cycle(minimums)) => {
                 // We depend on `not(subgoal)`. For us to continue,
                 // `subgoal` must be completely evaluated. Therefore,
@@ -1294,6 +1428,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             infer,
             mut ex_clause,
             selected_subgoal: _,
+            proof,
         } = strand;
         ex_clause.subgoals.remove(selected_subgoal.subgoal_index); // (i)
         ex_clause.delayed_literals.extend(delayed_literal); // (ii)
@@ -1303,6 +1438,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 infer,
                 ex_clause,
                 selected_subgoal: None,
+                proof,
             },
         )
     }
@@ -1,5 +1,5 @@
 use crate::fallible::Fallible;
-use crate::{ExClause, Literal};
+use crate::{CannotProveReason, DelayedLiteral, ExClause, Literal};
 use crate::forest::Forest;
 use crate::hh::HhGoal;
 use crate::context::prelude::*;
@@ -34,10 +34,19 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     let subgoal = infer.instantiate_binders_existentially(&subgoal);
                     pending_goals.push((environment, infer.into_hh_goal(subgoal)))
                 }
-                HhGoal::Implies(wc, subgoal) => {
-                    let new_environment = infer.add_clauses(&environment, wc);
-                    pending_goals.push((new_environment, infer.into_hh_goal(subgoal)));
-                }
+                HhGoal::Implies(wc, subgoal) => match infer.add_clauses(&environment, wc) {
+                    Some(new_environment) => {
+                        pending_goals.push((new_environment, infer.into_hh_goal(subgoal)));
+                    }
+                    None => {
+                        // The environment grew too large; like a
+                        // truncated answer, this leaves us unable to
+                        // say whether `subgoal` holds.
+                        ex_clause
+                            .delayed_literals
+                            .push(DelayedLiteral::CannotProve(CannotProveReason::Overflow));
+                    }
+                },
                 HhGoal::And(subgoal1, subgoal2) => {
                     pending_goals.push((environment.clone(), infer.into_hh_goal(subgoal1)));
                     pending_goals.push((environment, infer.into_hh_goal(subgoal2)));
@@ -51,6 +60,10 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     let result = infer.unify_parameters(&environment, &a, &b)?;
                     infer.into_ex_clause(result, &mut ex_clause)
                 }
+                HhGoal::Outlives(a, b) => {
+                    let result = infer.outlives_parameters(&environment, &a, &b)?;
+                    infer.into_ex_clause(result, &mut ex_clause)
+                }
                 HhGoal::DomainGoal(domain_goal) => {
                     ex_clause
                         .subgoals
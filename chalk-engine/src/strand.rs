@@ -1,5 +1,5 @@
 use std::fmt::{Debug, Error, Formatter};
-use crate::{ExClause, TableIndex};
+use crate::{AnswerProof, ExClause, TableIndex};
 use crate::context::{Context, InferenceTable};
 use crate::table::AnswerIndex;
 
@@ -9,6 +9,10 @@ crate struct CanonicalStrand<C: Context> {
 
     /// Index into `ex_clause.subgoals`.
     crate selected_subgoal: Option<SelectedSubgoal<C>>,
+
+    /// The derivation so far, if recorded; see
+    /// `ContextOps::record_proofs`.
+    crate proof: Option<AnswerProof>,
 }
 
 crate struct Strand<'table, C: Context + 'table, I: Context + 'table> {
@@ -18,6 +22,8 @@ crate struct Strand<'table, C: Context + 'table, I: Context + 'table> {
 
     /// Index into `ex_clause.subgoals`.
     crate selected_subgoal: Option<SelectedSubgoal<C>>,
+
+    crate proof: Option<AnswerProof>,
 }
 
 #[derive(Clone, Debug)]
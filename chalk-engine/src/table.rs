@@ -1,9 +1,8 @@
-use crate::{DelayedLiteralSet, DelayedLiteralSets};
+use crate::{AnswerProof, DelayedLiteralSet};
+use crate::answers::{AnswerStore, StoredAnswer};
 use crate::context::prelude::*;
 use crate::strand::CanonicalStrand;
-use fxhash::FxHashMap;
 use std::collections::VecDeque;
-use std::collections::hash_map::Entry;
 use std::mem;
 
 crate struct Table<C: Context> {
@@ -16,15 +15,15 @@ crate struct Table<C: Context> {
     crate coinductive_goal: bool,
 
     /// Stores the answers that we have found thus far. When we get a request
-    /// for an answer N, we will first check this vector.
-    answers: Vec<Answer<C>>,
+    /// for an answer N, we will first check this store. Answers that
+    /// are subsumed by an answer with the same substitution and fewer
+    /// delayed literals are not added, and are no longer looked up
+    /// once such an answer arrives.
+    answers: AnswerStore<Answer<C>>,
 
-    /// An alternative storage for the answers we have so far, used to
-    /// detect duplicates. Not every answer in `answers` will be
-    /// represented here -- we discard answers from `answers_hash`
-    /// (but not `answers`) when better answers arrive (in particular,
-    /// answers with fewer delayed literals).
-    answers_hash: FxHashMap<C::CanonicalConstrainedSubst, DelayedLiteralSets<C>>,
+    /// How each answer was derived, by the index of the answer, if
+    /// recorded; see `ContextOps::record_proofs`.
+    proofs: Vec<Option<AnswerProof>>,
 
     /// Stores the active strands that we can "pull on" to find more
     /// answers.
@@ -33,7 +32,7 @@ crate struct Table<C: Context> {
 
 index_struct! {
     crate struct AnswerIndex {
-        value: usize,
+        crate value: usize,
     }
 }
 
@@ -52,8 +51,8 @@ impl<C: Context> Table<C> {
         Table {
             table_goal,
             coinductive_goal,
-            answers: Vec::new(),
-            answers_hash: FxHashMap::default(),
+            answers: AnswerStore::new(),
+            proofs: Vec::new(),
             strands: VecDeque::new(),
         }
     }
@@ -83,33 +82,39 @@ impl<C: Context> Table<C> {
     /// an answer B if their substitutions are the same, but A has a subset
     /// of the delayed literals that B does.
     ///
-    /// Returns true if `answer` was added.
-    pub(super) fn push_answer(&mut self, answer: Answer<C>) -> bool {
+    /// Returns true if `answer` was added, in which case `proof` is
+    /// kept as its derivation.
+    pub(super) fn push_answer(&mut self, answer: Answer<C>, proof: Option<AnswerProof>) -> bool {
         debug_heading!("push_answer(answer={:?})", answer);
         debug!(
-            "pre-existing entry: {:?}",
-            self.answers_hash.get(&answer.subst)
+            "pre-existing entries: {:?}",
+            self.answers.lookup(&answer.subst)
         );
 
-        let added = match self.answers_hash.entry(answer.subst.clone()) {
-            Entry::Vacant(entry) => {
-                entry.insert(DelayedLiteralSets::singleton(answer.delayed_literals.clone()));
-                true
-            }
-
-            Entry::Occupied(mut entry) => {
-                entry.get_mut().insert_if_minimal(&answer.delayed_literals)
-            }
-        };
-
         info!(
             "new answer to table with goal {:?}: answer={:?}",
             self.table_goal, answer,
         );
-        if added {
-            self.answers.push(answer);
+        if self.answers.insert(answer) {
+            self.proofs.push(proof);
+            true
+        } else {
+            false
         }
-        added
+    }
+
+    /// How the answer with the given index was derived, if recorded.
+    pub(super) fn answer_proof(&self, index: usize) -> Option<&AnswerProof> {
+        self.proofs.get(index).and_then(|proof| proof.as_ref())
+    }
+
+    pub(super) fn answers(&self) -> &[Answer<C>] {
+        self.answers.answers()
+    }
+
+    /// Whether some answer with the substitution `subst` was found.
+    pub(super) fn has_answer_with_subst(&self, subst: &C::CanonicalConstrainedSubst) -> bool {
+        self.answers.contains_key(subst)
     }
 
     pub(super) fn answer(&self, index: AnswerIndex) -> Option<&Answer<C>> {
@@ -137,3 +142,17 @@ impl<C: Context> Answer<C> {
         self.delayed_literals.is_empty()
     }
 }
+
+/// An answer is better than another with the same substitution if
+/// it has a subset of its delayed literals.
+impl<C: Context> StoredAnswer for Answer<C> {
+    type Key = C::CanonicalConstrainedSubst;
+
+    fn key(&self) -> &Self::Key {
+        &self.subst
+    }
+
+    fn subsumes(&self, other: &Self) -> bool {
+        self.delayed_literals.is_subset(&other.delayed_literals)
+    }
+}
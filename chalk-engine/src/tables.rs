@@ -41,6 +41,15 @@ impl<C: Context> Tables<C> {
     }
 }
 
+impl<'a, C: Context> IntoIterator for &'a Tables<C> {
+    type IntoIter = <&'a Vec<Table<C>> as IntoIterator>::IntoIter;
+    type Item = <&'a Vec<Table<C>> as IntoIterator>::Item;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter(&self.tables)
+    }
+}
+
 impl<C: Context> Index<TableIndex> for Tables<C> {
     type Output = Table<C>;
 
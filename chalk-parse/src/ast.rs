@@ -1,4 +1,4 @@
-use lalrpop_intern::InternedString;
+use lalrpop_intern::{intern, InternedString};
 use std::fmt;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -17,38 +17,136 @@ pub struct Program {
     pub items: Vec<Item>
 }
 
+/// A goal given a name in a goal file, like `goal send_check { Foo: Send }`.
+pub struct NamedGoal {
+    pub name: Identifier,
+    pub goal: Box<Goal>,
+}
+
 pub enum Item {
-    StructDefn(StructDefn),
+    AdtDefn(AdtDefn),
     TraitDefn(TraitDefn),
     Impl(Impl),
+    InherentImpl(InherentImpl),
     Clause(Clause),
+    PredicateDefn(PredicateDefn),
+    OpaqueTyDefn(OpaqueTyDefn),
+    ClosureDefn(ClosureDefn),
 }
 
-pub struct StructDefn {
+/// A struct, enum or union. A struct or union has a single variant,
+/// named after the type itself.
+pub struct AdtDefn {
     pub name: Identifier,
+    pub kind: AdtKind,
     pub parameter_kinds: Vec<ParameterKind>,
+
+    /// The default of each parameter, if any, like `i32` in `struct
+    /// Foo<T = i32>`.
+    pub parameter_defaults: Vec<Option<Ty>>,
     pub where_clauses: Vec<QuantifiedWhereClause>,
+    pub variants: Vec<Variant>,
+    pub flags: AdtFlags,
+    pub attributes: Vec<Attribute>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AdtKind {
+    Struct,
+    Enum,
+    Union,
+}
+
+/// A variant of an enum, like `Some(T)` or `Pair { a: A, b: B }`. The
+/// fields of a tuple variant are named after their positions.
+pub struct Variant {
+    pub name: Identifier,
     pub fields: Vec<Field>,
-    pub flags: StructFlags,
 }
 
-pub struct StructFlags {
+/// An opaque type, e.g. `type Foo<T> = impl Iterator<Item = T> = IntoIter<T>;`:
+/// a type known to implement its bounds, whose definition -- the hidden
+/// type, after the second `=` -- is only visible to goals that can
+/// `Reveal` it.
+pub struct OpaqueTyDefn {
+    pub name: Identifier,
+    pub parameter_kinds: Vec<ParameterKind>,
+    pub bounds: Vec<InlineBound>,
+    pub hidden_ty: Ty,
+}
+
+/// A closure type, e.g. `closure mut c<'a>(i32) -> u32;`, which
+/// implements the `Fn` traits its kind allows for its argument and
+/// return types. Without `-> ..`, the closure returns `()`.
+pub struct ClosureDefn {
+    pub name: Identifier,
+    pub kind: ClosureKind,
+    pub parameter_kinds: Vec<ParameterKind>,
+    pub arg_tys: Vec<Ty>,
+    pub return_ty: Ty,
+}
+
+/// The most demanding `Fn` trait a closure implements: `closure c()`
+/// implements all three, `closure mut c()` only `FnMut` and `FnOnce`
+/// and `closure once c()` only `FnOnce`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClosureKind {
+    Fn,
+    FnMut,
+    FnOnce,
+}
+
+/// A user-defined predicate, e.g. `predicate Reaches(T, U);`, whose
+/// meaning is given entirely by the program's clauses.
+pub struct PredicateDefn {
+    pub name: Identifier,
+    pub parameter_kinds: Vec<ParameterKind>,
+}
+
+pub struct AdtFlags {
     pub external: bool,
+    pub fundamental: bool,
+}
+
+/// An attribute like `#[feature = "x"]`, or `#[hidden]` without a
+/// value. Unlike `#[auto]` and friends, these mean nothing to the
+/// solver; they are kept for its clients.
+pub struct Attribute {
+    pub key: Identifier,
+    pub value: Option<InternedString>,
 }
 
 pub struct TraitDefn {
     pub name: Identifier,
     pub parameter_kinds: Vec<ParameterKind>,
+
+    /// The default of each parameter, if any, like `Self` in `trait
+    /// Bar<T = Self>`. Defaults are allowed on type parameters only.
+    pub parameter_defaults: Vec<Option<Ty>>,
     pub where_clauses: Vec<QuantifiedWhereClause>,
     pub assoc_ty_defns: Vec<AssocTyDefn>,
+    pub assoc_const_defns: Vec<AssocConstDefn>,
     pub flags: TraitFlags,
+    pub attributes: Vec<Attribute>,
+
+    /// For a trait alias like `trait Alias<T> = Foo + Bar<T>;`, the
+    /// bounds it stands for. An alias has no associated items.
+    pub alias: Option<Vec<InlineBound>>,
 }
 
 pub struct TraitFlags {
     pub auto: bool,
     pub marker: bool,
     pub external: bool,
+    pub fundamental: bool,
     pub deref: bool,
+    pub sized: bool,
+    pub fn_trait: bool,
+    pub fn_mut: bool,
+    pub fn_once: bool,
+    pub copy: bool,
+    pub clone: bool,
+    pub unsize: bool,
 }
 
 pub struct AssocTyDefn {
@@ -58,14 +156,28 @@ pub struct AssocTyDefn {
     pub where_clauses: Vec<QuantifiedWhereClause>,
 }
 
+/// An associated const, like `const N: usize;` in a trait. The type is
+/// not kept: consts are integers.
+pub struct AssocConstDefn {
+    pub name: Identifier,
+}
+
 pub enum ParameterKind {
     Ty(Identifier),
     Lifetime(Identifier),
+
+    /// A const parameter, like `const N: usize`. The type is optional,
+    /// as in `forall<const N>`, and is not kept: consts are integers.
+    Const(Identifier),
 }
 
 pub enum Parameter {
     Ty(Ty),
     Lifetime(Lifetime),
+
+    /// A const value, like `3` in `Foo<3>`. A const parameter given as
+    /// an argument, like `N` in `Foo<N>`, is parsed as a `Ty` instead.
+    Const(usize),
 }
 
 /// An inline bound, e.g. `: Foo<K>` in `impl<K, T: Foo<K>> SomeType<T>`.
@@ -94,6 +206,7 @@ pub struct ProjectionEqBound {
 pub enum Kind {
     Ty,
     Lifetime,
+    Const,
 }
 
 impl fmt::Display for Kind {
@@ -102,6 +215,7 @@ impl fmt::Display for Kind {
             match *self {
                 Kind::Ty => "type",
                 Kind::Lifetime => "lifetime",
+                Kind::Const => "const",
             }
         )
     }
@@ -116,6 +230,7 @@ impl Kinded for ParameterKind {
         match *self {
             ParameterKind::Ty(_) => Kind::Ty,
             ParameterKind::Lifetime(_) => Kind::Lifetime,
+            ParameterKind::Const(_) => Kind::Const,
         }
     }
 }
@@ -125,6 +240,7 @@ impl Kinded for Parameter {
         match *self {
             Parameter::Ty(_) => Kind::Ty,
             Parameter::Lifetime(_) => Kind::Lifetime,
+            Parameter::Const(_) => Kind::Const,
         }
     }
 }
@@ -134,12 +250,44 @@ pub struct Impl {
     pub trait_ref: PolarizedTraitRef,
     pub where_clauses: Vec<QuantifiedWhereClause>,
     pub assoc_ty_values: Vec<AssocTyValue>,
+    pub assoc_const_values: Vec<AssocConstValue>,
+
+    /// Whether this is a `default impl`, all of whose associated type
+    /// values are `default`.
+    pub default: bool,
+    pub attributes: Vec<Attribute>,
+}
+
+/// An impl without a trait, like `impl<T> Foo<T> { type Bar = T; }`,
+/// whose associated types can be named as `Foo<T>::Bar`.
+pub struct InherentImpl {
+    pub parameter_kinds: Vec<ParameterKind>,
+    pub type_name: Identifier,
+    pub args: Vec<Parameter>,
+    pub where_clauses: Vec<QuantifiedWhereClause>,
+    pub assoc_ty_values: Vec<AssocTyValue>,
+
+    /// Whether this was written `default impl`, which lowering rejects:
+    /// only trait impls may be `default`.
+    pub default: bool,
+    pub attributes: Vec<Attribute>,
 }
 
 pub struct AssocTyValue {
     pub name: Identifier,
     pub parameter_kinds: Vec<ParameterKind>,
     pub value: Ty,
+
+    /// Whether the value is marked `default`, so that impls
+    /// specializing this one may override it.
+    pub default: bool,
+}
+
+/// The value of an associated const in an impl, like `const N: usize = 3;`.
+/// The value is a const: either a number or a const parameter of the impl.
+pub struct AssocConstValue {
+    pub name: Identifier,
+    pub value: Parameter,
 }
 
 pub enum Ty {
@@ -159,7 +307,136 @@ pub enum Ty {
     ForAll {
         lifetime_names: Vec<Identifier>,
         ty: Box<Ty>
+    },
+
+    /// A trait object type, like `dyn Foo<T> + Send + 'a`.
+    Dyn {
+        bounds: Vec<DynBound>,
+    },
+
+    /// A function pointer type, like `fn(A, B) -> C`; the return type
+    /// is `()` if omitted.
+    FnPtr {
+        arg_tys: Vec<Ty>,
+        return_ty: Box<Ty>,
+    },
+
+    /// A built-in scalar type, like `i32` or `bool`.
+    Scalar {
+        ty: ScalarType,
+    },
+
+    /// The never type, `!`.
+    Never,
+
+    /// A reference type, like `&'a T` or `&'a mut T`.
+    Ref {
+        lifetime: Lifetime,
+        mutable: bool,
+        ty: Box<Ty>,
+    },
+
+    /// A raw pointer type, like `*const T` or `*mut T`.
+    RawPtr {
+        mutable: bool,
+        ty: Box<Ty>,
+    },
+
+    /// A slice type, like `[T]`.
+    Slice {
+        ty: Box<Ty>,
+    },
+
+    /// An array type, like `[T; 3]`.
+    Array {
+        ty: Box<Ty>,
+        len: usize,
+    },
+
+    /// A tuple type, like `(A, B)`, `(A,)` or `()`.
+    Tuple {
+        types: Vec<Ty>,
+    },
+
+    /// `_`, a type for the solver to infer; only allowed in goals.
+    Hole,
+}
+
+/// The built-in scalar types. Their names are reserved: a type written
+/// `i32` is always the scalar, and no item may be declared with one of
+/// these names.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ScalarType {
+    Bool,
+    Char,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    pub const ALL: [ScalarType; 16] = [
+        ScalarType::Bool,
+        ScalarType::Char,
+        ScalarType::I8,
+        ScalarType::I16,
+        ScalarType::I32,
+        ScalarType::I64,
+        ScalarType::I128,
+        ScalarType::Isize,
+        ScalarType::U8,
+        ScalarType::U16,
+        ScalarType::U32,
+        ScalarType::U64,
+        ScalarType::U128,
+        ScalarType::Usize,
+        ScalarType::F32,
+        ScalarType::F64,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScalarType::Bool => "bool",
+            ScalarType::Char => "char",
+            ScalarType::I8 => "i8",
+            ScalarType::I16 => "i16",
+            ScalarType::I32 => "i32",
+            ScalarType::I64 => "i64",
+            ScalarType::I128 => "i128",
+            ScalarType::Isize => "isize",
+            ScalarType::U8 => "u8",
+            ScalarType::U16 => "u16",
+            ScalarType::U32 => "u32",
+            ScalarType::U64 => "u64",
+            ScalarType::U128 => "u128",
+            ScalarType::Usize => "usize",
+            ScalarType::F32 => "f32",
+            ScalarType::F64 => "f64",
+        }
     }
+
+    /// The scalar type called `name`, if any.
+    pub fn from_name(name: InternedString) -> Option<ScalarType> {
+        ScalarType::ALL.iter().cloned().find(|ty| intern(ty.name()) == name)
+    }
+}
+
+/// One of the bounds of a trait object type: a trait, or the
+/// lifetime the object is bounded by.
+pub enum DynBound {
+    Trait(TraitBound),
+    Lifetime(Lifetime),
 }
 
 pub enum Lifetime {
@@ -207,16 +484,24 @@ pub struct Identifier {
 
 pub enum WhereClause {
     Implemented { trait_ref: TraitRef },
+    /// `F: Fn(A, B) -> R`, sugar for `F: Fn<(A, B)>` and
+    /// `<F as FnOnce<(A, B)>>::Output = R`; `R` is `()` if omitted.
+    FnBound { ty: Ty, trait_name: Identifier, arg_tys: Vec<Ty>, output: Ty },
     Normalize { projection: ProjectionTy, ty: Ty },
+    NormalizeConst { projection: ProjectionTy, value: Parameter },
     ProjectionEq { projection: ProjectionTy, ty: Ty },
     TyWellFormed { ty: Ty },
     TraitRefWellFormed { trait_ref: TraitRef },
     TyFromEnv { ty: Ty },
     TraitRefFromEnv { trait_ref: TraitRef },
+    NotImplemented { trait_ref: TraitRef },
     UnifyTys { a: Ty, b: Ty },
     UnifyLifetimes { a: Lifetime, b: Lifetime },
     TraitInScope { trait_name: Identifier },
     Derefs { source: Ty, target: Ty },
+    Reveal,
+    Outlives { a: Parameter, b: Lifetime },
+    Predicate { name: Identifier, args: Vec<Parameter> },
 }
 
 pub struct QuantifiedWhereClause {
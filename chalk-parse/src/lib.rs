@@ -13,6 +13,7 @@ pub mod errors;
 mod parser;
 
 use errors::Result;
+use lalrpop_intern::intern;
 use lalrpop_util::ParseError;
 use std::fmt::Write;
 
@@ -30,6 +31,49 @@ pub fn parse_ty(text: &str) -> Result<ast::Ty> {
     }
 }
 
+/// Parses a file of named goals, like
+///
+/// ```text
+/// goal send_check { Foo: Send }
+/// goal clone_check { forall<T> { if (T: Clone) { Vec<T>: Clone } } }
+/// ```
+///
+/// Goal names must be unique within the file.
+pub fn parse_goals(text: &str) -> Result<Vec<ast::NamedGoal>> {
+    let goals = match parser::parse_Goals(text) {
+        Ok(v) => v,
+        Err(e) => bail!("parse error: {:?}", e),
+    };
+    for (index, goal) in goals.iter().enumerate() {
+        if goals[..index].iter().any(|other| other.name.str == goal.name.str) {
+            bail!("duplicate goal name `{}`", goal.name.str);
+        }
+    }
+    Ok(goals)
+}
+
+/// Picks the goals with the given names out of `goals`, in the order
+/// the names are given. With no names, every goal is picked.
+pub fn select_goals<'g>(
+    goals: &'g [ast::NamedGoal],
+    names: &[&str],
+) -> Result<Vec<&'g ast::NamedGoal>> {
+    if names.is_empty() {
+        return Ok(goals.iter().collect());
+    }
+
+    names
+        .iter()
+        .map(|&name| {
+            let name_str = intern(name);
+            match goals.iter().find(|goal| goal.name.str == name_str) {
+                Some(goal) => Ok(goal),
+                None => bail!("no goal named `{}`", name),
+            }
+        })
+        .collect()
+}
+
 pub fn parse_goal(text: &str) -> Result<Box<ast::Goal>> {
     match parser::parse_Goal(text) {
         Ok(v) => Ok(v),
@@ -0,0 +1,195 @@
+//! A stable facade over the crate, for clients that load programs,
+//! solve goals and report errors, but would rather not depend on the
+//! internal representations in `ir` and `solve`, which change freely.
+//! Programs and goals go in as text, in the surface syntax, and
+//! solutions and diagnostics come out as plain data and strings.
+//!
+//! The types here only change incompatibly along with `API_VERSION`.
+
+use chalk_parse;
+use errors;
+use ir;
+use ir::lowering::{LowerGoal, LowerProgram, PeeledBinderNames};
+use ir::PlaceholderNames;
+use solve::{Guidance, Solution, SolveOptions, SolverChoice};
+use std::fmt;
+use std::sync::Arc;
+
+mod test;
+
+pub use ir::PlaceholderStyle;
+
+/// The version of this facade, bumped on every incompatible change.
+pub const API_VERSION: u32 = 2;
+
+/// The solver to use for a program.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SolverKind {
+    /// The SLG solver, truncating goals larger than `max_size`.
+    Slg { max_size: usize },
+
+    /// The naive solver, giving up below `max_depth` nested subgoals.
+    Naive { max_depth: usize },
+}
+
+impl SolverKind {
+    fn solver_choice(self) -> SolverChoice {
+        match self {
+            SolverKind::Slg { max_size } => SolverChoice::slg().with_max_size(max_size),
+            SolverKind::Naive { max_depth } => SolverChoice::naive().with_max_size(max_depth),
+        }
+    }
+}
+
+impl Default for SolverKind {
+    fn default() -> Self {
+        SolverKind::Slg { max_size: 10 }
+    }
+}
+
+/// A program that has been parsed, lowered and checked for coherence
+/// and well-formedness, ready to solve goals against.
+pub struct Program {
+    program: Arc<ir::Program>,
+    env: Arc<ir::ProgramEnvironment>,
+    solver: SolverKind,
+    placeholders: PlaceholderStyle,
+}
+
+impl Program {
+    /// Loads the program `text`, whose goals are to be solved with
+    /// `solver`.
+    pub fn load(text: &str, solver: SolverKind) -> Result<Program, Diagnostic> {
+        let program = chalk_parse::parse_program(text)
+            .map_err(errors::Error::from)
+            .and_then(|program| program.lower(solver.solver_choice()))?;
+        let program = Arc::new(program);
+        let env = program.environment();
+        Ok(Program {
+            program,
+            env,
+            solver,
+            placeholders: PlaceholderStyle::default(),
+        })
+    }
+
+    /// Sets how answers render the placeholders standing for the
+    /// variables of `forall`s; by default, they are given the names of
+    /// those variables.
+    pub fn set_placeholder_style(&mut self, style: PlaceholderStyle) {
+        self.placeholders = style;
+    }
+
+    /// Solves the goal `text`. Variables introduced by `forall` at the
+    /// outermost level stand for arbitrary types or lifetimes, and the
+    /// answer gives values for those introduced by `exists`.
+    pub fn solve(&self, text: &str) -> Result<Answer, Diagnostic> {
+        ir::tls::set_current_program(&self.program, || {
+            let goal = chalk_parse::parse_goal(text).map_err(errors::Error::from)?;
+            let binder_names = goal.peeled_binder_names();
+            let goal = goal.lower(&*self.program)?;
+            let (goal, names) = goal.into_peeled_goal_with_names(binder_names);
+            let names = names.with_style(self.placeholders);
+            let solution = self.solver
+                .solver_choice()
+                .solve_root_goal(&self.env, &goal, SolveOptions::default())?
+                .solution;
+            Ok(Answer::from_solution(solution, &names))
+        })
+    }
+}
+
+/// Whether a goal holds; see `Answer`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The goal holds, and the values of its variables are known.
+    Proven,
+
+    /// The goal may hold, but the solver could not tell.
+    Ambiguous,
+
+    /// The goal cannot hold.
+    Disproven,
+}
+
+/// The result of solving a goal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Answer {
+    pub outcome: Outcome,
+
+    /// The values of the variables of the goal, in the order they were
+    /// introduced, with placeholders rendered as configured by
+    /// `Program::set_placeholder_style`. For an ambiguous goal, these are only given when the
+    /// solver has some guidance to offer; otherwise, and for a goal
+    /// that does not hold, this is empty.
+    pub values: Vec<String>,
+
+    /// A description of the answer, for display to users. Its format
+    /// is not covered by `API_VERSION`.
+    pub description: String,
+}
+
+impl Answer {
+    fn from_solution(solution: Option<Solution>, names: &PlaceholderNames) -> Self {
+        let solution = match solution {
+            Some(solution) => solution,
+            None => {
+                return Answer {
+                    outcome: Outcome::Disproven,
+                    values: vec![],
+                    description: "No possible solution".to_string(),
+                }
+            }
+        };
+
+        let (outcome, parameters) = match &solution {
+            Solution::Unique(constrained) => {
+                (Outcome::Proven, &constrained.value.subst.parameters[..])
+            }
+            Solution::Ambig(Guidance::Definite(subst), _)
+            | Solution::Ambig(Guidance::Suggested(subst), _) => {
+                (Outcome::Ambiguous, &subst.value.parameters[..])
+            }
+            Solution::Ambig(Guidance::Unknown, _) | Solution::MultipleSolutions(_) => {
+                (Outcome::Ambiguous, &[][..])
+            }
+        };
+        Answer {
+            outcome,
+            values: parameters
+                .iter()
+                .map(|p| format!("{:?}", names.named(p)))
+                .collect(),
+            description: solution.display_with_names(names),
+        }
+    }
+}
+
+/// An error in loading a program or solving a goal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+
+    /// What led to the error, if known, from the most to the least
+    /// direct cause.
+    pub causes: Vec<String>,
+}
+
+impl From<errors::Error> for Diagnostic {
+    fn from(error: errors::Error) -> Self {
+        Diagnostic {
+            message: error.to_string(),
+            causes: error.iter().skip(1).map(|cause| cause.to_string()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.message)?;
+        for cause in &self.causes {
+            write!(fmt, "\ncaused by: {}", cause)?;
+        }
+        Ok(())
+    }
+}
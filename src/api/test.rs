@@ -0,0 +1,61 @@
+#![cfg(test)]
+
+use super::*;
+
+#[test]
+fn load_and_solve() {
+    let program = Program::load(
+        "trait Clone { }
+         struct Foo { }
+         struct Vec<T> { }
+         impl Clone for Foo { }
+         impl<T> Clone for Vec<T> where T: Clone { }",
+        SolverKind::default(),
+    ).unwrap();
+
+    let answer = program.solve("exists<T> { Vec<T>: Clone, T = Foo }").unwrap();
+    assert_eq!(answer.outcome, Outcome::Proven);
+    assert_eq!(answer.values, vec!["Foo"]);
+
+    let answer = program.solve("exists<T> { T: Clone }").unwrap();
+    assert_eq!(answer.outcome, Outcome::Ambiguous);
+
+    let answer = program.solve("forall<T> { Vec<T>: Clone }").unwrap();
+    assert_eq!(answer.outcome, Outcome::Disproven);
+    assert_eq!(answer.description, "No possible solution");
+
+    let diagnostic = program.solve("Bar: Clone").unwrap_err();
+    assert_eq!(diagnostic.message, "invalid type name `Bar`");
+}
+
+#[test]
+fn placeholder_styles() {
+    let mut program = Program::load("struct Foo { }", SolverKind::default()).unwrap();
+
+    let goal = "forall<I> { exists<T> { T = I } }";
+    let answer = program.solve(goal).unwrap();
+    assert_eq!(answer.values, vec!["I"]);
+
+    program.set_placeholder_style(PlaceholderStyle::NameAndUniverse);
+    let answer = program.solve(goal).unwrap();
+    assert_eq!(answer.values, vec!["I/U1"]);
+
+    program.set_placeholder_style(PlaceholderStyle::Universe);
+    let answer = program.solve(goal).unwrap();
+    assert_eq!(answer.values, vec!["!1"]);
+}
+
+#[test]
+fn diagnostics() {
+    let diagnostic = Program::load(
+        "trait Foo { }
+         struct Bar { }
+         impl Foo for Bar { }
+         impl Foo for Bar { }",
+        SolverKind::Naive { max_depth: 10 },
+    ).err()
+        .unwrap();
+    assert_eq!(diagnostic.message, "overlapping impls of trait \"Foo\"");
+    assert_eq!(diagnostic.causes.len(), 1);
+    assert!(diagnostic.to_string().contains("\ncaused by: the impl headers unify"));
+}
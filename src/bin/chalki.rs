@@ -9,14 +9,17 @@ extern crate serde_derive;
 #[macro_use]
 extern crate error_chain;
 
+use std::cell::RefCell;
 use std::io::Read;
-use std::fs::File;
+use std::fs::{self, File};
 use std::sync::Arc;
 use std::process::exit;
 
 use chalk::ir;
 use chalk::ir::lowering::*;
-use chalk::solve::SolverChoice;
+use chalk_parse::ast;
+use chalk::solve::{AggregationPolicy, ClauseOrder, MixedCyclePolicy, QueryLevel, QueryLog,
+                   SolveOptions, Solver, SolverChoice, SubgoalOrder};
 use docopt::Docopt;
 use rustyline::error::ReadlineError;
 
@@ -31,8 +34,31 @@ Options:
   --help              Show this screen.
   --program=PATH      Specifies the path to the `.chalk` file containing traits/impls.
   --goal=GOAL         Specifies a goal to evaluate (may be given more than once).
+  --goals=PATH        Specifies the path to a file of named goals to evaluate.
+  --run=NAME          Evaluates only the named goal from `--goals` (may be given more than once).
   --overflow-depth=N  Specifies the overflow depth [default: 10].
+  --mixed-cycles=P    How to resolve cycles mixing auto traits and other goals:
+                      reject, ambiguous or stratified [default: reject].
   --no-cache          Disable caching.
+  --naive             Use the naive solver, which does plain SLD resolution without tabling,
+                      up to the overflow depth.
+  --erase-regions     Consider all lifetimes equal, skipping region constraints.
+  --max-env-clauses=N Specifies the maximum number of hypotheses in an environment [default: 100].
+  --no-trivial-cut    Keep searching for answers after finding one that subsumes all others.
+  --program-first     Try program clauses before hypotheses from the environment.
+  --reverse-impls     Try program clauses, including impls, in reverse declaration order.
+  --aggregation=A     How to combine several answers into a solution: anti-unify,
+                      first or all [default: anti-unify].
+  --max-answers=N     Specifies the maximum number of answers for `--aggregation=all` [default: 10].
+  --plan-subgoals     Work on the subgoal that looks cheapest first, rather than the last one.
+  --implied-bounds    Assume that the types mentioned by hypotheses are well-formed.
+  --log-queries       Log the queries solved for each goal and report those solved repeatedly.
+  --max-query-repeats=N
+                      Fail once some query has been solved more than N times.
+  --facts=PATH        Writes the trait implementations proven while evaluating the goals
+                      to PATH, as Datalog facts.
+  --placeholders=S    How to display the placeholders of `forall` binders: universe (`!1`),
+                      name (`T`) or name-universe (`T/U1`) [default: name].
 ";
 
 /// This struct represents the various command line options available.
@@ -40,8 +66,25 @@ Options:
 struct Args {
     flag_program: Option<String>,
     flag_goal: Vec<String>,
+    flag_goals: Option<String>,
+    flag_run: Vec<String>,
     flag_overflow_depth: usize,
+    flag_mixed_cycles: String,
     flag_no_cache: bool,
+    flag_naive: bool,
+    flag_erase_regions: bool,
+    flag_max_env_clauses: usize,
+    flag_no_trivial_cut: bool,
+    flag_program_first: bool,
+    flag_reverse_impls: bool,
+    flag_aggregation: String,
+    flag_max_answers: usize,
+    flag_plan_subgoals: bool,
+    flag_implied_bounds: bool,
+    flag_log_queries: bool,
+    flag_max_query_repeats: Option<usize>,
+    flag_facts: Option<String>,
+    flag_placeholders: String,
 }
 
 error_chain! {
@@ -61,6 +104,13 @@ struct Program {
     text: String,
     ir: Arc<ir::Program>,
     env: Arc<ir::ProgramEnvironment>,
+
+    /// Shared between goals so that work is reused, unless caching
+    /// was disabled.
+    solver: Option<RefCell<Solver>>,
+
+    /// Records the queries solved for all goals, if requested.
+    query_log: Option<QueryLog>,
 }
 
 impl Program {
@@ -68,10 +118,26 @@ impl Program {
     /// a [`SolverChoice`].
     ///
     /// [`SolverChoice`]: struct.solve.SolverChoice.html
-    fn new(text: String, solver_choice: SolverChoice) -> Result<Program> {
+    fn new(
+        text: String,
+        solver_choice: SolverChoice,
+        cache: bool,
+        query_log: Option<QueryLog>,
+    ) -> Result<Program> {
         let ir = Arc::new(chalk_parse::parse_program(&text)?.lower(solver_choice)?);
-        let env = Arc::new(ir.environment());
-        Ok(Program { text, ir, env })
+        let env = ir.environment();
+        let solver = match (cache, &query_log) {
+            (false, _) => None,
+            (true, Some(log)) => Some(RefCell::new(solver_choice.into_solver_with_query_log(&env, log))),
+            (true, None) => Some(RefCell::new(solver_choice.into_solver(&env))),
+        };
+        Ok(Program {
+            text,
+            ir,
+            env,
+            solver,
+            query_log,
+        })
     }
 }
 
@@ -88,6 +154,18 @@ fn run() -> Result<()> {
         eprintln!("error: overflow depth must be at least 1");
         exit(1);
     }
+    if args.parse_mixed_cycles().is_none() {
+        eprintln!("error: mixed cycle policy must be one of reject, ambiguous or stratified");
+        exit(1);
+    }
+    if args.parse_aggregation().is_none() {
+        eprintln!("error: aggregation policy must be one of anti-unify, first or all");
+        exit(1);
+    }
+    if args.parse_placeholders().is_none() {
+        eprintln!("error: placeholder style must be one of universe, name or name-universe");
+        exit(1);
+    }
 
     // Load the .chalk file, if given.
     let mut prog = None;
@@ -96,12 +174,15 @@ fn run() -> Result<()> {
             Ok(p) => prog = Some(p),
             Err(err) => {
                 eprintln!("error loading program: {}", err);
+                for cause in err.iter().skip(1) {
+                    eprintln!("caused by: {}", cause);
+                }
                 exit(1);
             }
         }
     }
 
-    if args.flag_goal.is_empty() {
+    if args.flag_goal.is_empty() && args.flag_goals.is_none() {
         // The user specified no goal. Enter interactive mode.
         readline_loop(&mut rustyline::Editor::new(), "?- ", |rl, line| {
             if let Err(e) = process(args, line, rl, &mut prog) {
@@ -123,6 +204,34 @@ fn run() -> Result<()> {
                     exit(1);
                 }
             }
+            if let Some(filename) = &args.flag_goals {
+                let names: Vec<&str> = args.flag_run.iter().map(|name| &name[..]).collect();
+                if let Err(e) = goal_file(&args, filename, &names, &prog) {
+                    eprintln!("error: {}", e);
+                    exit(1);
+                }
+            }
+            if let Some(log) = &prog.query_log {
+                print_query_log(log);
+                if log.exceeded().is_some() {
+                    exit(1);
+                }
+            }
+            if let Some(path) = &args.flag_facts {
+                match &prog.solver {
+                    Some(solver) => {
+                        let facts = solver.borrow().proven_facts();
+                        if let Err(e) = fs::write(path, facts.to_datalog()) {
+                            eprintln!("error writing facts: {}", e);
+                            exit(1);
+                        }
+                    }
+                    None => {
+                        eprintln!("error: `--facts` requires caching; remove `--no-cache`");
+                        exit(1);
+                    }
+                }
+            }
             Ok(())
         })?;
 
@@ -177,13 +286,27 @@ fn process(
 
     } else if command == "program" {
         // Load a .chalk file via stdin, until EOF is found.
-        *prog = Some(Program::new(read_program(rl)?, args.solver_choice())?);
+        *prog = Some(Program::new(
+            read_program(rl)?,
+            args.solver_choice(),
+            !args.flag_no_cache,
+            args.query_log(),
+        )?);
 
     } else if command.starts_with("load ") {
         // Load a .chalk file.
         let filename = &command["load ".len()..];
         *prog = Some(load_program(args, filename)?);
 
+    } else if command.starts_with("run ") {
+        // Solve goals from a goal file, optionally selected by name.
+        let mut words = command["run ".len()..].split_whitespace();
+        let filename = words.next().ok_or("usage: run <file> [<name>...]")?;
+        let names: Vec<&str> = words.collect();
+        let prog = prog.as_ref()
+            .ok_or("no program currently loaded; type 'help' to see available commands")?;
+        ir::tls::set_current_program(&prog.ir, || goal_file(args, filename, &names, prog))?;
+
     } else {
         // The command is either "print", "lowered", or a goal.
         
@@ -200,6 +323,12 @@ fn process(
                 // TODO: Write a line of documentation here.
                 "lowered" => println!("{:#?}", prog.env),
 
+                // Report the queries solved so far.
+                "queries" => match &prog.query_log {
+                    Some(log) => print_query_log(log),
+                    None => println!("queries are not logged; use `--log-queries`"),
+                },
+
                 // Assume this is a goal.
                 // TODO: Print out "type 'help' to see available commands" if it
                 // fails to parse?
@@ -218,7 +347,7 @@ fn process(
 fn load_program(args: &Args, filename: &str) -> Result<Program> {
     let mut text = String::new();
     File::open(filename)?.read_to_string(&mut text)?;
-    Ok(Program::new(text, args.solver_choice())?)
+    Ok(Program::new(text, args.solver_choice(), !args.flag_no_cache, args.query_log())?)
 }
 
 /// Print out help for commands in interpreter mode.
@@ -230,6 +359,9 @@ fn help() {
     println!("  load <file>  load program from <file>");
     println!("  print        print the current program");
     println!("  lowered      print the lowered program");
+    println!("  run <file> [<name>...]");
+    println!("               solve the named goals in goal file <file>, or all of them");
+    println!("  queries      report the queries solved so far, with `--log-queries`");
     println!("  <goal>       attempt to solve <goal>");
 }
 
@@ -249,18 +381,125 @@ fn read_program(rl: &mut rustyline::Editor<()>) -> Result<String> {
 // TODO: Could we pass in an Options struct or something? The Args struct
 // still has Strings where it should have Enums... (e.g. solver_choice)
 fn goal(args: &Args, text: &str, prog: &Program) -> Result<()> {
-    let goal = chalk_parse::parse_goal(text)?.lower(&*prog.ir)?;
-    let peeled_goal = goal.into_peeled_goal();
-    match args.solver_choice().solve_root_goal(&prog.env, &peeled_goal) {
-        Ok(Some(v)) => println!("{}\n", v),
+    let goal = chalk_parse::parse_goal(text)?;
+    solve_goal(args, &goal, prog)
+}
+
+/// Load a file of named goals and solve those with the given names, in
+/// that order; with no names, solve every goal in the file.
+fn goal_file(args: &Args, filename: &str, names: &[&str], prog: &Program) -> Result<()> {
+    let mut text = String::new();
+    File::open(filename)?.read_to_string(&mut text)?;
+    let goals = chalk_parse::parse_goals(&text)?;
+    for named in chalk_parse::select_goals(&goals, names)? {
+        println!("{}:", named.name.str);
+        solve_goal(args, &named.goal, prog)?;
+    }
+    Ok(())
+}
+
+/// Attempt to solve an already parsed goal.
+fn solve_goal(args: &Args, goal: &ast::Goal, prog: &Program) -> Result<()> {
+    let binder_names = goal.peeled_binder_names();
+    let (goal, num_holes) = goal.lower_with_holes(&*prog.ir)?;
+    let (peeled_goal, placeholder_names) =
+        goal.into_peeled_goal_with_holes(binder_names, num_holes);
+    let placeholder_names = placeholder_names.with_style(args.parse_placeholders().unwrap());
+    let result = match (&prog.solver, &prog.query_log) {
+        (Some(solver), _) => solver.borrow_mut().solve(&peeled_goal),
+        (None, Some(log)) => args.solver_choice()
+            .into_solver_with_query_log(&prog.env, log)
+            .solve(&peeled_goal),
+        (None, None) => args.solver_choice()
+            .solve_root_goal(&prog.env, &peeled_goal, SolveOptions::default())
+            .map(|outcome| outcome.solution),
+    };
+    match result {
+        Ok(Some(v)) => println!("{}\n", v.display_with_names(&placeholder_names)),
         Ok(None) => println!("No possible solution.\n"),
         Err(e) => println!("Solver failed: {}", e),
     }
     Ok(())
 }
 
+/// Print how many queries were solved, and which ones more than once.
+fn print_query_log(log: &QueryLog) {
+    for &(level, name) in &[(QueryLevel::Root, "root goals"), (QueryLevel::Subgoal, "subgoals")] {
+        let stats = log.stats(level);
+        println!(
+            "{}: {} solved, {} distinct, {} duplicates",
+            name,
+            stats.solved,
+            stats.distinct,
+            stats.duplicates()
+        );
+    }
+    for query in log.repeated() {
+        println!("  {}x {:?} {:?}", query.count, query.level, query.goal);
+    }
+}
+
 impl Args {
+    fn query_log(&self) -> Option<QueryLog> {
+        match self.flag_max_query_repeats {
+            Some(max_repeats) => Some(QueryLog::with_max_repeats(max_repeats)),
+            None if self.flag_log_queries => Some(QueryLog::new()),
+            None => None,
+        }
+    }
+
     fn solver_choice(&self) -> SolverChoice {
-        SolverChoice::SLG { max_size: self.flag_overflow_depth }
+        if self.flag_naive {
+            return SolverChoice::naive()
+                .with_max_size(self.flag_overflow_depth)
+                .with_implied_bounds(self.flag_implied_bounds);
+        }
+
+        SolverChoice::slg()
+            .with_max_size(self.flag_overflow_depth)
+            .with_mixed_cycles(self.parse_mixed_cycles().unwrap())
+            .with_erase_regions(self.flag_erase_regions)
+            .with_max_environment_clauses(self.flag_max_env_clauses)
+            .with_trivial_answer_cut(!self.flag_no_trivial_cut)
+            .with_clause_order(ClauseOrder {
+                environment_first: !self.flag_program_first,
+                reverse_program_clauses: self.flag_reverse_impls,
+            })
+            .with_aggregation(self.parse_aggregation().unwrap())
+            .with_subgoal_order(if self.flag_plan_subgoals {
+                SubgoalOrder::CheapestFirst
+            } else {
+                SubgoalOrder::Last
+            })
+            .with_implied_bounds(self.flag_implied_bounds)
+    }
+
+    fn parse_mixed_cycles(&self) -> Option<MixedCyclePolicy> {
+        match &self.flag_mixed_cycles[..] {
+            "reject" => Some(MixedCyclePolicy::Reject),
+            "ambiguous" => Some(MixedCyclePolicy::Ambiguous),
+            "stratified" => Some(MixedCyclePolicy::Stratified),
+            _ => None,
+        }
+    }
+
+    fn parse_aggregation(&self) -> Option<AggregationPolicy> {
+        match &self.flag_aggregation[..] {
+            "anti-unify" => Some(AggregationPolicy::AntiUnify),
+            "first" => Some(AggregationPolicy::FirstAnswer),
+            "all" => Some(AggregationPolicy::AllAnswers {
+                max_answers: self.flag_max_answers,
+            }),
+            _ => None,
+        }
+    }
+
+    fn parse_placeholders(&self) -> Option<ir::PlaceholderStyle> {
+        match &self.flag_placeholders[..] {
+            "universe" => Some(ir::PlaceholderStyle::Universe),
+            "name" => Some(ir::PlaceholderStyle::Name),
+            "name-universe" => Some(ir::PlaceholderStyle::NameAndUniverse),
+            _ => None,
+        }
     }
 }
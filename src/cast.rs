@@ -94,6 +94,18 @@ impl Cast<DomainGoal> for UnselectedNormalize {
     }
 }
 
+impl Cast<DomainGoal> for NormalizeConst {
+    fn cast(self) -> DomainGoal {
+        DomainGoal::NormalizeConst(self)
+    }
+}
+
+impl Cast<DomainGoal> for OpaqueEq {
+    fn cast(self) -> DomainGoal {
+        DomainGoal::OpaqueEq(self)
+    }
+}
+
 impl Cast<LeafGoal> for EqGoal {
     fn cast(self) -> LeafGoal {
         LeafGoal::EqGoal(self)
@@ -152,6 +164,12 @@ impl Cast<Parameter> for Lifetime {
     }
 }
 
+impl Cast<Parameter> for Const {
+    fn cast(self) -> Parameter {
+        ParameterKind::Const(self)
+    }
+}
+
 impl Cast<ProgramClause> for DomainGoal {
     fn cast(self) -> ProgramClause {
         ProgramClause::Implies(ProgramClauseImplication {
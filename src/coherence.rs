@@ -1,17 +1,244 @@
 use petgraph::prelude::*;
 
-use errors::Result;
+use errors::{Error, ErrorKind, Result};
 use ir::{self, ItemId, Program};
-use solve::SolverChoice;
+use progress::ProgressReporter;
+use solve::{ProofTrace, SolverChoice};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::sync::Arc;
 
+mod orphan;
 mod solve;
 mod test;
 
+/// A pair of impls whose headers unify, but which are nonetheless coherent
+/// because their where clauses can never hold at the same time. Such pairs are
+/// fragile: an impl satisfying both sets of where clauses (which an upstream
+/// crate may consider a minor change) would make them overlap.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FragileImplPair {
+    /// The blanket impl, e.g. `impl<T> Foo for T where T: Bar`.
+    pub blanket_impl: ItemId,
+
+    /// Another impl of the same trait whose header unifies with that of
+    /// the blanket impl, e.g. `impl Foo for Baz`.
+    pub other_impl: ItemId,
+}
+
+/// A specialization that is not "always applicable": there are types
+/// for which the impl it specializes applies but it does not, so which
+/// impl is used would depend on more than the types involved.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpecializationViolation {
+    /// The less special impl, e.g. `impl<T> Foo for T`.
+    pub base_impl: ItemId,
+
+    /// The more special impl, e.g. `impl<T> Foo for Vec<T> where T: Clone`.
+    pub specializing_impl: ItemId,
+
+    pub kind: SpecializationViolationKind,
+}
+
+/// Why a specialization is not always applicable.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpecializationViolationKind {
+    /// The where clauses of the specializing impl do not follow from
+    /// those of the base impl.
+    WhereClausesNotImplied,
+
+    /// The where clauses of the specializing impl only follow from
+    /// those of the base impl if certain lifetimes are related, which
+    /// cannot be relied upon once lifetimes are erased.
+    LifetimeDependent,
+}
+
+impl fmt::Display for SpecializationViolationKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpecializationViolationKind::WhereClausesNotImplied => {
+                write!(fmt, "its where clauses are not implied by those of the impl it specializes")
+            }
+            SpecializationViolationKind::LifetimeDependent => {
+                write!(fmt, "it only applies under lifetime constraints")
+            }
+        }
+    }
+}
+
+/// How the headers of two overlapping impls unify. This is attached as
+/// the cause of an `ErrorKind::OverlappingImpls` error, to explain
+/// overlaps that are not obvious, like those involving large blanket
+/// impls.
+///
+/// Types are rendered as in `Debug` output, with the parameters of the
+/// first impl written `?0`, `?1`, ..., followed by those of the second.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderUnification {
+    pub lhs_impl: ItemId,
+    pub rhs_impl: ItemId,
+
+    /// The parameters of the trait, starting with `Self`, in the order
+    /// they were unified.
+    pub steps: Vec<UnificationStep>,
+
+    /// The value each impl parameter was bound to, like `?0 := Bar`;
+    /// parameters left unbound are omitted.
+    pub bindings: Vec<String>,
+
+    /// The record of the solver failing to prove the impls disjoint,
+    /// which shows how their where clauses were checked.
+    pub trace: ProofTrace,
+}
+
+/// The unification of a parameter of the trait as given by either impl;
+/// see `HeaderUnification`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnificationStep {
+    pub lhs: String,
+    pub rhs: String,
+
+    /// Both sides with the impl parameters bound so far substituted.
+    pub unified: String,
+}
+
+impl fmt::Display for HeaderUnification {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "the impl headers unify")?;
+        for step in &self.steps {
+            write!(fmt, "\n  `{}` = `{}`, as `{}`", step.lhs, step.rhs, step.unified)?;
+        }
+        if !self.bindings.is_empty() {
+            write!(fmt, "\n  with {}", self.bindings.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The specialization relationships between the impls of a program,
+/// as found by the coherence check. The impls of each trait form a
+/// forest, in which the parent of an impl is the most specific impl it
+/// specializes; see `Program::specialization_graph`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpecializationGraph {
+    parents: BTreeMap<ItemId, ItemId>,
+    children: BTreeMap<ItemId, Vec<ItemId>>,
+}
+
+impl SpecializationGraph {
+    /// Builds the graph from every pair `(less_special, more_special)`
+    /// of impls where the second specializes the first. Since all the
+    /// impls that a given impl specializes overlap one another, they
+    /// form a chain, and the most specific of them is the one that
+    /// itself specializes the most impls.
+    fn from_specializations(specializations: &[(ItemId, ItemId)]) -> Self {
+        let mut specialized: BTreeMap<ItemId, Vec<ItemId>> = BTreeMap::new();
+        for &(less_special, more_special) in specializations {
+            specialized.entry(more_special).or_insert_with(Vec::new).push(less_special);
+        }
+
+        let mut graph = SpecializationGraph::default();
+        for (&impl_id, less_special) in &specialized {
+            let num_specialized = |id: &ItemId| specialized.get(id).map_or(0, |ids| ids.len());
+            let parent = *less_special.iter().max_by_key(|id| num_specialized(id)).unwrap();
+            graph.parents.insert(impl_id, parent);
+            graph.children.entry(parent).or_insert_with(Vec::new).push(impl_id);
+        }
+        graph
+    }
+
+    /// The most specific impl that `impl_id` specializes, if any.
+    pub fn parent(&self, impl_id: ItemId) -> Option<ItemId> {
+        self.parents.get(&impl_id).cloned()
+    }
+
+    /// The impls whose parent is `impl_id`, in declaration order.
+    pub fn children(&self, impl_id: ItemId) -> &[ItemId] {
+        self.children.get(&impl_id).map_or(&[][..], |children| &children[..])
+    }
+
+    /// Every impl that `impl_id` specializes, from the most specific
+    /// to the least.
+    pub fn ancestors(&self, impl_id: ItemId) -> Vec<ItemId> {
+        let mut ancestors = vec![];
+        let mut current = impl_id;
+        while let Some(parent) = self.parent(current) {
+            ancestors.push(parent);
+            current = parent;
+        }
+        ancestors
+    }
+
+    /// Every edge of the graph, as a `(parent, child)` pair.
+    pub fn edges<'a>(&'a self) -> impl Iterator<Item = (ItemId, ItemId)> + 'a {
+        self.parents.iter().map(|(&child, &parent)| (parent, child))
+    }
+}
+
 impl Program {
-    crate fn record_specialization_priorities(&mut self, solver_choice: SolverChoice) -> Result<()> {
+    /// The specialization relationships between the impls of the
+    /// program, as recorded when it was lowered.
+    pub fn specialization_graph(&self) -> &SpecializationGraph {
+        &self.specialization_graph
+    }
+
+    /// The impls of the given trait that specialize no other impl, in
+    /// declaration order: the roots of the trait's part of the
+    /// specialization graph.
+    pub fn specialization_roots(&self, trait_id: ItemId) -> Vec<ItemId> {
+        self.impl_data
+            .iter()
+            .filter(|(_, impl_datum)| {
+                impl_datum.binders.value.trait_ref.trait_ref().trait_id == trait_id
+            })
+            .map(|(&impl_id, _)| impl_id)
+            .filter(|&impl_id| self.specialization_graph.parent(impl_id).is_none())
+            .collect()
+    }
+
+    /// Checks that every specializing impl applies whenever the impl it
+    /// specializes does, and reports those that do not. The program is
+    /// expected to have passed the overlap check already.
+    pub fn specialization_violations(
+        &self,
+        solver_choice: SolverChoice,
+    ) -> Result<Vec<SpecializationViolation>> {
+        ir::tls::set_current_program(&Arc::new(self.clone()), || {
+            self.visit_specialization_violations(solver_choice)
+        })
+    }
+
+    /// Like `specialization_violations`, but reports the first violation
+    /// as an error.
+    pub fn check_always_applicable(&self, solver_choice: SolverChoice) -> Result<()> {
+        match self.specialization_violations(solver_choice)?.first() {
+            Some(violation) => {
+                let impl_datum = &self.impl_data[&violation.specializing_impl];
+                let trait_id = impl_datum.binders.value.trait_ref.trait_ref().trait_id;
+                let trait_name = self.type_kinds[&trait_id].name;
+                Err(Error::from_kind(ErrorKind::NotAlwaysApplicable(trait_name, violation.kind)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Finds every blanket impl (an impl whose self type is one of its
+    /// own type parameters, like `impl<T> Foo for T where T: Bar`) and
+    /// reports the other impls of the same trait that are only disjoint
+    /// from it because of where clauses.
+    pub fn fragile_impl_pairs(&self, solver_choice: SolverChoice) -> Vec<FragileImplPair> {
+        ir::tls::set_current_program(&Arc::new(self.clone()), || {
+            self.visit_fragile_impl_pairs(solver_choice)
+        })
+    }
+
+    crate fn record_specialization_priorities(
+        &mut self,
+        solver_choice: SolverChoice,
+        progress: &mut ProgressReporter,
+    ) -> Result<()> {
         ir::tls::set_current_program(&Arc::new(self.clone()), || {
-            let forest = self.build_specialization_forest(solver_choice)?;
+            let forest = self.build_specialization_forest(solver_choice, progress)?;
 
             // Visit every root in the forest & set specialization
             // priority for the tree that is the root of.
@@ -19,14 +246,76 @@ impl Program {
                 self.set_priorities(root_idx, &forest, 0);
             }
 
-            Ok(())
+            let specializations: Vec<_> = forest
+                .raw_edges()
+                .iter()
+                .map(|edge| (forest[edge.source()], forest[edge.target()]))
+                .collect();
+            self.specialization_graph = SpecializationGraph::from_specializations(&specializations);
+            self.invalidate_environment();
+
+            self.check_default_overrides()
         })
     }
 
+    /// The impls specializing `impl_id` that override its value of the
+    /// associated type `associated_ty_id`. An impl that does not
+    /// override the value inherits it, so the impls specializing that
+    /// one are considered in turn.
+    crate fn overriding_impls(&self, impl_id: ItemId, associated_ty_id: ItemId) -> Vec<ItemId> {
+        let mut overriding = vec![];
+        let mut pending = self.specialization_graph.children(impl_id).to_vec();
+        while let Some(child) = pending.pop() {
+            if self.associated_ty_value(child, associated_ty_id).is_some() {
+                overriding.push(child);
+            } else {
+                pending.extend(self.specialization_graph.children(child));
+            }
+        }
+        overriding
+    }
+
+    fn associated_ty_value(
+        &self,
+        impl_id: ItemId,
+        associated_ty_id: ItemId,
+    ) -> Option<&ir::AssociatedTyValue> {
+        self.impl_data[&impl_id]
+            .binders
+            .value
+            .associated_ty_values
+            .iter()
+            .find(|value| value.associated_ty_id == associated_ty_id)
+    }
+
+    /// Checks that every associated type value overridden by a
+    /// specializing impl is `default` in the nearest impl it specializes
+    /// that defines it.
+    fn check_default_overrides(&self) -> Result<()> {
+        for (&impl_id, impl_datum) in &self.impl_data {
+            for value in &impl_datum.binders.value.associated_ty_values {
+                let overridden = self.specialization_graph
+                    .ancestors(impl_id)
+                    .into_iter()
+                    .filter_map(|id| self.associated_ty_value(id, value.associated_ty_id))
+                    .next();
+                if overridden.map_or(false, |overridden| !overridden.default) {
+                    let trait_id = impl_datum.binders.value.trait_ref.trait_ref().trait_id;
+                    return Err(Error::from_kind(ErrorKind::OverridesNonDefault(
+                        self.type_kinds[&trait_id].name,
+                        self.associated_ty_data[&value.associated_ty_id].name,
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
     // Build the forest of specialization relationships.
     fn build_specialization_forest(
         &self,
         solver_choice: SolverChoice,
+        progress: &mut ProgressReporter,
     ) -> Result<Graph<ItemId, ()>> {
         // The forest is returned as a graph but built as a GraphMap; this is
         // so that we never add multiple nodes with the same ItemId.
@@ -35,7 +324,7 @@ impl Program {
         // Find all specializations (implemented in coherence/solve)
         // Record them in the forest by adding an edge from the less special
         // to the more special.
-        self.visit_specializations(solver_choice, |less_special, more_special| {
+        self.visit_specializations(solver_choice, progress, |less_special, more_special| {
             forest.add_edge(less_special, more_special, ());
         })?;
 
@@ -0,0 +1,128 @@
+use errors::*;
+use ir::debug::Angle;
+use ir::*;
+use std::sync::Arc;
+
+impl Program {
+    /// Checks that every impl of an upstream trait, one declared `extern` or
+    /// `#[upstream]`, is allowed by the orphan rules, so that no two crates
+    /// can provide overlapping impls without seeing each other's. Such an
+    /// impl must name a local type among the input types of the trait, and
+    /// no type parameter of the impl may appear uncovered before the first
+    /// local type:
+    ///
+    /// ```notrust
+    /// impl<T> Upstream for Vec<T> { }       // error: no local type
+    /// impl<T> Upstream<Local> for T { }     // error: `T` comes first
+    /// impl<T> Upstream<T> for Local { }     // ok
+    /// impl Upstream for Box<Local> { }      // ok, as `Box` is fundamental
+    /// ```
+    ///
+    /// A type parameter is covered when it appears under some type
+    /// constructor other than a fundamental one, as in `Vec<T>`; the type
+    /// is then not local, but no downstream crate can own it either.
+    crate fn check_orphan_rules(&self) -> Result<()> {
+        for impl_datum in self.impl_data.values() {
+            let trait_ref = impl_datum.binders.value.trait_ref.trait_ref();
+            if !self.trait_data[&trait_ref.trait_id].binders.value.flags.external {
+                continue;
+            }
+
+            if !self.orphan_check_trait_ref(trait_ref) {
+                let impl_header = self.impl_header(impl_datum);
+                return Err(Error::from_kind(ErrorKind::OrphanImpl(impl_header)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the header of an impl for error messages, like
+    /// `impl Foo<Local> for Vec<?0>`, where `?0` is the impl's first
+    /// type parameter.
+    fn impl_header(&self, impl_datum: &ImplDatum) -> String {
+        let polarized_trait_ref = &impl_datum.binders.value.trait_ref;
+        let polarity = if polarized_trait_ref.is_positive() { "" } else { "!" };
+        let trait_ref = polarized_trait_ref.trait_ref();
+        tls::set_current_program(&Arc::new(self.clone()), || {
+            format!(
+                "impl {}{:?}{:?} for {:?}",
+                polarity,
+                trait_ref.trait_id,
+                Angle(&trait_ref.parameters[1..]),
+                trait_ref.parameters[0]
+            )
+        })
+    }
+
+    fn orphan_check_trait_ref(&self, trait_ref: &TraitRef) -> bool {
+        for ty in trait_ref.parameters.iter().filter_map(|p| p.as_ref().ty()) {
+            if self.is_local_ty(ty) {
+                return true;
+            }
+            if self.has_uncovered_parameter(ty) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// A type is local if it is declared by this crate or, as coherence
+    /// looks through fundamental types, if it is a fundamental type over
+    /// a local type, like `Box<Local>` or `&Local`.
+    fn is_local_ty(&self, ty: &Ty) -> bool {
+        match *ty {
+            Ty::Apply(ApplicationTy {
+                name,
+                ref parameters,
+            }) => {
+                let local_constructor = match name {
+                    TypeName::ItemId(id) => self.adt_data
+                        .get(&id)
+                        .map_or(false, |adt_datum| !adt_datum.binders.value.flags.external),
+                    _ => false,
+                };
+                local_constructor
+                    || (self.is_fundamental_type_name(name)
+                        && parameters
+                            .iter()
+                            .filter_map(|p| p.as_ref().ty())
+                            .any(|ty| self.is_local_ty(ty)))
+            }
+            Ty::Dyn(ref dyn_ty) => dyn_ty
+                .bounds
+                .iter()
+                .any(|bound| !self.trait_data[&bound.trait_id].binders.value.flags.external),
+            _ => false,
+        }
+    }
+
+    fn has_uncovered_parameter(&self, ty: &Ty) -> bool {
+        match *ty {
+            Ty::Var(_) => true,
+            Ty::Apply(ApplicationTy {
+                name,
+                ref parameters,
+            }) if self.is_fundamental_type_name(name) =>
+            {
+                parameters
+                    .iter()
+                    .filter_map(|p| p.as_ref().ty())
+                    .any(|ty| self.has_uncovered_parameter(ty))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether coherence looks through applications of the given type
+    /// constructor: those of `#[fundamental]` types like `Box`, and
+    /// references.
+    fn is_fundamental_type_name(&self, name: TypeName) -> bool {
+        match name {
+            TypeName::ItemId(id) => self.adt_data
+                .get(&id)
+                .map_or(false, |adt_datum| adt_datum.binders.value.flags.fundamental),
+            TypeName::Ref(_) => true,
+            _ => false,
+        }
+    }
+}
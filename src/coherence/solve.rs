@@ -3,38 +3,53 @@ use std::sync::Arc;
 use fold::shift::Shift;
 use itertools::Itertools;
 use errors::*;
+use fallible::NoSolution;
 use ir::*;
 use cast::*;
-use solve::SolverChoice;
+use ir::could_match::CouldMatch;
+use progress::{CheckPhase, ProgressReporter};
+use solve::{Solution, SolveOptions, Solver, SolverChoice};
+use solve::infer::InferenceTable;
 
+use super::{FragileImplPair, HeaderUnification, SpecializationViolation,
+            SpecializationViolationKind, UnificationStep};
+
+/// Answers the coherence queries for the impls of a program. All
+/// queries go through a single `Solver`, so work shared between them
+/// -- e.g., proving the where clauses of an impl, which recur in the
+/// query for every other impl of the same trait -- is only done once.
 struct DisjointSolver {
     env: Arc<ProgramEnvironment>,
     solver_choice: SolverChoice,
+    solver: Solver,
+}
+
+impl DisjointSolver {
+    fn new(env: Arc<ProgramEnvironment>, solver_choice: SolverChoice) -> Self {
+        let solver = solver_choice.into_solver(&env);
+        DisjointSolver {
+            env,
+            solver_choice,
+            solver,
+        }
+    }
 }
 
 impl Program {
     pub(super) fn visit_specializations<F>(
         &self,
         solver_choice: SolverChoice,
+        progress: &mut ProgressReporter,
         mut record_specialization: F,
     ) -> Result<()>
     where
         F: FnMut(ItemId, ItemId),
     {
-        let mut solver = DisjointSolver {
-            env: Arc::new(self.environment()),
-            solver_choice,
-        };
+        let mut solver = DisjointSolver::new(self.environment(), solver_choice);
 
         // Create a vector of references to impl datums, sorted by trait ref.
         let impl_data = self.impl_data
             .iter()
-            .filter(|&(_, impl_datum)| {
-                // Ignore impls for marker traits as they are allowed to overlap.
-                let trait_id = impl_datum.binders.value.trait_ref.trait_ref().trait_id;
-                let trait_datum = &self.trait_data[&trait_id];
-                !trait_datum.binders.value.flags.marker
-            })
             .sorted_by(|&(_, lhs), &(_, rhs)| {
                 lhs.binders
                     .value
@@ -45,20 +60,32 @@ impl Program {
             });
 
         // Group impls by trait.
-        let impl_groupings = impl_data.into_iter().group_by(|&(_, impl_datum)| {
-            impl_datum.binders.value.trait_ref.trait_ref().trait_id
-        });
-
+        let impl_groupings: Vec<(ItemId, Vec<(&ItemId, &ImplDatum)>)> = impl_data
+            .into_iter()
+            .group_by(|&(_, impl_datum)| impl_datum.binders.value.trait_ref.trait_ref().trait_id)
+            .into_iter()
+            .map(|(trait_id, impls)| (trait_id, impls.collect()))
+            .collect();
 
         // Iterate over every pair of impls for the same trait.
-        for (trait_id, impls) in &impl_groupings {
-            let impls: Vec<(&ItemId, &ImplDatum)> = impls.collect();
+        let num_traits = impl_groupings.len();
+        for (checked, (trait_id, impls)) in impl_groupings.into_iter().enumerate() {
+            progress.report(CheckPhase::Coherence, checked, num_traits, trait_id)?;
+            let is_marker_trait = self.trait_data[&trait_id].binders.value.flags.marker;
 
             for ((&l_id, lhs), (&r_id, rhs)) in impls.into_iter().tuple_combinations() {
+                let lhs_positive = lhs.binders.value.trait_ref.is_positive();
+                let rhs_positive = rhs.binders.value.trait_ref.is_positive();
+
                 // Two negative impls never overlap.
-                if !lhs.binders.value.trait_ref.is_positive()
-                    && !rhs.binders.value.trait_ref.is_positive()
-                {
+                if !lhs_positive && !rhs_positive {
+                    continue;
+                }
+
+                // Positive impls of a marker trait are allowed to overlap, since there
+                // are no associated items whose values could conflict. A positive and
+                // a negative impl of a marker trait must still be disjoint.
+                if is_marker_trait && lhs_positive && rhs_positive {
                     continue;
                 }
 
@@ -71,7 +98,11 @@ impl Program {
                         (false, true) => record_specialization(r_id, l_id),
                         (_, _) => {
                             let trait_id = self.type_kinds.get(&trait_id).unwrap().name;
-                            return Err(Error::from_kind(ErrorKind::OverlappingImpls(trait_id)));
+                            let unification = solver.header_unification((l_id, lhs), (r_id, rhs));
+                            return Err(Error::with_chain(
+                                Error::from_kind(ErrorKind::ImplHeadersUnify(unification)),
+                                ErrorKind::OverlappingImpls(trait_id),
+                            ));
                         }
                     }
                 }
@@ -82,7 +113,126 @@ impl Program {
     }
 }
 
+impl Program {
+    pub(super) fn visit_fragile_impl_pairs(&self, solver_choice: SolverChoice) -> Vec<FragileImplPair> {
+        let mut solver = DisjointSolver::new(self.environment(), solver_choice);
+
+        let mut pairs = vec![];
+        for (&blanket_id, blanket) in &self.impl_data {
+            if !is_blanket_impl(blanket) {
+                continue;
+            }
+
+            let trait_id = blanket.binders.value.trait_ref.trait_ref().trait_id;
+
+            // Impls of marker traits may overlap anyway, so there is nothing to break.
+            if self.trait_data[&trait_id].binders.value.flags.marker {
+                continue;
+            }
+
+            for (&other_id, other) in &self.impl_data {
+                if other_id == blanket_id
+                    || other.binders.value.trait_ref.trait_ref().trait_id != trait_id
+                {
+                    continue;
+                }
+
+                // Two negative impls never overlap.
+                if !blanket.binders.value.trait_ref.is_positive()
+                    && !other.binders.value.trait_ref.is_positive()
+                {
+                    continue;
+                }
+
+                // Only report a pair of blanket impls once.
+                if is_blanket_impl(other) && other_id < blanket_id {
+                    continue;
+                }
+
+                if solver.headers_overlap(blanket, other) && solver.disjoint(blanket, other) {
+                    pairs.push(FragileImplPair {
+                        blanket_impl: blanket_id,
+                        other_impl: other_id,
+                    });
+                }
+            }
+        }
+
+        pairs
+    }
+}
+
+impl Program {
+    pub(super) fn visit_specialization_violations(
+        &self,
+        solver_choice: SolverChoice,
+    ) -> Result<Vec<SpecializationViolation>> {
+        let mut specializations = vec![];
+        let mut progress = ProgressReporter::silent();
+        self.visit_specializations(solver_choice, &mut progress, |less_special, more_special| {
+            specializations.push((less_special, more_special));
+        })?;
+
+        let mut solver = DisjointSolver::new(self.environment(), solver_choice);
+        let mut violations: Vec<_> = specializations
+            .into_iter()
+            .filter_map(|(base_impl, specializing_impl)| {
+                let kind = solver.always_applicable_violation(
+                    &self.impl_data[&base_impl],
+                    &self.impl_data[&specializing_impl],
+                )?;
+                Some(SpecializationViolation {
+                    base_impl,
+                    specializing_impl,
+                    kind,
+                })
+            })
+            .collect();
+        violations.sort();
+        Ok(violations)
+    }
+}
+
 impl DisjointSolver {
+    // Test if the headers of two impls unify, ignoring their where clauses.
+    //
+    // Example:
+    //
+    //  Impls:
+    //      impl<T> Foo for T where T: Bar { }
+    //      impl Foo for i32 { }
+    //  Generates:
+    //      exists<T> { T = i32 }
+    //
+    fn headers_overlap(&mut self, lhs: &ImplDatum, rhs: &ImplDatum) -> bool {
+        debug_heading!("headers_overlap(lhs={:#?}, rhs={:#?})", lhs, rhs);
+
+        if !headers_could_unify(lhs, rhs) {
+            debug!("headers_overlap: result = false (type constructors differ)");
+            return false;
+        }
+
+        let lhs_len = lhs.binders.len();
+
+        let mut binders = lhs.binders.binders.clone();
+        binders.extend(rhs.binders.binders.clone());
+
+        let lhs_params = params(lhs).iter().cloned();
+        let rhs_params = params(rhs).iter().map(|param| param.up_shift(lhs_len));
+
+        let goal = lhs_params
+            .zip(rhs_params)
+            .map(|(a, b)| Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })))
+            .fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
+            .expect("Every trait takes at least one input type")
+            .quantify(QuantifierKind::Exists, binders);
+
+        let canonical_goal = &goal.into_closed_goal();
+        let result = self.solver.solve(canonical_goal).unwrap().is_some();
+        debug!("headers_overlap: result = {:?}", result);
+        result
+    }
+
     // Test if two impls are disjoint. If the test does not succeed, there is an overlap.
     //
     // We combine the binders of the two impls & treat them as existential
@@ -111,9 +261,23 @@ impl DisjointSolver {
     //  Generates:
     //      not { exists<T, U> { Vec<T> = Vec<U>, T: Bar, U: Baz } }
     //
-    fn disjoint(&self, lhs: &ImplDatum, rhs: &ImplDatum) -> bool {
+    fn disjoint(&mut self, lhs: &ImplDatum, rhs: &ImplDatum) -> bool {
         debug_heading!("overlaps(lhs={:#?}, rhs={:#?})", lhs, rhs);
 
+        if !headers_could_unify(lhs, rhs) {
+            debug!("overlaps: result = true (type constructors differ)");
+            return true;
+        }
+
+        // Unless we can prove NO solution, we consider things to overlap.
+        let canonical_goal = &self.disjoint_goal(lhs, rhs).into_closed_goal();
+        let result = self.solver.solve(canonical_goal).unwrap().is_some();
+        debug!("overlaps: result = {:?}", result);
+        result
+    }
+
+    // The goal that holds if two impls are disjoint; see `disjoint`.
+    fn disjoint_goal(&self, lhs: &ImplDatum, rhs: &ImplDatum) -> Goal {
         let lhs_len = lhs.binders.len();
 
         // Join the two impls' binders together
@@ -123,11 +287,13 @@ impl DisjointSolver {
         // Upshift the rhs variables in params to account for the joined binders
         let lhs_params = params(lhs).iter().cloned();
         let rhs_params = params(rhs).iter().map(|param| param.up_shift(lhs_len));
+        let param_pairs: Vec<_> = lhs_params.zip(rhs_params).collect();
 
         // Create an equality goal for every input type the trait, attempting
         // to unify the inputs to both impls with one another
-        let params_goals = lhs_params
-            .zip(rhs_params)
+        let params_goals = param_pairs
+            .clone()
+            .into_iter()
             .map(|(a, b)| Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })));
 
         // Upshift the rhs variables in where clauses
@@ -137,29 +303,233 @@ impl DisjointSolver {
             .where_clauses
             .iter()
             .map(|wc| wc.up_shift(lhs_len));
+        let where_clauses: Vec<_> = lhs_where_clauses.chain(rhs_where_clauses).collect();
+        let open_world = self.open_world_where_clauses(&binders, param_pairs, &where_clauses);
 
         // Create a goal for each clause in both where clauses
-        let wc_goals = lhs_where_clauses
-            .chain(rhs_where_clauses)
-            .map(|wc| wc.cast());
+        let wc_goals = where_clauses
+            .into_iter()
+            .zip(open_world)
+            .map(|(wc, open_world)| self.overlap_goal(wc, open_world));
 
         // Join all the goals we've created together with And, then quantify them
         // over the joined binders. This is our query.
-        let goal = params_goals
+        params_goals
             .chain(wc_goals)
             .fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
             .expect("Every trait takes at least one input type")
             .quantify(QuantifierKind::Exists, binders)
-            .negate();
+            .negate()
+    }
 
-        // Unless we can prove NO solution, we consider things to overlap.
-        let canonical_goal = &goal.into_closed_goal();
-        let result = self.solver_choice
-            .solve_root_goal(&self.env, canonical_goal)
-            .unwrap()
-            .is_some();
-        debug!("overlaps: result = {:?}", result);
-        result
+    // Explain why two impls overlap: unify the parameters of their
+    // headers one at a time, recording what each unified to, and trace
+    // the solver's attempt to prove them disjoint.
+    //
+    // Example:
+    //
+    //  Impls:
+    //      impl<T> Foo<T> for Vec<T> { }
+    //      impl<U> Foo<Bar> for U { }
+    //  Steps:
+    //      `Vec<?0>` = `?1`, as `Vec<?0>`
+    //      `?0` = `Bar`, as `Bar`
+    //      with ?0 := Bar, ?1 := Vec<Bar>
+    //
+    fn header_unification(
+        &mut self,
+        (lhs_id, lhs): (ItemId, &ImplDatum),
+        (rhs_id, rhs): (ItemId, &ImplDatum),
+    ) -> HeaderUnification {
+        let lhs_len = lhs.binders.len();
+        let binders: Vec<_> = lhs.binders
+            .binders
+            .iter()
+            .chain(&rhs.binders.binders)
+            .map(|pk| pk.map(|()| UniverseIndex::root()))
+            .collect();
+        let params: Vec<(Parameter, Parameter)> = params(lhs)
+            .iter()
+            .cloned()
+            .zip(params(rhs).iter().map(|param| param.up_shift(lhs_len)))
+            .collect();
+
+        // The impl parameters become the first inference variables, so
+        // `?0` in the rendered steps is the first parameter of `lhs`.
+        let (mut infer, vars, params) =
+            InferenceTable::from_canonical(1, &Canonical { binders, value: params });
+        let environment = Environment::new();
+
+        let mut steps = vec![];
+        for (a, b) in &params {
+            let unified = match (a, b) {
+                (ParameterKind::Ty(a), ParameterKind::Ty(b)) => infer.unify(&environment, a, b),
+                (ParameterKind::Lifetime(a), ParameterKind::Lifetime(b)) => {
+                    infer.unify(&environment, a, b)
+                }
+                (ParameterKind::Const(a), ParameterKind::Const(b)) => {
+                    infer.unify(&environment, a, b)
+                }
+                _ => Err(NoSolution),
+            };
+            if unified.is_err() {
+                break;
+            }
+            steps.push(UnificationStep {
+                lhs: format!("{:?}", a),
+                rhs: format!("{:?}", b),
+                unified: format!("{:?}", infer.normalize_deep(a)),
+            });
+        }
+
+        let bindings = vars.parameters
+            .iter()
+            .enumerate()
+            .filter_map(|(index, var)| {
+                let value = infer.normalize_deep(var);
+                if value == *var {
+                    None
+                } else {
+                    Some(format!("?{} := {:?}", index, value))
+                }
+            })
+            .collect();
+
+        let canonical_goal = &self.disjoint_goal(lhs, rhs).into_closed_goal();
+        let options = SolveOptions::default().with_trace(true);
+        let outcome = self.solver_choice
+            .solve_root_goal(&self.env, canonical_goal, options)
+            .unwrap();
+
+        HeaderUnification {
+            lhs_impl: lhs_id,
+            rhs_impl: rhs_id,
+            steps,
+            bindings,
+            trace: outcome.trace.unwrap(),
+        }
+    }
+
+    // Converts an impl where clause into a goal for the overlap check.
+    //
+    // Usually this is just the where clause itself, which lets us use negative
+    // reasoning: if we can show that no type satisfies the where clauses of both
+    // impls, they are disjoint. But for open-world traits, some other crate may
+    // provide the missing impl later (see `open_world_where_clauses`), so we may
+    // only assume that the where clause does not hold if an explicit negative
+    // impl says so:
+    //
+    //  Impls:
+    //      extern trait Bar { }
+    //      impl !Bar for i32 { }
+    //      impl<T> Foo for T where T: Bar { }
+    //      impl Foo for i32 { }
+    //  Generates:
+    //      not { exists<T> { T = i32, not { NotImplemented(T: Bar) } } }
+    //
+    fn overlap_goal(&self, wc: QuantifiedDomainGoal, open_world: bool) -> Goal {
+        let open_world_trait_ref = match wc.value {
+            DomainGoal::Holds(WhereClauseAtom::Implemented(ref trait_ref)) if open_world => {
+                Some(trait_ref.clone())
+            }
+            _ => None,
+        };
+
+        match open_world_trait_ref {
+            Some(trait_ref) => {
+                let goal: Goal = DomainGoal::NotImplemented(trait_ref).cast();
+                let goal = goal.negate();
+                if wc.binders.is_empty() {
+                    goal
+                } else {
+                    goal.quantify(QuantifierKind::ForAll, wc.binders)
+                }
+            }
+            None => wc.cast(),
+        }
+    }
+
+    // For each where clause of two impls, whether it is about an open world,
+    // so that `overlap_goal` may not use negative reasoning on it.
+    //
+    // Besides the where clauses of open-world traits, these are the where
+    // clauses of `extern` fundamental traits that a downstream crate may
+    // still satisfy: it cannot implement such a trait for the types we know
+    // of, but it can for its own types. Which case we are in depends on what
+    // the impl headers unify to, so we unify them first:
+    //
+    //  Impls:
+    //      extern #[fundamental] trait Bar { }
+    //      #[fundamental] struct Box<T> { }
+    //      impl<T> Foo for T where T: Bar { }
+    //      impl<U> Foo for Vec<U> { }
+    //      impl<U> Foo for Box<U> { }
+    //  `T = Vec<?U>`: `T: Bar` is closed, as `Vec<?U>` is never downstream.
+    //  `T = Box<?U>`: `T: Bar` is open, as `Box<?U>` may be `Box<Local>`.
+    //
+    fn open_world_where_clauses(
+        &self,
+        binders: &[ParameterKind<()>],
+        params: Vec<(Parameter, Parameter)>,
+        where_clauses: &[QuantifiedDomainGoal],
+    ) -> Vec<bool> {
+        let binders = binders
+            .iter()
+            .map(|pk| pk.map(|()| UniverseIndex::root()))
+            .collect();
+        let (mut infer, _, (params, where_clauses)) = InferenceTable::from_canonical(
+            1,
+            &Canonical {
+                binders,
+                value: (params, where_clauses.to_vec()),
+            },
+        );
+        let environment = Environment::new();
+
+        // If the headers do not unify, the impls are disjoint whatever we
+        // answer here.
+        for (a, b) in &params {
+            if infer.unify(&environment, a, b).is_err() {
+                break;
+            }
+        }
+
+        where_clauses
+            .iter()
+            .map(|wc| match wc.value {
+                DomainGoal::Holds(WhereClauseAtom::Implemented(ref trait_ref)) => {
+                    self.env.is_open_world_trait(trait_ref.trait_id)
+                        || (self.env.is_fundamental_extern_trait(trait_ref.trait_id)
+                            && infer
+                                .normalize_deep(trait_ref)
+                                .parameters
+                                .iter()
+                                .filter_map(|p| p.as_ref().ty())
+                                .any(|ty| self.may_be_downstream(ty)))
+                }
+                _ => false,
+            })
+            .collect()
+    }
+
+    // Whether a type may turn out to be a type of some downstream crate. That
+    // is the case for the types we do not know yet and, as coherence looks
+    // through them, for fundamental types applied to those: a downstream
+    // crate may implement a fundamental trait for `Box<Local>` or `&Local`.
+    fn may_be_downstream(&self, ty: &Ty) -> bool {
+        match *ty {
+            Ty::Var(_) | Ty::Projection(_) | Ty::UnselectedProjection(_) => true,
+            Ty::Apply(ref apply) => match apply.name {
+                TypeName::ForAll(_) => true,
+                _ if self.env.is_fundamental_ty(ty) => apply
+                    .parameters
+                    .iter()
+                    .filter_map(|p| p.as_ref().ty())
+                    .any(|ty| self.may_be_downstream(ty)),
+                _ => false,
+            },
+            Ty::ForAll(_) | Ty::Dyn(_) | Ty::Opaque(_) => false,
+        }
     }
 
     // Test for specialization.
@@ -227,10 +597,7 @@ impl DisjointSolver {
             .quantify(QuantifierKind::ForAll, more_special.binders.binders.clone());
 
         let canonical_goal = &goal.into_closed_goal();
-        let result = match self.solver_choice
-            .solve_root_goal(&self.env, canonical_goal)
-            .unwrap()
-        {
+        let result = match self.solver.solve(canonical_goal).unwrap() {
             Some(sol) => sol.is_unique(),
             None => false,
         };
@@ -239,8 +606,103 @@ impl DisjointSolver {
 
         result
     }
+
+    // Test whether `more_special`, which specializes `less_special`, applies
+    // whenever `less_special` does: that is, whether its where clauses follow
+    // from those of `less_special`, without relying on lifetime constraints.
+    //
+    // Example lowering:
+    //
+    // more: impl<T: Clone> Foo for Vec<T>
+    // less: impl<U> Foo for U
+    //
+    // forall<T> {
+    //  exists<U> {
+    //    U = Vec<T>, if () { T: Clone }
+    //  }
+    // }
+    fn always_applicable_violation(
+        &mut self,
+        less_special: &ImplDatum,
+        more_special: &ImplDatum,
+    ) -> Option<SpecializationViolationKind> {
+        debug_heading!(
+            "always_applicable_violation(less_special={:#?}, more_special={:#?})",
+            less_special,
+            more_special
+        );
+
+        let less_len = less_special.binders.len();
+
+        // Within the `exists`, the variables of `less_special` come first.
+        let more_special_wc = more_special
+            .binders
+            .value
+            .where_clauses
+            .iter()
+            .map(|wc| wc.up_shift(less_len).cast())
+            .fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)));
+
+        // Without where clauses, the impl applies whenever its header matches.
+        let more_special_wc = match more_special_wc {
+            Some(goal) => goal,
+            None => return None,
+        };
+
+        let less_special_params = params(less_special).iter().cloned();
+        let more_special_params = params(more_special).iter().map(|p| p.up_shift(less_len));
+        let params_goals = less_special_params
+            .zip(more_special_params)
+            .map(|(a, b)| Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })));
+
+        let less_special_wc = less_special
+            .binders
+            .value
+            .where_clauses
+            .iter()
+            .cloned()
+            .casted()
+            .collect();
+
+        let goal = params_goals
+            .chain(Some(more_special_wc.implied_by(less_special_wc)))
+            .fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
+            .expect("Every trait takes at least one input type")
+            .quantify(QuantifierKind::Exists, less_special.binders.binders.clone())
+            .quantify(QuantifierKind::ForAll, more_special.binders.binders.clone());
+
+        let canonical_goal = &goal.into_closed_goal();
+        let result = match self.solver.solve(canonical_goal).unwrap() {
+            Some(Solution::Unique(ref subst)) if subst.value.constraints.is_empty() => None,
+            Some(Solution::Unique(_)) => Some(SpecializationViolationKind::LifetimeDependent),
+            _ => Some(SpecializationViolationKind::WhereClausesNotImplied),
+        };
+
+        debug!("always_applicable_violation: result = {:?}", result);
+
+        result
+    }
 }
 
 fn params(impl_datum: &ImplDatum) -> &[Parameter] {
     &impl_datum.binders.value.trait_ref.trait_ref().parameters
 }
+
+// A cheap test of whether the headers of two impls could possibly
+// unify, which lets us skip building and solving a goal for most
+// pairs of unrelated impls. It only compares type constructors, so a
+// `true` result says nothing.
+fn headers_could_unify(lhs: &ImplDatum, rhs: &ImplDatum) -> bool {
+    params(lhs)
+        .iter()
+        .zip(params(rhs))
+        .all(|(a, b)| a.could_match(b))
+}
+
+// An impl is a blanket impl if its self type is one of its own type parameters.
+fn is_blanket_impl(impl_datum: &ImplDatum) -> bool {
+    match params(impl_datum)[0] {
+        ParameterKind::Ty(Ty::Var(_)) => true,
+        _ => false,
+    }
+}
@@ -1,7 +1,10 @@
 #![cfg(test)]
 
+use solve::SolverChoice;
 use test_util::*;
 
+use super::{FragileImplPair, SpecializationViolation, SpecializationViolationKind};
+
 #[test]
 fn two_impls_for_same_type() {
     lowering_error! {
@@ -61,6 +64,44 @@ fn two_blanket_impls() {
     }
 }
 
+#[test]
+fn overlap_explains_header_unification() {
+    use errors::{Error, ErrorKind};
+
+    let program_text = "trait Foo<T> { }
+        struct Vec<T> { }
+        struct Bar { }
+        impl<T> Foo<T> for Vec<T> { }
+        impl<U> Foo<Bar> for U { }
+    ";
+    let error = parse_and_lower_program(program_text, SolverChoice::slg()).unwrap_err();
+    assert_eq!(error.to_string(), "overlapping impls of trait \"Foo\"");
+
+    let cause = error.iter().nth(1).unwrap().downcast_ref::<Error>().unwrap();
+    assert_eq!(
+        cause.to_string(),
+        "the impl headers unify\n  \
+         `Vec<?0>` = `?1`, as `Vec<?0>`\n  \
+         `?0` = `Bar`, as `Bar`\n  \
+         with ?0 := Bar, ?1 := Vec<Bar>"
+    );
+
+    // The impls may overlap once `Foo` is a marker trait, so they can
+    // be looked up in that program, whose items are otherwise the same.
+    let marker_program = parse_and_lower_program(
+        &format!("#[marker] {}", program_text),
+        SolverChoice::slg(),
+    ).unwrap();
+    match cause.kind() {
+        ErrorKind::ImplHeadersUnify(unification) => {
+            assert_eq!(unification.lhs_impl, impl_id(&marker_program, "Vec<?0> as Foo<?0>"));
+            assert_eq!(unification.rhs_impl, impl_id(&marker_program, "?0 as Foo<Bar>"));
+            assert!(!unification.trace.goals.is_empty());
+        }
+        kind => panic!("unexpected cause: {}", kind),
+    }
+}
+
 #[test]
 // FIXME This should be an error
 // We currently assume a closed universe always, but overlaps checking should
@@ -93,6 +134,47 @@ fn multiple_nonoverlapping_impls() {
     }
 }
 
+#[test]
+fn many_impls_of_one_trait() {
+    // Most of these pairs can be told apart by their type constructors
+    // alone; the ones that cannot must still be caught.
+    lowering_success! {
+        program {
+            trait Foo { }
+            struct A { }
+            struct B { }
+            struct C { }
+            struct Vec<T> { }
+            impl Foo for A { }
+            impl Foo for B { }
+            impl Foo for C { }
+            impl Foo for Vec<A> { }
+            impl Foo for Vec<B> { }
+            impl<T> Foo for Vec<Vec<T>> { }
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { }
+            trait Bar { }
+            trait Baz { }
+            struct A { }
+            struct B { }
+            struct Vec<T> { }
+            impl Bar for A { }
+            impl Baz for A { }
+            impl Foo for A { }
+            impl Foo for B { }
+            impl<T> Foo for Vec<T> where T: Bar { }
+            impl<T> Foo for Vec<T> where T: Baz { }
+        }
+        error_msg {
+            "overlapping impls of trait \"Foo\""
+        }
+    }
+}
+
 #[test]
 fn local_negative_reasoning_in_coherence() {
     lowering_success! {
@@ -192,12 +274,61 @@ fn overlapping_assoc_types_error() {
     }
 }
 
+#[test]
+fn specializing_non_default_assoc_type() {
+    lowering_success! {
+        program {
+            trait Foo { type Item; }
+            struct A { }
+            struct B { }
+
+            impl<T> Foo for T { default type Item = A; }
+            impl Foo for B { type Item = B; }
+        }
+    }
+
+    // In a `default impl`, every associated type value is `default`.
+    lowering_success! {
+        program {
+            trait Foo { type Item; }
+            struct A { }
+            struct B { }
+
+            default impl<T> Foo for T { type Item = A; }
+            impl Foo for B { type Item = B; }
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { }
+            struct A { }
+            default impl !Foo for A { }
+        } error_msg {
+            "negative impls cannot be `default`"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { type Item; }
+            struct A { }
+            struct B { }
+
+            impl<T> Foo for T { type Item = A; }
+            impl Foo for B { type Item = B; }
+        } error_msg {
+            "specializing impl of trait \"Foo\" overrides associated type `Item`, \
+             which is not `default` in the impl it specializes"
+        }
+    }
+}
+
 #[test]
 fn overlapping_negative_positive_impls() {
     lowering_error! {
         program {
             trait Send { }
-            struct i32 { }
 
             impl Send for i32 { }
             impl !Send for i32 { }
@@ -216,7 +347,6 @@ fn overlapping_negative_impls() {
             trait Bar { }
 
             struct Vec<T> { }
-            struct i32 { }
 
             impl Foo for i32 { }
             impl Bar for i32 { }
@@ -226,3 +356,309 @@ fn overlapping_negative_impls() {
         }
     }
 }
+
+#[test]
+fn open_world_negative_reasoning_in_coherence() {
+    lowering_error! {
+        program {
+            trait Foo { }
+            extern trait Bar { }
+            struct Baz { }
+            impl<T> Foo for T where T: Bar { }
+            impl Foo for Baz { }
+        } error_msg {
+            "overlapping impls of trait \"Foo\""
+        }
+    }
+}
+
+#[test]
+fn explicit_negative_reasoning_in_coherence() {
+    lowering_success! {
+        program {
+            trait Foo { }
+            extern trait Bar { }
+            struct Baz { }
+            impl !Bar for Baz { }
+            impl<T> Foo for T where T: Bar { }
+            impl Foo for Baz { }
+        }
+    }
+}
+
+#[test]
+fn fundamental_negative_reasoning_in_coherence() {
+    lowering_success! {
+        program {
+            trait Foo { }
+            extern #[fundamental] trait Bar { }
+            struct Baz { }
+            impl<T> Foo for T where T: Bar { }
+            impl Foo for Baz { }
+        }
+    }
+
+    lowering_success! {
+        program {
+            trait Foo { }
+            extern #[fundamental] trait Bar { }
+            #[fundamental] struct Box<T> { }
+            struct Vec<T> { }
+            struct Baz { }
+            impl<T> Foo for T where T: Bar { }
+            impl<U> Foo for Vec<U> { }
+            impl Foo for Box<Baz> { }
+        }
+    }
+
+    // A downstream crate may implement `Bar` for `Box<Local>`.
+    lowering_error! {
+        program {
+            trait Foo { }
+            extern #[fundamental] trait Bar { }
+            #[fundamental] struct Box<T> { }
+            impl<T> Foo for T where T: Bar { }
+            impl<U> Foo for Box<U> { }
+        } error_msg {
+            "overlapping impls of trait \"Foo\""
+        }
+    }
+
+    // References are fundamental too.
+    lowering_success! {
+        program {
+            trait Foo { }
+            extern #[fundamental] trait Bar { }
+            struct Baz { }
+            impl<T> Foo for T where T: Bar { }
+            impl<'a> Foo for &'a Baz { }
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { }
+            extern #[fundamental] trait Bar { }
+            impl<T> Foo for T where T: Bar { }
+            impl<'a, U> Foo for &'a U { }
+        } error_msg {
+            "overlapping impls of trait \"Foo\""
+        }
+    }
+}
+
+#[test]
+fn orphan_rules() {
+    lowering_success! {
+        program {
+            #[upstream] trait Foo<T> { }
+            #[upstream] struct Vec<T> { }
+            #[upstream] #[fundamental] struct Box<T> { }
+            struct Local { }
+            trait LocalTrait { }
+
+            impl<T> Foo<T> for Local { }
+            impl<T> Foo<T> for Box<Local> { }
+            impl<'a, T> Foo<T> for &'a Local { }
+            impl<'a, T> Foo<T> for &'a mut Box<Local> { }
+            impl<T> Foo<Local> for Vec<T> { }
+            impl<T> LocalTrait for T { }
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[upstream] trait Foo { }
+            #[upstream] struct Vec<T> { }
+            struct Local { }
+            impl Foo for Vec<Local> { }
+        } error_msg {
+            "`impl Foo for Vec<Local>` violates the orphan rules"
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[upstream] trait Foo<T> { }
+            struct Local { }
+            impl<T> Foo<Local> for T { }
+        } error_msg {
+            "`impl Foo<Local> for ?0` violates the orphan rules"
+        }
+    }
+
+    // `Box` is fundamental, so `T` is not covered by it.
+    lowering_error! {
+        program {
+            #[upstream] trait Foo<T> { }
+            #[upstream] #[fundamental] struct Box<T> { }
+            struct Local { }
+            impl<T> Foo<Local> for Box<T> { }
+        } error_msg {
+            "`impl Foo<Local> for Box<?0>` violates the orphan rules"
+        }
+    }
+
+    // So are references.
+    lowering_error! {
+        program {
+            #[upstream] trait Foo<T> { }
+            struct Local { }
+            impl<'a, T> Foo<Local> for &'a T { }
+        } error_msg {
+            "`impl Foo<Local> for &'?0 ?1` violates the orphan rules"
+        }
+    }
+
+    // Items declared `extern` are upstream just like `#[upstream]` ones,
+    // and negative impls are checked too.
+    lowering_error! {
+        program {
+            extern trait Send { }
+            extern struct Vec<T> { }
+            impl<T> !Send for Vec<T> { }
+        } error_msg {
+            "`impl !Send for Vec<?0>` violates the orphan rules"
+        }
+    }
+}
+
+#[test]
+fn overlapping_marker_impls() {
+    lowering_success! {
+        program {
+            #[marker] trait Marker { }
+            trait Foo { }
+            trait Bar { }
+            impl<T> Marker for T where T: Foo { }
+            impl<T> Marker for T where T: Bar { }
+            impl Marker for i32 { }
+            impl Foo for i32 { }
+            impl Bar for i32 { }
+        }
+    }
+}
+
+#[test]
+fn overlapping_negative_positive_marker_impls() {
+    lowering_error! {
+        program {
+            #[marker] trait Marker { }
+            impl<T> Marker for T { }
+            impl !Marker for i32 { }
+        } error_msg {
+            "overlapping impls of trait \"Marker\""
+        }
+    }
+}
+
+#[test]
+fn fragile_impl_pairs() {
+    let program = parse_and_lower_program(
+        "
+        trait Foo { }
+        trait Bar { }
+        trait Spec { }
+        struct Baz { }
+        struct Quux { }
+        impl Bar for Quux { }
+        impl<T> Foo for T where T: Bar { }
+        impl Foo for Baz { }
+        impl<T> Spec for T { }
+        impl Spec for Baz { }
+        ",
+        SolverChoice::slg(),
+    ).unwrap();
+
+    // `impl Foo for Baz` only avoids overlapping with the blanket impl
+    // because `Baz: Bar` does not hold. The `Spec` impls genuinely overlap
+    // (one specializes the other), so they are not reported.
+    assert_eq!(
+        program.fragile_impl_pairs(SolverChoice::slg()),
+        vec![FragileImplPair {
+            blanket_impl: impl_id(&program, "?0 as Foo"),
+            other_impl: impl_id(&program, "Baz as Foo"),
+        }]
+    );
+}
+
+#[test]
+fn specialization_graph() {
+    let program = parse_and_lower_program(
+        "
+        trait Foo { }
+        trait Bar { }
+        struct Vec<T> { }
+        impl<T> Foo for T { }
+        impl<T> Foo for Vec<T> { }
+        impl Foo for Vec<u32> { }
+        impl Foo for u32 { }
+        impl Bar for u32 { }
+        ",
+        SolverChoice::slg(),
+    ).unwrap();
+
+    let graph = program.specialization_graph();
+    let (blanket, vec, vec_u32, u32_impl) = (
+        impl_id(&program, "?0 as Foo"),
+        impl_id(&program, "Vec<?0> as Foo"),
+        impl_id(&program, "Vec<u32> as Foo"),
+        impl_id(&program, "u32 as Foo"),
+    );
+
+    // `impl Foo for Vec<u32>` specializes both other `Vec` impls, but
+    // its parent is the more specific of them.
+    assert_eq!(graph.parent(vec_u32), Some(vec));
+    assert_eq!(graph.parent(vec), Some(blanket));
+    assert_eq!(graph.parent(blanket), None);
+    assert_eq!(graph.children(blanket), &[vec, u32_impl]);
+    assert_eq!(graph.ancestors(vec_u32), vec![vec, blanket]);
+    assert_eq!(
+        graph.edges().collect::<Vec<_>>(),
+        vec![(blanket, vec), (vec, vec_u32), (blanket, u32_impl)]
+    );
+
+    assert_eq!(program.specialization_roots(type_id(&program, "Foo")), vec![blanket]);
+    assert_eq!(
+        program.specialization_roots(type_id(&program, "Bar")),
+        vec![impl_id(&program, "u32 as Bar")]
+    );
+}
+
+#[test]
+fn always_applicable_specializations() {
+    let program = parse_and_lower_program(
+        "
+        trait Clone { }
+        trait Foo<U> { }
+        trait Baz { }
+        struct Vec<T> { }
+        impl<T, U> Foo<U> for T where T: Clone { }
+        impl<T> Foo<T> for T where T: Clone { }
+        impl<T> Baz for T { }
+        impl<T> Baz for Vec<T> where T: Clone { }
+        impl Clone for i32 { }
+        ",
+        SolverChoice::slg(),
+    ).unwrap();
+
+    // `impl<T> Foo<T> for T` only requires what the impl it specializes
+    // already does, but `impl<T> Baz for Vec<T>` does not apply to, say,
+    // `Vec<Vec<i32>>`, even though `impl<T> Baz for T` does.
+    assert_eq!(
+        program.specialization_violations(SolverChoice::slg()).unwrap(),
+        vec![SpecializationViolation {
+            base_impl: impl_id(&program, "?0 as Baz"),
+            specializing_impl: impl_id(&program, "Vec<?0> as Baz"),
+            kind: SpecializationViolationKind::WhereClausesNotImplied,
+        }]
+    );
+    assert_eq!(
+        program
+            .check_always_applicable(SolverChoice::slg())
+            .unwrap_err()
+            .to_string(),
+        "specializing impl of trait \"Baz\" is not always applicable: \
+         its where clauses are not implied by those of the impl it specializes"
+    );
+}
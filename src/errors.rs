@@ -31,11 +31,47 @@ error_chain! {
             display("expected a trait, found `{}`, which is not a trait", identifier.str)
         }
 
+        NotStruct(identifier: ast::Identifier) {
+            description("not a struct")
+            display("expected a struct, found `{}`, which is not a struct", identifier.str)
+        }
+
+        NotPredicate(identifier: ast::Identifier) {
+            description("not a predicate")
+            display("expected a predicate, found `{}`, which is not a predicate", identifier.str)
+        }
+
         OverlappingImpls(trait_id: ir::Identifier) {
             description("overlapping impls")
             display("overlapping impls of trait {:?}", trait_id)
         }
 
+        OrphanImpl(impl_header: String) {
+            description("impl violates the orphan rules")
+            display("`{}` violates the orphan rules", impl_header)
+        }
+
+        ImplHeadersUnify(unification: ::coherence::HeaderUnification) {
+            description("impl headers unify")
+            display("{}", unification)
+        }
+
+        NotAlwaysApplicable(trait_id: ir::Identifier, kind: ::coherence::SpecializationViolationKind) {
+            description("specialization is not always applicable")
+            display("specializing impl of trait {:?} is not always applicable: {}", trait_id, kind)
+        }
+
+        OverridesNonDefault(trait_id: ir::Identifier, name: ir::Identifier) {
+            description("specializing impl overrides a value that is not default")
+            display("specializing impl of trait {:?} overrides associated type `{}`, \
+                     which is not `default` in the impl it specializes", trait_id, name)
+        }
+
+        ChecksAborted(phase: ::progress::CheckPhase, checked: usize, total: usize) {
+            description("checks aborted")
+            display("{:?} checks aborted after {} of {} items", phase, checked, total)
+        }
+
         IllFormedTypeDecl(ty_id: ir::Identifier) {
             description("ill-formed type declaration")
             display("type declaration {:?} does not meet well-formedness requirements", ty_id)
@@ -46,11 +82,62 @@ error_chain! {
             display("trait impl for {:?} does not meet well-formedness requirements", trait_id)
         }
 
+        NotObjectSafe(trait_id: ir::Identifier, violation: ::rules::ObjectSafetyViolation) {
+            description("trait is not object safe")
+            display("the trait {:?} cannot be made into an object: {}", trait_id, violation)
+        }
+
+        RepeatedQuery(query: ::solve::RepeatedQuery, max_repeats: usize) {
+            description("query solved too many times")
+            display("query {:?} was solved {} times, more than the allowed {}",
+                    query.goal, query.count, max_repeats)
+        }
+
+        UnknownTrait(name: ir::Identifier) {
+            description("unknown trait")
+            display("no trait named `{}`", name)
+        }
+
+        UnknownScope(name: ir::Identifier) {
+            description("unknown scope")
+            display("no scope named `{}` was registered", name)
+        }
+
+        NotTraitGoal {
+            description("not a trait goal")
+            display("expected a goal of the form `T: Trait`")
+        }
+
+        InvalidGoalRewrite(reason: &'static str) {
+            description("invalid goal rewrite")
+            display("invalid goal rewrite: {}", reason)
+        }
+
+        Overflow(limit: usize) {
+            description("overflow")
+            display("the goal overflowed the solver's limit of {}", limit)
+        }
+
+        Cancelled {
+            description("cancelled")
+            display("solving was cancelled")
+        }
+
+        NotSupportedByNaiveSolver(operation: &'static str) {
+            description("not supported by the naive solver")
+            display("the naive solver does not support {}", operation)
+        }
+
         CouldNotMatch {
             description("could not match")
                 display("could not match")
         }
 
+        ScalarTypeRedeclared(name: ir::Identifier) {
+            description("scalar type redeclared")
+            display("`{}` is a built-in scalar type and cannot be declared", name)
+        }
+
         DuplicateLangItem(item: ir::LangItem) {
             description("Duplicate lang item")
                 display("Duplicate lang item `{:?}`", item)
@@ -61,6 +61,7 @@ pub trait Folder: ExistentialFolder + UniversalFolder + TypeFolder {
 pub trait TypeFolder {
     fn fold_ty(&mut self, ty: &Ty, binders: usize) -> Fallible<Ty>;
     fn fold_lifetime(&mut self, lifetime: &Lifetime, binders: usize) -> Fallible<Lifetime>;
+    fn fold_const(&mut self, constant: &Const, binders: usize) -> Fallible<Const>;
 }
 
 impl<T: ExistentialFolder + UniversalFolder + TypeFolder> Folder for T {
@@ -84,6 +85,10 @@ impl<T: ExistentialFolder + UniversalFolder + DefaultTypeFolder> TypeFolder for
     fn fold_lifetime(&mut self, lifetime: &Lifetime, binders: usize) -> Fallible<Lifetime> {
         super_fold_lifetime(self.to_dyn(), lifetime, binders)
     }
+
+    fn fold_const(&mut self, constant: &Const, binders: usize) -> Fallible<Const> {
+        super_fold_const(self.to_dyn(), constant, binders)
+    }
 }
 
 /// The methods for folding free **existentially quantified
@@ -107,6 +112,9 @@ pub trait ExistentialFolder {
         depth: usize,
         binders: usize,
     ) -> Fallible<Lifetime>;
+
+    /// As `fold_free_existential_ty`, but for consts.
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const>;
 }
 
 /// A convenience trait. If you implement this, you get an
@@ -126,6 +134,10 @@ impl<T: IdentityExistentialFolder> ExistentialFolder for T {
     ) -> Fallible<Lifetime> {
         Ok(Lifetime::Var(depth + binders))
     }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        Ok(Const::Var(depth + binders))
+    }
 }
 
 pub trait UniversalFolder {
@@ -142,6 +154,13 @@ pub trait UniversalFolder {
         universe: UniverseIndex,
         binders: usize,
     ) -> Fallible<Lifetime>;
+
+    /// As with `fold_free_universal_ty`, but for consts.
+    fn fold_free_universal_const(
+        &mut self,
+        universe: UniverseIndex,
+        binders: usize,
+    ) -> Fallible<Const>;
 }
 
 /// A convenience trait. If you implement this, you get an
@@ -161,6 +180,14 @@ impl<T: IdentityUniversalFolder> UniversalFolder for T {
     ) -> Fallible<Lifetime> {
         Ok(universe.to_lifetime())
     }
+
+    fn fold_free_universal_const(
+        &mut self,
+        universe: UniverseIndex,
+        _binders: usize,
+    ) -> Fallible<Const> {
+        Ok(universe.to_const())
+    }
 }
 
 /// Applies the given folder to a value.
@@ -266,7 +293,17 @@ crate fn super_fold_ty(folder: &mut dyn Folder, ty: &Ty, binders: usize) -> Fall
                     folder.fold_free_universal_ty(ui, binders)
                 }
 
-                TypeName::ItemId(_) | TypeName::AssociatedType(_) => {
+                TypeName::ItemId(_)
+                | TypeName::AssociatedType(_)
+                | TypeName::Tuple(_)
+                | TypeName::FnPtr(_)
+                | TypeName::Slice
+                | TypeName::Array(_)
+                | TypeName::Str
+                | TypeName::Scalar(_)
+                | TypeName::Never
+                | TypeName::Ref(_)
+                | TypeName::RawPtr(_) => {
                     let parameters = parameters.fold_with(folder, binders)?;
                     Ok(ApplicationTy { name, parameters }.cast())
                 }
@@ -277,6 +314,8 @@ crate fn super_fold_ty(folder: &mut dyn Folder, ty: &Ty, binders: usize) -> Fall
             Ok(Ty::UnselectedProjection(proj.fold_with(folder, binders)?))
         }
         Ty::ForAll(ref quantified_ty) => Ok(Ty::ForAll(quantified_ty.fold_with(folder, binders)?)),
+        Ty::Dyn(ref dyn_ty) => Ok(Ty::Dyn(dyn_ty.fold_with(folder, binders)?)),
+        Ty::Opaque(ref opaque_ty) => Ok(Ty::Opaque(opaque_ty.fold_with(folder, binders)?)),
     }
 }
 
@@ -352,10 +391,36 @@ crate fn super_fold_lifetime(
     }
 }
 
+impl Fold for Const {
+    type Result = Self;
+    fn fold_with(&self, folder: &mut dyn Folder, binders: usize) -> Fallible<Self::Result> {
+        folder.fold_const(self, binders)
+    }
+}
+
+crate fn super_fold_const(
+    folder: &mut dyn Folder,
+    constant: &Const,
+    binders: usize,
+) -> Fallible<Const> {
+    match *constant {
+        Const::Var(depth) => if depth >= binders {
+            folder.fold_free_existential_const(depth - binders, binders)
+        } else {
+            Ok(Const::Var(depth))
+        },
+        Const::ForAll(universe) => folder.fold_free_universal_const(universe, binders),
+        Const::Value(value) => Ok(Const::Value(value)),
+    }
+}
+
 impl Fold for Substitution {
     type Result = Substitution;
     fn fold_with(&self, folder: &mut dyn Folder, binders: usize) -> Fallible<Self::Result> {
-        let parameters = self.parameters.fold_with(folder, binders)?;
+        let parameters = self.parameters
+            .iter()
+            .map(|p| p.fold_with(folder, binders))
+            .collect::<Fallible<_>>()?;
         Ok(Substitution { parameters })
     }
 }
@@ -378,6 +443,7 @@ copy_fold!(Identifier);
 copy_fold!(UniverseIndex);
 copy_fold!(ItemId);
 copy_fold!(usize);
+copy_fold!(bool);
 copy_fold!(QuantifierKind);
 // copy_fold!(TypeName); -- intentionally omitted! This is folded via `fold_ap`
 copy_fold!(());
@@ -422,12 +488,15 @@ macro_rules! enum_fold {
 }
 
 enum_fold!(PolarizedTraitRef[] { Positive(a), Negative(a) });
-enum_fold!(ParameterKind[T,L] { Ty(a), Lifetime(a) } where T: Fold, L: Fold);
+enum_fold!(ParameterKind[T,L,C] { Ty(a), Lifetime(a), Const(a) } where T: Fold, L: Fold, C: Fold);
 enum_fold!(WhereClauseAtom[] { Implemented(a), ProjectionEq(a) });
 enum_fold!(DomainGoal[] { Holds(a), WellFormed(a), FromEnv(a), Normalize(a), UnselectedNormalize(a),
-                          WellFormedTy(a), FromEnvTy(a), InScope(a), Derefs(a) });
+                          NormalizeConst(a),
+                          WellFormedTy(a), FromEnvTy(a), InScope(a), Derefs(a),
+                          Outlives(a), NotImplemented(a), Predicate(a), OpaqueEq(a), Reveal(a),
+                          IsFullyVisible(a) });
 enum_fold!(LeafGoal[] { EqGoal(a), DomainGoal(a) });
-enum_fold!(Constraint[] { LifetimeEq(a, b) });
+enum_fold!(Constraint[] { Outlives(a, b), RegionEq(a, b) });
 enum_fold!(Goal[] { Quantified(qkind, subgoal), Implies(wc, subgoal), And(g1, g2), Not(g),
                     Leaf(wc), CannotProve(a) });
 enum_fold!(ProgramClause[] { Implies(a), ForAll(a) });
@@ -560,18 +629,43 @@ struct_fold!(TraitRef {
     trait_id,
     parameters,
 });
+struct_fold!(DynTy { bounds, lifetime });
+struct_fold!(OpaqueTy {
+    opaque_ty_id,
+    parameters,
+});
+struct_fold!(DynBound {
+    trait_id,
+    parameters,
+});
 struct_fold!(Normalize { projection, ty });
 struct_fold!(ProjectionEq { projection, ty });
 struct_fold!(UnselectedNormalize { projection, ty });
+struct_fold!(ConstProjection {
+    associated_const_id,
+    parameters,
+});
+struct_fold!(NormalizeConst { projection, value });
+struct_fold!(OpaqueEq { opaque, ty });
 struct_fold!(AssociatedTyValue {
     associated_ty_id,
     value,
+    default,
 });
 struct_fold!(AssociatedTyValueBound { ty });
+struct_fold!(AssociatedConstValue {
+    associated_const_id,
+    value,
+});
 struct_fold!(Environment { clauses });
 struct_fold!(InEnvironment[F] { environment, goal } where F: Fold<Result = F>);
 struct_fold!(EqGoal { a, b });
 struct_fold!(Derefs { source, target });
+struct_fold!(Outlives { a, b });
+struct_fold!(PredicateApplication {
+    predicate_id,
+    parameters,
+});
 struct_fold!(ProgramClauseImplication {
     consequence,
     conditions,
@@ -99,6 +99,10 @@ impl ExistentialFolder for Shifter {
     ) -> Fallible<Lifetime> {
         Ok(Lifetime::Var(self.adjust(depth, binders)))
     }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        Ok(Const::Var(self.adjust(depth, binders)))
+    }
 }
 
 impl IdentityUniversalFolder for Shifter {}
@@ -141,6 +145,10 @@ impl ExistentialFolder for DownShifter {
     ) -> Fallible<Lifetime> {
         Ok(Lifetime::Var(self.adjust(depth, binders)?))
     }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        Ok(Const::Var(self.adjust(depth, binders)?))
+    }
 }
 
 impl IdentityUniversalFolder for DownShifter {}
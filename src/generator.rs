@@ -0,0 +1,145 @@
+//! Generates synthetic programs of a given shape, for use in
+//! benchmarks and fuzzing. Generation is deterministic: the same
+//! `GeneratorConfig` always yields the same program.
+
+use chalk_parse;
+use errors::Result;
+use ir::lowering::LowerProgram;
+use ir::Program;
+use solve::SolverChoice;
+use std::fmt::Write;
+
+mod test;
+
+/// The shape of a generated program.
+///
+/// The program declares `num_traits` traits `Trait0..TraitN` and
+/// `num_structs` structs `Struct0..StructM`. Each trait is implemented
+/// for `impls_per_trait` distinct structs, chosen at random.
+///
+/// Traits are grouped into chains of `where_clause_depth + 1`
+/// consecutive traits: an impl of `TraitI` requires that some struct
+/// implement `Trait(I + 1)`, unless `TraitI` is the last trait of its
+/// chain. Proving `StructJ: TraitI` for the first trait of a chain
+/// therefore takes up to `where_clause_depth` nested steps. If
+/// `cycles` is set, impls of the last trait of each chain require the
+/// first trait of the chain instead, closing the chain into a cycle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GeneratorConfig {
+    pub num_traits: usize,
+    pub num_structs: usize,
+    pub impls_per_trait: usize,
+    pub where_clause_depth: usize,
+    pub cycles: bool,
+    pub seed: u64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            num_traits: 10,
+            num_structs: 10,
+            impls_per_trait: 3,
+            where_clause_depth: 3,
+            cycles: false,
+            seed: 0,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    /// Generates the source text of the program.
+    pub fn generate_text(&self) -> String {
+        assert!(
+            self.impls_per_trait <= self.num_structs,
+            "cannot implement each trait for {} distinct structs out of {}",
+            self.impls_per_trait,
+            self.num_structs
+        );
+
+        let mut rng = XorShift::new(self.seed);
+        let mut text = String::new();
+
+        for trait_index in 0..self.num_traits {
+            writeln!(text, "trait Trait{} {{ }}", trait_index).unwrap();
+        }
+
+        for struct_index in 0..self.num_structs {
+            writeln!(text, "struct Struct{} {{ }}", struct_index).unwrap();
+        }
+
+        let chain_len = self.where_clause_depth + 1;
+        for trait_index in 0..self.num_traits {
+            let chain_start = trait_index - trait_index % chain_len;
+            let is_chain_end = trait_index % chain_len == self.where_clause_depth
+                || trait_index + 1 == self.num_traits;
+            let required_trait = if !is_chain_end {
+                Some(trait_index + 1)
+            } else if self.cycles && chain_start != trait_index {
+                Some(chain_start)
+            } else {
+                None
+            };
+
+            for struct_index in rng.choose_distinct(self.impls_per_trait, self.num_structs) {
+                write!(text, "impl Trait{} for Struct{}", trait_index, struct_index).unwrap();
+                if let Some(required_trait) = required_trait {
+                    write!(
+                        text,
+                        " where Struct{}: Trait{}",
+                        rng.below(self.num_structs),
+                        required_trait
+                    ).unwrap();
+                }
+                writeln!(text, " {{ }}").unwrap();
+            }
+        }
+
+        text
+    }
+
+    /// Generates the program and lowers it.
+    pub fn generate(&self, solver_choice: SolverChoice) -> Result<Program> {
+        chalk_parse::parse_program(&self.generate_text())?.lower(solver_choice)
+    }
+}
+
+/// A small, deterministic pseudo-random number generator
+/// (xorshift64*). We only need reproducibility, not quality.
+struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        XorShift {
+            state: (seed ^ 0x9E37_79B9_7F4A_7C15) | 1,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Returns `count` distinct numbers in `0..n`, in increasing order.
+    fn choose_distinct(&mut self, count: usize, n: usize) -> Vec<usize> {
+        // Partial Fisher-Yates shuffle.
+        let mut pool: Vec<usize> = (0..n).collect();
+        for i in 0..count {
+            let j = i + self.below(n - i);
+            pool.swap(i, j);
+        }
+        pool.truncate(count);
+        pool.sort();
+        pool
+    }
+}
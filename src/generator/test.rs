@@ -0,0 +1,67 @@
+#![cfg(test)]
+
+use ir;
+use solve::{SolveOptions, SolverChoice};
+use std::sync::Arc;
+use test_util::*;
+
+use super::GeneratorConfig;
+
+#[test]
+fn generation_is_deterministic() {
+    let config = GeneratorConfig {
+        seed: 42,
+        ..GeneratorConfig::default()
+    };
+    assert_eq!(config.generate_text(), config.generate_text());
+
+    let other_seed = GeneratorConfig { seed: 43, ..config };
+    assert_ne!(config.generate_text(), other_seed.generate_text());
+}
+
+#[test]
+fn generated_programs_lower_and_solve() {
+    for &cycles in &[false, true] {
+        for seed in 0..5 {
+            let config = GeneratorConfig {
+                cycles,
+                seed,
+                ..GeneratorConfig::default()
+            };
+            let program = Arc::new(config.generate(SolverChoice::slg()).unwrap());
+            assert_eq!(program.impl_data.len(), config.num_traits * config.impls_per_trait);
+
+            // Every goal must terminate, whatever the answer.
+            let env = program.environment();
+            ir::tls::set_current_program(&program, || {
+                for struct_index in 0..config.num_structs {
+                    let goal_text = format!("Struct{}: Trait0", struct_index);
+                    let goal = parse_and_lower_goal(&program, &goal_text).unwrap();
+                    SolverChoice::slg()
+                        .solve_root_goal(&env, &goal.into_peeled_goal(), SolveOptions::default())
+                        .unwrap();
+                }
+            });
+        }
+    }
+}
+
+#[test]
+fn cycles_close_chains() {
+    let config = GeneratorConfig {
+        num_traits: 2,
+        num_structs: 1,
+        impls_per_trait: 1,
+        where_clause_depth: 1,
+        cycles: true,
+        seed: 0,
+    };
+    assert_eq!(
+        config.generate_text(),
+        "trait Trait0 { }\n\
+         trait Trait1 { }\n\
+         struct Struct0 { }\n\
+         impl Trait0 for Struct0 where Struct0: Trait1 { }\n\
+         impl Trait1 for Struct0 where Struct0: Trait0 { }\n"
+    );
+}
@@ -0,0 +1,240 @@
+//! Opt-in checks of invariants that the IR should uphold as it passes
+//! from one phase to the next, enabled by setting the
+//! `CHALK_CHECK_INVARIANTS` environment variable (to anything but
+//! `0`). A violation means some earlier phase produced a corrupt term,
+//! and is reported by panicking right away, rather than leaving the
+//! solver to misbehave in some harder to diagnose way later on.
+//!
+//! The checks are:
+//!
+//! - after lowering, program clauses are closed: every de Bruijn index
+//!   refers to a binder within the clause;
+//! - after canonicalization, every variable in a canonical goal refers
+//!   to one of its binders, and the universes of its binders and
+//!   placeholders are all within the goal's `universes`;
+//! - after solving, the substitutions in a solution have one parameter
+//!   of the right kind per binder of the goal, and refer to no
+//!   variables besides their own binders, so no inference variables
+//!   escape from the solver.
+
+use chalk_parse::ast::Kinded;
+use fallible::*;
+use fold::{DefaultTypeFolder, ExistentialFolder, Fold, UniversalFolder};
+use ir::*;
+use solve::{Guidance, Solution};
+use std::env;
+use std::fmt::Debug;
+
+/// True if the invariant checks are enabled, through the
+/// `CHALK_CHECK_INVARIANTS` environment variable.
+crate fn enabled() -> bool {
+    env::var("CHALK_CHECK_INVARIANTS")
+        .ok()
+        .map_or(false, |s| !s.is_empty() && s != "0")
+}
+
+/// Panics if any `violations` were found in `value` after `phase`,
+/// rendering the offending term.
+crate fn assert_none<T: Debug>(phase: &str, value: &T, violations: Vec<String>) {
+    if !violations.is_empty() {
+        panic!(
+            "IR invariants violated after {}:\n  {}\nin: {:?}",
+            phase,
+            violations.join("\n  "),
+            value
+        );
+    }
+}
+
+/// The violations in a value that should be closed, such as a program
+/// clause.
+crate fn closed_violations<T: Fold>(value: &T) -> Vec<String> {
+    let mut checker = VariableChecker {
+        bound: 0,
+        universes: None,
+        violations: vec![],
+    };
+    value.fold_with(&mut checker, 0).unwrap();
+    checker.violations
+}
+
+/// The violations in a canonical value.
+crate fn u_canonical_violations<T: Fold>(value: &UCanonical<T>) -> Vec<String> {
+    let mut violations = binder_violations(&value.canonical.binders, value.universes);
+    violations.extend(canonical_violations(&value.canonical, value.universes));
+    violations
+}
+
+/// The violations in `solution`, given the goal it solves.
+crate fn solution_violations(goal: &UCanonical<InEnvironment<Goal>>, solution: &Solution) -> Vec<String> {
+    let substs: Vec<(&[ParameterKind<UniverseIndex>], &Substitution, Vec<String>)> = match solution {
+        Solution::Unique(answer) => vec![answer_violations(goal, answer)],
+        Solution::MultipleSolutions(answers) => answers
+            .iter()
+            .map(|answer| answer_violations(goal, answer))
+            .collect(),
+        Solution::Ambig(Guidance::Definite(subst), _)
+        | Solution::Ambig(Guidance::Suggested(subst), _) => vec![(
+            &subst.binders[..],
+            &subst.value,
+            canonical_violations(subst, goal.universes),
+        )],
+        Solution::Ambig(Guidance::Unknown, _) => vec![],
+    };
+
+    let mut violations = vec![];
+    for (binders, subst, value_violations) in substs {
+        violations.extend(value_violations);
+        violations.extend(binder_violations(binders, goal.universes));
+        violations.extend(substitution_violations(&goal.canonical.binders, subst));
+    }
+    violations
+}
+
+fn answer_violations<'a>(
+    goal: &UCanonical<InEnvironment<Goal>>,
+    answer: &'a Canonical<ConstrainedSubst>,
+) -> (&'a [ParameterKind<UniverseIndex>], &'a Substitution, Vec<String>) {
+    (
+        &answer.binders[..],
+        &answer.value.subst,
+        canonical_violations(answer, goal.universes),
+    )
+}
+
+/// Checks that `subst` has a parameter of the right kind for each of
+/// `binders`.
+fn substitution_violations(binders: &[ParameterKind<UniverseIndex>], subst: &Substitution) -> Vec<String> {
+    if binders.len() != subst.parameters.len() {
+        return vec![format!(
+            "substitution has {} parameters for {} binders",
+            subst.parameters.len(),
+            binders.len()
+        )];
+    }
+
+    binders
+        .iter()
+        .zip(&subst.parameters)
+        .enumerate()
+        .filter(|(_, (binder, parameter))| binder.kind() != parameter.kind())
+        .map(|(index, (binder, parameter))| {
+            format!(
+                "substitution maps {:?} variable ?{} to {:?}",
+                binder.kind(),
+                index,
+                parameter
+            )
+        })
+        .collect()
+}
+
+fn binder_violations(binders: &[ParameterKind<UniverseIndex>], universes: usize) -> Vec<String> {
+    binders
+        .iter()
+        .enumerate()
+        .filter(|(_, binder)| binder.into_inner().counter >= universes)
+        .map(|(index, binder)| {
+            format!(
+                "variable ?{} is in universe {:?}, but there are only {} universes",
+                index,
+                binder.into_inner(),
+                universes
+            )
+        })
+        .collect()
+}
+
+fn canonical_violations<T: Fold>(value: &Canonical<T>, universes: usize) -> Vec<String> {
+    let mut checker = VariableChecker {
+        bound: value.binders.len(),
+        universes: Some(universes),
+        violations: vec![],
+    };
+    value.fold_with(&mut checker, 0).unwrap();
+    checker.violations
+}
+
+/// A folder that leaves the value unchanged, recording the variables
+/// and placeholders in it that are out of range.
+struct VariableChecker {
+    /// The number of binders around the value being folded, which
+    /// free variables may refer to without being out of range.
+    bound: usize,
+
+    /// The number of universes placeholders may come from, if known.
+    universes: Option<usize>,
+
+    violations: Vec<String>,
+}
+
+impl VariableChecker {
+    fn unbound_variable(&mut self, depth: usize) {
+        self.violations.push(format!(
+            "variable ^{} is not bound, as there are only {} binders",
+            depth + self.bound,
+            self.bound
+        ));
+    }
+
+    fn check_universe(&mut self, universe: UniverseIndex) {
+        if let Some(universes) = self.universes {
+            if universe.counter >= universes {
+                self.violations.push(format!(
+                    "placeholder is in universe {:?}, but there are only {} universes",
+                    universe, universes
+                ));
+            }
+        }
+    }
+}
+
+impl DefaultTypeFolder for VariableChecker {}
+
+impl ExistentialFolder for VariableChecker {
+    // The canonical binders are already accounted for in `bound`, so
+    // every free variable that reaches here is out of range.
+    fn fold_free_existential_ty(&mut self, depth: usize, binders: usize) -> Fallible<Ty> {
+        self.unbound_variable(depth);
+        Ok(Ty::Var(depth + binders))
+    }
+
+    fn fold_free_existential_lifetime(
+        &mut self,
+        depth: usize,
+        binders: usize,
+    ) -> Fallible<Lifetime> {
+        self.unbound_variable(depth);
+        Ok(Lifetime::Var(depth + binders))
+    }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        self.unbound_variable(depth);
+        Ok(Const::Var(depth + binders))
+    }
+}
+
+impl UniversalFolder for VariableChecker {
+    fn fold_free_universal_ty(&mut self, universe: UniverseIndex, _binders: usize) -> Fallible<Ty> {
+        self.check_universe(universe);
+        Ok(TypeName::ForAll(universe).to_ty())
+    }
+
+    fn fold_free_universal_lifetime(
+        &mut self,
+        universe: UniverseIndex,
+        _binders: usize,
+    ) -> Fallible<Lifetime> {
+        self.check_universe(universe);
+        Ok(universe.to_lifetime())
+    }
+
+    fn fold_free_universal_const(
+        &mut self,
+        universe: UniverseIndex,
+        _binders: usize,
+    ) -> Fallible<Const> {
+        self.check_universe(universe);
+        Ok(universe.to_const())
+    }
+}
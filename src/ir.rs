@@ -2,18 +2,31 @@ use chalk_parse::ast;
 use fallible::*;
 use fold::{DefaultTypeFolder, ExistentialFolder, Fold, IdentityUniversalFolder};
 use fold::shift::Shift;
-use lalrpop_intern::InternedString;
+use fold::Subst;
+use lalrpop_intern::{intern, InternedString};
+use smallvec::SmallVec;
 use std::collections::{BTreeMap, BTreeSet};
-use std::sync::Arc;
+use std::hash::{Hash, Hasher};
+use std::fmt;
+use std::iter::FromIterator;
+use std::sync::{Arc, Mutex};
 
 #[macro_use]
 mod macros;
 
 crate mod could_match;
 crate mod debug;
+mod display;
 pub mod lowering;
+crate mod overrides;
+crate mod scope;
+crate mod serialization;
 pub mod tls;
 
+pub use self::display::Named;
+pub use self::overrides::TraitOverrides;
+pub use self::scope::TraitScope;
+
 crate type Identifier = InternedString;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -24,8 +37,14 @@ pub struct Program {
     /// For each struct/trait:
     crate type_kinds: BTreeMap<ItemId, TypeKind>,
 
+    /// For each struct/trait with parameter defaults, the default of
+    /// each of its parameters (including `Self` for a trait), if any.
+    /// A default is stated under the binders of the item, and only
+    /// mentions the parameters before it. Used during lowering only.
+    crate parameter_defaults: BTreeMap<ItemId, Vec<Option<Ty>>>,
+
     /// For each struct:
-    crate struct_data: BTreeMap<ItemId, StructDatum>,
+    crate adt_data: BTreeMap<ItemId, AdtDatum>,
 
     /// For each impl:
     crate impl_data: BTreeMap<ItemId, ImplDatum>,
@@ -36,6 +55,18 @@ pub struct Program {
     /// For each associated ty:
     crate associated_ty_data: BTreeMap<ItemId, AssociatedTyDatum>,
 
+    /// For each associated const:
+    crate associated_const_data: BTreeMap<ItemId, AssociatedConstDatum>,
+
+    /// For each inherent impl:
+    crate inherent_impl_data: BTreeMap<ItemId, InherentImplDatum>,
+
+    /// For each opaque type:
+    crate opaque_ty_data: BTreeMap<ItemId, OpaqueTyDatum>,
+
+    /// For each closure type:
+    crate closure_data: BTreeMap<ItemId, ClosureDatum>,
+
     /// For each default impl (automatically generated for auto traits):
     crate default_impl_data: Vec<DefaultImplDatum>,
 
@@ -44,6 +75,107 @@ pub struct Program {
 
     /// Special types and traits.
     crate lang_items: BTreeMap<LangItem, ItemId>,
+
+    /// The builtin rules enabled for the program.
+    crate builtin_traits: BuiltinTraits,
+
+    /// Which impls specialize which, found by the coherence check.
+    crate specialization_graph: ::coherence::SpecializationGraph,
+
+    /// For each struct, trait or impl declared with attributes:
+    crate item_attributes: BTreeMap<ItemId, Vec<ItemAttribute>>,
+
+    /// The scopes registered with `Program::register_scope`, by name.
+    crate trait_scopes: BTreeMap<Identifier, TraitScope>,
+
+    /// For each trait given overrides with `Program::override_trait`:
+    crate trait_overrides: BTreeMap<ItemId, TraitOverrides>,
+
+    /// The result of `environment`, once computed.
+    crate environment_cache: EnvironmentCache,
+}
+
+/// Memoizes `Program::environment`. Anything that modifies a program
+/// after lowering must call `Program::invalidate_environment`.
+///
+/// The cache is not part of the program's identity: it is ignored
+/// when comparing programs.
+#[derive(Default)]
+crate struct EnvironmentCache {
+    environment: Mutex<Option<Arc<ProgramEnvironment>>>,
+}
+
+impl EnvironmentCache {
+    crate fn get_or_insert_with(
+        &self,
+        op: impl FnOnce() -> ProgramEnvironment,
+    ) -> Arc<ProgramEnvironment> {
+        let mut environment = self.environment.lock().unwrap();
+        environment.get_or_insert_with(|| Arc::new(op())).clone()
+    }
+
+    crate fn clear(&mut self) {
+        *self.environment.get_mut().unwrap() = None;
+    }
+}
+
+impl Clone for EnvironmentCache {
+    fn clone(&self) -> Self {
+        EnvironmentCache {
+            environment: Mutex::new(self.environment.lock().unwrap().clone()),
+        }
+    }
+}
+
+impl PartialEq for EnvironmentCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for EnvironmentCache {}
+
+impl fmt::Debug for EnvironmentCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "EnvironmentCache")
+    }
+}
+
+/// Memoizes `ProgramEnvironment::program_hash`, which serializes the
+/// whole environment and is only needed by solvers with a cache.
+///
+/// Like `EnvironmentCache`, it is not part of the environment's
+/// identity: it is ignored when comparing, hashing or serializing
+/// environments.
+#[derive(Default)]
+crate struct ProgramHashCache {
+    crate hash: Mutex<Option<u64>>,
+}
+
+impl Clone for ProgramHashCache {
+    fn clone(&self) -> Self {
+        ProgramHashCache {
+            hash: Mutex::new(*self.hash.lock().unwrap()),
+        }
+    }
+}
+
+impl PartialEq for ProgramHashCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for ProgramHashCache {}
+
+impl Hash for ProgramHashCache {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl fmt::Debug for ProgramHashCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "ProgramHashCache")
+    }
 }
 
 impl Program {
@@ -63,9 +195,31 @@ impl Program {
         let (other_params, trait_params) = parameters.split_at(split_point);
         (associated_ty_data, trait_params, other_params)
     }
+
+    /// The attributes the given item was declared with, in order.
+    pub fn item_attributes(&self, item: ItemId) -> &[ItemAttribute] {
+        self.item_attributes.get(&item).map_or(&[][..], |attributes| &attributes[..])
+    }
+
+    /// The struct or trait called `name`, if any.
+    pub fn item_id(&self, name: &str) -> Option<ItemId> {
+        self.type_ids.get(&intern(name)).cloned()
+    }
+
+    /// The impls of the trait `trait_id`, in the order they were
+    /// declared.
+    pub fn impls_of(&self, trait_id: ItemId) -> Vec<ItemId> {
+        self.impl_data
+            .iter()
+            .filter(|(_, impl_datum)| {
+                impl_datum.binders.value.trait_ref.trait_ref().trait_id == trait_id
+            })
+            .map(|(&impl_id, _)| impl_id)
+            .collect()
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ProgramEnvironment {
     /// For each trait (used for debugging):
     crate trait_data: BTreeMap<ItemId, TraitDatum>,
@@ -73,16 +227,215 @@ pub struct ProgramEnvironment {
     /// For each associated type (used for debugging):
     crate associated_ty_data: BTreeMap<ItemId, AssociatedTyDatum>,
 
+    /// For each associated const, so that goals about it can be traced
+    /// back to its trait; see `GoalCache`.
+    crate associated_const_data: BTreeMap<ItemId, AssociatedConstDatum>,
+
     /// Compiled forms of the above:
     crate program_clauses: Vec<ProgramClause>,
+
+    /// Attributes of items, for clients of the solver to interpret.
+    crate item_attributes: BTreeMap<ItemId, Vec<ItemAttribute>>,
+
+    /// Overrides of how the solver treats the goals of some traits.
+    crate trait_overrides: BTreeMap<ItemId, TraitOverrides>,
+
+    /// For each trait with impls, those impls, so that solutions can be
+    /// traced back to the impls they may rely on; see `GoalCache`.
+    crate trait_impls: BTreeMap<ItemId, Vec<ItemId>>,
+
+    /// Special types and traits, and the builtin rules enabled for
+    /// them, for the clauses generated on demand; see
+    /// `rules::builtin_type_clauses`.
+    crate lang_items: BTreeMap<LangItem, ItemId>,
+    crate builtin_traits: BuiltinTraits,
+
+    /// The types declared `#[fundamental]`, for coherence.
+    crate fundamental_types: BTreeSet<ItemId>,
+
+    /// The result of `program_hash`, once computed.
+    #[serde(skip)]
+    crate program_hash: ProgramHashCache,
+}
+
+impl ProgramEnvironment {
+    /// A fingerprint of everything the solver knows about the program.
+    /// Used to check that cached results were computed for this same
+    /// program before reusing them, possibly by another process; see
+    /// `SolverCache`.
+    ///
+    /// The environment is serialized, with identifiers spelled out
+    /// rather than given by their interned indices, which depend on the
+    /// process, and hashed with `serialization::stable_hash`. This is
+    /// done the first time the hash is asked for; solvers only ask for
+    /// it when they have a cache to share results through.
+    pub fn program_hash(&self) -> u64 {
+        let mut hash = self.program_hash.hash.lock().unwrap();
+        *hash.get_or_insert_with(|| serialization::stable_hash(self))
+    }
+
+    /// True if impls of the given trait may be added by crates we cannot see,
+    /// which is the case for traits declared `extern`. For such traits we
+    /// cannot conclude `not { T: Trait }` from the absence of an impl; only an
+    /// explicit negative impl (see `DomainGoal::NotImplemented`) will do.
+    ///
+    /// A `#[fundamental]` trait is closed even when `extern`: nobody may add
+    /// an impl of it for an existing type without a breaking change.
+    crate fn is_open_world_trait(&self, trait_id: ItemId) -> bool {
+        let flags = &self.trait_data[&trait_id].binders.value.flags;
+        flags.external && !flags.fundamental
+    }
+
+    /// True if the given trait is both `extern` and `#[fundamental]`; see
+    /// `is_open_world_trait`.
+    crate fn is_fundamental_extern_trait(&self, trait_id: ItemId) -> bool {
+        let flags = &self.trait_data[&trait_id].binders.value.flags;
+        flags.external && flags.fundamental
+    }
+
+    /// True if the given type is an application of a `#[fundamental]` type
+    /// like `Box<T>`, or a reference, which coherence looks through.
+    crate fn is_fundamental_ty(&self, ty: &Ty) -> bool {
+        match *ty {
+            Ty::Apply(ApplicationTy { name: TypeName::ItemId(id), .. }) => {
+                self.fundamental_types.contains(&id)
+            }
+            Ty::Apply(ApplicationTy { name: TypeName::Ref(_), .. }) => true,
+            _ => false,
+        }
+    }
+
+    /// True if the given item is the lang item `lang_item`.
+    crate fn is_lang_item(&self, lang_item: LangItem, item_id: ItemId) -> bool {
+        self.lang_items.get(&lang_item) == Some(&item_id)
+    }
+
+    /// The attributes the given item was declared with, in order; see
+    /// `Program::item_attributes`.
+    pub fn item_attributes(&self, item: ItemId) -> &[ItemAttribute] {
+        self.item_attributes.get(&item).map_or(&[][..], |attributes| &attributes[..])
+    }
+
+    /// The items with an attribute `key`, with any value.
+    pub fn items_with_attribute<'a>(&'a self, key: &str) -> impl Iterator<Item = ItemId> + 'a {
+        let key = intern(key);
+        self.item_attributes
+            .iter()
+            .filter(move |(_, attributes)| attributes.iter().any(|a| a.key == key))
+            .map(|(&item, _)| item)
+    }
+}
+
+/// An attribute like `#[feature = "x"]`, or `#[hidden]` without a
+/// value, attached to a struct, trait or impl. The solver itself
+/// ignores these; they let clients layer on things like feature-gated
+/// impls without changes to the parser.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ItemAttribute {
+    #[serde(with = "serialization::identifier")]
+    crate key: Identifier,
+    #[serde(with = "serialization::optional_identifier")]
+    crate value: Option<InternedString>,
+}
+
+impl ItemAttribute {
+    pub fn key(&self) -> String {
+        self.key.to_string()
+    }
+
+    pub fn value(&self) -> Option<String> {
+        self.value.map(|value| value.to_string())
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum LangItem {
     DerefTrait,
+    SizedTrait,
+    FnTrait,
+    FnMutTrait,
+    FnOnceTrait,
+    CopyTrait,
+    CloneTrait,
+    UnsizeTrait,
+}
+
+/// Which of the rules that the solver provides itself, rather than
+/// deriving them from impls, are enabled for a program; see
+/// `LowerProgram::lower_with_builtins`. By default, all of them are.
+/// Disabling one makes the traits involved behave like any other.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BuiltinTraits {
+    /// Auto traits are implemented for every struct, enum or union
+    /// whose fields -- of any variant -- implement them, unless an
+    /// impl covers the type; see
+    /// `Program::add_default_impls`.
+    pub auto_traits: bool,
+
+    /// The `#[lang_sized]` trait is implemented for every struct, enum
+    /// or union whose fields implement it; see `AdtDatum::to_sized_clause`.
+    pub sized: bool,
+
+    /// `Derefs(T, U)` holds when `T` implements the `#[lang_deref]`
+    /// trait with `Target = U`, which references do, or when `T` is a
+    /// raw pointer to `U`; see `rules::ref_clauses`.
+    pub deref: bool,
+
+    /// Closures implement the `#[lang_fn]`, `#[lang_fn_mut]` and
+    /// `#[lang_fn_once]` traits their kind allows; see
+    /// `ClosureDatum::to_program_clauses`. Function pointers implement
+    /// all three; see `rules::fn_ptr_clauses`.
+    pub fn_traits: bool,
+
+    /// Function pointers, shared references and raw pointers implement
+    /// the `#[lang_copy]` and `#[lang_clone]` traits.
+    pub copy_clone: bool,
+
+    /// Arrays implement the `#[lang_unsize]` trait for slices of their
+    /// elements, and every type for the trait objects whose traits it
+    /// implements; see `rules::unsize_clauses`.
+    pub unsize: bool,
+
+    /// The never type implements every trait, as if its impls were
+    /// found by never-type fallback; see `rules::never_clauses`. Unlike
+    /// the other rules, this is not how Rust behaves, so it is off even
+    /// in `BuiltinTraits::all()`.
+    pub never_fallback: bool,
+}
+
+impl BuiltinTraits {
+    pub fn all() -> Self {
+        BuiltinTraits {
+            auto_traits: true,
+            sized: true,
+            deref: true,
+            fn_traits: true,
+            copy_clone: true,
+            unsize: true,
+            never_fallback: false,
+        }
+    }
+
+    pub fn none() -> Self {
+        BuiltinTraits {
+            auto_traits: false,
+            sized: false,
+            deref: false,
+            fn_traits: false,
+            copy_clone: false,
+            unsize: false,
+            never_fallback: false,
+        }
+    }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+impl Default for BuiltinTraits {
+    fn default() -> Self {
+        BuiltinTraits::all()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 /// The set of assumptions we've made so far, and the current number of
 /// universal (forall) quantifiers we're within.
 pub struct Environment {
@@ -90,22 +443,44 @@ pub struct Environment {
 }
 
 impl Environment {
-    crate fn new() -> Arc<Self> {
+    /// The empty environment, with no hypotheses.
+    pub fn new() -> Arc<Self> {
         Arc::new(Environment { clauses: vec![] })
     }
 
-    crate fn add_clauses<I>(&self, clauses: I) -> Arc<Self>
+    /// True if the environment assumes that `a` outlives `b`, as with
+    /// `if ('a: 'b) { ... }`.
+    crate fn assumes_outlives(&self, a: Lifetime, b: Lifetime) -> bool {
+        let outlives = DomainGoal::Outlives(Outlives {
+            a: ParameterKind::Lifetime(a),
+            b,
+        });
+        self.clauses.iter().any(|clause| match *clause {
+            ProgramClause::Implies(ref implication) => {
+                implication.conditions.is_empty() && implication.consequence == outlives
+            }
+            ProgramClause::ForAll(_) => false,
+        })
+    }
+
+    /// Extends `env` with `clauses`. If every clause is already
+    /// present, `env` itself is returned, so that repeatedly assuming
+    /// the same hypotheses does not allocate new environments.
+    crate fn add_clauses<I>(env: &Arc<Self>, clauses: I) -> Arc<Self>
     where
         I: IntoIterator<Item = ProgramClause>,
     {
-        let mut env = self.clone();
-        let env_clauses: BTreeSet<_> = env.clauses.into_iter().chain(clauses).collect();
-        env.clauses = env_clauses.into_iter().collect();
-        Arc::new(env)
+        let env_clauses: BTreeSet<_> = env.clauses.iter().cloned().chain(clauses).collect();
+        if env_clauses.len() == env.clauses.len() {
+            return env.clone();
+        }
+        Arc::new(Environment {
+            clauses: env_clauses.into_iter().collect(),
+        })
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct InEnvironment<G> {
     crate environment: Arc<Environment>,
     crate goal: G,
@@ -130,7 +505,7 @@ impl<G> InEnvironment<G> {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum TypeName {
     /// a type like `Vec<T>`
     ItemId(ItemId),
@@ -140,6 +515,44 @@ pub enum TypeName {
 
     /// an associated type like `Iterator::Item`; see `AssociatedType` for details
     AssociatedType(ItemId),
+
+    /// a tuple type like `(A, B)`, with the given number of elements
+    Tuple(usize),
+
+    /// a function pointer type like `fn(A, B) -> C`, with the given
+    /// number of arguments; the parameters are the argument types, then
+    /// the return type
+    FnPtr(usize),
+
+    /// a slice type like `[T]`
+    Slice,
+
+    /// an array type like `[T; 3]`, with the given length
+    Array(usize),
+
+    /// the string slice type, `str`
+    Str,
+
+    /// a scalar type like `i32` or `bool`
+    Scalar(#[serde(with = "serialization::ScalarTypeDef")] ast::ScalarType),
+
+    /// the never type, `!`
+    Never,
+
+    /// a reference type like `&'a T` or `&'a mut T`; the parameters are
+    /// the lifetime, then the referent type
+    Ref(Mutability),
+
+    /// a raw pointer type like `*const T` or `*mut T`
+    RawPtr(Mutability),
+}
+
+/// Whether a reference or raw pointer allows mutation: `&'a mut T` and
+/// `*mut T` do, `&'a T` and `*const T` do not.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Mutability {
+    Mut,
+    Not,
 }
 
 impl TypeName {
@@ -151,13 +564,14 @@ impl TypeName {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct UniverseIndex {
     crate counter: usize,
 }
 
 impl UniverseIndex {
-    crate const ROOT: UniverseIndex = UniverseIndex { counter: 0 };
+    /// The universe of everything that is not under a `forall`.
+    pub const ROOT: UniverseIndex = UniverseIndex { counter: 0 };
 
     crate fn root() -> UniverseIndex {
         Self::ROOT
@@ -171,6 +585,10 @@ impl UniverseIndex {
         Lifetime::ForAll(self)
     }
 
+    crate fn to_const(self) -> Const {
+        Const::ForAll(self)
+    }
+
     crate fn next(self) -> UniverseIndex {
         UniverseIndex {
             counter: self.counter + 1,
@@ -178,7 +596,7 @@ impl UniverseIndex {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ItemId {
     crate index: usize,
 }
@@ -192,8 +610,19 @@ pub struct TypeKind {
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum TypeSort {
-    Struct,
+    /// A struct, enum or union.
+    Adt,
     Trait,
+
+    /// A user-defined predicate (`predicate Reaches(T, U);`). These
+    /// share the namespace of types, but cannot be used as types.
+    Predicate,
+
+    /// An opaque type (`type Foo = impl Bar = Baz;`).
+    Opaque,
+
+    /// A closure type (`closure c(i32) -> u32;`).
+    Closure,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -206,9 +635,33 @@ pub struct ImplDatumBound {
     crate trait_ref: PolarizedTraitRef,
     crate where_clauses: Vec<QuantifiedDomainGoal>,
     crate associated_ty_values: Vec<AssociatedTyValue>,
+    crate associated_const_values: Vec<AssociatedConstValue>,
     crate specialization_priority: usize,
 }
 
+/// An impl without a trait, like `impl<T> Foo<T> { type Bar = T; }`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InherentImplDatum {
+    crate binders: Binders<InherentImplDatumBound>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InherentImplDatumBound {
+    crate self_ty: ApplicationTy,
+    crate where_clauses: Vec<QuantifiedDomainGoal>,
+    crate associated_ty_values: Vec<InherentAssociatedTyValue>,
+}
+
+/// An associated type defined in an inherent impl. Since there is no
+/// trait declaring it, it is identified by its name alone.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InherentAssociatedTyValue {
+    crate name: Identifier,
+
+    // note: these binders are in addition to those from the impl
+    crate value: Binders<AssociatedTyValueBound>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct DefaultImplDatum {
     crate binders: Binders<DefaultImplDatumBound>,
@@ -221,44 +674,134 @@ pub struct DefaultImplDatumBound {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct StructDatum {
-    crate binders: Binders<StructDatumBound>,
+pub struct OpaqueTyDatum {
+    crate opaque_ty_id: ItemId,
+    crate binders: Binders<OpaqueTyDatumBound>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OpaqueTyDatumBound {
+    /// The bounds the opaque type is known to satisfy, e.g. `Bar` in
+    /// `type Foo = impl Bar = Baz;`, stated about `Foo`.
+    crate bounds: Vec<WhereClauseAtom>,
+
+    /// The type the opaque type stands for, e.g. `Baz` above.
+    crate hidden_ty: Ty,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClosureDatum {
+    crate closure_id: ItemId,
+    crate binders: Binders<ClosureDatumBound>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClosureDatumBound {
+    crate kind: ClosureKind,
+
+    /// The types of the arguments, e.g. `[i32]` for `closure c(i32) -> u32;`.
+    crate inputs: Vec<Ty>,
+
+    /// The return type, e.g. `u32` above.
+    crate output: Ty,
+}
+
+/// The most demanding `Fn` trait a closure implements.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ClosureKind {
+    Fn,
+    FnMut,
+    FnOnce,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct StructDatumBound {
+pub struct AdtDatum {
+    crate binders: Binders<AdtDatumBound>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AdtDatumBound {
     crate self_ty: ApplicationTy,
-    crate fields: Vec<Ty>,
+    crate kind: AdtKind,
+
+    /// The variants of an enum, in order; a struct or union has exactly one.
+    crate variants: Vec<AdtVariant>,
     crate where_clauses: Vec<QuantifiedDomainGoal>,
-    crate flags: StructFlags,
+    crate flags: AdtFlags,
+}
+
+impl AdtDatumBound {
+    /// The types of the fields of all variants, in order.
+    crate fn fields(&self) -> Vec<Ty> {
+        self.variants
+            .iter()
+            .flat_map(|variant| variant.fields.iter().cloned())
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AdtKind {
+    Struct,
+    Enum,
+    Union,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct StructFlags {
-    crate external: bool,
+pub struct AdtVariant {
+    crate name: Identifier,
+    crate fields: Vec<Ty>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AdtFlags {
+    /// Whether this type is declared by an upstream crate rather than the
+    /// one whose impls we check, as `extern` or `#[upstream]` say; such a
+    /// type is not local for the orphan rules.
+    crate external: bool,
+
+    /// Whether this type is `#[fundamental]`, like `Box`. Such a type is
+    /// transparent to coherence: `Box<T>` counts as local exactly when `T`
+    /// does.
+    crate fundamental: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TraitDatum {
     crate binders: Binders<TraitDatumBound>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TraitDatumBound {
     crate trait_ref: TraitRef,
     crate where_clauses: Vec<QuantifiedDomainGoal>,
     crate flags: TraitFlags,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TraitFlags {
     crate auto: bool,
     crate marker: bool,
+
+    /// Whether this trait is declared by an upstream crate rather than the
+    /// one whose impls we check, as `extern` or `#[upstream]` say. Impls
+    /// of it must obey the orphan rules, and other crates may add impls
+    /// of it; see `ProgramEnvironment::is_open_world_trait`.
     crate external: bool,
+
+    /// Whether this trait is `#[fundamental]`, like `Sized`: adding an impl
+    /// of it for an existing type is a breaking change, so coherence may
+    /// rely on such impls not appearing later.
+    crate fundamental: bool,
     pub deref: bool,
+    pub sized: bool,
+
+    /// Whether this is a trait alias, like `trait Alias = Foo + Bar;`,
+    /// whose bounds are its where clauses.
+    crate alias: bool,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct AssociatedTyDatum {
     /// The trait this associated type is defined in.
     crate trait_id: ItemId,
@@ -267,13 +810,19 @@ pub struct AssociatedTyDatum {
     crate id: ItemId,
 
     /// Name of this associated type.
+    #[serde(with = "serialization::identifier")]
     crate name: Identifier,
 
     /// Parameters on this associated type, beginning with those from the trait,
     /// but possibly including more.
+    #[serde(with = "serialization::identifier_kinds")]
     crate parameter_kinds: Vec<ParameterKind<Identifier>>,
 
-    // FIXME: inline bounds on the associated ty need to be implemented
+    /// Bounds on the associated type itself, e.g. `Clone` in `type
+    /// Item: Clone`. These are stated about the projection
+    /// `<Self as Trait>::Item`, under the same binders as
+    /// `parameter_kinds`.
+    crate bounds: Vec<WhereClauseAtom>,
 
     /// Where clauses that must hold for the projection be well-formed.
     crate where_clauses: Vec<QuantifiedDomainGoal>,
@@ -285,6 +834,10 @@ pub struct AssociatedTyValue {
 
     // note: these binders are in addition to those from the impl
     crate value: Binders<AssociatedTyValueBound>,
+
+    /// Whether the value is `default`, and hence only used for the
+    /// types that no impl overriding it applies to.
+    crate default: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -293,7 +846,28 @@ pub struct AssociatedTyValueBound {
     crate ty: Ty,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AssociatedConstDatum {
+    /// The trait this associated const is defined in.
+    crate trait_id: ItemId,
+
+    /// The ID of this associated const.
+    crate id: ItemId,
+
+    /// Name of this associated const.
+    #[serde(with = "serialization::identifier")]
+    crate name: Identifier,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AssociatedConstValue {
+    crate associated_const_id: ItemId,
+
+    /// The const that we normalize to. The X in `const N: usize = X`.
+    crate value: Const,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Ty {
     /// References the binding at the given depth (deBruijn index
     /// style). In an inference context (i.e., when solving goals),
@@ -303,6 +877,8 @@ pub enum Ty {
     Projection(ProjectionTy),
     UnselectedProjection(UnselectedProjectionTy),
     ForAll(Box<QuantifiedTy>),
+    Dyn(DynTy),
+    Opaque(OpaqueTy),
 }
 
 impl Ty {
@@ -324,29 +900,93 @@ impl Ty {
 
 /// for<'a...'z> X -- all binders are instantiated at once,
 /// and we use deBruijn indices within `self.ty`
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct QuantifiedTy {
     crate num_binders: usize,
     crate ty: Ty,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// dyn Foo<T> + Send + 'a -- a trait object type. It implements each
+/// trait in `bounds` and their supertraits (and, since it is only ever
+/// built from object-safe traits, nothing else is checked); in
+/// particular, it implements an auto trait just when the auto trait is
+/// among its bounds or their supertraits.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DynTy {
+    /// Sorted by trait, without duplicates, so that equal trait object
+    /// types are spelled the same way.
+    crate bounds: Vec<DynBound>,
+    crate lifetime: Option<Lifetime>,
+}
+
+/// An opaque type applied to its parameters, like `Foo<T>` given
+/// `type Foo<T> = impl Bar = Vec<T>;`. It is a type of its own, equal to
+/// no other, except where its hidden type is revealed; see
+/// `DomainGoal::OpaqueEq`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OpaqueTy {
+    crate opaque_ty_id: ItemId,
+    crate parameters: Vec<Parameter>,
+}
+
+/// One trait bound of a trait object type: `Foo<T>` in `dyn Foo<T>`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DynBound {
+    crate trait_id: ItemId,
+
+    /// The parameters of the trait, not including `Self`.
+    crate parameters: Vec<Parameter>,
+}
+
+impl DynBound {
+    /// The trait reference `self_ty: Foo<T>` for this bound.
+    crate fn trait_ref(&self, self_ty: Ty) -> TraitRef {
+        TraitRef {
+            trait_id: self.trait_id,
+            parameters: Some(ParameterKind::Ty(self_ty))
+                .into_iter()
+                .chain(self.parameters.iter().cloned())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Lifetime {
     /// See Ty::Var(_).
     Var(usize),
     ForAll(UniverseIndex),
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// The value of a const parameter, like `N` in `struct Foo<const N:
+/// usize>`. The type given for a const parameter is not checked.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Const {
+    /// See Ty::Var(_).
+    Var(usize),
+    ForAll(UniverseIndex),
+
+    /// A concrete value, like `3` in `Foo<3>`.
+    Value(usize),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct ApplicationTy {
     crate name: TypeName,
     crate parameters: Vec<Parameter>,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub enum ParameterKind<T, L = T> {
+impl ApplicationTy {
+    pub fn new(name: TypeName, parameters: Vec<Parameter>) -> Self {
+        ApplicationTy { name, parameters }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ParameterKind<T, L = T, C = T> {
     Ty(T),
     Lifetime(L),
+    Const(C),
 }
 
 impl<T> ParameterKind<T> {
@@ -354,6 +994,7 @@ impl<T> ParameterKind<T> {
         match self {
             ParameterKind::Ty(t) => t,
             ParameterKind::Lifetime(t) => t,
+            ParameterKind::Const(t) => t,
         }
     }
 
@@ -364,11 +1005,12 @@ impl<T> ParameterKind<T> {
         match self {
             ParameterKind::Ty(t) => ParameterKind::Ty(op(t)),
             ParameterKind::Lifetime(t) => ParameterKind::Lifetime(op(t)),
+            ParameterKind::Const(t) => ParameterKind::Const(op(t)),
         }
     }
 }
 
-impl<T, L> ParameterKind<T, L> {
+impl<T, L, C> ParameterKind<T, L, C> {
     crate fn assert_ty_ref(&self) -> &T {
         self.as_ref().ty().unwrap()
     }
@@ -377,10 +1019,15 @@ impl<T, L> ParameterKind<T, L> {
         self.as_ref().lifetime().unwrap()
     }
 
-    crate fn as_ref(&self) -> ParameterKind<&T, &L> {
+    crate fn assert_const_ref(&self) -> &C {
+        self.as_ref().constant().unwrap()
+    }
+
+    crate fn as_ref(&self) -> ParameterKind<&T, &L, &C> {
         match *self {
             ParameterKind::Ty(ref t) => ParameterKind::Ty(t),
             ParameterKind::Lifetime(ref l) => ParameterKind::Lifetime(l),
+            ParameterKind::Const(ref c) => ParameterKind::Const(c),
         }
     }
 
@@ -397,13 +1044,21 @@ impl<T, L> ParameterKind<T, L> {
             _ => None,
         }
     }
+
+    crate fn constant(self) -> Option<C> {
+        match self {
+            ParameterKind::Const(c) => Some(c),
+            _ => None,
+        }
+    }
 }
 
-impl<T, L> ast::Kinded for ParameterKind<T, L> {
+impl<T, L, C> ast::Kinded for ParameterKind<T, L, C> {
     fn kind(&self) -> ast::Kind {
         match *self {
             ParameterKind::Ty(_) => ast::Kind::Ty,
             ParameterKind::Lifetime(_) => ast::Kind::Lifetime,
+            ParameterKind::Const(_) => ast::Kind::Const,
         }
     }
 }
@@ -433,20 +1088,22 @@ impl<'a> ToParameter for (&'a ParameterKind<()>, usize) {
         match *binder {
             ParameterKind::Lifetime(_) => ParameterKind::Lifetime(Lifetime::Var(index)),
             ParameterKind::Ty(_) => ParameterKind::Ty(Ty::Var(index)),
+            ParameterKind::Const(_) => ParameterKind::Const(Const::Var(index)),
         }
     }
 }
 
-crate type Parameter = ParameterKind<Ty, Lifetime>;
+pub type Parameter = ParameterKind<Ty, Lifetime, Const>;
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct ProjectionTy {
     crate associated_ty_id: ItemId,
     crate parameters: Vec<Parameter>,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct UnselectedProjectionTy {
+    #[serde(with = "serialization::identifier")]
     crate type_name: Identifier,
     crate parameters: Vec<Parameter>,
 }
@@ -459,7 +1116,7 @@ pub enum ProjectionTyEnum<S = ProjectionTy, U = UnselectedProjectionTy> {
 
 crate type ProjectionTyRefEnum<'a> = ProjectionTyEnum<&'a ProjectionTy, &'a UnselectedProjectionTy>;
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct TraitRef {
     crate trait_id: ItemId,
     crate parameters: Vec<Parameter>,
@@ -487,22 +1144,37 @@ impl PolarizedTraitRef {
 }
 
 /// "Basic" where clauses which have a WF/FromEnv version of themselves.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum WhereClauseAtom {
     Implemented(TraitRef),
     ProjectionEq(ProjectionEq),
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub struct Derefs {
     pub source: Ty,
     pub target: Ty,
 }
 
+/// Requires that a type or lifetime outlive a lifetime, as in `T: 'a`
+/// or `'a: 'b`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Outlives {
+    crate a: Parameter,
+    crate b: Lifetime,
+}
+
+/// An application of a user-defined predicate, e.g. `Reaches(A, B)`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PredicateApplication {
+    pub predicate_id: ItemId,
+    pub parameters: Vec<Parameter>,
+}
+
 /// A "domain goal" is a goal that is directly about Rust, rather than a pure
 /// logical statement. As much as possible, the Chalk solver should avoid
 /// decomposing this enum, and instead treat its values opaquely.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum DomainGoal {
     Holds(WhereClauseAtom),
 
@@ -529,12 +1201,31 @@ pub enum DomainGoal {
     ///     }
     /// }
     /// ```
+    ///
+    /// No impl proves `FromEnv`: it only holds if assumed, directly or
+    /// through the reverse rules of another `FromEnv` hypothesis.
     FromEnv(WhereClauseAtom),
 
+    /// A predicate which is true if an explicit negative impl rules out the
+    /// given trait ref. For example, given:
+    ///
+    /// ```notrust
+    /// impl<T> !Send for Rc<T> { }
+    /// ```
+    ///
+    /// we have the rule `forall<T> { NotImplemented(Rc<T>: Send) }`. Unlike
+    /// `not { Rc<T>: Send }`, which relies on negation as failure and hence on
+    /// a closed world, this predicate remains sound if other crates may add
+    /// impls, since coherence forbids a positive impl overlapping a negative one.
+    NotImplemented(TraitRef),
 
     Normalize(Normalize),
     UnselectedNormalize(UnselectedNormalize),
 
+    /// Like `Normalize`, for associated consts: `NormalizeConst(<T as
+    /// Foo>::N -> 3)` holds if `T: Foo` by an impl with `const N: usize = 3`.
+    NormalizeConst(NormalizeConst),
+
     /// A predicate which is true is some type is well-formed.
     /// For example, given the following type definition:
     ///
@@ -567,7 +1258,60 @@ pub enum DomainGoal {
     /// Derefs(T, U) :- Implemented(T: Deref<Target = U>)
     /// ```
     /// In Rust there are also raw pointers which can be deref'd but do not implement Deref.
-    Derefs(Derefs)
+    Derefs(Derefs),
+
+    /// Whether a type or lifetime outlives a lifetime. Given `struct
+    /// Foo<'a, T>`, we have:
+    ///
+    /// ```notrust
+    /// forall<'a, T, 'b> { Outlives(Foo<'a, T>, 'b) :- Outlives('a, 'b), Outlives(T, 'b) }
+    /// ```
+    ///
+    /// No program clauses relate two lifetimes: the solvers prove
+    /// `Outlives('a: 'b)` directly, returning it as a region constraint
+    /// unless `'a` and `'b` are equal or the environment assumes it
+    /// (see `InferenceTable::outlives`). A type parameter, on the other
+    /// hand, is only known to outlive a lifetime if the environment
+    /// says so.
+    Outlives(Outlives),
+
+    /// A predicate declared by the program itself, e.g. given
+    ///
+    /// ```notrust
+    /// predicate Reaches(T, U);
+    /// forall<T> { Reaches(T, T) }
+    /// ```
+    ///
+    /// `Reaches(A, B)` holds only as far as the program's clauses say
+    /// so. This allows experimenting with new predicates without
+    /// teaching the solver about them.
+    Predicate(PredicateApplication),
+
+    /// Whether an opaque type is equal to a type: only where the hidden
+    /// type of the opaque type is revealed, and the type is equal to
+    /// that. Given `type Foo<T> = impl Bar = Vec<T>;`, we have:
+    ///
+    /// ```notrust
+    /// forall<T> { OpaqueEq(Foo<T> = Vec<T>) :- Reveal }
+    /// ```
+    ///
+    /// Unifying an opaque type with any other type yields this goal.
+    OpaqueEq(OpaqueEq),
+
+    /// Whether the hidden types of opaque types are visible, as they are
+    /// within `if (Reveal) { ... }`. Nothing else proves it.
+    Reveal(()),
+
+    /// Whether a type mentions no placeholders, like the `!1` that `T`
+    /// becomes in `forall<T> { ... }`, nor other types we cannot see
+    /// into, like projections and opaque types. It holds for `Vec<u32>`
+    /// but not for `Vec<!1>`, and is ambiguous for `?0`. The clauses are
+    /// generated on demand; see `rules::builtin_type_clauses`.
+    ///
+    /// A `default` associated type value is only revealed for fully
+    /// visible types, since some specializing impl may apply to any
+    /// type we cannot see.
+    IsFullyVisible(Ty),
 }
 
 pub type QuantifiedDomainGoal = Binders<DomainGoal>;
@@ -593,7 +1337,7 @@ impl DomainGoal {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 /// A goal that does not involve any logical connectives. Equality is treated
 /// specially by the logic (as with most first-order logics), since it interacts
 /// with unification etc.
@@ -602,7 +1346,7 @@ pub enum LeafGoal {
     DomainGoal(DomainGoal),
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct EqGoal {
     crate a: Parameter,
     crate b: Parameter,
@@ -612,21 +1356,46 @@ pub struct EqGoal {
 /// type. A projection `T::Foo` normalizes to the type `U` if we can
 /// **match it to an impl** and that impl has a `type Foo = V` where
 /// `U = V`.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Normalize {
     crate projection: ProjectionTy,
     crate ty: Ty,
 }
 
+/// An associated const projection, like `<T as Foo>::N`. Associated
+/// consts have no parameters of their own, so the parameters are
+/// those of the trait ref.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ConstProjection {
+    crate associated_const_id: ItemId,
+    crate parameters: Vec<Parameter>,
+}
+
+/// Proves that the given associated const projection normalizes to
+/// the given const; see `DomainGoal::NormalizeConst`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NormalizeConst {
+    crate projection: ConstProjection,
+    crate value: Const,
+}
+
 /// Proves **equality** between a projection `T::Foo` and a type
 /// `U`. Equality can be proven via normalization, but we can also
 /// prove that `T::Foo = V::Foo` if `T = V` without normalizing.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct ProjectionEq {
     crate projection: ProjectionTy,
     crate ty: Ty,
 }
 
+/// Proves **equality** between an opaque type and a type `U`; see
+/// `DomainGoal::OpaqueEq`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OpaqueEq {
+    crate opaque: OpaqueTy,
+    crate ty: Ty,
+}
+
 /// Indicates that the trait where the associated type belongs to is
 /// not yet known, i.e. is unselected. For example, a normal
 /// `Normalize` would be of the form `<Vec<T> as Iterator>::Item ->
@@ -644,7 +1413,7 @@ pub struct ProjectionEq {
 ///     InScope(Iterator),
 ///     <Vec<T> as Iterator>::Item -> T
 /// ```
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct UnselectedNormalize {
     crate projection: UnselectedProjectionTy,
     crate ty: Ty,
@@ -657,7 +1426,7 @@ pub struct UnselectedNormalize {
 ///
 /// (IOW, we use deBruijn indices, where binders are introduced in reverse order
 /// of `self.binders`.)
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Binders<T> {
     crate binders: Vec<ParameterKind<()>>,
     crate value: T,
@@ -711,13 +1480,13 @@ impl<V: IntoIterator> Iterator for BindersIntoIterator<V> {
 /// Represents one clause of the form `consequence :- conditions` where
 /// `conditions = cond_1 && cond_2 && ...` is the conjunction of the individual
 /// conditions.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ProgramClauseImplication {
     crate consequence: DomainGoal,
     crate conditions: Vec<Goal>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum ProgramClause {
     Implies(ProgramClauseImplication),
     ForAll(Binders<ProgramClauseImplication>),
@@ -746,7 +1515,7 @@ impl ProgramClause {
 /// All unresolved existential variables are "renumbered" according to their
 /// first appearance; the kind/universe of the variable is recorded in the
 /// `binders` field.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Canonical<T> {
     crate value: T,
     crate binders: Vec<ParameterKind<UniverseIndex>>,
@@ -784,6 +1553,64 @@ impl<T> Canonical<T> {
         infer.rollback_to(snapshot);
         result.quantified
     }
+
+    /// The kinds of the variables bound by this value, along with the
+    /// universe each variable lives in.
+    pub fn binders(&self) -> &[ParameterKind<UniverseIndex>] {
+        &self.binders
+    }
+
+    /// The value, in terms of the variables bound by `binders`.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Replaces the bound variables with the parameters produced by
+    /// `fresh`, which is invoked once per binder, in order. This lets
+    /// callers instantiate an answer in their own inference context:
+    /// `fresh` should return a new variable of the binder's kind,
+    /// visible from the binder's universe.
+    pub fn instantiate_with<F>(&self, fresh: F) -> T::Result
+    where
+        F: FnMut(&ParameterKind<UniverseIndex>) -> Parameter,
+        T: Fold,
+    {
+        let parameters: Vec<_> = self.binders.iter().map(fresh).collect();
+        Subst::apply(&parameters, &self.value)
+    }
+
+    /// Replaces each bound variable with a placeholder in a universe
+    /// of its own, above all the universes already found in the value.
+    /// Returns the result along with the placeholders, in the order of
+    /// the binders they replaced.
+    pub fn skolemize(&self) -> (T::Result, Vec<Parameter>)
+    where
+        T: Fold,
+    {
+        use solve::infer::ucanonicalize::UniverseMap;
+        let mut next_universe = self.binders
+            .iter()
+            .map(|pk| pk.into_inner())
+            .chain(Some(UniverseMap::collect(&self.value).max_universe()))
+            .max()
+            .unwrap()
+            .counter;
+        let mut placeholders = vec![];
+        let value = self.instantiate_with(|pk| {
+            next_universe += 1;
+            let universe = UniverseIndex {
+                counter: next_universe,
+            };
+            let placeholder = match pk {
+                ParameterKind::Ty(_) => ParameterKind::Ty(TypeName::ForAll(universe).to_ty()),
+                ParameterKind::Lifetime(_) => ParameterKind::Lifetime(universe.to_lifetime()),
+                ParameterKind::Const(_) => ParameterKind::Const(universe.to_const()),
+            };
+            placeholders.push(placeholder.clone());
+            placeholder
+        });
+        (value, placeholders)
+    }
 }
 
 /// A "universe canonical" value. This is a wrapper around a
@@ -792,7 +1619,76 @@ impl<T> Canonical<T> {
 /// distinctions.
 ///
 /// To produce one of these values, use the `u_canonicalize` method.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// The source-level names of the placeholders in a peeled goal -- for
+/// example, that `'!1` was written `'a` -- used to render solutions
+/// in terms the user wrote. See `Goal::into_peeled_goal_with_names`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlaceholderNames {
+    names: BTreeMap<UniverseIndex, ParameterKind<Identifier>>,
+
+    /// The canonical variable that each type hole (`_`) of the goal
+    /// became, in order; see `Goal::into_peeled_goal_with_holes`.
+    holes: Vec<usize>,
+
+    style: PlaceholderStyle,
+}
+
+/// How `PlaceholderNames::named` renders the placeholders that have
+/// names.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PlaceholderStyle {
+    /// Leave placeholders as they are, e.g. `!1` and `'!2`.
+    Universe,
+
+    /// Use the names of the binders the placeholders came from, e.g.
+    /// `T` and `'a`.
+    Name,
+
+    /// Use the binder name followed by the universe, e.g. `T/U1` and
+    /// `'a/U2`, which tells apart binders with the same name.
+    NameAndUniverse,
+}
+
+impl Default for PlaceholderStyle {
+    fn default() -> Self {
+        PlaceholderStyle::Name
+    }
+}
+
+impl PlaceholderNames {
+    /// Renders placeholders in the given style from now on.
+    pub fn with_style(mut self, style: PlaceholderStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn style(&self) -> PlaceholderStyle {
+        self.style
+    }
+
+    /// The name of the placeholder of the given universe, e.g. `T` or
+    /// `'a`.
+    pub fn name(&self, universe: UniverseIndex) -> Option<Identifier> {
+        self.names.get(&universe).map(|name| name.clone().into_inner())
+    }
+
+    /// The name of `lifetime`, if it is a named placeholder. Useful for
+    /// the lifetimes in a `RegionConstraintSet`.
+    pub fn lifetime_name(&self, lifetime: Lifetime) -> Option<Identifier> {
+        match lifetime {
+            Lifetime::ForAll(universe) => self.name(universe),
+            Lifetime::Var(_) => None,
+        }
+    }
+
+    /// The canonical variable of each type hole of the goal, in the
+    /// order the holes appear.
+    pub fn holes(&self) -> &[usize] {
+        &self.holes
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct UCanonical<T> {
     crate canonical: Canonical<T>,
     crate universes: usize,
@@ -807,7 +1703,8 @@ impl<T> UCanonical<T> {
 }
 
 impl UCanonical<InEnvironment<Goal>> {
-    /// A goal has coinductive semantics if it is of the form `T: AutoTrait`, or if it is of the
+    /// A goal has coinductive semantics if it is of the form `T: AutoTrait` (or a trait overridden
+    /// to be coinductive, see `TraitOverrides`), or if it is of the
     /// form `WellFormed(T: Trait)` where `Trait` is any trait. The latter is needed for dealing
     /// with WF requirements and cyclic traits, which generates cycles in the proof tree which must
     /// not be rejected but instead must be treated as a success.
@@ -816,7 +1713,7 @@ impl UCanonical<InEnvironment<Goal>> {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 /// A general goal; this is the full range of questions you can pose to Chalk.
 pub enum Goal {
     /// Introduces a binding at depth 0, shifting other bindings up
@@ -869,14 +1766,48 @@ impl Goal {
     /// does not -- at present -- contain any variables. Useful for
     /// REPLs and tests but not much else.
     pub fn into_peeled_goal(self) -> UCanonical<InEnvironment<Goal>> {
+        self.into_peeled_goal_with_names(vec![]).0
+    }
+
+    /// Like `into_peeled_goal`, but also records which placeholder of
+    /// the result each peeled `forall` binder became. `binder_names`
+    /// gives the names of those binders, in order, as returned by
+    /// `PeeledBinderNames::peeled_binder_names`.
+    pub fn into_peeled_goal_with_names(
+        self,
+        binder_names: Vec<ParameterKind<Identifier>>,
+    ) -> (UCanonical<InEnvironment<Goal>>, PlaceholderNames) {
+        self.into_peeled_goal_with_holes(binder_names, 0)
+    }
+
+    /// Like `into_peeled_goal_with_names`, for a goal whose outermost
+    /// `num_holes` existential binders stand for type holes, as
+    /// produced by `LowerGoalWithHoles::lower_with_holes`. The result
+    /// records which canonical variable each hole became, so that
+    /// `Solution::inferred_holes` can report what was inferred.
+    pub fn into_peeled_goal_with_holes(
+        self,
+        binder_names: Vec<ParameterKind<Identifier>>,
+        num_holes: usize,
+    ) -> (UCanonical<InEnvironment<Goal>>, PlaceholderNames) {
+        use solve::infer::var::InferenceVariable;
         use solve::infer::InferenceTable;
         let mut infer = InferenceTable::new();
+        let mut binder_names = binder_names.into_iter();
+        let mut peeled_names = vec![];
         let peeled_goal = {
             let mut env_goal = InEnvironment::new(&Environment::new(), self);
             loop {
                 let InEnvironment { environment, goal } = env_goal;
                 match goal {
                     Goal::Quantified(QuantifierKind::ForAll, subgoal) => {
+                        // Each binder gets a universe of its own, in order.
+                        let first_universe = infer.max_universe().counter + 1;
+                        for counter in first_universe..first_universe + subgoal.len() {
+                            if let Some(name) = binder_names.next() {
+                                peeled_names.push((UniverseIndex { counter }, name));
+                            }
+                        }
                         let subgoal = infer.instantiate_binders_universally(&subgoal);
                         env_goal = InEnvironment::new(&environment, *subgoal);
                     }
@@ -887,7 +1818,7 @@ impl Goal {
                     }
 
                     Goal::Implies(wc, subgoal) => {
-                        let new_environment = &environment.add_clauses(wc);
+                        let new_environment = &Environment::add_clauses(&environment, wc);
                         env_goal = InEnvironment::new(&new_environment, *subgoal);
                     }
 
@@ -895,8 +1826,39 @@ impl Goal {
                 }
             }
         };
-        let canonical = infer.canonicalize(&peeled_goal).quantified;
-        infer.u_canonicalize(&canonical).quantified
+        let canonicalized = infer.canonicalize(&peeled_goal);
+
+        // The holes' binders are peeled first, so they became the
+        // first inference variables of the table.
+        let holes = (0..num_holes)
+            .map(|hole| {
+                let var = ParameterKind::Ty(InferenceVariable::from_depth(hole));
+                canonicalized
+                    .free_vars
+                    .iter()
+                    .position(|free_var| *free_var == var)
+                    .expect("type hole missing from the goal")
+            })
+            .collect();
+
+        let u_canonicalized = infer.u_canonicalize(&canonicalized.quantified);
+
+        // Placeholders that do not appear in the goal were dropped.
+        let names = peeled_names
+            .into_iter()
+            .filter_map(|(universe, name)| {
+                u_canonicalized
+                    .universes
+                    .map_placeholder_universe_to_canonical(universe)
+                    .map(|universe| (universe, name))
+            })
+            .collect();
+        let names = PlaceholderNames {
+            names,
+            holes,
+            style: PlaceholderStyle::default(),
+        };
+        (u_canonicalized.quantified, names)
     }
 
     /// Given a goal with no free variables (a "closed" goal), creates
@@ -920,10 +1882,7 @@ impl Goal {
         match self {
             Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(wca))) => {
                 match wca {
-                    WhereClauseAtom::Implemented(tr) => {
-                        let trait_datum = &program.trait_data[&tr.trait_id];
-                        trait_datum.binders.value.flags.auto
-                    }
+                    WhereClauseAtom::Implemented(tr) => program.is_coinductive_trait(tr.trait_id),
                     WhereClauseAtom::ProjectionEq(..) => false,
                 }
             }
@@ -936,7 +1895,7 @@ impl Goal {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum QuantifierKind {
     ForAll,
     Exists,
@@ -948,25 +1907,61 @@ pub enum QuantifierKind {
 /// lifetime constraints, instead gathering them up to return with our solution
 /// for later checking. This allows for decoupling between type and region
 /// checking in the compiler.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Constraint {
-    LifetimeEq(Lifetime, Lifetime),
+    /// `'a: 'b` -- the first lifetime outlives the second; proving
+    /// `Outlives('a: 'b)` for two distinct lifetimes produces one of
+    /// these.
+    Outlives(Lifetime, Lifetime),
+
+    /// The two lifetimes are equal; unifying two distinct placeholder
+    /// lifetimes produces one of these.
+    RegionEq(Lifetime, Lifetime),
+}
+
+impl Constraint {
+    /// The two lifetimes related by the constraint, in order; for
+    /// `Outlives`, the longer one comes first.
+    pub fn lifetimes(&self) -> (Lifetime, Lifetime) {
+        match *self {
+            Constraint::Outlives(a, b) | Constraint::RegionEq(a, b) => (a, b),
+        }
+    }
+
+    /// Returns every `(longer, shorter)` pair of lifetimes the
+    /// constraint requires; an equality `a == b` yields both `(a, b)`
+    /// and `(b, a)`.
+    pub fn outlives_edges(&self) -> Vec<(Lifetime, Lifetime)> {
+        match *self {
+            Constraint::Outlives(a, b) => vec![(a, b)],
+            Constraint::RegionEq(a, b) => vec![(a, b), (b, a)],
+        }
+    }
 }
 
 /// A mapping of inference variables to instantiations thereof.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Substitution {
     /// Map free variable with given index to the value with the same
     /// index. Naturally, the kind of the variable must agree with
     /// the kind of the value.
     ///
-    /// This is a map because the substitution is not necessarily
-    /// complete. We use a btree map to ensure that the result is in a
-    /// deterministic order.
-    crate parameters: Vec<Parameter>,
+    /// Substitutions are created and cloned constantly, but rarely
+    /// have more than a handful of entries, so they are stored inline
+    /// up to `SUBST_INLINE_PARAMETERS` without a heap allocation.
+    crate parameters: SmallVec<[Parameter; SUBST_INLINE_PARAMETERS]>,
 }
 
+/// The number of parameters a `Substitution` can hold before it
+/// spills over onto the heap.
+pub const SUBST_INLINE_PARAMETERS: usize = 4;
+
 impl Substitution {
+    /// The value of each variable, in order.
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
     crate fn is_empty(&self) -> bool {
         self.parameters.is_empty()
     }
@@ -988,12 +1983,21 @@ impl Substitution {
             match parameter {
                 ParameterKind::Ty(Ty::Var(depth)) => index == *depth,
                 ParameterKind::Lifetime(Lifetime::Var(depth)) => index == *depth,
+                ParameterKind::Const(Const::Var(depth)) => index == *depth,
                 _ => false,
             }
         })
     }
 }
 
+impl FromIterator<Parameter> for Substitution {
+    fn from_iter<I: IntoIterator<Item = Parameter>>(parameters: I) -> Self {
+        Substitution {
+            parameters: parameters.into_iter().collect(),
+        }
+    }
+}
+
 impl<'a> DefaultTypeFolder for &'a Substitution {}
 
 impl<'a> ExistentialFolder for &'a Substitution {
@@ -1012,12 +2016,31 @@ impl<'a> ExistentialFolder for &'a Substitution {
         let l = l.assert_lifetime_ref();
         Ok(l.up_shift(binders))
     }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        let c = &self.parameters[depth];
+        let c = c.assert_const_ref();
+        Ok(c.up_shift(binders))
+    }
 }
 
 impl<'a> IdentityUniversalFolder for &'a Substitution {}
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ConstrainedSubst {
     crate subst: Substitution,
     crate constraints: Vec<InEnvironment<Constraint>>,
 }
+
+impl ConstrainedSubst {
+    /// The substitution for the variables of the goal.
+    pub fn subst(&self) -> &Substitution {
+        &self.subst
+    }
+
+    /// The lifetime constraints under which the substitution holds,
+    /// each with the environment it arose in.
+    pub fn constraints(&self) -> &[InEnvironment<Constraint>] {
+        &self.constraints
+    }
+}
@@ -1,3 +1,5 @@
+use fold::Fold;
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display, Error, Formatter};
 
 use super::*;
@@ -9,6 +11,8 @@ impl Debug for ItemId {
                 write!(fmt, "{}", k.name)
             } else if let Some(k) = prog.associated_ty_data.get(self) {
                 write!(fmt, "({:?}::{})", k.trait_id, k.name)
+            } else if let Some(k) = prog.associated_const_data.get(self) {
+                write!(fmt, "({:?}::{})", k.trait_id, k.name)
             } else {
                 fmt.debug_struct("ItemId")
                     .field("index", &self.index)
@@ -37,8 +41,22 @@ impl Debug for TypeName {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match self {
             TypeName::ItemId(id) => write!(fmt, "{:?}", id),
-            TypeName::ForAll(universe) => write!(fmt, "!{}", universe.counter),
+            TypeName::ForAll(universe) => match tls::placeholder_name(*universe) {
+                Some(name) => write!(fmt, "{}", name),
+                None => write!(fmt, "!{}", universe.counter),
+            },
             TypeName::AssociatedType(assoc_ty) => write!(fmt, "{:?}", assoc_ty),
+            TypeName::Tuple(arity) => write!(fmt, "Tuple/{}", arity),
+            TypeName::FnPtr(arity) => write!(fmt, "FnPtr/{}", arity),
+            TypeName::Slice => write!(fmt, "Slice"),
+            TypeName::Array(len) => write!(fmt, "Array/{}", len),
+            TypeName::Str => write!(fmt, "str"),
+            TypeName::Scalar(scalar) => write!(fmt, "{}", scalar.name()),
+            TypeName::Never => write!(fmt, "!"),
+            TypeName::Ref(Mutability::Not) => write!(fmt, "Ref"),
+            TypeName::Ref(Mutability::Mut) => write!(fmt, "RefMut"),
+            TypeName::RawPtr(Mutability::Not) => write!(fmt, "*const"),
+            TypeName::RawPtr(Mutability::Mut) => write!(fmt, "*mut"),
         }
     }
 }
@@ -51,6 +69,8 @@ impl Debug for Ty {
             Ty::Projection(proj) => write!(fmt, "{:?}", proj),
             Ty::UnselectedProjection(proj) => write!(fmt, "{:?}", proj),
             Ty::ForAll(quantified_ty) => write!(fmt, "{:?}", quantified_ty),
+            Ty::Dyn(dyn_ty) => write!(fmt, "{:?}", dyn_ty),
+            Ty::Opaque(opaque_ty) => write!(fmt, "{:?}", opaque_ty),
         }
     }
 }
@@ -66,17 +86,97 @@ impl Debug for QuantifiedTy {
     }
 }
 
+impl Debug for DynTy {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "dyn ")?;
+        for (i, bound) in self.bounds.iter().enumerate() {
+            if i > 0 {
+                write!(fmt, " + ")?;
+            }
+            write!(fmt, "{:?}{:?}", bound.trait_id, Angle(&bound.parameters))?;
+        }
+        if let Some(lifetime) = &self.lifetime {
+            write!(fmt, " + {:?}", lifetime)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for OpaqueTy {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "{:?}{:?}", self.opaque_ty_id, Angle(&self.parameters))
+    }
+}
+
+impl Debug for OpaqueEq {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "OpaqueEq({:?} = {:?})", self.opaque, self.ty)
+    }
+}
+
 impl Debug for Lifetime {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match self {
             Lifetime::Var(depth) => write!(fmt, "'?{}", depth),
-            Lifetime::ForAll(universe) => write!(fmt, "'!{}", universe.counter),
+            Lifetime::ForAll(universe) => match tls::placeholder_name(*universe) {
+                Some(name) => write!(fmt, "{}", name),
+                None => write!(fmt, "'!{}", universe.counter),
+            },
+        }
+    }
+}
+
+impl Debug for Const {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Const::Var(depth) => write!(fmt, "?{}", depth),
+            Const::ForAll(universe) => match tls::placeholder_name(*universe) {
+                Some(name) => write!(fmt, "{}", name),
+                None => write!(fmt, "!{}", universe.counter),
+            },
+            Const::Value(value) => write!(fmt, "{}", value),
         }
     }
 }
 
 impl Debug for ApplicationTy {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match self.name {
+            TypeName::Slice => return write!(fmt, "[{:?}]", self.parameters[0]),
+            TypeName::Array(len) => return write!(fmt, "[{:?}; {}]", self.parameters[0], len),
+            TypeName::Ref(Mutability::Not) => {
+                return write!(fmt, "&{:?} {:?}", self.parameters[0], self.parameters[1])
+            }
+            TypeName::Ref(Mutability::Mut) => {
+                return write!(fmt, "&{:?} mut {:?}", self.parameters[0], self.parameters[1])
+            }
+            TypeName::RawPtr(Mutability::Not) => return write!(fmt, "*const {:?}", self.parameters[0]),
+            TypeName::RawPtr(Mutability::Mut) => return write!(fmt, "*mut {:?}", self.parameters[0]),
+            _ => {}
+        }
+        if let TypeName::FnPtr(arity) = self.name {
+            write!(fmt, "fn(")?;
+            for (i, parameter) in self.parameters[..arity].iter().enumerate() {
+                if i > 0 {
+                    write!(fmt, ", ")?;
+                }
+                write!(fmt, "{:?}", parameter)?;
+            }
+            return write!(fmt, ") -> {:?}", self.parameters[arity]);
+        }
+        if let TypeName::Tuple(_) = self.name {
+            write!(fmt, "(")?;
+            for (i, parameter) in self.parameters.iter().enumerate() {
+                if i > 0 {
+                    write!(fmt, ", ")?;
+                }
+                write!(fmt, "{:?}", parameter)?;
+            }
+            if self.parameters.len() == 1 {
+                write!(fmt, ",")?;
+            }
+            return write!(fmt, ")");
+        }
         write!(fmt, "{:?}{:?}", self.name, Angle(&self.parameters))
     }
 }
@@ -119,6 +219,30 @@ impl Debug for ProjectionTy {
     }
 }
 
+impl Debug for ConstProjection {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        tls::with_current_program(|p| match p {
+            Some(program) => {
+                let datum = &program.associated_const_data[&self.associated_const_id];
+                write!(
+                    fmt,
+                    "<{:?} as {:?}{:?}>::{}",
+                    &self.parameters[0],
+                    datum.trait_id,
+                    Angle(&self.parameters[1..]),
+                    datum.name
+                )
+            }
+            None => write!(
+                fmt,
+                "({:?}){:?}",
+                self.associated_const_id,
+                Angle(&self.parameters)
+            ),
+        })
+    }
+}
+
 impl Debug for UnselectedProjectionTy {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         let len = self.parameters.len();
@@ -157,6 +281,12 @@ impl Debug for Normalize {
     }
 }
 
+impl Debug for NormalizeConst {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "NormalizeConst({:?} -> {:?})", self.projection, self.value)
+    }
+}
+
 impl Debug for ProjectionEq {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         write!(fmt, "ProjectionEq({:?} = {:?})", self.projection, self.ty)
@@ -202,14 +332,40 @@ impl Debug for DomainGoal {
             }
             DomainGoal::Normalize(n) => write!(fmt, "{:?}", n),
             DomainGoal::UnselectedNormalize(n) => write!(fmt, "{:?}", n),
+            DomainGoal::NormalizeConst(n) => write!(fmt, "{:?}", n),
             DomainGoal::WellFormedTy(t) => write!(fmt, "WellFormed({:?})", t),
             DomainGoal::FromEnvTy(t) => write!(fmt, "FromEnv({:?})", t),
             DomainGoal::InScope(n) => write!(fmt, "InScope({:?})", n),
             DomainGoal::Derefs(n) => write!(fmt, "Derefs({:?})", n),
+            DomainGoal::Outlives(o) => write!(fmt, "{:?}", o),
+            DomainGoal::NotImplemented(tr) => write!(fmt, "NotImplemented({:?})", tr),
+            DomainGoal::Predicate(p) => write!(fmt, "{:?}", p),
+            DomainGoal::OpaqueEq(o) => write!(fmt, "{:?}", o),
+            DomainGoal::Reveal(()) => write!(fmt, "Reveal"),
+            DomainGoal::IsFullyVisible(t) => write!(fmt, "IsFullyVisible({:?})", t),
         }
     }
 }
 
+impl Debug for Outlives {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "Outlives({:?}: {:?})", self.a, self.b)
+    }
+}
+
+impl Debug for PredicateApplication {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "{:?}(", self.predicate_id)?;
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            if index > 0 {
+                write!(fmt, ", ")?;
+            }
+            write!(fmt, "{:?}", parameter)?;
+        }
+        write!(fmt, ")")
+    }
+}
+
 impl Debug for LeafGoal {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match *self {
@@ -237,6 +393,7 @@ impl Debug for Goal {
                     match *binder {
                         ParameterKind::Ty(()) => write!(fmt, "type")?,
                         ParameterKind::Lifetime(()) => write!(fmt, "lifetime")?,
+                        ParameterKind::Const(()) => write!(fmt, "const")?,
                     }
                 }
                 write!(fmt, "> {{ {:?} }}", subgoal.value)
@@ -265,6 +422,7 @@ impl<T: Debug> Debug for Binders<T> {
                 match *binder {
                     ParameterKind::Ty(()) => write!(fmt, "type")?,
                     ParameterKind::Lifetime(()) => write!(fmt, "lifetime")?,
+                    ParameterKind::Const(()) => write!(fmt, "const")?,
                 }
             }
             write!(fmt, "> ")?;
@@ -302,28 +460,95 @@ impl<T: Display> Display for Canonical<T> {
     }
 }
 
-impl<T: Debug, L: Debug> Debug for ParameterKind<T, L> {
+impl<T: Fold> Canonical<T>
+where
+    T::Result: Display,
+{
+    /// Renders the value like `Display` does, but with the binders
+    /// skolemized and given names -- `T0`, `T1`, ... for types, `'a`,
+    /// `'b`, ... for lifetimes and `N0`, `N1`, ... for consts. For
+    /// example, `for<?U0,?U0> { [?0 := Ref<'?0, ?1>] }` (where `?0` is
+    /// a lifetime) is rendered as `for<'a, T0> { [?0 := Ref<'a, T0>] }`.
+    pub fn display_named(&self) -> String {
+        let (value, placeholders) = self.skolemize();
+        if placeholders.is_empty() {
+            return value.to_string();
+        }
+
+        let mut names = BTreeMap::new();
+        let mut binder_names = vec![];
+        let (mut num_tys, mut num_lifetimes, mut num_consts) = (0, 0, 0);
+        for placeholder in &placeholders {
+            let (universe, name) = match placeholder {
+                ParameterKind::Ty(Ty::Apply(ApplicationTy {
+                    name: TypeName::ForAll(universe),
+                    ..
+                })) => {
+                    num_tys += 1;
+                    (*universe, format!("T{}", num_tys - 1))
+                }
+                ParameterKind::Lifetime(Lifetime::ForAll(universe)) => {
+                    num_lifetimes += 1;
+                    (*universe, format!("'{}", lifetime_name(num_lifetimes - 1)))
+                }
+                ParameterKind::Const(Const::ForAll(universe)) => {
+                    num_consts += 1;
+                    (*universe, format!("N{}", num_consts - 1))
+                }
+                _ => panic!("unexpected placeholder {:?}", placeholder),
+            };
+            binder_names.push(name.clone());
+            names.insert(universe, name);
+        }
+
+        format!(
+            "for<{}> {{ {} }}",
+            binder_names.join(", "),
+            Named::new(&value, names)
+        )
+    }
+}
+
+/// Names lifetimes `a` through `z`, then `r26`, `r27`, ...
+fn lifetime_name(index: usize) -> String {
+    if index < 26 {
+        ((b'a' + index as u8) as char).to_string()
+    } else {
+        format!("r{}", index)
+    }
+}
+
+impl<T: Debug, L: Debug, C: Debug> Debug for ParameterKind<T, L, C> {
     default fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match *self {
             ParameterKind::Ty(ref n) => write!(fmt, "Ty({:?})", n),
             ParameterKind::Lifetime(ref n) => write!(fmt, "Lifetime({:?})", n),
+            ParameterKind::Const(ref n) => write!(fmt, "Const({:?})", n),
         }
     }
 }
 
-impl Debug for Constraint {
+impl Display for Constraint {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match self {
-            Constraint::LifetimeEq(a, b) => write!(fmt, "{:?} == {:?}", a, b),
+            Constraint::Outlives(a, b) => write!(fmt, "{:?}: {:?}", a, b),
+            Constraint::RegionEq(a, b) => write!(fmt, "{:?} == {:?}", a, b),
         }
     }
 }
 
+impl Debug for Constraint {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        Display::fmt(self, fmt)
+    }
+}
+
 impl Debug for Parameter {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match *self {
             ParameterKind::Ty(ref n) => write!(fmt, "{:?}", n),
             ParameterKind::Lifetime(ref n) => write!(fmt, "{:?}", n),
+            ParameterKind::Const(ref n) => write!(fmt, "{:?}", n),
         }
     }
 }
@@ -332,12 +557,16 @@ impl Display for ConstrainedSubst {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         let ConstrainedSubst { subst, constraints } = self;
 
-        write!(
-            f,
-            "substitution {}, lifetime constraints {:?}",
-            subst,
-            constraints,
-        )
+        // The environments the constraints arose in are left out, to
+        // keep this readable; see `Solution::region_constraints`.
+        write!(f, "substitution {}, lifetime constraints [", subst)?;
+        for (index, constraint) in constraints.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", constraint.goal)?;
+        }
+        write!(f, "]")
     }
 }
 
@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Error, Formatter};
+
+use super::*;
+
+/// A value formatted with some of its placeholders given names: the
+/// placeholder of a universe in the table prints as its name (e.g.
+/// `T` or `'a`) instead of as `!1` or `'!1`. Both `Display` and
+/// `Debug` are forwarded to the value, with the table installed for
+/// the duration (see `tls::with_placeholder_names`).
+pub struct Named<'a, T: ?Sized + 'a> {
+    value: &'a T,
+    names: BTreeMap<UniverseIndex, String>,
+}
+
+impl<'a, T: ?Sized> Named<'a, T> {
+    crate fn new(value: &'a T, names: BTreeMap<UniverseIndex, String>) -> Self {
+        Named { value, names }
+    }
+}
+
+impl<'a, T: ?Sized + Display> Display for Named<'a, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        tls::with_placeholder_names(&self.names, || Display::fmt(self.value, fmt))
+    }
+}
+
+impl<'a, T: ?Sized + Debug> Debug for Named<'a, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        tls::with_placeholder_names(&self.names, || Debug::fmt(self.value, fmt))
+    }
+}
+
+impl PlaceholderNames {
+    /// Formats `value` with the placeholders that have names rendered
+    /// in the style of `self`, so that `'!1 == '!2` becomes, say,
+    /// `'a == 'b`, or `'a/U1 == 'b/U2` (see `PlaceholderStyle`).
+    /// Placeholders without a name are left alone.
+    pub fn named<'a, T: ?Sized>(&self, value: &'a T) -> Named<'a, T> {
+        if self.style == PlaceholderStyle::Universe {
+            return Named::new(value, BTreeMap::new());
+        }
+
+        let names = self.names
+            .iter()
+            .map(|(&universe, name)| {
+                let name = name.clone().into_inner().to_string();
+                let name = match self.style {
+                    PlaceholderStyle::NameAndUniverse => format!("{}/U{}", name, universe.counter),
+                    PlaceholderStyle::Name | PlaceholderStyle::Universe => name,
+                };
+                (universe, name)
+            })
+            .collect();
+        Named::new(value, names)
+    }
+}
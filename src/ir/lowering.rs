@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::collections::BTreeMap;
 
 use chalk_parse::ast::*;
@@ -5,8 +6,10 @@ use lalrpop_intern::intern;
 
 use cast::{Cast, Caster};
 use errors::*;
+use fold::Subst;
 use ir::{self, Anonymize, ToParameter};
 use itertools::Itertools;
+use progress::{CheckProgress, ProgressControl, ProgressReporter};
 use solve::SolverChoice;
 
 mod test;
@@ -14,6 +17,8 @@ mod test;
 type TypeIds = BTreeMap<ir::Identifier, ir::ItemId>;
 type TypeKinds = BTreeMap<ir::ItemId, ir::TypeKind>;
 type AssociatedTyInfos = BTreeMap<(ir::ItemId, ir::Identifier), AssociatedTyInfo>;
+type AssociatedConstIds = BTreeMap<(ir::ItemId, ir::Identifier), ir::ItemId>;
+type ParameterDefaults = BTreeMap<ir::ItemId, Vec<Option<ir::Ty>>>;
 type ParameterMap = BTreeMap<ir::ParameterKind<ir::Identifier>, usize>;
 
 #[derive(Clone, Debug)]
@@ -21,7 +26,14 @@ struct Env<'k> {
     type_ids: &'k TypeIds,
     type_kinds: &'k TypeKinds,
     associated_ty_infos: &'k AssociatedTyInfos,
+    associated_const_ids: &'k AssociatedConstIds,
+    parameter_defaults: &'k ParameterDefaults,
+    lang_items: &'k BTreeMap<ir::LangItem, ir::ItemId>,
     parameter_map: ParameterMap,
+
+    /// When lowering a goal, counts the type holes (`_`) found so far;
+    /// `None` elsewhere, where holes are not allowed.
+    holes: Option<&'k Cell<usize>>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -64,10 +76,37 @@ impl<'k> Env<'k> {
         bail!("invalid lifetime name: {:?}", name.str);
     }
 
+    /// If `name` is a const parameter, its index. A const parameter
+    /// given as an argument, like `N` in `Foo<N>`, is parsed as a type,
+    /// since it looks like one; this tells them apart.
+    fn lookup_const(&self, name: Identifier) -> Option<usize> {
+        self.parameter_map
+            .get(&ir::ParameterKind::Const(name.str))
+            .cloned()
+    }
+
     fn type_kind(&self, id: ir::ItemId) -> &ir::TypeKind {
         &self.type_kinds[&id]
     }
 
+    /// Fills in the parameters of the struct or trait `id` that were
+    /// left out, up to `len` parameters in all, from their defaults.
+    /// Stops at the first omitted parameter without a default, leaving
+    /// the caller to report the wrong number of parameters.
+    fn apply_defaults(&self, id: ir::ItemId, parameters: &mut Vec<ir::Parameter>, len: usize) {
+        let defaults = match self.parameter_defaults.get(&id) {
+            Some(defaults) => defaults,
+            None => return,
+        };
+        while parameters.len() < len {
+            let ty = match defaults[parameters.len()] {
+                Some(ref default) => Subst::apply(parameters, default),
+                None => break,
+            };
+            parameters.push(ir::ParameterKind::Ty(ty));
+        }
+    }
+
     /// Introduces new parameters, shifting the indices of existing
     /// parameters to accommodate them. The indices of the new binders
     /// will be assigned in order as they are iterated.
@@ -109,135 +148,489 @@ impl<'k> Env<'k> {
 
 pub trait LowerProgram {
     /// Lowers from a Program AST to the internal IR for a program.
+    /// Every item is kept, whatever its `#[cfg]` attributes.
     fn lower(&self, solver_choice: SolverChoice) -> Result<ir::Program>;
+
+    /// Like `lower`, but leaves out the items marked `#[cfg = "flag"]`
+    /// for a flag that is not among `enabled_flags`; an item with
+    /// several such attributes needs all of their flags enabled. This
+    /// way, one parsed program can be checked under several feature
+    /// combinations. Items left out cannot be referred to, so an impl
+    /// for a type that is left out must be left out as well.
+    fn lower_with_cfg(
+        &self,
+        solver_choice: SolverChoice,
+        enabled_flags: &[&str],
+    ) -> Result<ir::Program>;
+
+    /// Like `lower`, but only with the given builtin rules; for
+    /// modeling a subset of the language, or testing rules in
+    /// isolation. The program is checked for coherence and
+    /// well-formedness under these rules as well.
+    fn lower_with_builtins(
+        &self,
+        solver_choice: SolverChoice,
+        builtin_traits: ir::BuiltinTraits,
+    ) -> Result<ir::Program>;
+
+    /// Like `lower`, but reports the progress of the coherence and
+    /// well-formedness checks to `progress` before each item is
+    /// checked. If `progress` returns `ProgressControl::Abort`, the
+    /// checks stop and lowering fails with `ErrorKind::ChecksAborted`.
+    fn lower_with_progress(
+        &self,
+        solver_choice: SolverChoice,
+        progress: &mut dyn FnMut(&CheckProgress) -> ProgressControl,
+    ) -> Result<ir::Program>;
+
+    /// Like `lower`, but spreads the well-formedness checks over
+    /// `num_threads` threads; see
+    /// `Program::verify_well_formedness_with_threads`.
+    fn lower_with_threads(
+        &self,
+        solver_choice: SolverChoice,
+        num_threads: usize,
+    ) -> Result<ir::Program>;
 }
 
 impl LowerProgram for Program {
     fn lower(&self, solver_choice: SolverChoice) -> Result<ir::Program> {
-        let mut index = 0;
-        let mut next_item_id = || -> ir::ItemId {
-            let i = index;
-            index += 1;
-            ir::ItemId { index: i }
-        };
+        self.lower_with_builtins(solver_choice, ir::BuiltinTraits::default())
+    }
 
-        // Make a vector mapping each thing in `items` to an id,
-        // based just on its position:
-        let item_ids: Vec<_> = self.items.iter().map(|_| next_item_id()).collect();
+    fn lower_with_cfg(
+        &self,
+        solver_choice: SolverChoice,
+        enabled_flags: &[&str],
+    ) -> Result<ir::Program> {
+        let items: Vec<_> = self.items
+            .iter()
+            .filter(|item| item.is_cfg_enabled(enabled_flags))
+            .collect();
+        lower_items(
+            &items,
+            solver_choice,
+            ir::BuiltinTraits::default(),
+            1,
+            &mut ProgressReporter::silent(),
+        )
+    }
+
+    fn lower_with_builtins(
+        &self,
+        solver_choice: SolverChoice,
+        builtin_traits: ir::BuiltinTraits,
+    ) -> Result<ir::Program> {
+        let items: Vec<_> = self.items.iter().collect();
+        lower_items(&items, solver_choice, builtin_traits, 1, &mut ProgressReporter::silent())
+    }
+
+    fn lower_with_progress(
+        &self,
+        solver_choice: SolverChoice,
+        progress: &mut dyn FnMut(&CheckProgress) -> ProgressControl,
+    ) -> Result<ir::Program> {
+        let items: Vec<_> = self.items.iter().collect();
+        lower_items(
+            &items,
+            solver_choice,
+            ir::BuiltinTraits::default(),
+            1,
+            &mut ProgressReporter::new(progress),
+        )
+    }
+
+    fn lower_with_threads(
+        &self,
+        solver_choice: SolverChoice,
+        num_threads: usize,
+    ) -> Result<ir::Program> {
+        let items: Vec<_> = self.items.iter().collect();
+        lower_items(
+            &items,
+            solver_choice,
+            ir::BuiltinTraits::default(),
+            num_threads,
+            &mut ProgressReporter::silent(),
+        )
+    }
+}
 
-        // Create ids for associated types
-        let mut associated_ty_infos = BTreeMap::new();
-        for (item, &item_id) in self.items.iter().zip(&item_ids) {
-            if let Item::TraitDefn(ref d) = *item {
-                if d.flags.auto && !d.assoc_ty_defns.is_empty() {
-                    bail!("auto trait cannot define associated types");
+trait CfgEnabled {
+    fn is_cfg_enabled(&self, enabled_flags: &[&str]) -> bool;
+}
+
+impl CfgEnabled for Item {
+    fn is_cfg_enabled(&self, enabled_flags: &[&str]) -> bool {
+        let cfg = intern("cfg");
+        self.attributes()
+            .iter()
+            .filter(|attribute| attribute.key.str == cfg)
+            .all(|attribute| match attribute.value {
+                Some(flag) => enabled_flags.iter().any(|&enabled| intern(enabled) == flag),
+                None => false,
+            })
+    }
+}
+
+trait ItemAttributes {
+    fn attributes(&self) -> &[Attribute];
+}
+
+impl ItemAttributes for Item {
+    fn attributes(&self) -> &[Attribute] {
+        match *self {
+            Item::AdtDefn(ref d) => &d.attributes,
+            Item::TraitDefn(ref d) => &d.attributes,
+            Item::Impl(ref d) => &d.attributes,
+            Item::InherentImpl(ref d) => &d.attributes,
+            Item::Clause(_)
+            | Item::PredicateDefn(_)
+            | Item::OpaqueTyDefn(_)
+            | Item::ClosureDefn(_) => &[],
+        }
+    }
+}
+
+fn lower_items(
+    items: &[&Item],
+    solver_choice: SolverChoice,
+    builtin_traits: ir::BuiltinTraits,
+    num_threads: usize,
+    progress: &mut ProgressReporter,
+) -> Result<ir::Program> {
+    let mut index = 0;
+    let mut next_item_id = || -> ir::ItemId {
+        let i = index;
+        index += 1;
+        ir::ItemId { index: i }
+    };
+
+    // Make a vector mapping each thing in `items` to an id,
+    // based just on its position:
+    let item_ids: Vec<_> = items.iter().map(|_| next_item_id()).collect();
+
+    // Create ids for associated types and consts, and find the lang items
+    let mut associated_ty_infos = BTreeMap::new();
+    let mut associated_const_ids = BTreeMap::new();
+    let mut lang_items = BTreeMap::new();
+    for (&item, &item_id) in items.iter().zip(&item_ids) {
+        if let Item::TraitDefn(ref d) = *item {
+            if d.flags.auto && !d.assoc_ty_defns.is_empty() {
+                bail!("auto trait cannot define associated types");
+            }
+            if d.flags.marker && !d.assoc_ty_defns.is_empty() {
+                bail!("marker trait cannot define associated types");
+            }
+            for defn in &d.assoc_ty_defns {
+                let addl_parameter_kinds = defn.all_parameters();
+                let info = AssociatedTyInfo {
+                    id: next_item_id(),
+                    addl_parameter_kinds,
+                };
+                associated_ty_infos.insert((item_id, defn.name.str), info);
+            }
+            if d.flags.auto && !d.assoc_const_defns.is_empty() {
+                bail!("auto trait cannot define associated consts");
+            }
+            if d.flags.marker && !d.assoc_const_defns.is_empty() {
+                bail!("marker trait cannot define associated consts");
+            }
+            for defn in &d.assoc_const_defns {
+                associated_const_ids.insert((item_id, defn.name.str), next_item_id());
+            }
+
+            let lang_item_flags = [
+                (d.flags.deref, ir::LangItem::DerefTrait),
+                (d.flags.sized, ir::LangItem::SizedTrait),
+                (d.flags.fn_trait, ir::LangItem::FnTrait),
+                (d.flags.fn_mut, ir::LangItem::FnMutTrait),
+                (d.flags.fn_once, ir::LangItem::FnOnceTrait),
+                (d.flags.copy, ir::LangItem::CopyTrait),
+                (d.flags.clone, ir::LangItem::CloneTrait),
+                (d.flags.unsize, ir::LangItem::UnsizeTrait),
+            ];
+            for &(flag, lang_item) in &lang_item_flags {
+                if !flag {
+                    continue;
                 }
-                for defn in &d.assoc_ty_defns {
-                    let addl_parameter_kinds = defn.all_parameters();
-                    let info = AssociatedTyInfo {
-                        id: next_item_id(),
-                        addl_parameter_kinds,
-                    };
-                    associated_ty_infos.insert((item_id, defn.name.str), info);
+                use std::collections::btree_map::Entry::*;
+                match lang_items.entry(lang_item) {
+                    Vacant(entry) => { entry.insert(item_id); },
+                    Occupied(_) => {
+                        bail!(ErrorKind::DuplicateLangItem(lang_item))
+                    }
                 }
             }
         }
+    }
 
-        let mut type_ids = BTreeMap::new();
-        let mut type_kinds = BTreeMap::new();
-        for (item, &item_id) in self.items.iter().zip(&item_ids) {
-            let k = match *item {
-                Item::StructDefn(ref d) => d.lower_type_kind()?,
-                Item::TraitDefn(ref d) => d.lower_type_kind()?,
-                Item::Impl(_) => continue,
-                Item::Clause(_) => continue,
-            };
-            type_ids.insert(k.name, item_id);
-            type_kinds.insert(item_id, k);
+    let mut type_ids = BTreeMap::new();
+    let mut type_kinds = BTreeMap::new();
+    for (&item, &item_id) in items.iter().zip(&item_ids) {
+        let k = match *item {
+            Item::AdtDefn(ref d) => d.lower_type_kind()?,
+            Item::TraitDefn(ref d) => d.lower_type_kind()?,
+            Item::PredicateDefn(ref d) => d.lower_type_kind()?,
+            Item::OpaqueTyDefn(ref d) => d.lower_type_kind()?,
+            Item::ClosureDefn(ref d) => d.lower_type_kind()?,
+            Item::Impl(_) => continue,
+            Item::InherentImpl(_) => continue,
+            Item::Clause(_) => continue,
+        };
+        if ScalarType::from_name(k.name).is_some() {
+            bail!(ErrorKind::ScalarTypeRedeclared(k.name))
         }
+        type_ids.insert(k.name, item_id);
+        type_kinds.insert(item_id, k);
+    }
 
-        let mut struct_data = BTreeMap::new();
-        let mut trait_data = BTreeMap::new();
-        let mut impl_data = BTreeMap::new();
-        let mut associated_ty_data = BTreeMap::new();
-        let mut custom_clauses = Vec::new();
-        let mut lang_items = BTreeMap::new();
-        for (item, &item_id) in self.items.iter().zip(&item_ids) {
-            let empty_env = Env {
-                type_ids: &type_ids,
-                type_kinds: &type_kinds,
-                associated_ty_infos: &associated_ty_infos,
-                parameter_map: BTreeMap::new(),
-            };
+    // Lower the parameter defaults up front, so that they can be used
+    // wherever a struct or trait is named. A default itself cannot
+    // rely on other defaults.
+    let no_defaults = BTreeMap::new();
+    let mut parameter_defaults = BTreeMap::new();
+    for (&item, &item_id) in items.iter().zip(&item_ids) {
+        let env = Env {
+            type_ids: &type_ids,
+            type_kinds: &type_kinds,
+            associated_ty_infos: &associated_ty_infos,
+            associated_const_ids: &associated_const_ids,
+            parameter_defaults: &no_defaults,
+            lang_items: &lang_items,
+            parameter_map: BTreeMap::new(),
+            holes: None,
+        };
+        let defaults = match *item {
+            Item::AdtDefn(ref d) => lower_parameter_defaults(d, &d.parameter_defaults, &env)?,
+            Item::TraitDefn(ref d) => lower_parameter_defaults(d, &d.parameter_defaults, &env)?,
+            _ => continue,
+        };
+        if defaults.iter().any(|d| d.is_some()) {
+            parameter_defaults.insert(item_id, defaults);
+        }
+    }
 
-            match *item {
-                Item::StructDefn(ref d) => {
-                    struct_data.insert(item_id, d.lower_struct(item_id, &empty_env)?);
-                }
-                Item::TraitDefn(ref d) => {
-                    trait_data.insert(item_id, d.lower_trait(item_id, &empty_env)?);
-
-                    for defn in &d.assoc_ty_defns {
-                        let info = &associated_ty_infos[&(item_id, defn.name.str)];
-
-                        let mut parameter_kinds = defn.all_parameters();
-                        parameter_kinds.extend(d.all_parameters());
-                        let env = empty_env.introduce(parameter_kinds.clone())?;
-
-                        associated_ty_data.insert(
-                            info.id,
-                            ir::AssociatedTyDatum {
-                                trait_id: item_id,
-                                id: info.id,
-                                name: defn.name.str,
-                                parameter_kinds: parameter_kinds,
-                                where_clauses: defn.where_clauses.lower(&env)?,
-                            },
-                        );
-                    }
+    let mut adt_data = BTreeMap::new();
+    let mut trait_data = BTreeMap::new();
+    let mut impl_data = BTreeMap::new();
+    let mut inherent_impl_data = BTreeMap::new();
+    let mut opaque_ty_data = BTreeMap::new();
+    let mut associated_ty_data = BTreeMap::new();
+    let mut associated_const_data = BTreeMap::new();
+    let mut custom_clauses = Vec::new();
+    let mut closure_data = BTreeMap::new();
+    let mut item_attributes = BTreeMap::new();
+    for (&item, &item_id) in items.iter().zip(&item_ids) {
+        let empty_env = Env {
+            type_ids: &type_ids,
+            type_kinds: &type_kinds,
+            associated_ty_infos: &associated_ty_infos,
+            associated_const_ids: &associated_const_ids,
+            parameter_defaults: &parameter_defaults,
+            lang_items: &lang_items,
+            parameter_map: BTreeMap::new(),
+            holes: None,
+        };
 
-                    if d.flags.deref {
-                        use std::collections::btree_map::Entry::*;
-                        match lang_items.entry(ir::LangItem::DerefTrait) {
-                            Vacant(entry) => { entry.insert(item_id); },
-                            Occupied(_) => {
-                                bail!(ErrorKind::DuplicateLangItem(ir::LangItem::DerefTrait))
-                            }
+        let attributes = item.attributes();
+        if !attributes.is_empty() {
+            item_attributes.insert(item_id, attributes.iter().map(|a| a.lower()).collect());
+        }
+
+        match *item {
+            Item::AdtDefn(ref d) => {
+                adt_data.insert(item_id, d.lower_adt(item_id, &empty_env)?);
+            }
+            Item::TraitDefn(ref d) => {
+                trait_data.insert(item_id, d.lower_trait(item_id, &empty_env)?);
+
+                for defn in &d.assoc_ty_defns {
+                    let info = &associated_ty_infos[&(item_id, defn.name.str)];
+
+                    let mut parameter_kinds = defn.all_parameters();
+                    parameter_kinds.extend(d.all_parameters());
+                    let env = empty_env.introduce(parameter_kinds.clone())?;
+
+                    // The bounds are about `<Self as Trait>::Assoc<..>`.
+                    let projection_ty = ir::Ty::Projection(ir::ProjectionTy {
+                        associated_ty_id: info.id,
+                        parameters: parameter_kinds
+                            .anonymize()
+                            .iter()
+                            .zip(0..)
+                            .map(|p| p.to_parameter())
+                            .collect(),
+                    });
+                    let bounds = defn.bounds
+                        .iter()
+                        .map(|b| b.lower_with_self(projection_ty.clone(), &env))
+                        .collect::<Result<Vec<_>>>()?;
+
+                    let mut where_clauses: Vec<ir::QuantifiedDomainGoal> =
+                        defn.where_clauses.lower(&env)?;
+                    let own_len = defn.all_parameters().len();
+                    for wc in implied_outlives(&parameter_kinds.anonymize(), own_len) {
+                        if !where_clauses.contains(&wc) {
+                            where_clauses.push(wc);
                         }
                     }
+
+                    associated_ty_data.insert(
+                        info.id,
+                        ir::AssociatedTyDatum {
+                            trait_id: item_id,
+                            id: info.id,
+                            name: defn.name.str,
+                            parameter_kinds: parameter_kinds,
+                            bounds,
+                            where_clauses,
+                        },
+                    );
                 }
-                Item::Impl(ref d) => {
-                    impl_data.insert(item_id, d.lower_impl(&empty_env)?);
-                }
-                Item::Clause(ref clause) => {
-                    custom_clauses.extend(clause.lower_clause(&empty_env)?);
+
+                for defn in &d.assoc_const_defns {
+                    let id = associated_const_ids[&(item_id, defn.name.str)];
+                    associated_const_data.insert(
+                        id,
+                        ir::AssociatedConstDatum {
+                            trait_id: item_id,
+                            id,
+                            name: defn.name.str,
+                        },
+                    );
                 }
             }
+            Item::Impl(ref d) => {
+                impl_data.insert(item_id, d.lower_impl(&empty_env)?);
+            }
+            Item::InherentImpl(ref d) => {
+                inherent_impl_data.insert(item_id, d.lower_inherent_impl(&empty_env)?);
+            }
+            Item::Clause(ref clause) => {
+                custom_clauses.extend(clause.lower_clause(&empty_env)?);
+            }
+            Item::PredicateDefn(_) => {
+                // Predicates are defined by custom clauses; the
+                // declaration only introduces the name.
+            }
+            Item::OpaqueTyDefn(ref d) => {
+                opaque_ty_data.insert(item_id, d.lower_opaque_ty(item_id, &empty_env)?);
+            }
+            Item::ClosureDefn(ref d) => {
+                closure_data.insert(item_id, d.lower_closure(item_id, &empty_env)?);
+            }
         }
+    }
 
-        let mut program = ir::Program {
-            type_ids,
-            type_kinds,
-            struct_data,
-            trait_data,
-            impl_data,
-            associated_ty_data,
-            custom_clauses,
-            lang_items,
-            default_impl_data: Vec::new(),
-        };
-
-        program.add_default_impls();
-        program.record_specialization_priorities(solver_choice)?;
-        program.verify_well_formedness(solver_choice)?;
-        Ok(program)
+    let mut program = ir::Program {
+        type_ids,
+        type_kinds,
+        parameter_defaults,
+        adt_data,
+        trait_data,
+        impl_data,
+        inherent_impl_data,
+        opaque_ty_data,
+        closure_data,
+        associated_ty_data,
+        associated_const_data,
+        custom_clauses,
+        lang_items,
+        builtin_traits,
+        specialization_graph: Default::default(),
+        item_attributes,
+        trait_scopes: BTreeMap::new(),
+        trait_overrides: BTreeMap::new(),
+        default_impl_data: Vec::new(),
+        environment_cache: ir::EnvironmentCache::default(),
+    };
+
+    for impl_datum in program.impl_data.values() {
+        let trait_id = impl_datum.binders.value.trait_ref.trait_ref().trait_id;
+        if program.trait_data[&trait_id].binders.value.flags.alias {
+            bail!("cannot implement trait alias `{}`", program.type_kinds[&trait_id].name);
+        }
     }
+
+    program.check_orphan_rules()?;
+    program.add_default_impls();
+    program.record_specialization_priorities(solver_choice, progress)?;
+    program.verify_well_formedness_with_progress(solver_choice, num_threads, progress)?;
+    Ok(program)
 }
 
 trait LowerTypeKind {
     fn lower_type_kind(&self) -> Result<ir::TypeKind>;
 }
 
+/// The outlives requirements implied by the lifetime parameters of an
+/// associated type. Such a lifetime may be used to borrow from `Self`
+/// or the other type parameters of the trait, as in `type Iter<'a>`
+/// being `Iter<'a, T>` for `Vec<T>`, so they must outlive it: given
+/// `trait Foo<T> { type Bar<'a>; }`, we require `Self: 'a` and `T: 'a`.
+///
+/// `parameter_kinds` are those of the associated type, whose own
+/// `own_len` parameters come first and those of the trait after.
+fn implied_outlives(
+    parameter_kinds: &[ir::ParameterKind<()>],
+    own_len: usize,
+) -> Vec<ir::QuantifiedDomainGoal> {
+    let (own_kinds, trait_kinds) = parameter_kinds.split_at(own_len);
+    let lifetimes = own_kinds.iter().zip(0..).filter_map(|(kind, index)| match kind {
+        ir::ParameterKind::Lifetime(()) => Some(ir::Lifetime::Var(index)),
+        ir::ParameterKind::Ty(()) | ir::ParameterKind::Const(()) => None,
+    });
+    let tys: Vec<_> = trait_kinds
+        .iter()
+        .zip(own_len..)
+        .filter_map(|(kind, index)| match kind {
+            ir::ParameterKind::Ty(()) => Some(ir::Ty::Var(index)),
+            ir::ParameterKind::Lifetime(()) | ir::ParameterKind::Const(()) => None,
+        })
+        .collect();
+
+    lifetimes
+        .flat_map(|b| {
+            tys.iter().map(move |a| ir::Binders {
+                binders: vec![],
+                value: ir::DomainGoal::Outlives(ir::Outlives {
+                    a: ir::ParameterKind::Ty(a.clone()),
+                    b,
+                }),
+            })
+        })
+        .collect()
+}
+
+/// Lowers the defaults given for the declared parameters of a struct or
+/// trait, indexed by all its parameters. Each default is lowered with
+/// just the parameters before it in scope.
+fn lower_parameter_defaults<D: LowerParameterMap>(
+    defn: &D,
+    defaults: &[Option<Ty>],
+    env: &Env,
+) -> Result<Vec<Option<ir::Ty>>> {
+    let parameters = defn.all_parameters();
+    let num_synthetic = parameters.len() - defaults.len();
+    let mut lowered = vec![None; num_synthetic];
+    for default in defaults {
+        match *default {
+            Some(ref ty) => {
+                let env = env.introduce(parameters[..lowered.len()].iter().cloned())?;
+                lowered.push(Some(ty.lower(&env)?));
+            }
+            None => {
+                if lowered.iter().any(|d| d.is_some()) {
+                    bail!("generic parameters with a default must be trailing");
+                }
+                lowered.push(None);
+            }
+        }
+    }
+    Ok(lowered)
+}
+
 trait LowerParameterMap {
     fn synthetic_parameters(&self) -> Option<ir::ParameterKind<ir::Identifier>>;
     fn declared_parameters(&self) -> &[ParameterKind];
@@ -284,7 +677,27 @@ trait LowerParameterMap {
     }
 }
 
-impl LowerParameterMap for StructDefn {
+impl LowerParameterMap for AdtDefn {
+    fn synthetic_parameters(&self) -> Option<ir::ParameterKind<ir::Identifier>> {
+        None
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
+impl LowerParameterMap for OpaqueTyDefn {
+    fn synthetic_parameters(&self) -> Option<ir::ParameterKind<ir::Identifier>> {
+        None
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
+impl LowerParameterMap for ClosureDefn {
     fn synthetic_parameters(&self) -> Option<ir::ParameterKind<ir::Identifier>> {
         None
     }
@@ -304,6 +717,16 @@ impl LowerParameterMap for Impl {
     }
 }
 
+impl LowerParameterMap for InherentImpl {
+    fn synthetic_parameters(&self) -> Option<ir::ParameterKind<ir::Identifier>> {
+        None
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
 impl LowerParameterMap for AssocTyDefn {
     fn synthetic_parameters(&self) -> Option<ir::ParameterKind<ir::Identifier>> {
         None
@@ -344,6 +767,19 @@ impl LowerParameterMap for Clause {
     }
 }
 
+trait LowerAttribute {
+    fn lower(&self) -> ir::ItemAttribute;
+}
+
+impl LowerAttribute for Attribute {
+    fn lower(&self) -> ir::ItemAttribute {
+        ir::ItemAttribute {
+            key: self.key.str,
+            value: self.value,
+        }
+    }
+}
+
 trait LowerParameterKind {
     fn lower(&self) -> ir::ParameterKind<ir::Identifier>;
 }
@@ -353,6 +789,7 @@ impl LowerParameterKind for ParameterKind {
         match *self {
             ParameterKind::Ty(ref n) => ir::ParameterKind::Ty(n.str),
             ParameterKind::Lifetime(ref n) => ir::ParameterKind::Lifetime(n.str),
+            ParameterKind::Const(ref n) => ir::ParameterKind::Const(n.str),
         }
     }
 }
@@ -365,10 +802,10 @@ trait LowerWhereClauses {
     }
 }
 
-impl LowerTypeKind for StructDefn {
+impl LowerTypeKind for AdtDefn {
     fn lower_type_kind(&self) -> Result<ir::TypeKind> {
         Ok(ir::TypeKind {
-            sort: ir::TypeSort::Struct,
+            sort: ir::TypeSort::Adt,
             name: self.name.str,
             binders: ir::Binders {
                 binders: self.all_parameters().anonymize(),
@@ -378,7 +815,7 @@ impl LowerTypeKind for StructDefn {
     }
 }
 
-impl LowerWhereClauses for StructDefn {
+impl LowerWhereClauses for AdtDefn {
     fn where_clauses(&self) -> &[QuantifiedWhereClause] {
         &self.where_clauses
     }
@@ -399,6 +836,46 @@ impl LowerTypeKind for TraitDefn {
     }
 }
 
+impl LowerTypeKind for PredicateDefn {
+    fn lower_type_kind(&self) -> Result<ir::TypeKind> {
+        let binders: Vec<_> = self.parameter_kinds.iter().map(|p| p.lower()).collect();
+        Ok(ir::TypeKind {
+            sort: ir::TypeSort::Predicate,
+            name: self.name.str,
+            binders: ir::Binders {
+                binders: binders.anonymize(),
+                value: (),
+            },
+        })
+    }
+}
+
+impl LowerTypeKind for OpaqueTyDefn {
+    fn lower_type_kind(&self) -> Result<ir::TypeKind> {
+        Ok(ir::TypeKind {
+            sort: ir::TypeSort::Opaque,
+            name: self.name.str,
+            binders: ir::Binders {
+                binders: self.all_parameters().anonymize(),
+                value: (),
+            },
+        })
+    }
+}
+
+impl LowerTypeKind for ClosureDefn {
+    fn lower_type_kind(&self) -> Result<ir::TypeKind> {
+        Ok(ir::TypeKind {
+            sort: ir::TypeSort::Closure,
+            name: self.name.str,
+            binders: ir::Binders {
+                binders: self.all_parameters().anonymize(),
+                value: (),
+            },
+        })
+    }
+}
+
 impl LowerWhereClauses for TraitDefn {
     fn where_clauses(&self) -> &[QuantifiedWhereClause] {
         &self.where_clauses
@@ -411,6 +888,12 @@ impl LowerWhereClauses for Impl {
     }
 }
 
+impl LowerWhereClauses for InherentImpl {
+    fn where_clauses(&self) -> &[QuantifiedWhereClause] {
+        &self.where_clauses
+    }
+}
+
 trait LowerWhereClauseVec<T> {
     fn lower(&self, env: &Env) -> Result<Vec<T>>;
 }
@@ -459,6 +942,13 @@ impl LowerWhereClause<ir::DomainGoal> for WhereClause {
                 projection: projection.lower(env)?,
                 ty: ty.lower(env)?,
             }),
+            WhereClause::NormalizeConst {
+                projection,
+                value,
+            } => ir::DomainGoal::NormalizeConst(ir::NormalizeConst {
+                projection: projection.lower_const_projection(env)?,
+                value: lower_const(value, env)?,
+            }),
             WhereClause::TyWellFormed { ty } => ir::DomainGoal::WellFormedTy(ty.lower(env)?),
             WhereClause::TraitRefWellFormed { trait_ref } => {
                 ir::DomainGoal::WellFormed(ir::WhereClauseAtom::Implemented(trait_ref.lower(env)?))
@@ -467,6 +957,9 @@ impl LowerWhereClause<ir::DomainGoal> for WhereClause {
             WhereClause::TraitRefFromEnv { trait_ref } => {
                 ir::DomainGoal::FromEnv(ir::WhereClauseAtom::Implemented(trait_ref.lower(env)?))
             }
+            WhereClause::NotImplemented { trait_ref } => {
+                ir::DomainGoal::NotImplemented(trait_ref.lower(env)?)
+            }
             WhereClause::UnifyTys { .. } | WhereClause::UnifyLifetimes { .. } => {
                 bail!("this form of where-clause not allowed here")
             }
@@ -488,6 +981,93 @@ impl LowerWhereClause<ir::DomainGoal> for WhereClause {
                                         target: target.lower(env)?
                                     })
             }
+            WhereClause::Outlives { a, b } => ir::DomainGoal::Outlives(ir::Outlives {
+                a: a.lower(env)?,
+                b: b.lower(env)?,
+            }),
+            WhereClause::Reveal => ir::DomainGoal::Reveal(()),
+            &WhereClause::FnBound {
+                ref ty,
+                trait_name,
+                ref arg_tys,
+                ref output,
+            } => {
+                let id = match env.lookup(trait_name)? {
+                    NameLookup::Type(id) => id,
+                    NameLookup::Parameter(_) => bail!(ErrorKind::NotTrait(trait_name)),
+                };
+                let is_fn_trait = [
+                    ir::LangItem::FnTrait,
+                    ir::LangItem::FnMutTrait,
+                    ir::LangItem::FnOnceTrait,
+                ].iter()
+                    .any(|lang_item| env.lang_items.get(lang_item) == Some(&id));
+                if !is_fn_trait {
+                    bail!("`{}` is not one of the `Fn` traits", trait_name.str);
+                }
+
+                // `Output` is the associated type of `FnOnce`.
+                let associated_ty_id = match env.lang_items.get(&ir::LangItem::FnOnceTrait) {
+                    Some(&fn_once_id) => env.associated_ty_infos
+                        .get(&(fn_once_id, intern("Output")))
+                        .map(|info| info.id),
+                    None => None,
+                };
+                let associated_ty_id = match associated_ty_id {
+                    Some(id) => id,
+                    None => bail!("no `#[lang_fn_once]` trait with an `Output` type"),
+                };
+
+                let parameters: Vec<ir::Parameter> = vec![
+                    ty.lower(env)?.cast(),
+                    lower_tuple(arg_tys, env)?.cast(),
+                ];
+                return Ok(vec![
+                    ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(ir::TraitRef {
+                        trait_id: id,
+                        parameters: parameters.clone(),
+                    })),
+                    ir::DomainGoal::Holds(ir::WhereClauseAtom::ProjectionEq(ir::ProjectionEq {
+                        projection: ir::ProjectionTy {
+                            associated_ty_id,
+                            parameters,
+                        },
+                        ty: output.lower(env)?,
+                    })),
+                ]);
+            }
+            &WhereClause::Predicate { name, ref args } => {
+                let id = match env.lookup(name)? {
+                    NameLookup::Type(id) => id,
+                    NameLookup::Parameter(_) => bail!(ErrorKind::NotPredicate(name)),
+                };
+
+                let k = env.type_kind(id);
+                if k.sort != ir::TypeSort::Predicate {
+                    bail!(ErrorKind::NotPredicate(name));
+                }
+
+                if k.binders.len() != args.len() {
+                    bail!(ErrorKind::IncorrectNumberOfTypeParameters(
+                        name,
+                        k.binders.len(),
+                        args.len()
+                    ))
+                }
+
+                let parameters = args.iter()
+                    .map(|a| Ok(a.lower(env)?))
+                    .collect::<Result<Vec<_>>>()?;
+
+                for (param, arg) in k.binders.binders.iter().zip(parameters.iter()) {
+                    check_type_kinds("incorrect parameter kind", param, arg)?;
+                }
+
+                ir::DomainGoal::Predicate(ir::PredicateApplication {
+                    predicate_id: id,
+                    parameters,
+                })
+            }
         };
         Ok(vec![goal])
     }
@@ -513,11 +1093,17 @@ impl LowerWhereClause<ir::LeafGoal> for WhereClause {
             WhereClause::Implemented { .. }
             | WhereClause::ProjectionEq { .. }
             | WhereClause::Normalize { .. }
+            | WhereClause::NormalizeConst { .. }
             | WhereClause::TyWellFormed { .. }
             | WhereClause::TraitRefWellFormed { .. }
             | WhereClause::TyFromEnv { .. }
             | WhereClause::TraitRefFromEnv { .. }
-            | WhereClause::Derefs { .. } => {
+            | WhereClause::NotImplemented { .. }
+            | WhereClause::Derefs { .. }
+            | WhereClause::Outlives { .. }
+            | WhereClause::Reveal
+            | WhereClause::FnBound { .. }
+            | WhereClause::Predicate { .. } => {
                 let goals: Vec<ir::DomainGoal> = self.lower(env)?;
                 goals.into_iter().casted().collect()
             }
@@ -545,12 +1131,75 @@ impl LowerWhereClause<ir::LeafGoal> for WhereClause {
     }
 }
 
-trait LowerStructDefn {
-    fn lower_struct(&self, item_id: ir::ItemId, env: &Env) -> Result<ir::StructDatum>;
+trait LowerOpaqueTyDefn {
+    fn lower_opaque_ty(&self, item_id: ir::ItemId, env: &Env) -> Result<ir::OpaqueTyDatum>;
+}
+
+impl LowerOpaqueTyDefn for OpaqueTyDefn {
+    fn lower_opaque_ty(&self, item_id: ir::ItemId, env: &Env) -> Result<ir::OpaqueTyDatum> {
+        let binders = env.in_binders(self.all_parameters(), |env| {
+            // The bounds are about the opaque type itself.
+            let self_ty = ir::Ty::Opaque(ir::OpaqueTy {
+                opaque_ty_id: item_id,
+                parameters: self.all_parameters()
+                    .anonymize()
+                    .iter()
+                    .zip(0..)
+                    .map(|p| p.to_parameter())
+                    .collect(),
+            });
+            let bounds = self.bounds
+                .iter()
+                .map(|b| b.lower_with_self(self_ty.clone(), env))
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(ir::OpaqueTyDatumBound {
+                bounds,
+                hidden_ty: self.hidden_ty.lower(env)?,
+            })
+        })?;
+
+        Ok(ir::OpaqueTyDatum {
+            opaque_ty_id: item_id,
+            binders,
+        })
+    }
+}
+
+trait LowerClosureDefn {
+    fn lower_closure(&self, item_id: ir::ItemId, env: &Env) -> Result<ir::ClosureDatum>;
 }
 
-impl LowerStructDefn for StructDefn {
-    fn lower_struct(&self, item_id: ir::ItemId, env: &Env) -> Result<ir::StructDatum> {
+impl LowerClosureDefn for ClosureDefn {
+    fn lower_closure(&self, item_id: ir::ItemId, env: &Env) -> Result<ir::ClosureDatum> {
+        let binders = env.in_binders(self.all_parameters(), |env| {
+            Ok(ir::ClosureDatumBound {
+                kind: match self.kind {
+                    ClosureKind::Fn => ir::ClosureKind::Fn,
+                    ClosureKind::FnMut => ir::ClosureKind::FnMut,
+                    ClosureKind::FnOnce => ir::ClosureKind::FnOnce,
+                },
+                inputs: self.arg_tys
+                    .iter()
+                    .map(|ty| ty.lower(env))
+                    .collect::<Result<_>>()?,
+                output: self.return_ty.lower(env)?,
+            })
+        })?;
+
+        Ok(ir::ClosureDatum {
+            closure_id: item_id,
+            binders,
+        })
+    }
+}
+
+trait LowerAdtDefn {
+    fn lower_adt(&self, item_id: ir::ItemId, env: &Env) -> Result<ir::AdtDatum>;
+}
+
+impl LowerAdtDefn for AdtDefn {
+    fn lower_adt(&self, item_id: ir::ItemId, env: &Env) -> Result<ir::AdtDatum> {
         let binders = env.in_binders(self.all_parameters(), |env| {
             let self_ty = ir::ApplicationTy {
                 name: ir::TypeName::ItemId(item_id),
@@ -562,20 +1211,37 @@ impl LowerStructDefn for StructDefn {
                     .collect(),
             };
 
-            let fields: Result<_> = self.fields.iter().map(|f| f.ty.lower(env)).collect();
+            let variants = self.variants
+                .iter()
+                .map(|variant| {
+                    Ok(ir::AdtVariant {
+                        name: variant.name.str,
+                        fields: variant.fields
+                            .iter()
+                            .map(|f| f.ty.lower(env))
+                            .collect::<Result<_>>()?,
+                    })
+                })
+                .collect::<Result<_>>()?;
             let where_clauses = self.lower_where_clauses(env)?;
 
-            Ok(ir::StructDatumBound {
+            Ok(ir::AdtDatumBound {
                 self_ty,
-                fields: fields?,
+                kind: match self.kind {
+                    AdtKind::Struct => ir::AdtKind::Struct,
+                    AdtKind::Enum => ir::AdtKind::Enum,
+                    AdtKind::Union => ir::AdtKind::Union,
+                },
+                variants,
                 where_clauses,
-                flags: ir::StructFlags {
+                flags: ir::AdtFlags {
                     external: self.flags.external,
+                    fundamental: self.flags.fundamental,
                 },
             })
         })?;
 
-        Ok(ir::StructDatum { binders })
+        Ok(ir::AdtDatum { binders })
     }
 }
 
@@ -605,10 +1271,11 @@ impl LowerTraitRef for TraitRef {
             bail!(ErrorKind::NotTrait(self.trait_name));
         }
 
-        let parameters = self.args
+        let mut parameters = self.args
             .iter()
             .map(|a| Ok(a.lower(env)?))
             .collect::<Result<Vec<_>>>()?;
+        env.apply_defaults(id, &mut parameters, k.binders.len() + 1);
 
         if parameters.len() != k.binders.len() + 1 {
             bail!(
@@ -629,6 +1296,104 @@ impl LowerTraitRef for TraitRef {
     }
 }
 
+trait LowerTraitBound {
+    fn lower_with_self(&self, self_ty: ir::Ty, env: &Env) -> Result<ir::TraitRef>;
+}
+
+impl LowerTraitBound for TraitBound {
+    fn lower_with_self(&self, self_ty: ir::Ty, env: &Env) -> Result<ir::TraitRef> {
+        let id = match env.lookup(self.trait_name)? {
+            NameLookup::Type(id) => id,
+            NameLookup::Parameter(_) => bail!(ErrorKind::NotTrait(self.trait_name)),
+        };
+
+        let k = env.type_kind(id);
+        if k.sort != ir::TypeSort::Trait {
+            bail!(ErrorKind::NotTrait(self.trait_name));
+        }
+
+        let mut parameters = Some(Ok(ir::ParameterKind::Ty(self_ty)))
+            .into_iter()
+            .chain(self.args_no_self.iter().map(|a| a.lower(env)))
+            .collect::<Result<Vec<_>>>()?;
+        env.apply_defaults(id, &mut parameters, k.binders.len() + 1);
+
+        if parameters.len() != k.binders.len() + 1 {
+            bail!(
+                "wrong number of parameters, expected `{:?}`, got `{:?}`",
+                k.binders.len(),
+                parameters.len() - 1
+            )
+        }
+
+        for (binder, param) in k.binders.binders.iter().zip(parameters.iter().skip(1)) {
+            check_type_kinds("incorrect kind for trait parameter", binder, param)?;
+        }
+
+        Ok(ir::TraitRef {
+            trait_id: id,
+            parameters: parameters,
+        })
+    }
+}
+
+trait LowerInlineBound {
+    /// Lowers a bound such as `Foo<K>` or `Foo<Item = K>` into a where
+    /// clause about `self_ty`.
+    fn lower_with_self(&self, self_ty: ir::Ty, env: &Env) -> Result<ir::WhereClauseAtom>;
+}
+
+impl LowerInlineBound for InlineBound {
+    fn lower_with_self(&self, self_ty: ir::Ty, env: &Env) -> Result<ir::WhereClauseAtom> {
+        Ok(match *self {
+            InlineBound::TraitBound(ref b) => {
+                ir::WhereClauseAtom::Implemented(b.lower_with_self(self_ty, env)?)
+            }
+            InlineBound::ProjectionEqBound(ProjectionEqBound {
+                ref trait_bound,
+                ref name,
+                ref parameters,
+                ref value,
+            }) => {
+                let ir::TraitRef {
+                    trait_id,
+                    parameters: trait_parameters,
+                } = trait_bound.lower_with_self(self_ty, env)?;
+                let info = match env.associated_ty_infos.get(&(trait_id, name.str)) {
+                    Some(info) => info,
+                    None => bail!("no associated type `{}` defined in trait", name.str),
+                };
+                let mut args: Vec<_> = parameters
+                    .iter()
+                    .map(|a| a.lower(env))
+                    .collect::<Result<_>>()?;
+
+                if args.len() != info.addl_parameter_kinds.len() {
+                    bail!(
+                        "wrong number of parameters for associated type (expected {}, got {})",
+                        info.addl_parameter_kinds.len(),
+                        args.len()
+                    )
+                }
+
+                for (param, arg) in info.addl_parameter_kinds.iter().zip(args.iter()) {
+                    check_type_kinds("incorrect kind for associated type parameter", param, arg)?;
+                }
+
+                args.extend(trait_parameters);
+
+                ir::WhereClauseAtom::ProjectionEq(ir::ProjectionEq {
+                    projection: ir::ProjectionTy {
+                        associated_ty_id: info.id,
+                        parameters: args,
+                    },
+                    ty: value.lower(env)?,
+                })
+            }
+        })
+    }
+}
+
 trait LowerPolarizedTraitRef {
     fn lower(&self, env: &Env) -> Result<ir::PolarizedTraitRef>;
 }
@@ -684,6 +1449,36 @@ impl LowerProjectionTy for ProjectionTy {
     }
 }
 
+trait LowerConstProjection {
+    fn lower_const_projection(&self, env: &Env) -> Result<ir::ConstProjection>;
+}
+
+impl LowerConstProjection for ProjectionTy {
+    fn lower_const_projection(&self, env: &Env) -> Result<ir::ConstProjection> {
+        let ProjectionTy {
+            ref trait_ref,
+            ref name,
+            ref args,
+        } = *self;
+        let ir::TraitRef {
+            trait_id,
+            parameters,
+        } = trait_ref.lower(env)?;
+        let associated_const_id = match env.associated_const_ids.get(&(trait_id, name.str)) {
+            Some(&id) => id,
+            None => bail!("no associated const `{}` defined in trait", name.str),
+        };
+        if !args.is_empty() {
+            bail!("associated consts cannot have parameters");
+        }
+
+        Ok(ir::ConstProjection {
+            associated_const_id,
+            parameters,
+        })
+    }
+}
+
 trait LowerUnselectedProjectionTy {
     fn lower(&self, env: &Env) -> Result<ir::UnselectedProjectionTy>;
 }
@@ -706,10 +1501,25 @@ trait LowerTy {
 impl LowerTy for Ty {
     fn lower(&self, env: &Env) -> Result<ir::Ty> {
         match *self {
+            // `str` is built in, unless the program declares a type of
+            // that name itself.
+            Ty::Id { name } if name.str == intern("str") && env.lookup(name).is_err() => {
+                Ok(ir::Ty::Apply(ir::ApplicationTy {
+                    name: ir::TypeName::Str,
+                    parameters: vec![],
+                }))
+            }
+
             Ty::Id { name } => match env.lookup(name)? {
                 NameLookup::Type(id) => {
                     let k = env.type_kind(id);
-                    if k.binders.len() > 0 {
+                    if k.sort == ir::TypeSort::Predicate {
+                        bail!(ErrorKind::InvalidTypeName(name))
+                    }
+
+                    let mut parameters = vec![];
+                    env.apply_defaults(id, &mut parameters, k.binders.len());
+                    if k.binders.len() != parameters.len() {
                         bail!(ErrorKind::IncorrectNumberOfTypeParameters(
                             name,
                             k.binders.len(),
@@ -717,9 +1527,16 @@ impl LowerTy for Ty {
                         ))
                     }
 
+                    if k.sort == ir::TypeSort::Opaque {
+                        return Ok(ir::Ty::Opaque(ir::OpaqueTy {
+                            opaque_ty_id: id,
+                            parameters,
+                        }));
+                    }
+
                     Ok(ir::Ty::Apply(ir::ApplicationTy {
                         name: ir::TypeName::ItemId(id),
-                        parameters: vec![],
+                        parameters,
                     }))
                 }
                 NameLookup::Parameter(d) => Ok(ir::Ty::Var(d)),
@@ -732,7 +1549,16 @@ impl LowerTy for Ty {
                 };
 
                 let k = env.type_kind(id);
-                if k.binders.len() != args.len() {
+                if k.sort == ir::TypeSort::Predicate {
+                    bail!(ErrorKind::InvalidTypeName(name))
+                }
+
+                let mut parameters = args.iter()
+                    .map(|t| Ok(t.lower(env)?))
+                    .collect::<Result<Vec<_>>>()?;
+                env.apply_defaults(id, &mut parameters, k.binders.len());
+
+                if k.binders.len() != parameters.len() {
                     bail!(ErrorKind::IncorrectNumberOfTypeParameters(
                         name,
                         k.binders.len(),
@@ -740,14 +1566,17 @@ impl LowerTy for Ty {
                     ))
                 }
 
-                let parameters = args.iter()
-                    .map(|t| Ok(t.lower(env)?))
-                    .collect::<Result<Vec<_>>>()?;
-
-                for (param, arg) in k.binders.binders.iter().zip(args.iter()) {
+                for (param, arg) in k.binders.binders.iter().zip(parameters.iter()) {
                     check_type_kinds("incorrect parameter kind", param, arg)?;
                 }
 
+                if k.sort == ir::TypeSort::Opaque {
+                    return Ok(ir::Ty::Opaque(ir::OpaqueTy {
+                        opaque_ty_id: id,
+                        parameters,
+                    }));
+                }
+
                 Ok(ir::Ty::Apply(ir::ApplicationTy {
                     name: ir::TypeName::ItemId(id),
                     parameters: parameters,
@@ -777,10 +1606,129 @@ impl LowerTy for Ty {
                 };
                 Ok(ir::Ty::ForAll(Box::new(quantified_ty)))
             }
+
+            Ty::Dyn { ref bounds } => {
+                let mut trait_bounds = vec![];
+                let mut lifetime = None;
+                for bound in bounds {
+                    match bound {
+                        DynBound::Trait(trait_bound) => {
+                            // The self type is dropped again right away;
+                            // the bound is stored without it.
+                            let trait_ref = trait_bound.lower_with_self(ir::Ty::Var(0), env)?;
+                            trait_bounds.push(ir::DynBound {
+                                trait_id: trait_ref.trait_id,
+                                parameters: trait_ref.parameters[1..].to_vec(),
+                            });
+                        }
+                        DynBound::Lifetime(l) => {
+                            if lifetime.is_some() {
+                                bail!("a trait object type can only have one lifetime bound");
+                            }
+                            lifetime = Some(l.lower(env)?);
+                        }
+                    }
+                }
+
+                if trait_bounds.is_empty() {
+                    bail!("a trait object type needs at least one trait bound");
+                }
+                trait_bounds.sort();
+                trait_bounds.dedup();
+
+                Ok(ir::Ty::Dyn(ir::DynTy {
+                    bounds: trait_bounds,
+                    lifetime,
+                }))
+            }
+
+            Ty::FnPtr {
+                ref arg_tys,
+                ref return_ty,
+            } => {
+                let parameters = arg_tys
+                    .iter()
+                    .chain(Some(&**return_ty))
+                    .map(|ty| Ok(ir::ParameterKind::Ty(ty.lower(env)?)))
+                    .collect::<Result<_>>()?;
+                Ok(ir::Ty::Apply(ir::ApplicationTy {
+                    name: ir::TypeName::FnPtr(arg_tys.len()),
+                    parameters,
+                }))
+            }
+
+            Ty::Scalar { ty } => Ok(ir::Ty::Apply(ir::ApplicationTy {
+                name: ir::TypeName::Scalar(ty),
+                parameters: vec![],
+            })),
+
+            Ty::Never => Ok(ir::TypeName::Never.to_ty()),
+
+            Ty::Ref {
+                ref lifetime,
+                mutable,
+                ref ty,
+            } => Ok(ir::Ty::Apply(ir::ApplicationTy {
+                name: ir::TypeName::Ref(lower_mutability(mutable)),
+                parameters: vec![
+                    ir::ParameterKind::Lifetime(lifetime.lower(env)?),
+                    ir::ParameterKind::Ty(ty.lower(env)?),
+                ],
+            })),
+
+            Ty::RawPtr { mutable, ref ty } => Ok(ir::Ty::Apply(ir::ApplicationTy {
+                name: ir::TypeName::RawPtr(lower_mutability(mutable)),
+                parameters: vec![ir::ParameterKind::Ty(ty.lower(env)?)],
+            })),
+
+            Ty::Slice { ref ty } => Ok(ir::Ty::Apply(ir::ApplicationTy {
+                name: ir::TypeName::Slice,
+                parameters: vec![ir::ParameterKind::Ty(ty.lower(env)?)],
+            })),
+
+            Ty::Array { ref ty, len } => Ok(ir::Ty::Apply(ir::ApplicationTy {
+                name: ir::TypeName::Array(len),
+                parameters: vec![ir::ParameterKind::Ty(ty.lower(env)?)],
+            })),
+
+            Ty::Tuple { ref types } => lower_tuple(types, env),
+
+            Ty::Hole => {
+                let holes = match env.holes {
+                    Some(holes) => holes,
+                    None => bail!("type holes (`_`) are only allowed in goals"),
+                };
+
+                // The holes are bound by `exists` binders wrapped around
+                // the whole goal, outside of every binder in scope here;
+                // see `LowerGoalWithHoles`.
+                let hole = holes.get();
+                holes.set(hole + 1);
+                Ok(ir::Ty::Var(env.parameter_map.len() + hole))
+            }
         }
     }
 }
 
+/// Lowers the tuple type with elements `types`.
+fn lower_tuple(types: &[Ty], env: &Env) -> Result<ir::Ty> {
+    Ok(ir::Ty::Apply(ir::ApplicationTy {
+        name: ir::TypeName::Tuple(types.len()),
+        parameters: types
+            .iter()
+            .map(|ty| Ok(ir::ParameterKind::Ty(ty.lower(env)?)))
+            .collect::<Result<_>>()?,
+    }))
+}
+
+fn lower_mutability(mutable: bool) -> ir::Mutability {
+    if mutable {
+        ir::Mutability::Mut
+    } else {
+        ir::Mutability::Not
+    }
+}
+
 trait LowerParameter {
     fn lower(&self, env: &Env) -> Result<ir::Parameter>;
 }
@@ -788,12 +1736,29 @@ trait LowerParameter {
 impl LowerParameter for Parameter {
     fn lower(&self, env: &Env) -> Result<ir::Parameter> {
         match *self {
-            Parameter::Ty(ref t) => Ok(ir::ParameterKind::Ty(t.lower(env)?)),
+            Parameter::Ty(ref t) => {
+                if let Ty::Id { name } = *t {
+                    if let Some(d) = env.lookup_const(name) {
+                        return Ok(ir::ParameterKind::Const(ir::Const::Var(d)));
+                    }
+                }
+                Ok(ir::ParameterKind::Ty(t.lower(env)?))
+            }
             Parameter::Lifetime(ref l) => Ok(ir::ParameterKind::Lifetime(l.lower(env)?)),
+            Parameter::Const(value) => Ok(ir::ParameterKind::Const(ir::Const::Value(value))),
         }
     }
 }
 
+/// Lowers a parameter that must be a const, like the value of an
+/// associated const.
+fn lower_const(parameter: &Parameter, env: &Env) -> Result<ir::Const> {
+    match parameter.lower(env)? {
+        ir::ParameterKind::Const(c) => Ok(c),
+        _ => bail!("expected a const"),
+    }
+}
+
 trait LowerLifetime {
     fn lower(&self, env: &Env) -> Result<ir::Lifetime>;
 }
@@ -817,22 +1782,32 @@ impl LowerImpl for Impl {
         let binders = empty_env.in_binders(self.all_parameters(), |env| {
             let trait_ref = self.trait_ref.lower(env)?;
 
-            if !trait_ref.is_positive() && !self.assoc_ty_values.is_empty() {
+            if !trait_ref.is_positive()
+                && (!self.assoc_ty_values.is_empty() || !self.assoc_const_values.is_empty())
+            {
                 bail!("negative impls cannot define associated values");
             }
+            if !trait_ref.is_positive() && self.default {
+                bail!("negative impls cannot be `default`");
+            }
 
             let trait_id = trait_ref.trait_ref().trait_id;
             let where_clauses = self.lower_where_clauses(&env)?;
             let associated_ty_values = try!(
                 self.assoc_ty_values
                     .iter()
-                    .map(|v| v.lower(trait_id, env))
+                    .map(|v| v.lower(trait_id, self.default, env))
                     .collect()
             );
+            let associated_const_values = self.assoc_const_values
+                .iter()
+                .map(|v| v.lower(trait_id, env))
+                .collect::<Result<_>>()?;
             Ok(ir::ImplDatumBound {
                 trait_ref,
                 where_clauses,
                 associated_ty_values,
+                associated_const_values,
                 specialization_priority: 0,
             })
         })?;
@@ -841,6 +1816,76 @@ impl LowerImpl for Impl {
     }
 }
 
+trait LowerInherentImpl {
+    fn lower_inherent_impl(&self, empty_env: &Env) -> Result<ir::InherentImplDatum>;
+}
+
+impl LowerInherentImpl for InherentImpl {
+    fn lower_inherent_impl(&self, empty_env: &Env) -> Result<ir::InherentImplDatum> {
+        let binders = empty_env.in_binders(self.all_parameters(), |env| {
+            let id = match env.lookup(self.type_name)? {
+                NameLookup::Type(id) => id,
+                NameLookup::Parameter(_) => bail!(ErrorKind::NotStruct(self.type_name)),
+            };
+
+            let k = env.type_kind(id);
+            if k.sort != ir::TypeSort::Adt {
+                bail!(ErrorKind::NotStruct(self.type_name));
+            }
+
+            if k.binders.len() != self.args.len() {
+                bail!(ErrorKind::IncorrectNumberOfTypeParameters(
+                    self.type_name,
+                    k.binders.len(),
+                    self.args.len()
+                ))
+            }
+
+            let parameters = self.args
+                .iter()
+                .map(|a| a.lower(env))
+                .collect::<Result<Vec<_>>>()?;
+
+            for (param, arg) in k.binders.binders.iter().zip(parameters.iter()) {
+                check_type_kinds("incorrect parameter kind", param, arg)?;
+            }
+
+            if self.default {
+                bail!("inherent impls cannot be `default`");
+            }
+
+            let associated_ty_values = self.assoc_ty_values
+                .iter()
+                .map(|v| {
+                    if v.default {
+                        bail!("inherent impls cannot define `default` associated types");
+                    }
+                    let value = env.in_binders(v.all_parameters(), |env| {
+                        Ok(ir::AssociatedTyValueBound {
+                            ty: v.value.lower(env)?,
+                        })
+                    })?;
+                    Ok(ir::InherentAssociatedTyValue {
+                        name: v.name.str,
+                        value,
+                    })
+                })
+                .collect::<Result<_>>()?;
+
+            Ok(ir::InherentImplDatumBound {
+                self_ty: ir::ApplicationTy {
+                    name: ir::TypeName::ItemId(id),
+                    parameters,
+                },
+                where_clauses: self.lower_where_clauses(&env)?,
+                associated_ty_values,
+            })
+        })?;
+
+        Ok(ir::InherentImplDatum { binders })
+    }
+}
+
 trait LowerClause {
     fn lower_clause(&self, env: &Env) -> Result<Vec<ir::ProgramClause>>;
 }
@@ -885,11 +1930,23 @@ impl LowerClause for Clause {
 }
 
 trait LowerAssocTyValue {
-    fn lower(&self, trait_id: ir::ItemId, env: &Env) -> Result<ir::AssociatedTyValue>;
+    /// Lowers the value as defined in an impl of `trait_id`; in a
+    /// `default impl`, every value is `default`.
+    fn lower(
+        &self,
+        trait_id: ir::ItemId,
+        default_impl: bool,
+        env: &Env,
+    ) -> Result<ir::AssociatedTyValue>;
 }
 
 impl LowerAssocTyValue for AssocTyValue {
-    fn lower(&self, trait_id: ir::ItemId, env: &Env) -> Result<ir::AssociatedTyValue> {
+    fn lower(
+        &self,
+        trait_id: ir::ItemId,
+        default_impl: bool,
+        env: &Env,
+    ) -> Result<ir::AssociatedTyValue> {
         let info = &env.associated_ty_infos[&(trait_id, self.name.str)];
         let value = env.in_binders(self.all_parameters(), |env| {
             Ok(ir::AssociatedTyValueBound {
@@ -899,6 +1956,25 @@ impl LowerAssocTyValue for AssocTyValue {
         Ok(ir::AssociatedTyValue {
             associated_ty_id: info.id,
             value: value,
+            default: self.default || default_impl,
+        })
+    }
+}
+
+trait LowerAssocConstValue {
+    fn lower(&self, trait_id: ir::ItemId, env: &Env) -> Result<ir::AssociatedConstValue>;
+}
+
+impl LowerAssocConstValue for AssocConstValue {
+    fn lower(&self, trait_id: ir::ItemId, env: &Env) -> Result<ir::AssociatedConstValue> {
+        let key = (trait_id, self.name.str);
+        let associated_const_id = match env.associated_const_ids.get(&key) {
+            Some(&id) => id,
+            None => bail!("no associated const `{}` defined in trait", self.name.str),
+        };
+        Ok(ir::AssociatedConstValue {
+            associated_const_id,
+            value: lower_const(&self.value, env)?,
         })
     }
 }
@@ -924,14 +2000,39 @@ impl LowerTrait for TraitDefn {
                 }
             }
 
+            let mut where_clauses = self.lower_where_clauses(env)?;
+
+            // A trait alias requires its bounds just like where clauses.
+            if let Some(ref bounds) = self.alias {
+                let flags = &self.flags;
+                if flags.auto || flags.marker || flags.deref || flags.sized || flags.fn_trait
+                    || flags.fn_mut || flags.fn_once || flags.copy || flags.clone
+                    || flags.unsize
+                {
+                    bail!("trait alias cannot be an auto, marker or lang item trait");
+                }
+
+                let self_ty = trait_ref.parameters[0].assert_ty_ref().clone();
+                for bound in bounds {
+                    let wc = ir::DomainGoal::Holds(bound.lower_with_self(self_ty.clone(), env)?);
+                    where_clauses.push(ir::Binders {
+                        binders: vec![],
+                        value: wc,
+                    });
+                }
+            }
+
             Ok(ir::TraitDatumBound {
                 trait_ref: trait_ref,
-                where_clauses: self.lower_where_clauses(env)?,
+                where_clauses,
                 flags: ir::TraitFlags {
                     auto: self.flags.auto,
                     marker: self.flags.marker,
                     external: self.flags.external,
+                    fundamental: self.flags.fundamental,
                     deref: self.flags.deref,
+                    sized: self.flags.sized,
+                    alias: self.alias.is_some(),
                 },
             })
         })?;
@@ -946,6 +2047,23 @@ pub trait LowerGoal<A> {
 
 impl LowerGoal<ir::Program> for Goal {
     fn lower(&self, program: &ir::Program) -> Result<Box<ir::Goal>> {
+        Ok(self.lower_with_holes(program)?.0)
+    }
+}
+
+pub trait LowerGoalWithHoles {
+    /// Lowers a goal that may contain type holes (`_`), each of which
+    /// becomes a fresh existential variable: the goal is wrapped in an
+    /// `exists` binder per hole, in the order they appear. Since these
+    /// binders are outermost, a hole cannot stand for a type that
+    /// mentions the goal's own `forall` binders. Returns the lowered
+    /// goal along with the number of holes; see
+    /// `ir::Goal::into_peeled_goal_with_holes`.
+    fn lower_with_holes(&self, program: &ir::Program) -> Result<(Box<ir::Goal>, usize)>;
+}
+
+impl LowerGoalWithHoles for Goal {
+    fn lower_with_holes(&self, program: &ir::Program) -> Result<(Box<ir::Goal>, usize)> {
         let associated_ty_infos: BTreeMap<_, _> = program
             .associated_ty_data
             .iter()
@@ -961,15 +2079,33 @@ impl LowerGoal<ir::Program> for Goal {
                 ((datum.trait_id, datum.name), info)
             })
             .collect();
+        let associated_const_ids: BTreeMap<_, _> = program
+            .associated_const_data
+            .iter()
+            .map(|(&associated_const_id, datum)| {
+                ((datum.trait_id, datum.name), associated_const_id)
+            })
+            .collect();
 
+        let holes = Cell::new(0);
         let env = Env {
             type_ids: &program.type_ids,
             type_kinds: &program.type_kinds,
             associated_ty_infos: &associated_ty_infos,
+            associated_const_ids: &associated_const_ids,
+            parameter_defaults: &program.parameter_defaults,
+            lang_items: &program.lang_items,
             parameter_map: BTreeMap::new(),
+            holes: Some(&holes),
         };
 
-        self.lower(&env)
+        let goal = self.lower(&env)?;
+        let num_holes = holes.get();
+        if num_holes == 0 {
+            return Ok((goal, 0));
+        }
+        let binders = vec![ir::ParameterKind::Ty(()); num_holes];
+        Ok((Box::new((*goal).quantify(ir::QuantifierKind::Exists, binders)), num_holes))
     }
 }
 
@@ -1009,6 +2145,29 @@ impl<'k> LowerGoal<Env<'k>> for Goal {
     }
 }
 
+pub trait PeeledBinderNames {
+    /// The names of the `forall` binders that `ir::Goal::into_peeled_goal`
+    /// turns into placeholders, in the order it does so.
+    fn peeled_binder_names(&self) -> Vec<ir::ParameterKind<ir::Identifier>>;
+}
+
+impl PeeledBinderNames for Goal {
+    fn peeled_binder_names(&self) -> Vec<ir::ParameterKind<ir::Identifier>> {
+        let mut names = vec![];
+        let mut goal = self;
+        loop {
+            match goal {
+                Goal::ForAll(ids, g) => {
+                    names.extend(ids.iter().map(|id| id.lower()));
+                    goal = &**g;
+                }
+                Goal::Exists(_, g) | Goal::Implies(_, g) => goal = &**g,
+                _ => return names,
+            }
+        }
+    }
+}
+
 trait LowerQuantifiedGoal {
     fn lower_quantified(
         &self,
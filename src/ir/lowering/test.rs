@@ -69,6 +69,26 @@ fn auto_trait() {
     }
 }
 
+#[test]
+fn marker_trait() {
+    lowering_error! {
+        program {
+            #[marker] trait Foo {
+                type Item;
+            }
+        }
+        error_msg {
+            "marker trait cannot define associated types"
+        }
+    }
+
+    lowering_success! {
+        program {
+            #[marker] trait Foo { }
+        }
+    }
+}
+
 #[test]
 fn negative_impl() {
     lowering_error! {
@@ -77,8 +97,6 @@ fn negative_impl() {
                 type Item;
             }
 
-            struct i32 { }
-
             impl !Foo for i32 {
                 type Item = i32;
             }
@@ -96,8 +114,6 @@ fn negative_impl() {
                 type Item;
             }
 
-            struct i32 { }
-
             impl<T> !Foo for T where T: Iterator<Item = i32> { }
         }
     }
@@ -117,6 +133,18 @@ fn invalid_name() {
     }
 }
 
+#[test]
+fn scalar_type_redeclared() {
+    lowering_error! {
+        program {
+            struct i32 { }
+        }
+        error_msg {
+            "`i32` is a built-in scalar type and cannot be declared"
+        }
+    }
+}
+
 #[test]
 fn type_parameter() {
     lowering_success! {
@@ -212,9 +240,11 @@ fn atc_accounting() {
                 associated_ty_id: (Iterable::Iter),
                 value: for<lifetime> AssociatedTyValueBound {
                     ty: Iter<'?0, ?1>
-                }
+                },
+                default: false
             }
         ],
+        associated_const_values: [],
         specialization_priority: 0
     }
 }"#
@@ -238,7 +268,6 @@ fn check_parameter_kinds() {
     lowering_error! {
         program {
             struct Foo<'a> { }
-            struct i32 { }
             trait Bar { }
             impl Bar for Foo<i32> { }
         }
@@ -308,6 +337,7 @@ fn gat_parse() {
     lowering_success! {
         program {
             trait Sized {}
+            trait Clone {}
 
             trait Foo {
                 type Item<'a, T>: Sized + Clone where Self: Sized;
@@ -344,6 +374,44 @@ fn gat_parse() {
             "invalid type name `K`"
         }
     }
+
+    lowering_error! {
+        program {
+            struct Sized { }
+
+            trait Foo {
+                type Item: Sized;
+            }
+        }
+
+        error_msg {
+            "expected a trait, found `Sized`, which is not a trait"
+        }
+    }
+}
+
+#[test]
+fn type_holes() {
+    lowering_error! {
+        program {
+            trait Foo { }
+            impl Foo for _ { }
+        }
+        error_msg {
+            "type holes (`_`) are only allowed in goals"
+        }
+    }
+
+    let program = Arc::new(
+        parse_and_lower_program("trait Foo<T> { } struct Vec<T> { }", SolverChoice::slg()).unwrap()
+    );
+    let goal = parse_and_lower_goal(&program, "forall<T> { Vec<_>: Foo<_> }").unwrap();
+    tls::set_current_program(&program, || {
+        assert_eq!(
+            format!("{:?}", goal),
+            "Exists<type, type> { ForAll<type> { Implemented(Vec<?1>: Foo<?2>) } }"
+        );
+    });
 }
 
 #[test]
@@ -370,3 +438,331 @@ fn duplicate_parameters() {
         }
     }
 }
+
+#[test]
+fn predicates() {
+    lowering_success! {
+        program {
+            predicate Reaches(T, U);
+            struct Foo { }
+            forall<T> { Reaches(T, T) }
+            forall<T, U> { Reaches(T, Foo) if Reaches(T, U), Reaches(U, Foo) }
+        }
+    }
+
+    lowering_error! {
+        program {
+            predicate Reaches(T, U);
+            struct Foo { }
+            forall<T> { Reaches(T) }
+        }
+
+        error_msg {
+            "`Reaches` takes 2 type parameters, not 1"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Bar { }
+            struct Foo { }
+            forall<> { Bar(Foo) }
+        }
+
+        error_msg {
+            "expected a predicate, found `Bar`, which is not a predicate"
+        }
+    }
+
+    lowering_error! {
+        program {
+            predicate Reaches(T, U);
+            struct Foo { field: Reaches }
+        }
+
+        error_msg {
+            "invalid type name `Reaches`"
+        }
+    }
+}
+
+#[test]
+fn inherent_impls() {
+    lowering_success! {
+        program {
+            struct Foo<T> { }
+            impl<T> Foo<T> { type Bar<'a> = T; }
+            impl Foo<u32> { }
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { }
+            impl Foo { type Bar = Foo; }
+        }
+        error_msg {
+            "expected a struct, found `Foo`, which is not a struct"
+        }
+    }
+
+    lowering_error! {
+        program {
+            struct Foo<T> { }
+            impl Foo { }
+        }
+        error_msg {
+            "`Foo` takes 1 type parameters, not 0"
+        }
+    }
+}
+
+#[test]
+fn environment_is_memoized() {
+    // The overlapping impls make lowering compute the environment for
+    // coherence checking, before specialization priorities are set.
+    let program = parse_and_lower_program(
+        "trait Foo { }
+         struct Vec<T> { }
+         impl<T> Foo for Vec<T> { }
+         impl Foo for Vec<u32> { }",
+        SolverChoice::slg(),
+    ).unwrap();
+
+    let env = program.environment();
+    assert!(Arc::ptr_eq(&env, &program.environment()));
+    assert_eq!(*env, program.compute_environment());
+}
+
+#[test]
+fn item_attributes() {
+    let program = parse_and_lower_program(
+        r#"#[hidden] #[since = "1.2"] extern struct Foo { }
+           #[feature = "x"] #[marker] trait Send { }
+           #[feature = "y"] impl Send for Foo { }
+           trait Clone { }"#,
+        SolverChoice::slg(),
+    ).unwrap();
+    let (foo, send, clone) = (
+        type_id(&program, "Foo"),
+        type_id(&program, "Send"),
+        type_id(&program, "Clone"),
+    );
+    let send_impl = impl_id(&program, "Foo as Send");
+
+    let attributes: Vec<_> = program
+        .item_attributes(foo)
+        .iter()
+        .map(|a| (a.key(), a.value()))
+        .collect();
+    assert_eq!(
+        attributes,
+        vec![
+            ("hidden".to_string(), None),
+            ("since".to_string(), Some("1.2".to_string())),
+        ]
+    );
+    assert!(program.item_attributes(clone).is_empty());
+
+    // The attributes are available to the solver as well.
+    let env = program.environment();
+    let featured: Vec<_> = env.items_with_attribute("feature").collect();
+    assert_eq!(featured, vec![send, send_impl]);
+    assert_eq!(env.item_attributes(send_impl)[0].value(), Some("y".to_string()));
+}
+
+#[test]
+fn dyn_trait() {
+    lowering_success! {
+        program {
+            trait Foo<T> { }
+            #[auto] trait Send { }
+            struct Box<T> { }
+            struct Bar<'a, T> { field: Box<dyn Foo<T> + Send + 'a> }
+        }
+    }
+
+    lowering_error! {
+        program {
+            struct Box<T> { }
+            struct Bar<'a> { field: Box<dyn 'a> }
+        }
+        error_msg {
+            "a trait object type needs at least one trait bound"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { }
+            struct Box<T> { }
+            struct Bar<'a, 'b> { field: Box<dyn Foo + 'a + 'b> }
+        }
+        error_msg {
+            "a trait object type can only have one lifetime bound"
+        }
+    }
+
+    lowering_error! {
+        program {
+            struct Foo { }
+            struct Box<T> { }
+            struct Bar { field: Box<dyn Foo> }
+        }
+        error_msg {
+            "expected a trait, found `Foo`, which is not a trait"
+        }
+    }
+}
+
+#[test]
+fn opaque_types() {
+    lowering_success! {
+        program {
+            trait Iterator { type Item; }
+            struct Vec<T> { }
+            struct IntoIter<T> { }
+            type Iter<T> = impl Iterator<Item = T> = IntoIter<T>;
+            struct Foo<T> { field: Vec<Iter<T>> }
+        }
+    }
+
+    lowering_error! {
+        program {
+            struct Vec<T> { }
+            type Foo<T> = impl Vec<T> = Vec<T>;
+        }
+        error_msg {
+            "expected a trait, found `Vec`, which is not a trait"
+        }
+    }
+}
+
+#[test]
+fn closures() {
+    lowering_success! {
+        program {
+            #[lang_fn_once] trait FnOnce<Args> { type Output; }
+            #[lang_fn_mut] trait FnMut<Args> where Self: FnOnce<Args> { }
+            struct Foo<F> where F: FnMut(u32, (u32, u32)) -> () { }
+            closure mut c<'a, T>(T, (u32,)) -> (T, u32);
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[lang_fn_once] trait FnOnce<Args> { type Output; }
+            trait Bar<Args> { }
+            struct Foo<F> where F: Bar(u32) { }
+        }
+        error_msg {
+            "`Bar` is not one of the `Fn` traits"
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[lang_fn_once] trait FnOnce<Args> { }
+            struct Foo<F> where F: FnOnce() { }
+        }
+        error_msg {
+            "no `#[lang_fn_once]` trait with an `Output` type"
+        }
+    }
+}
+
+#[test]
+fn assoc_consts() {
+    lowering_success! {
+        program {
+            struct Foo<const N: usize> { }
+            trait Len { type Item; const LEN: usize; }
+            impl<const N> Len for Foo<N> { const LEN: usize = N; type Item = Foo<3>; }
+        }
+    }
+
+    lowering_error! {
+        program {
+            struct Foo { }
+            trait Len { const LEN: usize; }
+            impl Len for Foo { const SIZE: usize = 3; }
+        }
+        error_msg {
+            "no associated const `SIZE` defined in trait"
+        }
+    }
+
+    lowering_error! {
+        program {
+            struct Foo { }
+            trait Len { const LEN: usize; }
+            impl Len for Foo { const LEN: usize = Foo; }
+        }
+        error_msg {
+            "expected a const"
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[auto] trait Send { const LEN: usize; }
+        }
+        error_msg {
+            "auto trait cannot define associated consts"
+        }
+    }
+}
+
+#[test]
+fn trait_aliases() {
+    lowering_success! {
+        program {
+            trait Foo { }
+            trait Iterator { type Item; }
+            trait Alias<T> = Foo + Iterator<Item = T> where T: Foo;
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { }
+            trait Alias = Foo;
+            struct S { }
+            impl Alias for S { }
+        }
+        error_msg {
+            "cannot implement trait alias `Alias`"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Foo { }
+            #[auto] trait Alias = Foo;
+        }
+        error_msg {
+            "trait alias cannot be an auto, marker or lang item trait"
+        }
+    }
+}
+
+#[test]
+fn parameter_defaults() {
+    lowering_success! {
+        program {
+            struct A { }
+            struct Foo<T, U = Foo<T, A>> { }
+            trait Eq<Rhs = Self> where Self: Eq<Rhs> { }
+        }
+    }
+
+    lowering_error! {
+        program {
+            struct A { }
+            struct Foo<T = A, U> { }
+        }
+        error_msg {
+            "generic parameters with a default must be trailing"
+        }
+    }
+}
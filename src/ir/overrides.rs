@@ -0,0 +1,67 @@
+//! Overriding how the solver treats the goals of particular traits,
+//! to work around pathological cases -- say, a trait with infinitely
+//! many impls -- without changing the settings for every goal.
+
+use errors::*;
+use ir::*;
+use lalrpop_intern::intern;
+
+/// Solver behavior for the goals of one trait; see
+/// `Program::override_trait`. The default changes nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TraitOverrides {
+    /// Consider the trait implemented for all types, as if by the
+    /// impl `impl<T> Trait for T { }`, on top of its actual impls. For
+    /// traits like `Unpin` whose impls are beside the point.
+    pub always_implemented: bool,
+
+    /// Treat the goals of the trait as coinductive, like those of auto
+    /// traits, so that cycles through them are accepted.
+    pub coinductive: bool,
+
+    /// Stop enumerating answers to goals of the trait after this many.
+    /// The goals then get one last answer, which is ambiguous and
+    /// leaves all their variables unconstrained, in place of those
+    /// that were not enumerated. Only the SLG solver enumerates
+    /// answers, so this has no effect on the naive solver.
+    pub max_answers: Option<usize>,
+}
+
+impl Program {
+    /// Overrides how the solver treats the goals of the trait called
+    /// `trait_name`. Overriding a trait again replaces its overrides.
+    pub fn override_trait(&mut self, trait_name: &str, overrides: TraitOverrides) -> Result<()> {
+        let trait_name = intern(trait_name);
+        let trait_id = match self.type_ids.get(&trait_name) {
+            Some(id) if self.trait_data.contains_key(id) => *id,
+            _ => bail!(ErrorKind::UnknownTrait(trait_name)),
+        };
+        if overrides == TraitOverrides::default() {
+            self.trait_overrides.remove(&trait_id);
+        } else {
+            self.trait_overrides.insert(trait_id, overrides);
+        }
+        self.invalidate_environment();
+        Ok(())
+    }
+
+    /// The overrides for the trait `trait_id`, if any.
+    pub fn trait_overrides(&self, trait_id: ItemId) -> Option<&TraitOverrides> {
+        self.trait_overrides.get(&trait_id)
+    }
+}
+
+impl ProgramEnvironment {
+    crate fn is_coinductive_trait(&self, trait_id: ItemId) -> bool {
+        self.trait_data[&trait_id].binders.value.flags.auto
+            || self.trait_overrides
+                .get(&trait_id)
+                .map_or(false, |overrides| overrides.coinductive)
+    }
+
+    crate fn max_answers(&self, trait_id: ItemId) -> Option<usize> {
+        self.trait_overrides
+            .get(&trait_id)
+            .and_then(|overrides| overrides.max_answers)
+    }
+}
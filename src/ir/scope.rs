@@ -0,0 +1,84 @@
+//! Scopes of traits, against which unselected projections like
+//! `T::Item` are resolved.
+//!
+//! An unselected projection may refer to the associated type of any
+//! trait `Trait` for which `InScope(Trait)` holds, and such facts are
+//! only ever hypotheses of a goal. Rather than spelling them out in
+//! every goal, as in `if (InScope(Iterator)) { ... }`, clients can
+//! register the scopes of their program -- one per module, say -- and
+//! place goals within one of them.
+
+use cast::Cast;
+use errors::*;
+use ir::*;
+use lalrpop_intern::intern;
+use std::collections::BTreeSet;
+
+/// The traits in scope somewhere in a program, like a module; see
+/// `Program::register_scope`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraitScope {
+    /// Traits that are in scope by name, because they are declared or
+    /// imported there.
+    crate traits: Vec<ItemId>,
+
+    /// Other scopes, all of whose traits are in scope here too, as
+    /// with a glob import `use module::*`.
+    crate globs: Vec<Identifier>,
+}
+
+impl Program {
+    /// Registers a scope called `name`, in which the traits named
+    /// `traits` are in scope, along with every trait in scope in the
+    /// scopes named `globs`. Those need not have been registered yet,
+    /// and may themselves import this scope. Registering a scope again
+    /// replaces it.
+    pub fn register_scope(&mut self, name: &str, traits: &[&str], globs: &[&str]) -> Result<()> {
+        let traits = traits
+            .iter()
+            .map(|&trait_name| {
+                let trait_name = intern(trait_name);
+                match self.type_ids.get(&trait_name) {
+                    Some(id) if self.trait_data.contains_key(id) => Ok(*id),
+                    _ => Err(Error::from_kind(ErrorKind::UnknownTrait(trait_name))),
+                }
+            })
+            .collect::<Result<_>>()?;
+        let scope = TraitScope {
+            traits,
+            globs: globs.iter().map(|&glob| intern(glob)).collect(),
+        };
+        self.trait_scopes.insert(intern(name), scope);
+        Ok(())
+    }
+
+    /// The traits in scope in the scope called `name`, including those
+    /// imported through globs, in the order they were declared.
+    pub fn traits_in_scope(&self, name: &str) -> Result<Vec<ItemId>> {
+        let mut traits = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![intern(name)];
+        while let Some(name) = pending.pop() {
+            if !visited.insert(name) {
+                continue;
+            }
+            let scope = match self.trait_scopes.get(&name) {
+                Some(scope) => scope,
+                None => bail!(ErrorKind::UnknownScope(name)),
+            };
+            traits.extend(scope.traits.iter().cloned());
+            pending.extend(scope.globs.iter().cloned());
+        }
+        Ok(traits.into_iter().collect())
+    }
+
+    /// Places `goal` within the scope called `name`, by assuming
+    /// `InScope(Trait)` for each trait in scope there.
+    pub fn goal_in_scope(&self, name: &str, goal: Box<Goal>) -> Result<Box<Goal>> {
+        let hypotheses = self.traits_in_scope(name)?
+            .into_iter()
+            .map(|trait_id| DomainGoal::InScope(trait_id).cast())
+            .collect();
+        Ok(Box::new(Goal::Implies(hypotheses, goal)))
+    }
+}
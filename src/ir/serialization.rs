@@ -0,0 +1,446 @@
+//! Support for serializing the types of goals and solutions, and the
+//! solver settings they were found with, which is mostly derived.
+//! Identifiers are interned, and the interned indices differ from one
+//! process to the next, so they are written out as strings and
+//! interned again when read back.
+//!
+//! The same serialized form is what `stable_hash` fingerprints, for
+//! `ProgramEnvironment::program_hash`.
+
+use chalk_engine::MixedCyclePolicy;
+use chalk_parse::ast::ScalarType;
+use ir::ParameterKind;
+use lalrpop_intern::{intern, InternedString};
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+use std::error::Error;
+use std::fmt;
+
+crate mod identifier {
+    use super::*;
+
+    crate fn serialize<S: Serializer>(
+        identifier: &InternedString,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        identifier.to_string().serialize(serializer)
+    }
+
+    crate fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<InternedString, D::Error> {
+        String::deserialize(deserializer).map(|name| intern(&name))
+    }
+}
+
+crate mod optional_identifier {
+    use super::*;
+
+    crate fn serialize<S: Serializer>(
+        identifier: &Option<InternedString>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        identifier.map(|identifier| identifier.to_string()).serialize(serializer)
+    }
+
+    crate fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<InternedString>, D::Error> {
+        Option::<String>::deserialize(deserializer).map(|name| name.map(|name| intern(&name)))
+    }
+}
+
+crate mod identifier_kinds {
+    use super::*;
+
+    crate fn serialize<S: Serializer>(
+        kinds: &[ParameterKind<InternedString>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let kinds: Vec<_> = kinds.iter().map(|kind| kind.map(|name| name.to_string())).collect();
+        kinds.serialize(serializer)
+    }
+
+    crate fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<ParameterKind<InternedString>>, D::Error> {
+        Vec::<ParameterKind<String>>::deserialize(deserializer)
+            .map(|kinds| kinds.into_iter().map(|kind| kind.map(|name| intern(&name))).collect())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ScalarType")]
+crate enum ScalarTypeDef {
+    Bool,
+    Char,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+    F32,
+    F64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MixedCyclePolicy")]
+crate enum MixedCyclePolicyDef {
+    Reject,
+    Ambiguous,
+    Stratified,
+}
+
+/// A fingerprint of `value`: the 64-bit FNV-1a hash of an encoding of
+/// its serialized form. Unlike `DefaultHasher`, whose algorithm may
+/// change from one release of Rust to the next, FNV-1a is specified,
+/// so the result is the same for every build and every process and
+/// may be stored.
+///
+/// The encoding is unambiguous for values of a given type: integers
+/// are written as 8 little-endian bytes, strings and byte arrays are
+/// preceded by their length, options and enum variants by a tag, and
+/// each element of a sequence or map by a 1 byte, with a 0 byte after
+/// the last one. Struct and tuple fields are written in order.
+crate fn stable_hash<T: Serialize + ?Sized>(value: &T) -> u64 {
+    let mut hasher = StableHasher {
+        state: FNV_OFFSET_BASIS,
+    };
+    if let Err(error) = value.serialize(&mut hasher) {
+        panic!("cannot hash value: {}", error);
+    }
+    hasher.state
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    fn write_byte(&mut self, byte: u8) {
+        self.state = (self.state ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        for shift in 0..8 {
+            self.write_byte((n >> (8 * shift)) as u8);
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u64(bytes.len() as u64);
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+}
+
+/// Raised by the `Serialize` impl of a value passed to `stable_hash`;
+/// hashing itself cannot fail.
+#[derive(Debug)]
+crate struct StableHashError {
+    message: String,
+}
+
+impl fmt::Display for StableHashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for StableHashError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl ser::Error for StableHashError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        StableHashError {
+            message: message.to_string(),
+        }
+    }
+}
+
+type HashResult = Result<(), StableHashError>;
+
+impl<'a> Serializer for &'a mut StableHasher {
+    type Ok = ();
+    type Error = StableHashError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> HashResult {
+        self.write_byte(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> HashResult {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> HashResult {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> HashResult {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> HashResult {
+        self.write_u64(v as u64);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> HashResult {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> HashResult {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> HashResult {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> HashResult {
+        self.write_u64(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> HashResult {
+        self.serialize_u32(v.to_bits())
+    }
+
+    fn serialize_f64(self, v: f64) -> HashResult {
+        self.serialize_u64(v.to_bits())
+    }
+
+    fn serialize_char(self, v: char) -> HashResult {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> HashResult {
+        self.write_bytes(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> HashResult {
+        self.write_bytes(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> HashResult {
+        self.write_byte(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> HashResult {
+        self.write_byte(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> HashResult {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> HashResult {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> HashResult {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> HashResult {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> HashResult {
+        self.serialize_u32(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self, StableHashError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, StableHashError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self, StableHashError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, StableHashError> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self, StableHashError> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, StableHashError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, StableHashError> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+}
+
+impl<'a> ser::SerializeSeq for &'a mut StableHasher {
+    type Ok = ();
+    type Error = StableHashError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> HashResult {
+        self.write_byte(1);
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> HashResult {
+        self.write_byte(0);
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTuple for &'a mut StableHasher {
+    type Ok = ();
+    type Error = StableHashError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> HashResult {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> HashResult {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for &'a mut StableHasher {
+    type Ok = ();
+    type Error = StableHashError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> HashResult {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> HashResult {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for &'a mut StableHasher {
+    type Ok = ();
+    type Error = StableHashError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> HashResult {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> HashResult {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeMap for &'a mut StableHasher {
+    type Ok = ();
+    type Error = StableHashError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> HashResult {
+        self.write_byte(1);
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> HashResult {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> HashResult {
+        self.write_byte(0);
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStruct for &'a mut StableHasher {
+    type Ok = ();
+    type Error = StableHashError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> HashResult {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> HashResult {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for &'a mut StableHasher {
+    type Ok = ();
+    type Error = StableHashError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> HashResult {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> HashResult {
+        Ok(())
+    }
+}
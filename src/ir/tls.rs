@@ -1,5 +1,6 @@
 use ir;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 thread_local! {
@@ -16,14 +17,42 @@ where
     })
 }
 
+/// Runs `op` with `p` as the current program, so that items are
+/// formatted by their names. Whatever program was current before is
+/// restored afterwards.
 pub fn set_current_program<OP, R>(p: &Arc<ir::Program>, op: OP) -> R
 where
     OP: FnOnce() -> R,
 {
     PROGRAM.with(|prog_cell| {
-        *prog_cell.borrow_mut() = Some(p.clone());
+        let outer = prog_cell.replace(Some(p.clone()));
         let r = op();
-        *prog_cell.borrow_mut() = None;
+        *prog_cell.borrow_mut() = outer;
         r
     })
 }
+
+thread_local! {
+    static PLACEHOLDER_NAMES: RefCell<BTreeMap<ir::UniverseIndex, String>> =
+        RefCell::new(BTreeMap::new())
+}
+
+/// Runs `op` with the placeholders of the universes in `names` given
+/// those names when formatted; see `ir::Named`. Any names installed by
+/// the caller are hidden until `op` returns.
+pub fn with_placeholder_names<OP, R>(names: &BTreeMap<ir::UniverseIndex, String>, op: OP) -> R
+where
+    OP: FnOnce() -> R,
+{
+    PLACEHOLDER_NAMES.with(|names_cell| {
+        let outer = names_cell.replace(names.clone());
+        let r = op();
+        *names_cell.borrow_mut() = outer;
+        r
+    })
+}
+
+/// The name the placeholder of `universe` is formatted with, if any.
+pub fn placeholder_name(universe: ir::UniverseIndex) -> Option<String> {
+    PLACEHOLDER_NAMES.with(|names_cell| names_cell.borrow().get(&universe).cloned())
+}
@@ -22,6 +22,12 @@ extern crate error_chain;
 extern crate itertools;
 extern crate lalrpop_intern;
 extern crate petgraph;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[cfg(test)]
+extern crate serde_json;
+extern crate smallvec;
 extern crate stacker;
 
 #[macro_use]
@@ -36,10 +42,18 @@ crate mod zip;
 #[macro_use]
 pub mod ir;
 
+pub mod api;
 crate mod cast;
 crate mod coherence;
+crate mod invariants;
+crate mod progress;
 crate mod rules;
 pub mod errors;
+pub mod generator;
 pub mod solve;
 
+pub use crate::coherence::{FragileImplPair, HeaderUnification, SpecializationGraph,
+                           SpecializationViolation, SpecializationViolationKind, UnificationStep};
 pub use crate::chalk_engine::fallible;
+pub use crate::progress::{CheckPhase, CheckProgress, ProgressControl};
+pub use crate::rules::ObjectSafetyViolation;
@@ -0,0 +1,98 @@
+//! Reporting the progress of the checks run over a whole program
+//! when it is lowered, which can take a while for large programs, and
+//! aborting them part-way; see `LowerProgram::lower_with_progress`.
+
+use errors::*;
+use ir::ItemId;
+use std::time::{Duration, Instant};
+
+/// The checks run when a program is lowered, in the order they run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CheckPhase {
+    /// Checking that the impls of each trait do not overlap (or that
+    /// one specializes the other), one trait at a time.
+    Coherence,
+
+    /// Checking that each struct and impl is well-formed.
+    WellFormedness,
+}
+
+/// A report that some item is about to be checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckProgress {
+    pub phase: CheckPhase,
+
+    /// The number of items of this phase checked before `current`.
+    /// When checks are spread across threads, some of those may still
+    /// be in progress.
+    pub checked: usize,
+
+    /// The number of items to check in this phase.
+    pub total: usize,
+
+    /// The item about to be checked: a trait, whose impls are checked
+    /// against each other, during coherence checking, and a struct or
+    /// impl during well-formedness checking.
+    pub current: ItemId,
+
+    /// The time since the first check of any phase began.
+    pub elapsed: Duration,
+}
+
+/// Returned by progress callbacks to say whether checking should go on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgressControl {
+    Continue,
+
+    /// Stop checking; lowering fails with `ErrorKind::ChecksAborted`.
+    Abort,
+}
+
+/// Hands out progress reports to a callback, if there is one.
+crate struct ProgressReporter<'p> {
+    callback: Option<&'p mut dyn FnMut(&CheckProgress) -> ProgressControl>,
+    start: Instant,
+}
+
+impl<'p> ProgressReporter<'p> {
+    crate fn new(callback: &'p mut dyn FnMut(&CheckProgress) -> ProgressControl) -> Self {
+        ProgressReporter {
+            callback: Some(callback),
+            start: Instant::now(),
+        }
+    }
+
+    /// A reporter for checks that nobody is following.
+    crate fn silent() -> Self {
+        ProgressReporter {
+            callback: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// Reports that `current` is about to be checked, failing if the
+    /// callback asks for the checks to be aborted.
+    crate fn report(
+        &mut self,
+        phase: CheckPhase,
+        checked: usize,
+        total: usize,
+        current: ItemId,
+    ) -> Result<()> {
+        let callback = match &mut self.callback {
+            Some(callback) => callback,
+            None => return Ok(()),
+        };
+        let progress = CheckProgress {
+            phase,
+            checked,
+            total,
+            current,
+            elapsed: self.start.elapsed(),
+        };
+        match callback(&progress) {
+            ProgressControl::Continue => Ok(()),
+            ProgressControl::Abort => bail!(ErrorKind::ChecksAborted(phase, checked, total)),
+        }
+    }
+}
@@ -1,12 +1,35 @@
 use cast::{Cast, Caster};
 use fold::shift::Shift;
+use fold::Subst;
+use invariants;
 use ir::{self, ToParameter};
+use itertools::Itertools;
+use lalrpop_intern::intern;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 mod default;
 mod wf;
 
+pub use self::wf::ObjectSafetyViolation;
+crate use self::wf::{environment_clauses, implied_hypotheses};
+
 impl ir::Program {
-    pub fn environment(&self) -> ir::ProgramEnvironment {
+    /// Returns the clauses and data the solver needs about this
+    /// program. This is computed on first use and memoized, so it is
+    /// cheap to call repeatedly.
+    pub fn environment(&self) -> Arc<ir::ProgramEnvironment> {
+        self.environment_cache
+            .get_or_insert_with(|| self.compute_environment())
+    }
+
+    /// Discards the memoized result of `environment`. Must be called
+    /// whenever the program is modified.
+    crate fn invalidate_environment(&mut self) {
+        self.environment_cache.clear();
+    }
+
+    crate fn compute_environment(&self) -> ir::ProgramEnvironment {
         // Construct the set of *clauses*; these are sort of a compiled form
         // of the data above that always has the form:
         //
@@ -16,7 +39,7 @@ impl ir::Program {
         program_clauses.extend(self.custom_clauses.iter().cloned());
 
         program_clauses.extend(
-            self.struct_data
+            self.adt_data
                 .values()
                 .flat_map(|d| d.to_program_clauses()),
         );
@@ -25,6 +48,16 @@ impl ir::Program {
                 .values()
                 .flat_map(|d| d.to_program_clauses()),
         );
+        program_clauses.extend(
+            self.opaque_ty_data
+                .values()
+                .flat_map(|d| d.to_program_clauses(self)),
+        );
+        program_clauses.extend(
+            self.closure_data
+                .values()
+                .flat_map(|d| d.to_program_clauses(self)),
+        );
         program_clauses.extend(
             self.associated_ty_data
                 .values()
@@ -34,7 +67,10 @@ impl ir::Program {
 
         // Adds clause that defines the Derefs domain goal:
         // forall<T, U> { Derefs(T, U) :- ProjectionEq(<T as Deref>::Target = U>) }
-        if let Some(trait_id) = self.lang_items.get(&ir::LangItem::DerefTrait) {
+        let deref_trait_id = self.lang_items
+            .get(&ir::LangItem::DerefTrait)
+            .filter(|_| self.builtin_traits.deref);
+        if let Some(trait_id) = deref_trait_id {
             // Find `Deref::Target`.
             let associated_ty_id = self.associated_ty_data.values()
                                                         .find(|d| d.trait_id == *trait_id)
@@ -57,29 +93,97 @@ impl ir::Program {
             }.cast());
         }
 
-        for datum in self.impl_data.values() {
-            // If we encounter a negative impl, do not generate any rule. Negative impls
-            // are currently just there to deactivate default impls for auto traits.
-            if datum.binders.value.trait_ref.is_positive() {
-                program_clauses.push(datum.to_program_clause());
-                program_clauses.extend(
-                    datum
-                        .binders
-                        .value
-                        .associated_ty_values
-                        .iter()
-                        .flat_map(|atv| atv.to_program_clauses(self, datum)),
-                );
+        // The `Sized` trait is implemented by the solver itself, see
+        // `AdtDatum::to_sized_clause`.
+        let sized_trait_id = self.lang_items
+            .get(&ir::LangItem::SizedTrait)
+            .filter(|_| self.builtin_traits.sized);
+        if let Some(&sized_trait_id) = sized_trait_id {
+            program_clauses.extend(
+                self.adt_data
+                    .values()
+                    .map(|d| d.to_sized_clause(sized_trait_id)),
+            );
+        }
+
+        for (&impl_id, datum) in &self.impl_data {
+            // Negative impls generate a `NotImplemented` rule (and, being negative, carry
+            // no associated type values). They also deactivate default impls for auto
+            // traits, see `add_default_impls`.
+            program_clauses.push(datum.to_program_clause());
+            program_clauses.extend(
+                datum
+                    .binders
+                    .value
+                    .associated_ty_values
+                    .iter()
+                    .flat_map(|atv| atv.to_program_clauses(self, impl_id, datum)),
+            );
+            program_clauses.extend(
+                datum
+                    .binders
+                    .value
+                    .associated_const_values
+                    .iter()
+                    .map(|acv| acv.to_program_clause(datum)),
+            );
+        }
+
+        // Traits overridden to be implemented for all types:
+        // forall<T, ...> { Implemented(T: Trait<...>) }
+        program_clauses.extend(
+            self.trait_overrides
+                .iter()
+                .filter(|(_, overrides)| overrides.always_implemented)
+                .map(|(trait_id, _)| {
+                    self.trait_data[trait_id].binders.map_ref(|bound| {
+                        ir::ProgramClauseImplication {
+                            consequence: bound.trait_ref.clone().cast(),
+                            conditions: vec![],
+                        }
+                    }).cast()
+                }),
+        );
+
+        program_clauses.extend(
+            self.inherent_impl_data
+                .values()
+                .flat_map(|d| d.to_program_clauses()),
+        );
+
+        if invariants::enabled() {
+            for clause in &program_clauses {
+                invariants::assert_none("lowering", clause, invariants::closed_violations(clause));
             }
         }
 
         let trait_data = self.trait_data.clone();
         let associated_ty_data = self.associated_ty_data.clone();
 
+        let mut trait_impls = BTreeMap::new();
+        for (&impl_id, impl_datum) in &self.impl_data {
+            let trait_id = impl_datum.binders.value.trait_ref.trait_ref().trait_id;
+            trait_impls.entry(trait_id).or_insert_with(Vec::new).push(impl_id);
+        }
+
+        let fundamental_types = self.adt_data
+            .iter()
+            .filter(|(_, adt_datum)| adt_datum.binders.value.flags.fundamental)
+            .map(|(&adt_id, _)| adt_id)
+            .collect();
+
         ir::ProgramEnvironment {
             trait_data,
             associated_ty_data,
+            associated_const_data: self.associated_const_data.clone(),
             program_clauses,
+            item_attributes: self.item_attributes.clone(),
+            trait_overrides: self.trait_overrides.clone(),
+            trait_impls,
+            lang_items: self.lang_items.clone(),
+            builtin_traits: self.builtin_traits,
+            fundamental_types,
+            program_hash: Default::default(),
         }
     }
 }
@@ -90,10 +194,21 @@ impl ir::ImplDatum {
     /// ```notrust
     /// forall<T> { (Vec<T>: Clone) :- (T: Clone) }
     /// ```
+    ///
+    /// Given the negative impl `impl<T: Foo> !Send for Vec<T>`, generate:
+    ///
+    /// ```notrust
+    /// forall<T> { NotImplemented(Vec<T>: Send) :- (T: Foo) }
+    /// ```
     fn to_program_clause(&self) -> ir::ProgramClause {
         self.binders.map_ref(|bound| {
             ir::ProgramClauseImplication {
-                consequence: bound.trait_ref.trait_ref().clone().cast(),
+                consequence: match bound.trait_ref {
+                    ir::PolarizedTraitRef::Positive(ref trait_ref) => trait_ref.clone().cast(),
+                    ir::PolarizedTraitRef::Negative(ref trait_ref) => {
+                        ir::DomainGoal::NotImplemented(trait_ref.clone())
+                    }
+                },
                 conditions: bound
                     .where_clauses
                     .iter()
@@ -105,10 +220,73 @@ impl ir::ImplDatum {
     }
 }
 
+impl ir::InherentImplDatum {
+    /// Inherent associated types are only ever named through unselected
+    /// projections, and are always in scope. So, given:
+    ///
+    /// ```notrust
+    /// impl<T> Foo<T> where T: Clone {
+    ///     type Bar<'a> = Ref<'a, T>;
+    /// }
+    /// ```
+    ///
+    /// generate:
+    ///
+    /// ```notrust
+    /// forall<'a, T> {
+    ///     UnselectedNormalize(Foo<T>::Bar<'a> -> Ref<'a, T>) :- (T: Clone)
+    /// }
+    /// ```
+    fn to_program_clauses(&self) -> Vec<ir::ProgramClause> {
+        let bound = &self.binders.value;
+        bound.associated_ty_values
+            .iter()
+            .map(|value| {
+                // Begin with the innermost parameters (`'a`) and then add those from
+                // the impl (`T`).
+                let shift = value.value.len();
+                let binders = value.value
+                    .binders
+                    .iter()
+                    .cloned()
+                    .chain(self.binders.binders.iter().cloned())
+                    .collect();
+
+                let self_ty = ir::Ty::Apply(bound.self_ty.clone()).up_shift(shift);
+                let parameters = value.value
+                    .binders
+                    .iter()
+                    .zip(0..)
+                    .map(|p| p.to_parameter())
+                    .chain(Some(ir::ParameterKind::Ty(self_ty)))
+                    .collect();
+
+                ir::Binders {
+                    binders,
+                    value: ir::ProgramClauseImplication {
+                        consequence: ir::DomainGoal::UnselectedNormalize(ir::UnselectedNormalize {
+                            projection: ir::UnselectedProjectionTy {
+                                type_name: value.name,
+                                parameters,
+                            },
+                            ty: value.value.value.ty.clone(),
+                        }),
+                        conditions: bound.where_clauses
+                            .up_shift(shift)
+                            .into_iter()
+                            .casted()
+                            .collect(),
+                    },
+                }.cast()
+            })
+            .collect()
+    }
+}
+
 impl ir::DefaultImplDatum {
     /// For each accessible type `T` in a struct which needs a default implementation for the auto
-    /// trait `Foo` (accessible types are the struct fields types), we add a bound `T: Foo` (which
-    /// is then expanded with `WF(T: Foo)`). For example, given:
+    /// trait `Foo` (accessible types are the types of its fields, of every variant for an enum),
+    /// we add a bound `T: Foo` (which is then expanded with `WF(T: Foo)`). For example, given:
     ///
     /// ```notrust
     /// #[auto] trait Send { }
@@ -176,9 +354,25 @@ impl ir::AssociatedTyValue {
     ///         Normalize(<Vec<T> as Iterable>::IntoIter<'a> -> Iter<'a, T>)
     /// }
     /// ```
+    ///
+    /// A `default` value only applies to the types that no impl
+    /// overriding it applies to. So, if `impl<T> Iterable for Vec<T>`
+    /// is specialized by `impl Iterable for Vec<u32> where u32: Copy`,
+    /// which defines its own `IntoIter`, the normalization rule also
+    /// requires:
+    ///
+    /// ```notrust
+    /// IsFullyVisible(Vec<T>),                   // (3)
+    /// not { (Vec<T> = Vec<u32>), (u32: Copy) }  // (4)
+    /// ```
+    ///
+    /// Without (3), `forall<T> { ... }` would reveal the default for
+    /// `Vec<!1>`, as the placeholder `!1` never equals `u32`, though the
+    /// overriding impl applies to one of the types `T` stands for.
     fn to_program_clauses(
         &self,
         program: &ir::Program,
+        impl_id: ir::ItemId,
         impl_datum: &ir::ImplDatum,
     ) -> Vec<ir::ProgramClause> {
         // Begin with the innermost parameters (`'a`) and then add those from impl (`T`).
@@ -194,13 +388,36 @@ impl ir::AssociatedTyValue {
         //
         // 1. require that the trait is implemented
         // 2. any where-clauses from the `type` declaration in the impl
+        //
+        // and, for a `default` value, (3) and (4): that no overriding impl
+        // can apply.
         let impl_trait_ref = impl_datum
             .binders
             .value
             .trait_ref
             .trait_ref()
             .up_shift(self.value.len());
-        let conditions: Vec<ir::Goal> = vec![impl_trait_ref.clone().cast()];
+        let mut conditions: Vec<ir::Goal> = vec![impl_trait_ref.clone().cast()];
+        let overriding_impls = if self.default {
+            program.overriding_impls(impl_id, self.associated_ty_id)
+        } else {
+            vec![]
+        };
+        if !overriding_impls.is_empty() {
+            conditions.extend(
+                impl_trait_ref
+                    .parameters
+                    .iter()
+                    .filter_map(|parameter| parameter.as_ref().ty())
+                    .map(|ty| ir::DomainGoal::IsFullyVisible(ty.clone()).cast()),
+            );
+            conditions.extend(
+                overriding_impls
+                    .into_iter()
+                    .map(|id| impl_applies(&impl_trait_ref, &program.impl_data[&id]))
+                    .map(|goal| ir::Goal::Not(Box::new(goal))),
+            );
+        }
 
         // Bound parameters + `Self` type of the trait-ref
         let parameters: Vec<_> = {
@@ -262,7 +479,230 @@ impl ir::AssociatedTyValue {
     }
 }
 
-impl ir::StructDatum {
+/// The goal that `impl_datum` applies to `trait_ref`: its header unifies
+/// with `trait_ref`, and its where clauses hold.
+fn impl_applies(trait_ref: &ir::TraitRef, impl_datum: &ir::ImplDatum) -> ir::Goal {
+    let binders = &impl_datum.binders;
+    let trait_ref = trait_ref.up_shift(binders.len());
+    let header = trait_ref
+        .parameters
+        .into_iter()
+        .zip(binders.value.trait_ref.trait_ref().parameters.iter().cloned())
+        .map(|(a, b)| ir::Goal::Leaf(ir::LeafGoal::EqGoal(ir::EqGoal { a, b })));
+    let where_clauses = binders.value.where_clauses.iter().cloned().casted();
+    header
+        .chain(where_clauses)
+        .fold1(|goal, leaf| ir::Goal::And(Box::new(goal), Box::new(leaf)))
+        .expect("every trait takes at least one input type")
+        .quantify(ir::QuantifierKind::Exists, binders.binders.clone())
+}
+
+impl ir::AssociatedConstValue {
+    /// Given:
+    ///
+    /// ```notrust
+    /// impl<T> Foo for Vec<T> {
+    ///     const N: usize = 3;
+    /// }
+    /// ```
+    ///
+    /// generate:
+    ///
+    /// ```notrust
+    /// forall<T> {
+    ///     NormalizeConst(<Vec<T> as Foo>::N -> 3) :- (Vec<T>: Foo)
+    /// }
+    /// ```
+    fn to_program_clause(&self, impl_datum: &ir::ImplDatum) -> ir::ProgramClause {
+        impl_datum.binders.map_ref(|bound| {
+            let trait_ref = bound.trait_ref.trait_ref();
+            ir::ProgramClauseImplication {
+                consequence: ir::NormalizeConst {
+                    projection: ir::ConstProjection {
+                        associated_const_id: self.associated_const_id,
+                        parameters: trait_ref.parameters.clone(),
+                    },
+                    value: self.value.clone(),
+                }.cast(),
+                conditions: vec![trait_ref.clone().cast()],
+            }
+        }).cast()
+    }
+}
+
+impl ir::OpaqueTyDatum {
+    /// Given:
+    ///
+    /// ```notrust
+    /// type Foo<T> = impl Bar + Iterator<Item = T> = IntoIter<T>;
+    /// ```
+    ///
+    /// generate:
+    ///
+    /// ```notrust
+    /// forall<T> { Implemented(Foo<T>: Bar) }
+    /// forall<T> { Implemented(Foo<T>: Iterator) }
+    /// forall<T> { Normalize(<Foo<T> as Iterator>::Item -> T) }
+    /// forall<T> { OpaqueEq(Foo<T> = IntoIter<T>) :- Reveal }
+    /// forall<T> { WellFormed(Foo<T>) }
+    /// ```
+    ///
+    /// That is, the bounds hold whether or not the hidden type is
+    /// revealed, and nothing else is known about the opaque type unless
+    /// it is.
+    fn to_program_clauses(&self, program: &ir::Program) -> Vec<ir::ProgramClause> {
+        let opaque = ir::OpaqueTy {
+            opaque_ty_id: self.opaque_ty_id,
+            parameters: self.binders
+                .binders
+                .iter()
+                .zip(0..)
+                .map(|p| p.to_parameter())
+                .collect(),
+        };
+        let fact = |consequence: ir::DomainGoal| -> ir::ProgramClause {
+            ir::Binders {
+                binders: self.binders.binders.clone(),
+                value: ir::ProgramClauseImplication {
+                    consequence,
+                    conditions: vec![],
+                },
+            }.cast()
+        };
+
+        let mut clauses = vec![];
+        for bound in &self.binders.value.bounds {
+            match bound {
+                ir::WhereClauseAtom::Implemented(trait_ref) => {
+                    clauses.push(fact(trait_ref.clone().cast()));
+                }
+                ir::WhereClauseAtom::ProjectionEq(projection_eq) => {
+                    let projection = &projection_eq.projection;
+                    let (associated_ty_data, trait_params, _) =
+                        program.split_projection(projection);
+                    let trait_ref = ir::TraitRef {
+                        trait_id: associated_ty_data.trait_id,
+                        parameters: trait_params.to_owned(),
+                    };
+                    clauses.push(fact(trait_ref.cast()));
+                    clauses.push(fact(ir::Normalize {
+                        projection: projection.clone(),
+                        ty: projection_eq.ty.clone(),
+                    }.cast()));
+                }
+            }
+        }
+
+        clauses.push(ir::Binders {
+            binders: self.binders.binders.clone(),
+            value: ir::ProgramClauseImplication {
+                consequence: ir::OpaqueEq {
+                    opaque: opaque.clone(),
+                    ty: self.binders.value.hidden_ty.clone(),
+                }.cast(),
+                conditions: vec![ir::DomainGoal::Reveal(()).cast()],
+            },
+        }.cast());
+        clauses.push(fact(ir::DomainGoal::WellFormedTy(ir::Ty::Opaque(opaque))));
+        clauses
+    }
+}
+
+impl ir::ClosureDatum {
+    /// Given:
+    ///
+    /// ```notrust
+    /// closure mut c<'a, T>(T) -> Ref<'a, T>;
+    /// ```
+    ///
+    /// generate, where `FnOnce` and `FnMut` are the `#[lang_fn_once]`
+    /// and `#[lang_fn_mut]` traits:
+    ///
+    /// ```notrust
+    /// forall<'a, T> { WellFormed(c<'a, T>) }
+    /// forall<'a, T> { Implemented(c<'a, T>: FnOnce<(T,)>) }
+    /// forall<'a, T> { Implemented(c<'a, T>: FnMut<(T,)>) }
+    /// forall<'a, T> { Normalize(<c<'a, T> as FnOnce<(T,)>>::Output -> Ref<'a, T>) }
+    /// ```
+    ///
+    /// A closure declared without `mut` implements the `#[lang_fn]`
+    /// trait as well, and one declared with `once` only `FnOnce`.
+    fn to_program_clauses(&self, program: &ir::Program) -> Vec<ir::ProgramClause> {
+        let closure_ty = ir::Ty::Apply(ir::ApplicationTy {
+            name: ir::TypeName::ItemId(self.closure_id),
+            parameters: self.binders
+                .binders
+                .iter()
+                .zip(0..)
+                .map(|p| p.to_parameter())
+                .collect(),
+        });
+        let fact = |consequence: ir::DomainGoal| -> ir::ProgramClause {
+            ir::Binders {
+                binders: self.binders.binders.clone(),
+                value: ir::ProgramClauseImplication {
+                    consequence,
+                    conditions: vec![],
+                },
+            }.cast()
+        };
+
+        let mut clauses = vec![fact(ir::DomainGoal::WellFormedTy(closure_ty.clone()))];
+        if !program.builtin_traits.fn_traits {
+            return clauses;
+        }
+
+        let bound = &self.binders.value;
+        let parameters: Vec<ir::Parameter> = vec![
+            closure_ty.cast(),
+            ir::Ty::Apply(ir::ApplicationTy {
+                name: ir::TypeName::Tuple(bound.inputs.len()),
+                parameters: bound.inputs.iter().cloned().casted().collect(),
+            }).cast(),
+        ];
+
+        let fn_traits = match bound.kind {
+            ir::ClosureKind::Fn => &[
+                ir::LangItem::FnOnceTrait,
+                ir::LangItem::FnMutTrait,
+                ir::LangItem::FnTrait,
+            ][..],
+            ir::ClosureKind::FnMut => &[ir::LangItem::FnOnceTrait, ir::LangItem::FnMutTrait][..],
+            ir::ClosureKind::FnOnce => &[ir::LangItem::FnOnceTrait][..],
+        };
+        for lang_item in fn_traits {
+            if let Some(&trait_id) = program.lang_items.get(lang_item) {
+                clauses.push(fact(ir::TraitRef {
+                    trait_id,
+                    parameters: parameters.clone(),
+                }.cast()));
+            }
+        }
+
+        let output = program
+            .lang_items
+            .get(&ir::LangItem::FnOnceTrait)
+            .and_then(|&trait_id| {
+                program
+                    .associated_ty_data
+                    .values()
+                    .find(|d| d.trait_id == trait_id && d.name == intern("Output"))
+            });
+        if let Some(output) = output {
+            clauses.push(fact(ir::Normalize {
+                projection: ir::ProjectionTy {
+                    associated_ty_id: output.id,
+                    parameters,
+                },
+                ty: bound.output.clone(),
+            }.cast()));
+        }
+
+        clauses
+    }
+}
+
+impl ir::AdtDatum {
     fn to_program_clauses(&self) -> Vec<ir::ProgramClause> {
         // Given:
         //
@@ -272,6 +712,8 @@ impl ir::StructDatum {
         //
         //    forall<T> { WF(Foo<T>) :- (T: Eq). }
         //    forall<T> { FromEnv(T: Eq) :- FromEnv(Foo<T>). }
+        //
+        // as well as the outlives rule, see `to_outlives_clause`.
 
         let wf = self.binders.map_ref(|bound_datum| {
             ir::ProgramClauseImplication {
@@ -315,8 +757,81 @@ impl ir::StructDatum {
             }.cast());
         }
 
+        clauses.push(self.to_outlives_clause());
         clauses
     }
+
+    /// A struct outlives a lifetime if all of its type and lifetime
+    /// parameters do; consts outlive everything. So, given `struct
+    /// Foo<'a, T> { }`, generate:
+    ///
+    /// ```notrust
+    /// forall<'b, 'a, T> { Outlives(Foo<'a, T>: 'b) :- Outlives('a: 'b), Outlives(T: 'b) }
+    /// ```
+    fn to_outlives_clause(&self) -> ir::ProgramClause {
+        let self_ty = &self.binders.value.self_ty;
+        let outlives = |a: ir::Parameter| {
+            ir::DomainGoal::Outlives(ir::Outlives {
+                a: a.up_shift(1),
+                b: ir::Lifetime::Var(0),
+            })
+        };
+
+        ir::Binders {
+            binders: Some(ir::ParameterKind::Lifetime(()))
+                .into_iter()
+                .chain(self.binders.binders.iter().cloned())
+                .collect(),
+            value: ir::ProgramClauseImplication {
+                consequence: outlives(ir::ParameterKind::Ty(ir::Ty::Apply(self_ty.clone()))),
+                conditions: self_ty.parameters
+                    .iter()
+                    .filter(|parameter| parameter.as_ref().constant().is_none())
+                    .cloned()
+                    .map(outlives)
+                    .casted()
+                    .collect(),
+            },
+        }.cast()
+    }
+
+    /// A struct is `Sized` if its last field is, since all the other
+    /// fields must be sized anyway. So, given:
+    ///
+    /// ```notrust
+    /// struct Foo<T> { a: u32, b: T }
+    /// ```
+    ///
+    /// generate:
+    ///
+    /// ```notrust
+    /// forall<T> { (Foo<T>: Sized) :- (T: Sized) }
+    /// ```
+    ///
+    /// A struct without fields is always `Sized`. The fields of enums
+    /// and unions are not allowed to be unsized at all, so these are
+    /// `Sized` if all of their fields are.
+    fn to_sized_clause(&self, sized_trait_id: ir::ItemId) -> ir::ProgramClause {
+        let sized = |ty: ir::Ty| ir::TraitRef {
+            trait_id: sized_trait_id,
+            parameters: vec![ir::ParameterKind::Ty(ty)],
+        };
+
+        self.binders.map_ref(|bound| {
+            let mut fields = bound.fields();
+            if bound.kind == ir::AdtKind::Struct {
+                fields = fields.pop().into_iter().collect();
+            }
+            ir::ProgramClauseImplication {
+                consequence: sized(bound.self_ty.clone().cast()).cast(),
+                conditions: fields
+                    .into_iter()
+                    .map(&sized)
+                    .casted()
+                    .collect(),
+            }
+        }).cast()
+    }
 }
 
 impl ir::TraitDatum {
@@ -366,7 +881,7 @@ impl ir::TraitDatum {
                       .map(|wc| wc.map(|bound| bound.into_from_env_goal()))
         {
             // We move the binders of the where-clause to the left for the reverse rules,
-            // cf `StructDatum::to_program_clauses`.
+            // cf `AdtDatum::to_program_clauses`.
             let shift = wc.binders.len();
             clauses.push(ir::Binders {
                 binders: wc.binders.into_iter().chain(self.binders.binders.clone()).collect(),
@@ -379,11 +894,30 @@ impl ir::TraitDatum {
 
         clauses.push(self.binders.map_ref(|_| {
             ir::ProgramClauseImplication {
-                consequence: ir::DomainGoal::Holds(trait_ref_impl),
+                consequence: ir::DomainGoal::Holds(trait_ref_impl.clone()),
                 conditions: vec![condition.cast()],
             }
         }).cast());
 
+        // A trait alias is implemented exactly when its bounds hold, so
+        // given `trait Alias<T> = Foo + Bar<T>;` we also generate:
+        //
+        //    forall<Self, T> { (Self: Alias<T>) :- (Self: Foo), (Self: Bar<T>) }
+        //
+        // The other direction is given by the reverse rules above.
+        if self.binders.value.flags.alias {
+            clauses.push(self.binders.map_ref(|bound| {
+                ir::ProgramClauseImplication {
+                    consequence: ir::DomainGoal::Holds(trait_ref_impl),
+                    conditions: bound.where_clauses
+                        .iter()
+                        .cloned()
+                        .casted()
+                        .collect(),
+                }
+            }).cast());
+        }
+
         clauses
     }
 }
@@ -488,6 +1022,27 @@ impl ir::AssociatedTyDatum {
             },
         }.cast());
 
+        // Bounds on the associated type are implied by the trait ref, much like the
+        // where clauses of a trait are implied by the trait ref itself. So, given
+        // `type Assoc: Bar`, we have:
+        //
+        //    forall<T> {
+        //        FromEnv(<T as Foo>::Assoc: Bar) :- FromEnv(T: Foo)
+        //    }
+        for bound in &self.bounds {
+            clauses.push(ir::Binders {
+                binders: binders.clone(),
+                value: ir::ProgramClauseImplication {
+                    consequence: ir::DomainGoal::FromEnv(bound.clone()),
+                    conditions: vec![
+                        ir::DomainGoal::FromEnv(
+                            ir::WhereClauseAtom::Implemented(trait_ref.clone())
+                        ).cast()
+                    ],
+                },
+            }.cast());
+        }
+
         // add new type parameter U
         let mut binders = binders;
         binders.push(ir::ParameterKind::Ty(()));
@@ -563,3 +1118,909 @@ impl ir::AssociatedTyDatum {
         clauses
     }
 }
+
+/// The clauses for `goal` about the built-in types, which are generated
+/// on demand rather than up front; see the functions below.
+crate fn builtin_type_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    let mut clauses = dyn_clauses(env, goal);
+    clauses.extend(tuple_clauses(env, goal));
+    clauses.extend(fn_ptr_clauses(env, goal));
+    clauses.extend(slice_clauses(env, goal));
+    clauses.extend(ref_clauses(env, goal));
+    clauses.extend(scalar_clauses(env, goal));
+    clauses.extend(never_clauses(env, goal));
+    clauses.extend(unsize_clauses(env, goal));
+    clauses.extend(unknown_self_ty_clauses(env, goal));
+    clauses.extend(fully_visible_clauses(goal));
+    clauses
+}
+
+/// The clause for a goal `?0: Trait` whose self type is still an
+/// inference variable, if any of the built-in types implements `Trait`.
+/// The clauses above are only generated once the self type is known,
+/// so without this one, a lone impl `impl Copy for Foo` would make
+/// `exists<T> { T: Copy }` hold only for `T = Foo`, although `()`,
+/// `i32`, `&Foo` and so on implement `Copy` as well:
+///
+/// ```notrust
+/// (?0: Copy) :- CannotProve.
+/// ```
+fn unknown_self_ty_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    let trait_ref = match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => trait_ref,
+        _ => return vec![],
+    };
+    match trait_ref.parameters[0].as_ref().ty() {
+        Some(ir::Ty::Var(_)) => {}
+        _ => return vec![],
+    }
+
+    let trait_id = trait_ref.trait_id;
+    let builtin_traits = &env.builtin_traits;
+    let is_lang_item = |item: ir::LangItem| env.is_lang_item(item, trait_id);
+    let has_builtin_impls = builtin_traits.never_fallback
+        || (builtin_traits.auto_traits && env.trait_data[&trait_id].binders.value.flags.auto)
+        || (builtin_traits.copy_clone
+            && (is_lang_item(ir::LangItem::CopyTrait) || is_lang_item(ir::LangItem::CloneTrait)))
+        || (builtin_traits.sized && is_lang_item(ir::LangItem::SizedTrait))
+        || (builtin_traits.fn_traits
+            && (is_lang_item(ir::LangItem::FnOnceTrait)
+                || is_lang_item(ir::LangItem::FnMutTrait)
+                || is_lang_item(ir::LangItem::FnTrait)))
+        || (builtin_traits.deref && is_lang_item(ir::LangItem::DerefTrait))
+        || (builtin_traits.unsize && is_lang_item(ir::LangItem::UnsizeTrait));
+    if !has_builtin_impls {
+        return vec![];
+    }
+
+    let clause = ir::ProgramClauseImplication {
+        consequence: goal.clone(),
+        conditions: vec![ir::Goal::CannotProve(())],
+    };
+    vec![clause.cast()]
+}
+
+/// The clauses for `IsFullyVisible`, generated on demand for every type
+/// but those that are never fully visible. Given `Vec<A>`, `dyn Foo<B>`
+/// and an inference variable `?0`, they are:
+///
+/// ```notrust
+/// IsFullyVisible(Vec<A>) :- IsFullyVisible(A).
+/// IsFullyVisible(dyn Foo<B>) :- IsFullyVisible(B).
+/// IsFullyVisible(?0) :- CannotProve.
+/// ```
+///
+/// No clause proves it for placeholders, projections, opaque types or
+/// higher-ranked types.
+fn fully_visible_clauses(goal: &ir::DomainGoal) -> Vec<ir::ProgramClause> {
+    let ty = match goal {
+        ir::DomainGoal::IsFullyVisible(ty) => ty,
+        _ => return vec![],
+    };
+    let conditions: Vec<ir::Goal> = match ty {
+        ir::Ty::Var(_) => vec![ir::Goal::CannotProve(())],
+        ir::Ty::Apply(apply) => match apply.name {
+            ir::TypeName::ForAll(_) => return vec![],
+            _ => apply
+                .parameters
+                .iter()
+                .filter_map(|parameter| parameter.as_ref().ty())
+                .map(|ty| ir::DomainGoal::IsFullyVisible(ty.clone()).cast())
+                .collect(),
+        },
+        ir::Ty::Dyn(dyn_ty) => dyn_ty
+            .bounds
+            .iter()
+            .flat_map(|bound| &bound.parameters)
+            .filter_map(|parameter| parameter.as_ref().ty())
+            .map(|ty| ir::DomainGoal::IsFullyVisible(ty.clone()).cast())
+            .collect(),
+        ir::Ty::Projection(_)
+        | ir::Ty::UnselectedProjection(_)
+        | ir::Ty::ForAll(_)
+        | ir::Ty::Opaque(_) => return vec![],
+    };
+    let clause = ir::ProgramClauseImplication {
+        consequence: goal.clone(),
+        conditions,
+    };
+    vec![clause.cast()]
+}
+
+/// The clauses for trait object types, which, unlike those for the
+/// other types, cannot be generated up front: there is one trait object
+/// type for every combination of traits. So they are generated on
+/// demand, for the goal `goal`. Given `dyn Foo<T> + Send`, where `trait
+/// Foo<T> where Self: Bar<T>`, they are:
+///
+/// ```notrust
+/// (dyn Foo<T> + Send: Foo<T>).
+/// (dyn Foo<T> + Send: Bar<T>).
+/// (dyn Foo<T> + Send: Send).
+/// WellFormed(dyn Foo<T> + Send) :- WellFormed(T).
+/// ```
+///
+/// Object safety is not checked: a trait object type implements each of
+/// its traits and their supertraits, and -- auto traits included -- no
+/// other; see `dyn_trait_refs`.
+fn dyn_clauses(env: &ir::ProgramEnvironment, goal: &ir::DomainGoal) -> Vec<ir::ProgramClause> {
+    match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => {
+            let dyn_ty = match &trait_ref.parameters[0] {
+                ir::ParameterKind::Ty(ir::Ty::Dyn(dyn_ty)) => dyn_ty,
+                _ => return vec![],
+            };
+            dyn_trait_refs(env, dyn_ty)
+                .into_iter()
+                .filter(|dyn_trait_ref| dyn_trait_ref.trait_id == trait_ref.trait_id)
+                .map(|dyn_trait_ref| {
+                    let fact: ir::DomainGoal = dyn_trait_ref.cast();
+                    fact.cast()
+                })
+                .collect()
+        }
+
+        ir::DomainGoal::WellFormedTy(ir::Ty::Dyn(dyn_ty)) => {
+            let clause = ir::ProgramClauseImplication {
+                consequence: ir::DomainGoal::WellFormedTy(ir::Ty::Dyn(dyn_ty.clone())),
+                conditions: dyn_ty
+                    .bounds
+                    .iter()
+                    .flat_map(|bound| &bound.parameters)
+                    .filter_map(|parameter| parameter.as_ref().ty())
+                    .map(|ty| ir::DomainGoal::WellFormedTy(ty.clone()).cast())
+                    .collect(),
+            };
+            vec![clause.cast()]
+        }
+
+        _ => vec![],
+    }
+}
+
+/// The bound lists, with the traits of `target`, that `source` may be
+/// upcast to: each bound of `target` is replaced with one of the traits
+/// `source` implements as a trait object, in every possible way.
+fn upcast_targets(
+    env: &ir::ProgramEnvironment,
+    source: &ir::DynTy,
+    target: &ir::DynTy,
+) -> Vec<Vec<ir::DynBound>> {
+    let source_trait_refs = dyn_trait_refs(env, source);
+    let mut targets = vec![vec![]];
+    for bound in &target.bounds {
+        let candidates: Vec<_> = source_trait_refs
+            .iter()
+            .filter(|trait_ref| trait_ref.trait_id == bound.trait_id)
+            .map(|trait_ref| ir::DynBound {
+                trait_id: trait_ref.trait_id,
+                parameters: trait_ref.parameters[1..].to_vec(),
+            })
+            .collect();
+        targets = targets
+            .into_iter()
+            .flat_map(|bounds: Vec<ir::DynBound>| {
+                candidates.iter().map(move |candidate| {
+                    let mut bounds = bounds.clone();
+                    bounds.push(candidate.clone());
+                    bounds
+                })
+            })
+            .collect();
+    }
+    targets
+}
+
+/// The traits that the trait object type `dyn_ty` implements: those of
+/// its bounds and, transitively, their supertraits -- the traits that
+/// their where clauses require `Self` to implement. Higher-ranked where
+/// clauses are not elaborated.
+fn dyn_trait_refs(env: &ir::ProgramEnvironment, dyn_ty: &ir::DynTy) -> Vec<ir::TraitRef> {
+    let self_ty = ir::Ty::Dyn(dyn_ty.clone());
+    let mut trait_refs = vec![];
+    let mut stack: Vec<_> = dyn_ty
+        .bounds
+        .iter()
+        .rev()
+        .map(|bound| bound.trait_ref(self_ty.clone()))
+        .collect();
+    while let Some(trait_ref) = stack.pop() {
+        if trait_refs.contains(&trait_ref) {
+            continue;
+        }
+        for wc in &env.trait_data[&trait_ref.trait_id].binders.value.where_clauses {
+            if !wc.binders.is_empty() {
+                continue;
+            }
+            if let ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(super_ref)) = &wc.value {
+                if super_ref.parameters[0] == ir::ParameterKind::Ty(ir::Ty::Var(0)) {
+                    stack.push(Subst::apply(&trait_ref.parameters, super_ref));
+                }
+            }
+        }
+        trait_refs.push(trait_ref);
+    }
+    trait_refs
+}
+
+/// The clauses for tuple types which, like those for trait object
+/// types, are generated on demand: there is one tuple type for every
+/// number of elements. A tuple implements the auto traits, and the
+/// `#[lang_copy]` and `#[lang_clone]` traits, if its elements do, and
+/// the `#[lang_sized]` trait if its last element does, the others
+/// having to be sized anyway. So for `(A, B)`:
+///
+/// ```notrust
+/// (A, B): Send :- A: Send, B: Send.
+/// (A, B): Copy :- A: Copy, B: Copy.
+/// (A, B): Clone :- A: Clone, B: Clone.
+/// (A, B): Sized :- B: Sized.
+/// WellFormed((A, B)) :- WellFormed(A), WellFormed(B).
+/// ```
+///
+/// In particular, `()` implements all of these traits.
+fn tuple_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    fn as_tuple(ty: &ir::Ty) -> Option<&ir::ApplicationTy> {
+        match ty {
+            ir::Ty::Apply(apply) => match apply.name {
+                ir::TypeName::Tuple(_) => Some(apply),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => {
+            let tuple = match trait_ref.parameters[0].as_ref().ty().and_then(as_tuple) {
+                Some(tuple) => tuple,
+                None => return vec![],
+            };
+            let trait_id = trait_ref.trait_id;
+            let elements = tuple.parameters.iter().filter_map(|p| p.as_ref().ty());
+
+            let is_structural = (env.builtin_traits.auto_traits
+                && env.trait_data[&trait_id].binders.value.flags.auto)
+                || (env.builtin_traits.copy_clone
+                    && (env.is_lang_item(ir::LangItem::CopyTrait, trait_id)
+                        || env.is_lang_item(ir::LangItem::CloneTrait, trait_id)));
+            let constituents: Vec<&ir::Ty> = if is_structural {
+                elements.collect()
+            } else if env.builtin_traits.sized
+                && env.is_lang_item(ir::LangItem::SizedTrait, trait_id)
+            {
+                elements.last().into_iter().collect()
+            } else {
+                return vec![];
+            };
+
+            let implemented = |ty: ir::Ty| ir::TraitRef {
+                trait_id,
+                parameters: Some(ty.cast())
+                    .into_iter()
+                    .chain(trait_ref.parameters[1..].iter().cloned())
+                    .collect(),
+            };
+            let clause = ir::ProgramClauseImplication {
+                consequence: implemented(ir::Ty::Apply(tuple.clone())).cast(),
+                conditions: constituents
+                    .into_iter()
+                    .map(|ty| implemented(ty.clone()).cast())
+                    .collect(),
+            };
+            vec![clause.cast()]
+        }
+
+        ir::DomainGoal::WellFormedTy(ty) => {
+            let tuple = match as_tuple(ty) {
+                Some(tuple) => tuple,
+                None => return vec![],
+            };
+            let clause = ir::ProgramClauseImplication {
+                consequence: ir::DomainGoal::WellFormedTy(ty.clone()),
+                conditions: tuple
+                    .parameters
+                    .iter()
+                    .filter_map(|parameter| parameter.as_ref().ty())
+                    .map(|ty| ir::DomainGoal::WellFormedTy(ty.clone()).cast())
+                    .collect(),
+            };
+            vec![clause.cast()]
+        }
+
+        _ => vec![],
+    }
+}
+
+/// The clauses for function pointer types, generated on demand like
+/// those for tuples. Given `fn(A, B) -> C`, they are:
+///
+/// ```notrust
+/// (fn(A, B) -> C: FnOnce<(A, B)>).
+/// (fn(A, B) -> C: FnMut<(A, B)>).
+/// (fn(A, B) -> C: Fn<(A, B)>).
+/// Normalize(<fn(A, B) -> C as FnOnce<(A, B)>>::Output -> C).
+/// (fn(A, B) -> C: Copy).
+/// (fn(A, B) -> C: Clone).
+/// (fn(A, B) -> C: Send), and so on for every auto trait.
+/// WellFormed(fn(A, B) -> C) :- WellFormed(A), WellFormed(B), WellFormed(C).
+/// ```
+///
+/// where `FnOnce` and the others are the `#[lang_fn_once]`, ...,
+/// `#[lang_clone]` traits, if the program has them.
+fn fn_ptr_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    fn as_fn_ptr(parameter: &ir::Parameter) -> Option<(usize, &ir::ApplicationTy)> {
+        match parameter {
+            ir::ParameterKind::Ty(ir::Ty::Apply(apply)) => match apply.name {
+                ir::TypeName::FnPtr(arity) => Some((arity, apply)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    let arguments = |arity: usize, fn_ptr: &ir::ApplicationTy| -> ir::Parameter {
+        ir::Ty::Apply(ir::ApplicationTy {
+            name: ir::TypeName::Tuple(arity),
+            parameters: fn_ptr.parameters[..arity].to_vec(),
+        }).cast()
+    };
+
+    match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => {
+            let (arity, fn_ptr) = match as_fn_ptr(&trait_ref.parameters[0]) {
+                Some(fn_ptr) => fn_ptr,
+                None => return vec![],
+            };
+            let trait_id = trait_ref.trait_id;
+            let self_ty: ir::Parameter = ir::Ty::Apply(fn_ptr.clone()).cast();
+
+            let is_fn_trait = env.builtin_traits.fn_traits && (
+                env.is_lang_item(ir::LangItem::FnOnceTrait, trait_id)
+                    || env.is_lang_item(ir::LangItem::FnMutTrait, trait_id)
+                    || env.is_lang_item(ir::LangItem::FnTrait, trait_id)
+            );
+            let is_copy_clone = env.builtin_traits.copy_clone && (
+                env.is_lang_item(ir::LangItem::CopyTrait, trait_id)
+                    || env.is_lang_item(ir::LangItem::CloneTrait, trait_id)
+            );
+            let is_auto = env.builtin_traits.auto_traits
+                && env.trait_data[&trait_id].binders.value.flags.auto;
+
+            let parameters = if is_fn_trait {
+                vec![self_ty, arguments(arity, fn_ptr)]
+            } else if is_copy_clone || is_auto {
+                vec![self_ty]
+            } else {
+                return vec![];
+            };
+            let fact: ir::DomainGoal = ir::TraitRef {
+                trait_id,
+                parameters,
+            }.cast();
+            vec![fact.cast()]
+        }
+
+        ir::DomainGoal::Normalize(normalize) => {
+            let (arity, fn_ptr) = match as_fn_ptr(&normalize.projection.parameters[0]) {
+                Some(fn_ptr) => fn_ptr,
+                None => return vec![],
+            };
+            let is_output = env.builtin_traits.fn_traits && {
+                let datum = &env.associated_ty_data[&normalize.projection.associated_ty_id];
+                env.is_lang_item(ir::LangItem::FnOnceTrait, datum.trait_id)
+                    && datum.name == intern("Output")
+            };
+            if !is_output {
+                return vec![];
+            }
+            let fact: ir::DomainGoal = ir::Normalize {
+                projection: ir::ProjectionTy {
+                    associated_ty_id: normalize.projection.associated_ty_id,
+                    parameters: vec![
+                        ir::Ty::Apply(fn_ptr.clone()).cast(),
+                        arguments(arity, fn_ptr),
+                    ],
+                },
+                ty: fn_ptr.parameters[arity].assert_ty_ref().clone(),
+            }.cast();
+            vec![fact.cast()]
+        }
+
+        ir::DomainGoal::WellFormedTy(ir::Ty::Apply(fn_ptr)) => match fn_ptr.name {
+            ir::TypeName::FnPtr(_) => {
+                let clause = ir::ProgramClauseImplication {
+                    consequence: ir::DomainGoal::WellFormedTy(ir::Ty::Apply(fn_ptr.clone())),
+                    conditions: fn_ptr
+                        .parameters
+                        .iter()
+                        .filter_map(|parameter| parameter.as_ref().ty())
+                        .map(|ty| ir::DomainGoal::WellFormedTy(ty.clone()).cast())
+                        .collect(),
+                };
+                vec![clause.cast()]
+            }
+            _ => vec![],
+        },
+
+        _ => vec![],
+    }
+}
+
+/// The clauses for slice, array and string slice types, generated on
+/// demand like those for tuples. Arrays are sized, but slices and `str`
+/// are not, and arrays and slices implement the auto traits if their
+/// elements do; arrays also implement `#[lang_copy]` and `#[lang_clone]`
+/// if their elements do. So for `[T]`, `[T; 3]` and `str`:
+///
+/// ```notrust
+/// [T]: Send :- T: Send.
+/// [T; 3]: Send :- T: Send.
+/// [T; 3]: Copy :- T: Copy.
+/// [T; 3]: Clone :- T: Clone.
+/// [T; 3]: Sized.
+/// str: Send.
+/// WellFormed([T]) :- WellFormed(T).
+/// WellFormed([T; 3]) :- WellFormed(T).
+/// WellFormed(str).
+/// ```
+fn slice_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    fn as_slice_like(ty: &ir::Ty) -> Option<&ir::ApplicationTy> {
+        match ty {
+            ir::Ty::Apply(apply) => match apply.name {
+                ir::TypeName::Slice | ir::TypeName::Array(_) | ir::TypeName::Str => Some(apply),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => {
+            let slice = match trait_ref.parameters[0].as_ref().ty().and_then(as_slice_like) {
+                Some(slice) => slice,
+                None => return vec![],
+            };
+            let trait_id = trait_ref.trait_id;
+            let is_array = match slice.name {
+                ir::TypeName::Array(_) => true,
+                _ => false,
+            };
+
+            let is_structural = (env.builtin_traits.auto_traits
+                && env.trait_data[&trait_id].binders.value.flags.auto)
+                || (is_array && env.builtin_traits.copy_clone
+                    && (env.is_lang_item(ir::LangItem::CopyTrait, trait_id)
+                        || env.is_lang_item(ir::LangItem::CloneTrait, trait_id)));
+            let constituents: Vec<&ir::Ty> = if is_structural {
+                slice.parameters.iter().filter_map(|p| p.as_ref().ty()).collect()
+            } else if is_array && env.builtin_traits.sized
+                && env.is_lang_item(ir::LangItem::SizedTrait, trait_id)
+            {
+                vec![]
+            } else {
+                return vec![];
+            };
+
+            let implemented = |ty: ir::Ty| ir::TraitRef {
+                trait_id,
+                parameters: Some(ty.cast())
+                    .into_iter()
+                    .chain(trait_ref.parameters[1..].iter().cloned())
+                    .collect(),
+            };
+            let clause = ir::ProgramClauseImplication {
+                consequence: implemented(ir::Ty::Apply(slice.clone())).cast(),
+                conditions: constituents
+                    .into_iter()
+                    .map(|ty| implemented(ty.clone()).cast())
+                    .collect(),
+            };
+            vec![clause.cast()]
+        }
+
+        ir::DomainGoal::WellFormedTy(ty) => {
+            let slice = match as_slice_like(ty) {
+                Some(slice) => slice,
+                None => return vec![],
+            };
+            let clause = ir::ProgramClauseImplication {
+                consequence: ir::DomainGoal::WellFormedTy(ty.clone()),
+                conditions: slice
+                    .parameters
+                    .iter()
+                    .filter_map(|parameter| parameter.as_ref().ty())
+                    .map(|ty| ir::DomainGoal::WellFormedTy(ty.clone()).cast())
+                    .collect(),
+            };
+            vec![clause.cast()]
+        }
+
+        _ => vec![],
+    }
+}
+
+/// The clauses for reference and raw pointer types, generated on demand
+/// like those for tuples. For `&'a T`, `&'a mut T` and `*const T`, they
+/// are:
+///
+/// ```notrust
+/// (&'a T: Send) :- (T: Send), and so on for every auto trait.
+/// (&'a T: Copy).
+/// (&'a T: Clone).
+/// (&'a T: Sized).
+/// (&'a T: Deref).
+/// Normalize(<&'a T as Deref>::Target -> T).
+/// Derefs(*const T, T).
+/// Outlives(&'a T: 'b) :- Outlives('a: 'b), Outlives(T: 'b).
+/// WellFormed(&'a T) :- WellFormed(T), Outlives(T: 'a).
+/// ```
+///
+/// and likewise for `&'a mut T` and `*mut T`, except that `&'a mut T`
+/// is neither `Copy` nor `Clone`. Raw pointers are not `Deref`, but
+/// they can still be dereferenced, so they satisfy `Derefs` directly.
+/// All of these types are `Sized`, whatever they point to.
+fn ref_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    /// The pointer type, its mutability, its lifetime (for references)
+    /// and its pointee.
+    fn as_pointer(
+        ty: &ir::Ty,
+    ) -> Option<(&ir::ApplicationTy, ir::Mutability, Option<ir::Lifetime>, &ir::Ty)> {
+        match ty {
+            ir::Ty::Apply(apply) => match apply.name {
+                ir::TypeName::Ref(mutability) => Some((
+                    apply,
+                    mutability,
+                    Some(*apply.parameters[0].assert_lifetime_ref()),
+                    apply.parameters[1].assert_ty_ref(),
+                )),
+                ir::TypeName::RawPtr(mutability) => {
+                    Some((apply, mutability, None, apply.parameters[0].assert_ty_ref()))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    let is_ref = |pointer: &ir::ApplicationTy| match pointer.name {
+        ir::TypeName::Ref(_) => true,
+        _ => false,
+    };
+
+    match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => {
+            let self_ty = trait_ref.parameters[0].as_ref().ty();
+            let (pointer, mutability, _, pointee) = match self_ty.and_then(as_pointer) {
+                Some(pointer) => pointer,
+                None => return vec![],
+            };
+            let trait_id = trait_ref.trait_id;
+
+            let is_auto = env.builtin_traits.auto_traits
+                && env.trait_data[&trait_id].binders.value.flags.auto;
+            let is_copy_clone = env.builtin_traits.copy_clone
+                && (env.is_lang_item(ir::LangItem::CopyTrait, trait_id)
+                    || env.is_lang_item(ir::LangItem::CloneTrait, trait_id))
+                && !(is_ref(pointer) && mutability == ir::Mutability::Mut);
+            let is_sized = env.builtin_traits.sized
+                && env.is_lang_item(ir::LangItem::SizedTrait, trait_id);
+            let is_deref = env.builtin_traits.deref && is_ref(pointer)
+                && env.is_lang_item(ir::LangItem::DerefTrait, trait_id);
+
+            let conditions: Vec<ir::Goal> = if is_auto {
+                vec![ir::TraitRef {
+                    trait_id,
+                    parameters: vec![pointee.clone().cast()],
+                }.cast()]
+            } else if is_copy_clone || is_sized || is_deref {
+                vec![]
+            } else {
+                return vec![];
+            };
+            let clause = ir::ProgramClauseImplication {
+                consequence: ir::TraitRef {
+                    trait_id,
+                    parameters: vec![ir::Ty::Apply(pointer.clone()).cast()],
+                }.cast(),
+                conditions,
+            };
+            vec![clause.cast()]
+        }
+
+        ir::DomainGoal::Normalize(normalize) => {
+            let self_ty = normalize.projection.parameters[0].as_ref().ty();
+            let (pointer, _, _, pointee) = match self_ty.and_then(as_pointer) {
+                Some(pointer) => pointer,
+                None => return vec![],
+            };
+            let is_target = env.builtin_traits.deref && is_ref(pointer) && {
+                let datum = &env.associated_ty_data[&normalize.projection.associated_ty_id];
+                env.is_lang_item(ir::LangItem::DerefTrait, datum.trait_id)
+                    && datum.name == intern("Target")
+            };
+            if !is_target {
+                return vec![];
+            }
+            let fact: ir::DomainGoal = ir::Normalize {
+                projection: ir::ProjectionTy {
+                    associated_ty_id: normalize.projection.associated_ty_id,
+                    parameters: vec![ir::Ty::Apply(pointer.clone()).cast()],
+                },
+                ty: pointee.clone(),
+            }.cast();
+            vec![fact.cast()]
+        }
+
+        ir::DomainGoal::Derefs(derefs) => match as_pointer(&derefs.source) {
+            Some((pointer, _, _, pointee)) if env.builtin_traits.deref && !is_ref(pointer) => {
+                let fact = ir::DomainGoal::Derefs(ir::Derefs {
+                    source: derefs.source.clone(),
+                    target: pointee.clone(),
+                });
+                vec![fact.cast()]
+            }
+            _ => vec![],
+        },
+
+        ir::DomainGoal::Outlives(outlives) => {
+            let self_ty = outlives.a.as_ref().ty();
+            let (_, _, lifetime, pointee) = match self_ty.and_then(as_pointer) {
+                Some(pointer) => pointer,
+                None => return vec![],
+            };
+            let outlives_b = |a: ir::Parameter| -> ir::Goal {
+                ir::DomainGoal::Outlives(ir::Outlives { a, b: outlives.b }).cast()
+            };
+            let clause = ir::ProgramClauseImplication {
+                consequence: ir::DomainGoal::Outlives(outlives.clone()),
+                conditions: lifetime
+                    .map(|lifetime| outlives_b(ir::ParameterKind::Lifetime(lifetime)))
+                    .into_iter()
+                    .chain(Some(outlives_b(pointee.clone().cast())))
+                    .collect(),
+            };
+            vec![clause.cast()]
+        }
+
+        ir::DomainGoal::WellFormedTy(ty) => {
+            let (_, _, lifetime, pointee) = match as_pointer(ty) {
+                Some(pointer) => pointer,
+                None => return vec![],
+            };
+            let referent_outlives: Option<ir::Goal> = lifetime.map(|lifetime| {
+                ir::DomainGoal::Outlives(ir::Outlives {
+                    a: pointee.clone().cast(),
+                    b: lifetime,
+                }).cast()
+            });
+            let clause = ir::ProgramClauseImplication {
+                consequence: ir::DomainGoal::WellFormedTy(ty.clone()),
+                conditions: Some(ir::DomainGoal::WellFormedTy(pointee.clone()).cast())
+                    .into_iter()
+                    .chain(referent_outlives)
+                    .collect(),
+            };
+            vec![clause.cast()]
+        }
+
+        _ => vec![],
+    }
+}
+
+/// The clauses for scalar types like `i32`, generated on demand like
+/// those for tuples. Scalars have no parameters, so these are all facts:
+///
+/// ```notrust
+/// (i32: Copy).
+/// (i32: Clone).
+/// (i32: Sized).
+/// Outlives(i32: 'a).
+/// WellFormed(i32).
+/// ```
+///
+/// Scalars implement the auto traits as well, but through default impls
+/// like those of structs, so that impls can opt them out; see
+/// `Program::add_default_impls`.
+fn scalar_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    fn is_scalar(ty: &ir::Ty) -> bool {
+        match ty {
+            ir::Ty::Apply(apply) => match apply.name {
+                ir::TypeName::Scalar(_) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    let holds = match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => {
+            let trait_id = trait_ref.trait_id;
+            trait_ref.parameters.len() == 1
+                && trait_ref.parameters[0].as_ref().ty().map_or(false, is_scalar)
+                && ((env.builtin_traits.copy_clone
+                    && (env.is_lang_item(ir::LangItem::CopyTrait, trait_id)
+                        || env.is_lang_item(ir::LangItem::CloneTrait, trait_id)))
+                    || (env.builtin_traits.sized
+                        && env.is_lang_item(ir::LangItem::SizedTrait, trait_id)))
+        }
+        ir::DomainGoal::Outlives(outlives) => outlives.a.as_ref().ty().map_or(false, is_scalar),
+        ir::DomainGoal::WellFormedTy(ty) => is_scalar(ty),
+        _ => false,
+    };
+    if holds {
+        vec![goal.clone().cast()]
+    } else {
+        vec![]
+    }
+}
+
+/// The clauses for the never type, `!`, generated on demand like those
+/// for tuples. A value of type `!` can never be produced, so it is
+/// trivially `Sized`, `Copy` and so on; in fact, any impl for it would
+/// be vacuous. With `BuiltinTraits::never_fallback`, it implements every
+/// trait; otherwise only these:
+///
+/// ```notrust
+/// (!: Send), and so on for every auto trait.
+/// (!: Copy).
+/// (!: Clone).
+/// (!: Sized).
+/// Outlives(!: 'a).
+/// WellFormed(!).
+/// ```
+///
+/// So `!` satisfies a `T: Sized` bound, as well as the `?Sized` ones
+/// that require nothing, and is a candidate wherever fallback picks it.
+fn never_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    let is_never = |ty: &ir::Ty| match ty {
+        ir::Ty::Apply(apply) => apply.name == ir::TypeName::Never,
+        _ => false,
+    };
+
+    let holds = match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => {
+            let trait_id = trait_ref.trait_id;
+            let is_auto = env.builtin_traits.auto_traits
+                && env.trait_data[&trait_id].binders.value.flags.auto;
+            let is_copy_clone = env.builtin_traits.copy_clone
+                && (env.is_lang_item(ir::LangItem::CopyTrait, trait_id)
+                    || env.is_lang_item(ir::LangItem::CloneTrait, trait_id));
+            let is_sized = env.builtin_traits.sized
+                && env.is_lang_item(ir::LangItem::SizedTrait, trait_id);
+            trait_ref.parameters[0].as_ref().ty().map_or(false, is_never)
+                && (env.builtin_traits.never_fallback || is_auto || is_copy_clone || is_sized)
+        }
+        ir::DomainGoal::Outlives(outlives) => outlives.a.as_ref().ty().map_or(false, is_never),
+        ir::DomainGoal::WellFormedTy(ty) => is_never(ty),
+        _ => false,
+    };
+    if holds {
+        vec![goal.clone().cast()]
+    } else {
+        vec![]
+    }
+}
+
+/// The clauses for the `#[lang_unsize]` trait, which relates a type to
+/// the unsized types it can be coerced to: an array to the slice of its
+/// elements, a sized type to a trait object whose traits it implements,
+/// and a trait object to one whose traits are among those it implements
+/// through its own (upcasting). Given the goal `T: Unsize<U>`, they are:
+///
+/// ```notrust
+/// [A; 3]: Unsize<[A]>.
+/// T: Unsize<dyn Foo + Send> :- T: Foo, T: Send, T: Sized.
+/// dyn Sub<A> + Send + 'a: Unsize<dyn Super<A> + 'b> :- 'a: 'b.
+/// ```
+///
+/// the first if `T` is `[A; 3]`, the second if `U` is `dyn Foo + Send`,
+/// where `Sized` is the `#[lang_sized]` trait, if the program has one,
+/// and the third if `T` is `dyn Sub<A> + Send + 'a` and `U` a trait
+/// object, where `trait Sub<A> where Self: Super<A>`; see
+/// `dyn_trait_refs`. Upcasting may drop auto traits and shorten the
+/// lifetime of the trait object, but not add traits.
+fn unsize_clauses(
+    env: &ir::ProgramEnvironment,
+    goal: &ir::DomainGoal,
+) -> Vec<ir::ProgramClause> {
+    let trait_ref = match goal {
+        ir::DomainGoal::Holds(ir::WhereClauseAtom::Implemented(trait_ref)) => trait_ref,
+        _ => return vec![],
+    };
+    let trait_id = trait_ref.trait_id;
+    if !env.builtin_traits.unsize || !env.is_lang_item(ir::LangItem::UnsizeTrait, trait_id) {
+        return vec![];
+    }
+    let types: Vec<&ir::Ty> = trait_ref.parameters.iter().filter_map(|p| p.as_ref().ty()).collect();
+    let (source, target) = match types[..] {
+        [source, target] => (source, target),
+        _ => return vec![],
+    };
+    let unsize = |target: ir::Ty| ir::TraitRef {
+        trait_id,
+        parameters: vec![source.clone().cast(), target.cast()],
+    };
+
+    let mut clauses = vec![];
+    if let ir::Ty::Apply(array) = source {
+        if let ir::TypeName::Array(_) = array.name {
+            let fact: ir::DomainGoal = unsize(ir::Ty::Apply(ir::ApplicationTy {
+                name: ir::TypeName::Slice,
+                parameters: array.parameters.clone(),
+            })).cast();
+            clauses.push(fact.cast());
+        }
+    }
+    match (source, target) {
+        (ir::Ty::Dyn(source_dyn), ir::Ty::Dyn(target_dyn)) => {
+            clauses.extend(
+                upcast_targets(env, source_dyn, target_dyn)
+                    .into_iter()
+                    .map(|bounds| {
+                        let target = ir::Ty::Dyn(ir::DynTy {
+                            bounds,
+                            lifetime: target_dyn.lifetime,
+                        });
+                        let outlives = match (source_dyn.lifetime, target_dyn.lifetime) {
+                            (Some(a), Some(b)) => Some(ir::DomainGoal::Outlives(ir::Outlives {
+                                a: ir::ParameterKind::Lifetime(a),
+                                b,
+                            })),
+                            _ => None,
+                        };
+                        ir::ProgramClauseImplication {
+                            consequence: unsize(target).cast(),
+                            conditions: outlives.into_iter().map(|goal| goal.cast()).collect(),
+                        }.cast()
+                    }),
+            );
+        }
+        (ir::Ty::Dyn(_), _) => {}
+        (_, ir::Ty::Dyn(dyn_ty)) => {
+            let sized = env.lang_items.get(&ir::LangItem::SizedTrait).map(|&trait_id| {
+                ir::TraitRef {
+                    trait_id,
+                    parameters: vec![source.clone().cast()],
+                }
+            });
+            let clause = ir::ProgramClauseImplication {
+                consequence: unsize(target.clone()).cast(),
+                conditions: dyn_ty
+                    .bounds
+                    .iter()
+                    .map(|bound| bound.trait_ref(source.clone()))
+                    .chain(sized)
+                    .map(|trait_ref| trait_ref.cast())
+                    .collect(),
+            };
+            clauses.push(clause.cast());
+        }
+        _ => {}
+    }
+    clauses
+}
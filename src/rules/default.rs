@@ -1,48 +1,83 @@
+use chalk_parse::ast::ScalarType;
 use ir::*;
 use solve::infer::InferenceTable;
 use cast::Cast;
 
 impl Program {
     pub fn add_default_impls(&mut self) {
+        if !self.builtin_traits.auto_traits {
+            return;
+        }
+
         // For each auto trait `MyAutoTrait` and for each struct/type `MyStruct`
         for auto_trait in self.trait_data
             .values()
             .filter(|t| t.binders.value.flags.auto)
         {
-            for struct_datum in self.struct_data.values() {
+            for adt_datum in self.adt_data.values() {
                 // `MyStruct: MyAutoTrait`
                 let trait_ref = TraitRef {
                     trait_id: auto_trait.binders.value.trait_ref.trait_id,
                     parameters: vec![
-                        ParameterKind::Ty(Ty::Apply(struct_datum.binders.value.self_ty.clone())),
+                        ParameterKind::Ty(Ty::Apply(adt_datum.binders.value.self_ty.clone())),
                     ],
                 };
 
                 // If a positive or negative impl is already provided for a type family
                 // which includes `MyStruct`, we do not generate a default impl.
-                if self.impl_provided_for(trait_ref.clone(), struct_datum) {
+                if self.impl_provided_for(trait_ref.clone(), &adt_datum.binders.binders) {
+                    continue;
+                }
+
+                self.default_impl_data.push(DefaultImplDatum {
+                    binders: Binders {
+                        binders: adt_datum.binders.binders.clone(),
+                        value: DefaultImplDatumBound {
+                            trait_ref,
+                            accessible_tys: adt_datum.binders.value.fields(),
+                        },
+                    },
+                });
+            }
+
+            // Scalar types are treated like structs without fields:
+            // `i32: MyAutoTrait` holds unless an impl says otherwise.
+            for &scalar in ScalarType::ALL.iter() {
+                let trait_ref = TraitRef {
+                    trait_id: auto_trait.binders.value.trait_ref.trait_id,
+                    parameters: vec![ParameterKind::Ty(TypeName::Scalar(scalar).to_ty())],
+                };
+
+                if self.impl_provided_for(trait_ref.clone(), &[]) {
                     continue;
                 }
 
                 self.default_impl_data.push(DefaultImplDatum {
                     binders: Binders {
-                        binders: struct_datum.binders.binders.clone(),
+                        binders: vec![],
                         value: DefaultImplDatumBound {
                             trait_ref,
-                            accessible_tys: struct_datum.binders.value.fields.clone(),
+                            accessible_tys: vec![],
                         },
                     },
                 });
             }
         }
+        self.invalidate_environment();
     }
 
-    fn impl_provided_for(&self, trait_ref: TraitRef, struct_datum: &StructDatum) -> bool {
+    /// Whether an impl covers `trait_ref`, whose type parameters are
+    /// bound by `binders`.
+    fn impl_provided_for(&self, trait_ref: TraitRef, binders: &[ParameterKind<()>]) -> bool {
         let goal: DomainGoal = trait_ref.cast();
+        let goal = Binders {
+            binders: binders.to_vec(),
+            value: goal,
+        };
 
         let mut infer = InferenceTable::new();
 
-        let goal = infer.instantiate_binders_existentially(&(&struct_datum.binders.binders, &goal));
+        let goal = infer.instantiate_binders_existentially(&goal);
 
         for impl_datum in self.impl_data.values() {
             // We retrieve the trait ref given by the positive impl (even if the actual impl is negative)
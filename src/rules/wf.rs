@@ -1,9 +1,19 @@
+use std::collections::BTreeSet;
+use std::fmt;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
 
 use ir::*;
 use errors::*;
 use cast::*;
-use solve::SolverChoice;
+use fallible::Fallible;
+use fold::shift::Shift;
+use fold::{self, DefaultTypeFolder, ExistentialFolder, Fold, IdentityExistentialFolder,
+           IdentityUniversalFolder, Subst, TypeFolder};
+use progress::{CheckPhase, ProgressReporter};
+use solve::{SolveOptions, SolverChoice};
 use itertools::Itertools;
 
 mod test;
@@ -13,34 +23,465 @@ struct WfSolver {
     solver_choice: SolverChoice,
 }
 
+/// An item whose well-formedness must be checked.
+#[derive(Copy, Clone, Debug)]
+enum WfItem {
+    Adt(ItemId),
+    Impl(ItemId),
+}
+
+impl WfItem {
+    fn id(self) -> ItemId {
+        match self {
+            WfItem::Adt(id) | WfItem::Impl(id) => id,
+        }
+    }
+}
+
 impl Program {
+    /// Checks the well-formedness of every struct and impl on the
+    /// current thread; see `verify_well_formedness_with_threads`.
     pub fn verify_well_formedness(&self, solver_choice: SolverChoice) -> Result<()> {
-        tls::set_current_program(&Arc::new(self.clone()), || self.solve_wf_requirements(solver_choice))
+        self.verify_well_formedness_with_threads(solver_choice, 1)
     }
 
-    fn solve_wf_requirements(&self, solver_choice: SolverChoice) -> Result<()> {
-        let solver = WfSolver {
-            env: Arc::new(self.environment()),
-            solver_choice,
+    /// Checks the well-formedness of every struct and impl, spreading
+    /// the (independent) checks over `num_threads` threads. If several
+    /// items are ill-formed, the error reports the same one that a
+    /// sequential check would, regardless of `num_threads`.
+    pub fn verify_well_formedness_with_threads(
+        &self,
+        solver_choice: SolverChoice,
+        num_threads: usize,
+    ) -> Result<()> {
+        let mut progress = ProgressReporter::silent();
+        self.verify_well_formedness_with_progress(solver_choice, num_threads, &mut progress)
+    }
+
+    /// Like `verify_well_formedness_with_threads`, but reports each
+    /// item to `progress` before it is checked.
+    crate fn verify_well_formedness_with_progress(
+        &self,
+        solver_choice: SolverChoice,
+        num_threads: usize,
+        progress: &mut ProgressReporter,
+    ) -> Result<()> {
+        let program = Arc::new(self.clone());
+        tls::set_current_program(&program, || {
+            program.verify_object_safety()?;
+            solve_wf_requirements(&program, solver_choice, num_threads, progress)
+        })
+    }
+
+    /// Checks that every trait named in a `dyn` type of the program is
+    /// object safe; see `check_object_safety`.
+    fn verify_object_safety(&self) -> Result<()> {
+        let mut collector = DynTraitCollector {
+            trait_ids: BTreeSet::new(),
         };
+        for datum in self.adt_data.values() {
+            let bound = &datum.binders.value;
+            collector.collect(&bound.fields());
+            collector.collect(&bound.where_clauses);
+        }
+        for datum in self.impl_data.values() {
+            let bound = &datum.binders.value;
+            collector.collect(&bound.trait_ref);
+            collector.collect(&bound.where_clauses);
+            collector.collect(&bound.associated_ty_values);
+        }
+        for datum in self.inherent_impl_data.values() {
+            let bound = &datum.binders.value;
+            let self_ty: Ty = bound.self_ty.clone().cast();
+            collector.collect(&self_ty);
+            collector.collect(&bound.where_clauses);
+            for value in &bound.associated_ty_values {
+                collector.collect(&value.value);
+            }
+        }
+        for datum in self.trait_data.values() {
+            collector.collect(&datum.binders.value.where_clauses);
+        }
+        for datum in self.associated_ty_data.values() {
+            collector.collect(&datum.bounds);
+            collector.collect(&datum.where_clauses);
+        }
+        for datum in self.opaque_ty_data.values() {
+            collector.collect(&datum.binders.value.bounds);
+            collector.collect(&datum.binders.value.hidden_ty);
+        }
+        for datum in self.closure_data.values() {
+            collector.collect(&datum.binders.value.inputs);
+            collector.collect(&datum.binders.value.output);
+        }
+        collector.collect(&self.custom_clauses);
 
-        for (id, struct_datum) in &self.struct_data {
-            if !solver.verify_struct_decl(struct_datum) {
-                let name = self.type_kinds.get(id).unwrap().name;
-                return Err(Error::from_kind(ErrorKind::IllFormedTypeDecl(name)));
+        for trait_id in collector.trait_ids {
+            if let Err(violation) = check_object_safety(self, trait_id) {
+                let name = self.type_kinds[&trait_id].name;
+                bail!(ErrorKind::NotObjectSafe(name, violation));
             }
         }
+        Ok(())
+    }
+}
 
-        for impl_datum in self.impl_data.values() {
-            if !solver.verify_trait_impl(impl_datum) {
-                let trait_ref = impl_datum.binders.value.trait_ref.trait_ref();
-                let name = self.type_kinds.get(&trait_ref.trait_id).unwrap().name;
-                return Err(Error::from_kind(ErrorKind::IllFormedTraitImpl(name)));
+/// Why a trait cannot be named in a `dyn` type. The language has no
+/// methods, so the rules are those of Rust for the rest of a trait:
+/// its where clauses and its associated types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ObjectSafetyViolation {
+    /// The trait requires `Self: Sized`, directly or through one of its
+    /// supertraits; trait object types are not sized.
+    SizedSelf,
+
+    /// A where clause of the trait mentions `Self` other than as the
+    /// type it is about, as in `trait Foo where Self: PartialEq<Self>`.
+    SelfInWhereClause,
+
+    /// The associated type has parameters of its own -- the counterpart
+    /// of a generic method -- so no trait object type can give it a
+    /// value for all of them.
+    GenericAssociatedType(Identifier),
+
+    /// A bound of the associated type mentions `Self`, as in `type Item:
+    /// Into<Self>`, which a trait object type cannot stand for.
+    SelfInAssociatedType(Identifier),
+}
+
+impl fmt::Display for ObjectSafetyViolation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectSafetyViolation::SizedSelf => write!(fmt, "it requires `Self: Sized`"),
+            ObjectSafetyViolation::SelfInWhereClause => {
+                write!(fmt, "one of its where clauses mentions `Self`")
+            }
+            ObjectSafetyViolation::GenericAssociatedType(name) => {
+                write!(fmt, "its associated type `{}` has parameters", name)
+            }
+            ObjectSafetyViolation::SelfInAssociatedType(name) => {
+                write!(fmt, "a bound of its associated type `{}` mentions `Self`", name)
             }
         }
+    }
+}
 
-        Ok(())
+/// Checks whether the trait `trait_id` is object safe, i.e., whether
+/// `dyn Trait` is a valid type, reporting the first violation found.
+crate fn check_object_safety(
+    program: &Program,
+    trait_id: ItemId,
+) -> ::std::result::Result<(), ObjectSafetyViolation> {
+    let sized_trait_id = program.lang_items.get(&LangItem::SizedTrait).cloned();
+
+    // The trait and its supertraits, i.e., the traits that `Self` is
+    // required to implement.
+    let mut supertraits = BTreeSet::new();
+    let mut stack = vec![trait_id];
+    while let Some(id) = stack.pop() {
+        if !supertraits.insert(id) {
+            continue;
+        }
+        if Some(id) == sized_trait_id {
+            return Err(ObjectSafetyViolation::SizedSelf);
+        }
+        for wc in &program.trait_data[&id].binders.value.where_clauses {
+            // Within the where clause, `Self` is the first variable
+            // bound outside of its own binders.
+            let self_index = wc.binders.len();
+            if let DomainGoal::Holds(WhereClauseAtom::Implemented(trait_ref)) = &wc.value {
+                if trait_ref.parameters[0] == ParameterKind::Ty(Ty::Var(self_index)) {
+                    stack.push(trait_ref.trait_id);
+                }
+            }
+        }
+    }
+
+    for wc in &program.trait_data[&trait_id].binders.value.where_clauses {
+        let self_index = wc.binders.len();
+        let mentions = match &wc.value {
+            DomainGoal::Holds(WhereClauseAtom::Implemented(trait_ref)) => {
+                mentions_self(&trait_ref.parameters[1..].to_vec(), self_index)
+            }
+            DomainGoal::Holds(WhereClauseAtom::ProjectionEq(projection_eq)) => {
+                mentions_self(&projection_eq.ty, self_index)
+            }
+            _ => false,
+        };
+        if mentions {
+            return Err(ObjectSafetyViolation::SelfInWhereClause);
+        }
+    }
+
+    let trait_len = program.trait_data[&trait_id].binders.len();
+    for datum in program.associated_ty_data.values() {
+        if datum.trait_id != trait_id {
+            continue;
+        }
+
+        // The parameters of the associated type come first, then those
+        // of the trait, starting with `Self`.
+        let self_index = datum.parameter_kinds.len() - trait_len;
+        if self_index > 0 {
+            return Err(ObjectSafetyViolation::GenericAssociatedType(datum.name));
+        }
+        for bound in &datum.bounds {
+            let mentions = match bound {
+                WhereClauseAtom::Implemented(trait_ref) => {
+                    mentions_self(&trait_ref.parameters[1..].to_vec(), self_index)
+                }
+                WhereClauseAtom::ProjectionEq(projection_eq) => {
+                    mentions_self(&projection_eq.ty, self_index)
+                }
+            };
+            if mentions {
+                return Err(ObjectSafetyViolation::SelfInAssociatedType(datum.name));
+            }
+        }
     }
+
+    Ok(())
+}
+
+/// Whether `value` mentions `Self`, which is the free variable with the
+/// index `self_index`.
+fn mentions_self<T: Fold>(value: &T, self_index: usize) -> bool {
+    let mut finder = SelfFinder {
+        self_index,
+        found: false,
+    };
+    value.fold_with(&mut finder, 0)
+        .expect("finding `Self` cannot fail");
+    finder.found
+}
+
+struct SelfFinder {
+    self_index: usize,
+    found: bool,
+}
+
+impl ExistentialFolder for SelfFinder {
+    fn fold_free_existential_ty(&mut self, depth: usize, binders: usize) -> Fallible<Ty> {
+        if depth == self.self_index {
+            self.found = true;
+        }
+        Ok(Ty::Var(depth + binders))
+    }
+
+    fn fold_free_existential_lifetime(
+        &mut self,
+        depth: usize,
+        binders: usize,
+    ) -> Fallible<Lifetime> {
+        Ok(Lifetime::Var(depth + binders))
+    }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        Ok(Const::Var(depth + binders))
+    }
+}
+
+impl IdentityUniversalFolder for SelfFinder {}
+
+impl DefaultTypeFolder for SelfFinder {}
+
+/// Collects the traits named in `dyn` types.
+struct DynTraitCollector {
+    trait_ids: BTreeSet<ItemId>,
+}
+
+impl DynTraitCollector {
+    fn collect<T: Fold>(&mut self, value: &T) {
+        value.fold_with(self, 0)
+            .expect("collecting traits cannot fail");
+    }
+}
+
+impl TypeFolder for DynTraitCollector {
+    fn fold_ty(&mut self, ty: &Ty, binders: usize) -> Fallible<Ty> {
+        if let Ty::Dyn(dyn_ty) = ty {
+            self.trait_ids
+                .extend(dyn_ty.bounds.iter().map(|bound| bound.trait_id));
+        }
+        fold::super_fold_ty(self, ty, binders)
+    }
+
+    fn fold_lifetime(&mut self, lifetime: &Lifetime, binders: usize) -> Fallible<Lifetime> {
+        fold::super_fold_lifetime(self, lifetime, binders)
+    }
+
+    fn fold_const(&mut self, constant: &Const, binders: usize) -> Fallible<Const> {
+        fold::super_fold_const(self, constant, binders)
+    }
+}
+
+impl IdentityExistentialFolder for DynTraitCollector {}
+
+impl IdentityUniversalFolder for DynTraitCollector {}
+
+fn solve_wf_requirements(
+    program: &Arc<Program>,
+    solver_choice: SolverChoice,
+    num_threads: usize,
+    progress: &mut ProgressReporter,
+) -> Result<()> {
+    let env = program.environment();
+    let items: Vec<_> = program
+        .adt_data
+        .keys()
+        .map(|&id| WfItem::Adt(id))
+        .chain(program.impl_data.keys().map(|&id| WfItem::Impl(id)))
+        .collect();
+    let total = items.len();
+    let mut aborted = None;
+
+    // Debug output from worker threads cannot name items (the string
+    // interner is thread-local), so stay on this thread when logging.
+    let results = if num_threads <= 1 || items.len() <= 1 || *::chalk_macros::INFO_ENABLED {
+        let mut checked = 0;
+        verify_items(program, &env, solver_choice, &items, |item| {
+            match progress.report(CheckPhase::WellFormedness, checked, total, item.id()) {
+                Ok(()) => {
+                    checked += 1;
+                    true
+                }
+                Err(error) => {
+                    aborted = Some(error);
+                    false
+                }
+            }
+        })
+    } else {
+        // Workers announce each item they are about to check, so that
+        // progress is reported from this thread, and stop once the
+        // checks are aborted.
+        let (started_tx, started_rx) = mpsc::channel();
+        let abort = Arc::new(AtomicBool::new(false));
+        let chunk_size = (items.len() + num_threads - 1) / num_threads;
+        let workers: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let program = program.clone();
+                let env = env.clone();
+                let chunk = chunk.to_vec();
+                let started_tx = started_tx.clone();
+                let abort = abort.clone();
+                thread::spawn(move || {
+                    tls::set_current_program(&program, || {
+                        verify_items(&program, &env, solver_choice, &chunk, |item| {
+                            started_tx.send(item).is_ok() && !abort.load(Ordering::SeqCst)
+                        })
+                    })
+                })
+            })
+            .collect();
+        drop(started_tx);
+
+        for (checked, item) in started_rx.iter().enumerate() {
+            if aborted.is_none() {
+                if let Err(error) =
+                    progress.report(CheckPhase::WellFormedness, checked, total, item.id())
+                {
+                    abort.store(true, Ordering::SeqCst);
+                    aborted = Some(error);
+                }
+            }
+        }
+
+        // Chunks are joined in order, so `results` lines up with `items`.
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("WF worker thread panicked"))
+            .collect()
+    };
+
+    if let Some(error) = aborted {
+        return Err(error);
+    }
+
+    match items.iter().zip(results).find(|&(_, well_formed)| !well_formed) {
+        Some((&WfItem::Adt(id), _)) => {
+            let name = program.type_kinds.get(&id).unwrap().name;
+            Err(Error::from_kind(ErrorKind::IllFormedTypeDecl(name)))
+        }
+        Some((&WfItem::Impl(id), _)) => {
+            let trait_ref = program.impl_data[&id].binders.value.trait_ref.trait_ref();
+            let name = program.type_kinds.get(&trait_ref.trait_id).unwrap().name;
+            Err(Error::from_kind(ErrorKind::IllFormedTraitImpl(name)))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Checks each of `items`, returning whether each is well-formed.
+/// `starting` is called before each item is checked, and the checks
+/// stop early if it returns false.
+fn verify_items<F>(
+    program: &Program,
+    env: &Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+    items: &[WfItem],
+    mut starting: F,
+) -> Vec<bool>
+where
+    F: FnMut(WfItem) -> bool,
+{
+    let solver = WfSolver {
+        env: env.clone(),
+        solver_choice,
+    };
+
+    items
+        .iter()
+        .take_while(|&&item| starting(item))
+        .map(|&item| match item {
+            WfItem::Adt(id) => solver.verify_adt_decl(&program.adt_data[&id]),
+            WfItem::Impl(id) => solver.verify_trait_impl(&program.impl_data[&id]),
+        })
+        .collect()
+}
+
+/// The hypotheses of `environment`, followed, if `implied_bounds` is set,
+/// by those they imply; see `implied_hypotheses`. Both solvers look up
+/// hypotheses through this, so that the root goal's hypotheses -- which
+/// are added to its environment before it reaches a solver -- are
+/// elaborated just like those of nested implication goals.
+crate fn environment_clauses(environment: &Environment, implied_bounds: bool) -> Vec<ProgramClause> {
+    let mut clauses = environment.clauses.clone();
+    if implied_bounds {
+        let mut seen: BTreeSet<_> = clauses.iter().cloned().collect();
+        for hypothesis in &environment.clauses {
+            let implied = implied_hypotheses(hypothesis);
+            clauses.extend(implied.into_iter().filter(|clause| seen.insert(clause.clone())));
+        }
+    }
+    clauses
+}
+
+/// The hypotheses implied by the types that `hypothesis` mentions, when
+/// those are assumed to be well-formed (RFC 2089): from `FromEnv(Foo<T>:
+/// Bar)` we get `FromEnvTy(Foo<T>)`, and hence the where clauses of
+/// `Foo`. Only trait hypotheses without binders or conditions are
+/// considered.
+crate fn implied_hypotheses(hypothesis: &ProgramClause) -> Vec<ProgramClause> {
+    let mut input_types = vec![];
+    if let ProgramClause::Implies(implication) = hypothesis {
+        if let DomainGoal::FromEnv(wca) = &implication.consequence {
+            if implication.conditions.is_empty() {
+                wca.fold(&mut input_types);
+            }
+        }
+    }
+
+    input_types
+        .into_iter()
+        .map(|ty| {
+            ProgramClause::Implies(ProgramClauseImplication {
+                consequence: DomainGoal::FromEnvTy(ty),
+                conditions: vec![],
+            })
+        })
+        .collect()
 }
 
 /// A trait for retrieving all types appearing in some Chalk construction.
@@ -90,6 +531,17 @@ impl FoldInputTypes for Ty {
             // sense, well-formedness requirements for the input types of an HKT will be enforced
             // lazily, so no need to include them here.
             Ty::ForAll(..) => (),
+
+            // A trait object type is well-formed when the parameters of
+            // its traits are; see `dyn_clauses`.
+            Ty::Dyn(dyn_ty) => for bound in &dyn_ty.bounds {
+                bound.parameters.fold(accumulator);
+            },
+
+            Ty::Opaque(opaque) => {
+                accumulator.push(self.clone());
+                opaque.parameters.fold(accumulator);
+            }
         }
     }
 }
@@ -136,18 +588,48 @@ impl FoldInputTypes for DomainGoal {
             DomainGoal::Holds(wca) => wca.fold(accumulator),
             DomainGoal::Normalize(n) => n.fold(accumulator),
             DomainGoal::UnselectedNormalize(n) => n.fold(accumulator),
+            DomainGoal::NormalizeConst(n) => n.projection.parameters.fold(accumulator),
+            DomainGoal::Predicate(p) => p.parameters.fold(accumulator),
+            DomainGoal::Outlives(o) => o.a.fold(accumulator),
 
             DomainGoal::WellFormed(..) |
             DomainGoal::FromEnv(..) |
             DomainGoal::WellFormedTy(..) |
             DomainGoal::FromEnvTy(..) |
-            DomainGoal::Derefs(..) => panic!("unexpected where clause"),
+            DomainGoal::Derefs(..) |
+            DomainGoal::NotImplemented(..) |
+            DomainGoal::OpaqueEq(..) |
+            DomainGoal::IsFullyVisible(..) => panic!("unexpected where clause"),
 
-            DomainGoal::InScope(..) => (),
+            DomainGoal::InScope(..) |
+            DomainGoal::Reveal(..) => (),
         }
     }
 }
 
+/// Breaks an outlives requirement about a type up into requirements
+/// about its parameters, recursively, keeping the original as well:
+/// from `Outlives(Vec<Ref<'b, T>>: 'a)` we get `Outlives(Ref<'b, T>: 'a)`,
+/// `Outlives('b: 'a)` and `Outlives(T: 'a)`. No program clause lets the
+/// solver go from a type to its parameters, so the requirements we
+/// assume are elaborated this way instead.
+fn elaborate_outlives(wc: QuantifiedDomainGoal) -> Vec<QuantifiedDomainGoal> {
+    let mut elaborated = vec![];
+    if let DomainGoal::Outlives(Outlives { a: ParameterKind::Ty(Ty::Apply(app)), b }) = &wc.value {
+        for parameter in &app.parameters {
+            elaborated.extend(elaborate_outlives(Binders {
+                binders: wc.binders.clone(),
+                value: DomainGoal::Outlives(Outlives {
+                    a: parameter.clone(),
+                    b: *b,
+                }),
+            }));
+        }
+    }
+    elaborated.push(wc);
+    elaborated
+}
+
 impl<T: FoldInputTypes> FoldInputTypes for Binders<T> {
     fn fold(&self, accumulator: &mut Vec<Ty>) {
         self.value.fold(accumulator);
@@ -155,11 +637,11 @@ impl<T: FoldInputTypes> FoldInputTypes for Binders<T> {
 }
 
 impl WfSolver {
-    fn verify_struct_decl(&self, struct_datum: &StructDatum) -> bool {
-        // We retrieve all the input types of the struct fields.
+    fn verify_adt_decl(&self, adt_datum: &AdtDatum) -> bool {
+        // We retrieve all the input types of the fields, of every variant.
         let mut input_types = Vec::new();
-        struct_datum.binders.value.fields.fold(&mut input_types);
-        struct_datum.binders.value.where_clauses.fold(&mut input_types);
+        adt_datum.binders.value.fields().fold(&mut input_types);
+        adt_datum.binders.value.where_clauses.fold(&mut input_types);
 
         if input_types.is_empty() {
             return true;
@@ -170,21 +652,25 @@ impl WfSolver {
                         .expect("at least one goal");
 
         let hypotheses =
-            struct_datum.binders
+            adt_datum.binders
                         .value
                         .where_clauses
                         .iter()
                         .cloned()
                         .map(|wc| wc.map(|bound| bound.into_from_env_goal()))
+                        .flat_map(elaborate_outlives)
                         .casted()
                         .collect();
 
         // We ask that the above input types are well-formed provided that all the where-clauses
         // on the struct definition hold.
         let goal = Goal::Implies(hypotheses, Box::new(goal))
-            .quantify(QuantifierKind::ForAll, struct_datum.binders.binders.clone());
+            .quantify(QuantifierKind::ForAll, adt_datum.binders.binders.clone());
 
-        match self.solver_choice.solve_root_goal(&self.env, &goal.into_closed_goal()).unwrap() {
+        let outcome = self.solver_choice
+            .solve_root_goal(&self.env, &goal.into_closed_goal(), SolveOptions::default())
+            .unwrap();
+        match outcome.solution {
             Some(sol) => sol.is_unique(),
             None => false,
         }
@@ -253,6 +739,10 @@ impl WfSolver {
         // }
         // ```
         // we would issue the following subgoal: `forall<'a> { WellFormed(Box<&'a T>) }`.
+        //
+        // The where clauses of the associated type may be assumed there, among them the
+        // outlives requirements implied by its lifetime parameters: here, `Box<T>: 'a`, and
+        // hence `T: 'a`.
         let compute_assoc_ty_goal = |assoc_ty: &AssociatedTyValue| {
             let mut input_types = Vec::new();
             assoc_ty.value.value.ty.fold(&mut input_types);
@@ -264,7 +754,25 @@ impl WfSolver {
             let goals = input_types.into_iter().map(|ty| DomainGoal::WellFormedTy(ty).cast());
             let goal = goals.fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
                             .expect("at least one goal");
-            Some(goal.quantify(QuantifierKind::ForAll, assoc_ty.value.binders.clone()))
+
+            // The parameters of the associated type, followed by those of the trait ref.
+            let assoc_ty_datum = &self.env.associated_ty_data[&assoc_ty.associated_ty_id];
+            let parameters: Vec<_> =
+                assoc_ty.value.binders.iter()
+                                      .zip(0..)
+                                      .map(|p| p.to_parameter())
+                                      .chain(trait_ref.parameters.iter().map(|p| p.up_shift(assoc_ty.value.len())))
+                                      .collect();
+            let hypotheses =
+                Subst::apply(&parameters, &assoc_ty_datum.where_clauses)
+                    .into_iter()
+                    .map(|wc| wc.map(|bound| bound.into_from_env_goal()))
+                    .flat_map(elaborate_outlives)
+                    .casted()
+                    .collect();
+
+            Some(goal.implied_by(hypotheses)
+                     .quantify(QuantifierKind::ForAll, assoc_ty.value.binders.clone()))
         };
 
         let assoc_ty_goals =
@@ -299,6 +807,7 @@ impl WfSolver {
                       .iter()
                       .cloned()
                       .map(|wc| wc.map(|bound| bound.into_from_env_goal()))
+                      .flat_map(elaborate_outlives)
                       .casted()
                       .chain(header_other_types.into_iter().map(|ty| DomainGoal::FromEnvTy(ty).cast()))
                       .collect();
@@ -306,7 +815,10 @@ impl WfSolver {
         let goal = Goal::Implies(hypotheses, Box::new(goal))
             .quantify(QuantifierKind::ForAll, impl_datum.binders.binders.clone());
 
-        match self.solver_choice.solve_root_goal(&self.env, &goal.into_closed_goal()).unwrap() {
+        let outcome = self.solver_choice
+            .solve_root_goal(&self.env, &goal.into_closed_goal(), SolveOptions::default())
+            .unwrap();
+        match outcome.solution {
             Some(sol) => sol.is_unique(),
             None => false,
         }
@@ -9,8 +9,6 @@ fn well_formed_trait_decl() {
             trait Clone { }
             trait Copy where Self: Clone { }
 
-            struct i32 { }
-
             impl Clone for i32 { }
             impl Copy for i32 { }
         }
@@ -24,8 +22,6 @@ fn ill_formed_trait_decl() {
             trait Clone { }
             trait Copy where Self: Clone { }
 
-            struct i32 { }
-
             impl Copy for i32 { }
         } error_msg {
             "trait impl for \"Copy\" does not meet well-formedness requirements"
@@ -96,8 +92,6 @@ fn ill_formed_assoc_ty() {
             trait Foo { }
             struct OnlyFoo<T> where T: Foo { }
 
-            struct i32 { }
-
             trait Bar {
                 type Value;
             }
@@ -252,7 +246,6 @@ fn higher_ranked_trait_bounds() {
         program {
             trait Foo<'a> { }
             trait Bar where forall<'a> Self: Foo<'a> { }
-            struct i32 { }
 
             impl Bar for i32 { }
         } error_msg {
@@ -264,7 +257,6 @@ fn higher_ranked_trait_bounds() {
         program {
             trait Foo<'a> { }
             trait Bar where forall<'a> Self: Foo<'a> { }
-            struct i32 { }
 
             impl<'a> Foo<'a> for i32 { }
             impl Bar for i32 { }
@@ -327,3 +319,193 @@ fn deref_trait() {
         }
     }
 }
+
+#[test]
+fn gat_implied_outlives() {
+    // `type Iter<'a>` implies `Self: 'a`, so the impl may assume `Vec<T>: 'a`
+    // and hence `T: 'a`.
+    lowering_success! {
+        program {
+            struct Vec<T> { }
+            struct Iter<'a, T> where T: 'a { }
+
+            trait Iterable {
+                type Iter<'a>;
+            }
+
+            impl<T> Iterable for Vec<T> {
+                type Iter<'a> = Iter<'a, T>;
+            }
+        }
+    }
+
+    // Nothing is implied about the type parameters of the associated type itself.
+    lowering_error! {
+        program {
+            struct Vec<T> { }
+            struct Iter<'a, T> where T: 'a { }
+
+            trait Iterable {
+                type Iter<'a, U>;
+            }
+
+            impl<T> Iterable for Vec<T> {
+                type Iter<'a, U> = Iter<'a, U>;
+            }
+        } error_msg {
+            "trait impl for \"Iterable\" does not meet well-formedness requirements"
+        }
+    }
+}
+
+#[test]
+fn object_safety() {
+    lowering_success! {
+        program {
+            #[lang_sized] trait Sized { }
+            trait Clone { }
+            trait Iterator where Self: Clone { type Item: Clone; }
+            struct Box<T> { }
+            struct Foo { field: Box<dyn Iterator> }
+        }
+    }
+
+    // A trait that is not object safe is fine, as long as no `dyn` type
+    // names it.
+    lowering_success! {
+        program {
+            #[lang_sized] trait Sized { }
+            trait Copy where Self: Sized { }
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[lang_sized] trait Sized { }
+            trait Clone where Self: Sized { }
+            trait Copy where Self: Clone { }
+            struct Box<T> { }
+            struct Foo { field: Box<dyn Copy> }
+        } error_msg {
+            "the trait \"Copy\" cannot be made into an object: it requires `Self: Sized`"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait PartialEq<T> { }
+            trait Eq where Self: PartialEq<Self> { }
+            struct Box<T> { }
+            impl Eq for Box<dyn Eq> { }
+        } error_msg {
+            "the trait \"Eq\" cannot be made into an object: one of its where clauses mentions `Self`"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Collection { type Iter<'a>; }
+            struct Box<T> { }
+            struct Foo { field: Box<dyn Collection> }
+        } error_msg {
+            "the trait \"Collection\" cannot be made into an object: its associated type `Iter` has parameters"
+        }
+    }
+
+    lowering_error! {
+        program {
+            trait Into<T> { }
+            trait Parse { type Output: Into<Self>; }
+            struct Box<T> { }
+            struct Foo { field: Box<dyn Parse> }
+        } error_msg {
+            "the trait \"Parse\" cannot be made into an object: a bound of its associated type `Output` mentions `Self`"
+        }
+    }
+}
+
+#[test]
+fn parallel_wf_checking() {
+    use solve::SolverChoice;
+
+    let mut program = parse_and_lower_program(
+        "trait Clone { }
+         trait Copy where Self: Clone { }
+         struct Vec<T> where T: Copy { }
+         struct Wrapper<T> where T: Copy { vec: Vec<T> }
+         impl Clone for i32 { }
+         impl Copy for i32 { }
+         impl Clone for u32 { }
+         impl Copy for u32 { }",
+        SolverChoice::slg(),
+    ).unwrap();
+
+    for &num_threads in &[1, 2, 4, 16] {
+        assert!(
+            program
+                .verify_well_formedness_with_threads(SolverChoice::slg(), num_threads)
+                .is_ok()
+        );
+    }
+
+    // Dropping `impl Clone for i32` makes `impl Copy for i32`
+    // ill-formed; every thread count must report the same error.
+    program.impl_data.remove(&impl_id(&program, "i32 as Clone"));
+    program.invalidate_environment();
+    for &num_threads in &[1, 2, 4, 16] {
+        let error = program
+            .verify_well_formedness_with_threads(SolverChoice::slg(), num_threads)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "trait impl for \"Copy\" does not meet well-formedness requirements"
+        );
+    }
+}
+
+#[test]
+fn progress_reporting() {
+    use chalk_parse;
+    use ir::lowering::LowerProgram;
+    use progress::{CheckPhase, ProgressControl};
+    use solve::SolverChoice;
+
+    let program = chalk_parse::parse_program(
+        "trait Clone { }
+         trait Copy where Self: Clone { }
+         impl Clone for i32 { }
+         impl Copy for i32 { }
+         impl Clone for u32 { }",
+    ).unwrap();
+
+    // Coherence is checked one trait at a time, then each struct and
+    // impl is checked for well-formedness.
+    let mut reports = vec![];
+    program
+        .lower_with_progress(SolverChoice::slg(), &mut |progress| {
+            reports.push((progress.phase, progress.checked, progress.total));
+            ProgressControl::Continue
+        })
+        .unwrap();
+    assert_eq!(
+        reports,
+        vec![
+            (CheckPhase::Coherence, 0, 2),
+            (CheckPhase::Coherence, 1, 2),
+            (CheckPhase::WellFormedness, 0, 3),
+            (CheckPhase::WellFormedness, 1, 3),
+            (CheckPhase::WellFormedness, 2, 3),
+        ]
+    );
+
+    let error = program
+        .lower_with_progress(SolverChoice::slg(), &mut |progress| {
+            if progress.phase == CheckPhase::WellFormedness && progress.checked == 2 {
+                ProgressControl::Abort
+            } else {
+                ProgressControl::Continue
+            }
+        })
+        .unwrap_err();
+    assert_eq!(error.to_string(), "WellFormedness checks aborted after 2 of 3 items");
+}
@@ -1,13 +1,54 @@
+use chalk_engine::answers::{AnswerStore, StoredAnswer};
+use chalk_engine::forest::Forest;
+use itertools::Itertools;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use invariants;
 use ir::*;
+use ir::serialization;
+use self::environment::EnvironmentOverflowLog;
+use self::slg::implementation::SlgContext;
 
+pub use chalk_engine::{CycleKind, ForestStats, LimitKind, MixedCyclePolicy};
+
+crate mod ambiguity;
+crate mod cache;
+crate mod candidates;
+crate mod compat;
+crate mod environment;
+crate mod facts;
+crate mod hypotheses;
 crate mod infer;
+crate mod naive;
+crate mod proof;
+crate mod query_log;
 crate mod slg;
+crate mod region_constraints;
+crate mod rewrite;
 mod test;
+crate mod trace;
 mod truncate;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+pub use self::ambiguity::{AmbiguityInfo, CompetingCandidates};
+pub use self::cache::GoalCache;
+pub use self::candidates::{ImplCandidate, ImplResidual};
+pub use self::compat::{GoalRegression, RegressionKind};
+pub use self::environment::{EnvironmentOverflow, EnvironmentOverflowKind};
+pub use self::facts::FactSet;
+pub use self::hypotheses::HypothesisUsage;
+pub use self::infer::InferenceTable;
+pub use self::infer::unify::UnificationResult;
+pub use self::naive::{NaiveSolver, NaiveStep};
+pub use self::proof::ProofTree;
+pub use self::query_log::{QueryLevel, QueryLog, QueryStats, RepeatedQuery};
+pub use self::rewrite::GoalRewrite;
+pub use self::region_constraints::{RegionConstraintSet, RegionConstraintSink, RegionEquality,
+                                   RegionOutlives};
+pub use self::trace::{ProofTrace, SubgoalCost, SubgoalPlan, TraceDiff, TracedAnswer, TracedGoal};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 /// A (possible) solution for a proposed goal. Usually packaged in a `Result`,
 /// where `Err` represents definite *failure* to prove a goal.
 pub enum Solution {
@@ -19,11 +60,17 @@ pub enum Solution {
     /// The goal may be provable in multiple ways, but regardless we may have some guidance
     /// for type inference. In this case, we don't return any lifetime
     /// constraints, since we have not "committed" to any particular solution
-    /// yet.
-    Ambig(Guidance),
+    /// yet. We do say *why* the goal is ambiguous, though.
+    Ambig(Guidance, AmbiguityReason),
+
+    /// The goal holds in several ways, each of which was proven
+    /// unconditionally; only produced under
+    /// `AggregationPolicy::AllAnswers`. Each answer carries its own
+    /// lifetime constraints.
+    MultipleSolutions(Vec<Canonical<ConstrainedSubst>>),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 /// When a goal holds ambiguously (e.g., because there are multiple possible
 /// solutions), we issue a set of *guidance* back to type inference.
 pub enum Guidance {
@@ -41,6 +88,48 @@ pub enum Guidance {
     Unknown,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+/// Why a goal holds only ambiguously. When there are several reasons,
+/// the most significant one is reported; variants are ordered from
+/// least to most significant.
+pub enum AmbiguityReason {
+    /// The goal has more than one answer, giving different values to
+    /// its variables: more must be known about its types to tell which
+    /// holds. For example, `exists<T> { T: Clone }`.
+    InsufficientTypeInfo,
+
+    /// Like `InsufficientTypeInfo`, for a goal involving an associated
+    /// type that could not be resolved to a single type, e.g. because
+    /// its self type is unknown or several traits define it.
+    UnresolvedProjection,
+
+    /// The goal holds in more than one way -- say, through overlapping
+    /// impls -- with the same values for its variables but different
+    /// lifetime constraints, so we could not commit to any of them. Also
+    /// the reason given for `Solution::MultipleSolutions`.
+    MultipleCandidates,
+
+    /// The goal depends on a negative loop (e.g., `P :- not { P }`),
+    /// so that it is neither true nor false.
+    NegativeCycle,
+
+    /// The goal depends on a cycle mixing coinductive and inductive
+    /// goals, which `MixedCyclePolicy::Ambiguous` leaves undecided.
+    MixedCycle,
+
+    /// The goal depends on a negative goal that still contained
+    /// unresolved inference variables, so it could not be solved.
+    FlounderedNegation,
+
+    /// The goal, or a goal it depends on, exceeded the maximum size
+    /// and was truncated.
+    Overflow,
+
+    /// The solver ran out of budget before it could finish, so the
+    /// solution only reflects the answers found up to that point.
+    BudgetExceeded,
+}
+
 impl Solution {
     crate fn is_unique(&self) -> bool {
         match *self {
@@ -48,58 +137,883 @@ impl Solution {
             _ => false,
         }
     }
+
+    /// Weakens the solution found for a goal whose search was cut
+    /// short, so that it only claims what the answers found so far
+    /// support. No answers means we know nothing yet, rather than that
+    /// the goal cannot be proven; and a unique or definite result is
+    /// merely a suggestion, since later answers could contradict it.
+    crate fn cut_short(solution: Option<Solution>) -> Solution {
+        let guidance = match solution {
+            None => Guidance::Unknown,
+            Some(Solution::Unique(constrained)) => {
+                Guidance::Suggested(constrained.map(|cs| cs.subst))
+            }
+            Some(Solution::Ambig(Guidance::Definite(subst), _)) => Guidance::Suggested(subst),
+            Some(Solution::Ambig(guidance, _)) => guidance,
+            Some(Solution::MultipleSolutions(_)) => Guidance::Unknown,
+        };
+        Solution::Ambig(guidance, AmbiguityReason::BudgetExceeded)
+    }
+
+    /// Renders the solution like `Display` does, but with the binders
+    /// of the answer given names; see `Canonical::display_named`.
+    pub fn display_named(&self) -> String {
+        match self {
+            Solution::Unique(constrained) => format!("Unique; {}", constrained.display_named()),
+            Solution::Ambig(Guidance::Definite(subst), reason) => format!(
+                "Ambiguous; definite substitution {}; {}",
+                subst.display_named(),
+                reason
+            ),
+            Solution::Ambig(Guidance::Suggested(subst), reason) => format!(
+                "Ambiguous; suggested substitution {}; {}",
+                subst.display_named(),
+                reason
+            ),
+            Solution::Ambig(Guidance::Unknown, _) => self.to_string(),
+            Solution::MultipleSolutions(answers) => format!(
+                "Multiple; {}",
+                answers.iter().map(|answer| answer.display_named()).join("; ")
+            ),
+        }
+    }
+
+    /// Renders the solution like `Display` does, but with the
+    /// placeholders of the goal given the names they were written with
+    /// (see `Goal::into_peeled_goal_with_names`). Unlike
+    /// `display_named`, this leaves the answer's own binders alone.
+    pub fn display_with_names(&self, names: &PlaceholderNames) -> String {
+        let mut text = names.named(self).to_string();
+        if let Some(holes) = self.inferred_holes(names) {
+            let holes = holes.iter().map(|hole| format!("{:?}", names.named(hole))).join(", ");
+            text.push_str(&format!("; inferred holes [{}]", holes));
+        }
+        text
+    }
+
+    /// The values inferred for the type holes (`_`) of the goal, in the
+    /// order the holes appear (see `Goal::into_peeled_goal_with_holes`).
+    /// Returns `None` if the goal had no holes, or if the solution says
+    /// nothing about them: when it is ambiguous without guidance, or
+    /// consists of several answers.
+    pub fn inferred_holes(&self, names: &PlaceholderNames) -> Option<Vec<Parameter>> {
+        if names.holes().is_empty() {
+            return None;
+        }
+
+        let subst = match self {
+            Solution::Unique(constrained) => &constrained.value.subst,
+            Solution::Ambig(Guidance::Definite(subst), _)
+            | Solution::Ambig(Guidance::Suggested(subst), _) => &subst.value,
+            Solution::Ambig(Guidance::Unknown, _) | Solution::MultipleSolutions(_) => return None,
+        };
+        Some(names.holes().iter().map(|&hole| subst.parameters[hole].clone()).collect())
+    }
+
+    /// If the solution is ambiguous, returns the reason why.
+    pub fn ambiguity_reason(&self) -> Option<AmbiguityReason> {
+        match *self {
+            Solution::Unique(..) => None,
+            Solution::Ambig(_, reason) => Some(reason),
+            Solution::MultipleSolutions(_) => Some(AmbiguityReason::MultipleCandidates),
+        }
+    }
 }
 
 impl fmt::Display for Solution {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             Solution::Unique(constrained) => write!(f, "Unique; {}", constrained,),
-            Solution::Ambig(Guidance::Definite(subst)) => {
-                write!(f, "Ambiguous; definite substitution {}", subst)
+            Solution::Ambig(Guidance::Definite(subst), reason) => write!(
+                f,
+                "Ambiguous; definite substitution {}; {}",
+                subst, reason
+            ),
+            Solution::Ambig(Guidance::Suggested(subst), reason) => write!(
+                f,
+                "Ambiguous; suggested substitution {}; {}",
+                subst, reason
+            ),
+            Solution::Ambig(Guidance::Unknown, reason) => {
+                write!(f, "Ambiguous; no inference guidance; {}", reason)
+            }
+            Solution::MultipleSolutions(answers) => {
+                write!(f, "Multiple; {}", answers.iter().join("; "))
             }
-            Solution::Ambig(Guidance::Suggested(subst)) => {
-                write!(f, "Ambiguous; suggested substitution {}", subst)
+        }
+    }
+}
+
+impl fmt::Display for AmbiguityReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let description = match self {
+            AmbiguityReason::InsufficientTypeInfo => "insufficient type information",
+            AmbiguityReason::UnresolvedProjection => "unresolved associated type",
+            AmbiguityReason::MultipleCandidates => "multiple candidates",
+            AmbiguityReason::NegativeCycle => "negative cycle",
+            AmbiguityReason::MixedCycle => "mixed cycle",
+            AmbiguityReason::FlounderedNegation => "floundered negation",
+            AmbiguityReason::Overflow => "overflow",
+            AmbiguityReason::BudgetExceeded => "budget exceeded",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Limits how much work the solver may spend on a goal; see
+/// `SolveOptions::with_budget`.
+pub enum Budget {
+    /// Give up after the given number of quanta. A quantum is the
+    /// unit of work the solver does between checks of the budget, so
+    /// this is deterministic, but has no fixed relation to time.
+    Quanta(usize),
+
+    /// Give up once the given instant has passed.
+    Deadline(Instant),
+}
+
+impl Budget {
+    /// Returns a closure that is called after each quantum of work,
+    /// and returns false once the budget has been used up.
+    crate fn into_limit(self) -> impl FnMut() -> bool {
+        let mut quanta = 0;
+        move || match self {
+            Budget::Quanta(max_quanta) => {
+                quanta += 1;
+                quanta <= max_quanta
             }
-            Solution::Ambig(Guidance::Unknown) => write!(f, "Ambiguous; no inference guidance"),
+            Budget::Deadline(deadline) => Instant::now() < deadline,
+        }
+    }
+}
+
+/// Lets an embedder abort solving from outside, e.g. from another
+/// thread or once a deadline has passed; see
+/// `SolveOptions::with_cancellation`. Clones of a token share its
+/// state, so cancelling any of them cancels them all.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// A token that cancels itself once `deadline` has passed.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        CancellationToken {
+            cancelled: Arc::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// A token that cancels itself once `timeout` has elapsed from now.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        CancellationToken::with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self.deadline.map_or(false, |deadline| Instant::now() >= deadline)
+    }
+}
+
+/// How `SolverChoice::solve_root_goal` goes about solving a goal: when
+/// to give up, and what to report besides the solution (see
+/// `SolveOutcome`). The default solves the goal to completion and
+/// reports nothing else.
+#[derive(Clone, Debug, Default)]
+pub struct SolveOptions {
+    budget: Option<Budget>,
+    cancellation: Option<CancellationToken>,
+    overflow_as_error: bool,
+    cycles: bool,
+    limits_hit: bool,
+    proof: bool,
+    ambiguity_info: bool,
+    trace: bool,
+    hypotheses: bool,
+}
+
+impl SolveOptions {
+    /// Gives up once `budget` is used up. In that case, rather than
+    /// discarding the work done so far, the solution is ambiguous with
+    /// `AmbiguityReason::BudgetExceeded`, carrying whatever guidance
+    /// the answers found so far provide. The naive solver is bounded
+    /// by its `max_depth` alone, and ignores the budget.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Fails with `ErrorKind::Cancelled` if `token` is cancelled
+    /// before the solution is found. Unlike running out of budget, no
+    /// partial solution is returned. The SLG solver checks the token
+    /// each time it has to extend a table it depends on, and the naive
+    /// solver before each goal it selects.
+    pub fn with_cancellation(mut self, token: &CancellationToken) -> Self {
+        self.cancellation = Some(token.clone());
+        self
+    }
+
+    /// Fails with `ErrorKind::Overflow` rather than returning a
+    /// solution that is ambiguous because the goal, or some goal it
+    /// depends on, overflowed; for clients that would rather report
+    /// such goals as errors. See `SolverChoice::with_max_size` for
+    /// raising the limit.
+    pub fn with_overflow_as_error(mut self, enabled: bool) -> Self {
+        self.overflow_as_error = enabled;
+        self
+    }
+
+    /// Reports the cycles encountered while solving and how each was
+    /// treated; see `SolveOutcome::cycles`.
+    pub fn with_cycles(mut self, enabled: bool) -> Self {
+        self.cycles = enabled;
+        self
+    }
+
+    /// Reports the limits on the size of terms that were hit while
+    /// solving; see `SolveOutcome::limits_hit`.
+    pub fn with_limits_hit(mut self, enabled: bool) -> Self {
+        self.limits_hit = enabled;
+        self
+    }
+
+    /// Records how the solution was derived; see
+    /// `SolveOutcome::proof`. Only the SLG solver can record proofs.
+    pub fn with_proof(mut self, enabled: bool) -> Self {
+        self.proof = enabled;
+        self
+    }
+
+    /// Explains ambiguous solutions; see `SolveOutcome::ambiguity_info`.
+    /// Only the SLG solver records what it needs for this.
+    pub fn with_ambiguity_info(mut self, enabled: bool) -> Self {
+        self.ambiguity_info = enabled;
+        self
+    }
+
+    /// Records every subgoal considered; see `SolveOutcome::trace`.
+    pub fn with_trace(mut self, enabled: bool) -> Self {
+        self.trace = enabled;
+        self
+    }
+
+    /// Reports which hypotheses the solution depends on; see
+    /// `SolveOutcome::hypotheses`. This solves the goal once more per
+    /// hypothesis, so it is meant for diagnostics, like suggesting
+    /// minimal bounds.
+    pub fn with_hypotheses(mut self, enabled: bool) -> Self {
+        self.hypotheses = enabled;
+        self
+    }
+
+    /// A closure that returns false once the budget is used up or the
+    /// token is cancelled, for `Forest::solve_with_limit`.
+    fn should_continue<'a>(&'a self) -> impl FnMut() -> bool + 'a {
+        let mut budget = self.budget.map(Budget::into_limit);
+        move || {
+            budget.as_mut().map_or(true, |limit| limit()) && !self.is_cancelled()
         }
     }
+
+    /// True if the cancellation token, if any, has been cancelled.
+    fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().map_or(false, |token| token.is_cancelled())
+    }
+}
+
+/// The result of `SolverChoice::solve_root_goal`. Besides the solution,
+/// it holds whatever else was asked for in the `SolveOptions`; the
+/// rest is left empty.
+#[derive(Clone, Debug, Default)]
+pub struct SolveOutcome {
+    /// `None` if the goal cannot be proven; otherwise a solution,
+    /// which may reflect ambiguity and unknowns.
+    pub solution: Option<Solution>,
+
+    /// The cycles encountered while solving and how each was treated.
+    /// This is useful for understanding results that hinge on the
+    /// difference between inductive and coinductive goals. The naive
+    /// solver does not detect cycles, so it never reports any.
+    pub cycles: Vec<CycleReport>,
+
+    /// The limits on the size of terms that were hit while solving,
+    /// and for which subgoals, to help with tuning `max_size`. The
+    /// naive solver has no such limits: its `max_depth` shows up only
+    /// as `AmbiguityReason::Overflow`.
+    pub limits_hit: Vec<LimitReport>,
+
+    /// How the solution was derived -- which clauses were used, and
+    /// which answers to subgoals; `None` if the goal has no answers.
+    pub proof: Option<ProofTree>,
+
+    /// If the solution is ambiguous, which clauses competed to prove
+    /// which subgoals.
+    pub ambiguity_info: Option<AmbiguityInfo>,
+
+    /// Every subgoal the solver considered and the answers it found
+    /// for each. Comparing two traces with `ProofTrace::diff` shows
+    /// where two runs diverged. The naive solver has no tables, so its
+    /// trace only records the root goal.
+    pub trace: Option<ProofTrace>,
+
+    /// Which hypotheses in the environment of the goal -- e.g., the
+    /// `T: Clone` in a peeled `forall<T> { if (T: Clone) { ... } }` --
+    /// the solution depends on.
+    pub hypotheses: Option<HypothesisUsage>,
+}
+
+/// The order in which a subgoal tries the clauses that might prove
+/// it. Every matching clause is eventually tried, so this does not
+/// affect which goals are provable, but it does affect which answers
+/// are found first. That matters for performance -- e.g., once an
+/// unconditional answer is found, the remaining clauses are skipped
+/// (see `SolverChoice::SLG`) -- and for the guidance of solutions
+/// whose search was cut short.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ClauseOrder {
+    /// Try the hypotheses of the environment before the clauses of
+    /// the program. True by default, since hypotheses are usually
+    /// what the user expects a goal to be proven with.
+    pub environment_first: bool,
+
+    /// Try program clauses -- and hence impls -- in the reverse of
+    /// the order in which they were declared. False by default.
+    pub reverse_program_clauses: bool,
+}
+
+impl Default for ClauseOrder {
+    fn default() -> Self {
+        ClauseOrder {
+            environment_first: true,
+            reverse_program_clauses: false,
+        }
+    }
+}
+
+/// How the answers to a goal are combined into a `Solution` when there
+/// is more than one, or when the only one is ambiguous.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum AggregationPolicy {
+    /// Generalize the answers into guidance for type inference: e.g.,
+    /// answers `?0 := Vec<u32>` and `?0 := Vec<i32>` give the guidance
+    /// `?0 := Vec<?1>`. This is the default.
+    AntiUnify,
+
+    /// Suggest the first answer found as guidance, without looking at
+    /// the others. Which answer that is depends on `ClauseOrder`.
+    FirstAnswer,
+
+    /// Give back every answer, as `Solution::MultipleSolutions`. If
+    /// there are more than `max_answers`, or some answer is
+    /// ambiguous, this falls back to `AntiUnify`.
+    AllAnswers { max_answers: usize },
+}
+
+impl Default for AggregationPolicy {
+    fn default() -> Self {
+        AggregationPolicy::AntiUnify
+    }
+}
+
+/// Which of the pending subgoals of a clause the SLG solver works on
+/// next. Like `ClauseOrder`, this affects the work needed to solve a
+/// goal, and may affect which answers are found first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SubgoalOrder {
+    /// Always pick the last subgoal. This is the default.
+    Last,
+
+    /// Estimate the cost of each subgoal (see `SubgoalCost`) and pick
+    /// the cheapest one, preferring the last among equally cheap
+    /// ones. The choices made are recorded in `ProofTrace::plans`.
+    CheapestFirst,
+}
+
+impl Default for SubgoalOrder {
+    fn default() -> Self {
+        SubgoalOrder::Last
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A cycle encountered while solving a goal. `goal` is the subgoal at
+/// the head of the cycle -- the one that ended up (indirectly)
+/// depending on itself -- and `kind` says whether the cycle was
+/// accepted coinductively or treated inductively (contributing no
+/// answers).
+pub struct CycleReport {
+    pub goal: UCanonical<InEnvironment<Goal>>,
+    pub kind: CycleKind,
+}
+
+impl fmt::Display for CycleReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:?} cycle on {:?}", self.kind, self.goal.canonical.value.goal)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A limit on the size of terms that the SLG solver hit while solving
+/// `goal`, so that some of its answers are approximations (and any
+/// solution depending on them is ambiguous, with
+/// `AmbiguityReason::Overflow`). Raising `max_size` may help.
+pub struct LimitReport {
+    pub goal: UCanonical<InEnvironment<Goal>>,
+    pub kind: LimitKind,
+    pub max_size: usize,
+}
+
+impl LimitReport {
+    crate fn from_forest(
+        reports: &[chalk_engine::LimitReport<SlgContext>],
+        max_size: usize,
+    ) -> Vec<LimitReport> {
+        reports
+            .iter()
+            .map(|report| LimitReport {
+                goal: report.table_goal.clone(),
+                kind: report.kind,
+                max_size,
+            })
+            .collect()
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+impl fmt::Display for LimitReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let limit = match self.kind {
+            LimitKind::SubgoalSize => "subgoal",
+            LimitKind::AnswerSize => "answer",
+        };
+        write!(
+            f,
+            "{} size limit of {} hit while solving {:?}",
+            limit, self.max_size, self.goal.canonical.value.goal
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum SolverChoice {
-    /// Run the SLG solver, producing a Solution.
-    SLG { max_size: usize },
+    /// Run the SLG solver, producing a Solution. `mixed_cycles`
+    /// controls how cycles mixing coinductive and inductive goals
+    /// are resolved.
+    ///
+    /// If `erase_regions` is set, all lifetimes are considered equal:
+    /// no region constraints are generated, and lifetimes in the
+    /// solution are left unconstrained. This is much cheaper, and
+    /// suffices for clients (like IDE completion) that only care
+    /// whether a goal holds at the type level.
+    ///
+    /// `max_environment_clauses` bounds the number of hypotheses an
+    /// environment may accumulate through implication goals, and the
+    /// number that implied bounds may elaborate it to; see
+    /// `EnvironmentOverflow`.
+    ///
+    /// `trivial_answer_cut` enables an optimization that stops
+    /// searching for answers to a subgoal once it is known to hold
+    /// unconditionally. It should never change the solution, only
+    /// the work needed to find it; disabling it is meant for testing.
+    ///
+    /// `clause_order` controls which clauses are tried first when
+    /// proving a subgoal.
+    ///
+    /// `aggregation` controls how several answers to the root goal
+    /// are combined into a solution.
+    ///
+    /// `subgoal_order` controls which subgoal is worked on next when
+    /// a clause has several.
+    ///
+    /// If `implied_bounds` is set, the types mentioned by the
+    /// hypotheses of an implication goal are assumed to be well-formed,
+    /// as RFC 2089 proposes: assuming `Foo<T>: Bar` also assumes
+    /// `FromEnv(Foo<T>)`, and with it the where clauses of `struct Foo`.
+    SLG {
+        max_size: usize,
+        #[serde(with = "serialization::MixedCyclePolicyDef")]
+        mixed_cycles: MixedCyclePolicy,
+        erase_regions: bool,
+        max_environment_clauses: usize,
+        trivial_answer_cut: bool,
+        clause_order: ClauseOrder,
+        aggregation: AggregationPolicy,
+        subgoal_order: SubgoalOrder,
+        implied_bounds: bool,
+    },
+
+    /// Run the naive solver, which does plain SLD resolution without
+    /// tabling and gives up on a branch once it uses more than
+    /// `max_depth` nested clauses. It is meant for teaching, to be
+    /// compared against the SLG solver; see `NaiveSolver`.
+    /// `implied_bounds` is as for the SLG solver.
+    Naive { max_depth: usize, implied_bounds: bool },
 }
 
 impl SolverChoice {
     /// Attempts to solve the given root goal, which must be in
     /// canonical form. The solution is searching for unique answers
-    /// to any free existential variables in this goal.
+    /// to any free existential variables in this goal. `options` says
+    /// when to give up, and what to report besides the solution.
     ///
     /// # Returns
     ///
-    /// - `Ok(None)` is the goal cannot be proven.
-    /// - `Ok(Some(solution))` if we succeeded in finding *some* answers,
-    ///   although `solution` may reflect ambiguity and unknowns.
+    /// - `Ok(outcome)`, where `outcome.solution` is `None` if the goal
+    ///   cannot be proven, and otherwise *some* answers, although the
+    ///   solution may reflect ambiguity and unknowns.
     /// - `Err` if there was an internal error solving the goal, which does not
-    ///   reflect success nor failure.
+    ///   reflect success nor failure, or if `options` asked for
+    ///   something this solver cannot provide.
     pub fn solve_root_goal(
         self,
         env: &Arc<ProgramEnvironment>,
         canonical_goal: &UCanonical<InEnvironment<Goal>>,
-    ) -> ::errors::Result<Option<Solution>> {
-        use self::slg::implementation::SlgContext;
+        options: SolveOptions,
+    ) -> ::errors::Result<SolveOutcome> {
+        check_root_goal(canonical_goal);
+        let (mut outcome, cut_short) = match self {
+            SolverChoice::SLG { .. } => {
+                self.slg_context(env).solve_root_goal(&canonical_goal, &options)
+            }
+            SolverChoice::Naive { .. } => {
+                if options.proof {
+                    bail!(::errors::ErrorKind::NotSupportedByNaiveSolver("recording proofs"));
+                }
+                if options.ambiguity_info {
+                    bail!(::errors::ErrorKind::NotSupportedByNaiveSolver(
+                        "explaining ambiguity"
+                    ));
+                }
+                self.naive_solver(env).solve_with_options(&canonical_goal, &options)
+            }
+        };
+        if cut_short && options.is_cancelled() {
+            bail!(::errors::ErrorKind::Cancelled);
+        }
+        if cut_short {
+            outcome.solution = Some(Solution::cut_short(outcome.solution));
+        }
+        check_solution(canonical_goal, &outcome.solution);
 
-        match self {
-            SolverChoice::SLG { max_size } => {
-                Ok(SlgContext::new(env, max_size).solve_root_goal(&canonical_goal))
+        let reason = outcome.solution.as_ref().and_then(|solution| solution.ambiguity_reason());
+        if options.overflow_as_error && reason == Some(AmbiguityReason::Overflow) {
+            bail!(::errors::ErrorKind::Overflow(self.max_size()));
+        }
+        if options.hypotheses {
+            let mut solver = self.into_solver(env);
+            outcome.hypotheses = Some(HypothesisUsage::compute(
+                &mut solver,
+                canonical_goal,
+                &outcome.solution,
+            )?);
+        }
+        Ok(outcome)
+    }
+
+    /// For a goal that does not hold uniquely, like the peeled
+    /// `forall<T> { Vec<T>: Clone }`, looks for a minimal set of bounds
+    /// on its placeholders (here, `!1: Clone`) that would make it hold.
+    /// Returns `None` if assuming every bound we know to try does not
+    /// help, and an empty set if the goal already holds.
+    ///
+    /// Only bounds on traits without parameters besides `Self` are
+    /// tried.
+    pub fn infer_missing_bounds(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> ::errors::Result<Option<Vec<TraitRef>>> {
+        let mut solver = self.into_solver(env);
+        hypotheses::missing_bounds(&mut solver, env, canonical_goal)
+    }
+
+    /// Solves `goals` against two versions of a program and reports
+    /// those that got worse: goals that held before but cannot be
+    /// proven anymore, or that were unique but became ambiguous. This
+    /// is meant for checking whether removing or changing an impl is a
+    /// breaking change. Both programs have passed the coherence check
+    /// when they were lowered, so overlap is reported there, not here.
+    ///
+    /// Each goal is lowered against each program separately, and the
+    /// goals against one program share a single `Solver`. The solutions
+    /// against `before` that depend on no item the edit changed are
+    /// reused for `after`, as long as the edit kept the ids of the
+    /// items; see `GoalCache`.
+    pub fn goal_regressions(
+        self,
+        before: &Program,
+        after: &Program,
+        goals: &[::chalk_parse::ast::Goal],
+    ) -> ::errors::Result<Vec<GoalRegression>> {
+        compat::goal_regressions(self, before, after, goals)
+    }
+
+    /// Solves the goals of a goal file (see `chalk_parse::parse_goals`)
+    /// with the given names against `program`, in the order the names
+    /// are given; with no names, every goal in the file is solved.
+    /// Naming a goal that is not in the file is an error.
+    ///
+    /// The goals share a single `Solver`.
+    pub fn solve_named_goals(
+        self,
+        program: &Program,
+        goals: &[::chalk_parse::ast::NamedGoal],
+        names: &[&str],
+    ) -> ::errors::Result<Vec<Option<Solution>>> {
+        let selected = ::chalk_parse::select_goals(goals, names)?;
+        compat::solve_all(
+            self,
+            program,
+            selected.iter().map(|named| &*named.goal),
+            &GoalCache::new(),
+        )
+    }
+
+    /// Creates a `Solver` that can be used to solve many goals
+    /// against `env`, sharing work between them.
+    pub fn into_solver(self, env: &Arc<ProgramEnvironment>) -> Solver {
+        self.into_solver_with(env, None)
+    }
+
+    /// Like `into_solver`, but records every root goal and subgoal the
+    /// solver works on in `query_log`. Attaching the same log to
+    /// several solvers shows the work they duplicate; see `QueryLog`.
+    pub fn into_solver_with_query_log(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        query_log: &QueryLog,
+    ) -> Solver {
+        self.into_solver_with(env, Some(query_log.clone()))
+    }
+
+    /// Like `into_solver`, but looks up the solutions of root goals in
+    /// `goal_cache` before solving them, and adds those it finds.
+    /// Attaching the same cache to several solvers -- possibly on
+    /// different threads -- lets them share their solutions; see
+    /// `GoalCache`.
+    pub fn into_solver_with_goal_cache(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        goal_cache: &GoalCache,
+    ) -> Solver {
+        let mut solver = self.into_solver(env);
+        solver.goal_cache = Some(goal_cache.clone());
+        solver
+    }
+
+    /// The naive solver has no subgoal tables, so only root goals are
+    /// recorded in its query log.
+    fn into_solver_with(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        query_log: Option<QueryLog>,
+    ) -> Solver {
+        let (backend, environment_overflows) = match self {
+            SolverChoice::SLG { .. } => {
+                let mut context = self.slg_context(env);
+                if let Some(query_log) = &query_log {
+                    context = context.with_query_log(query_log.clone());
+                }
+                let environment_overflows = context.environment_overflows().clone();
+                (Backend::Slg(Forest::new(context)), environment_overflows)
+            }
+            SolverChoice::Naive { .. } => {
+                (Backend::Naive(self.naive_solver(env)), Default::default())
             }
+        };
+        Solver {
+            solver_choice: self,
+            program: env.clone(),
+            environment_overflows,
+            query_log,
+            backend,
+            results: AnswerStore::new(),
+            goal_cache: None,
+            rewrites: vec![],
+        }
+    }
+
+    /// The SLG context for these settings; must not be called for
+    /// `SolverChoice::Naive`.
+    fn slg_context(self, env: &Arc<ProgramEnvironment>) -> SlgContext {
+        match self {
+            SolverChoice::SLG {
+                max_size,
+                mixed_cycles,
+                erase_regions,
+                max_environment_clauses,
+                trivial_answer_cut,
+                clause_order,
+                aggregation,
+                subgoal_order,
+                implied_bounds,
+            } => SlgContext::new(env, max_size)
+                .with_mixed_cycle_policy(mixed_cycles)
+                .with_erased_regions(erase_regions)
+                .with_max_environment_clauses(max_environment_clauses)
+                .with_trivial_answer_cut(trivial_answer_cut)
+                .with_clause_order(clause_order)
+                .with_aggregation_policy(aggregation)
+                .with_subgoal_order(subgoal_order)
+                .with_implied_bounds(implied_bounds),
+            SolverChoice::Naive { .. } => panic!("the naive solver has no SLG context"),
+        }
+    }
+
+    /// The naive solver for these settings; must not be called for
+    /// `SolverChoice::SLG`.
+    fn naive_solver(self, env: &Arc<ProgramEnvironment>) -> NaiveSolver {
+        match self {
+            SolverChoice::Naive {
+                max_depth,
+                implied_bounds,
+            } => NaiveSolver::new(env, max_depth).with_implied_bounds(implied_bounds),
+            SolverChoice::SLG { .. } => panic!("the SLG solver has no naive solver"),
         }
     }
 
     /// Returns the default SLG parameters.
     pub fn slg() -> Self {
-        SolverChoice::SLG { max_size: 10 }
+        SolverChoice::SLG {
+            max_size: 10,
+            mixed_cycles: MixedCyclePolicy::default(),
+            erase_regions: false,
+            max_environment_clauses: 100,
+            trivial_answer_cut: true,
+            clause_order: ClauseOrder::default(),
+            aggregation: AggregationPolicy::default(),
+            subgoal_order: SubgoalOrder::default(),
+            implied_bounds: false,
+        }
+    }
+
+    /// Returns the default parameters for the naive solver.
+    pub fn naive() -> Self {
+        SolverChoice::Naive {
+            max_depth: 10,
+            implied_bounds: false,
+        }
+    }
+
+    /// These parameters with the limit past which goals overflow set
+    /// to `limit`: the `max_size` of the SLG solver, or the
+    /// `max_depth` of the naive solver.
+    pub fn with_max_size(mut self, limit: usize) -> Self {
+        match &mut self {
+            SolverChoice::SLG { max_size, .. } => *max_size = limit,
+            SolverChoice::Naive { max_depth, .. } => *max_depth = limit,
+        }
+        self
+    }
+
+    /// These parameters with `implied_bounds` set as given.
+    pub fn with_implied_bounds(mut self, enabled: bool) -> Self {
+        match &mut self {
+            SolverChoice::SLG { implied_bounds, .. }
+            | SolverChoice::Naive { implied_bounds, .. } => *implied_bounds = enabled,
+        }
+        self
+    }
+
+    // The remaining knobs only exist for the SLG solver; setting them
+    // on the parameters of the naive solver leaves those unchanged.
+
+    /// These parameters with `mixed_cycles` set as given.
+    pub fn with_mixed_cycles(mut self, policy: MixedCyclePolicy) -> Self {
+        if let SolverChoice::SLG { mixed_cycles, .. } = &mut self {
+            *mixed_cycles = policy;
+        }
+        self
+    }
+
+    /// These parameters with `erase_regions` set as given.
+    pub fn with_erase_regions(mut self, enabled: bool) -> Self {
+        if let SolverChoice::SLG { erase_regions, .. } = &mut self {
+            *erase_regions = enabled;
+        }
+        self
+    }
+
+    /// These parameters with `max_environment_clauses` set to `limit`.
+    pub fn with_max_environment_clauses(mut self, limit: usize) -> Self {
+        if let SolverChoice::SLG { max_environment_clauses, .. } = &mut self {
+            *max_environment_clauses = limit;
+        }
+        self
+    }
+
+    /// These parameters with `trivial_answer_cut` set as given.
+    pub fn with_trivial_answer_cut(mut self, enabled: bool) -> Self {
+        if let SolverChoice::SLG { trivial_answer_cut, .. } = &mut self {
+            *trivial_answer_cut = enabled;
+        }
+        self
+    }
+
+    /// These parameters with `clause_order` set as given.
+    pub fn with_clause_order(mut self, order: ClauseOrder) -> Self {
+        if let SolverChoice::SLG { clause_order, .. } = &mut self {
+            *clause_order = order;
+        }
+        self
+    }
+
+    /// These parameters with `aggregation` set as given.
+    pub fn with_aggregation(mut self, policy: AggregationPolicy) -> Self {
+        if let SolverChoice::SLG { aggregation, .. } = &mut self {
+            *aggregation = policy;
+        }
+        self
+    }
+
+    /// These parameters with `subgoal_order` set as given.
+    pub fn with_subgoal_order(mut self, order: SubgoalOrder) -> Self {
+        if let SolverChoice::SLG { subgoal_order, .. } = &mut self {
+            *subgoal_order = order;
+        }
+        self
+    }
+
+    /// The limit past which goals overflow; see `with_max_size`.
+    pub fn max_size(self) -> usize {
+        match self {
+            SolverChoice::SLG { max_size, .. } => max_size,
+            SolverChoice::Naive { max_depth, .. } => max_depth,
+        }
+    }
+}
+
+impl SolverChoice {
+    /// SLG parameters for a lenient "probing" mode, modeled after how
+    /// rustc probes for method candidates: the question is not whether
+    /// a goal definitely holds, but whether it might, and what types
+    /// it suggests. Compared to `slg()`:
+    ///
+    /// - regions are erased, so lifetimes never rule out a candidate;
+    /// - cycles mixing auto traits and other goals are ambiguous rather
+    ///   than rejected, like overflow (which is always ambiguous);
+    /// - when a goal has several answers -- as a projection that can be
+    ///   normalized does, since it also equals its placeholder -- the
+    ///   first one is suggested, rather than generalized away.
+    ///
+    /// Solutions found in this mode should be treated as "may hold":
+    /// anything but `None` keeps a candidate in the running.
+    pub fn method_probe() -> Self {
+        SolverChoice::slg()
+            .with_mixed_cycles(MixedCyclePolicy::Ambiguous)
+            .with_erase_regions(true)
+            .with_aggregation(AggregationPolicy::FirstAnswer)
     }
 }
 
@@ -108,3 +1022,399 @@ impl Default for SolverChoice {
         SolverChoice::slg()
     }
 }
+
+/// A solver that retains its work between queries. Both the final
+/// solutions for root goals and the tables for every subgoal
+/// encountered along the way are cached, so solving many goals that
+/// share subgoals -- e.g., the same implication under the same
+/// hypotheses -- only proves each of them once.
+///
+/// Everything is keyed by the u-canonical goal *together with* its
+/// environment, so an answer that was found using the hypotheses of
+/// one environment is never reused in another. Since `Environment`
+/// keeps its clauses sorted and deduplicated, environments that
+/// contain the same hypotheses share results regardless of the order
+/// in which those hypotheses were introduced.
+///
+/// Create one with `Solver::new`, or with `SolverChoice::into_solver`
+/// given the environment of a program.
+pub struct Solver {
+    solver_choice: SolverChoice,
+    program: Arc<ProgramEnvironment>,
+    environment_overflows: EnvironmentOverflowLog,
+    query_log: Option<QueryLog>,
+    backend: Backend,
+    results: AnswerStore<CachedSolution>,
+    goal_cache: Option<GoalCache>,
+    rewrites: Vec<GoalRewrite>,
+}
+
+/// The solution of a root goal, as cached by a `Solver`.
+#[derive(Clone, Debug)]
+struct CachedSolution {
+    goal: UCanonical<InEnvironment<Goal>>,
+    solution: Option<Solution>,
+}
+
+/// A goal has only one solution, so the first one cached for it is
+/// kept.
+impl StoredAnswer for CachedSolution {
+    type Key = UCanonical<InEnvironment<Goal>>;
+
+    fn key(&self) -> &Self::Key {
+        &self.goal
+    }
+
+    fn subsumes(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Checks a root goal for corruption, if enabled; see `invariants`.
+fn check_root_goal(canonical_goal: &UCanonical<InEnvironment<Goal>>) {
+    if invariants::enabled() {
+        let violations = invariants::u_canonical_violations(canonical_goal);
+        invariants::assert_none("canonicalization", canonical_goal, violations);
+    }
+}
+
+/// Checks the solution found for a root goal for corruption, if
+/// enabled; see `invariants`.
+fn check_solution(canonical_goal: &UCanonical<InEnvironment<Goal>>, result: &Option<Solution>) {
+    if invariants::enabled() {
+        if let Some(solution) = result {
+            let violations = invariants::solution_violations(canonical_goal, solution);
+            invariants::assert_none("solving", solution, violations);
+        }
+    }
+}
+
+enum Backend {
+    Slg(Forest<SlgContext, SlgContext>),
+    Naive(NaiveSolver),
+}
+
+impl Solver {
+    /// Creates a solver for the goals of `program`, to be reused for
+    /// as many of them as needed; see `SolverChoice::into_solver`.
+    pub fn new(program: &Arc<Program>, solver_choice: SolverChoice) -> Solver {
+        solver_choice.into_solver(&program.environment())
+    }
+
+    /// Attempts to solve the given root goal; see
+    /// `SolverChoice::solve_root_goal`.
+    pub fn solve(
+        &mut self,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> ::errors::Result<Option<Solution>> {
+        self.solve_with_options(canonical_goal, &SolveOptions::default())
+    }
+
+    /// Like `solve`, but gives up once `budget` is used up; see
+    /// `SolveOptions::with_budget`. Partial solutions are not cached,
+    /// but the work that went into them is, so trying again with a
+    /// fresh budget continues where this left off.
+    pub fn solve_with_budget(
+        &mut self,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+        budget: Budget,
+    ) -> ::errors::Result<Option<Solution>> {
+        let options = SolveOptions::default().with_budget(budget);
+        self.solve_with_options(canonical_goal, &options)
+    }
+
+    /// Like `solve`, but fails with `ErrorKind::Cancelled` if `token`
+    /// is cancelled first; see `SolveOptions::with_cancellation`. As
+    /// with `solve_with_budget`, the work done before cancelling is
+    /// kept.
+    pub fn solve_with_cancellation(
+        &mut self,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+        token: &CancellationToken,
+    ) -> ::errors::Result<Option<Solution>> {
+        let options = SolveOptions::default().with_cancellation(token);
+        self.solve_with_options(canonical_goal, &options)
+    }
+
+    /// The work shared by `solve` and its variants, which only differ
+    /// in the budget and cancellation token of `options`; the rest of
+    /// the options are ignored.
+    fn solve_with_options(
+        &mut self,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+        options: &SolveOptions,
+    ) -> ::errors::Result<Option<Solution>> {
+        let rewritten;
+        let canonical_goal = if self.rewrites.is_empty() {
+            canonical_goal
+        } else {
+            rewritten = self.rewrite(canonical_goal);
+            &rewritten
+        };
+        if let Some(result) = self.cached_solution(canonical_goal) {
+            return Ok(result);
+        }
+
+        check_root_goal(canonical_goal);
+        self.log_query(canonical_goal);
+        let (result, cut_short) = match &mut self.backend {
+            Backend::Slg(forest) => {
+                forest.solve_with_limit(canonical_goal, options.should_continue())
+            }
+            Backend::Naive(solver) => {
+                let (outcome, cancelled) = solver.solve_with_options(canonical_goal, options);
+                (outcome.solution, cancelled)
+            }
+        };
+        if cut_short && options.is_cancelled() {
+            bail!(::errors::ErrorKind::Cancelled);
+        }
+        check_solution(canonical_goal, &result);
+        self.check_query_repeats()?;
+        if cut_short {
+            return Ok(Some(Solution::cut_short(result)));
+        }
+
+        self.cache_solution(canonical_goal, &result);
+        Ok(result)
+    }
+
+    /// Enumerates the answers to the given root goal one by one, rather
+    /// than aggregating them into a solution as `solve` does -- e.g., to
+    /// find every type that implements a trait. Each answer is only
+    /// searched for once the one before it has been consumed, and at
+    /// most `max_answers` are returned, if given. Only the SLG solver
+    /// can enumerate answers.
+    pub fn solve_iter<'s>(
+        &'s mut self,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+        max_answers: Option<usize>,
+    ) -> ::errors::Result<impl Iterator<Item = EnumeratedAnswer> + 's> {
+        check_root_goal(canonical_goal);
+        self.log_query(canonical_goal);
+        let forest = match &mut self.backend {
+            Backend::Slg(forest) => forest,
+            Backend::Naive(_) => bail!(::errors::ErrorKind::NotSupportedByNaiveSolver(
+                "enumerating answers"
+            )),
+        };
+        Ok(forest
+            .answers(canonical_goal)
+            .take(max_answers.unwrap_or(::std::usize::MAX))
+            .map(|answer| EnumeratedAnswer {
+                subst: answer.subst,
+                ambiguous: answer.ambiguous,
+            }))
+    }
+
+    fn cached_solution(
+        &self,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Option<Option<Solution>> {
+        let cached = self.results
+            .lookup(canonical_goal)
+            .first()
+            .map(|cached| cached.solution.clone());
+        cached.or_else(|| {
+            self.goal_cache.as_ref().and_then(|goal_cache| {
+                goal_cache.get(self.program.program_hash(), self.solver_choice, canonical_goal)
+            })
+        })
+    }
+
+    fn cache_solution(
+        &mut self,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+        solution: &Option<Solution>,
+    ) {
+        self.results.insert(CachedSolution {
+            goal: canonical_goal.clone(),
+            solution: solution.clone(),
+        });
+        if let Some(goal_cache) = &self.goal_cache {
+            let dependencies = match &self.backend {
+                Backend::Slg(forest) => cache::dependencies(
+                    &self.program,
+                    forest.table_goals().map(|goal| &goal.canonical.value.goal),
+                ),
+                Backend::Naive(solver) => cache::dependencies(
+                    &self.program,
+                    solver.steps().iter().map(|step| &step.goal),
+                ),
+            };
+            goal_cache.insert(
+                self.program.program_hash(),
+                self.solver_choice,
+                canonical_goal,
+                solution,
+                dependencies,
+            );
+        }
+    }
+
+    /// Registers a rule that rewrites root goals before they are
+    /// solved; rules are tried in the order they were added. Only the
+    /// root goals given to this solver are rewritten -- the program
+    /// clauses used to prove them, and the hypotheses of implications,
+    /// are left as they were lowered. Solutions are cached for the
+    /// rewritten goals.
+    pub fn add_rewrite(&mut self, rewrite: GoalRewrite) {
+        self.rewrites.push(rewrite);
+    }
+
+    fn rewrite(
+        &self,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> UCanonical<InEnvironment<Goal>> {
+        let mut rewritten = canonical_goal.clone();
+        rewritten.canonical.value.goal =
+            rewrite::rewrite_goal(&self.rewrites, &canonical_goal.canonical.value.goal);
+        rewritten
+    }
+
+    fn log_query(&self, canonical_goal: &UCanonical<InEnvironment<Goal>>) {
+        if let Some(query_log) = &self.query_log {
+            query_log.record(QueryLevel::Root, canonical_goal);
+        }
+    }
+
+    /// Fails if the query log is in assertion mode and some query has
+    /// been solved too often.
+    fn check_query_repeats(&self) -> ::errors::Result<()> {
+        if let Some(query_log) = &self.query_log {
+            if let Some(query) = query_log.exceeded() {
+                let max_repeats = query_log.max_repeats().unwrap();
+                bail!(::errors::ErrorKind::RepeatedQuery(query, max_repeats));
+            }
+        }
+        Ok(())
+    }
+
+    /// The hypotheses that made some environment exceed the solver's
+    /// `max_environment_clauses` while solving goals so far. Each
+    /// overflow is reported once, even if it was hit repeatedly.
+    pub fn environment_overflows(&self) -> Vec<EnvironmentOverflow> {
+        self.environment_overflows.lock().unwrap().clone()
+    }
+
+    /// The limits on the size of terms hit while solving goals so far;
+    /// see `SolveOutcome::limits_hit`.
+    pub fn limits_hit(&self) -> Vec<LimitReport> {
+        match &self.backend {
+            Backend::Slg(forest) => {
+                let max_size = match self.solver_choice {
+                    SolverChoice::SLG { max_size, .. } => max_size,
+                    SolverChoice::Naive { .. } => unreachable!(),
+                };
+                LimitReport::from_forest(forest.limits(), max_size)
+            }
+            Backend::Naive(_) => vec![],
+        }
+    }
+
+    /// Statistics about the work done while solving goals so far. The
+    /// naive solver does none of the work counted there.
+    pub fn stats(&self) -> ForestStats {
+        match &self.backend {
+            Backend::Slg(forest) => forest.stats(),
+            Backend::Naive(_) => ForestStats::default(),
+        }
+    }
+
+    /// The ground trait implementations proven while solving goals so
+    /// far, including those proven for subgoals; see `FactSet`. The
+    /// naive solver keeps no tables, so only the unique solutions of
+    /// its root goals are considered.
+    pub fn proven_facts(&self) -> FactSet {
+        match &self.backend {
+            Backend::Slg(forest) => FactSet::from_answers(
+                forest
+                    .tables_and_answers()
+                    .into_iter()
+                    .flat_map(|(goal, answers)| {
+                        answers
+                            .into_iter()
+                            .map(move |answer| (goal.clone(), answer.subst, answer.ambiguous))
+                    }),
+            ),
+            Backend::Naive(_) => FactSet::from_answers(self.results.iter().filter_map(
+                |cached| match &cached.solution {
+                    Some(Solution::Unique(subst)) => {
+                        Some((cached.goal.clone(), subst.clone(), false))
+                    }
+                    _ => None,
+                },
+            )),
+        }
+    }
+
+    /// The number of root goals whose solutions have been cached.
+    pub fn num_cached_results(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Exports the solutions found so far, so that a later solver for
+    /// the same program can start from them (see `warm_start`).
+    pub fn export_cache(&self) -> SolverCache {
+        SolverCache {
+            solver_choice: self.solver_choice,
+            program_hash: self.program.program_hash(),
+            results: self.results
+                .iter()
+                .map(|cached| (cached.goal.clone(), cached.solution.clone()))
+                .collect(),
+        }
+    }
+
+    /// Seeds this solver with solutions exported by another solver,
+    /// so those goals are not proven again. The cache is only used if
+    /// it was produced with the same solver settings for a program
+    /// with the same hash; otherwise it is discarded. Returns whether
+    /// the cache was used.
+    pub fn warm_start(&mut self, cache: SolverCache) -> bool {
+        if cache.solver_choice != self.solver_choice
+            || cache.program_hash != self.program.program_hash()
+        {
+            return false;
+        }
+
+        for (goal, solution) in cache.results {
+            self.results.insert(CachedSolution { goal, solution });
+        }
+        true
+    }
+}
+
+/// One answer to a goal, as enumerated by `Solver::solve_iter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnumeratedAnswer {
+    /// The values of the variables of the goal for which it holds,
+    /// along with the region constraints under which it does.
+    pub subst: Canonical<ConstrainedSubst>,
+
+    /// Whether the answer could be neither proven nor disproven, e.g.
+    /// because it was truncated; see `SolverChoice::SLG`.
+    pub ambiguous: bool,
+}
+
+/// Solutions exported from a `Solver`, along with what is needed to
+/// check that they still apply; see `Solver::warm_start`. It can be
+/// serialized, so that a later process for the same program -- say, the
+/// next run of a tool -- can start from it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolverCache {
+    solver_choice: SolverChoice,
+    program_hash: u64,
+    results: Vec<(UCanonical<InEnvironment<Goal>>, Option<Solution>)>,
+}
+
+impl SolverCache {
+    /// The number of cached solutions.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
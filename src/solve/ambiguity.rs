@@ -0,0 +1,120 @@
+//! Telling apart why a goal with several answers is ambiguous: because
+//! too little is known about its types to pick one, or because the
+//! same goal genuinely holds in more than one way -- and, on request,
+//! which clauses compete to prove it.
+
+use fallible::*;
+use fold::{self, Fold, IdentityExistentialFolder, IdentityUniversalFolder, TypeFolder};
+use ir::*;
+use solve::{AmbiguityReason, ProofTree};
+use std::fmt;
+
+/// The reason that `goal` is ambiguous when it has several answers,
+/// each proven outright. `answers_differ` says whether any of those
+/// answers give different values to the variables of the goal.
+crate fn multiple_answers_reason(goal: &Goal, answers_differ: bool) -> AmbiguityReason {
+    if !answers_differ {
+        AmbiguityReason::MultipleCandidates
+    } else if mentions_projection(goal) {
+        AmbiguityReason::UnresolvedProjection
+    } else {
+        AmbiguityReason::InsufficientTypeInfo
+    }
+}
+
+/// Whether `goal` mentions an associated type, selected or not.
+fn mentions_projection(goal: &Goal) -> bool {
+    let mut finder = ProjectionFinder { found: false };
+    goal.fold_with(&mut finder, 0)
+        .expect("finding projections cannot fail");
+    finder.found
+}
+
+struct ProjectionFinder {
+    found: bool,
+}
+
+impl TypeFolder for ProjectionFinder {
+    fn fold_ty(&mut self, ty: &Ty, binders: usize) -> Fallible<Ty> {
+        match ty {
+            Ty::Projection(_) | Ty::UnselectedProjection(_) => self.found = true,
+            Ty::Var(_) | Ty::Apply(_) | Ty::ForAll(_) | Ty::Dyn(_) | Ty::Opaque(_) => {}
+        }
+        fold::super_fold_ty(self, ty, binders)
+    }
+
+    fn fold_lifetime(&mut self, lifetime: &Lifetime, binders: usize) -> Fallible<Lifetime> {
+        fold::super_fold_lifetime(self, lifetime, binders)
+    }
+
+    fn fold_const(&mut self, constant: &Const, binders: usize) -> Fallible<Const> {
+        fold::super_fold_const(self, constant, binders)
+    }
+}
+
+impl IdentityExistentialFolder for ProjectionFinder {}
+
+impl IdentityUniversalFolder for ProjectionFinder {}
+
+/// Why a goal is ambiguous, in more detail than `AmbiguityReason`:
+/// the subgoals that were proven in more than one way, and how. See
+/// `SolveOutcome::ambiguity_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmbiguityInfo {
+    pub reason: AmbiguityReason,
+
+    /// The subgoals with competing candidates, in the order they were
+    /// reached from the root goal. Empty if the ambiguity has nothing
+    /// to do with competing candidates, as for overflow.
+    pub subgoals: Vec<CompetingCandidates>,
+}
+
+/// A subgoal that more than one clause proved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompetingCandidates {
+    pub goal: UCanonical<InEnvironment<Goal>>,
+
+    /// Each clause that proved `goal`, with the first answer it gave.
+    pub candidates: Vec<(ProgramClause, Canonical<ConstrainedSubst>)>,
+}
+
+impl AmbiguityInfo {
+    /// Collects the competing candidates from the derivations of the
+    /// answers to an ambiguous goal.
+    crate fn from_proofs(reason: AmbiguityReason, proofs: &[ProofTree]) -> Self {
+        let mut subgoals: Vec<CompetingCandidates> = vec![];
+        let mut stack: Vec<&ProofTree> = proofs.iter().rev().collect();
+        while let Some(proof) = stack.pop() {
+            if let Some(clause) = &proof.clause {
+                let found = subgoals.iter().position(|subgoal| subgoal.goal == proof.goal);
+                let index = found.unwrap_or_else(|| {
+                    subgoals.push(CompetingCandidates {
+                        goal: proof.goal.clone(),
+                        candidates: vec![],
+                    });
+                    subgoals.len() - 1
+                });
+                let candidates = &mut subgoals[index].candidates;
+                if !candidates.iter().any(|(candidate, _)| candidate == clause) {
+                    candidates.push((clause.clone(), proof.answer.clone()));
+                }
+            }
+            stack.extend(proof.subproofs.iter().rev());
+        }
+        subgoals.retain(|subgoal| subgoal.candidates.len() > 1);
+        AmbiguityInfo { reason, subgoals }
+    }
+}
+
+impl fmt::Display for AmbiguityInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ambiguous: {}", self.reason)?;
+        for subgoal in &self.subgoals {
+            write!(f, "\n`{:?}` could be proven by any of:", subgoal.goal.canonical.value.goal)?;
+            for (clause, answer) in &subgoal.candidates {
+                write!(f, "\n  `{:?}`, giving {}", clause, answer.value.subst)?;
+            }
+        }
+        Ok(())
+    }
+}
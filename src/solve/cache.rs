@@ -0,0 +1,315 @@
+//! A cache of the solutions of root goals that outlives any one
+//! `Solver`. Each solver already caches the solutions it finds, but
+//! separate solvers -- say, one per thread, or one per request of an
+//! IDE -- share nothing, and keep proving the same goals over again.
+//! A `GoalCache` attached to all of them proves each goal only once.
+//!
+//! Each solution is stored with the items it may depend on, so that a
+//! client that edits its program incrementally can drop just the
+//! solutions an edit affects, and keep the others for the edited
+//! program; see `GoalCache::invalidate_item` and `GoalCache::carry_over`.
+
+use fallible::*;
+use fold::{self, Fold, IdentityExistentialFolder, IdentityUniversalFolder, TypeFolder};
+use ir::*;
+use solve::{Solution, SolverChoice};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Solutions of root goals, shared by all the solvers it is attached
+/// to; see `SolverChoice::into_solver_with_goal_cache`. Clones share
+/// the same cache, which can be used from several threads at once.
+///
+/// The solution of a goal depends on the solver settings and on the
+/// program, so entries are keyed by those as well, the program by its
+/// `program_hash`: solvers for different programs may share a cache,
+/// but not each other's solutions. To reuse solutions across an edit
+/// of the program, invalidate the items it touched, then carry the
+/// remaining solutions over to the edited program.
+#[derive(Clone, Debug, Default)]
+pub struct GoalCache {
+    data: Arc<Mutex<GoalCacheData>>,
+}
+
+#[derive(Debug, Default)]
+struct GoalCacheData {
+    entries: HashMap<GoalCacheKey, GoalCacheEntry>,
+
+    /// How many lookups found a solution.
+    hits: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct GoalCacheKey {
+    program_hash: u64,
+    solver_choice: SolverChoice,
+    goal: UCanonical<InEnvironment<Goal>>,
+}
+
+#[derive(Clone, Debug)]
+struct GoalCacheEntry {
+    solution: Option<Solution>,
+
+    /// The structs, traits, associated types and impls whose
+    /// definitions the solution may depend on; see `dependencies`.
+    dependencies: BTreeSet<ItemId>,
+}
+
+impl GoalCache {
+    pub fn new() -> Self {
+        GoalCache::default()
+    }
+
+    /// The number of cached solutions.
+    pub fn len(&self) -> usize {
+        self.data.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of times a solver found the solution it was after in
+    /// this cache, rather than proving the goal itself.
+    pub fn hits(&self) -> usize {
+        self.data.lock().unwrap().hits
+    }
+
+    /// Forgets every cached solution.
+    pub fn clear(&self) {
+        let mut data = self.data.lock().unwrap();
+        data.entries.clear();
+        data.hits = 0;
+    }
+
+    /// Forgets the solutions that may depend on the definition of
+    /// `item` -- a struct, trait, associated type or impl -- e.g.,
+    /// because it was edited. Adding or removing an impl affects the
+    /// goals of its trait, so invalidate the trait in that case.
+    /// Returns the number of solutions forgotten.
+    pub fn invalidate_item(&self, item: ItemId) -> usize {
+        let mut data = self.data.lock().unwrap();
+        let len = data.entries.len();
+        data.entries
+            .retain(|_, entry| !entry.dependencies.contains(&item));
+        len - data.entries.len()
+    }
+
+    /// Makes the solutions cached for the program `old` available to
+    /// solvers for `new`, an edited version of it. The solutions that
+    /// depend on the items the edit touched must have been forgotten
+    /// with `invalidate_item` first. Returns the number of solutions
+    /// carried over.
+    pub fn carry_over(&self, old: &ProgramEnvironment, new: &ProgramEnvironment) -> usize {
+        let (old_hash, new_hash) = (old.program_hash(), new.program_hash());
+        if old_hash == new_hash {
+            return 0;
+        }
+
+        let mut data = self.data.lock().unwrap();
+        let old_keys: Vec<_> = data.entries
+            .keys()
+            .filter(|key| key.program_hash == old_hash)
+            .cloned()
+            .collect();
+        for key in &old_keys {
+            let entry = data.entries.remove(key).unwrap();
+            let new_key = GoalCacheKey {
+                program_hash: new_hash,
+                ..key.clone()
+            };
+            data.entries.entry(new_key).or_insert(entry);
+        }
+        old_keys.len()
+    }
+
+    crate fn get(
+        &self,
+        program_hash: u64,
+        solver_choice: SolverChoice,
+        goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Option<Option<Solution>> {
+        let key = GoalCacheKey {
+            program_hash,
+            solver_choice,
+            goal: goal.clone(),
+        };
+        let mut data = self.data.lock().unwrap();
+        let solution = data.entries.get(&key).map(|entry| entry.solution.clone());
+        if solution.is_some() {
+            data.hits += 1;
+        }
+        solution
+    }
+
+    crate fn insert(
+        &self,
+        program_hash: u64,
+        solver_choice: SolverChoice,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        solution: &Option<Solution>,
+        dependencies: BTreeSet<ItemId>,
+    ) {
+        let key = GoalCacheKey {
+            program_hash,
+            solver_choice,
+            goal: goal.clone(),
+        };
+        self.data
+            .lock()
+            .unwrap()
+            .entries
+            .entry(key)
+            .or_insert_with(|| GoalCacheEntry {
+                solution: solution.clone(),
+                dependencies,
+            });
+    }
+}
+
+/// The items whose definitions the solutions of `goals` may depend on,
+/// where `goals` are every goal the solver looked at while solving:
+/// the items they mention, the traits of the associated types they
+/// mention, and the impls of all those traits. This errs on the side
+/// of including too much.
+crate fn dependencies<'g>(
+    env: &ProgramEnvironment,
+    goals: impl IntoIterator<Item = &'g Goal>,
+) -> BTreeSet<ItemId> {
+    let mut collector = ItemCollector {
+        env,
+        items: BTreeSet::new(),
+    };
+    for goal in goals {
+        collector.add_goal(goal);
+        goal.fold_with(&mut collector, 0)
+            .expect("collecting items cannot fail");
+    }
+    collector.items
+}
+
+struct ItemCollector<'e> {
+    env: &'e ProgramEnvironment,
+    items: BTreeSet<ItemId>,
+}
+
+impl<'e> ItemCollector<'e> {
+    fn add_trait(&mut self, trait_id: ItemId) {
+        if self.items.insert(trait_id) {
+            if let Some(impls) = self.env.trait_impls.get(&trait_id) {
+                self.items.extend(impls.iter().cloned());
+            }
+        }
+    }
+
+    fn add_associated_ty(&mut self, associated_ty_id: ItemId) {
+        if self.items.insert(associated_ty_id) {
+            if let Some(datum) = self.env.associated_ty_data.get(&associated_ty_id) {
+                self.add_trait(datum.trait_id);
+            }
+        }
+    }
+
+    fn add_associated_const(&mut self, associated_const_id: ItemId) {
+        if self.items.insert(associated_const_id) {
+            if let Some(datum) = self.env.associated_const_data.get(&associated_const_id) {
+                self.add_trait(datum.trait_id);
+            }
+        }
+    }
+
+    /// Adds the traits and associated types that `goal` is about. The
+    /// types it mentions are added by folding it.
+    fn add_goal(&mut self, goal: &Goal) {
+        match goal {
+            Goal::Quantified(_, subgoal) => self.add_goal(&subgoal.value),
+            Goal::Implies(_, subgoal) | Goal::Not(subgoal) => self.add_goal(subgoal),
+            Goal::And(a, b) => {
+                self.add_goal(a);
+                self.add_goal(b);
+            }
+            Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => self.add_domain_goal(domain_goal),
+            Goal::Leaf(LeafGoal::EqGoal(_)) | Goal::CannotProve(()) => {}
+        }
+    }
+
+    fn add_domain_goal(&mut self, domain_goal: &DomainGoal) {
+        match domain_goal {
+            DomainGoal::Holds(atom) | DomainGoal::WellFormed(atom) | DomainGoal::FromEnv(atom) => {
+                match atom {
+                    WhereClauseAtom::Implemented(trait_ref) => self.add_trait(trait_ref.trait_id),
+                    WhereClauseAtom::ProjectionEq(projection_eq) => {
+                        self.add_associated_ty(projection_eq.projection.associated_ty_id)
+                    }
+                }
+            }
+            DomainGoal::NotImplemented(trait_ref) => self.add_trait(trait_ref.trait_id),
+            DomainGoal::Normalize(normalize) => {
+                self.add_associated_ty(normalize.projection.associated_ty_id)
+            }
+            DomainGoal::NormalizeConst(normalize) => {
+                self.add_associated_const(normalize.projection.associated_const_id)
+            }
+            DomainGoal::InScope(trait_id) => self.add_trait(*trait_id),
+            DomainGoal::OpaqueEq(opaque_eq) => {
+                self.items.insert(opaque_eq.opaque.opaque_ty_id);
+            }
+            DomainGoal::Predicate(predicate) => {
+                self.items.insert(predicate.predicate_id);
+            }
+            DomainGoal::UnselectedNormalize(_)
+            | DomainGoal::WellFormedTy(_)
+            | DomainGoal::FromEnvTy(_)
+            | DomainGoal::Derefs(_)
+            | DomainGoal::Outlives(_)
+            | DomainGoal::Reveal(_)
+            | DomainGoal::IsFullyVisible(_) => {}
+        }
+    }
+}
+
+impl<'e> TypeFolder for ItemCollector<'e> {
+    fn fold_ty(&mut self, ty: &Ty, binders: usize) -> Fallible<Ty> {
+        match ty {
+            Ty::Apply(application) => match application.name {
+                TypeName::ItemId(item_id) => {
+                    self.items.insert(item_id);
+                }
+                TypeName::AssociatedType(associated_ty_id) => {
+                    self.add_associated_ty(associated_ty_id)
+                }
+                TypeName::ForAll(_)
+                | TypeName::Tuple(_)
+                | TypeName::FnPtr(_)
+                | TypeName::Slice
+                | TypeName::Array(_)
+                | TypeName::Str
+                | TypeName::Scalar(_)
+                | TypeName::Never
+                | TypeName::Ref(_)
+                | TypeName::RawPtr(_) => {}
+            },
+            Ty::Projection(projection) => self.add_associated_ty(projection.associated_ty_id),
+            Ty::Opaque(opaque) => {
+                self.items.insert(opaque.opaque_ty_id);
+            }
+            Ty::Dyn(dyn_ty) => for bound in &dyn_ty.bounds {
+                self.add_trait(bound.trait_id);
+            },
+            Ty::Var(_) | Ty::UnselectedProjection(_) | Ty::ForAll(_) => {}
+        }
+        fold::super_fold_ty(self, ty, binders)
+    }
+
+    fn fold_lifetime(&mut self, lifetime: &Lifetime, binders: usize) -> Fallible<Lifetime> {
+        fold::super_fold_lifetime(self, lifetime, binders)
+    }
+
+    fn fold_const(&mut self, constant: &Const, binders: usize) -> Fallible<Const> {
+        fold::super_fold_const(self, constant, binders)
+    }
+}
+
+impl<'e> IdentityExistentialFolder for ItemCollector<'e> {}
+
+impl<'e> IdentityUniversalFolder for ItemCollector<'e> {}
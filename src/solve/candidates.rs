@@ -0,0 +1,155 @@
+//! Enumerating the impls that might prove a trait goal, together with
+//! what would remain to be proven if each were selected. Nothing is
+//! solved to find them: an impl is a candidate as soon as its header
+//! unifies with the goal, which is what tooling explaining why an impl
+//! does (or does not) apply needs to start from. Solving what remains
+//! tells which candidates the goal can actually be proven through.
+
+use cast::Cast;
+use errors::*;
+use ir::*;
+use itertools::Itertools;
+use solve::SolverChoice;
+use solve::infer::InferenceTable;
+use solve::infer::unify::UnificationResult;
+
+/// An impl whose header unifies with a trait goal; see
+/// `Program::impl_candidates`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImplCandidate {
+    pub impl_id: ItemId,
+
+    /// What selecting the impl would mean for the goal. The binders
+    /// are the variables of the goal that the impl header leaves
+    /// unconstrained, along with those of the impl that do not appear
+    /// in its header.
+    pub residual: Canonical<ImplResidual>,
+}
+
+/// The residual obligations of an `ImplCandidate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImplResidual {
+    /// The value of each variable of the goal, once unified with the
+    /// impl header.
+    pub subst: Substitution,
+
+    /// The where clauses of the impl that would still have to be
+    /// proven, along with any goals that arose from unifying the
+    /// header, such as normalizing a projection.
+    pub obligations: Vec<InEnvironment<Goal>>,
+
+    /// The region constraints under which the header unifies.
+    pub constraints: Vec<InEnvironment<Constraint>>,
+}
+
+impl Program {
+    /// Returns the impls that might prove `goal`, which must be a
+    /// trait goal like `exists<T> { Vec<T>: Clone }`, in the order the
+    /// impls were declared. The where clauses of each impl are not
+    /// solved, but returned as part of its residual obligations.
+    /// Negative impls never prove such a goal, so they are not
+    /// candidates.
+    pub fn impl_candidates(
+        &self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Result<Vec<ImplCandidate>> {
+        let (mut infer, goal_subst, goal) =
+            InferenceTable::from_canonical(goal.universes, &goal.canonical);
+        let InEnvironment { environment, goal } = goal;
+        let trait_ref = match goal {
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(WhereClauseAtom::Implemented(
+                trait_ref,
+            )))) => trait_ref,
+            _ => bail!(ErrorKind::NotTraitGoal),
+        };
+
+        let mut candidates = vec![];
+        for (&impl_id, impl_datum) in &self.impl_data {
+            let bound = &impl_datum.binders.value;
+            if !bound.trait_ref.is_positive()
+                || bound.trait_ref.trait_ref().trait_id != trait_ref.trait_id
+            {
+                continue;
+            }
+
+            let snapshot = infer.snapshot();
+            let (impl_trait_ref, where_clauses) = infer.instantiate_binders_existentially(&(
+                &impl_datum.binders.binders,
+                &(bound.trait_ref.trait_ref().clone(), bound.where_clauses.clone()),
+            ));
+            if let Ok(UnificationResult { goals, constraints }) =
+                infer.unify(&environment, &trait_ref, &impl_trait_ref)
+            {
+                let obligations: Vec<InEnvironment<Goal>> = where_clauses
+                    .into_iter()
+                    .map(|where_clause| InEnvironment::new(&environment, where_clause.cast()))
+                    .chain(goals.into_iter().map(|goal| goal.map(|goal| goal.cast())))
+                    .collect();
+                let Canonical { binders, value } = infer
+                    .canonicalize(&(goal_subst.clone(), obligations, constraints))
+                    .quantified;
+                let (subst, obligations, constraints) = value;
+                candidates.push(ImplCandidate {
+                    impl_id,
+                    residual: Canonical {
+                        binders,
+                        value: ImplResidual {
+                            subst,
+                            obligations,
+                            constraints,
+                        },
+                    },
+                });
+            }
+            infer.rollback_to(snapshot);
+        }
+        Ok(candidates)
+    }
+
+    /// Returns the impls through which `goal`, a trait goal, can be
+    /// proven: the candidates (see `impl_candidates`) whose residual
+    /// obligations might hold. Those obligations are solved with
+    /// `solver_choice`, so an impl counts even if its where clauses
+    /// only hold ambiguously. A goal proven from its environment
+    /// alone uses no impl.
+    ///
+    /// When several impls give the same answer, all of them are
+    /// returned, so this pins down the reasoning behind a solution
+    /// rather than just the solution itself.
+    pub fn proving_impls(
+        &self,
+        solver_choice: SolverChoice,
+        goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Result<Vec<ItemId>> {
+        let candidates = self.impl_candidates(goal)?;
+        let mut solver = solver_choice.into_solver(&self.environment());
+        let environment = &goal.canonical.value.environment;
+
+        let mut impls = vec![];
+        for candidate in candidates {
+            let Canonical { binders, value } = candidate.residual;
+            let obligations = value
+                .obligations
+                .into_iter()
+                .map(|obligation| obligation.goal)
+                .fold1(|a, b| Goal::And(Box::new(a), Box::new(b)));
+            let holds = match obligations {
+                None => true,
+                Some(obligations) => {
+                    let obligations = UCanonical {
+                        canonical: Canonical {
+                            binders,
+                            value: InEnvironment::new(environment, obligations),
+                        },
+                        universes: goal.universes,
+                    };
+                    solver.solve(&obligations)?.is_some()
+                }
+            };
+            if holds {
+                impls.push(candidate.impl_id);
+            }
+        }
+        Ok(impls)
+    }
+}
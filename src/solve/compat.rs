@@ -0,0 +1,194 @@
+//! Checking whether an edit to a program -- typically removing or
+//! changing an impl -- breaks goals that downstream code relies on.
+//! This is what a semver checker would ask of the solver: given the
+//! goals that held against the old version of a crate, which of them
+//! no longer hold against the new one?
+//!
+//! The goals are solved against both versions, sharing a `GoalCache`:
+//! the solutions found for the old version that do not depend on any
+//! item the edit changed are reused for the new one, so only the goals
+//! the edit may affect are solved again.
+
+use chalk_parse::ast;
+use ir::lowering::LowerGoal;
+use ir::{self, ItemId, Program};
+use solve::{GoalCache, Solution, SolverChoice};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// How a goal got worse between two versions of a program.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RegressionKind {
+    /// The goal held (possibly ambiguously) before, but cannot be
+    /// proven anymore.
+    Unprovable,
+
+    /// The goal had a unique solution before, but is now ambiguous.
+    Ambiguous,
+}
+
+/// A goal that held against the old version of a program but got
+/// worse against the new one; see `SolverChoice::goal_regressions`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GoalRegression {
+    /// The index of the goal in the list that was checked.
+    pub goal_index: usize,
+
+    pub kind: RegressionKind,
+
+    /// The solution against the old version of the program.
+    pub before: Solution,
+
+    /// The solution against the new version of the program.
+    pub after: Option<Solution>,
+}
+
+impl RegressionKind {
+    /// Classifies the change from `before` to `after`, if it is a
+    /// regression. Goals that did not hold before cannot regress.
+    fn of(before: &Option<Solution>, after: &Option<Solution>) -> Option<Self> {
+        match (before, after) {
+            (Some(_), None) => Some(RegressionKind::Unprovable),
+            (Some(Solution::Unique(_)), Some(after)) if !after.is_unique() => {
+                Some(RegressionKind::Ambiguous)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Solves each of `goals` against `program` with a single solver, so
+/// that work is shared between them. Goals are lowered against the
+/// program itself, since item ids need not agree between versions.
+/// Solutions are looked up in and added to `goal_cache`.
+crate fn solve_all<'g>(
+    solver_choice: SolverChoice,
+    program: &Program,
+    goals: impl IntoIterator<Item = &'g ast::Goal>,
+    goal_cache: &GoalCache,
+) -> ::errors::Result<Vec<Option<Solution>>> {
+    let program = Arc::new(program.clone());
+    let env = program.environment();
+    ir::tls::set_current_program(&program, || {
+        let mut solver = solver_choice.into_solver_with_goal_cache(&env, goal_cache);
+        goals
+            .into_iter()
+            .map(|goal| {
+                let goal = goal.lower(&*program)?.into_peeled_goal();
+                solver.solve(&goal)
+            })
+            .collect()
+    })
+}
+
+crate fn goal_regressions(
+    solver_choice: SolverChoice,
+    before: &Program,
+    after: &Program,
+    goals: &[ast::Goal],
+) -> ::errors::Result<Vec<GoalRegression>> {
+    goal_regressions_with_cache(solver_choice, before, after, goals, &GoalCache::new())
+}
+
+/// Like `goal_regressions`, with the solutions for both versions going
+/// through `goal_cache`.
+crate fn goal_regressions_with_cache(
+    solver_choice: SolverChoice,
+    before: &Program,
+    after: &Program,
+    goals: &[ast::Goal],
+    goal_cache: &GoalCache,
+) -> ::errors::Result<Vec<GoalRegression>> {
+    // Nothing the solver can see has changed, so nothing can regress.
+    let (before_env, after_env) = (before.environment(), after.environment());
+    if before_env.program_hash() == after_env.program_hash() {
+        return Ok(vec![]);
+    }
+
+    let before_solutions = solve_all(solver_choice, before, goals, goal_cache)?;
+    if let Some(changed_items) = changed_items(before, after) {
+        for item in changed_items {
+            goal_cache.invalidate_item(item);
+        }
+        goal_cache.carry_over(&before_env, &after_env);
+    }
+    let after_solutions = solve_all(solver_choice, after, goals, goal_cache)?;
+
+    Ok(before_solutions
+        .into_iter()
+        .zip(after_solutions)
+        .enumerate()
+        .filter_map(|(goal_index, (before, after))| {
+            let kind = RegressionKind::of(&before, &after)?;
+            Some(GoalRegression {
+                goal_index,
+                kind,
+                before: before.unwrap(),
+                after,
+            })
+        })
+        .collect())
+}
+
+/// The items of `before` whose definitions differ in `after`, along
+/// with the traits of the impls that were added, removed or changed,
+/// since those affect every goal about the trait. Returns `None` if
+/// the two programs cannot be compared item by item: when items were
+/// added, removed or reordered, so that their ids no longer agree, or
+/// when something that belongs to no item changed, like a custom
+/// clause.
+fn changed_items(before: &Program, after: &Program) -> Option<BTreeSet<ItemId>> {
+    let comparable = before.type_ids == after.type_ids
+        && before.trait_scopes == after.trait_scopes
+        && before.custom_clauses == after.custom_clauses
+        && before.default_impl_data == after.default_impl_data
+        && before.lang_items == after.lang_items
+        && before.builtin_traits == after.builtin_traits;
+    if !comparable {
+        return None;
+    }
+
+    let mut changed = BTreeSet::new();
+    changed_keys(&before.type_kinds, &after.type_kinds, &mut changed);
+    changed_keys(&before.parameter_defaults, &after.parameter_defaults, &mut changed);
+    changed_keys(&before.adt_data, &after.adt_data, &mut changed);
+    changed_keys(&before.trait_data, &after.trait_data, &mut changed);
+    changed_keys(&before.associated_ty_data, &after.associated_ty_data, &mut changed);
+    changed_keys(&before.associated_const_data, &after.associated_const_data, &mut changed);
+    changed_keys(&before.inherent_impl_data, &after.inherent_impl_data, &mut changed);
+    changed_keys(&before.opaque_ty_data, &after.opaque_ty_data, &mut changed);
+    changed_keys(&before.closure_data, &after.closure_data, &mut changed);
+    changed_keys(&before.item_attributes, &after.item_attributes, &mut changed);
+    changed_keys(&before.trait_overrides, &after.trait_overrides, &mut changed);
+
+    let mut changed_impls = BTreeSet::new();
+    changed_keys(&before.impl_data, &after.impl_data, &mut changed_impls);
+    for impl_id in changed_impls {
+        for program in &[before, after] {
+            if let Some(impl_datum) = program.impl_data.get(&impl_id) {
+                changed.insert(impl_datum.binders.value.trait_ref.trait_ref().trait_id);
+            }
+        }
+        changed.insert(impl_id);
+    }
+    Some(changed)
+}
+
+/// Adds to `changed` the keys whose values differ between `before` and
+/// `after`, including those present in only one of them.
+fn changed_keys<V: PartialEq>(
+    before: &BTreeMap<ItemId, V>,
+    after: &BTreeMap<ItemId, V>,
+    changed: &mut BTreeSet<ItemId>,
+) {
+    for (id, value) in before {
+        if after.get(id) != Some(value) {
+            changed.insert(*id);
+        }
+    }
+    for id in after.keys() {
+        if !before.contains_key(id) {
+            changed.insert(*id);
+        }
+    }
+}
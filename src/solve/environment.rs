@@ -0,0 +1,190 @@
+//! Limits on the hypotheses that goals may add to their environment.
+//!
+//! An implication goal `if (WC) { G }` proves `G` in an environment
+//! extended with `WC`. When such goals nest recursively -- say,
+//! through a clause whose body assumes a where-clause about a larger
+//! type -- environments can grow without bound, and since tables are
+//! keyed by environment, each of them is solved from scratch. We
+//! therefore cap the size of environments and, instead of adding
+//! hypotheses beyond the cap, treat the goal as one we cannot prove.
+//! The same cap applies to the hypotheses that implied bounds
+//! elaborate from an environment when a goal looks them up.
+//!
+//! Environments built while solving are kept in an `EnvironmentDag`,
+//! so that all the subgoals assuming the same hypotheses share a
+//! single environment, however those hypotheses were reached.
+
+use ir::*;
+use rules;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Records that assuming `hypothesis` would have grown `environment`
+/// beyond `limit` clauses, so the goal depending on it was reported
+/// as ambiguous instead.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EnvironmentOverflow {
+    pub hypothesis: ProgramClause,
+    pub environment: Arc<Environment>,
+    pub limit: usize,
+    pub kind: EnvironmentOverflowKind,
+}
+
+/// How a hypothesis made an environment overflow.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EnvironmentOverflowKind {
+    /// An implication goal assumed the hypothesis.
+    Assumption,
+
+    /// The hypothesis was already in the environment, but the bounds
+    /// it implies pushed the elaborated environment over the limit;
+    /// see `rules::environment_clauses`.
+    Elaboration,
+}
+
+/// The overflows hit while solving, shared between a `SlgContext`,
+/// the inference tables it creates, and the `Solver` that owns it.
+crate type EnvironmentOverflowLog = Arc<Mutex<Vec<EnvironmentOverflow>>>;
+
+/// The environments built while solving, shared like an
+/// `EnvironmentOverflowLog`.
+crate type SharedEnvironments = Arc<Mutex<EnvironmentDag>>;
+
+/// A DAG whose nodes are environments and whose edges lead from an
+/// environment to the ones obtained by adding hypotheses to it. Equal
+/// environments are stored once, so an edge may lead to a node that
+/// was first reached along another path.
+#[derive(Debug, Default)]
+crate struct EnvironmentDag {
+    nodes: HashSet<Arc<Environment>>,
+
+    /// Keyed by the address of the source node, which `nodes` keeps
+    /// alive, and the hypotheses added to it.
+    edges: HashMap<(usize, Vec<ProgramClause>), Arc<Environment>>,
+}
+
+impl EnvironmentDag {
+    /// The node equal to `env`, adding it if there is none.
+    fn node(&mut self, env: &Arc<Environment>) -> Arc<Environment> {
+        if let Some(node) = self.nodes.get(env) {
+            return node.clone();
+        }
+        self.nodes.insert(env.clone());
+        env.clone()
+    }
+
+    /// The node for `env` extended with `clauses`; see
+    /// `Environment::add_clauses`.
+    fn extend(&mut self, env: &Arc<Environment>, clauses: &[ProgramClause]) -> Arc<Environment> {
+        let source = self.node(env);
+        let key = (&*source as *const Environment as usize, clauses.to_vec());
+        if let Some(target) = self.edges.get(&key) {
+            return target.clone();
+        }
+
+        let target = Environment::add_clauses(&source, clauses.iter().cloned());
+        let target = self.node(&target);
+        self.edges.insert(key, target.clone());
+        target
+    }
+}
+
+impl Environment {
+    /// Like `add_clauses`, but returns `None` if the result would have
+    /// more than `limit` clauses, recording the first hypothesis that
+    /// was new to `env` in `overflows`. An environment that is already
+    /// over the limit may still be used if `clauses` adds nothing to it.
+    crate fn add_clauses_within_limit(
+        env: &Arc<Environment>,
+        clauses: Vec<ProgramClause>,
+        limit: usize,
+        environments: &SharedEnvironments,
+        overflows: &EnvironmentOverflowLog,
+    ) -> Option<Arc<Environment>> {
+        let new_environment = environments.lock().unwrap().extend(env, &clauses);
+        if new_environment.clauses.len() <= limit
+            || new_environment.clauses.len() == env.clauses.len()
+        {
+            return Some(new_environment);
+        }
+
+        let hypothesis = clauses
+            .into_iter()
+            .find(|clause| env.clauses.binary_search(clause).is_err())?;
+        record_overflow(
+            overflows,
+            EnvironmentOverflow {
+                hypothesis,
+                environment: env.clone(),
+                limit,
+                kind: EnvironmentOverflowKind::Assumption,
+            },
+        );
+        None
+    }
+
+    /// Like `rules::environment_clauses`, but returns `None` if
+    /// elaboration yields more than `limit` clauses, recording in
+    /// `overflows` the hypothesis whose implied bounds crossed it.
+    crate fn elaborated_clauses_within_limit(
+        env: &Arc<Environment>,
+        implied_bounds: bool,
+        limit: usize,
+        overflows: &EnvironmentOverflowLog,
+    ) -> Option<Vec<ProgramClause>> {
+        if !implied_bounds {
+            return Some(env.clauses.clone());
+        }
+
+        let mut clauses = env.clauses.clone();
+        let mut seen: HashSet<_> = env.clauses.iter().cloned().collect();
+        for hypothesis in &env.clauses {
+            let implied = rules::implied_hypotheses(hypothesis);
+            clauses.extend(implied.into_iter().filter(|clause| seen.insert(clause.clone())));
+            if clauses.len() > limit && clauses.len() > env.clauses.len() {
+                record_overflow(
+                    overflows,
+                    EnvironmentOverflow {
+                        hypothesis: hypothesis.clone(),
+                        environment: env.clone(),
+                        limit,
+                        kind: EnvironmentOverflowKind::Elaboration,
+                    },
+                );
+                return None;
+            }
+        }
+        Some(clauses)
+    }
+}
+
+fn record_overflow(overflows: &EnvironmentOverflowLog, overflow: EnvironmentOverflow) {
+    debug!("record_overflow: {}", overflow);
+    let mut overflows = overflows.lock().unwrap();
+    if !overflows.contains(&overflow) {
+        overflows.push(overflow);
+    }
+}
+
+impl fmt::Display for EnvironmentOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.kind {
+            EnvironmentOverflowKind::Assumption => write!(
+                f,
+                "assuming `{:?}` would grow an environment of {} clauses beyond the limit of {}",
+                self.hypothesis,
+                self.environment.clauses.len(),
+                self.limit
+            ),
+            EnvironmentOverflowKind::Elaboration => write!(
+                f,
+                "the bounds implied by `{:?}` would grow an environment of {} clauses \
+                 beyond the limit of {}",
+                self.hypothesis,
+                self.environment.clauses.len(),
+                self.limit
+            ),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Exporting what a solver has proven as a set of ground facts, for
+//! analyses outside of chalk. Only trait implementations are exported
+//! so far, as the relation
+//!
+//! ```text
+//! implemented(trait: symbol, self_ty: symbol, parameters: symbol)
+//! ```
+//!
+//! where `parameters` lists the trait's parameters besides `Self`,
+//! separated by commas. Types are written as in chalk programs; like
+//! the `Debug` output of other IR types, this relies on the current
+//! program (see `ir::tls`) for the names of items.
+
+use fold::Subst;
+use ir::*;
+use itertools::Itertools;
+use std::collections::BTreeSet;
+
+/// The ground facts proven by a solver; see `Solver::proven_facts`.
+///
+/// A fact is a goal that was proven outright: the answer must not be
+/// ambiguous or carry region constraints, and the goal must not have
+/// depended on hypotheses from its environment. Goals that still
+/// mention inference variables or placeholders once the answer is
+/// applied are not ground, so they are left out as well.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FactSet {
+    implemented: BTreeSet<TraitRef>,
+}
+
+impl FactSet {
+    /// Collects the facts among the given answers, each of which is
+    /// given with the goal it answers and whether it is ambiguous.
+    crate fn from_answers<I>(answers: I) -> FactSet
+    where
+        I: IntoIterator<Item = (UCanonical<InEnvironment<Goal>>, Canonical<ConstrainedSubst>, bool)>,
+    {
+        FactSet {
+            implemented: answers
+                .into_iter()
+                .filter_map(|(goal, answer, ambiguous)| {
+                    proven_trait_ref(&goal, &answer, ambiguous)
+                })
+                .collect(),
+        }
+    }
+
+    /// The trait implementations that were proven, in a deterministic
+    /// order.
+    pub fn implemented(&self) -> &BTreeSet<TraitRef> {
+        &self.implemented
+    }
+
+    pub fn len(&self) -> usize {
+        self.implemented.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.implemented.is_empty()
+    }
+
+    /// Renders the facts as a Datalog program, declaring the relation
+    /// followed by one fact per line.
+    pub fn to_datalog(&self) -> String {
+        let mut datalog =
+            String::from(".decl implemented(trait: symbol, self_ty: symbol, parameters: symbol)\n");
+        for trait_ref in &self.implemented {
+            let columns = columns(trait_ref)
+                .iter()
+                .map(|column| format!("\"{}\"", column.replace('\\', "\\\\").replace('"', "\\\"")))
+                .join(", ");
+            datalog.push_str(&format!("implemented({}).\n", columns));
+        }
+        datalog
+    }
+
+    /// Renders the facts as CSV, with a header row naming the columns.
+    /// Every field is quoted, since types may contain commas.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("trait,self_ty,parameters\n");
+        for trait_ref in &self.implemented {
+            let columns = columns(trait_ref)
+                .iter()
+                .map(|column| format!("\"{}\"", column.replace('"', "\"\"")))
+                .join(",");
+            csv.push_str(&columns);
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// If `answer` proves that `goal` holds for a ground trait reference,
+/// returns that trait reference.
+fn proven_trait_ref(
+    goal: &UCanonical<InEnvironment<Goal>>,
+    answer: &Canonical<ConstrainedSubst>,
+    ambiguous: bool,
+) -> Option<TraitRef> {
+    // A goal in more than the root universe mentions placeholders, and
+    // an answer with binders leaves some variables unresolved.
+    let proven = !ambiguous
+        && answer.binders.is_empty()
+        && answer.value.constraints.is_empty()
+        && goal.universes == 1
+        && goal.canonical.value.environment.clauses.is_empty();
+    if !proven {
+        return None;
+    }
+
+    let trait_ref = match &goal.canonical.value.goal {
+        Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(WhereClauseAtom::Implemented(
+            trait_ref,
+        )))) => trait_ref,
+        _ => return None,
+    };
+
+    let trait_ref = Subst::apply(&answer.value.subst.parameters, trait_ref);
+    if trait_ref.parameters.iter().all(is_ground) {
+        Some(trait_ref)
+    } else {
+        None
+    }
+}
+
+/// True for types built from named types alone, without projections,
+/// higher-ranked types or lifetimes.
+fn is_ground(parameter: &Parameter) -> bool {
+    match parameter {
+        ParameterKind::Ty(Ty::Apply(apply)) => match apply.name {
+            TypeName::ItemId(_)
+            | TypeName::Tuple(_)
+            | TypeName::FnPtr(_)
+            | TypeName::Slice
+            | TypeName::Array(_)
+            | TypeName::Str
+            | TypeName::Scalar(_)
+            | TypeName::Never
+            | TypeName::Ref(_)
+            | TypeName::RawPtr(_) => {
+                apply.parameters.iter().all(is_ground)
+            }
+            TypeName::ForAll(_) | TypeName::AssociatedType(_) => false,
+        },
+        ParameterKind::Ty(_) | ParameterKind::Lifetime(_) | ParameterKind::Const(_) => false,
+    }
+}
+
+fn columns(trait_ref: &TraitRef) -> Vec<String> {
+    vec![
+        format!("{:?}", trait_ref.trait_id),
+        format!("{:?}", trait_ref.parameters[0]),
+        trait_ref.parameters[1..]
+            .iter()
+            .map(|parameter| format!("{:?}", parameter))
+            .join(", "),
+    ]
+}
@@ -0,0 +1,181 @@
+//! Finding out which hypotheses a solution depends on. When a goal
+//! like `forall<T> { if (T: Clone; T: Debug) { Vec<T>: Clone } }` is
+//! peeled, its hypotheses end up in the environment of the root goal;
+//! here we determine which of them could have been left out, and,
+//! conversely, which bounds a goal that does not hold is missing.
+
+use cast::Cast;
+use ir::*;
+use solve::{Solution, Solver};
+
+/// The hypotheses in the environment of a root goal, split by whether
+/// the solution depends on them; see `SolveOutcome::hypotheses`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HypothesisUsage {
+    /// A minimal set of hypotheses under which the goal has the same
+    /// solution: dropping any one of them changes the solution.
+    pub used: Vec<ProgramClause>,
+
+    /// The remaining hypotheses, which can all be dropped together
+    /// without changing the solution.
+    pub unused: Vec<ProgramClause>,
+}
+
+impl HypothesisUsage {
+    /// Determines which hypotheses of `canonical_goal` its `solution`
+    /// depends on. If the goal cannot be proven, no hypothesis is
+    /// considered used.
+    ///
+    /// Note that only the environment of the root goal is considered;
+    /// implications nested within the goal itself (as in `A, if (B) {
+    /// C }`) are not.
+    crate fn compute(
+        solver: &mut Solver,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+        solution: &Option<Solution>,
+    ) -> ::errors::Result<Self> {
+        let hypotheses = &canonical_goal.canonical.value.environment.clauses;
+        if solution.is_none() {
+            return Ok(HypothesisUsage {
+                used: vec![],
+                unused: hypotheses.clone(),
+            });
+        }
+
+        minimize(solver, canonical_goal, &[], hypotheses, solution)
+    }
+
+    /// The traits whose `InScope` hypotheses were used. For a goal
+    /// involving an unselected projection like `T::Item`, placed in a
+    /// scope with `Program::goal_in_scope`, these are the traits whose
+    /// associated types the projection was resolved against.
+    pub fn selected_traits(&self) -> Vec<ItemId> {
+        self.used
+            .iter()
+            .filter_map(|hypothesis| match hypothesis {
+                ProgramClause::Implies(ProgramClauseImplication {
+                    consequence: DomainGoal::InScope(trait_id),
+                    conditions,
+                }) if conditions.is_empty() => Some(*trait_id),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Looks for bounds `P: Trait` on the placeholders `P` of
+/// `canonical_goal` under which it would hold uniquely, and returns a
+/// minimal set of them; see `SolverChoice::infer_missing_bounds`.
+///
+/// Only traits without parameters besides `Self` are considered.
+crate fn missing_bounds(
+    solver: &mut Solver,
+    env: &ProgramEnvironment,
+    canonical_goal: &UCanonical<InEnvironment<Goal>>,
+) -> ::errors::Result<Option<Vec<TraitRef>>> {
+    if let Some(Solution::Unique(_)) = solver.solve(canonical_goal)? {
+        return Ok(Some(vec![]));
+    }
+
+    // Placeholders are numbered from universe 1 on. Some of them may
+    // be lifetimes, in which case the bounds we assume about the type
+    // placeholder of the same universe are simply never used.
+    let environment = &canonical_goal.canonical.value.environment;
+    let candidates: Vec<(TraitRef, ProgramClause)> = (1..canonical_goal.universes)
+        .flat_map(|counter| {
+            let placeholder = TypeName::ForAll(UniverseIndex { counter }).to_ty();
+            env.trait_data
+                .iter()
+                .filter(|(_, trait_datum)| trait_datum.binders.len() == 1)
+                .map(move |(&trait_id, _)| TraitRef {
+                    trait_id,
+                    parameters: vec![ParameterKind::Ty(placeholder.clone())],
+                })
+        })
+        .map(|trait_ref| {
+            let hypothesis =
+                DomainGoal::FromEnv(WhereClauseAtom::Implemented(trait_ref.clone())).cast();
+            (trait_ref, hypothesis)
+        })
+        .filter(|(_, hypothesis)| !environment.clauses.contains(hypothesis))
+        .collect();
+
+    let hypotheses: Vec<ProgramClause> = candidates
+        .iter()
+        .map(|(_, hypothesis)| hypothesis.clone())
+        .collect();
+    let mut goal = canonical_goal.clone();
+    goal.canonical.value.environment =
+        Environment::add_clauses(environment, hypotheses.iter().cloned());
+    let solution = solver.solve(&goal)?;
+    match solution {
+        Some(Solution::Unique(_)) => {}
+        _ => return Ok(None),
+    }
+
+    // The hypotheses the goal came with are kept as they are.
+    let usage = minimize(solver, &goal, &environment.clauses, &hypotheses, &solution)?;
+    Ok(Some(
+        candidates
+            .into_iter()
+            .filter(|(_, hypothesis)| usage.used.contains(hypothesis))
+            .map(|(trait_ref, _)| trait_ref)
+            .collect(),
+    ))
+}
+
+/// Drops the `candidates` from the environment of `canonical_goal` one
+/// at a time, in order, keeping each out if the goal still has
+/// `solution` without it. The `fixed` hypotheses are never dropped.
+fn minimize(
+    solver: &mut Solver,
+    canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    fixed: &[ProgramClause],
+    candidates: &[ProgramClause],
+    solution: &Option<Solution>,
+) -> ::errors::Result<HypothesisUsage> {
+    let solution = without_constraint_environments(solution.clone());
+    let mut usage = HypothesisUsage::default();
+    for (index, candidate) in candidates.iter().enumerate() {
+        // Keep the candidates found to be used so far, and those we
+        // have yet to look at; drop the rest.
+        let clauses = fixed
+            .iter()
+            .chain(&usage.used)
+            .chain(&candidates[index + 1..])
+            .cloned();
+        let mut goal = canonical_goal.clone();
+        goal.canonical.value.environment =
+            Environment::add_clauses(&Environment::new(), clauses);
+
+        if without_constraint_environments(solver.solve(&goal)?) == solution {
+            usage.unused.push(candidate.clone());
+        } else {
+            usage.used.push(candidate.clone());
+        }
+    }
+    Ok(usage)
+}
+
+/// Region constraints record the environment they arose in, which of
+/// course differs once hypotheses are dropped; we only want to know
+/// whether the constraints themselves changed.
+fn without_constraint_environments(solution: Option<Solution>) -> Option<Solution> {
+    solution.map(|solution| match solution {
+        Solution::Unique(mut constrained) => {
+            for constraint in &mut constrained.value.constraints {
+                constraint.environment = Environment::new();
+            }
+            Solution::Unique(constrained)
+        }
+        Solution::MultipleSolutions(mut answers) => {
+            for answer in &mut answers {
+                for constraint in &mut answer.value.constraints {
+                    constraint.environment = Environment::new();
+                }
+            }
+            Solution::MultipleSolutions(answers)
+        }
+        ambiguous @ Solution::Ambig(..) => ambiguous,
+    })
+}
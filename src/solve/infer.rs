@@ -2,6 +2,9 @@ use ena::unify as ena;
 use ir::*;
 use fold::Fold;
 use fold::shift::Shift;
+use smallvec::SmallVec;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
 crate mod canonicalize;
 crate mod ucanonicalize;
@@ -15,30 +18,107 @@ mod test;
 
 use self::var::*;
 
-#[derive(Clone)]
 pub struct InferenceTable {
     // FIXME pub b/c of trait impl for SLG
     unify: ena::UnificationTable<InferenceVariable>,
-    vars: Vec<InferenceVariable>,
+
+    /// Most tables only have as many variables as the goal they were
+    /// created for has binders, so like the parameters of a
+    /// `Substitution`, they are stored inline up to
+    /// `SUBST_INLINE_PARAMETERS`.
+    vars: SmallVec<[InferenceVariable; SUBST_INLINE_PARAMETERS]>,
     max_universe: UniverseIndex,
+
+    /// If true, lifetimes are never unified; see `erase_regions`.
+    erase_regions: bool,
+
+    /// A snapshot of the table while it was still empty, which `clear`
+    /// rolls back to. Only present once the table has been cleared.
+    empty: Option<InferenceSnapshot>,
 }
 
 crate struct InferenceSnapshot {
     unify_snapshot: ena::Snapshot<InferenceVariable>,
     max_universe: UniverseIndex,
-    vars: Vec<InferenceVariable>,
+
+    /// Variables are only ever pushed onto `vars`, so remembering its
+    /// length is enough to restore it.
+    vars_len: usize,
 }
 
 crate type ParameterInferenceVariable = ParameterKind<InferenceVariable>;
 
+/// Inference tables that are no longer in use, kept so that their
+/// storage can be reused, for code that creates many short-lived
+/// tables. Clones share the same tables.
+#[derive(Clone, Default)]
+crate struct InferenceTablePool {
+    tables: Arc<Mutex<Vec<InferenceTable>>>,
+}
+
+impl InferenceTablePool {
+    /// Takes a table from the pool, or creates one if there are none
+    /// left. The table still has to be cleared before it is used; see
+    /// `InferenceTable::clear_and_instantiate_canonical`.
+    crate fn take(&self) -> InferenceTable {
+        self.tables
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(InferenceTable::new)
+    }
+
+    /// Returns a table to the pool, once it is no longer in use.
+    crate fn put_back(&self, table: InferenceTable) {
+        self.tables.lock().unwrap().push(table);
+    }
+}
+
+impl fmt::Debug for InferenceTablePool {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "InferenceTablePool({})", self.tables.lock().unwrap().len())
+    }
+}
+
 impl InferenceTable {
     /// Create an empty inference table with no variables.
-    crate fn new() -> Self {
+    pub fn new() -> Self {
         InferenceTable {
             unify: ena::UnificationTable::new(),
-            vars: vec![],
+            vars: SmallVec::new(),
             max_universe: UniverseIndex::root(),
+            erase_regions: false,
+            empty: None,
+        }
+    }
+
+    /// Resets the table to the state of a new one, without freeing the
+    /// storage for its variables, so that it can be reused for another
+    /// goal. Any snapshots must have been committed or rolled back
+    /// first. Settings such as `erase_regions` are kept.
+    ///
+    /// The table is cleared by rolling back to a snapshot of it taken
+    /// while it was empty, which leaves the memory of the unification
+    /// table, its undo log and `vars` allocated. The first call cannot
+    /// do this yet, so it starts over with a fresh table instead; from
+    /// then on, the table keeps an undo log of every change to it.
+    pub fn clear(&mut self) {
+        match self.empty.take() {
+            Some(empty) => self.rollback_to(empty),
+            None => {
+                self.unify = ena::UnificationTable::new();
+                self.vars.clear();
+                self.max_universe = UniverseIndex::root();
+            }
         }
+        self.empty = Some(self.snapshot());
+    }
+
+    /// From now on, consider all lifetimes equal: unifying two
+    /// lifetimes always succeeds, without binding any variables or
+    /// producing region constraints.
+    crate fn erase_regions(&mut self) {
+        self.erase_regions = true;
     }
 
     /// Creates a new inference table, pre-populated with
@@ -56,13 +136,38 @@ impl InferenceTable {
         T: Fold<Result = T> + Clone,
     {
         let mut table = InferenceTable::new();
+        let (subst, value) = table.instantiate_canonical_when_empty(num_universes, canonical);
+        (table, subst, value)
+    }
+
+    /// Like `from_canonical`, but reuses this table, which is cleared
+    /// first; see `clear`.
+    crate fn clear_and_instantiate_canonical<T>(
+        &mut self,
+        num_universes: usize,
+        canonical: &Canonical<T>,
+    ) -> (Substitution, T)
+    where
+        T: Fold<Result = T> + Clone,
+    {
+        self.clear();
+        self.instantiate_canonical_when_empty(num_universes, canonical)
+    }
 
+    fn instantiate_canonical_when_empty<T>(
+        &mut self,
+        num_universes: usize,
+        canonical: &Canonical<T>,
+    ) -> (Substitution, T)
+    where
+        T: Fold<Result = T> + Clone,
+    {
         assert!(num_universes >= 1); // always have U0
         for _ in 1..num_universes {
-            table.new_universe();
+            self.new_universe();
         }
 
-        let subst = table.fresh_subst(&canonical.binders);
+        let subst = self.fresh_subst(&canonical.binders);
 
         // Pointless micro-optimization: The fully correct way to
         // instantiate `value` is to substitute `subst` like so:
@@ -71,15 +176,15 @@ impl InferenceTable {
         //
         // However, because (a) this is a canonical value, and hence
         // contains no free variables except for those bound in the
-        // canonical binders and (b) we just create the inference
-        // table, and we created all of its variables from those same
+        // canonical binders and (b) the inference table was empty,
+        // and we created all of its variables from those same
         // binders, we know that this substitution will have the form
         // `?0 := ?0` and so forth.  So we can just "clone" the
         // canonical value rather than actually substituting.
         assert!(subst.is_identity_subst());
         let value = canonical.value.clone();
 
-        (table, subst, value)
+        (subst, value)
     }
 
     /// Creates and returns a fresh universe that is distinct from all
@@ -107,6 +212,13 @@ impl InferenceTable {
         var
     }
 
+    /// Creates a new type inference variable in the universe `ui`,
+    /// for callers outside the solver, which cannot name the variable
+    /// itself.
+    pub fn new_ty_variable(&mut self, ui: UniverseIndex) -> Ty {
+        self.new_variable(ui).to_ty()
+    }
+
     /// Takes a "snapshot" of the current state of the inference
     /// table.  Later, you must invoke either `rollback_to` or
     /// `commit` with that snapshot.  Snapshots can be nested, but you
@@ -115,19 +227,19 @@ impl InferenceTable {
     /// created).
     crate fn snapshot(&mut self) -> InferenceSnapshot {
         let unify_snapshot = self.unify.snapshot();
-        let vars = self.vars.clone();
+        let vars_len = self.vars.len();
         let max_universe = self.max_universe;
         InferenceSnapshot {
             unify_snapshot,
             max_universe,
-            vars,
+            vars_len,
         }
     }
 
     /// Restore the table to the state it had when the snapshot was taken.
     crate fn rollback_to(&mut self, snapshot: InferenceSnapshot) {
         self.unify.rollback_to(snapshot.unify_snapshot);
-        self.vars = snapshot.vars;
+        self.vars.truncate(snapshot.vars_len);
         self.max_universe = snapshot.max_universe;
     }
 
@@ -150,9 +262,16 @@ impl InferenceTable {
                 let var = InferenceVariable::from_depth(depth - binders);
                 match self.unify.probe_value(var) {
                     InferenceValue::Unbound(_) => None,
-                    InferenceValue::Bound(ref val) => {
-                        let ty = val.as_ref().ty().unwrap();
-                        Some(ty.up_shift(binders))
+                    InferenceValue::Bound(val) => {
+                        // `probe_value` already hands us a fresh copy,
+                        // so avoid making another when there is
+                        // nothing to shift.
+                        let ty = val.ty().unwrap();
+                        if binders == 0 {
+                            Some(ty)
+                        } else {
+                            Some(ty.up_shift(binders))
+                        }
                     }
                 }
             }
@@ -174,6 +293,21 @@ impl InferenceTable {
         }
     }
 
+    /// If `leaf` represents an inference variable `X`, and `X` is bound,
+    /// returns `Some(v)` where `v` is the value to which `X` is bound.
+    crate fn normalize_const(&mut self, leaf: &Const, binders: usize) -> Option<Const> {
+        match *leaf {
+            Const::Var(v) => {
+                if v < binders {
+                    return None;
+                }
+                let v1 = self.probe_const_var(InferenceVariable::from_depth(v - binders))?;
+                Some(v1.up_shift(binders))
+            }
+            Const::ForAll(_) | Const::Value(_) => None,
+        }
+    }
+
     /// Finds the type to which `var` is bound, returning `None` if it is not yet
     /// bound.
     ///
@@ -203,6 +337,20 @@ impl InferenceTable {
         }
     }
 
+    /// Finds the const to which `var` is bound, returning `None` if it is not yet
+    /// bound.
+    ///
+    /// # Panics
+    ///
+    /// This method is only valid for inference variables of kind
+    /// const. If this variable is of a different kind, then the function may panic.
+    fn probe_const_var(&mut self, var: InferenceVariable) -> Option<Const> {
+        match self.unify.probe_value(var) {
+            InferenceValue::Unbound(_) => None,
+            InferenceValue::Bound(ref val) => Some(*val.as_ref().constant().unwrap()),
+        }
+    }
+
     /// Given an unbound variable, returns its universe.
     ///
     /// # Panics
@@ -232,6 +380,7 @@ impl ParameterInferenceVariable {
         match self {
             ParameterKind::Ty(v) => ParameterKind::Ty(v.to_ty()),
             ParameterKind::Lifetime(v) => ParameterKind::Lifetime(v.to_lifetime()),
+            ParameterKind::Const(v) => ParameterKind::Const(v.to_const()),
         }
     }
 }
@@ -2,6 +2,7 @@ use fallible::*;
 use fold::{DefaultTypeFolder, ExistentialFolder, Fold, UniversalFolder};
 use fold::shift::Shift;
 use ir::*;
+use smallvec::SmallVec;
 use std::cmp::max;
 
 use super::{InferenceTable, InferenceVariable, ParameterInferenceVariable};
@@ -29,7 +30,7 @@ impl InferenceTable {
         debug!("canonicalize({:#?})", value);
         let mut q = Canonicalizer {
             table: self,
-            free_vars: Vec::new(),
+            free_vars: SmallVec::new(),
             max_universe: UniverseIndex::root(),
         };
         let value = value.fold_with(&mut q, 0).unwrap();
@@ -53,7 +54,7 @@ crate struct Canonicalized<T> {
     crate quantified: Canonical<T>,
 
     /// The free existential variables, along with the universes they inhabit.
-    crate free_vars: Vec<ParameterInferenceVariable>,
+    crate free_vars: SmallVec<[ParameterInferenceVariable; SUBST_INLINE_PARAMETERS]>,
 
     /// The maximum universe of any universally quantified variables
     /// encountered.
@@ -62,7 +63,7 @@ crate struct Canonicalized<T> {
 
 struct Canonicalizer<'q> {
     table: &'q mut InferenceTable,
-    free_vars: Vec<ParameterInferenceVariable>,
+    free_vars: SmallVec<[ParameterInferenceVariable; SUBST_INLINE_PARAMETERS]>,
     max_universe: UniverseIndex,
 }
 
@@ -107,6 +108,15 @@ impl<'q> UniversalFolder for Canonicalizer<'q> {
         self.max_universe = max(self.max_universe, universe);
         Ok(universe.to_lifetime())
     }
+
+    fn fold_free_universal_const(
+        &mut self,
+        universe: UniverseIndex,
+        _binders: usize,
+    ) -> Fallible<Const> {
+        self.max_universe = max(self.max_universe, universe);
+        Ok(universe.to_const())
+    }
 }
 
 impl<'q> ExistentialFolder for Canonicalizer<'q> {
@@ -159,4 +169,25 @@ impl<'q> ExistentialFolder for Canonicalizer<'q> {
             }
         }
     }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        debug_heading!(
+            "fold_free_existential_const(depth={:?}, binders={:?})",
+            depth,
+            binders
+        );
+        let var = InferenceVariable::from_depth(depth);
+        match self.table.probe_const_var(var) {
+            Some(c) => {
+                debug!("bound to {:?}", c);
+                Ok(c.fold_with(self, 0)?.up_shift(binders))
+            }
+            None => {
+                let free_var = ParameterKind::Const(self.table.unify.find(var));
+                let position = self.add(free_var);
+                debug!("not yet unified: position={:?}", position);
+                Ok(InferenceVariable::from_depth(position + binders).to_const())
+            }
+        }
+    }
 }
@@ -1,8 +1,11 @@
 use fallible::*;
 use fold::*;
+use smallvec::SmallVec;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use super::*;
+use super::unify::UnificationResult;
 
 impl InferenceTable {
     /// Create a instance of `arg` where each variable is replaced with
@@ -13,7 +16,7 @@ impl InferenceTable {
         U: IntoIterator<Item = ParameterKind<UniverseIndex>>,
     {
         debug!("instantiate(arg={:?})", arg);
-        let vars: Vec<_> = universes
+        let vars: SmallVec<_> = universes
             .into_iter()
             .map(|param_kind| self.parameter_kind_to_parameter(param_kind))
             .collect();
@@ -31,6 +34,7 @@ impl InferenceTable {
             ParameterKind::Lifetime(ui) => {
                 ParameterKind::Lifetime(self.new_variable(ui).to_lifetime())
             }
+            ParameterKind::Const(ui) => ParameterKind::Const(self.new_variable(ui).to_const()),
         }
     }
 
@@ -51,14 +55,102 @@ impl InferenceTable {
         }
     }
 
-    /// Variant on `instantiate` that takes a `Canonical<T>`.
-    crate fn instantiate_canonical<T>(&mut self, bound: &Canonical<T>) -> T::Result
+    /// Variant on `instantiate` that takes a `Canonical<T>`. This is
+    /// how callers outside the solver introduce inference variables:
+    /// each binder of `bound` becomes a fresh variable.
+    pub fn instantiate_canonical<T>(&mut self, bound: &Canonical<T>) -> T::Result
     where
         T: Fold + Debug,
     {
         self.instantiate(bound.binders.iter().cloned(), &bound.value)
     }
 
+    /// Like `instantiate_canonical`, but also returns the substitution
+    /// mapping each binder of `bound` to its fresh variable. A caller
+    /// that instantiates a canonical goal this way can later apply an
+    /// answer to it with `apply_answer`.
+    pub fn instantiate_canonical_with_subst<T>(
+        &mut self,
+        bound: &Canonical<T>,
+    ) -> (Substitution, T::Result)
+    where
+        T: Fold,
+    {
+        let subst = self.fresh_subst(&bound.binders);
+        let value = Subst::apply(&subst.parameters, &bound.value);
+        (subst, value)
+    }
+
+    /// Instantiates the binders of an answer, such as the `for<?U0>`
+    /// in `for<?U0> { substitution [?0 := '?0], lifetime constraints
+    /// [] }`, with fresh inference variables from this table, each in
+    /// the universe named by its binder. The substitution and
+    /// constraints returned refer to those variables, so they can be
+    /// used alongside other values from this table.
+    pub fn instantiate_answer(&mut self, answer: &Canonical<ConstrainedSubst>) -> ConstrainedSubst {
+        self.instantiate_canonical(answer)
+    }
+
+    /// Applies `answer`, an answer to some canonical goal, to
+    /// `goal_subst`, the substitution with which the binders of that
+    /// goal were instantiated in this table (see
+    /// `instantiate_canonical_with_subst`). The answer is instantiated
+    /// as by `instantiate_answer`, and each of its parameters unified
+    /// with the corresponding parameter of `goal_subst`. The result
+    /// holds the goals and region constraints arising from that
+    /// unification, together with the answer's own lifetime
+    /// constraints.
+    pub fn apply_answer(
+        &mut self,
+        environment: &Arc<Environment>,
+        goal_subst: &Substitution,
+        answer: &Canonical<ConstrainedSubst>,
+    ) -> Fallible<UnificationResult> {
+        let snapshot = self.snapshot();
+        match self.apply_answer_in_snapshot(environment, goal_subst, answer) {
+            Ok(result) => {
+                self.commit(snapshot);
+                Ok(result)
+            }
+            Err(e) => {
+                self.rollback_to(snapshot);
+                Err(e)
+            }
+        }
+    }
+
+    fn apply_answer_in_snapshot(
+        &mut self,
+        environment: &Arc<Environment>,
+        goal_subst: &Substitution,
+        answer: &Canonical<ConstrainedSubst>,
+    ) -> Fallible<UnificationResult> {
+        let ConstrainedSubst { subst, constraints } = self.instantiate_answer(answer);
+        if subst.parameters.len() != goal_subst.parameters.len() {
+            return Err(NoSolution);
+        }
+
+        let mut result = UnificationResult {
+            goals: vec![],
+            constraints,
+        };
+        for (a, b) in goal_subst.parameters.iter().zip(&subst.parameters) {
+            let UnificationResult { goals, constraints } = match (a, b) {
+                (ParameterKind::Ty(a), ParameterKind::Ty(b)) => self.unify(environment, a, b)?,
+                (ParameterKind::Lifetime(a), ParameterKind::Lifetime(b)) => {
+                    self.unify(environment, a, b)?
+                }
+                (ParameterKind::Const(a), ParameterKind::Const(b)) => {
+                    self.unify(environment, a, b)?
+                }
+                _ => return Err(NoSolution),
+            };
+            result.goals.extend(goals);
+            result.constraints.extend(constraints);
+        }
+        Ok(result)
+    }
+
     /// Instantiates `arg` with fresh existential variables in the
     /// given universe; the kinds of the variables are implied by
     /// `binders`. This is used to apply a universally quantified
@@ -100,7 +192,7 @@ impl InferenceTable {
         T: Fold,
     {
         let (binders, value) = arg.split();
-        let parameters: Vec<_> = binders
+        let parameters: SmallVec<[_; SUBST_INLINE_PARAMETERS]> = binders
             .iter()
             .map(|pk| {
                 let new_universe = self.new_universe();
@@ -113,6 +205,7 @@ impl InferenceTable {
                         name: TypeName::ForAll(new_universe),
                         parameters: vec![],
                     })),
+                    ParameterKind::Const(()) => ParameterKind::Const(new_universe.to_const()),
                 }
             })
             .collect();
@@ -143,7 +236,7 @@ impl<'a, T> BindersAndValue for (&'a Vec<ParameterKind<()>>, &'a T) {
 }
 
 struct Instantiator {
-    vars: Vec<Parameter>,
+    vars: SmallVec<[Parameter; SUBST_INLINE_PARAMETERS]>,
 }
 
 impl DefaultTypeFolder for Instantiator {}
@@ -173,6 +266,14 @@ impl ExistentialFolder for Instantiator {
             Ok(Lifetime::Var(depth + binders - self.vars.len())) // see comment above
         }
     }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        if depth < self.vars.len() {
+            Ok(self.vars[depth].assert_const_ref().up_shift(binders))
+        } else {
+            Ok(Const::Var(depth + binders - self.vars.len())) // see comment above
+        }
+    }
 }
 
 impl IdentityUniversalFolder for Instantiator {}
@@ -36,6 +36,15 @@ impl<'q> ExistentialFolder for Normalizer<'q> {
         assert_eq!(binders, 0);
         Ok(InferenceVariable::from_depth(depth).to_lifetime())
     }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        assert_eq!(binders, 0);
+        let var = InferenceVariable::from_depth(depth);
+        match self.table.probe_const_var(var) {
+            Some(c) => c.fold_with(self, 0),
+            None => Ok(var.to_const()),
+        }
+    }
 }
 
 impl<'q> IdentityUniversalFolder for Normalizer<'q> {}
@@ -297,3 +306,115 @@ fn lifetime_constraint_indirect() {
         "InEnvironment { environment: Env([]), goal: \'?2 == \'!1 }",
     );
 }
+
+#[test]
+fn could_unify_helpers() {
+    let mut table = InferenceTable::new();
+    let environment0 = Environment::new();
+    let a = table.new_variable(U0).to_ty();
+
+    // Probing leaves `?0` unbound.
+    assert!(table.types_could_unify(&environment0, &a, &ty!(apply (item 0))));
+    assert!(table.types_could_unify(&environment0, &a, &ty!(apply (item 1))));
+    assert!(!table.types_could_unify(
+        &environment0,
+        &ty!(apply (item 0)),
+        &ty!(apply (item 1))
+    ));
+
+    let trait_ref = |trait_index, ty: Ty| TraitRef {
+        trait_id: ItemId { index: trait_index },
+        parameters: vec![ParameterKind::Ty(ty)],
+    };
+    table
+        .unify_trait_refs(
+            &environment0,
+            &trait_ref(2, ty!(apply (item 0) (expr a))),
+            &trait_ref(2, ty!(apply (item 0) (apply (item 1)))),
+        )
+        .unwrap();
+    assert_eq!(table.normalize(&a), ty!(apply (item 1)));
+    table
+        .unify_trait_refs(
+            &environment0,
+            &trait_ref(2, ty!(apply (item 0))),
+            &trait_ref(3, ty!(apply (item 0))),
+        )
+        .unwrap_err();
+
+    let projection = |ty: Ty| ProjectionTy {
+        associated_ty_id: ItemId { index: 4 },
+        parameters: vec![ParameterKind::Ty(ty)],
+    };
+    let b = table.new_variable(U0).to_ty();
+    let result = table
+        .unify_projections(&environment0, &projection(b.clone()), &projection(a.clone()))
+        .unwrap();
+    assert!(result.goals().is_empty());
+    assert_eq!(table.normalize(&b), ty!(apply (item 1)));
+}
+
+#[test]
+fn apply_answer() {
+    // The goal `exists<'a, T, U> { Ref<'a, T> = Ref<'a, U> }` has the
+    // answer `for<?U0,?U0> { [?0 := '?0, ?1 := ?1, ?2 := ?1] }`.
+    let goal = Canonical {
+        value: ty!(apply (item 0) (lifetime (var 0)) (var 1) (var 2)),
+        binders: vec![
+            ParameterKind::Lifetime(U0),
+            ParameterKind::Ty(U0),
+            ParameterKind::Ty(U0),
+        ],
+    };
+    let answer = Canonical {
+        value: ConstrainedSubst {
+            subst: Substitution {
+                parameters: vec![arg!((lifetime (var 0))), arg!((var 1)), arg!((var 1))]
+                    .into_iter()
+                    .collect(),
+            },
+            constraints: vec![],
+        },
+        binders: vec![ParameterKind::Lifetime(U0), ParameterKind::Ty(U0)],
+    };
+
+    let mut table = InferenceTable::new();
+    let environment0 = Environment::new();
+    let (goal_subst, _) = table.instantiate_canonical_with_subst(&goal);
+
+    // Instantiating the answer gives it variables of its own.
+    let instantiated = table.instantiate_answer(&answer);
+    assert_eq!(
+        instantiated.subst().parameters(),
+        &[arg!((lifetime (var 3))), arg!((var 4)), arg!((var 4))][..]
+    );
+
+    // Applying it relates the goal's `T` and `U`.
+    let result = table
+        .apply_answer(&environment0, &goal_subst, &answer)
+        .unwrap();
+    assert!(result.goals().is_empty());
+    assert!(result.constraints().is_empty());
+    let t = goal_subst.parameters()[1].assert_ty_ref().clone();
+    let u = goal_subst.parameters()[2].assert_ty_ref().clone();
+    table
+        .unify(&environment0, &t, &ty!(apply (item 1)))
+        .unwrap();
+    assert_eq!(table.normalize(&u), ty!(apply (item 1)));
+
+    // An answer with parameters of the wrong kind does not apply.
+    let mismatched = Canonical {
+        value: ConstrainedSubst {
+            subst: Substitution {
+                parameters: vec![arg!((var 0)), arg!((var 0)), arg!((var 0))]
+                    .into_iter()
+                    .collect(),
+            },
+            constraints: vec![],
+        },
+        binders: vec![ParameterKind::Ty(U0)],
+    };
+    table
+        .apply_answer(&environment0, &goal_subst, &mismatched)
+        .unwrap_err();
+}
@@ -9,16 +9,7 @@ impl InferenceTable {
         debug!("u_canonicalize({:#?})", value0);
 
         // First, find all the universes that appear in `value`.
-        let mut universes = UniverseMap::new();
-        value0
-            .value
-            .fold_with(
-                &mut UCollector {
-                    universes: &mut universes,
-                },
-                0,
-            )
-            .unwrap();
+        let universes = UniverseMap::collect(&value0.value);
 
         // Now re-map the universes found in value. We have to do this
         // in a second pass because it is only then that we know the
@@ -80,6 +71,26 @@ impl UniverseMap {
         }
     }
 
+    /// Collects the universes of all the placeholders that appear in
+    /// `value`, along with the root universe.
+    crate fn collect<T: Fold>(value: &T) -> UniverseMap {
+        let mut universes = UniverseMap::new();
+        value
+            .fold_with(
+                &mut UCollector {
+                    universes: &mut universes,
+                },
+                0,
+            )
+            .unwrap();
+        universes
+    }
+
+    /// The largest universe in the map.
+    crate fn max_universe(&self) -> UniverseIndex {
+        *self.universes.last().unwrap()
+    }
+
     /// Number of canonical universes.
     fn num_canonical_universes(&self) -> usize {
         self.universes.len()
@@ -158,6 +169,18 @@ impl UniverseMap {
         }
     }
 
+    /// Like `map_universe_to_canonical`, but only for universes that
+    /// actually appeared in the original value.
+    crate fn map_placeholder_universe_to_canonical(
+        &self,
+        universe: UniverseIndex,
+    ) -> Option<UniverseIndex> {
+        self.universes
+            .binary_search(&universe)
+            .ok()
+            .map(|index| UniverseIndex { counter: index })
+    }
+
     /// Given a "canonical universe" -- one found in the
     /// `u_canonicalize` result -- returns the original universe that
     /// it corresponded to.
@@ -233,6 +256,15 @@ impl<'q> UniversalFolder for UCollector<'q> {
         self.universes.add(universe);
         Ok(universe.to_lifetime())
     }
+
+    fn fold_free_universal_const(
+        &mut self,
+        universe: UniverseIndex,
+        _binders: usize,
+    ) -> Fallible<Const> {
+        self.universes.add(universe);
+        Ok(universe.to_const())
+    }
 }
 
 impl<'q> IdentityExistentialFolder for UCollector<'q> {}
@@ -261,6 +293,15 @@ impl<'q> UniversalFolder for UMapToCanonical<'q> {
         let universe = self.universes.map_universe_to_canonical(universe0);
         Ok(universe.to_lifetime())
     }
+
+    fn fold_free_universal_const(
+        &mut self,
+        universe0: UniverseIndex,
+        _binders: usize,
+    ) -> Fallible<Const> {
+        let universe = self.universes.map_universe_to_canonical(universe0);
+        Ok(universe.to_const())
+    }
 }
 
 impl<'q> IdentityExistentialFolder for UMapToCanonical<'q> {}
@@ -289,6 +330,15 @@ impl<'q> UniversalFolder for UMapFromCanonical<'q> {
         let universe = self.universes.map_universe_from_canonical(universe0);
         Ok(universe.to_lifetime())
     }
+
+    fn fold_free_universal_const(
+        &mut self,
+        universe0: UniverseIndex,
+        _binders: usize,
+    ) -> Fallible<Const> {
+        let universe = self.universes.map_universe_from_canonical(universe0);
+        Ok(universe.to_const())
+    }
 }
 
 impl<'q> IdentityExistentialFolder for UMapFromCanonical<'q> {}
@@ -35,6 +35,78 @@ impl InferenceTable {
             }
         }
     }
+
+    /// Unifies two types, binding inference variables in either; see
+    /// `unify_trait_refs`.
+    pub fn unify_tys(
+        &mut self,
+        environment: &Arc<Environment>,
+        a: &Ty,
+        b: &Ty,
+    ) -> Fallible<UnificationResult> {
+        self.unify(environment, a, b)
+    }
+
+    /// Unifies two trait references, binding inference variables in
+    /// either. Fails if they name different traits. The result holds
+    /// the goals that must also be proven -- e.g., normalizing a
+    /// projection found in one of them -- and region constraints.
+    pub fn unify_trait_refs(
+        &mut self,
+        environment: &Arc<Environment>,
+        a: &TraitRef,
+        b: &TraitRef,
+    ) -> Fallible<UnificationResult> {
+        self.unify(environment, a, b)
+    }
+
+    /// Like `unify_trait_refs`, but for the projections `<A as
+    /// Trait>::Item` and `<B as Trait>::Item` themselves rather than
+    /// the types they normalize to: they unify if they name the same
+    /// associated type and their parameters unify.
+    pub fn unify_projections(
+        &mut self,
+        environment: &Arc<Environment>,
+        a: &ProjectionTy,
+        b: &ProjectionTy,
+    ) -> Fallible<UnificationResult> {
+        self.unify(environment, a, b)
+    }
+
+    /// Requires `a` to outlive `b`. Unlike unifying them, this binds no
+    /// variables: unless the two lifetimes are the same, or the
+    /// environment assumes `a: b` outright, the requirement is returned
+    /// as a region constraint for the caller to check.
+    crate fn outlives(
+        &mut self,
+        environment: &Arc<Environment>,
+        a: &Lifetime,
+        b: &Lifetime,
+    ) -> UnificationResult {
+        let a = self.normalize_lifetime(a, 0).unwrap_or(*a);
+        let b = self.normalize_lifetime(b, 0).unwrap_or(*b);
+        debug!("outlives(a={:?}, b={:?})", a, b);
+
+        let mut constraints = vec![];
+        if !self.erase_regions && a != b && !environment.assumes_outlives(a, b) {
+            constraints.push(InEnvironment::new(environment, Constraint::Outlives(a, b)));
+        }
+        UnificationResult {
+            goals: vec![],
+            constraints,
+        }
+    }
+
+    /// True if `a` and `b` could be made equal. No variables are
+    /// bound: the unification is rolled back either way. This is an
+    /// approximation, since goals that unification produces (like
+    /// normalizing a projection) are assumed to hold.
+    pub fn types_could_unify(&mut self, environment: &Arc<Environment>, a: &Ty, b: &Ty) -> bool {
+        let snapshot = self.snapshot();
+        let could_unify = self.unify(environment, a, b).is_ok();
+        self.rollback_to(snapshot);
+        could_unify
+    }
 }
 
 struct Unifier<'t> {
@@ -50,6 +122,19 @@ pub struct UnificationResult { // FIXME pub b/c of trait impl for SLG
     crate constraints: Vec<InEnvironment<Constraint>>,
 }
 
+impl UnificationResult {
+    /// Goals that must hold for the unification to be valid.
+    pub fn goals(&self) -> &[InEnvironment<DomainGoal>] {
+        &self.goals
+    }
+
+    /// Region constraints that must hold for the unification to be
+    /// valid.
+    pub fn constraints(&self) -> &[InEnvironment<Constraint>] {
+        &self.constraints
+    }
+}
+
 impl<'t> Unifier<'t> {
     fn new(table: &'t mut InferenceTable, environment: &'t Arc<Environment>) -> Self {
         Unifier {
@@ -116,7 +201,11 @@ impl<'t> Unifier<'t> {
             (&Ty::Var(depth), ty @ &Ty::Apply(_))
             | (ty @ &Ty::Apply(_), &Ty::Var(depth))
             | (&Ty::Var(depth), ty @ &Ty::ForAll(_))
-            | (ty @ &Ty::ForAll(_), &Ty::Var(depth)) => {
+            | (ty @ &Ty::ForAll(_), &Ty::Var(depth))
+            | (&Ty::Var(depth), ty @ &Ty::Dyn(_))
+            | (ty @ &Ty::Dyn(_), &Ty::Var(depth))
+            | (&Ty::Var(depth), ty @ &Ty::Opaque(_))
+            | (ty @ &Ty::Opaque(_), &Ty::Var(depth)) => {
                 self.unify_var_ty(InferenceVariable::from_depth(depth), ty)
             }
 
@@ -125,10 +214,30 @@ impl<'t> Unifier<'t> {
             }
 
             (&Ty::ForAll(ref quantified_ty), apply_ty @ &Ty::Apply(_))
-            | (apply_ty @ &Ty::Apply(_), &Ty::ForAll(ref quantified_ty)) => {
+            | (apply_ty @ &Ty::Apply(_), &Ty::ForAll(ref quantified_ty))
+            | (&Ty::ForAll(ref quantified_ty), apply_ty @ &Ty::Dyn(_))
+            | (apply_ty @ &Ty::Dyn(_), &Ty::ForAll(ref quantified_ty)) => {
                 self.unify_forall_apply(quantified_ty, apply_ty)
             }
 
+            (&Ty::Dyn(ref dyn1), &Ty::Dyn(ref dyn2)) => Zip::zip_with(self, dyn1, dyn2),
+
+            (&Ty::Apply(_), &Ty::Dyn(_)) | (&Ty::Dyn(_), &Ty::Apply(_)) => Err(NoSolution),
+
+            (&Ty::Opaque(ref opaque1), &Ty::Opaque(ref opaque2))
+                if opaque1.opaque_ty_id == opaque2.opaque_ty_id =>
+            {
+                Zip::zip_with(self, &opaque1.parameters, &opaque2.parameters)
+            }
+
+            (&Ty::Opaque(ref opaque), ty @ &Ty::Opaque(_))
+            | (&Ty::Opaque(ref opaque), ty @ &Ty::Apply(_))
+            | (&Ty::Opaque(ref opaque), ty @ &Ty::ForAll(_))
+            | (&Ty::Opaque(ref opaque), ty @ &Ty::Dyn(_))
+            | (ty @ &Ty::Apply(_), &Ty::Opaque(ref opaque))
+            | (ty @ &Ty::ForAll(_), &Ty::Opaque(ref opaque))
+            | (ty @ &Ty::Dyn(_), &Ty::Opaque(ref opaque)) => self.unify_opaque_ty(opaque, ty),
+
             (&Ty::Apply(ref apply1), &Ty::Apply(ref apply2)) => {
                 if apply1.name != apply2.name {
                     return Err(NoSolution);
@@ -149,17 +258,25 @@ impl<'t> Unifier<'t> {
             (ty @ &Ty::Apply(_), &Ty::Projection(ref proj))
             | (ty @ &Ty::ForAll(_), &Ty::Projection(ref proj))
             | (ty @ &Ty::Var(_), &Ty::Projection(ref proj))
+            | (ty @ &Ty::Dyn(_), &Ty::Projection(ref proj))
+            | (ty @ &Ty::Opaque(_), &Ty::Projection(ref proj))
             | (&Ty::Projection(ref proj), ty @ &Ty::Projection(_))
             | (&Ty::Projection(ref proj), ty @ &Ty::Apply(_))
             | (&Ty::Projection(ref proj), ty @ &Ty::ForAll(_))
-            | (&Ty::Projection(ref proj), ty @ &Ty::Var(_)) => self.unify_projection_ty(proj, ty),
+            | (&Ty::Projection(ref proj), ty @ &Ty::Var(_))
+            | (&Ty::Projection(ref proj), ty @ &Ty::Dyn(_))
+            | (&Ty::Projection(ref proj), ty @ &Ty::Opaque(_)) => self.unify_projection_ty(proj, ty),
 
             (ty @ &Ty::Apply(_), &Ty::UnselectedProjection(ref proj))
             | (ty @ &Ty::ForAll(_), &Ty::UnselectedProjection(ref proj))
             | (ty @ &Ty::Var(_), &Ty::UnselectedProjection(ref proj))
+            | (ty @ &Ty::Dyn(_), &Ty::UnselectedProjection(ref proj))
+            | (ty @ &Ty::Opaque(_), &Ty::UnselectedProjection(ref proj))
             | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::Apply(_))
             | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::ForAll(_))
-            | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::Var(_)) => {
+            | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::Var(_))
+            | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::Dyn(_))
+            | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::Opaque(_)) => {
                 self.unify_unselected_projection_ty(proj, ty)
             }
         }
@@ -224,6 +341,18 @@ impl<'t> Unifier<'t> {
         )))
     }
 
+    /// An opaque type only equals another type if its hidden type does,
+    /// which can only be proven when it is revealed; see `OpaqueEq`.
+    fn unify_opaque_ty(&mut self, opaque: &OpaqueTy, ty: &Ty) -> Fallible<()> {
+        Ok(self.goals.push(InEnvironment::new(
+            self.environment,
+            OpaqueEq {
+                opaque: opaque.clone(),
+                ty: ty.clone(),
+            }.cast(),
+        )))
+    }
+
     fn unify_unselected_projection_ty(
         &mut self,
         proj: &UnselectedProjectionTy,
@@ -262,18 +391,151 @@ impl<'t> Unifier<'t> {
         // as the variable is unified.
         let universe_index = self.table.universe_of_unbound_var(var);
 
-        let ty1 = ty.fold_with(&mut OccursCheck::new(self, var, universe_index), 0)?;
+        // Most of the time, the occurs check has nothing to rewrite,
+        // so check that first without allocating and only fold if we
+        // must.
+        let ty1 = if self.occurs_check_needs_fold(var, universe_index, ty, 0)? {
+            ty.fold_with(&mut OccursCheck::new(self, var, universe_index), 0)?
+        } else {
+            ty.clone()
+        };
 
+        debug!("unify_var_ty: var {:?} set to {:?}", var, ty1);
         self.table
             .unify
-            .unify_var_value(var, InferenceValue::from(ty1.clone()))
+            .unify_var_value(var, InferenceValue::from(ty1))
             .unwrap();
-        debug!("unify_var_ty: var {:?} set to {:?}", var, ty1);
 
         Ok(())
     }
 
+    /// A read-only pre-flight for `OccursCheck`: walks `ty` (which
+    /// appears beneath `binders` binders) and returns `Ok(false)` if
+    /// folding it with `OccursCheck` would yield an identical copy,
+    /// in which case the caller can just clone it. Returns `Ok(true)`
+    /// if the fold has real work to do -- resolving bound variables,
+    /// promoting universes, or introducing lifetime constraints --
+    /// and `Err(NoSolution)` if the occurs check is certain to fail.
+    fn occurs_check_needs_fold(
+        &mut self,
+        var: InferenceVariable,
+        universe_index: UniverseIndex,
+        ty: &Ty,
+        binders: usize,
+    ) -> Fallible<bool> {
+        match *ty {
+            Ty::Var(depth) => {
+                if depth < binders {
+                    return Ok(false);
+                }
+                if binders > 0 {
+                    // Free variables beneath binders are rare; leave
+                    // them to the fold.
+                    return Ok(true);
+                }
+                let v = InferenceVariable::from_depth(depth);
+                match self.table.unify.probe_value(v) {
+                    InferenceValue::Bound(_) => Ok(true),
+                    InferenceValue::Unbound(ui) => {
+                        if self.table.unify.unioned(v, var) {
+                            return Err(NoSolution);
+                        }
+                        Ok(universe_index < ui)
+                    }
+                }
+            }
+
+            Ty::Apply(ref apply) => {
+                if let TypeName::ForAll(ui) = apply.name {
+                    if universe_index < ui {
+                        return Err(NoSolution);
+                    }
+                }
+                self.occurs_check_parameters_need_fold(
+                    var,
+                    universe_index,
+                    &apply.parameters,
+                    binders,
+                )
+            }
+
+            Ty::Projection(ref proj) => self.occurs_check_parameters_need_fold(
+                var,
+                universe_index,
+                &proj.parameters,
+                binders,
+            ),
+
+            Ty::UnselectedProjection(ref proj) => self.occurs_check_parameters_need_fold(
+                var,
+                universe_index,
+                &proj.parameters,
+                binders,
+            ),
+
+            Ty::Opaque(ref opaque) => self.occurs_check_parameters_need_fold(
+                var,
+                universe_index,
+                &opaque.parameters,
+                binders,
+            ),
+
+            Ty::ForAll(ref quantified_ty) => self.occurs_check_needs_fold(
+                var,
+                universe_index,
+                &quantified_ty.ty,
+                binders + quantified_ty.num_binders,
+            ),
+
+            // Rare enough to leave to the fold.
+            Ty::Dyn(_) => Ok(true),
+        }
+    }
+
+    fn occurs_check_parameters_need_fold(
+        &mut self,
+        var: InferenceVariable,
+        universe_index: UniverseIndex,
+        parameters: &[Parameter],
+        binders: usize,
+    ) -> Fallible<bool> {
+        let mut needs_fold = false;
+        for parameter in parameters {
+            needs_fold |= match *parameter {
+                ParameterKind::Ty(ref ty) => {
+                    self.occurs_check_needs_fold(var, universe_index, ty, binders)?
+                }
+                ParameterKind::Lifetime(Lifetime::Var(depth))
+                | ParameterKind::Const(Const::Var(depth)) => {
+                    if depth < binders {
+                        false
+                    } else if binders > 0 {
+                        true
+                    } else {
+                        match self.table.unify.probe_value(InferenceVariable::from_depth(depth)) {
+                            InferenceValue::Bound(_) => true,
+                            InferenceValue::Unbound(ui) => universe_index < ui,
+                        }
+                    }
+                }
+                ParameterKind::Lifetime(Lifetime::ForAll(ui)) => universe_index < ui,
+                ParameterKind::Const(Const::ForAll(ui)) => {
+                    if universe_index < ui {
+                        return Err(NoSolution);
+                    }
+                    false
+                }
+            };
+        }
+        Ok(needs_fold)
+    }
+
     fn unify_lifetime_lifetime(&mut self, a: &Lifetime, b: &Lifetime) -> Fallible<()> {
+        if self.table.erase_regions {
+            // All lifetimes are considered equal; see `erase_regions`.
+            return Ok(());
+        }
+
         if let Some(n_a) = self.table.normalize_lifetime(a, 0) {
             return self.unify_lifetime_lifetime(&n_a, b);
         } else if let Some(n_b) = self.table.normalize_lifetime(b, 0) {
@@ -326,10 +588,60 @@ impl<'t> Unifier<'t> {
         }
     }
 
+    fn unify_const_const(&mut self, a: &Const, b: &Const) -> Fallible<()> {
+        if let Some(n_a) = self.table.normalize_const(a, 0) {
+            return self.unify_const_const(&n_a, b);
+        } else if let Some(n_b) = self.table.normalize_const(b, 0) {
+            return self.unify_const_const(a, &n_b);
+        }
+
+        debug_heading!("unify_const_const({:?}, {:?})", a, b);
+
+        match (a, b) {
+            (&Const::Var(depth_a), &Const::Var(depth_b)) => {
+                let var_a = InferenceVariable::from_depth(depth_a);
+                let var_b = InferenceVariable::from_depth(depth_b);
+                debug!("unify_const_const: var_a={:?} var_b={:?}", var_a, var_b);
+                self.table.unify.unify_var_var(var_a, var_b).unwrap();
+                Ok(())
+            }
+
+            (&Const::Var(depth), c) | (c, &Const::Var(depth)) => {
+                let var = InferenceVariable::from_depth(depth);
+
+                // Unlike a lifetime, a const variable cannot be
+                // related to a placeholder it cannot name by a side
+                // constraint: it either is that placeholder or not.
+                if let Const::ForAll(ui) = *c {
+                    let var_ui = self.table.universe_of_unbound_var(var);
+                    if !var_ui.can_see(ui) {
+                        debug!(
+                            "unify_const_const: {:?} in {:?} cannot see {:?}",
+                            var, var_ui, ui
+                        );
+                        return Err(NoSolution);
+                    }
+                }
+
+                self.table
+                    .unify
+                    .unify_var_value(var, InferenceValue::from(*c))
+                    .unwrap();
+                Ok(())
+            }
+
+            (&Const::ForAll(_), _) | (&Const::Value(_), _) => if a == b {
+                Ok(())
+            } else {
+                Err(NoSolution)
+            },
+        }
+    }
+
     fn push_lifetime_eq_constraint(&mut self, a: Lifetime, b: Lifetime) {
         self.constraints.push(InEnvironment::new(
             self.environment,
-            Constraint::LifetimeEq(a, b),
+            Constraint::RegionEq(a, b),
         ));
     }
 }
@@ -343,6 +655,10 @@ impl<'t> Zipper for Unifier<'t> {
         self.unify_lifetime_lifetime(a, b)
     }
 
+    fn zip_consts(&mut self, a: &Const, b: &Const) -> Fallible<()> {
+        self.unify_const_const(a, b)
+    }
+
     fn zip_binders<T>(&mut self, _: &Binders<T>, _: &Binders<T>) -> Fallible<()>
     where
         T: Zip + Fold<Result = T>,
@@ -411,6 +727,18 @@ impl<'u, 't> UniversalFolder for OccursCheck<'u, 't> {
             Ok(ui.to_lifetime()) // no need to shift, not relative to depth
         }
     }
+
+    fn fold_free_universal_const(
+        &mut self,
+        universe: UniverseIndex,
+        _binders: usize,
+    ) -> Fallible<Const> {
+        if self.universe_index < universe {
+            Err(NoSolution)
+        } else {
+            Ok(universe.to_const()) // no need to shift, not relative to depth
+        }
+    }
 }
 
 impl<'u, 't> ExistentialFolder for OccursCheck<'u, 't> {
@@ -420,7 +748,12 @@ impl<'u, 't> ExistentialFolder for OccursCheck<'u, 't> {
             // If this variable already has a value, fold over that value instead.
             InferenceValue::Bound(normalized_ty) => {
                 let normalized_ty = normalized_ty.ty().unwrap();
-                Ok(normalized_ty.fold_with(self, 0)?.up_shift(binders))
+                let normalized_ty = normalized_ty.fold_with(self, 0)?;
+                if binders == 0 {
+                    Ok(normalized_ty)
+                } else {
+                    Ok(normalized_ty.up_shift(binders))
+                }
             }
 
             // Otherwise, check the universe of the variable, and also
@@ -482,4 +815,27 @@ impl<'u, 't> ExistentialFolder for OccursCheck<'u, 't> {
             }
         }
     }
+
+    fn fold_free_existential_const(&mut self, depth: usize, binders: usize) -> Fallible<Const> {
+        let v = InferenceVariable::from_depth(depth);
+        match self.unifier.table.unify.probe_value(v) {
+            InferenceValue::Unbound(ui) => {
+                if self.universe_index < ui {
+                    // As for lifetimes: promote the variable to the
+                    // universe of `self.var`.
+                    self.unifier
+                        .table
+                        .unify
+                        .unify_var_value(v, InferenceValue::Unbound(self.universe_index))
+                        .unwrap();
+                }
+                Ok(Const::Var(depth).up_shift(binders))
+            }
+
+            InferenceValue::Bound(c) => {
+                let c = c.constant().unwrap().up_shift(binders);
+                c.fold_with(self, binders)
+            }
+        }
+    }
 }
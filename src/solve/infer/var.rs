@@ -4,8 +4,8 @@ use std::cmp::min;
 use std::fmt;
 use std::u32;
 
-/// An inference variable represents an unknown term -- either a type
-/// or a lifetime. The variable itself is just an index into the unification
+/// An inference variable represents an unknown term -- a type, a
+/// lifetime or a const. The variable itself is just an index into the unification
 /// table; the unification table maps it to an `InferenceValue`.
 ///
 /// Inference variables can be in one of two states (represents by the variants
@@ -63,6 +63,13 @@ impl InferenceVariable {
     crate fn to_lifetime(self) -> Lifetime {
         Lifetime::Var(self.index as usize)
     }
+
+    /// Convert this inference variable into a const. When using this
+    /// method, naturally you should know from context that the kind
+    /// of this inference variable is a const (we can't check it).
+    crate fn to_const(self) -> Const {
+        Const::Var(self.index as usize)
+    }
 }
 
 impl UnifyKey for InferenceVariable {
@@ -102,6 +109,12 @@ impl From<Lifetime> for InferenceValue {
     }
 }
 
+impl From<Const> for InferenceValue {
+    fn from(constant: Const) -> Self {
+        InferenceValue::Bound(ParameterKind::Const(constant))
+    }
+}
+
 impl UnifyValue for InferenceValue {
     fn unify_values(
         a: &InferenceValue,
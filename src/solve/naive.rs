@@ -0,0 +1,513 @@
+//! A naive solver, meant for teaching: plain SLD resolution, as in
+//! Prolog, with an explicit goal stack and no tabling. Subgoals are
+//! proven depth-first, trying clauses in order and backtracking when
+//! one fails, and nothing is remembered between subgoals. Cycles thus
+//! are not detected at all; the search just gives up on a branch once
+//! it is nested `max_depth` clauses deep.
+//!
+//! Comparing this solver against the SLG solver on cyclic programs
+//! (like `inner_cycle` in the tests) shows what tabling buys us. The
+//! selected goals are recorded as `NaiveStep`s, so the search can be
+//! followed step by step.
+
+use cast::Cast;
+use ir::could_match::CouldMatch;
+use ir::*;
+use rules;
+use solve::infer::{InferenceSnapshot, InferenceTable};
+use solve::trace::{ProofTrace, TracedAnswer, TracedGoal};
+use solve::{AmbiguityReason, CancellationToken, Guidance, Solution, SolveOptions, SolveOutcome};
+use solve::ambiguity::multiple_answers_reason;
+use std::fmt;
+use std::sync::Arc;
+
+/// The search stops once it has found this many distinct answers,
+/// since that is enough to know the goal is ambiguous.
+const MAX_ANSWERS: usize = 2;
+
+pub struct NaiveSolver {
+    program: Arc<ProgramEnvironment>,
+    max_depth: usize,
+
+    /// Whether the types mentioned by hypotheses are assumed to be
+    /// well-formed; see `SolverChoice::with_implied_bounds`.
+    implied_bounds: bool,
+    steps: Vec<NaiveStep>,
+
+    /// While solving with `solve_with_cancellation`, the token to check
+    /// before each step.
+    cancellation: Option<CancellationToken>,
+}
+
+/// One step of the search: the goal selected from the top of the goal
+/// stack, and the goals still on the stack below it (top first). Any
+/// inference variables already bound are shown with their values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NaiveStep {
+    /// The number of clauses used to reach `goal` from the root goal.
+    pub depth: usize,
+    pub goal: Goal,
+    pub stack: Vec<Goal>,
+}
+
+impl fmt::Display for NaiveStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{:1$}{2:?}", "", self.depth * 2, self.goal)?;
+        if !self.stack.is_empty() {
+            write!(f, " (then {} more)", self.stack.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct PendingGoal {
+    environment: Arc<Environment>,
+    goal: Goal,
+    depth: usize,
+}
+
+/// The state of one branch of the search.
+#[derive(Clone)]
+struct Path {
+    goals: Vec<PendingGoal>,
+    constraints: Vec<InEnvironment<Constraint>>,
+
+    /// Set if some goal on this branch could be neither proven nor
+    /// refuted, so that its answer is only ambiguous.
+    ambiguity: Option<AmbiguityReason>,
+}
+
+impl Path {
+    fn make_ambiguous(&mut self, reason: AmbiguityReason) {
+        self.ambiguity = Some(self.ambiguity.map_or(reason, |r| r.max(reason)));
+    }
+}
+
+/// A domain goal whose clauses have not all been tried yet. Going
+/// back to it undoes everything done since the snapshot was taken.
+struct ChoicePoint {
+    snapshot: InferenceSnapshot,
+    path: Path,
+    goal: InEnvironment<DomainGoal>,
+    depth: usize,
+
+    /// The clauses still to try, last one first.
+    clauses: Vec<ProgramClause>,
+}
+
+#[derive(Default)]
+struct Answers {
+    answers: Vec<(Canonical<ConstrainedSubst>, Option<AmbiguityReason>)>,
+
+    /// Set if some branch was cut off at `max_depth`, so that there
+    /// may be answers we did not find.
+    overflowed: bool,
+}
+
+impl NaiveSolver {
+    pub fn new(program: &Arc<ProgramEnvironment>, max_depth: usize) -> Self {
+        NaiveSolver {
+            program: program.clone(),
+            max_depth,
+            implied_bounds: false,
+            steps: vec![],
+            cancellation: None,
+        }
+    }
+
+    /// If `implied_bounds` is true, the types mentioned by hypotheses
+    /// are assumed to be well-formed; see `rules::environment_clauses`.
+    pub fn with_implied_bounds(self, implied_bounds: bool) -> Self {
+        NaiveSolver {
+            implied_bounds,
+            ..self
+        }
+    }
+
+    /// Solves the given root goal; see `SolverChoice::solve_root_goal`.
+    /// The steps taken are available from `steps` afterwards.
+    pub fn solve(&mut self, root_goal: &UCanonical<InEnvironment<Goal>>) -> Option<Solution> {
+        self.steps.clear();
+        self.search(root_goal, 0).into_solution(root_goal)
+    }
+
+    /// Like `solve`, but stops searching once the cancellation token
+    /// of `options` is cancelled, and records a `ProofTrace` if asked
+    /// to; see `SolverChoice::solve_root_goal`. Since nothing is
+    /// tabled, the trace only holds the root goal and its answers.
+    /// Also returns whether the search was cancelled, in which case
+    /// the solution is meaningless.
+    crate fn solve_with_options(
+        &mut self,
+        root_goal: &UCanonical<InEnvironment<Goal>>,
+        options: &SolveOptions,
+    ) -> (SolveOutcome, bool) {
+        self.steps.clear();
+        self.cancellation = options.cancellation.clone();
+        let answers = self.search(root_goal, 0);
+        self.cancellation = None;
+
+        let trace = if options.trace {
+            let traced_goal = TracedGoal {
+                goal: root_goal.clone(),
+                answers: answers
+                    .answers
+                    .iter()
+                    .map(|(subst, ambiguity)| TracedAnswer {
+                        subst: subst.clone(),
+                        ambiguous: ambiguity.is_some(),
+                    })
+                    .collect(),
+            };
+            Some(ProofTrace::new(vec![traced_goal]))
+        } else {
+            None
+        };
+        let outcome = SolveOutcome {
+            solution: answers.into_solution(root_goal),
+            trace,
+            ..SolveOutcome::default()
+        };
+        (outcome, options.is_cancelled())
+    }
+
+    /// The steps taken while solving the last goal, in order. Negated
+    /// goals are solved by a nested search, whose steps are included.
+    pub fn steps(&self) -> &[NaiveStep] {
+        &self.steps
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .map_or(false, |token| token.is_cancelled())
+    }
+
+    fn search(&mut self, root_goal: &UCanonical<InEnvironment<Goal>>, depth: usize) -> Answers {
+        let (mut infer, subst, InEnvironment { environment, goal }) =
+            InferenceTable::from_canonical(root_goal.universes, &root_goal.canonical);
+        let mut choice_points = vec![];
+        let mut answers = Answers::default();
+        let mut path = Some(Path {
+            goals: vec![PendingGoal {
+                environment,
+                goal,
+                depth,
+            }],
+            constraints: vec![],
+            ambiguity: None,
+        });
+
+        loop {
+            let mut current = match path.take() {
+                Some(current) => current,
+                None => match choice_points.pop() {
+                    Some(choice_point) => {
+                        path = self.backtrack(&mut infer, &mut choice_points, choice_point);
+                        continue;
+                    }
+                    None => return answers,
+                },
+            };
+
+            let pending = match current.goals.pop() {
+                Some(pending) => pending,
+                None => {
+                    // Every goal on this branch is proven: we have an answer.
+                    let answer = infer
+                        .canonicalize(&ConstrainedSubst {
+                            subst: subst.clone(),
+                            constraints: current.constraints,
+                        })
+                        .quantified;
+                    if current.ambiguity.is_none() && answer.value.is_trivial() {
+                        // This answer subsumes any other we could find.
+                        answers.answers = vec![(answer, None)];
+                        return answers;
+                    }
+                    if !answers.answers.iter().any(|(a, _)| *a == answer) {
+                        answers.answers.push((answer, current.ambiguity));
+                    }
+                    if answers.answers.len() >= MAX_ANSWERS {
+                        return answers;
+                    }
+                    continue;
+                }
+            };
+
+            if self.is_cancelled() {
+                return answers;
+            }
+
+            self.steps.push(NaiveStep {
+                depth: pending.depth,
+                goal: infer.normalize_deep(&pending.goal),
+                stack: current
+                    .goals
+                    .iter()
+                    .rev()
+                    .map(|p| infer.normalize_deep(&p.goal))
+                    .collect(),
+            });
+
+            path = self.select(&mut infer, &mut choice_points, &mut answers, current, pending);
+        }
+    }
+
+    /// Proves the goal `pending` taken from the top of `path`, giving
+    /// back the path to continue with, or `None` if the goal failed.
+    fn select(
+        &mut self,
+        infer: &mut InferenceTable,
+        choice_points: &mut Vec<ChoicePoint>,
+        answers: &mut Answers,
+        mut path: Path,
+        pending: PendingGoal,
+    ) -> Option<Path> {
+        let PendingGoal {
+            environment,
+            goal,
+            depth,
+        } = pending;
+        let push = |path: &mut Path, environment: &Arc<Environment>, goal: Goal| {
+            path.goals.push(PendingGoal {
+                environment: environment.clone(),
+                goal,
+                depth,
+            })
+        };
+
+        match goal {
+            Goal::Quantified(QuantifierKind::ForAll, binders) => {
+                let subgoal = infer.instantiate_binders_universally(&binders);
+                push(&mut path, &environment, *subgoal);
+            }
+            Goal::Quantified(QuantifierKind::Exists, binders) => {
+                let subgoal = infer.instantiate_binders_existentially(&binders);
+                push(&mut path, &environment, *subgoal);
+            }
+            Goal::Implies(clauses, subgoal) => {
+                let environment = Environment::add_clauses(&environment, clauses);
+                push(&mut path, &environment, *subgoal);
+            }
+            Goal::And(left, right) => {
+                // The stack is popped from the end, so the left-hand
+                // goal is proven first.
+                push(&mut path, &environment, *right);
+                push(&mut path, &environment, *left);
+            }
+            Goal::Not(subgoal) => {
+                // As in the SLG solver, negation is only known to hold
+                // for traits of the closed world.
+                if let Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(
+                    WhereClauseAtom::Implemented(ref trait_ref),
+                ))) = *subgoal
+                {
+                    if self.program.is_open_world_trait(trait_ref.trait_id) {
+                        let goal = DomainGoal::NotImplemented(trait_ref.clone()).cast();
+                        push(&mut path, &environment, goal);
+                        return Some(path);
+                    }
+                }
+
+                let canonical = infer
+                    .canonicalize(&InEnvironment::new(&environment, *subgoal))
+                    .quantified;
+                if !canonical.binders.is_empty() {
+                    // The negated goal still has inference variables:
+                    // it flounders.
+                    path.make_ambiguous(AmbiguityReason::FlounderedNegation);
+                    return Some(path);
+                }
+
+                let subgoal = infer.u_canonicalize(&canonical).quantified;
+                let result = self.search(&subgoal, depth);
+                match result.answers.iter().map(|(_, ambiguity)| *ambiguity).min() {
+                    // The negated goal holds, so this one fails.
+                    Some(None) => return None,
+                    Some(Some(reason)) => path.make_ambiguous(reason),
+                    None if result.overflowed => path.make_ambiguous(AmbiguityReason::Overflow),
+                    None => {}
+                }
+            }
+            Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })) => {
+                let result = infer.unify(&environment, &a, &b).ok()?;
+                path.constraints.extend(result.constraints);
+                for goal in result.goals {
+                    push(&mut path, &goal.environment, goal.goal.cast());
+                }
+            }
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Outlives(Outlives {
+                a: ParameterKind::Lifetime(a),
+                b,
+            }))) => {
+                // No clauses relate two lifetimes; see `DomainGoal::Outlives`.
+                let result = infer.outlives(&environment, &a, &b);
+                path.constraints.extend(result.constraints);
+            }
+            Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => {
+                if depth >= self.max_depth {
+                    answers.overflowed = true;
+                    return None;
+                }
+
+                let mut clauses = self.clauses(&environment, &infer.normalize_deep(&domain_goal));
+                clauses.reverse();
+                let snapshot = infer.snapshot();
+                return self.backtrack(
+                    infer,
+                    choice_points,
+                    ChoicePoint {
+                        snapshot,
+                        path,
+                        goal: InEnvironment::new(&environment, domain_goal),
+                        depth,
+                        clauses,
+                    },
+                );
+            }
+            Goal::CannotProve(()) => {
+                // The SLG solver reports this as a negative cycle, since
+                // that is how it treats `CannotProve`.
+                path.make_ambiguous(AmbiguityReason::NegativeCycle);
+            }
+        }
+
+        Some(path)
+    }
+
+    /// Tries the remaining clauses of `choice_point` in turn, giving
+    /// back the path of the first that applies. If any clauses are
+    /// left after it, the choice point is kept for later.
+    fn backtrack(
+        &mut self,
+        infer: &mut InferenceTable,
+        choice_points: &mut Vec<ChoicePoint>,
+        mut choice_point: ChoicePoint,
+    ) -> Option<Path> {
+        infer.rollback_to(choice_point.snapshot);
+        while let Some(clause) = choice_point.clauses.pop() {
+            let snapshot = infer.snapshot();
+            let path = self.resolve(
+                infer,
+                &choice_point.path,
+                &choice_point.goal,
+                choice_point.depth,
+                &clause,
+            );
+            if let Some(path) = path {
+                choice_points.push(ChoicePoint {
+                    snapshot,
+                    ..choice_point
+                });
+                return Some(path);
+            }
+            infer.rollback_to(snapshot);
+        }
+        None
+    }
+
+    /// Unifies `goal`, taken from `path` at the given depth, with the
+    /// consequence of `clause`, and pushes the clause's conditions in
+    /// its place.
+    fn resolve(
+        &self,
+        infer: &mut InferenceTable,
+        path: &Path,
+        goal: &InEnvironment<DomainGoal>,
+        depth: usize,
+        clause: &ProgramClause,
+    ) -> Option<Path> {
+        let ProgramClauseImplication {
+            consequence,
+            conditions,
+        } = match clause {
+            ProgramClause::Implies(implication) => implication.clone(),
+            ProgramClause::ForAll(implication) => infer.instantiate_binders_existentially(implication),
+        };
+
+        let InEnvironment { environment, goal } = goal;
+        let result = infer.unify(environment, goal, &consequence).ok()?;
+
+        let mut path = path.clone();
+        let depth = depth + 1;
+        path.constraints.extend(result.constraints);
+        for condition in conditions.into_iter().rev() {
+            path.goals.push(PendingGoal {
+                environment: environment.clone(),
+                goal: condition,
+                depth,
+            });
+        }
+        for goal in result.goals {
+            path.goals.push(PendingGoal {
+                environment: goal.environment,
+                goal: goal.goal.cast(),
+                depth,
+            });
+        }
+        Some(path)
+    }
+
+    /// The clauses that could prove `goal`: the hypotheses in the
+    /// environment, then the program clauses, in order, then the clauses
+    /// generated on demand for the built-in types in `goal`, or for those
+    /// its self type may still turn out to be.
+    fn clauses(&self, environment: &Arc<Environment>, goal: &DomainGoal) -> Vec<ProgramClause> {
+        rules::environment_clauses(environment, self.implied_bounds)
+            .into_iter()
+            .chain(self.program.program_clauses.iter().cloned())
+            .filter(|clause| clause.could_match(goal))
+            .chain(rules::builtin_type_clauses(&self.program, goal))
+            .collect()
+    }
+}
+
+impl Answers {
+    fn into_solution(self, root_goal: &UCanonical<InEnvironment<Goal>>) -> Option<Solution> {
+        let Answers {
+            mut answers,
+            overflowed,
+        } = self;
+
+        if answers.len() > 1 {
+            let reason = answers.iter().filter_map(|(_, ambiguity)| *ambiguity).max();
+            let reason = reason.unwrap_or_else(|| {
+                let first = &answers[0].0;
+                let answers_differ = answers[1..].iter().any(|(answer, _)| {
+                    answer.binders != first.binders || answer.value.subst != first.value.subst
+                });
+                multiple_answers_reason(&root_goal.canonical.value.goal, answers_differ)
+            });
+            return Some(Solution::Ambig(Guidance::Unknown, reason));
+        }
+
+        match answers.pop() {
+            None if overflowed => Some(Solution::Ambig(Guidance::Unknown, AmbiguityReason::Overflow)),
+            None => None,
+            Some((answer, Some(reason))) => Some(Solution::Ambig(
+                Guidance::Definite(answer.map(|cs| cs.subst)),
+                reason,
+            )),
+            Some((answer, None)) => {
+                // The branches we cut off might have had other answers,
+                // unless this one subsumes them all.
+                if overflowed && !answer.value.is_trivial() {
+                    let guidance = Guidance::Suggested(answer.map(|cs| cs.subst));
+                    Some(Solution::Ambig(guidance, AmbiguityReason::Overflow))
+                } else {
+                    Some(Solution::Unique(answer))
+                }
+            }
+        }
+    }
+}
+
+impl ConstrainedSubst {
+    /// True if this answer binds no variables and imposes no
+    /// constraints, so that it holds however the goal is instantiated.
+    fn is_trivial(&self) -> bool {
+        self.constraints.is_empty() && self.subst.is_identity_subst()
+    }
+}
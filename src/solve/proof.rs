@@ -0,0 +1,63 @@
+//! The derivations of answers, as recorded by the SLG solver, for
+//! debugging solutions that come out wrong: which program clauses
+//! were used to prove a goal, and which answers to its subgoals.
+
+use ir::*;
+use std::fmt;
+
+/// The derivation of an answer to a goal; see `SolveOutcome::proof`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofTree {
+    /// The goal, as it was tabled.
+    pub goal: UCanonical<InEnvironment<Goal>>,
+
+    /// The answer derived for `goal`.
+    pub answer: Canonical<ConstrainedSubst>,
+
+    /// True if the answer is not known to hold, e.g. because it was
+    /// truncated or depends on a negative cycle.
+    pub ambiguous: bool,
+
+    /// The program clause or hypothesis that was used, or `None` if
+    /// `goal` is not a domain goal -- like `exists<T> { ... }` -- and
+    /// was broken down into subgoals instead.
+    pub clause: Option<ProgramClause>,
+
+    /// The derivations of the answers to subgoals that were used, in
+    /// the order they were used. Subgoals that held coinductively, or
+    /// that were negative, are left out.
+    pub subproofs: Vec<ProofTree>,
+}
+
+impl ProofTree {
+    /// The number of goals in the tree, including this one.
+    pub fn size(&self) -> usize {
+        1 + self.subproofs.iter().map(|subproof| subproof.size()).sum::<usize>()
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(f, "{:1$}{2:?}", "", depth * 2, self.goal.canonical.value.goal)?;
+        if !self.answer.value.subst.parameters.is_empty() {
+            write!(f, " with {}", self.answer.value.subst)?;
+        }
+        if self.ambiguous {
+            write!(f, " (ambiguous)")?;
+        }
+        write!(f, "\n")?;
+        if let Some(clause) = &self.clause {
+            write!(f, "{:1$}using {2:?}\n", "", depth * 2 + 2, clause)?;
+        }
+        for subproof in &self.subproofs {
+            subproof.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows one goal per line, indented by its depth in the tree, each
+/// followed by the clause used to prove it.
+impl fmt::Display for ProofTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
@@ -0,0 +1,149 @@
+//! Logging the canonical queries that solvers work on, to find work
+//! that is done more than once. Within one `Solver`, every root goal
+//! and every subgoal is solved at most once; but goals solved with
+//! separate solvers -- or with `SolverChoice::solve_root_goal`, which
+//! creates a fresh one each time -- share nothing, and a log attached
+//! to all of them shows what a shared solver would have saved.
+
+use ir::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Where a query was solved.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum QueryLevel {
+    /// A root goal given to `Solver::solve`. Goals answered from the
+    /// solver's cache are not logged, since they are not solved again.
+    Root,
+
+    /// A table created for a subgoal while solving a root goal. The
+    /// table for the root goal itself counts as well.
+    Subgoal,
+}
+
+/// Logs every canonical query solved by the solvers it is attached to;
+/// see `SolverChoice::into_solver_with_query_log`. Clones share the
+/// same log.
+#[derive(Clone, Debug, Default)]
+pub struct QueryLog {
+    data: Arc<Mutex<QueryLogData>>,
+}
+
+#[derive(Debug, Default)]
+struct QueryLogData {
+    /// If set, solving some query more often makes the solver fail.
+    max_repeats: Option<usize>,
+
+    /// How often each query was solved, by level.
+    counts: HashMap<(QueryLevel, UCanonical<InEnvironment<Goal>>), usize>,
+
+    /// The queries in `counts`, in the order they were first solved.
+    queries: Vec<(QueryLevel, UCanonical<InEnvironment<Goal>>)>,
+
+    /// The first query to be solved more than `max_repeats` times.
+    exceeded: Option<RepeatedQuery>,
+}
+
+/// A query that was solved more than once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepeatedQuery {
+    pub level: QueryLevel,
+    pub goal: UCanonical<InEnvironment<Goal>>,
+
+    /// The number of times the query was solved.
+    pub count: usize,
+}
+
+/// How many queries of one level were solved, and how many of those
+/// were distinct.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    pub solved: usize,
+    pub distinct: usize,
+}
+
+impl QueryStats {
+    /// The number of queries that were solved again, which a shared
+    /// cache could have saved.
+    pub fn duplicates(&self) -> usize {
+        self.solved - self.distinct
+    }
+}
+
+impl QueryLog {
+    pub fn new() -> Self {
+        QueryLog::default()
+    }
+
+    /// Creates a log in "assertion mode": once some query has been
+    /// solved more than `max_repeats` times, the solvers it is attached
+    /// to fail with `ErrorKind::RepeatedQuery`.
+    pub fn with_max_repeats(max_repeats: usize) -> Self {
+        let log = QueryLog::new();
+        log.data.lock().unwrap().max_repeats = Some(max_repeats);
+        log
+    }
+
+    /// The limit set with `with_max_repeats`, if any.
+    pub fn max_repeats(&self) -> Option<usize> {
+        self.data.lock().unwrap().max_repeats
+    }
+
+    crate fn record(&self, level: QueryLevel, goal: &UCanonical<InEnvironment<Goal>>) {
+        let mut data = self.data.lock().unwrap();
+        let key = (level, goal.clone());
+        let count = {
+            let count = data.counts.entry(key.clone()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        if count == 1 {
+            data.queries.push(key);
+        }
+
+        let exceeds = data.max_repeats.map_or(false, |max_repeats| count > max_repeats);
+        if exceeds && data.exceeded.is_none() {
+            data.exceeded = Some(RepeatedQuery {
+                level,
+                goal: goal.clone(),
+                count,
+            });
+        }
+    }
+
+    /// The statistics for the queries of the given level.
+    pub fn stats(&self, level: QueryLevel) -> QueryStats {
+        let data = self.data.lock().unwrap();
+        data.counts
+            .iter()
+            .filter(|((query_level, _), _)| *query_level == level)
+            .fold(QueryStats::default(), |stats, (_, &count)| QueryStats {
+                solved: stats.solved + count,
+                distinct: stats.distinct + 1,
+            })
+    }
+
+    /// The queries that were solved more than once, most repeated
+    /// first; queries repeated equally often are listed in the order
+    /// they were first solved.
+    pub fn repeated(&self) -> Vec<RepeatedQuery> {
+        let data = self.data.lock().unwrap();
+        let mut repeated: Vec<_> = data.queries
+            .iter()
+            .map(|key| RepeatedQuery {
+                level: key.0,
+                goal: key.1.clone(),
+                count: data.counts[key],
+            })
+            .filter(|query| query.count > 1)
+            .collect();
+        repeated.sort_by(|a, b| b.count.cmp(&a.count));
+        repeated
+    }
+
+    /// In assertion mode, the first query that was solved more than
+    /// the allowed number of times, if any.
+    pub fn exceeded(&self) -> Option<RepeatedQuery> {
+        self.data.lock().unwrap().exceeded.clone()
+    }
+}
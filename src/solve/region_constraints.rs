@@ -0,0 +1,157 @@
+//! A typed view of the region constraints attached to a solution, for
+//! handing them to a host's region solver (e.g., a borrow checker)
+//! without going through their debug representation.
+
+use ir::*;
+use solve::Solution;
+use std::fmt;
+use std::sync::Arc;
+
+/// Requires that two regions be equal. The regions are given in terms
+/// of the solution they were extracted from: `Lifetime::Var(i)` is the
+/// solution's `i`th canonical variable and `Lifetime::ForAll(u)` is the
+/// placeholder region of universe `u`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegionEquality {
+    /// The environment in which the constraint arose. Its clauses may
+    /// imply further relationships between regions that the host is
+    /// free to take into account.
+    pub environment: Arc<Environment>,
+    pub a: Lifetime,
+    pub b: Lifetime,
+}
+
+/// Requires that `longer: shorter`; the regions are given as for
+/// `RegionEquality`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegionOutlives {
+    pub environment: Arc<Environment>,
+    pub longer: Lifetime,
+    pub shorter: Lifetime,
+}
+
+/// The region constraints that must hold for a solution to be valid.
+///
+/// Hosts that only understand outlives relationships can use
+/// `outlives_edges` or `export_to`, which express each equality as a
+/// pair of edges.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RegionConstraintSet {
+    pub equalities: Vec<RegionEquality>,
+    pub outlives: Vec<RegionOutlives>,
+}
+
+/// A host's region solver, which receives the constraints of a
+/// `RegionConstraintSet` via `RegionConstraintSet::export_to`.
+pub trait RegionConstraintSink {
+    /// The host's representation of regions.
+    type Region: Clone;
+
+    /// Translates a region of the constraint set into the host's
+    /// representation; see `RegionEquality` for what the regions mean.
+    fn region(&mut self, lifetime: Lifetime) -> Self::Region;
+
+    /// Records that `longer: shorter`.
+    fn outlives(&mut self, longer: Self::Region, shorter: Self::Region);
+
+    /// Records that `a` and `b` are equal. By default, this records
+    /// that each outlives the other.
+    fn equal(&mut self, a: Self::Region, b: Self::Region) {
+        self.outlives(a.clone(), b.clone());
+        self.outlives(b, a);
+    }
+}
+
+impl RegionConstraintSet {
+    crate fn from_constraints(constraints: &[InEnvironment<Constraint>]) -> Self {
+        let mut set = RegionConstraintSet::default();
+        for constraint in constraints {
+            let environment = constraint.environment.clone();
+            match constraint.goal {
+                Constraint::Outlives(longer, shorter) => set.outlives.push(RegionOutlives {
+                    environment,
+                    longer,
+                    shorter,
+                }),
+                Constraint::RegionEq(a, b) => set.equalities.push(RegionEquality { environment, a, b }),
+            }
+        }
+        set
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.equalities.is_empty() && self.outlives.is_empty()
+    }
+
+    /// Returns every constraint as an outlives edge `(longer,
+    /// shorter)`; an equality `a == b` yields both `(a, b)` and `(b, a)`.
+    pub fn outlives_edges<'a>(&'a self) -> impl Iterator<Item = (Lifetime, Lifetime)> + 'a {
+        let equalities = self.equalities
+            .iter()
+            .flat_map(|equality| Constraint::RegionEq(equality.a, equality.b).outlives_edges());
+        let outlives = self.outlives
+            .iter()
+            .map(|outlives| (outlives.longer, outlives.shorter));
+        equalities.chain(outlives)
+    }
+
+    /// Feeds every constraint to `sink`. Environments are not passed
+    /// along; hosts that need them can walk `equalities` and
+    /// `outlives` directly.
+    pub fn export_to<S: RegionConstraintSink>(&self, sink: &mut S) {
+        for equality in &self.equalities {
+            let a = sink.region(equality.a);
+            let b = sink.region(equality.b);
+            sink.equal(a, b);
+        }
+        for outlives in &self.outlives {
+            let longer = sink.region(outlives.longer);
+            let shorter = sink.region(outlives.shorter);
+            sink.outlives(longer, shorter);
+        }
+    }
+}
+
+impl Solution {
+    /// Returns the region constraints of a unique solution. Ambiguous
+    /// solutions carry no region constraints, since we have not
+    /// committed to any particular answer; for them, this returns
+    /// `None`. So do multiple solutions, whose answers each carry
+    /// their own constraints.
+    pub fn region_constraints(&self) -> Option<RegionConstraintSet> {
+        match self {
+            Solution::Unique(constrained) => Some(RegionConstraintSet::from_constraints(
+                &constrained.value.constraints,
+            )),
+            Solution::Ambig(..) | Solution::MultipleSolutions(..) => None,
+        }
+    }
+}
+
+impl RegionConstraintSet {
+    /// Renders the constraints like `Display` does, but with
+    /// placeholders given the names they had in the goal.
+    pub fn display_with_names(&self, names: &PlaceholderNames) -> String {
+        names.named(self).to_string()
+    }
+}
+
+impl fmt::Display for RegionConstraintSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let equalities = self.equalities
+            .iter()
+            .map(|equality| Constraint::RegionEq(equality.a, equality.b));
+        let outlives = self.outlives
+            .iter()
+            .map(|outlives| Constraint::Outlives(outlives.longer, outlives.shorter));
+
+        write!(f, "[")?;
+        for (index, constraint) in equalities.chain(outlives).enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", constraint)?;
+        }
+        write!(f, "]")
+    }
+}
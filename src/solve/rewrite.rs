@@ -0,0 +1,219 @@
+//! Rewriting root goals before they are solved, so that new
+//! desugarings -- e.g., an alternative encoding of well-formedness --
+//! can be prototyped without changing how goals are lowered.
+//!
+//! A rewrite rule has a pattern and a replacement, quantified over the
+//! same pattern variables. Wherever a goal matches the pattern, it is
+//! replaced, with each pattern variable standing for the type or
+//! lifetime it matched. Matching is hygienic: a pattern variable only
+//! matches a term that does not refer to binders introduced within
+//! the matched goal, and the replacement is shifted as needed when
+//! placed under binders of its own.
+
+use errors::*;
+use fallible::*;
+use fold::Subst;
+use fold::shift::Shift;
+use fold::Fold;
+use ir::*;
+use zip::{Zip, Zipper};
+
+/// A rule rewriting goals that match `pattern` into `replacement`; see
+/// `Solver::add_rewrite`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GoalRewrite {
+    pattern: Binders<Goal>,
+    replacement: Binders<Goal>,
+}
+
+impl GoalRewrite {
+    /// Creates a rule from two lowered goals. If the pattern is of
+    /// the form `forall<...> { P }`, its binders are the pattern
+    /// variables and `P` is matched; the replacement must then be of
+    /// the form `forall<...> { R }`, with the same binders, and
+    /// matching goals are replaced with `R`. Otherwise, the pattern
+    /// has no variables and is matched as is.
+    ///
+    /// Every pattern variable must appear in the pattern.
+    pub fn new(pattern: Goal, replacement: Goal) -> Result<GoalRewrite> {
+        let (binders, pattern) = match pattern {
+            Goal::Quantified(QuantifierKind::ForAll, pattern) => (pattern.binders, *pattern.value),
+            pattern => (vec![], pattern),
+        };
+        let replacement = if binders.is_empty() {
+            replacement
+        } else {
+            match replacement {
+                Goal::Quantified(QuantifierKind::ForAll, ref replacement)
+                    if replacement.binders == binders =>
+                {
+                    (*replacement.value).clone()
+                }
+                _ => bail!(ErrorKind::InvalidGoalRewrite(
+                    "the replacement must quantify over the same variables as the pattern"
+                )),
+            }
+        };
+
+        let rewrite = GoalRewrite {
+            pattern: Binders {
+                binders: binders.clone(),
+                value: pattern,
+            },
+            replacement: Binders {
+                binders,
+                value: replacement,
+            },
+        };
+
+        // Matching the pattern against itself binds exactly the
+        // variables that appear in it.
+        if rewrite.bindings(&rewrite.pattern.value).is_none() {
+            bail!(ErrorKind::InvalidGoalRewrite(
+                "every pattern variable must appear in the pattern"
+            ));
+        }
+        Ok(rewrite)
+    }
+
+    /// If `goal` matches the pattern, the value of each pattern
+    /// variable, in terms of the variables free in `goal`.
+    fn bindings(&self, goal: &Goal) -> Option<Vec<Parameter>> {
+        let mut matcher = Matcher {
+            kinds: &self.pattern.binders,
+            binders: 0,
+            bindings: vec![None; self.pattern.binders.len()],
+        };
+        Zip::zip_with(&mut matcher, &self.pattern.value, goal).ok()?;
+        matcher.bindings.into_iter().collect()
+    }
+
+    fn apply(&self, goal: &Goal) -> Option<Goal> {
+        let bindings = self.bindings(goal)?;
+        Some(Subst::apply(&bindings, &self.replacement.value))
+    }
+}
+
+/// Rewrites `goal` and its subgoals, from the outside in, with the
+/// first of `rewrites` that matches each. The result of a rewrite is
+/// not rewritten again, and the hypotheses of implications are left
+/// alone, as are the program clauses that goals are proven with.
+crate fn rewrite_goal(rewrites: &[GoalRewrite], goal: &Goal) -> Goal {
+    if let Some(rewritten) = rewrites.iter().filter_map(|rewrite| rewrite.apply(goal)).next() {
+        debug!("rewrite_goal: {:?} => {:?}", goal, rewritten);
+        return rewritten;
+    }
+
+    let rewrite = |goal: &Goal| Box::new(rewrite_goal(rewrites, goal));
+    match goal {
+        Goal::Quantified(kind, subgoal) => Goal::Quantified(*kind, subgoal.map_ref(|g| rewrite(g))),
+        Goal::Implies(hypotheses, subgoal) => Goal::Implies(hypotheses.clone(), rewrite(subgoal)),
+        Goal::And(a, b) => Goal::And(rewrite(a), rewrite(b)),
+        Goal::Not(subgoal) => Goal::Not(rewrite(subgoal)),
+        Goal::Leaf(_) | Goal::CannotProve(()) => goal.clone(),
+    }
+}
+
+/// Matches a pattern against a goal, zipping them together.
+struct Matcher<'k> {
+    /// The kinds of the pattern variables.
+    kinds: &'k [ParameterKind<()>],
+
+    /// The number of binders crossed within the goal so far, which
+    /// pattern variables may not refer to.
+    binders: usize,
+
+    bindings: Vec<Option<Parameter>>,
+}
+
+impl<'k> Matcher<'k> {
+    /// If `depth` refers to a pattern variable, the index of that
+    /// variable.
+    fn pattern_variable(&self, depth: usize) -> Option<usize> {
+        if depth >= self.binders {
+            Some(depth - self.binders)
+        } else {
+            None
+        }
+    }
+
+    fn bind(&mut self, index: usize, value: Parameter) -> Fallible<()> {
+        match (self.kinds.get(index), &value) {
+            (Some(ParameterKind::Ty(())), ParameterKind::Ty(_))
+            | (Some(ParameterKind::Lifetime(())), ParameterKind::Lifetime(_))
+            | (Some(ParameterKind::Const(())), ParameterKind::Const(_)) => {}
+            _ => return Err(NoSolution),
+        }
+        let value = value.down_shift(self.binders)?;
+        match &self.bindings[index] {
+            Some(bound) if *bound != value => return Err(NoSolution),
+            _ => {}
+        }
+        self.bindings[index] = Some(value);
+        Ok(())
+    }
+}
+
+impl<'k> Zipper for Matcher<'k> {
+    fn zip_tys(&mut self, pattern: &Ty, goal: &Ty) -> Fallible<()> {
+        match (pattern, goal) {
+            (&Ty::Var(depth), _) => match self.pattern_variable(depth) {
+                Some(index) => self.bind(index, ParameterKind::Ty(goal.clone())),
+                None if *goal == Ty::Var(depth) => Ok(()),
+                None => Err(NoSolution),
+            },
+            (Ty::Apply(pattern), Ty::Apply(goal)) => Zip::zip_with(self, pattern, goal),
+            (Ty::Projection(pattern), Ty::Projection(goal)) => Zip::zip_with(self, pattern, goal),
+            (Ty::UnselectedProjection(pattern), Ty::UnselectedProjection(goal)) => {
+                Zip::zip_with(self, pattern, goal)
+            }
+            (Ty::Dyn(pattern), Ty::Dyn(goal)) => Zip::zip_with(self, pattern, goal),
+            (Ty::Opaque(pattern), Ty::Opaque(goal)) => Zip::zip_with(self, pattern, goal),
+            (Ty::ForAll(pattern), Ty::ForAll(goal)) if pattern.num_binders == goal.num_binders => {
+                self.binders += pattern.num_binders;
+                let result = self.zip_tys(&pattern.ty, &goal.ty);
+                self.binders -= pattern.num_binders;
+                result
+            }
+            _ => Err(NoSolution),
+        }
+    }
+
+    fn zip_lifetimes(&mut self, pattern: &Lifetime, goal: &Lifetime) -> Fallible<()> {
+        match (pattern, goal) {
+            (&Lifetime::Var(depth), _) => match self.pattern_variable(depth) {
+                Some(index) => self.bind(index, ParameterKind::Lifetime(goal.clone())),
+                None if *goal == Lifetime::Var(depth) => Ok(()),
+                None => Err(NoSolution),
+            },
+            (Lifetime::ForAll(pattern), Lifetime::ForAll(goal)) if pattern == goal => Ok(()),
+            _ => Err(NoSolution),
+        }
+    }
+
+    fn zip_consts(&mut self, pattern: &Const, goal: &Const) -> Fallible<()> {
+        match (pattern, goal) {
+            (&Const::Var(depth), _) => match self.pattern_variable(depth) {
+                Some(index) => self.bind(index, ParameterKind::Const(*goal)),
+                None if *goal == Const::Var(depth) => Ok(()),
+                None => Err(NoSolution),
+            },
+            (Const::ForAll(pattern), Const::ForAll(goal)) if pattern == goal => Ok(()),
+            (Const::Value(pattern), Const::Value(goal)) if pattern == goal => Ok(()),
+            _ => Err(NoSolution),
+        }
+    }
+
+    fn zip_binders<T>(&mut self, pattern: &Binders<T>, goal: &Binders<T>) -> Fallible<()>
+    where
+        T: Zip + Fold<Result = T>,
+    {
+        if pattern.binders != goal.binders {
+            return Err(NoSolution);
+        }
+        self.binders += pattern.binders.len();
+        let result = Zip::zip_with(self, &pattern.value, &goal.value);
+        self.binders -= pattern.binders.len();
+        result
+    }
+}
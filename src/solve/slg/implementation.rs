@@ -2,32 +2,67 @@ use crate::cast::{Cast, Caster};
 use crate::fallible::Fallible;
 use crate::ir::could_match::CouldMatch;
 use crate::ir::*;
+use crate::rules;
 use crate::solve::infer::ucanonicalize::{UCanonicalized, UniverseMap};
 use crate::solve::infer::unify::UnificationResult;
-use crate::solve::infer::InferenceTable;
+use crate::solve::environment::{EnvironmentOverflowLog, SharedEnvironments};
+use crate::solve::infer::{InferenceTable, InferenceTablePool};
 use crate::solve::truncate::{self, Truncated};
-use crate::solve::Solution;
+use crate::solve::ambiguity::AmbiguityInfo;
+use crate::solve::proof::ProofTree;
+use crate::solve::trace::{ProofTrace, TracedAnswer, TracedGoal};
+use crate::solve::{AggregationPolicy, ClauseOrder, CycleReport, LimitReport, QueryLevel, QueryLog,
+                   Solution, SolveOptions, SolveOutcome, SubgoalOrder};
 
 use chalk_engine::context;
 use chalk_engine::forest::Forest;
 use chalk_engine::hh::HhGoal;
-use chalk_engine::{DelayedLiteral, ExClause, Literal};
+use chalk_engine::{DelayedLiteral, ExClause, Literal, MixedCyclePolicy, ProofNode};
 
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::usize;
 
 mod aggregate;
+mod plan;
 mod resolvent;
 
+use self::plan::SubgoalPlanLog;
+
 #[derive(Clone, Debug)]
 pub struct SlgContext {
     program: Arc<ProgramEnvironment>,
     max_size: usize,
+    mixed_cycle_policy: MixedCyclePolicy,
+    erase_regions: bool,
+    max_environment_clauses: usize,
+    environment_overflows: EnvironmentOverflowLog,
+    environments: SharedEnvironments,
+    trivial_answer_cut: bool,
+    clause_order: ClauseOrder,
+    aggregation_policy: AggregationPolicy,
+    query_log: Option<QueryLog>,
+    subgoal_order: SubgoalOrder,
+    subgoal_plans: Option<SubgoalPlanLog>,
+    record_proofs: bool,
+    implied_bounds: bool,
+
+    /// Tables that are reused whenever a goal or ex-clause is
+    /// instantiated, since each is only needed until the callback
+    /// working on it returns.
+    inference_tables: InferenceTablePool,
 }
 
 pub struct TruncatingInferenceTable {
     program: Arc<ProgramEnvironment>,
     max_size: usize,
+    clause_order: ClauseOrder,
+    max_environment_clauses: usize,
+    environment_overflows: EnvironmentOverflowLog,
+    environments: SharedEnvironments,
+    subgoal_order: SubgoalOrder,
+    subgoal_plans: Option<SubgoalPlanLog>,
+    implied_bounds: bool,
     infer: InferenceTable,
 }
 
@@ -36,16 +71,259 @@ impl SlgContext {
         SlgContext {
             program: program.clone(),
             max_size,
+            mixed_cycle_policy: MixedCyclePolicy::default(),
+            erase_regions: false,
+            max_environment_clauses: usize::MAX,
+            environment_overflows: Arc::new(Mutex::new(vec![])),
+            environments: SharedEnvironments::default(),
+            trivial_answer_cut: true,
+            clause_order: ClauseOrder::default(),
+            aggregation_policy: AggregationPolicy::default(),
+            query_log: None,
+            subgoal_order: SubgoalOrder::default(),
+            subgoal_plans: None,
+            record_proofs: false,
+            implied_bounds: false,
+            inference_tables: InferenceTablePool::default(),
+        }
+    }
+
+    crate fn with_mixed_cycle_policy(self, mixed_cycle_policy: MixedCyclePolicy) -> SlgContext {
+        SlgContext {
+            mixed_cycle_policy,
+            ..self
+        }
+    }
+
+    /// If `erase_regions` is true, all lifetimes are considered equal
+    /// while solving; see `InferenceTable::erase_regions`.
+    crate fn with_erased_regions(self, erase_regions: bool) -> SlgContext {
+        SlgContext {
+            erase_regions,
+            ..self
+        }
+    }
+
+    /// Limits the number of clauses in the environments of subgoals;
+    /// see `EnvironmentOverflow`.
+    crate fn with_max_environment_clauses(self, max_environment_clauses: usize) -> SlgContext {
+        SlgContext {
+            max_environment_clauses,
+            ..self
         }
     }
 
-    /// Convenience fn for solving a root goal.
+    /// If `trivial_answer_cut` is false, tables keep looking for
+    /// answers even after finding one that subsumes all others; see
+    /// `ContextOps::trivial_answer_cut`.
+    crate fn with_trivial_answer_cut(self, trivial_answer_cut: bool) -> SlgContext {
+        SlgContext {
+            trivial_answer_cut,
+            ..self
+        }
+    }
+
+    /// Sets the order in which subgoals try their clauses; see
+    /// `ClauseOrder`.
+    crate fn with_clause_order(self, clause_order: ClauseOrder) -> SlgContext {
+        SlgContext {
+            clause_order,
+            ..self
+        }
+    }
+
+    /// Sets how the answers to a root goal are combined into a
+    /// solution; see `AggregationPolicy`.
+    crate fn with_aggregation_policy(self, aggregation_policy: AggregationPolicy) -> SlgContext {
+        SlgContext {
+            aggregation_policy,
+            ..self
+        }
+    }
+
+    /// Sets which pending subgoal of a clause is worked on next; see
+    /// `SubgoalOrder`.
+    crate fn with_subgoal_order(self, subgoal_order: SubgoalOrder) -> SlgContext {
+        SlgContext {
+            subgoal_order,
+            ..self
+        }
+    }
+
+    /// If `implied_bounds` is true, the types mentioned by hypotheses
+    /// are assumed to be well-formed; see `rules::environment_clauses`.
+    crate fn with_implied_bounds(self, implied_bounds: bool) -> SlgContext {
+        SlgContext {
+            implied_bounds,
+            ..self
+        }
+    }
+
+    /// Records every table created while solving in `query_log`; see
+    /// `QueryLevel::Subgoal`.
+    crate fn with_query_log(self, query_log: QueryLog) -> SlgContext {
+        SlgContext {
+            query_log: Some(query_log),
+            ..self
+        }
+    }
+
+    /// The log in which environment overflows are recorded while
+    /// solving.
+    crate fn environment_overflows(&self) -> &EnvironmentOverflowLog {
+        &self.environment_overflows
+    }
+
+    fn truncating_inference_table(&self, mut infer: InferenceTable) -> TruncatingInferenceTable {
+        if self.erase_regions {
+            infer.erase_regions();
+        }
+        TruncatingInferenceTable {
+            program: self.program.clone(),
+            max_size: self.max_size,
+            clause_order: self.clause_order,
+            max_environment_clauses: self.max_environment_clauses,
+            environment_overflows: self.environment_overflows.clone(),
+            environments: self.environments.clone(),
+            subgoal_order: self.subgoal_order,
+            subgoal_plans: self.subgoal_plans.clone(),
+            implied_bounds: self.implied_bounds,
+            infer,
+        }
+    }
+
+    /// Solves a root goal, recording what `options` asks for; see
+    /// `SolverChoice::solve_root_goal`. Also returns whether the search
+    /// was cut short by the budget or cancellation token of `options`,
+    /// in which case the solution is based only on the answers found
+    /// so far.
     crate fn solve_root_goal(
-        self,
+        mut self,
+        root_goal: &UCanonical<InEnvironment<Goal>>,
+        options: &SolveOptions,
+    ) -> (SolveOutcome, bool) {
+        // Proofs are needed to explain ambiguity as well.
+        self.record_proofs |= options.proof || options.ambiguity_info;
+        let plans = if options.trace {
+            let plans = SubgoalPlanLog::default();
+            self.subgoal_plans = Some(plans.clone());
+            Some(plans)
+        } else {
+            None
+        };
+
+        let mut forest = Forest::new(self.clone());
+        let (solution, cut_short) = forest.solve_with_limit(root_goal, options.should_continue());
+        let mut outcome = SolveOutcome {
+            solution,
+            ..SolveOutcome::default()
+        };
+
+        if options.cycles {
+            outcome.cycles = forest
+                .cycles()
+                .iter()
+                .map(|report| CycleReport {
+                    goal: report.table_goal.clone(),
+                    kind: report.kind,
+                })
+                .collect();
+        }
+
+        if options.limits_hit {
+            outcome.limits_hit = LimitReport::from_forest(forest.limits(), self.max_size);
+        }
+
+        if options.proof || options.ambiguity_info {
+            let mut proofs = self.proof_trees(&forest, root_goal);
+            if options.ambiguity_info {
+                outcome.ambiguity_info = outcome
+                    .solution
+                    .as_ref()
+                    .and_then(|solution| solution.ambiguity_reason())
+                    .map(|reason| AmbiguityInfo::from_proofs(reason, &proofs));
+            }
+
+            // The proof of the first answer that was proven outright,
+            // if any, or else of the first answer.
+            if options.proof {
+                let proven = proofs.iter().position(|proof| !proof.ambiguous);
+                outcome.proof = match proven {
+                    Some(index) => Some(proofs.swap_remove(index)),
+                    None => proofs.into_iter().next(),
+                };
+            }
+        }
+
+        if let Some(plans) = plans {
+            outcome.trace = Some(
+                ProofTrace::new(
+                    forest
+                        .tables_and_answers()
+                        .into_iter()
+                        .map(|(goal, answers)| TracedGoal {
+                            goal,
+                            answers: answers
+                                .into_iter()
+                                .map(|answer| TracedAnswer {
+                                    subst: answer.subst,
+                                    ambiguous: answer.ambiguous,
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                ).with_plans(plans.lock().unwrap().clone()),
+            );
+        }
+
+        (outcome, cut_short)
+    }
+
+    /// The derivations of every answer found for `root_goal`.
+    fn proof_trees(
+        &self,
+        forest: &Forest<SlgContext, SlgContext>,
         root_goal: &UCanonical<InEnvironment<Goal>>,
-    ) -> Option<Solution> {
-        let mut forest = Forest::new(self);
-        forest.solve(root_goal)
+    ) -> Vec<ProofTree> {
+        (0..)
+            .map(|index| forest.proof_tree(root_goal, index))
+            .take_while(|node| node.is_some())
+            .filter_map(|node| node.map(|node| self.proof_tree(node)))
+            .collect()
+    }
+
+    fn proof_tree(&self, node: ProofNode<SlgContext>) -> ProofTree {
+        ProofTree {
+            clause: node.clause
+                .and_then(|index| self.program_clause(&node.table_goal, index)),
+            goal: node.table_goal,
+            answer: node.answer.subst,
+            ambiguous: node.answer.ambiguous,
+            subproofs: node.children
+                .into_iter()
+                .map(|child| self.proof_tree(child))
+                .collect(),
+        }
+    }
+
+    /// The clause with the given index among those that might prove
+    /// `goal`, as numbered by `AnswerProof::clause`.
+    fn program_clause(
+        &self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        index: usize,
+    ) -> Option<ProgramClause> {
+        let (infer, _, InEnvironment { environment, goal }) =
+            InferenceTable::from_canonical(goal.universes, &goal.canonical);
+        let mut infer = self.truncating_inference_table(infer);
+        match context::InferenceTable::into_hh_goal(&mut infer, goal) {
+            HhGoal::DomainGoal(domain_goal) => {
+                context::UnificationOps::program_clauses(&infer, &environment, &domain_goal)
+                    .into_iter()
+                    .nth(index)
+            }
+            _ => None,
+        }
     }
 }
 
@@ -87,15 +365,44 @@ impl context::ContextOps<SlgContext> for SlgContext {
         goal.is_coinductive(&self.program)
     }
 
+    fn mixed_cycle_policy(&self) -> MixedCyclePolicy {
+        self.mixed_cycle_policy
+    }
+
+    fn trivial_answer_cut(&self) -> bool {
+        self.trivial_answer_cut
+    }
+
+    fn record_proofs(&self) -> bool {
+        self.record_proofs
+    }
+
+    fn max_answers(&self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<usize> {
+        match &goal.canonical.value.goal {
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(WhereClauseAtom::Implemented(
+                trait_ref,
+            )))) => self.program.max_answers(trait_ref.trait_id),
+            _ => None,
+        }
+    }
+
     fn instantiate_ucanonical_goal<R>(
         &self,
         arg: &UCanonical<InEnvironment<Goal>>,
         op: impl context::WithInstantiatedUCanonicalGoal<Self, Output = R>,
     ) -> R {
-        let (infer, subst, InEnvironment { environment, goal }) =
-            InferenceTable::from_canonical(arg.universes, &arg.canonical);
-        let dyn_infer = &mut TruncatingInferenceTable::new(&self.program, self.max_size, infer);
-        op.with(dyn_infer, subst, environment, goal)
+        // This is called once for every table, when it is created.
+        if let Some(query_log) = &self.query_log {
+            query_log.record(QueryLevel::Subgoal, arg);
+        }
+
+        let mut infer = self.inference_tables.take();
+        let (subst, InEnvironment { environment, goal }) =
+            infer.clear_and_instantiate_canonical(arg.universes, &arg.canonical);
+        let mut infer = self.truncating_inference_table(infer);
+        let result = op.with(&mut infer, subst, environment, goal);
+        self.inference_tables.put_back(infer.infer);
+        result
     }
 
     fn instantiate_ex_clause<R>(
@@ -104,10 +411,13 @@ impl context::ContextOps<SlgContext> for SlgContext {
         canonical_ex_clause: &Canonical<ExClause<SlgContext>>,
         op: impl context::WithInstantiatedExClause<Self, Output = R>,
     ) -> R {
-        let (infer, _subst, ex_cluse) =
-            InferenceTable::from_canonical(num_universes, canonical_ex_clause);
-        let dyn_infer = &mut TruncatingInferenceTable::new(&self.program, self.max_size, infer);
-        op.with(dyn_infer, ex_cluse)
+        let mut infer = self.inference_tables.take();
+        let (_subst, ex_cluse) =
+            infer.clear_and_instantiate_canonical(num_universes, canonical_ex_clause);
+        let mut infer = self.truncating_inference_table(infer);
+        let result = op.with(&mut infer, ex_cluse);
+        self.inference_tables.put_back(infer.infer);
+        result
     }
 
     fn inference_normalized_subst_from_ex_clause(
@@ -133,6 +443,26 @@ impl context::ContextOps<SlgContext> for SlgContext {
         u_canon.is_trivial_substitution(canonical_subst)
     }
 
+    fn identity_answer(u_canon: &UCanonical<InEnvironment<Goal>>) -> Canonical<ConstrainedSubst> {
+        let binders = &u_canon.canonical.binders;
+        let parameters = binders
+            .iter()
+            .zip(0..)
+            .map(|(binder, index)| match binder {
+                ParameterKind::Ty(_) => ParameterKind::Ty(Ty::Var(index)),
+                ParameterKind::Lifetime(_) => ParameterKind::Lifetime(Lifetime::Var(index)),
+                ParameterKind::Const(_) => ParameterKind::Const(Const::Var(index)),
+            })
+            .collect();
+        Canonical {
+            binders: binders.clone(),
+            value: ConstrainedSubst {
+                subst: Substitution { parameters },
+                constraints: vec![],
+            },
+        }
+    }
+
     fn num_universes(u_canon: &UCanonical<InEnvironment<Goal>>) -> usize {
         u_canon.universes
     }
@@ -152,16 +482,6 @@ impl context::ContextOps<SlgContext> for SlgContext {
     }
 }
 
-impl TruncatingInferenceTable {
-    fn new(program: &Arc<ProgramEnvironment>, max_size: usize, infer: InferenceTable) -> Self {
-        Self {
-            program: program.clone(),
-            max_size,
-            infer,
-        }
-    }
-}
-
 impl context::TruncateOps<SlgContext, SlgContext> for TruncatingInferenceTable {
     fn truncate_goal(&mut self, subgoal: &InEnvironment<Goal>) -> Option<InEnvironment<Goal>> {
         let Truncated { overflow, value } =
@@ -191,8 +511,25 @@ impl context::InferenceTable<SlgContext, SlgContext> for TruncatingInferenceTabl
             Goal::Quantified(QuantifierKind::Exists, binders_goal) => HhGoal::Exists(binders_goal),
             Goal::Implies(dg, subgoal) => HhGoal::Implies(dg, *subgoal),
             Goal::And(g1, g2) => HhGoal::And(*g1, *g2),
-            Goal::Not(g1) => HhGoal::Not(*g1),
+            Goal::Not(g1) => {
+                // Negation as failure assumes a closed world. For traits that other
+                // crates may implement, `not { T: Trait }` can only be established by
+                // an explicit negative impl.
+                if let Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(
+                    WhereClauseAtom::Implemented(ref trait_ref),
+                ))) = *g1
+                {
+                    if self.program.is_open_world_trait(trait_ref.trait_id) {
+                        return HhGoal::DomainGoal(DomainGoal::NotImplemented(trait_ref.clone()));
+                    }
+                }
+                HhGoal::Not(*g1)
+            }
             Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })) => HhGoal::Unify(a, b),
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Outlives(Outlives {
+                a: a @ ParameterKind::Lifetime(_),
+                b,
+            }))) => HhGoal::Outlives(a, ParameterKind::Lifetime(b)),
             Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => HhGoal::DomainGoal(domain_goal),
             Goal::CannotProve(()) => HhGoal::CannotProve,
         }
@@ -203,8 +540,14 @@ impl context::InferenceTable<SlgContext, SlgContext> for TruncatingInferenceTabl
         &mut self,
         env: &Arc<Environment>,
         clauses: Vec<ProgramClause>,
-    ) -> Arc<Environment> {
-        Environment::add_clauses(env, clauses)
+    ) -> Option<Arc<Environment>> {
+        Environment::add_clauses_within_limit(
+            env,
+            clauses,
+            self.max_environment_clauses,
+            &self.environments,
+            &self.environment_overflows,
+        )
     }
 }
 
@@ -214,19 +557,42 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
         environment: &Arc<Environment>,
         goal: &DomainGoal,
     ) -> Vec<ProgramClause> {
-        let environment_clauses = environment
-            .clauses
-            .iter()
-            .filter(|&env_clause| env_clause.could_match(goal))
-            .cloned();
+        let environment_clauses = match Environment::elaborated_clauses_within_limit(
+            environment,
+            self.implied_bounds,
+            self.max_environment_clauses,
+            &self.environment_overflows,
+        ) {
+            Some(clauses) => clauses,
+            None => {
+                // Like an environment that grew too large, this leaves
+                // us unable to say whether `goal` holds.
+                return vec![ProgramClause::Implies(ProgramClauseImplication {
+                    consequence: goal.clone(),
+                    conditions: vec![Goal::CannotProve(())],
+                })];
+            }
+        };
+        let environment_clauses = environment_clauses
+            .into_iter()
+            .filter(|env_clause| env_clause.could_match(goal));
 
-        let program_clauses = self.program
+        let mut program_clauses: Vec<_> = self.program
             .program_clauses
             .iter()
             .filter(|&clause| clause.could_match(goal))
-            .cloned();
+            .cloned()
+            .chain(rules::builtin_type_clauses(&self.program, goal))
+            .collect();
+        if self.clause_order.reverse_program_clauses {
+            program_clauses.reverse();
+        }
 
-        environment_clauses.chain(program_clauses).collect()
+        if self.clause_order.environment_first {
+            environment_clauses.chain(program_clauses).collect()
+        } else {
+            program_clauses.into_iter().chain(environment_clauses).collect()
+        }
     }
 
     fn instantiate_binders_universally(&mut self, arg: &Binders<Box<Goal>>) -> Goal {
@@ -255,11 +621,38 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
         self.infer.canonicalize(value).quantified
     }
 
+    /// Answers are compared (and deduplicated by the table) in this
+    /// canonical form, so we normalize away differences that do not
+    /// matter: the order in which the region constraints were found,
+    /// and constraints that were found more than once. Otherwise, two
+    /// ways of proving a goal that yield the same constraints would
+    /// look like distinct answers, and make the solution ambiguous.
     fn canonicalize_constrained_subst(
         &mut self,
         subst: Substitution,
         constraints: Vec<InEnvironment<Constraint>>,
     ) -> Canonical<ConstrainedSubst> {
+        // Sort by the canonical form of each constraint, rather than by
+        // the inference variables it happens to mention.
+        let canonical = self.infer
+            .canonicalize(&ConstrainedSubst {
+                subst: subst.clone(),
+                constraints: constraints.clone(),
+            })
+            .quantified;
+        let mut constraints: Vec<_> = canonical
+            .value
+            .constraints
+            .into_iter()
+            .zip(constraints)
+            .collect();
+        constraints.sort_by(|(a, _), (b, _)| a.cmp(b));
+        constraints.dedup_by(|(a, _), (b, _)| a == b);
+        let constraints = constraints
+            .into_iter()
+            .map(|(_, constraint)| constraint)
+            .collect();
+
         self.infer
             .canonicalize(&ConstrainedSubst { subst, constraints })
             .quantified
@@ -292,6 +685,20 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
         self.infer.unify(environment, a, b)
     }
 
+    fn outlives_parameters(
+        &mut self,
+        environment: &Arc<Environment>,
+        a: &Parameter,
+        b: &Parameter,
+    ) -> Fallible<UnificationResult> {
+        match (a, b) {
+            (&ParameterKind::Lifetime(a), &ParameterKind::Lifetime(b)) => {
+                Ok(self.infer.outlives(environment, &a, &b))
+            }
+            _ => panic!("outlives_parameters invoked with non-lifetimes: {:?}, {:?}", a, b),
+        }
+    }
+
     /// Since we do not have distinct types for the inference context and the slg-context,
     /// these conversion operations are just no-ops.q
     fn sink_answer_subset(&self, c: &Canonical<ConstrainedSubst>) -> Canonical<ConstrainedSubst> {
@@ -311,6 +718,13 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
     ) {
         into_ex_clause(result, ex_clause)
     }
+
+    fn select_subgoal(&mut self, subgoals: &[Literal<SlgContext>]) -> usize {
+        match self.subgoal_order {
+            SubgoalOrder::Last => subgoals.len() - 1,
+            SubgoalOrder::CheapestFirst => self.cheapest_subgoal(subgoals),
+        }
+    }
 }
 
 /// Helper function
@@ -343,7 +757,10 @@ impl MayInvalidate {
             (ParameterKind::Lifetime(l1), ParameterKind::Lifetime(l2)) => {
                 self.aggregate_lifetimes(l1, l2)
             }
-            (ParameterKind::Ty(_), _) | (ParameterKind::Lifetime(_), _) => panic!(
+            (ParameterKind::Const(c1), ParameterKind::Const(c2)) => self.aggregate_consts(c1, c2),
+            (ParameterKind::Ty(_), _)
+            | (ParameterKind::Lifetime(_), _)
+            | (ParameterKind::Const(_), _) => panic!(
                 "mismatched parameter kinds: new={:?} current={:?}",
                 new, current
             ),
@@ -384,10 +801,16 @@ impl MayInvalidate {
                 self.aggregate_unselected_projection_tys(apply1, apply2)
             }
 
+            (Ty::Opaque(opaque1), Ty::Opaque(opaque2)) => {
+                self.aggregate_opaque_tys(opaque1, opaque2)
+            }
+
             (Ty::ForAll(_), _)
             | (Ty::Apply(_), _)
             | (Ty::Projection(_), _)
-            | (Ty::UnselectedProjection(_), _) => true,
+            | (Ty::UnselectedProjection(_), _)
+            | (Ty::Dyn(_), _)
+            | (Ty::Opaque(_), _) => true,
         }
     }
 
@@ -395,6 +818,15 @@ impl MayInvalidate {
         true
     }
 
+    // Returns true if the two consts could be unequal; as for types.
+    fn aggregate_consts(&mut self, new: &Const, current: &Const) -> bool {
+        match (new, current) {
+            (_, Const::Var(_)) => false,
+            (Const::Var(_), _) => true,
+            (Const::ForAll(_), _) | (Const::Value(_), _) => new != current,
+        }
+    }
+
     fn aggregate_application_tys(&mut self, new: &ApplicationTy, current: &ApplicationTy) -> bool {
         let ApplicationTy {
             name: new_name,
@@ -421,6 +853,19 @@ impl MayInvalidate {
         self.aggregate_name_and_substs(new_name, new_parameters, current_name, current_parameters)
     }
 
+    fn aggregate_opaque_tys(&mut self, new: &OpaqueTy, current: &OpaqueTy) -> bool {
+        let OpaqueTy {
+            opaque_ty_id: new_name,
+            parameters: new_parameters,
+        } = new;
+        let OpaqueTy {
+            opaque_ty_id: current_name,
+            parameters: current_parameters,
+        } = current;
+
+        self.aggregate_name_and_substs(new_name, new_parameters, current_name, current_parameters)
+    }
+
     fn aggregate_unselected_projection_tys(
         &mut self,
         new: &UnselectedProjectionTy,
@@ -486,6 +931,7 @@ enum_fold!(LiteralSlgContext {
 });
 
 copy_fold!(::chalk_engine::TableIndex);
+copy_fold!(::chalk_engine::CannotProveReason);
 
 type DelayedLiteralSlgContext = DelayedLiteral<SlgContext>;
 enum_fold!(DelayedLiteralSlgContext {
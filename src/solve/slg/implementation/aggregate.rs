@@ -1,10 +1,12 @@
 use crate::cast::Cast;
 use crate::ir::*;
-use crate::solve::{Guidance, Solution};
+use crate::solve::{AggregationPolicy, AmbiguityReason, Guidance, Solution};
+use crate::solve::ambiguity::multiple_answers_reason;
 use crate::solve::infer::InferenceTable;
 
 use chalk_engine::context;
-use chalk_engine::SimplifiedAnswer;
+use chalk_engine::{CannotProveReason, SimplifiedAnswer};
+use smallvec::SmallVec;
 use std::fmt::Debug;
 
 use super::SlgContext;
@@ -21,13 +23,26 @@ impl context::AggregateOps<SlgContext> for SlgContext {
         if simplified_answers.peek_answer().is_none() {
             return None;
         }
-        let SimplifiedAnswer { subst, ambiguous } = simplified_answers.next_answer().unwrap();
+        let answer = simplified_answers.next_answer().unwrap();
+        let mut blocked = blocked_reason(&answer);
+        let SimplifiedAnswer { subst, ambiguous, .. } = answer;
 
         // Exactly 1 unconditional answer?
         if simplified_answers.peek_answer().is_none() && !ambiguous {
             return Some(Solution::Unique(subst));
         }
 
+        // Whether the answers we look at give different values to the
+        // variables of the goal, which tells apart a goal we know too
+        // little about from one that holds in several ways.
+        let first_subst = subst.clone().map(|cs| cs.subst);
+        let mut answers_differ = simplified_answers
+            .peek_answer()
+            .map_or(false, |answer| !same_subst(&answer.subst, &first_subst));
+        let reason = |blocked: Option<AmbiguityReason>, answers_differ: bool| {
+            blocked.unwrap_or_else(|| multiple_answers_reason(&root_goal.value.goal, answers_differ))
+        };
+
         // Otherwise, we either have >1 answer, or else we have
         // ambiguity.  Either way, we are only going to be giving back
         // **guidance**, and with guidance, the caller doesn't get
@@ -40,7 +55,44 @@ impl context::AggregateOps<SlgContext> for SlgContext {
         // cases into an `OR` region constraint at some point, but I
         // leave that for future work. This is basically
         // rust-lang/rust#21974.
-        let mut subst = subst.map(|cs| cs.subst);
+        let mut subst = match self.aggregation_policy {
+            AggregationPolicy::AntiUnify => subst.map(|cs| cs.subst),
+
+            AggregationPolicy::FirstAnswer => {
+                let guidance = Guidance::Suggested(subst.map(|cs| cs.subst));
+                return Some(Solution::Ambig(guidance, reason(blocked, answers_differ)));
+            }
+
+            AggregationPolicy::AllAnswers { max_answers } => {
+                if ambiguous {
+                    subst.map(|cs| cs.subst)
+                } else {
+                    // Unlike guidance, each of these answers keeps its
+                    // region constraints.
+                    let mut answers = vec![subst];
+                    while answers.len() < max_answers {
+                        match simplified_answers.peek_answer() {
+                            Some(ref answer) if !answer.ambiguous => {}
+                            _ => break,
+                        }
+                        let answer = simplified_answers.next_answer().unwrap().subst;
+                        answers_differ |= !same_subst(&answer, &first_subst);
+                        answers.push(answer);
+                    }
+                    if simplified_answers.peek_answer().is_none() {
+                        return Some(Solution::MultipleSolutions(answers));
+                    }
+
+                    // Too many answers, or an ambiguous one: fall back
+                    // to guidance covering the answers drawn so far.
+                    let mut answers = answers.into_iter();
+                    let first = answers.next().unwrap().map(|cs| cs.subst);
+                    answers.fold(first, |subst, answer| {
+                        merge_into_guidance(root_goal, subst, &answer)
+                    })
+                }
+            }
+        };
 
         // Extract answers and merge them into `subst`. Stop once we have
         // a trivial subst (or run out of answers).
@@ -63,6 +115,8 @@ impl context::AggregateOps<SlgContext> for SlgContext {
 
             match simplified_answers.next_answer() {
                 Some(answer1) => {
+                    blocked = blocked.max(blocked_reason(&answer1));
+                    answers_differ |= !same_subst(&answer1.subst, &first_subst);
                     subst = merge_into_guidance(root_goal, subst, &answer1.subst);
                 }
 
@@ -72,8 +126,31 @@ impl context::AggregateOps<SlgContext> for SlgContext {
             }
         };
 
-        Some(Solution::Ambig(guidance))
+        Some(Solution::Ambig(guidance, reason(blocked, answers_differ)))
+    }
+}
+
+/// The reason that `answer` is ambiguous in itself, if it is. An
+/// answer that was proven outright only makes a solution ambiguous
+/// because there are others alongside it; see
+/// `multiple_answers_reason`.
+fn blocked_reason(answer: &SimplifiedAnswer<SlgContext>) -> Option<AmbiguityReason> {
+    if !answer.ambiguous {
+        return None;
     }
+
+    Some(match answer.cannot_prove {
+        Some(CannotProveReason::Overflow) => AmbiguityReason::Overflow,
+        Some(CannotProveReason::Floundered) => AmbiguityReason::FlounderedNegation,
+        Some(CannotProveReason::MixedCycle) => AmbiguityReason::MixedCycle,
+        None => AmbiguityReason::NegativeCycle,
+    })
+}
+
+/// Whether `answer` gives the variables of the goal the values in
+/// `subst`, whatever its region constraints.
+fn same_subst(answer: &Canonical<ConstrainedSubst>, subst: &Canonical<Substitution>) -> bool {
+    answer.binders == subst.binders && answer.value.subst == subst.value
 }
 
 /// Given a current substitution used as guidance for `root_goal`, and
@@ -98,7 +175,7 @@ fn merge_into_guidance(
 
     // Collect the types that the two substitutions have in
     // common.
-    let aggr_parameters: Vec<_> = guidance
+    let aggr_parameters: SmallVec<_> = guidance
         .value
         .parameters
         .iter()
@@ -110,22 +187,26 @@ fn merge_into_guidance(
             // of X.
             let universe = root_goal.binders[index].into_inner();
 
+            let mut aggr = AntiUnifier {
+                infer: &mut infer,
+                universe,
+            };
+
             let ty = match value {
                 ParameterKind::Ty(ty) => ty,
                 ParameterKind::Lifetime(_) => {
                     // Ignore the lifetimes from the substitution: we're just
                     // creating guidance here anyway.
-                    return infer.new_variable(universe).to_lifetime().cast();
+                    return aggr.new_lifetime_variable().cast();
+                }
+                ParameterKind::Const(c) => {
+                    return aggr.aggregate_consts(c, value1.assert_const_ref()).cast();
                 }
             };
 
             let ty1 = value1.assert_ty_ref();
 
             // Combine the two types into a new type.
-            let mut aggr = AntiUnifier {
-                infer: &mut infer,
-                universe,
-            };
             aggr.aggregate_tys(&ty, ty1).cast()
         })
         .collect();
@@ -156,6 +237,9 @@ fn is_trivial(subst: &Canonical<Substitution>) -> bool {
             // And no lifetime mappings. (This is too strict, but we never
             // product substs with lifetimes.)
             ParameterKind::Lifetime(_) => false,
+
+            // Consts are like types.
+            ParameterKind::Const(c) => *c == Const::Var(index),
         })
 }
 
@@ -184,7 +268,7 @@ impl<'infer> AntiUnifier<'infer> {
             // &'a u32)` and `for<'a, 'b> fn(&'a u32, &'b u32)` seems
             // kinda' hard. Don't try to be smart for now, just plop a
             // variable in there and be done with it.
-            (Ty::ForAll(_), Ty::ForAll(_)) => self.new_variable(),
+            (Ty::ForAll(_), Ty::ForAll(_)) | (Ty::Dyn(_), Ty::Dyn(_)) => self.new_variable(),
 
             (Ty::Apply(apply1), Ty::Apply(apply2)) => {
                 self.aggregate_application_tys(apply1, apply2)
@@ -198,12 +282,18 @@ impl<'infer> AntiUnifier<'infer> {
                 self.aggregate_unselected_projection_tys(apply1, apply2)
             }
 
+            (Ty::Opaque(opaque1), Ty::Opaque(opaque2)) => {
+                self.aggregate_opaque_tys(opaque1, opaque2)
+            }
+
             // Mismatched base kinds.
             (Ty::Var(_), _)
             | (Ty::ForAll(_), _)
             | (Ty::Apply(_), _)
             | (Ty::Projection(_), _)
-            | (Ty::UnselectedProjection(_), _) => self.new_variable(),
+            | (Ty::UnselectedProjection(_), _)
+            | (Ty::Dyn(_), _)
+            | (Ty::Opaque(_), _) => self.new_variable(),
         }
     }
 
@@ -242,6 +332,26 @@ impl<'infer> AntiUnifier<'infer> {
             .unwrap_or_else(|| self.new_variable())
     }
 
+    fn aggregate_opaque_tys(&mut self, opaque1: &OpaqueTy, opaque2: &OpaqueTy) -> Ty {
+        let OpaqueTy {
+            opaque_ty_id: name1,
+            parameters: parameters1,
+        } = opaque1;
+        let OpaqueTy {
+            opaque_ty_id: name2,
+            parameters: parameters2,
+        } = opaque2;
+
+        self.aggregate_name_and_substs(name1, parameters1, name2, parameters2)
+            .map(|(&opaque_ty_id, parameters)| {
+                Ty::Opaque(OpaqueTy {
+                    opaque_ty_id,
+                    parameters,
+                })
+            })
+            .unwrap_or_else(|| self.new_variable())
+    }
+
     fn aggregate_unselected_projection_tys(
         &mut self,
         proj1: &UnselectedProjectionTy,
@@ -308,7 +418,12 @@ impl<'infer> AntiUnifier<'infer> {
             (ParameterKind::Lifetime(l1), ParameterKind::Lifetime(l2)) => {
                 ParameterKind::Lifetime(self.aggregate_lifetimes(l1, l2))
             }
-            (ParameterKind::Ty(_), _) | (ParameterKind::Lifetime(_), _) => {
+            (ParameterKind::Const(c1), ParameterKind::Const(c2)) => {
+                ParameterKind::Const(self.aggregate_consts(c1, c2))
+            }
+            (ParameterKind::Ty(_), _)
+            | (ParameterKind::Lifetime(_), _)
+            | (ParameterKind::Const(_), _) => {
                 panic!("mismatched parameter kinds: p1={:?} p2={:?}", p1, p2)
             }
         }
@@ -326,6 +441,18 @@ impl<'infer> AntiUnifier<'infer> {
         }
     }
 
+    fn aggregate_consts(&mut self, c1: &Const, c2: &Const) -> Const {
+        match (c1, c2) {
+            (Const::Var(_), _) | (_, Const::Var(_)) => self.new_const_variable(),
+
+            (Const::ForAll(_), _) | (Const::Value(_), _) => if c1 == c2 {
+                *c1
+            } else {
+                self.new_const_variable()
+            },
+        }
+    }
+
     fn new_variable(&mut self) -> Ty {
         self.infer.new_variable(self.universe).to_ty()
     }
@@ -333,6 +460,10 @@ impl<'infer> AntiUnifier<'infer> {
     fn new_lifetime_variable(&mut self) -> Lifetime {
         self.infer.new_variable(self.universe).to_lifetime()
     }
+
+    fn new_const_variable(&mut self) -> Const {
+        self.infer.new_variable(self.universe).to_const()
+    }
 }
 
 /// Test the equivalent of `Vec<i32>` vs `Vec<u32>`
@@ -0,0 +1,82 @@
+//! Choosing which pending subgoal of an ex-clause to work on next,
+//! for `SubgoalOrder::CheapestFirst`.
+//!
+//! The cost of a subgoal is only a rough estimate: how many clauses
+//! might prove it, and how many of its inference variables are still
+//! unresolved. Picking subgoals that no clause matches first makes the
+//! strand fail early, and putting off subgoals like `?T: Sized` until
+//! their variables are resolved avoids enumerating every type that
+//! could be plugged in. Negative subgoals that would flounder are put
+//! off for as long as possible.
+
+use crate::ir::*;
+use crate::solve::trace::{SubgoalCost, SubgoalPlan};
+
+use chalk_engine::context::UnificationOps;
+use chalk_engine::Literal;
+use std::sync::{Arc, Mutex};
+
+use super::{SlgContext, TruncatingInferenceTable};
+
+/// The log in which the choices made while solving are recorded, so
+/// they can be included in a `ProofTrace`.
+crate type SubgoalPlanLog = Arc<Mutex<Vec<SubgoalPlan>>>;
+
+impl TruncatingInferenceTable {
+    /// Picks the subgoal with the lowest cost; among equally cheap
+    /// subgoals, the last one, as `SubgoalOrder::Last` would.
+    crate fn cheapest_subgoal(&mut self, subgoals: &[Literal<SlgContext>]) -> usize {
+        let costs: Vec<_> = subgoals
+            .iter()
+            .map(|subgoal| self.subgoal_cost(subgoal))
+            .collect();
+        let selected = (0..costs.len())
+            .rev()
+            .min_by_key(|&index| costs[index])
+            .unwrap();
+
+        if subgoals.len() > 1 && self.subgoal_plans.is_some() {
+            let subgoals = subgoals
+                .iter()
+                .zip(costs)
+                .map(|(subgoal, cost)| (self.normalize_literal(subgoal), cost))
+                .collect();
+            let plans = self.subgoal_plans.as_ref().unwrap();
+            plans.lock().unwrap().push(SubgoalPlan { subgoals, selected });
+        }
+
+        selected
+    }
+
+    fn subgoal_cost(&mut self, subgoal: &Literal<SlgContext>) -> SubgoalCost {
+        let (goal, negative) = match subgoal {
+            Literal::Positive(goal) => (goal, false),
+            Literal::Negative(goal) => (goal, true),
+        };
+
+        let unbound_variables = self.infer.canonicalize(goal).quantified.binders.len();
+        let candidates = match &goal.goal {
+            Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => {
+                let domain_goal = self.infer.normalize_deep(domain_goal);
+                self.program_clauses(&goal.environment, &domain_goal).len()
+            }
+
+            // Other goals are simplified rather than matched against
+            // clauses.
+            _ => 1,
+        };
+
+        SubgoalCost {
+            flounders: negative && unbound_variables > 0,
+            candidates,
+            unbound_variables,
+        }
+    }
+
+    fn normalize_literal(&mut self, subgoal: &Literal<SlgContext>) -> Goal {
+        match subgoal {
+            Literal::Positive(goal) => self.infer.normalize_deep(&goal.goal),
+            Literal::Negative(goal) => Goal::Not(Box::new(self.infer.normalize_deep(&goal.goal))),
+        }
+    }
+}
@@ -93,8 +93,16 @@ impl context::ResolventOps<SlgContext, SlgContext> for TruncatingInferenceTable
         debug!("consequence = {:?}", consequence);
         debug!("conditions = {:?}", conditions);
 
-        // Unify the selected literal Li with C'.
-        let unification_result = self.infer.unify(environment, goal, &consequence)?;
+        // Unify the selected literal Li with C'. On failure, the
+        // snapshot must still be closed, since the table is reused once
+        // the strand is done with it (see `InferenceTable::clear`).
+        let unification_result = match self.infer.unify(environment, goal, &consequence) {
+            Ok(result) => result,
+            Err(error) => {
+                self.infer.rollback_to(snapshot);
+                return Err(error);
+            }
+        };
 
         // Final X-clause that we will return.
         let mut ex_clause = ExClause {
@@ -274,7 +282,7 @@ impl<'t> AnswerSubstitutor<'t> {
     fn unify_free_answer_var(
         &mut self,
         answer_depth: usize,
-        pending: ParameterKind<&Ty, &Lifetime>,
+        pending: ParameterKind<&Ty, &Lifetime, &Const>,
     ) -> Fallible<bool> {
         // This variable is bound in the answer, not free, so it
         // doesn't represent a reference into the answer substitution.
@@ -352,6 +360,10 @@ impl<'t> Zipper for AnswerSubstitutor<'t> {
                 Zip::zip_with(self, answer, pending)
             }
 
+            (Ty::Dyn(answer), Ty::Dyn(pending)) => Zip::zip_with(self, answer, pending),
+
+            (Ty::Opaque(answer), Ty::Opaque(pending)) => Zip::zip_with(self, answer, pending),
+
             (Ty::ForAll(answer), Ty::ForAll(pending)) => {
                 self.answer_binders += answer.num_binders;
                 self.pending_binders += pending.num_binders;
@@ -365,7 +377,9 @@ impl<'t> Zipper for AnswerSubstitutor<'t> {
             | (Ty::Apply(_), _)
             | (Ty::Projection(_), _)
             | (Ty::UnselectedProjection(_), _)
-            | (Ty::ForAll(_), _) => panic!(
+            | (Ty::ForAll(_), _)
+            | (Ty::Dyn(_), _)
+            | (Ty::Opaque(_), _) => panic!(
                 "structural mismatch between answer `{:?}` and pending goal `{:?}`",
                 answer, pending,
             ),
@@ -400,6 +414,39 @@ impl<'t> Zipper for AnswerSubstitutor<'t> {
         }
     }
 
+    fn zip_consts(&mut self, answer: &Const, pending: &Const) -> Fallible<()> {
+        if let Some(pending) = self.table.normalize_const(pending, self.pending_binders) {
+            return Zip::zip_with(self, answer, &pending);
+        }
+
+        if let Const::Var(answer_depth) = answer {
+            if self.unify_free_answer_var(*answer_depth, ParameterKind::Const(pending))? {
+                return Ok(());
+            }
+        }
+
+        match (answer, pending) {
+            (Const::Var(answer_depth), Const::Var(pending_depth)) => {
+                self.assert_matching_vars(*answer_depth, *pending_depth)
+            }
+
+            (Const::ForAll(answer_ui), Const::ForAll(pending_ui)) => {
+                assert_eq!(answer_ui, pending_ui);
+                Ok(())
+            }
+
+            (Const::Value(answer_value), Const::Value(pending_value)) => {
+                assert_eq!(answer_value, pending_value);
+                Ok(())
+            }
+
+            (Const::Var(_), _) | (Const::ForAll(_), _) | (Const::Value(_), _) => panic!(
+                "structural mismatch between answer `{:?}` and pending goal `{:?}`",
+                answer, pending,
+            ),
+        }
+    }
+
     fn zip_binders<T>(&mut self, answer: &Binders<T>, pending: &Binders<T>) -> Fallible<()>
     where
         T: Zip + Fold<Result = T>,
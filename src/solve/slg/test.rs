@@ -31,7 +31,7 @@ fn solve_goal(program_text: &str, goals: Vec<(usize, usize, &str, &str)>) {
             SolverChoice::slg()
         ).unwrap()
     );
-    let env = &Arc::new(program.environment());
+    let env = &program.environment();
     ir::tls::set_current_program(&program, || {
         for (max_size, num_answers, goal_text, expected) in goals {
             println!("----------------------------------------------------------------------");
@@ -58,7 +58,7 @@ fn solve_goal_fixed_num_answers(program_text: &str, goals: Vec<(usize, usize, &s
             SolverChoice::slg()
         ).unwrap()
     );
-    let env = &Arc::new(program.environment());
+    let env = &program.environment();
     ir::tls::set_current_program(&program, || {
         for (max_size, num_answers, goal_text, expected) in goals {
             println!("----------------------------------------------------------------------");
@@ -88,7 +88,6 @@ fn basic() {
         program {
             trait Sized { }
 
-            struct i32 { }
             impl Sized for i32 { }
         }
 
@@ -119,7 +118,6 @@ fn breadth_first() {
         program {
             trait Sized { }
 
-            struct i32 { }
             impl Sized for i32 { }
 
             struct Vec<T> { }
@@ -271,7 +269,7 @@ fn flounder() {
                     delayed_literals: DelayedLiteralSet {
                         delayed_literals: {
                             CannotProve(
-                                ()
+                                Floundered
                             )
                         }
                     }
@@ -292,7 +290,6 @@ fn only_draw_so_many() {
             struct Vec<T> { }
             impl<T> Sized for Vec<T> where T: Sized { }
 
-            struct i32 { }
             impl Sized for i32 { }
 
             struct Slice<T> { }
@@ -302,7 +299,7 @@ fn only_draw_so_many() {
         goal {
             exists<T> { T: Sized }
         } fixed 2 with max 10 {
-            "Some(Ambig(Unknown))"
+            "Some(Ambig(Unknown, InsufficientTypeInfo))"
         }
     }
 }
@@ -318,7 +315,6 @@ fn only_draw_so_many_blow_up() {
             impl<T> Sized for Vec<T> where T: Sized { }
             impl<T> Foo for Vec<T> where T: Sized { }
 
-            struct i32 { }
             impl Sized for i32 { }
 
             struct Slice<T> { }
@@ -328,7 +324,7 @@ fn only_draw_so_many_blow_up() {
         goal {
             exists<T> { T: Foo }
         } fixed 2 with max 10 {
-            "Some(Ambig(Definite(Canonical { value: [?0 := Vec<?0>], binders: [Ty(U0)] })))"
+            "Some(Ambig(Definite(Canonical { value: [?0 := Vec<?0>], binders: [Ty(U0)] }), InsufficientTypeInfo))"
         }
     }
 }
@@ -340,7 +336,6 @@ fn negative_loop() {
         program {
             trait P { }
             trait Q { }
-            struct u32 { }
 
             forall<> { u32: P if not { u32: Q } }
             forall<> { u32: Q if not { u32: P } }
@@ -378,7 +373,6 @@ fn subgoal_cycle_uninhabited() {
             trait Foo { }
             struct Box<T> { }
             struct Vec<T> { }
-            struct u32 { }
             impl<T> Foo for Box<T> where Box<Vec<T>>: Foo { }
         }
 
@@ -447,7 +441,7 @@ fn subgoal_cycle_uninhabited() {
                     delayed_literals: DelayedLiteralSet {
                         delayed_literals: {
                             CannotProve(
-                                ()
+                                Overflow
                             )
                         }
                     }
@@ -504,7 +498,6 @@ fn subgoal_cycle_inhabited() {
             trait Foo { }
             struct Box<T> { }
             struct Vec<T> { }
-            struct u32 { }
             impl<T> Foo for Box<T> where Box<Vec<T>>: Foo { }
             impl Foo for u32 { }
         }
@@ -536,7 +529,6 @@ fn basic_region_constraint_from_positive_impl() {
         program {
             trait Foo { }
             struct Ref<'a, 'b, T> { }
-            struct u32 { }
             impl<'x, T> Foo for Ref<'x, 'x, T> { }
         }
 
@@ -764,7 +756,6 @@ fn contradiction() {
     test! {
         program {
             trait P { }
-            struct u32 { }
 
             forall<> { u32: P if not { u32: P } }
         }
@@ -885,7 +876,7 @@ fn cached_answers_1() {
                     delayed_literals: DelayedLiteralSet {
                         delayed_literals: {
                             CannotProve(
-                                ()
+                                Overflow
                             )
                         }
                     }
@@ -975,7 +966,7 @@ fn cached_answers_2() {
                     delayed_literals: DelayedLiteralSet {
                         delayed_literals: {
                             CannotProve(
-                                ()
+                                Overflow
                             )
                         }
                     }
@@ -1053,7 +1044,7 @@ fn cached_answers_3() {
                     delayed_literals: DelayedLiteralSet {
                         delayed_literals: {
                             CannotProve(
-                                ()
+                                Overflow
                             )
                         }
                     }
@@ -1084,7 +1075,6 @@ fn negative_answer_delayed_literal() {
         program {
             trait P { }
             trait Q { }
-            struct u32 { }
 
             forall<> { u32: P if not { u32: Q } }
             forall<> { u32: Q if not { u32: Q } }
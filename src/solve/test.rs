@@ -1,13 +1,22 @@
 #![cfg(test)]
 
 use errors::*;
+use invariants;
 use ir;
-use solve::{Solution, SolverChoice};
+use solve::{AggregationPolicy, AmbiguityReason, Budget, CancellationToken, ClauseOrder, EnvironmentOverflowKind, GoalCache, GoalRewrite, LimitKind,
+            MixedCyclePolicy, NaiveSolver, ProofTrace, QueryLevel, QueryLog, QueryStats, RegionConstraintSink, RegressionKind, Solution, SolveOptions,
+            Solver, SolverCache, SolverChoice, SubgoalOrder};
+use solve::compat;
+use solve::infer::InferenceTable;
+use chalk_engine::answers::{AnswerStore, StoredAnswer};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use test_util::*;
 
 mod bench;
+#[cfg(feature = "shared-prelude")]
+mod std_prelude;
 
 fn result_to_string(result: &Result<Option<Solution>>) -> String {
     match result {
@@ -39,6 +48,21 @@ macro_rules! test {
         solve_goal(stringify!($program), vec![$($parsed_goals),*])
     };
 
+    // goal { G } yields { "Y" } yields_using { impl N, ... } -- test that
+    // the SLG solver yields Y, and that G can be proven through exactly
+    // the listed impls, numbered from 0 in declaration order
+    (@program[$program:tt] @parsed_goals[$($parsed_goals:tt)*] @unparsed_goals[
+        goal $goal:tt yields { $expected:expr } yields_using { $(impl $impl_index:expr),* }
+        $($unparsed_goals:tt)*
+    ]) => {
+        test!(@program[$program]
+              @parsed_goals[
+                  $($parsed_goals)*
+                      (stringify!($goal), SolverChoice::slg(), $expected, Some(vec![$($impl_index),*]))
+              ]
+              @unparsed_goals[$($unparsed_goals)*])
+    };
+
     // goal { G } yields { "Y" } -- test both solvers behave the same (the default)
     (@program[$program:tt] @parsed_goals[$($parsed_goals:tt)*] @unparsed_goals[
         goal $goal:tt yields { $expected:expr }
@@ -47,7 +71,7 @@ macro_rules! test {
         test!(@program[$program]
               @parsed_goals[
                   $($parsed_goals)*
-                      (stringify!($goal), SolverChoice::slg(), $expected)
+                      (stringify!($goal), SolverChoice::slg(), $expected, None)
               ]
               @unparsed_goals[$($unparsed_goals)*])
     };
@@ -65,7 +89,7 @@ macro_rules! test {
     ]) => {
         test!(@program[$program]
               @parsed_goals[$($parsed_goals)*
-                            $($((stringify!($goal), $C, $expected))+)+]
+                            $($((stringify!($goal), $C, $expected, None))+)+]
               @unparsed_goals[goal $($unparsed_goals)*])
     };
 
@@ -75,24 +99,22 @@ macro_rules! test {
     ]) => {
         test!(@program[$program]
               @parsed_goals[$($parsed_goals)*
-                            $($((stringify!($goal), $C, $expected))+)+]
+                            $($((stringify!($goal), $C, $expected, None))+)+]
               @unparsed_goals[])
     };
 }
 
-fn solve_goal(program_text: &str, goals: Vec<(&str, SolverChoice, &str)>) {
+fn solve_goal(program_text: &str, goals: Vec<(&str, SolverChoice, &str, Option<Vec<usize>>)>) {
     println!("program {}", program_text);
     assert!(program_text.starts_with("{"));
     assert!(program_text.ends_with("}"));
-    let mut program_env_cache = HashMap::new();
-    for (goal_text, solver_choice, expected) in goals {
-        let (program, env) = program_env_cache.entry(solver_choice).or_insert_with(|| {
+    let mut program_cache = HashMap::new();
+    for (goal_text, solver_choice, expected, expected_impls) in goals {
+        let program = program_cache.entry(solver_choice).or_insert_with(|| {
             let program_text = &program_text[1..program_text.len() - 1]; // exclude `{}`
-            let program =
-                Arc::new(parse_and_lower_program(program_text, solver_choice).unwrap());
-            let env = Arc::new(program.environment());
-            (program, env)
+            Arc::new(parse_and_lower_program(program_text, solver_choice).unwrap())
         });
+        let env = program.environment();
 
         ir::tls::set_current_program(&program, || {
             println!("----------------------------------------------------------------------");
@@ -103,8 +125,21 @@ fn solve_goal(program_text: &str, goals: Vec<(&str, SolverChoice, &str)>) {
 
             println!("using solver: {:?}", solver_choice);
             let peeled_goal = goal.into_peeled_goal();
-            let result = solver_choice.solve_root_goal(&env, &peeled_goal);
+            let result = solver_choice
+                .solve_root_goal(&env, &peeled_goal, SolveOptions::default())
+                .map(|outcome| outcome.solution);
             assert_result(&result, expected);
+
+            if let Some(expected_impls) = expected_impls {
+                let impl_indices: Vec<_> = program
+                    .proving_impls(solver_choice, &peeled_goal)
+                    .unwrap()
+                    .into_iter()
+                    .map(|impl_id| program.impl_data.keys().position(|&id| id == impl_id).unwrap())
+                    .collect();
+                println!("proven using impls: {:?}", impl_indices);
+                assert_eq!(impl_indices, expected_impls);
+            }
         });
     }
 }
@@ -162,7 +197,6 @@ fn inner_cycle() {
             #[marker]
             trait B { }
 
-            struct i32 { }
             struct Vec<T> { }
 
             impl<T> A for T where T: B { }
@@ -180,6 +214,126 @@ fn inner_cycle() {
     }
 }
 
+#[test]
+fn naive_solver() {
+    // Without tables, the naive solver follows the cycle between `A`
+    // and `B` until it reaches its maximum depth, and only then tries
+    // the other impls. It does find the same answers as SLG in the
+    // end, though.
+    test! {
+        program {
+            #[marker]
+            trait A { }
+            #[marker]
+            trait B { }
+
+            struct Vec<T> { }
+
+            impl<T> A for T where T: B { }
+            impl A for i32 { }
+
+            impl<T> B for T where T: A { }
+            impl<T> B for Vec<T> where T: B { }
+        }
+
+        goal {
+            i32: A
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            Vec<Vec<i32>>: B
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            exists<T> { T: A }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Ambiguous"
+        }
+    }
+
+    test! {
+        program {
+            struct Foo { }
+            struct Bar { }
+            struct Vec<T> { }
+            trait Clone { }
+            impl<T> Clone for Vec<T> where T: Clone { }
+            impl Clone for Foo { }
+        }
+
+        goal {
+            exists<T> { Vec<T>: Clone }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique; substitution [?0 := Foo], lifetime constraints []"
+        }
+
+        goal {
+            Vec<Bar>: Clone
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> { if (T: Clone) { Vec<T>: Clone } }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            not { Bar: Clone }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique; substitution [], lifetime constraints []"
+        }
+    }
+
+    // A goal that only ever recurses: SLG sees the cycle, while the
+    // naive solver overflows.
+    test! {
+        program {
+            trait Foo { }
+            struct Vec<T> { }
+            impl<T> Foo for Vec<T> where Vec<Vec<T>>: Foo { }
+        }
+
+        goal {
+            exists<T> { Vec<T>: Foo }
+        } yields[SolverChoice::naive()] {
+            "Ambiguous; no inference guidance"
+        }
+    }
+}
+
+#[test]
+fn naive_solver_steps() {
+    let program = Arc::new(
+        parse_and_lower_program(
+            "struct Foo { } trait Clone { } impl Clone for Foo { }",
+            SolverChoice::naive(),
+        ).unwrap(),
+    );
+    let env = program.environment();
+
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Clone }").unwrap();
+        let mut solver = NaiveSolver::new(&env, 10);
+        let solution = solver.solve(&goal.into_peeled_goal()).unwrap();
+        assert_eq!(
+            format!("{}", solution),
+            "Unique; substitution [?0 := Foo], lifetime constraints []"
+        );
+
+        // The peeled goal is the first goal selected.
+        let first = &solver.steps()[0];
+        assert_eq!(first.depth, 0);
+        assert_eq!(format!("{:?}", first.goal), "Implemented(?0: Clone)");
+        assert!(first.stack.is_empty());
+    });
+}
+
 #[test]
 fn prove_infer() {
     test! {
@@ -285,7 +439,6 @@ fn prove_forall() {
 fn higher_ranked() {
     test! {
         program {
-            struct u8 { }
             struct SomeType<T> { }
             trait Foo<T> { }
             impl<U> Foo<u8> for SomeType<U> { }
@@ -349,7 +502,6 @@ fn cycle_many_solutions() {
         program {
             trait Foo { }
             struct S<T> { }
-            struct i32 { }
             impl<T> Foo for S<T> where T: Foo { }
             impl Foo for i32 { }
         }
@@ -372,7 +524,6 @@ fn cycle_unique_solution() {
             trait Foo { }
             trait Bar { }
             struct S<T> { }
-            struct i32 { }
             impl<T> Foo for S<T> where T: Foo, T: Bar { }
             impl Foo for i32 { }
         }
@@ -434,14 +585,13 @@ fn multiple_ambiguous_cycles() {
 }
 
 #[test]
-#[should_panic]
 fn overflow() {
     test! {
         program {
             trait Q { }
             struct Z { }
-            struct G<X>
-            struct S<X>
+            struct G<X> { }
+            struct S<X> { }
 
             impl Q for Z { }
             impl<X> Q for G<X> where X: Q { }
@@ -451,19 +601,56 @@ fn overflow() {
         // Will try to prove S<G<Z>>: Q then S<G<G<Z>>>: Q etc ad infinitum
         goal {
             S<Z>: Q
-        } yields {
-            ""
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Ambiguous"
         }
     }
 }
 
+#[test]
+fn overflow_error() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Q { } struct Z { } struct G<X> { } struct S<X> { }
+             impl Q for Z { } impl<X> Q for G<X> where X: Q { }
+             impl<X> Q for S<X> where X: Q, S<G<X>>: Q { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+
+    ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal();
+        let options = || SolveOptions::default().with_overflow_as_error(true);
+        for &solver_choice in &[SolverChoice::slg(), SolverChoice::naive()] {
+            let solver_choice = solver_choice.with_max_size(5);
+            assert_eq!(solver_choice.max_size(), 5);
+
+            match solver_choice.solve_root_goal(env, &goal("S<Z>: Q"), options()) {
+                Err(error) => match error.kind() {
+                    ErrorKind::Overflow(5) => {}
+                    kind => panic!("unexpected error: {}", kind),
+                },
+                Ok(outcome) => panic!("expected overflow, got {:?}", outcome.solution),
+            }
+
+            // Goals that stay within the limit are solved as usual.
+            assert_result(
+                &solver_choice
+                    .solve_root_goal(env, &goal("G<Z>: Q"), options())
+                    .map(|outcome| outcome.solution),
+                "Unique",
+            );
+        }
+    });
+}
+
 #[test]
 fn normalize_basic() {
     test! {
         program {
             trait Iterator { type Item; }
             struct Vec<T> { }
-            struct u32 { }
             impl<T> Iterator for Vec<T> {
                 type Item = T;
             }
@@ -552,7 +739,6 @@ fn normalize_implied_bound() {
         program {
             trait Clone { }
             trait Iterator where Self: Clone { type Item; }
-            struct u32 { }
         }
 
         goal {
@@ -567,6 +753,47 @@ fn normalize_implied_bound() {
     }
 }
 
+/// Like `normalize_implied_bound`, but for bounds declared on the
+/// associated type itself.
+#[test]
+fn assoc_ty_bound_implied_bound() {
+    test! {
+        program {
+            trait Clone { }
+            trait Eq<T> { }
+            trait Iterator { type Item: Clone + Eq<Self>; }
+        }
+
+        goal {
+            forall<T> {
+                if (T: Iterator) {
+                    <T as Iterator>::Item: Clone
+                }
+            }
+        } yields {
+            "Unique; substitution []"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Iterator<Item = u32>) {
+                    u32: Eq<T>
+                }
+            }
+        } yields {
+            "Unique; substitution []"
+        }
+
+        goal {
+            forall<T> {
+                <T as Iterator>::Item: Clone
+            }
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
 /// Demonstrates that, given the expected value of the associated
 /// type, we can use that to narrow down the relevant impls.
 #[test]
@@ -574,8 +801,6 @@ fn normalize_rev_infer() {
     test! {
         program {
             trait Identity { type Item; }
-            struct u32 { }
-            struct i32 { }
             impl Identity for u32 { type Item = u32; }
             impl Identity for i32 { type Item = i32; }
         }
@@ -610,7 +835,7 @@ fn region_equality() {
         } yields {
             "Unique; substitution [],
                      lifetime constraints \
-                     [InEnvironment { environment: Env([]), goal: '!2 == '!1 }]
+                     ['!2 == '!1]
                      "
         }
 
@@ -626,6 +851,522 @@ fn region_equality() {
     }
 }
 
+#[test]
+fn erased_regions() {
+    test! {
+        program {
+            trait Eq<T> { }
+            impl<T> Eq<T> for T { }
+
+            struct Unit { }
+            struct Ref<'a, T> { }
+        }
+
+        // With regions erased, `'a` and `'b` are considered equal, so
+        // no constraint relating them is produced.
+        goal {
+            forall<'a, 'b> {
+                Ref<'a, Unit>: Eq<Ref<'b, Unit>>
+            }
+        } yields[SolverChoice::slg()] {
+            "Unique; substitution [], lifetime constraints ['!2 == '!1]"
+        } yields[SolverChoice::slg().with_erase_regions(true)] {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        // Types are still checked as usual.
+        goal {
+            forall<'a, 'b> {
+                Ref<'a, Unit>: Eq<Unit>
+            }
+        } yields[SolverChoice::slg().with_erase_regions(true)] {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn named_answer_binders() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "struct Ref<'a, T> { }
+             trait Foo {
+                 type T;
+             }
+
+             impl<U, 'a> Foo for Ref<'a, U> {
+                 type T = U;
+             }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(
+            &program,
+            "exists<T> { exists<'a> { exists<U> { Normalize(<Ref<'a, T> as Foo>::T -> U) } } }",
+        ).unwrap();
+        let solution = SolverChoice::slg()
+            .solve_root_goal(env, &goal.into_peeled_goal(), SolveOptions::default())
+            .unwrap()
+            .solution
+            .unwrap();
+        assert_eq!(
+            solution.to_string(),
+            "Unique; for<?U0,?U0> { substitution [?0 := '?0, ?1 := ?1, ?2 := ?1], \
+             lifetime constraints [] }"
+        );
+        assert_eq!(
+            solution.display_named(),
+            "Unique; for<'a, T0> { substitution [?0 := 'a, ?1 := T0, ?2 := T0], \
+             lifetime constraints [] }"
+        );
+
+        let answer = match solution {
+            Solution::Unique(answer) => answer,
+            _ => panic!("expected a unique solution"),
+        };
+        assert_eq!(answer.binders().len(), 2);
+
+        // Instantiating the binders with parameters of our choosing.
+        let mut infer = InferenceTable::new();
+        let instantiated = answer.instantiate_with(|pk| {
+            pk.map(|ui| infer.new_variable(ui)).to_parameter()
+        });
+        assert_eq!(instantiated.subst.parameters[1], instantiated.subst.parameters[2]);
+    });
+}
+
+#[test]
+fn region_constraint_export() {
+    struct Edges(Vec<(String, String)>);
+
+    impl RegionConstraintSink for Edges {
+        type Region = String;
+
+        fn region(&mut self, lifetime: ir::Lifetime) -> String {
+            format!("{:?}", lifetime)
+        }
+
+        fn outlives(&mut self, longer: String, shorter: String) {
+            self.0.push((longer, shorter));
+        }
+    }
+
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Eq<T> { }
+             impl<T> Eq<T> for T { }
+             struct Unit { }
+             struct Ref<'a, T> { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(
+            &program,
+            "forall<'a, 'b> { Ref<'a, Unit>: Eq<Ref<'b, Unit>> }",
+        ).unwrap();
+        let solution = SolverChoice::slg()
+            .solve_root_goal(env, &goal.into_peeled_goal(), SolveOptions::default())
+            .unwrap()
+            .solution
+            .unwrap();
+        let constraints = solution.region_constraints().unwrap();
+        assert_eq!(constraints.to_string(), "['!2 == '!1]");
+
+        let mut edges = Edges(vec![]);
+        constraints.export_to(&mut edges);
+        assert_eq!(
+            edges.0,
+            vec![
+                ("'!2".to_string(), "'!1".to_string()),
+                ("'!1".to_string(), "'!2".to_string()),
+            ]
+        );
+        assert_eq!(constraints.outlives_edges().count(), 2);
+
+        // The same constraint, as the solver produced it.
+        if let Solution::Unique(constrained) = &solution {
+            let constraint = &constrained.value.constraints()[0].goal;
+            assert_eq!(constraint.to_string(), "'!2 == '!1");
+            let (a, b) = constraint.lifetimes();
+            assert_eq!(ir::Constraint::Outlives(a, b).to_string(), "'!2: '!1");
+            assert_eq!(ir::Constraint::Outlives(a, b).outlives_edges(), vec![(a, b)]);
+        } else {
+            panic!("expected a unique solution, got {}", solution);
+        }
+    });
+}
+
+#[test]
+fn placeholder_names() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Eq<T> { }
+             impl<T> Eq<T> for T { }
+             struct Ref<'a, T> { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        // `'unused` does not appear in the goal, so its placeholder is
+        // dropped, and `T` and `'b` become `!2` and `'!3`.
+        let (goal, binder_names) = parse_and_lower_goal_with_names(
+            &program,
+            "forall<'a, 'unused> { forall<T, 'b> { Ref<'a, T>: Eq<Ref<'b, T>> } }",
+        ).unwrap();
+        let (peeled_goal, names) = goal.into_peeled_goal_with_names(binder_names);
+        let solution = SolverChoice::slg()
+            .solve_root_goal(env, &peeled_goal, SolveOptions::default())
+            .unwrap()
+            .solution
+            .unwrap();
+        assert_eq!(
+            solution.to_string(),
+            "Unique; substitution [], lifetime constraints \
+             ['!3 == '!1]"
+        );
+        assert_eq!(
+            solution.display_with_names(&names),
+            "Unique; substitution [], lifetime constraints \
+             ['b == 'a]"
+        );
+
+        let constraints = solution.region_constraints().unwrap();
+        assert_eq!(constraints.display_with_names(&names), "['b == 'a]");
+        let equality = &constraints.equalities[0];
+        assert_eq!(names.lifetime_name(equality.a).unwrap().to_string(), "'b");
+        assert_eq!(names.lifetime_name(equality.b).unwrap().to_string(), "'a");
+
+        let names = names.with_style(ir::PlaceholderStyle::NameAndUniverse);
+        assert_eq!(constraints.display_with_names(&names), "['b/U3 == 'a/U1]");
+        let names = names.with_style(ir::PlaceholderStyle::Universe);
+        assert_eq!(solution.display_with_names(&names), solution.to_string());
+    });
+}
+
+#[test]
+fn type_holes() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Clone { }
+             trait Foo<T> { }
+             struct Vec<T> { }
+             struct Bar { }
+             impl Clone for Vec<u32> { }
+             impl Foo<u32> for Bar { }
+             impl Clone for u32 { }
+             impl Clone for i32 { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let solve = |goal_text: &str| {
+            let (goal, names) = parse_and_peel_goal_with_holes(&program, goal_text).unwrap();
+            let solution = SolverChoice::slg()
+                .solve_root_goal(env, &goal, SolveOptions::default())
+                .unwrap()
+                .solution
+                .unwrap();
+            (solution, names)
+        };
+
+        let (solution, names) = solve("Vec<_>: Clone");
+        assert_eq!(
+            solution.display_with_names(&names),
+            "Unique; substitution [?0 := u32], lifetime constraints []; inferred holes [u32]"
+        );
+
+        // The hole is not necessarily the first variable of the goal.
+        let (solution, names) = solve("exists<T> { T: Foo<_> }");
+        assert_eq!(names.holes(), &[1]);
+        assert_eq!(
+            solution.display_with_names(&names),
+            "Unique; substitution [?0 := Bar, ?1 := u32], lifetime constraints []; \
+             inferred holes [u32]"
+        );
+
+        // Nothing is inferred for an ambiguous hole.
+        let (solution, names) = solve("_: Clone");
+        assert_eq!(solution.inferred_holes(&names), None);
+        assert_eq!(solution.display_with_names(&names), solution.to_string());
+    });
+}
+
+#[test]
+fn used_hypotheses() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Clone { }
+             trait Debug { }
+             struct Vec<T> { }
+             impl<T> Clone for Vec<T> where T: Clone { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(
+            &program,
+            "forall<T> { if (T: Debug; T: Clone) { Vec<T>: Clone } }",
+        ).unwrap();
+        let options = SolveOptions::default().with_hypotheses(true);
+        let outcome = SolverChoice::slg()
+            .solve_root_goal(env, &goal.into_peeled_goal(), options.clone())
+            .unwrap();
+        let usage = outcome.hypotheses.unwrap();
+        assert!(outcome.solution.unwrap().is_unique());
+        assert_eq!(usage.used.len(), 1);
+        assert!(format!("{:?}", usage.used[0]).contains("Clone"));
+        assert_eq!(usage.unused.len(), 1);
+        assert!(format!("{:?}", usage.unused[0]).contains("Debug"));
+
+        // If the goal cannot be proven, nothing was used.
+        let goal = parse_and_lower_goal(
+            &program,
+            "forall<T> { if (T: Debug) { Vec<T>: Clone } }",
+        ).unwrap();
+        let outcome = SolverChoice::slg()
+            .solve_root_goal(env, &goal.into_peeled_goal(), options)
+            .unwrap();
+        let usage = outcome.hypotheses.unwrap();
+        assert_eq!(outcome.solution, None);
+        assert!(usage.used.is_empty());
+        assert_eq!(usage.unused.len(), 1);
+    });
+}
+
+#[test]
+fn infer_missing_bounds() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Clone { }
+             trait Debug { }
+             trait Into<T> { }
+             struct Vec<T> { }
+             struct Foo { }
+             impl<T> Clone for Vec<T> where T: Clone { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "forall<T> { Vec<T>: Clone }").unwrap();
+        let bounds = SolverChoice::slg()
+            .infer_missing_bounds(env, &goal.into_peeled_goal())
+            .unwrap()
+            .unwrap();
+        assert_eq!(bounds.len(), 1);
+        assert!(format!("{:?}", bounds[0]).contains("Clone"));
+
+        // Bounds that were already there need not be inferred again.
+        let goal =
+            parse_and_lower_goal(&program, "forall<T> { if (T: Clone) { Vec<T>: Clone } }")
+                .unwrap();
+        let bounds = SolverChoice::slg()
+            .infer_missing_bounds(env, &goal.into_peeled_goal())
+            .unwrap();
+        assert_eq!(bounds, Some(vec![]));
+
+        // No bound on `T` will make `Foo: Clone` hold.
+        let goal = parse_and_lower_goal(&program, "forall<T> { Foo: Clone }").unwrap();
+        let bounds = SolverChoice::slg()
+            .infer_missing_bounds(env, &goal.into_peeled_goal())
+            .unwrap();
+        assert_eq!(bounds, None);
+    });
+}
+
+#[test]
+fn cfg_items() {
+    use ir::lowering::LowerProgram;
+
+    let program = ::chalk_parse::parse_program(
+        r#"trait Foo { }
+           #[cfg = "std"] struct String { }
+           #[cfg = "std"] impl Foo for String { }
+           #[cfg = "std"] #[cfg = "alloc"] impl Foo for u32 { }"#,
+    ).unwrap();
+
+    let solve = |enabled_flags: &[&str], goal_text: &str| {
+        let program = Arc::new(program.lower_with_cfg(SolverChoice::slg(), enabled_flags).unwrap());
+        let env = program.environment();
+        ir::tls::set_current_program(&program, || {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            SolverChoice::slg()
+                .solve_root_goal(&env, &goal.into_peeled_goal(), SolveOptions::default())
+                .unwrap()
+                .solution
+        })
+    };
+
+    assert!(solve(&["std"], "String: Foo").is_some());
+    assert_eq!(solve(&["std"], "u32: Foo"), None);
+    assert!(solve(&["std", "alloc"], "u32: Foo").is_some());
+
+    // Without `std`, `String` does not even exist.
+    let program = program.lower_with_cfg(SolverChoice::slg(), &[]).unwrap();
+    assert!(parse_and_lower_goal(&program, "String: Foo").is_err());
+}
+
+#[test]
+fn goal_regressions() {
+    let before = parse_and_lower_program(
+        "trait Clone { }
+         struct Foo { }
+         struct Bar { }
+         struct Vec<T> { }
+         struct Baz<T> { }
+         impl Clone for Foo { }
+         impl Clone for Bar { }
+         impl<T> Clone for Vec<T> where T: Clone { }
+         impl Clone for Baz<Bar> { }",
+        SolverChoice::slg(),
+    ).unwrap();
+    let after = parse_and_lower_program(
+        "trait Clone { }
+         struct Foo { }
+         struct Bar { }
+         struct Vec<T> { }
+         struct Baz<T> { }
+         impl Clone for Bar { }
+         impl<T> Clone for Vec<T> where T: Clone { }
+         impl Clone for Baz<Bar> { }
+         impl Clone for Baz<Foo> { }",
+        SolverChoice::slg(),
+    ).unwrap();
+    let goals: Vec<_> = [
+        "Foo: Clone",
+        "Bar: Clone",
+        "Vec<Foo>: Clone",
+        "exists<T> { Vec<T>: Clone }",
+        "Vec<Vec<Bar>>: Clone",
+        "exists<T> { Baz<T>: Clone }",
+    ].iter()
+        .map(|text| *::chalk_parse::parse_goal(text).unwrap())
+        .collect();
+
+    let regressions = SolverChoice::slg()
+        .goal_regressions(&before, &after, &goals)
+        .unwrap();
+    let regressed: Vec<_> = regressions
+        .iter()
+        .map(|regression| (regression.goal_index, regression.kind))
+        .collect();
+    assert_eq!(
+        regressed,
+        vec![
+            (0, RegressionKind::Unprovable),
+            (2, RegressionKind::Unprovable),
+            (5, RegressionKind::Ambiguous),
+        ]
+    );
+
+    // An unchanged program has no regressions.
+    let regressions = SolverChoice::slg()
+        .goal_regressions(&before, &before, &goals)
+        .unwrap();
+    assert!(regressions.is_empty());
+}
+
+#[test]
+fn goal_regressions_reuse_solutions() {
+    let before = parse_and_lower_program(
+        "trait Clone { }
+         trait Debug { }
+         struct Foo { }
+         struct Bar { }
+         impl Debug for Foo { }
+         impl Clone for Foo { }
+         impl Clone for Bar { }",
+        SolverChoice::slg(),
+    ).unwrap();
+    let after = parse_and_lower_program(
+        "trait Clone { }
+         trait Debug { }
+         struct Foo { }
+         struct Bar { }
+         impl Debug for Foo { }
+         impl Clone for Foo { }",
+        SolverChoice::slg(),
+    ).unwrap();
+    let goals: Vec<_> = ["Foo: Debug", "Bar: Debug", "Foo: Clone", "Bar: Clone"]
+        .iter()
+        .map(|text| *::chalk_parse::parse_goal(text).unwrap())
+        .collect();
+
+    // Only the goals about `Clone`, whose impls changed, are solved
+    // again against the new version.
+    let goal_cache = GoalCache::new();
+    let regressions = compat::goal_regressions_with_cache(
+        SolverChoice::slg(),
+        &before,
+        &after,
+        &goals,
+        &goal_cache,
+    ).unwrap();
+    assert_eq!(goal_cache.hits(), 2);
+    let regressed: Vec<_> = regressions
+        .iter()
+        .map(|regression| (regression.goal_index, regression.kind))
+        .collect();
+    assert_eq!(regressed, vec![(3, RegressionKind::Unprovable)]);
+}
+
+#[test]
+fn named_goals() {
+    let program = parse_and_lower_program(
+        "trait Clone { }
+         struct Foo { }
+         struct Bar { }
+         impl Clone for Foo { }",
+        SolverChoice::slg(),
+    ).unwrap();
+    let goals = ::chalk_parse::parse_goals(
+        "// Checks that hold.
+         goal foo_clone { Foo: Clone }
+         goal some_clone { exists<T> { T: Clone } }
+
+         // And one that does not.
+         goal bar_clone { Bar: Clone }",
+    ).unwrap();
+
+    let results = |names: &[&str]| -> Vec<String> {
+        SolverChoice::slg()
+            .solve_named_goals(&program, &goals, names)
+            .unwrap()
+            .into_iter()
+            .map(|solution| result_to_string(&Ok(solution)))
+            .collect()
+    };
+
+    assert_eq!(
+        results(&[]),
+        vec![
+            "Unique; substitution [], lifetime constraints []",
+            "Unique; substitution [?0 := Foo], lifetime constraints []",
+            "No possible solution",
+        ]
+    );
+    assert_eq!(
+        results(&["bar_clone", "foo_clone"]),
+        vec![
+            "No possible solution",
+            "Unique; substitution [], lifetime constraints []",
+        ]
+    );
+    assert!(
+        SolverChoice::slg()
+            .solve_named_goals(&program, &goals, &["baz_clone"])
+            .is_err()
+    );
+    assert!(::chalk_parse::parse_goals("goal a { Foo: Clone } goal a { Bar: Clone }").is_err());
+}
+
 /// Demonstrates that, given the expected value of the associated
 /// type, we can use that to narrow down the relevant impls.
 #[test]
@@ -659,7 +1400,7 @@ fn forall_equality() {
                 for<'c, 'd> Ref<'c, Ref<'d, Ref<'d, Unit>>>>
         } yields {
             "Unique; substitution [], lifetime constraints [
-                 InEnvironment { environment: Env([]), goal: '!2 == '!1 }
+                 '!2 == '!1
              ]"
         }
     }
@@ -730,68 +1471,356 @@ fn atc1() {
 }
 
 #[test]
-fn struct_wf() {
+fn outlives() {
     test! {
         program {
-            struct Foo<T> where T: Eq { }
-            struct Bar { }
-            struct Baz { }
-
-            trait Eq { }
+            struct Foo { }
+            struct Vec<T> { }
+            struct Ref<'a, T> { }
+        }
 
-            impl Eq for Baz { }
-            impl<T> Eq for Foo<T> where T: Eq { }
+        goal {
+            forall<'a> {
+                Vec<Foo>: 'a
+            }
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
         }
 
+        // Nothing is known about `T`.
         goal {
-            WellFormed(Foo<Bar>)
+            forall<'a, T> {
+                Ref<'a, T>: 'a
+            }
         } yields {
             "No possible solution"
         }
 
         goal {
-            WellFormed(Foo<Baz>)
+            forall<'a, T> {
+                if (T: 'a) {
+                    Ref<'a, Vec<T>>: 'a
+                }
+            }
         } yields {
             "Unique; substitution [], lifetime constraints []"
         }
 
+        // Relating two distinct lifetimes produces a region constraint,
+        // unless the environment assumes it.
         goal {
-            WellFormed(Foo<Foo<Baz>>)
+            forall<'a, 'b> {
+                'a: 'b
+            }
         } yields {
-            "Unique; substitution [], lifetime constraints []"
+            "Unique; substitution [], lifetime constraints ['!1: '!2]"
         }
-    }
-}
-
-#[test]
-fn generic_trait() {
-    test! {
-        program {
-            struct Int { }
-            struct Uint { }
-
-            trait Eq<T> { }
 
-            impl Eq<Int> for Int { }
-            impl Eq<Uint> for Uint { }
+        goal {
+            forall<'a, 'b> {
+                if ('a: 'b) {
+                    'a: 'b
+                }
+            }
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
         }
 
         goal {
-            Int: Eq<Int>
+            forall<'a, 'b> {
+                Ref<'a, Foo>: 'b
+            }
         } yields {
-            "Unique; substitution [], lifetime constraints []"
+            "Unique; substitution [], lifetime constraints ['!1: '!2]"
         }
 
         goal {
-            Uint: Eq<Uint>
+            forall<'a, 'b, T> {
+                if (T: 'b) {
+                    Ref<'a, T>: 'b
+                }
+            }
         } yields {
-            "Unique; substitution [], lifetime constraints []"
+            "Unique; substitution [], lifetime constraints ['!1: '!2]"
         }
 
         goal {
-            Int: Eq<Uint>
+            forall<'a> {
+                'a: 'a
+            }
         } yields {
-            "No possible solution"
+            "Unique; substitution [], lifetime constraints []"
+        }
+    }
+}
+
+#[test]
+fn struct_wf() {
+    test! {
+        program {
+            struct Foo<T> where T: Eq { }
+            struct Bar { }
+            struct Baz { }
+
+            trait Eq { }
+
+            impl Eq for Baz { }
+            impl<T> Eq for Foo<T> where T: Eq { }
+        }
+
+        goal {
+            WellFormed(Foo<Bar>)
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            WellFormed(Foo<Baz>)
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            WellFormed(Foo<Foo<Baz>>)
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+    }
+}
+
+#[test]
+fn specialized_assoc_types() {
+    test! {
+        program {
+            trait Foo { type Item; }
+
+            struct A { }
+            struct B { }
+            struct Vec<T> { }
+
+            impl<T> Foo for T { default type Item = A; }
+            impl Foo for B { type Item = B; }
+            impl<T> Foo for Vec<T> { }
+        }
+
+        goal {
+            exists<U> { Normalize(<A as Foo>::Item -> U) }
+        } yields {
+            "Unique; substitution [?0 := A]"
+        }
+
+        // The most specific impl defining `Item` wins.
+        goal {
+            exists<U> { Normalize(<B as Foo>::Item -> U) }
+        } yields {
+            "Unique; substitution [?0 := B]"
+        }
+
+        // `impl<T> Foo for Vec<T>` inherits the default.
+        goal {
+            exists<U> { Normalize(<Vec<B> as Foo>::Item -> U) }
+        } yields {
+            "Unique; substitution [?0 := A]"
+        }
+
+        // Without knowing the self type, a specializing impl may apply.
+        goal {
+            exists<T, U> { Normalize(<T as Foo>::Item -> U) }
+        } yields {
+            "Ambiguous"
+        }
+
+        // The same goes for a placeholder: `T` may be `B`.
+        goal {
+            forall<T> { Normalize(<T as Foo>::Item -> A) }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> { exists<U> { Normalize(<Vec<T> as Foo>::Item -> U) } }
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn default_impls() {
+    test! {
+        program {
+            trait Foo { type Item; }
+
+            struct A { }
+            struct B { }
+
+            default impl<T> Foo for T { type Item = A; }
+            impl Foo for B { type Item = B; }
+        }
+
+        goal {
+            exists<U> { Normalize(<A as Foo>::Item -> U) }
+        } yields {
+            "Unique; substitution [?0 := A]"
+        }
+
+        goal {
+            exists<U> { Normalize(<B as Foo>::Item -> U) }
+        } yields {
+            "Unique; substitution [?0 := B]"
+        }
+    }
+}
+
+#[test]
+fn trait_ref_from_env_and_well_formed() {
+    test! {
+        program {
+            trait Foo where Self: Bar { }
+            trait Bar { }
+
+            struct A { }
+
+            impl Foo for A { }
+            impl Bar for A { }
+        }
+
+        goal {
+            forall<T> {
+                if (FromEnv(T: Foo)) {
+                    T: Bar
+                }
+            }
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            forall<T> {
+                if (FromEnv(T: Bar)) {
+                    T: Foo
+                }
+            }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Foo) {
+                    WellFormed(T: Foo)
+                }
+            }
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Bar) {
+                    WellFormed(T: Foo)
+                }
+            }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            WellFormed(A: Foo)
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        // Impls prove that a trait is implemented, not that it can be
+        // assumed.
+        goal {
+            FromEnv(A: Foo)
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn implied_bounds_from_structs() {
+    test! {
+        program {
+            struct Foo<T> where T: Eq { }
+            struct Vec<T> { }
+
+            trait Eq { }
+            trait Bar { }
+        }
+
+        // Assuming `Foo<T>: Bar` says nothing about `T`, unless `Foo<T>`
+        // is also assumed to be well-formed.
+        goal {
+            forall<T> {
+                if (Foo<T>: Bar) {
+                    T: Eq
+                }
+            }
+        } yields[SolverChoice::slg()] {
+            "No possible solution"
+        } yields[SolverChoice::slg().with_implied_bounds(true)] {
+            "Unique; substitution [], lifetime constraints []"
+        } yields[SolverChoice::naive().with_implied_bounds(true)] {
+            "Unique"
+        }
+
+        goal {
+            forall<T> {
+                if (Vec<Foo<T>>: Bar) {
+                    T: Eq
+                }
+            }
+        } yields[SolverChoice::slg()] {
+            "No possible solution"
+        } yields[SolverChoice::slg().with_implied_bounds(true)] {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            forall<T> {
+                if (Vec<T>: Bar) {
+                    T: Eq
+                }
+            }
+        } yields[SolverChoice::slg().with_implied_bounds(true)] {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn generic_trait() {
+    test! {
+        program {
+            struct Int { }
+            struct Uint { }
+
+            trait Eq<T> { }
+
+            impl Eq<Int> for Int { }
+            impl Eq<Uint> for Uint { }
+        }
+
+        goal {
+            Int: Eq<Int>
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            Uint: Eq<Uint>
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        goal {
+            Int: Eq<Uint>
+        } yields {
+            "No possible solution"
         }
     }
 }
@@ -869,7 +1898,7 @@ fn normalize_under_binder() {
         } yields {
             "Unique; for<?U0> { \
              substitution [?0 := Ref<'?0, I32>], \
-             lifetime constraints [InEnvironment { environment: Env([]), goal: '?0 == '!1 }] \
+             lifetime constraints ['?0 == '!1] \
              }"
         }
     }
@@ -893,7 +1922,7 @@ fn unify_quantified_lifetimes() {
         } yields {
             "Unique; for<?U0> { \
              substitution [?0 := '?0], \
-             lifetime constraints [InEnvironment { environment: Env([]), goal: '?0 == '!1 }] \
+             lifetime constraints ['?0 == '!1] \
              }"
         }
 
@@ -910,12 +1939,46 @@ fn unify_quantified_lifetimes() {
         } yields {
             "Unique; for<?U0> { \
              substitution [?0 := '?0, ?1 := '!1], \
-             lifetime constraints [InEnvironment { environment: Env([]), goal: '?0 == '!1 }] \
+             lifetime constraints ['?0 == '!1] \
              }"
         }
     }
 }
 
+/// Two ways of proving `Unit: Foo` that differ only in the order in
+/// which they produce the same region constraints are one answer, so
+/// the solution is unique.
+#[test]
+fn equivalent_answers_are_deduplicated() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             struct Unit { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(
+            &program,
+            "forall<'a, 'b, 'c> {
+                 if (Unit: Foo :- 'a = 'b, 'b = 'c; Unit: Foo :- 'b = 'c, 'a = 'b) {
+                     Unit: Foo
+                 }
+             }",
+        ).unwrap();
+        let solution = SolverChoice::slg()
+            .solve_root_goal(env, &goal.into_peeled_goal(), SolveOptions::default())
+            .unwrap()
+            .solution
+            .unwrap();
+        match solution {
+            Solution::Unique(answer) => assert_eq!(answer.value.constraints.len(), 2),
+            _ => panic!("expected a unique solution, got {}", solution),
+        }
+    });
+}
+
 #[test]
 fn equality_binder() {
     test! {
@@ -935,7 +1998,7 @@ fn equality_binder() {
         } yields {
             "Unique; for<?U1> { \
                  substitution [?0 := '?0], \
-                 lifetime constraints [InEnvironment { environment: Env([]), goal: '!2 == '?0 }] \
+                 lifetime constraints ['!2 == '?0] \
              }"
         }
     }
@@ -1103,8 +2166,6 @@ fn suggested_subst() {
             trait SomeTrait<A> {}
             struct Foo {}
             struct Bar {}
-            struct i32 {}
-            struct bool {}
             impl SomeTrait<i32> for Foo {}
             impl SomeTrait<bool> for Bar {}
             impl SomeTrait<i32> for Bar {}
@@ -1209,7 +2270,6 @@ fn suggested_subst() {
 fn simple_negation() {
     test! {
         program {
-            struct i32 {}
             trait Foo {}
         }
 
@@ -1304,8 +2364,6 @@ fn deep_negation() {
 fn negation_quantifiers() {
     test! {
         program {
-            struct i32 {}
-            struct u32 {}
         }
 
         goal {
@@ -1345,8 +2403,6 @@ fn negation_free_vars() {
     test! {
         program {
             struct Vec<T> {}
-            struct i32 {}
-            struct u32 {}
             trait Foo {}
             impl Foo for Vec<u32> {}
         }
@@ -1387,8 +2443,6 @@ fn where_clause_trumps() {
 fn inapplicable_assumption_does_not_shadow() {
     test! {
         program {
-            struct i32 { }
-            struct u32 { }
 
             trait Foo<T> { }
 
@@ -1415,8 +2469,6 @@ fn auto_trait_without_impls() {
         program {
             #[auto] trait Send { }
 
-            struct i32 { }
-
             struct Useless<T> { }
 
             struct Data<T> {
@@ -1457,8 +2509,6 @@ fn auto_trait_with_impls() {
         program {
             #[auto] trait Send { }
 
-            struct i32 { }
-            struct f32 { }
             struct Vec<T> { }
 
             impl<T> Send for Vec<T> where T: Send { }
@@ -1500,74 +2550,1240 @@ fn auto_trait_with_impls() {
 }
 
 #[test]
-fn coinductive_semantics() {
+fn enums_and_unions() {
     test! {
         program {
             #[auto] trait Send { }
+            #[lang_sized] trait Sized { }
+            trait Eq { }
 
-            struct i32 { }
+            struct Rc<T> { }
+            impl<T> !Send for Rc<T> { }
 
-            struct Ptr<T> { }
-            impl<T> Send for Ptr<T> where T: Send { }
+            enum Option<T> { None, Some(T) }
+            enum Either<A, B> { Left { value: A }, Right { value: B } }
+            union Bits<T> where T: Eq { a: T, b: u32 }
+        }
 
-            struct List<T> {
-                data: T,
-                next: Ptr<List<T>>
-            }
+        goal {
+            Option<i32>: Send
+        } yields {
+            "Unique"
         }
 
+        // Auto traits look at the fields of every variant.
         goal {
-            forall<T> {
-                List<T>: Send
-            }
+            Either<i32, Rc<i32>>: Send
         } yields {
             "No possible solution"
         }
+
         goal {
             forall<T> {
                 if (T: Send) {
-                    List<T>: Send
+                    Option<T>: Send
                 }
             }
         } yields {
             "Unique"
         }
 
+        // Unlike a struct, an enum is only `Sized` if all of its fields are.
         goal {
-            List<i32>: Send
+            Either<[u32], i32>: Sized
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            Either<u32, i32>: Sized
         } yields {
             "Unique"
         }
 
         goal {
-            exists<T> {
-                T: Send
+            forall<T> {
+                WellFormed(Bits<T>)
             }
         } yields {
-            "Ambiguous"
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Eq) {
+                    WellFormed(Bits<T>)
+                }
+            }
+        } yields {
+            "Unique"
         }
     }
 }
 
 #[test]
-fn mixed_semantics() {
+fn trait_aliases() {
     test! {
         program {
-            #[auto] trait Send { }
             trait Foo { }
+            trait Bar<T> { }
+            trait Alias<T> = Foo + Bar<T>;
 
-            impl<T> Send for T where T: Foo { }
-            impl<T> Foo for T where T: Send { }
+            struct S { }
+            struct U { }
+
+            impl Foo for S { }
+            impl Bar<U> for S { }
+            impl Foo for U { }
+        }
+
+        goal {
+            S: Alias<U>
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            U: Alias<U>
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<T> { S: Alias<T> }
+        } yields {
+            "Unique; substitution [?0 := U]"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Alias<S>) {
+                    T: Foo
+                }
+            }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Alias<S>) {
+                    T: Bar<S>
+                }
+            }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Foo) {
+                    T: Alias<S>
+                }
+            }
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn parameter_defaults() {
+    test! {
+        program {
+            struct A { }
+            struct B { }
+            struct Foo<T = A> { }
+            struct Pair<T, U = T> { }
+
+            trait Eq<Rhs = Self> { }
+
+            impl Eq for A { }
+            impl Eq<B> for Foo { }
+            impl Eq for Pair<A> { }
+        }
+
+        goal {
+            A: Eq
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            A: Eq<B>
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            Foo<A>: Eq<B>
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            Pair<A, A>: Eq<Pair<A>>
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            exists<T> { Pair<A, T>: Eq }
+        } yields {
+            "Unique; substitution [?0 := A]"
+        }
+    }
+}
+
+#[test]
+fn dyn_trait() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            trait Foo { }
+            trait Bar<T> { }
+
+            struct Box<T> { }
+
+            impl<T> Foo for Box<T> where T: Foo { }
+        }
+
+        goal {
+            dyn Foo + Send: Foo
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        // Auto traits are only implemented if they are among the bounds.
+        goal {
+            dyn Foo + Send: Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            dyn Foo: Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            Box<dyn Foo>: Foo
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            dyn Bar<i32>: Bar<i32>
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            dyn Bar<i32>: Bar<f32>
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<T> {
+                dyn Bar<T> + Send: Bar<i32>
+            }
+        } yields {
+            "Unique; substitution [?0 := i32]"
+        }
+
+        // The order of the bounds does not matter.
+        goal {
+            dyn Send + Foo = dyn Foo + Send
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            dyn Foo = dyn Foo + Send
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<'a> {
+                dyn Foo + 'a: Foo
+            }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            WellFormed(dyn Bar<i32> + Send)
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn dyn_trait_supertraits() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            trait Base<T> { }
+            trait Super<T> where Self: Base<T> { }
+            trait Sub<T> where Self: Super<Vec<T>>, Self: Send { }
+            trait Other { }
+
+            struct Vec<T> { }
+        }
+
+        goal {
+            dyn Sub<i32>: Super<Vec<i32>>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            dyn Sub<i32>: Super<i32>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        // Supertraits are elaborated transitively.
+        goal {
+            exists<T> {
+                dyn Sub<i32>: Base<T>
+            }
+        } yields {
+            "Unique; substitution [?0 := Vec<i32>]"
+        }
+
+        // Auto traits among the supertraits count too.
+        goal {
+            dyn Sub<i32>: Send
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            dyn Super<i32>: Send
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            dyn Sub<i32>: Other
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn opaque_types() {
+    test! {
+        program {
+            trait Bar { }
+            trait Clone { }
+            trait Iterator { type Item; }
+
+            struct Vec<T> { }
+            struct IntoIter<T> { }
+
+            impl<T> Clone for Vec<T> { }
+            impl<T> Iterator for IntoIter<T> { type Item = T; }
+            impl<T> Bar for Vec<T> { }
+
+            type Foo<T> = impl Bar = Vec<T>;
+            type Iter<T> = impl Iterator<Item = T> = IntoIter<T>;
+        }
+
+        goal {
+            forall<T> { Foo<T>: Bar }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        // Only the declared bounds are known about an opaque type...
+        goal {
+            forall<T> { Foo<T>: Clone }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> { Foo<T> = Vec<T> }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        // ...unless its hidden type is revealed.
+        goal {
+            forall<T> { if (Reveal) { Foo<T>: Clone } }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            exists<U> { if (Reveal) { Foo<u32> = U } }
+        } yields {
+            "Unique; substitution [?0 := Foo<u32>]"
+        }
+
+        goal {
+            forall<T> { if (Reveal) { Foo<T> = Vec<T> } }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> { if (Reveal) { Foo<T> = Vec<u32> } }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            Iter<u32>: Iterator<Item = u32>
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            exists<U> { Normalize(<Iter<u32> as Iterator>::Item -> U) }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            forall<T> { Foo<T> = Foo<T> }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> { WellFormed(Foo<T>) }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn closures() {
+    test! {
+        program {
+            #[lang_fn_once] trait FnOnce<Args> { type Output; }
+            #[lang_fn_mut] trait FnMut<Args> where Self: FnOnce<Args> { }
+            #[lang_fn] trait Fn<Args> where Self: FnMut<Args> { }
+
+            struct Ref<'a, T> { }
+
+            closure c(i32) -> u32;
+            closure mut m<'a, T>(T, i32) -> Ref<'a, T>;
+            closure once o();
+        }
+
+        goal {
+            c: Fn(i32) -> u32
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            c: Fn(i32) -> i32
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            c: Fn(u32) -> u32
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            exists<U> { Normalize(<c as FnOnce<(i32,)>>::Output -> U) }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            exists<A> { c: Fn<A> }
+        } yields {
+            "Unique; substitution [?0 := (i32,)]"
+        }
+
+        // A `mut` closure does not implement `Fn`...
+        goal {
+            forall<'a, T> { m<'a, T>: FnMut(T, i32) -> Ref<'a, T> }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<'a, T> { m<'a, T>: Fn<(T, i32)> }
+        } yields {
+            "No possible solution"
+        }
+
+        // ...and a `once` closure only implements `FnOnce`.
+        goal {
+            o: FnOnce()
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            o: FnMut<()>
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            WellFormed(c: Fn<(i32,)>)
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn tuples() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            #[lang_sized] trait Sized { }
+            #[lang_clone] trait Clone { }
+            #[lang_copy] trait Copy where Self: Clone { }
+            trait Foo { }
+
+            struct Rc<T> { }
+            struct Vec<T> { }
+            impl<T> !Send for Rc<T> { }
+            impl Clone for i32 { }
+            impl Copy for i32 { }
+            impl<T> Clone for Vec<T> { }
+        }
+
+        goal {
+            (i32, Vec<i32>): Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            (i32, Rc<i32>): Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            (): Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        // `T` could be any tuple, or `i32`, so the impls alone cannot
+        // tell which type it is.
+        goal {
+            exists<T> { T: Copy }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Ambiguous"
+        }
+
+        goal {
+            exists<T> { T: Sized }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Ambiguous"
+        }
+
+        goal {
+            (i32, (i32,)): Copy
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            (i32, Vec<i32>): Copy
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            (i32, Vec<i32>): Clone
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> { (T, i32): Sized }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> { (i32, T): Sized }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> { if (T: Send) { (T, T): Send } }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            (i32,): Foo
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<T> { (i32, T) = (T, i32) }
+        } yields {
+            "Unique; substitution [?0 := i32]"
+        }
+
+        goal {
+            (i32,) = (i32, i32)
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn fn_ptrs() {
+    test! {
+        program {
+            #[lang_fn_once] trait FnOnce<Args> { type Output; }
+            #[lang_fn_mut] trait FnMut<Args> where Self: FnOnce<Args> { }
+            #[lang_fn] trait Fn<Args> where Self: FnMut<Args> { }
+            #[lang_clone] trait Clone { }
+            #[lang_copy] trait Copy where Self: Clone { }
+            #[auto] trait Send { }
+            trait Foo { }
+
+            struct Rc<T> { }
+            impl<T> !Send for Rc<T> { }
+        }
+
+        goal {
+            fn(i32) -> u32: Fn(i32) -> u32
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            fn(i32) -> u32: FnOnce(i32) -> i32
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            exists<U> { Normalize(<fn(i32, u32) as FnOnce<(i32, u32)>>::Output -> U) }
+        } yields {
+            "Unique; substitution [?0 := ()]"
+        }
+
+        goal {
+            exists<A> { fn(i32, u32) -> i32: FnMut<A> }
+        } yields {
+            "Unique; substitution [?0 := (i32, u32)]"
+        }
+
+        goal {
+            fn() -> Rc<i32>: Copy
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        // Function pointers implement auto traits whatever their
+        // argument and return types.
+        goal {
+            fn(Rc<u32>): Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            fn(i32): Foo
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            WellFormed(fn(Rc<i32>) -> u32)
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            fn(i32) = fn(i32) -> ()
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn slices_and_arrays() {
+    test! {
+        program {
+            #[lang_sized] trait Sized { }
+            #[lang_clone] trait Clone { }
+            #[lang_copy] trait Copy where Self: Clone { }
+            #[lang_unsize] trait Unsize<T> { }
+            #[auto] trait Send { }
+            trait Display { }
+            trait CoerceUnsized<T> { }
+
+            struct Rc<T> { }
+            struct Box<T> { }
+            impl Clone for i32 { }
+            impl Copy for i32 { }
+            impl Display for i32 { }
+            impl<T> !Send for Rc<T> { }
+            impl<T, U> CoerceUnsized<Box<U>> for Box<T> where T: Unsize<U> { }
+        }
+
+        goal {
+            [i32; 3]: Sized
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            [i32]: Sized
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            str: Sized
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            [i32; 3]: Copy
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            [Rc<i32>; 3]: Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> { if (T: Send) { [T]: Send } }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            str: Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            exists<U> { [i32; 3]: Unsize<U> }
+        } yields {
+            "Unique; substitution [?0 := [i32]]"
+        }
+
+        goal {
+            [i32]: Unsize<[i32]>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            i32: Unsize<dyn Display>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            Box<[i32; 3]>: CoerceUnsized<Box<[i32]>>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            Box<[i32]>: CoerceUnsized<Box<dyn Display>>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            WellFormed([[i32; 2]])
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn dyn_upcasting() {
+    test! {
+        program {
+            #[lang_unsize] trait Unsize<T> { }
+            #[auto] trait Send { }
+            trait CoerceUnsized<T> { }
+            trait Super<T> { }
+            trait Sub<T> where Self: Super<Box<T>> { }
+            trait Other { }
+
+            struct Box<T> { }
+            impl<T, U> CoerceUnsized<Box<U>> for Box<T> where T: Unsize<U> { }
+        }
+
+        goal {
+            dyn Sub<i32>: Unsize<dyn Super<Box<i32>>>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            exists<T> {
+                dyn Sub<i32>: Unsize<dyn Super<T>>
+            }
+        } yields {
+            "Unique; substitution [?0 := Box<i32>]"
+        }
+
+        // Auto traits may be dropped, but not added.
+        goal {
+            dyn Sub<i32> + Send: Unsize<dyn Super<Box<i32>>>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            dyn Sub<i32> + Send: Unsize<dyn Super<Box<i32>> + Send>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            dyn Sub<i32>: Unsize<dyn Super<Box<i32>> + Send>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            dyn Super<Box<i32>>: Unsize<dyn Sub<i32>>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            dyn Sub<i32>: Unsize<dyn Other>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        // The lifetime of the trait object may only be shortened.
+        goal {
+            forall<'a, 'b> {
+                if ('a: 'b) {
+                    dyn Sub<i32> + 'a: Unsize<dyn Super<Box<i32>> + 'b>
+                }
+            }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            Box<dyn Sub<i32>>: CoerceUnsized<Box<dyn Super<Box<i32>>>>
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn scalars() {
+    test! {
+        program {
+            #[lang_sized] trait Sized { }
+            #[lang_clone] trait Clone { }
+            #[lang_copy] trait Copy where Self: Clone { }
+            #[auto] trait Send { }
+            trait Foo { }
+
+            impl !Send for char { }
+            impl Foo for u8 { }
+        }
+
+        goal {
+            bool: Copy
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            f64: Sized
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            i128: Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            char: Send
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            u16: Foo
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            exists<T> { T: Foo }
+        } yields {
+            "Unique; substitution [?0 := u8]"
+        }
+
+        goal {
+            forall<'a> { WellFormed(usize), isize: 'a }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn references_and_raw_pointers() {
+    test! {
+        program {
+            #[lang_sized] trait Sized { }
+            #[lang_clone] trait Clone { }
+            #[lang_copy] trait Copy where Self: Clone { }
+            #[lang_deref] trait Deref { type Target; }
+            #[auto] trait Send { }
+
+            struct Rc<T> { }
+            impl<T> !Send for Rc<T> { }
+        }
+
+        goal {
+            forall<'a, T> { &'a T: Copy }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            forall<'a, T> { &'a mut T: Clone }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> { *mut T: Copy }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            forall<'a> { &'a [i32]: Sized }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "Unique"
+        }
+
+        goal {
+            forall<'a> { &'a Rc<i32>: Send }
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            forall<'a> { exists<U> { Derefs(&'a mut i32, U) } }
+        } yields {
+            "Unique; substitution [?0 := i32]"
+        }
+
+        goal {
+            exists<U> { Derefs(*const Rc<i32>, U) }
+        } yields {
+            "Unique; substitution [?0 := Rc<i32>]"
+        }
+
+        goal {
+            *const i32: Deref
+        } yields[SolverChoice::slg(), SolverChoice::naive()] {
+            "No possible solution"
+        }
+
+        goal {
+            forall<'a, 'b> { if ('a: 'b) { &'a i32: 'b } }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<'a> { WellFormed(&'a i32) }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn never_type() {
+    use ir::lowering::LowerProgram;
+
+    let program_text = "
+        #[auto] trait Send { }
+        #[lang_sized] trait Sized { }
+        #[lang_clone] trait Clone { }
+        #[lang_copy] trait Copy where Self: Clone { }
+        trait Display { }
+        trait Into<T> { }
+        struct Foo { }
+    ";
+    let solve = |builtin_traits: ir::BuiltinTraits, goal_text: &str| {
+        let program = Arc::new(
+            ::chalk_parse::parse_program(program_text)
+                .unwrap()
+                .lower_with_builtins(SolverChoice::slg(), builtin_traits)
+                .unwrap(),
+        );
+        let env = program.environment();
+        ir::tls::set_current_program(&program, || {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            let result = SolverChoice::slg()
+                .solve_root_goal(&env, &goal.into_peeled_goal(), SolveOptions::default())
+                .map(|outcome| outcome.solution);
+            result_to_string(&result)
+        })
+    };
+    let fallback = ir::BuiltinTraits {
+        never_fallback: true,
+        ..ir::BuiltinTraits::all()
+    };
+
+    for goal in &["!: Send", "!: Sized", "!: Copy", "WellFormed(!)", "forall<'a> { !: 'a }"] {
+        assert!(solve(ir::BuiltinTraits::all(), goal).starts_with("Unique"));
+    }
+
+    // Other traits are implemented only under fallback.
+    for goal in &["!: Display", "!: Into<Foo>"] {
+        assert_eq!(solve(ir::BuiltinTraits::all(), goal), "No possible solution");
+        assert!(solve(fallback, goal).starts_with("Unique"));
+    }
+    assert_eq!(solve(fallback, "Foo: Display"), "No possible solution");
+    assert_eq!(solve(fallback, "(!, Foo): Display"), "No possible solution");
+    assert!(solve(fallback, "(!, Foo): Send").starts_with("Unique"));
+}
+
+#[test]
+fn builtin_traits() {
+    use ir::lowering::LowerProgram;
+
+    let program_text = "
+        #[auto] trait Send { }
+        #[lang_sized] trait Sized { }
+        #[lang_deref] trait Deref { type Target; }
+        #[lang_fn_once] trait FnOnce<Args> { type Output; }
+        struct Foo { }
+        struct Bar { }
+        impl Deref for Foo { type Target = Bar; }
+        closure c();
+    ";
+    let solve = |builtin_traits: ir::BuiltinTraits, goal_text: &str| {
+        let program = Arc::new(
+            ::chalk_parse::parse_program(program_text)
+                .unwrap()
+                .lower_with_builtins(SolverChoice::slg(), builtin_traits)
+                .unwrap(),
+        );
+        let env = program.environment();
+        ir::tls::set_current_program(&program, || {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            let result = SolverChoice::slg()
+                .solve_root_goal(&env, &goal.into_peeled_goal(), SolveOptions::default())
+                .map(|outcome| outcome.solution);
+            result_to_string(&result)
+        })
+    };
+
+    for goal in &["Foo: Send", "Foo: Sized", "Derefs(Foo, Bar)", "c: FnOnce<()>"] {
+        assert!(solve(ir::BuiltinTraits::all(), goal).starts_with("Unique"));
+        assert_eq!(solve(ir::BuiltinTraits::none(), goal), "No possible solution");
+    }
+
+    // Each rule can be enabled on its own.
+    let only_sized = ir::BuiltinTraits {
+        sized: true,
+        ..ir::BuiltinTraits::none()
+    };
+    assert!(solve(only_sized, "Foo: Sized").starts_with("Unique"));
+    assert_eq!(solve(only_sized, "Foo: Send"), "No possible solution");
+    assert_eq!(solve(only_sized, "Derefs(Foo, Bar)"), "No possible solution");
+}
+
+#[test]
+fn negative_impls() {
+    test! {
+        program {
+            extern trait Send { }
+            trait Foo { }
+
+            struct Rc<T> { }
+            struct Cell { }
+
+            impl !Send for Cell { }
+            impl<T> !Send for Rc<T> { }
+        }
+
+        goal {
+            NotImplemented(Cell: Send)
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            NotImplemented(f32: Send)
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            not { Cell: Send }
+        } yields {
+            "Unique"
+        }
+
+        // `Send` may be implemented by some other crate, so the absence
+        // of an impl is not enough to prove the negation.
+        goal {
+            not { f32: Send }
+        } yields {
+            "No possible solution"
+        }
+
+        // Explicit negative impls do not need a ground goal.
+        goal {
+            exists<T> { not { Rc<T>: Send } }
+        } yields {
+            "Unique"
+        }
+
+        // `Foo` is not open-world, so negation as failure still applies.
+        goal {
+            not { f32: Foo }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn negative_reasoning_for_fundamental_traits() {
+    test! {
+        program {
+            extern #[fundamental] trait Sized { }
+            struct Foo { }
+            struct Bar { }
+
+            impl Sized for Foo { }
+        }
+
+        // Adding an impl of a fundamental trait would be a breaking change,
+        // so the absence of one is enough even though `Sized` is `extern`.
+        goal {
+            not { Bar: Sized }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            not { Foo: Sized }
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn coinductive_semantics() {
+    test! {
+        program {
+            #[auto] trait Send { }
+
+            struct Ptr<T> { }
+            impl<T> Send for Ptr<T> where T: Send { }
+
+            struct List<T> {
+                data: T,
+                next: Ptr<List<T>>
+            }
+        }
+
+        goal {
+            forall<T> {
+                List<T>: Send
+            }
+        } yields {
+            "No possible solution"
+        }
+        goal {
+            forall<T> {
+                if (T: Send) {
+                    List<T>: Send
+                }
+            }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            List<i32>: Send
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            exists<T> {
+                T: Send
+            }
+        } yields {
+            "Ambiguous"
+        }
+    }
+}
+
+#[test]
+fn mixed_semantics() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            trait Foo { }
+
+            impl<T> Send for T where T: Foo { }
+            impl<T> Foo for T where T: Send { }
         }
 
         // We have a cycle `(T: Send) :- (T: Foo) :- (T: Send)` with a non-coinductive
-        // inner component `T: Foo` so we reject it.
+        // inner component `T: Foo` so we reject it. `T` could still be a
+        // built-in type like `()`, which is `Send` whatever the impls say.
         goal {
             exists<T> {
                 T: Send
             }
         } yields {
-            "No possible solution"
+            "Ambiguous"
         }
 
         goal {
@@ -1575,7 +3791,1426 @@ fn mixed_semantics() {
                 T: Foo
             }
         } yields {
+            "Ambiguous"
+        }
+    }
+}
+
+#[test]
+fn mixed_cycle_policies() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            trait Foo { }
+
+            impl<T> Send for T where T: Foo { }
+            impl<T> Foo for T where T: Send { }
+        }
+
+        // The cycle `(T: Send) :- (T: Foo) :- (T: Send)` is headed by
+        // the coinductive goal `T: Send`, so only the stratified
+        // policy accepts it.
+        goal {
+            forall<T> {
+                T: Send
+            }
+        } yields[SolverChoice::slg()] {
+            "No possible solution"
+        } yields[SolverChoice::slg().with_mixed_cycles(MixedCyclePolicy::Ambiguous)] {
+            "Ambiguous"
+        } yields[SolverChoice::slg().with_mixed_cycles(MixedCyclePolicy::Stratified)] {
+            "Unique"
+        }
+
+        // Here the cycle is headed by the inductive goal `T: Foo`.
+        goal {
+            forall<T> {
+                T: Foo
+            }
+        } yields[SolverChoice::slg()] {
+            "No possible solution"
+        } yields[SolverChoice::slg().with_mixed_cycles(MixedCyclePolicy::Ambiguous)] {
+            "Ambiguous"
+        } yields[SolverChoice::slg().with_mixed_cycles(MixedCyclePolicy::Stratified)] {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn method_probe_mode() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            trait Foo { }
+            trait Eq<T> { }
+            trait Bar { }
+            struct Vec<T> { }
+            struct Ref<'a, T> { }
+
+            impl<T> Send for T where T: Foo { }
+            impl<T> Foo for T where T: Send { }
+            impl<T> Eq<T> for T { }
+            impl Bar for Vec<u32> { }
+            impl Bar for Vec<i32> { }
+        }
+
+        // Lifetimes do not matter.
+        goal {
+            forall<'a, 'b> {
+                Ref<'a, u32>: Eq<Ref<'b, u32>>
+            }
+        } yields[SolverChoice::method_probe()] {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        // A mixed cycle may hold, rather than not.
+        goal {
+            forall<T> {
+                T: Send
+            }
+        } yields[SolverChoice::slg()] {
             "No possible solution"
+        } yields[SolverChoice::method_probe()] {
+            "Ambiguous"
+        }
+
+        // With several candidates, the first is suggested.
+        goal {
+            exists<T> { Vec<T>: Bar }
+        } yields[SolverChoice::method_probe()] {
+            "Ambiguous; suggested substitution [?0 := "
+        }
+    }
+}
+
+/// The cycles reported while solving `goal_text`, rendered.
+fn solve_goal_cycles(program_text: &str, goal_text: &str) -> Vec<String> {
+    let program = &Arc::new(parse_and_lower_program(program_text, SolverChoice::slg()).unwrap());
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+        let options = SolveOptions::default().with_cycles(true);
+        SolverChoice::slg()
+            .solve_root_goal(env, &goal.into_peeled_goal(), options)
+            .unwrap()
+            .cycles
+            .iter()
+            .map(|cycle| cycle.to_string())
+            .collect()
+    })
+}
+
+#[test]
+fn cycle_reports() {
+    // The mixed cycle `(T: Send) :- (T: Foo) :- (T: Send)` passes
+    // through the inductive goal `T: Foo`, so it yields no answers.
+    let cycles = solve_goal_cycles(
+        "#[auto] trait Send { }
+         trait Foo { }
+         impl<T> Send for T where T: Foo { }
+         impl<T> Foo for T where T: Send { }",
+        "exists<T> { T: Send }",
+    );
+    assert!(
+        cycles.contains(&"Inductive cycle on Implemented(?0: Send)".to_string()),
+        "{:?}",
+        cycles
+    );
+
+    // Here the only cycle is through auto trait goals, so it is
+    // accepted coinductively.
+    let cycles = solve_goal_cycles(
+        "#[auto] trait Send { }
+         struct Ptr<T> { }
+         impl<T> Send for Ptr<T> where T: Send { }
+         struct List<T> { data: T, next: Ptr<List<T>> }",
+        "List<i32>: Send",
+    );
+    assert_eq!(cycles, vec!["Coinductive cycle on Implemented(List<i32>: Send)"]);
+
+    // No cycles at all.
+    let cycles = solve_goal_cycles(
+        "struct Foo { }
+         trait Clone { }
+         impl Clone for Foo { }",
+        "Foo: Clone",
+    );
+    assert!(cycles.is_empty());
+}
+
+#[test]
+fn solver_caches_results_per_environment() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             struct Vec<T> { }
+             impl<T> Foo for Vec<T> where T: Foo { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let mut solver = SolverChoice::slg().into_solver(env);
+        {
+            let mut solve = |goal_text: &str| {
+                let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+                solver.solve(&goal.into_peeled_goal())
+            };
+
+            // `Vec<T>: Foo` holds under the hypothesis `T: Foo`...
+            let goal = "forall<T> { if (T: Foo) { Vec<T>: Foo } }";
+            assert_result(&solve(goal), "Unique");
+            assert_result(&solve(goal), "Unique");
+
+            // ...but the answer found in that environment must not leak
+            // into one without the hypothesis.
+            assert_result(&solve("forall<T> { Vec<T>: Foo }"), "No possible solution");
+            assert_result(&solve(goal), "Unique");
+        }
+        assert_eq!(solver.num_cached_results(), 2);
+    });
+}
+
+#[test]
+fn solver_warm_start() {
+    let lower = |text: &str| {
+        let program = Arc::new(parse_and_lower_program(text, SolverChoice::slg()).unwrap());
+        let env = program.environment();
+        (program, env)
+    };
+    let (program, env) = &lower("trait Foo { } struct Bar { } impl Foo for Bar { }");
+    let (_, other_env) = &lower("trait Foo { } struct Bar { }");
+
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Bar: Foo").unwrap().into_peeled_goal();
+
+        let mut solver = SolverChoice::slg().into_solver(env);
+        assert_result(&solver.solve(&goal), "Unique");
+        let cache = solver.export_cache();
+        assert_eq!(cache.len(), 1);
+
+        // A fresh solver for the same program picks up the solution.
+        let mut warm_solver = SolverChoice::slg().into_solver(env);
+        assert!(warm_solver.warm_start(cache.clone()));
+        assert_eq!(warm_solver.num_cached_results(), 1);
+        assert_result(&warm_solver.solve(&goal), "Unique");
+
+        // Solutions that are already cached are not added again.
+        assert!(warm_solver.warm_start(cache.clone()));
+        assert_eq!(warm_solver.num_cached_results(), 1);
+
+        // The cache is rejected for a different program...
+        let mut other_solver = SolverChoice::slg().into_solver(other_env);
+        assert!(!other_solver.warm_start(cache.clone()));
+        assert_eq!(other_solver.num_cached_results(), 0);
+
+        // ...and for different solver settings.
+        let other_choice = SolverChoice::slg().with_max_size(5);
+        assert!(!other_choice.into_solver(env).warm_start(cache));
+    });
+}
+
+#[test]
+fn solver_cache_round_trip() {
+    let text = "trait Foo { } struct Bar<T> { } impl<T> Foo for Bar<T> where T: Foo { } impl Foo for i32 { }";
+    let program = Arc::new(parse_and_lower_program(text, SolverChoice::slg()).unwrap());
+    let env = &program.environment();
+
+    // The hash does not depend on how items are named while it is
+    // computed, nor on the process interning their names, which both
+    // lowerings here share.
+    let relowered = Arc::new(parse_and_lower_program(text, SolverChoice::slg()).unwrap());
+    let relowered_env = &relowered.environment();
+    let program_hash = env.program_hash();
+    assert_eq!(relowered_env.program_hash(), program_hash);
+    ir::tls::set_current_program(&program, || assert_eq!(env.program_hash(), program_hash));
+
+    ir::tls::set_current_program(&program, || {
+        let mut solver = SolverChoice::slg().into_solver(env);
+        for goal in &["Bar<i32>: Foo", "exists<T> { Bar<T>: Foo }"] {
+            let goal = parse_and_lower_goal(&program, goal).unwrap().into_peeled_goal();
+            solver.solve(&goal).unwrap();
+        }
+        let cache = solver.export_cache();
+        assert_eq!(cache.len(), 2);
+
+        let written = serde_json::to_string(&cache).unwrap();
+        let read: SolverCache = serde_json::from_str(&written).unwrap();
+        assert_eq!(read, cache);
+
+        let mut warm_solver = SolverChoice::slg().into_solver(relowered_env);
+        assert!(warm_solver.warm_start(read));
+        assert_eq!(warm_solver.num_cached_results(), 2);
+    });
+}
+
+#[test]
+fn program_hash_is_lazy() {
+    // The hash is FNV-1a, over strings spelled out after their length.
+    assert_eq!(ir::serialization::stable_hash(""), 0xa8c7_f832_281a_39c5);
+    assert_eq!(ir::serialization::stable_hash("ab"), 0x9c60_01d3_28a9_4690);
+
+    let text = "trait Foo { } struct Bar { } impl Foo for Bar { }";
+    let program = Arc::new(parse_and_lower_program(text, SolverChoice::slg()).unwrap());
+    let env = &program.environment();
+
+    // Solvers without a cache never need the hash...
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Bar: Foo").unwrap().into_peeled_goal();
+        let mut solver = SolverChoice::slg().into_solver(env);
+        assert_result(&solver.solve(&goal), "Unique");
+    });
+    assert_eq!(*env.program_hash.hash.lock().unwrap(), None);
+
+    // ...and once computed, it is kept.
+    let program_hash = env.program_hash();
+    assert_eq!(*env.program_hash.hash.lock().unwrap(), Some(program_hash));
+
+    let other_text = "trait Foo { } struct Bar { }";
+    let other = parse_and_lower_program(other_text, SolverChoice::slg()).unwrap();
+    assert_ne!(other.environment().program_hash(), program_hash);
+}
+
+#[test]
+fn solver_reuse() {
+    let program = Arc::new(
+        parse_and_lower_program(
+            "trait Foo { } struct Bar { } struct Baz { } struct Vec<T> { }
+             impl Foo for Bar { } impl<T> Foo for Vec<T> where T: Foo { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+
+    ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal();
+        let mut solver = Solver::new(&program, SolverChoice::slg());
+        assert_result(&solver.solve(&goal("Vec<Bar>: Foo")), "Unique");
+        assert_result(&solver.solve(&goal("Vec<Vec<Bar>>: Foo")), "Unique");
+        assert_result(&solver.solve(&goal("Vec<Baz>: Foo")), "No possible solution");
+        assert_eq!(solver.num_cached_results(), 3);
+
+        // Solving a goal again is answered from the cache.
+        assert_result(&solver.solve(&goal("Vec<Bar>: Foo")), "Unique");
+        assert_eq!(solver.num_cached_results(), 3);
+    });
+}
+
+#[test]
+fn goal_cache() {
+    let program = Arc::new(
+        parse_and_lower_program(
+            "trait Foo { } struct Bar { } struct Baz { } struct Vec<T> { }
+             impl Foo for Bar { } impl<T> Foo for Vec<T> where T: Foo { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    let goal_cache = GoalCache::new();
+
+    ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal();
+        let mut solver = SolverChoice::slg().into_solver_with_goal_cache(env, &goal_cache);
+        assert_result(&solver.solve(&goal("Vec<Bar>: Foo")), "Unique");
+        assert_result(&solver.solve(&goal("Vec<Baz>: Foo")), "No possible solution");
+        assert_eq!(goal_cache.len(), 2);
+        assert_eq!(goal_cache.hits(), 0);
+
+        // Another solver, here on another thread, finds the solutions
+        // in the shared cache rather than proving the goals again.
+        let vec_bar = goal("Vec<Bar>: Foo");
+        let thread = {
+            let program = program.clone();
+            let env = env.clone();
+            let goal_cache = goal_cache.clone();
+            ::std::thread::spawn(move || {
+                ir::tls::set_current_program(&program, || {
+                    let mut solver = SolverChoice::slg().into_solver_with_goal_cache(&env, &goal_cache);
+                    solver.solve(&vec_bar).unwrap()
+                })
+            })
+        };
+        assert!(thread.join().unwrap().unwrap().is_unique());
+        assert_eq!(goal_cache.hits(), 1);
+
+        // Solutions found with other settings are not reused.
+        let mut solver = SolverChoice::naive().into_solver_with_goal_cache(env, &goal_cache);
+        assert_result(&solver.solve(&goal("Vec<Bar>: Foo")), "Unique");
+        assert_eq!(goal_cache.len(), 3);
+        assert_eq!(goal_cache.hits(), 1);
+
+        // Nor are solutions found for another program.
+        let other_program = Arc::new(
+            parse_and_lower_program(
+                "trait Foo { } struct Bar { } struct Baz { } struct Vec<T> { }",
+                SolverChoice::slg(),
+            ).unwrap(),
+        );
+        let mut solver = SolverChoice::slg().into_solver_with_goal_cache(&other_program.environment(), &goal_cache);
+        assert_result(&solver.solve(&goal("Vec<Bar>: Foo")), "No possible solution");
+        assert_eq!(goal_cache.len(), 4);
+        assert_eq!(goal_cache.hits(), 1);
+
+        goal_cache.clear();
+        assert!(goal_cache.is_empty());
+    });
+}
+
+#[test]
+fn goal_cache_invalidation() {
+    let program = Arc::new(
+        parse_and_lower_program(
+            "trait Foo { } trait Bar { } struct A { } struct B { } struct Vec<T> { }
+             impl Foo for A { } impl<T> Foo for Vec<T> where T: Bar { } impl Bar for B { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    let foo = program.item_id("Foo").unwrap();
+    let bar = program.item_id("Bar").unwrap();
+    let a = program.item_id("A").unwrap();
+
+    ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal();
+        for &solver_choice in &[SolverChoice::slg(), SolverChoice::naive()] {
+            let goal_cache = GoalCache::new();
+            let fill = || {
+                // Separate solvers, so that neither shares tables with
+                // the other.
+                for text in &["A: Foo", "Vec<B>: Foo"] {
+                    let mut solver = solver_choice.into_solver_with_goal_cache(env, &goal_cache);
+                    assert_result(&solver.solve(&goal(text)), "Unique");
+                }
+                assert_eq!(goal_cache.len(), 2);
+            };
+
+            // `Vec<B>: Foo` is proven with an impl of `Bar`.
+            fill();
+            assert_eq!(goal_cache.invalidate_item(bar), 1);
+            assert_eq!(goal_cache.invalidate_item(a), 1);
+            assert!(goal_cache.is_empty());
+
+            // Both goals may depend on any impl of `Foo`.
+            fill();
+            assert_eq!(goal_cache.invalidate_item(program.impls_of(foo)[0]), 2);
+        }
+
+        // Removing the impl of `Bar` only affects `Vec<B>: Foo`, so the
+        // other solution carries over to the edited program.
+        let edited = Arc::new(
+            parse_and_lower_program(
+                "trait Foo { } trait Bar { } struct A { } struct B { } struct Vec<T> { }
+                 impl Foo for A { } impl<T> Foo for Vec<T> where T: Bar { }",
+                SolverChoice::slg(),
+            ).unwrap(),
+        );
+        let edited_env = &edited.environment();
+        let goal_cache = GoalCache::new();
+        for text in &["A: Foo", "Vec<B>: Foo"] {
+            let mut solver = SolverChoice::slg().into_solver_with_goal_cache(env, &goal_cache);
+            assert_result(&solver.solve(&goal(text)), "Unique");
+        }
+        assert_eq!(goal_cache.invalidate_item(bar), 1);
+        assert_eq!(goal_cache.carry_over(env, edited_env), 1);
+
+        let mut solver = SolverChoice::slg().into_solver_with_goal_cache(edited_env, &goal_cache);
+        assert_result(&solver.solve(&goal("A: Foo")), "Unique");
+        assert_eq!(goal_cache.hits(), 1);
+        assert_result(&solver.solve(&goal("Vec<B>: Foo")), "No possible solution");
+    });
+}
+
+#[test]
+fn solver_solve_iter() {
+    let program = Arc::new(
+        parse_and_lower_program(
+            "trait Foo { } trait Small { } struct Bar { } struct Baz { } struct Vec<T> { }
+             impl Foo for Bar { } impl Foo for Baz { } impl<T> Foo for Vec<T> where T: Foo { }
+             impl Small for Bar { } impl Small for Baz { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+
+    ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal();
+        let mut solver = Solver::new(&program, SolverChoice::slg());
+
+        let mut answers: Vec<String> = solver
+            .solve_iter(&goal("exists<T> { T: Small }"), None)
+            .unwrap()
+            .map(|answer| answer.subst.value.subst.to_string())
+            .collect();
+        answers.sort();
+        assert_eq!(answers, vec!["[?0 := Bar]", "[?0 := Baz]"]);
+
+        // There are infinitely many answers, so take only a few.
+        let answers: Vec<_> = solver
+            .solve_iter(&goal("exists<T> { T: Foo }"), Some(3))
+            .unwrap()
+            .collect();
+        assert_eq!(answers.len(), 3);
+
+        let mut naive = Solver::new(&program, SolverChoice::naive());
+        assert!(naive.solve_iter(&goal("Bar: Foo"), None).is_err());
+    });
+}
+
+#[test]
+fn answer_store() {
+    // An answer with a subset of the conditions of another subsumes it.
+    #[derive(Debug, PartialEq)]
+    struct Conditional {
+        fact: &'static str,
+        conditions: Vec<u32>,
+    }
+
+    impl StoredAnswer for Conditional {
+        type Key = &'static str;
+
+        fn key(&self) -> &Self::Key {
+            &self.fact
+        }
+
+        fn subsumes(&self, other: &Self) -> bool {
+            self.conditions.iter().all(|c| other.conditions.contains(c))
+        }
+    }
+
+    let answer = |fact, conditions: &[u32]| Conditional {
+        fact,
+        conditions: conditions.to_vec(),
+    };
+    let mut store = AnswerStore::new();
+    assert!(store.insert(answer("Vec<?0>", &[1, 2])));
+    assert!(store.insert(answer("Vec<u32>", &[3])));
+    assert!(!store.insert(answer("Vec<?0>", &[1, 2, 3])));
+    assert!(store.insert(answer("Vec<?0>", &[1])));
+    assert!(!store.insert(answer("Vec<?0>", &[1, 2])));
+
+    // Subsumed answers stay in place, but are no longer looked up.
+    assert_eq!(store.len(), 3);
+    assert_eq!(store.get(0), Some(&answer("Vec<?0>", &[1, 2])));
+    assert_eq!(store.lookup(&"Vec<?0>"), vec![&answer("Vec<?0>", &[1])]);
+    assert!(store.lookup(&"Vec<i32>").is_empty());
+
+    let generalizes = |general: &&str, specific: &&str| {
+        general == specific || general.ends_with("?0>") && specific.starts_with("Vec<")
+    };
+    assert_eq!(
+        store.lookup_generalizing(&"Vec<u32>", generalizes),
+        vec![&answer("Vec<u32>", &[3]), &answer("Vec<?0>", &[1])]
+    );
+}
+
+#[test]
+fn query_log() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             struct Bar { }
+             struct Vec<T> { }
+             impl Foo for Bar { }
+             impl<T> Foo for Vec<T> where T: Foo { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Vec<Bar>: Foo").unwrap().into_peeled_goal();
+
+        // A solver does not solve the same root goal twice...
+        let log = QueryLog::new();
+        let mut solver = SolverChoice::slg().into_solver_with_query_log(env, &log);
+        assert_result(&solver.solve(&goal), "Unique");
+        assert_result(&solver.solve(&goal), "Unique");
+        assert_eq!(log.stats(QueryLevel::Root), QueryStats { solved: 1, distinct: 1 });
+        let subgoals = log.stats(QueryLevel::Subgoal);
+        assert_eq!(subgoals.duplicates(), 0);
+        assert!(log.repeated().is_empty());
+
+        // ...but a second solver repeats all of its work.
+        let mut other_solver = SolverChoice::slg().into_solver_with_query_log(env, &log);
+        assert_result(&other_solver.solve(&goal), "Unique");
+        assert_eq!(log.stats(QueryLevel::Root), QueryStats { solved: 2, distinct: 1 });
+        assert_eq!(log.stats(QueryLevel::Subgoal).duplicates(), subgoals.distinct);
+        let repeated = log.repeated();
+        assert_eq!(repeated[0].level, QueryLevel::Root);
+        assert_eq!(repeated[0].goal, goal);
+        assert_eq!(repeated[0].count, 2);
+        assert!(log.exceeded().is_none());
+
+        // In assertion mode, that makes the second solver fail.
+        let log = QueryLog::with_max_repeats(1);
+        let mut solver = SolverChoice::slg().into_solver_with_query_log(env, &log);
+        assert_result(&solver.solve(&goal), "Unique");
+        let mut other_solver = SolverChoice::slg().into_solver_with_query_log(env, &log);
+        assert!(other_solver.solve(&goal).is_err());
+        assert_eq!(log.exceeded().unwrap().count, 2);
+    });
+}
+
+fn solve_goal_trace(program_text: &str, goal_text: &str) -> ProofTrace {
+    let program = &Arc::new(parse_and_lower_program(program_text, SolverChoice::slg()).unwrap());
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+        let options = SolveOptions::default().with_trace(true);
+        SolverChoice::slg()
+            .solve_root_goal(env, &goal.into_peeled_goal(), options)
+            .unwrap()
+            .trace
+            .unwrap()
+    })
+}
+
+#[test]
+fn trace_diff() {
+    let program = "trait Foo { }
+                   trait Bar { }
+                   struct Vec<T> { }
+                   impl<T> Foo for Vec<T> where T: Bar { }";
+    let before = solve_goal_trace(program, "Vec<u32>: Foo");
+
+    // Solving the same goal twice gives the same trace.
+    assert!(before.diff(&solve_goal_trace(program, "Vec<u32>: Foo")).is_empty());
+
+    // Adding an impl changes the answers for `u32: Bar` and for the
+    // root goal, but not which subgoals are considered.
+    let after = solve_goal_trace(
+        &format!("{} impl Bar for u32 {{ }}", program),
+        "Vec<u32>: Foo",
+    );
+    let diff = before.diff(&after);
+    assert!(diff.only_in_left.is_empty());
+    assert!(diff.only_in_right.is_empty());
+    assert_eq!(diff.diverged.len(), 2);
+    for (left, right) in &diff.diverged {
+        assert!(left.answers.is_empty());
+        assert!(!right.answers.is_empty());
+    }
+    let rendered = diff.to_string();
+    let mut diverged: Vec<_> = rendered.lines().filter(|line| line.starts_with("~ ")).collect();
+    diverged.sort();
+    assert_eq!(
+        diverged,
+        vec!["~ Implemented(Vec<u32>: Foo)", "~ Implemented(u32: Bar)"],
+        "{}",
+        rendered
+    );
+
+    // Dropping the where clause means `u32: Bar` is never considered.
+    let after = solve_goal_trace(
+        "trait Foo { }
+         trait Bar { }
+         struct Vec<T> { }
+         impl<T> Foo for Vec<T> { }",
+        "Vec<u32>: Foo",
+    );
+    let diff = before.diff(&after);
+    assert_eq!(diff.only_in_left.len(), 1);
+    assert!(diff.only_in_right.is_empty());
+    assert_eq!(diff.diverged.len(), 1);
+    let rendered = diff.to_string();
+    assert!(
+        rendered.starts_with(
+            "- Implemented(u32: Bar)\n    no answers\n\
+             ~ Implemented(Vec<u32>: Foo)\n    no answers\n  became\n"
+        ),
+        "{}",
+        rendered
+    );
+}
+
+#[test]
+fn proof_tree() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             trait Bar { }
+             struct Vec<T> { }
+             impl<T> Foo for Vec<T> where T: Bar { }
+             impl Bar for u32 { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+
+    ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal();
+        let options = || SolveOptions::default().with_proof(true);
+
+        let outcome = SolverChoice::slg()
+            .solve_root_goal(env, &goal("Vec<u32>: Foo"), options())
+            .unwrap();
+        assert!(outcome.solution.unwrap().is_unique());
+        let proof = outcome.proof.unwrap();
+        let rendered = proof.to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", rendered);
+        assert_eq!(lines[0], "Implemented(Vec<u32>: Foo)");
+        assert!(lines[1].starts_with("  using "), "{}", rendered);
+        assert_eq!(lines[2], "  Implemented(u32: Bar)");
+        assert!(lines[3].starts_with("    using "), "{}", rendered);
+        assert!(proof.clause.is_some());
+        assert_eq!(proof.size(), 2);
+        let subproof = &proof.subproofs[0];
+        assert_eq!(format!("{:?}", subproof.goal.canonical.value.goal), "Implemented(u32: Bar)");
+        assert!(subproof.clause.is_some());
+        assert!(!subproof.ambiguous);
+
+        // Goals that are not domain goals are broken down, rather than
+        // proven with a clause.
+        let proof = SolverChoice::slg()
+            .solve_root_goal(env, &goal("exists<T> { Vec<T>: Foo }"), options())
+            .unwrap()
+            .proof
+            .unwrap();
+        let rendered = proof.to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert!(lines[0].ends_with(" with [?0 := u32]"), "{}", rendered);
+        assert!(!lines[1].trim_left().starts_with("using "), "{}", rendered);
+        assert!(proof.clause.is_none());
+        assert_eq!(proof.answer.value.subst.to_string(), "[?0 := u32]");
+        assert_eq!(proof.size(), 3);
+
+        // A goal without answers has no proof.
+        let outcome = SolverChoice::slg()
+            .solve_root_goal(env, &goal("Vec<Vec<u32>>: Foo"), options())
+            .unwrap();
+        assert!(outcome.solution.is_none());
+        assert!(outcome.proof.is_none());
+
+        assert!(SolverChoice::naive()
+            .solve_root_goal(env, &goal("Vec<u32>: Foo"), options())
+            .is_err());
+    });
+}
+
+#[test]
+fn ambiguity_info() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait SomeTrait<T> { }
+             struct Bar { }
+             impl SomeTrait<i32> for Bar { }
+             impl SomeTrait<bool> for Bar { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+
+    ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal();
+        let options = || SolveOptions::default().with_ambiguity_info(true);
+
+        let outcome = SolverChoice::slg()
+            .solve_root_goal(env, &goal("exists<T> { Bar: SomeTrait<T> }"), options())
+            .unwrap();
+        assert_result(&Ok(outcome.solution), "Ambiguous");
+        let info = outcome.ambiguity_info.unwrap();
+        assert_eq!(info.reason, AmbiguityReason::InsufficientTypeInfo);
+        let rendered = info.to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines[0], "ambiguous: insufficient type information");
+        assert_eq!(lines[1], "`Implemented(Bar: SomeTrait<?0>)` could be proven by any of:");
+        let mut answers: Vec<_> = lines[2..]
+            .iter()
+            .map(|line| &line[line.rfind(", giving ").expect(line)..])
+            .collect();
+        answers.sort();
+        assert_eq!(answers, vec![", giving [?0 := bool]", ", giving [?0 := i32]"]);
+
+        // Solutions that are not ambiguous need no explanation.
+        let outcome = SolverChoice::slg()
+            .solve_root_goal(env, &goal("Bar: SomeTrait<i32>"), options())
+            .unwrap();
+        assert!(outcome.ambiguity_info.is_none());
+
+        assert!(SolverChoice::naive()
+            .solve_root_goal(env, &goal("Bar: SomeTrait<i32>"), options())
+            .is_err());
+    });
+}
+
+#[test]
+fn subgoal_order() {
+    let choice = |subgoal_order| SolverChoice::slg().with_subgoal_order(subgoal_order);
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             trait Bar { }
+             impl Foo for u32 { }
+             impl Foo for i32 { }
+             impl Bar for u32 { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Bar, T: Foo }")
+            .unwrap()
+            .into_peeled_goal();
+
+        let options = || SolveOptions::default().with_trace(true);
+        let outcome = choice(SubgoalOrder::Last)
+            .solve_root_goal(env, &goal, options())
+            .unwrap();
+        assert_result(&Ok(outcome.solution), "Unique; substitution [?0 := u32]");
+        assert!(outcome.trace.unwrap().plans.is_empty());
+
+        // `?0: Bar` has fewer candidate impls, so it is solved first.
+        let outcome = choice(SubgoalOrder::CheapestFirst)
+            .solve_root_goal(env, &goal, options())
+            .unwrap();
+        assert_result(&Ok(outcome.solution), "Unique; substitution [?0 := u32]");
+        let trace = outcome.trace.unwrap();
+        assert_eq!(trace.plans.len(), 1);
+        let plan = &trace.plans[0];
+        let (selected_goal, selected_cost) = &plan.subgoals[plan.selected];
+        assert_eq!(format!("{:?}", selected_goal), "Implemented(?0: Bar)");
+        assert!(plan.subgoals.iter().all(|(_, cost)| selected_cost <= cost));
+
+        // The selected subgoal is marked, and each is shown with its cost.
+        let rendered = plan.to_string();
+        let mut goals: Vec<_> = rendered
+            .lines()
+            .map(|line| &line[..line.find(" (").expect(line)])
+            .collect();
+        goals.sort();
+        assert_eq!(goals, vec!["  Implemented(?0: Foo)", "* Implemented(?0: Bar)"]);
+        assert!(
+            rendered.contains(&format!("* Implemented(?0: Bar) ({})", selected_cost)),
+            "{}",
+            rendered
+        );
+    });
+}
+
+#[test]
+fn proven_facts() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "struct Foo { }
+             struct Bar<T> { }
+             trait Clone { }
+             trait Into<T> { }
+             impl Clone for Foo { }
+             impl<T> Clone for Bar<T> where T: Clone { }
+             impl Into<Bar<Foo>> for Foo { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let mut solver = SolverChoice::slg().into_solver(env);
+        for goal_text in &[
+            "Bar<Bar<Foo>>: Clone",
+            "exists<T> { Foo: Into<T> }",
+            "forall<T> { Bar<T>: Clone }",
+        ] {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            solver.solve(&goal.into_peeled_goal()).unwrap();
+        }
+
+        // The subgoals of the first goal are facts too, but nothing is
+        // proven about the placeholder in the last one.
+        let facts = solver.proven_facts();
+        assert_eq!(facts.len(), 4);
+        assert_eq!(
+            facts.to_datalog(),
+            ".decl implemented(trait: symbol, self_ty: symbol, parameters: symbol)\n\
+             implemented(\"Clone\", \"Foo\", \"\").\n\
+             implemented(\"Clone\", \"Bar<Foo>\", \"\").\n\
+             implemented(\"Clone\", \"Bar<Bar<Foo>>\", \"\").\n\
+             implemented(\"Into\", \"Foo\", \"Bar<Foo>\").\n"
+        );
+        assert_eq!(
+            facts.to_csv(),
+            "trait,self_ty,parameters\n\
+             \"Clone\",\"Foo\",\"\"\n\
+             \"Clone\",\"Bar<Foo>\",\"\"\n\
+             \"Clone\",\"Bar<Bar<Foo>>\",\"\"\n\
+             \"Into\",\"Foo\",\"Bar<Foo>\"\n"
+        );
+    });
+}
+
+#[test]
+fn impl_candidates() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "struct Foo { }
+             struct Bar<T> { }
+             trait Clone { }
+             trait Send { }
+             impl Clone for Foo { }
+             impl<T> Clone for Bar<T> where T: Clone, T: Send { }
+             impl !Send for Foo { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    ir::tls::set_current_program(&program, || {
+        let candidates = |goal_text: &str| {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            program.impl_candidates(&goal.into_peeled_goal())
+        };
+
+        // The where clauses are left to the caller, even though
+        // `Foo: Send` can never hold.
+        let bar = candidates("Bar<Foo>: Clone").unwrap();
+        assert_eq!(bar.len(), 1);
+        let residual = bar[0].residual.value();
+        assert!(bar[0].residual.binders().is_empty());
+        assert_eq!(
+            format!("{:?}", residual.obligations.iter().map(|o| &o.goal).collect::<Vec<_>>()),
+            "[Implemented(Foo: Clone), Implemented(Foo: Send)]"
+        );
+
+        // Unifying with the header constrains the goal's variable.
+        let any = candidates("exists<T> { T: Clone }").unwrap();
+        assert_eq!(any.len(), 2);
+        assert_eq!(format!("{:?}", any[0].residual.value().subst), "[?0 := Foo]");
+        assert_eq!(format!("{:?}", any[1].residual.value().subst), "[?0 := Bar<?0>]");
+        assert_eq!(any[1].residual.binders().len(), 1);
+
+        // Negative impls are not candidates.
+        assert!(candidates("Foo: Send").unwrap().is_empty());
+        assert!(candidates("Bar<Foo>: Send").unwrap().is_empty());
+
+        assert!(candidates("Foo = Foo").is_err());
+    });
+}
+
+#[test]
+fn invariants() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Clone { }
+             struct Vec<T> { }
+             impl<T> Clone for Vec<T> { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        for clause in &env.program_clauses {
+            assert!(invariants::closed_violations(clause).is_empty());
+        }
+
+        let goal = parse_and_lower_goal(&program, "exists<T> { Vec<T>: Clone }")
+            .unwrap()
+            .into_peeled_goal();
+        assert!(invariants::u_canonical_violations(&goal).is_empty());
+
+        let solution = SolverChoice::slg()
+            .solve_root_goal(env, &goal, SolveOptions::default())
+            .unwrap()
+            .solution
+            .unwrap();
+        assert!(invariants::solution_violations(&goal, &solution).is_empty());
+
+        // Corrupt the goal and solution to check that this is noticed.
+        let mut bad_goal = goal.clone();
+        bad_goal.universes = 0;
+        assert_eq!(
+            invariants::u_canonical_violations(&bad_goal),
+            vec!["variable ?0 is in universe U0, but there are only 0 universes"]
+        );
+
+        let bad_solution = match solution {
+            Solution::Unique(mut answer) => {
+                answer.binders.clear();
+                let parameter = answer.value.subst.parameters[0].clone();
+                answer.value.subst.parameters.push(parameter);
+                Solution::Unique(answer)
+            }
+            solution => panic!("unexpected solution {:?}", solution),
+        };
+        assert_eq!(
+            invariants::solution_violations(&goal, &bad_solution),
+            vec![
+                "variable ^0 is not bound, as there are only 0 binders",
+                "variable ^0 is not bound, as there are only 0 binders",
+                "substitution has 2 parameters for 1 binders",
+            ]
+        );
+    });
+}
+
+#[test]
+fn limits_hit() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             trait Make<T> { }
+             trait Big<T> { }
+             struct Vec<T> { }
+             impl<T> Foo for Vec<T> where Vec<Vec<T>>: Foo { }
+             impl<T> Make<T> for u32 where u32: Big<T> { }
+             impl Big<Vec<Vec<Vec<Vec<Vec<Vec<Vec<Vec<Vec<Vec<Vec<Vec<u32>>>>>>>>>>>>> for u32 { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let solve = |goal_text: &str| {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap().into_peeled_goal();
+            let options = SolveOptions::default().with_limits_hit(true);
+            let outcome = SolverChoice::slg().solve_root_goal(env, &goal, options).unwrap();
+            (outcome.solution, outcome.limits_hit)
+        };
+
+        let (_, limits) = solve("u32: Big<Vec<u32>>");
+        assert!(limits.is_empty());
+
+        // Each subgoal is larger than the last, until one is truncated.
+        let (_, limits) = solve("Vec<u32>: Foo");
+        assert!(!limits.is_empty());
+        assert!(limits.iter().all(|report| report.kind == LimitKind::SubgoalSize));
+        assert!(limits.iter().all(|report| report.max_size == 10));
+
+        // The answer of `u32: Big<?0>` is fine on its own, but is too
+        // large once it becomes the answer of `u32: Make<?0>`.
+        let (solution, limits) = solve("exists<T> { u32: Make<T> }");
+        assert_eq!(solution.unwrap().ambiguity_reason(), Some(AmbiguityReason::Overflow));
+        assert_eq!(limits.len(), 1);
+        assert_eq!(limits[0].kind, LimitKind::AnswerSize);
+        assert_eq!(
+            limits[0].to_string(),
+            "answer size limit of 10 hit while solving Implemented(u32: Make<?0>)"
+        );
+    });
+}
+
+fn solve_goal_ambiguity_reason(
+    program_text: &str,
+    goal_text: &str,
+    solver_choice: SolverChoice,
+) -> Option<AmbiguityReason> {
+    let program = &Arc::new(parse_and_lower_program(program_text, solver_choice).unwrap());
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+        solver_choice
+            .solve_root_goal(env, &goal.into_peeled_goal(), SolveOptions::default())
+            .unwrap()
+            .solution
+            .unwrap()
+            .ambiguity_reason()
+    })
+}
+
+#[test]
+fn ambiguity_reasons() {
+    let program = "trait Foo { }
+                   trait Iterator { type Item; }
+                   struct Vec<T> { }
+                   impl Foo for u32 { }
+                   impl Foo for i32 { }
+                   impl Iterator for u32 { type Item = u32; }
+                   impl Iterator for i32 { type Item = i32; }";
+
+    assert_eq!(
+        solve_goal_ambiguity_reason(program, "u32: Foo", SolverChoice::slg()),
+        None
+    );
+    for &solver_choice in &[SolverChoice::slg(), SolverChoice::naive()] {
+        assert_eq!(
+            solve_goal_ambiguity_reason(program, "exists<T> { T: Foo }", solver_choice),
+            Some(AmbiguityReason::InsufficientTypeInfo)
+        );
+        assert_eq!(
+            solve_goal_ambiguity_reason(
+                program,
+                "exists<T, U> { Normalize(<T as Iterator>::Item -> U) }",
+                solver_choice
+            ),
+            Some(AmbiguityReason::UnresolvedProjection)
+        );
+    }
+
+    // Both clauses prove the goal, under different lifetime
+    // constraints.
+    assert_eq!(
+        solve_goal_ambiguity_reason(
+            "trait Pick<A, B> { }
+             struct Ref<'a> { }
+             forall<'a, 'b> { Ref<'a>: Pick<Ref<'a>, Ref<'b>> }
+             forall<'a, 'b> { Ref<'a>: Pick<Ref<'b>, Ref<'a>> }",
+            "forall<'x, 'y, 'z> { Ref<'x>: Pick<Ref<'y>, Ref<'z>> }",
+            SolverChoice::slg()
+        ),
+        Some(AmbiguityReason::MultipleCandidates)
+    );
+    assert_eq!(
+        solve_goal_ambiguity_reason(program, "exists<T> { not { T: Foo } }", SolverChoice::slg()),
+        Some(AmbiguityReason::FlounderedNegation)
+    );
+
+    // The negative goal exceeds the maximum size of 3.
+    let small = SolverChoice::slg().with_max_size(3);
+    assert_eq!(
+        solve_goal_ambiguity_reason(
+            program,
+            "exists<T> { T = Vec<u32>, not { Vec<Vec<T>>: Foo } }",
+            small
+        ),
+        Some(AmbiguityReason::Overflow)
+    );
+
+    assert_eq!(
+        solve_goal_ambiguity_reason(
+            "trait P { }
+             trait Q { }
+             forall<> { u32: P if not { u32: Q } }
+             forall<> { u32: Q if not { u32: P } }",
+            "u32: P",
+            SolverChoice::slg()
+        ),
+        Some(AmbiguityReason::NegativeCycle)
+    );
+
+    // The reason is propagated from `T: Foo`, where the cycle is
+    // detected, to `T: Send`.
+    let ambiguous_cycles = SolverChoice::slg().with_mixed_cycles(MixedCyclePolicy::Ambiguous);
+    assert_eq!(
+        solve_goal_ambiguity_reason(
+            "#[auto] trait Send { }
+             trait Foo { }
+             impl<T> Send for T where T: Foo { }
+             impl<T> Foo for T where T: Send { }",
+            "forall<T> { T: Send }",
+            ambiguous_cycles
+        ),
+        Some(AmbiguityReason::MixedCycle)
+    );
+}
+
+#[test]
+fn solve_with_budget() {
+    // Proving `u32: Foo` takes more than one quantum, since the only
+    // candidate fails.
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             trait Bar { }
+             impl<T> Foo for T where T: Bar { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "u32: Foo")
+            .unwrap()
+            .into_peeled_goal();
+
+        // Running out of budget is not the same as failing.
+        let with_budget = |budget| SolveOptions::default().with_budget(budget);
+        let solution = SolverChoice::slg()
+            .solve_root_goal(env, &goal, with_budget(Budget::Quanta(0)))
+            .unwrap()
+            .solution
+            .unwrap();
+        assert_eq!(solution.ambiguity_reason(), Some(AmbiguityReason::BudgetExceeded));
+        assert_eq!(solution.to_string(), "Ambiguous; no inference guidance; budget exceeded");
+
+        let solution = SolverChoice::slg()
+            .solve_root_goal(env, &goal, with_budget(Budget::Deadline(Instant::now())))
+            .unwrap()
+            .solution
+            .unwrap();
+        assert_eq!(solution.ambiguity_reason(), Some(AmbiguityReason::BudgetExceeded));
+
+        // With enough budget, we get the real answer.
+        let solution = SolverChoice::slg()
+            .solve_root_goal(env, &goal, with_budget(Budget::Quanta(100)))
+            .unwrap()
+            .solution;
+        assert_eq!(solution, None);
+
+        // A `Solver` does not cache partial solutions.
+        let mut solver = SolverChoice::slg().into_solver(env);
+        let solution = solver
+            .solve_with_budget(&goal, Budget::Quanta(0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(solution.ambiguity_reason(), Some(AmbiguityReason::BudgetExceeded));
+        assert_eq!(solver.num_cached_results(), 0);
+        assert_eq!(solver.solve(&goal).unwrap(), None);
+        assert_eq!(solver.num_cached_results(), 1);
+    });
+}
+
+#[test]
+fn solve_with_cancellation() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             trait Bar { }
+             impl<T> Foo for T where T: Bar { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "u32: Foo")
+            .unwrap()
+            .into_peeled_goal();
+
+        for &solver_choice in &[SolverChoice::slg(), SolverChoice::naive()] {
+            let cancelled = CancellationToken::new();
+            cancelled.clone().cancel();
+            let expired = CancellationToken::with_deadline(Instant::now());
+            for token in &[cancelled, expired] {
+                assert!(token.is_cancelled());
+                let options = SolveOptions::default().with_cancellation(token);
+                match solver_choice.solve_root_goal(env, &goal, options) {
+                    Err(error) => match error.kind() {
+                        ErrorKind::Cancelled => {}
+                        kind => panic!("unexpected error: {}", kind),
+                    },
+                    Ok(outcome) => panic!("expected cancellation, got {:?}", outcome.solution),
+                }
+            }
+
+            // A token that is never cancelled changes nothing.
+            let token = CancellationToken::new();
+            let options = SolveOptions::default().with_cancellation(&token);
+            let solution = solver_choice
+                .solve_root_goal(env, &goal, options)
+                .unwrap()
+                .solution;
+            assert_eq!(solution, None);
+        }
+
+        // A `Solver` does not cache the solutions it was cancelled on.
+        let mut solver = SolverChoice::slg().into_solver(env);
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(solver.solve_with_cancellation(&goal, &token).is_err());
+        assert_eq!(solver.num_cached_results(), 0);
+        assert_eq!(solver.solve(&goal).unwrap(), None);
+    });
+}
+
+#[test]
+fn environment_overflow() {
+    // Proving `u32: Foo` assumes `Box<u32>: Bar`, then `Box<Box<u32>>:
+    // Bar`, and so on, growing the environment without bound.
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             trait Bar { }
+             struct Box<T> { }
+             forall<T> { T: Foo if if (Box<T>: Bar) { Box<T>: Foo } }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "u32: Foo")
+            .unwrap()
+            .into_peeled_goal();
+
+        let solver_choice = SolverChoice::slg().with_max_environment_clauses(3);
+        let mut solver = solver_choice.into_solver(env);
+        let solution = solver.solve(&goal).unwrap().unwrap();
+        assert_eq!(solution.ambiguity_reason(), Some(AmbiguityReason::Overflow));
+
+        let overflows = solver.environment_overflows();
+        assert!(!overflows.is_empty());
+        assert_eq!(overflows[0].limit, 3);
+        assert!(overflows[0].environment.clauses.len() <= 3);
+        assert_eq!(overflows[0].kind, EnvironmentOverflowKind::Assumption);
+    });
+}
+
+#[test]
+fn environment_over_limit_without_new_hypotheses() {
+    // The root goal's environment is already over the limit, but
+    // assuming its hypothesis again does not grow it.
+    let program = &Arc::new(
+        parse_and_lower_program("trait Bar { }", SolverChoice::slg()).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "if (u32: Bar) { if (u32: Bar) { u32: Bar } }")
+            .unwrap()
+            .into_peeled_goal();
+
+        let solver_choice = SolverChoice::slg().with_max_environment_clauses(0);
+        let mut solver = solver_choice.into_solver(env);
+        assert_result(&solver.solve(&goal), "Unique");
+        assert!(solver.environment_overflows().is_empty());
+    });
+}
+
+#[test]
+fn elaboration_overflow() {
+    // `Foo<Vec<T>>: Bar` implies that `Foo<Vec<T>>`, `Vec<T>` and `T`
+    // are well-formed, which is more than the limit allows.
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "struct Foo<T> where T: Eq { }
+             struct Vec<T> { }
+             trait Eq { }
+             trait Bar { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "forall<T> { if (Foo<Vec<T>>: Bar) { Vec<T>: Eq } }")
+            .unwrap()
+            .into_peeled_goal();
+
+        let solver_choice = SolverChoice::slg()
+            .with_max_environment_clauses(2)
+            .with_implied_bounds(true);
+        let mut solver = solver_choice.into_solver(env);
+        assert_result(&solver.solve(&goal), "Ambiguous");
+
+        let overflows = solver.environment_overflows();
+        assert_eq!(overflows.len(), 1);
+        assert_eq!(overflows[0].kind, EnvironmentOverflowKind::Elaboration);
+        assert!(format!("{:?}", overflows[0].hypothesis).contains("FromEnv(Foo<Vec<!1>>: Bar)"));
+
+        let mut solver = SolverChoice::slg().with_implied_bounds(true).into_solver(env);
+        assert_result(&solver.solve(&goal), "Unique");
+        assert!(solver.environment_overflows().is_empty());
+    });
+}
+
+#[test]
+fn trivial_answer_cut() {
+    // `u32: Foo` can be proven both via the impl and via the custom
+    // clause; once either succeeds, the other is not needed.
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Foo { }
+             impl Foo for u32 { }
+             forall<> { u32: Foo }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "u32: Foo")
+            .unwrap()
+            .into_peeled_goal();
+
+        let mut solver = SolverChoice::slg().into_solver(env);
+        let with_cut = solver.solve(&goal);
+        assert!(solver.stats().trivial_answer_cuts > 0);
+
+        let solver_choice = SolverChoice::slg().with_trivial_answer_cut(false);
+        let mut solver = solver_choice.into_solver(env);
+        let without_cut = solver.solve(&goal);
+        assert_eq!(solver.stats().trivial_answer_cuts, 0);
+        assert_eq!(solver.stats().strands_cut, 0);
+
+        assert_result(&with_cut, "Unique");
+        assert_eq!(with_cut.unwrap(), without_cut.unwrap());
+    });
+}
+
+/// Solves `goal_text` and returns the goals of the tables that were
+/// created along the way.
+fn solve_goal_tables(
+    program_text: &str,
+    goal_text: &str,
+    solver_choice: SolverChoice,
+) -> Vec<String> {
+    let program = &Arc::new(parse_and_lower_program(program_text, solver_choice).unwrap());
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+        let options = SolveOptions::default().with_trace(true);
+        let trace = solver_choice
+            .solve_root_goal(env, &goal.into_peeled_goal(), options)
+            .unwrap()
+            .trace
+            .unwrap();
+        trace
+            .goals
+            .iter()
+            .map(|traced| format!("{:?}", traced.goal.canonical.value.goal))
+            .collect()
+    })
+}
+
+#[test]
+fn clause_order() {
+    // Each clause is tried only until one of them proves the goal
+    // unconditionally, so we can tell which clauses came first by
+    // which of `Q` and `R` were needed.
+    let program = "predicate P(T);
+                   predicate Q(T);
+                   predicate R(T);
+                   forall<T> { P(T) if Q(T) }
+                   forall<T> { P(T) if R(T) }
+                   forall<T> { Q(T) }
+                   forall<T> { R(T) }";
+    let choice = |environment_first, reverse_program_clauses| {
+        SolverChoice::slg().with_clause_order(ClauseOrder {
+            environment_first,
+            reverse_program_clauses,
+        })
+    };
+    let used = |tables: &[String], predicate: &str| {
+        tables.iter().any(|table| table.starts_with(predicate))
+    };
+
+    let tables = solve_goal_tables(program, "P(u32)", choice(true, false));
+    assert!(used(&tables, "Q("));
+    assert!(!used(&tables, "R("));
+
+    let tables = solve_goal_tables(program, "P(u32)", choice(true, true));
+    assert!(!used(&tables, "Q("));
+    assert!(used(&tables, "R("));
+
+    // The hypothesis proves `P(u32)` outright, unless we try the
+    // program clauses first.
+    let goal = "if (P(u32)) { P(u32) }";
+    let tables = solve_goal_tables(program, goal, choice(true, false));
+    assert!(!used(&tables, "Q("));
+
+    let tables = solve_goal_tables(program, goal, choice(false, false));
+    assert!(used(&tables, "Q("));
+}
+
+#[test]
+fn aggregation_policies() {
+    let choice = |aggregation| SolverChoice::slg().with_aggregation(aggregation);
+
+    test! {
+        program {
+            trait Foo { }
+            struct Vec<T> { }
+            impl Foo for Vec<u32> { }
+            impl Foo for Vec<i32> { }
+        }
+
+        goal {
+            exists<T> { T: Foo }
+        } yields[choice(AggregationPolicy::AntiUnify)] {
+            "Ambiguous; definite substitution for<?U0> { [?0 := Vec<?0>] }"
+        } yields[choice(AggregationPolicy::FirstAnswer)] {
+            "Ambiguous; suggested substitution [?0 := Vec<"
+        } yields[choice(AggregationPolicy::AllAnswers { max_answers: 2 })] {
+            "Multiple; substitution [?0 := Vec<"
+        } yields[choice(AggregationPolicy::AllAnswers { max_answers: 1 })] {
+            "Ambiguous; definite substitution for<?U0> { [?0 := Vec<?0>] }"
+        }
+
+        // A single answer is unique whatever the policy.
+        goal {
+            exists<T> { Vec<T>: Foo, T = u32 }
+        } yields[
+            choice(AggregationPolicy::FirstAnswer),
+            choice(AggregationPolicy::AllAnswers { max_answers: 2 })
+        ] {
+            "Unique; substitution [?0 := u32]"
         }
     }
 }
@@ -1610,39 +5245,67 @@ fn partial_overlap_2() {
             trait Foo {}
             trait Bar {}
 
-            struct i32 {}
-            struct u32 {}
-
             impl<T> Marker<i32> for T where T: Foo {}
             impl<T> Marker<u32> for T where T: Bar {}
         }
 
-        goal {
-            forall<T> {
-                if (T: Foo; T: Bar) {
-                    exists<A> { T: Marker<A> }
-                }
-            }
-        } yields {
-            "Ambiguous"
+        goal {
+            forall<T> {
+                if (T: Foo; T: Bar) {
+                    exists<A> { T: Marker<A> }
+                }
+            }
+        } yields {
+            "Ambiguous"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Foo; T: Bar) {
+                    T: Marker<u32>
+                }
+            }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Foo; T: Bar) {
+                    T: Marker<i32>
+                }
+            }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn partial_overlap_3() {
+    test! {
+        program {
+            #[marker] trait Marker {}
+            trait Foo {}
+            trait Bar {}
+
+            impl<T> Marker for T where T: Foo {}
+            impl<T> Marker for T where T: Bar {}
+
+            impl Foo for i32 {}
+            impl Bar for i32 {}
         }
 
         goal {
             forall<T> {
-                if (T: Foo; T: Bar) {
-                    T: Marker<u32>
-                }
+                if (T: Foo; T: Bar) { T: Marker }
             }
         } yields {
             "Unique"
         }
 
         goal {
-            forall<T> {
-                if (T: Foo; T: Bar) {
-                    T: Marker<i32>
-                }
-            }
+            i32: Marker
         } yields {
             "Unique"
         }
@@ -1650,7 +5313,7 @@ fn partial_overlap_2() {
 }
 
 #[test]
-fn partial_overlap_3() {
+fn proof_provenance() {
     test! {
         program {
             #[marker] trait Marker {}
@@ -1660,23 +5323,53 @@ fn partial_overlap_3() {
             impl<T> Marker for T where T: Foo {}
             impl<T> Marker for T where T: Bar {}
 
-            struct i32 {}
             impl Foo for i32 {}
             impl Bar for i32 {}
+            impl Bar for u32 {}
+        }
+
+        // Both derivations give the same (empty) substitution.
+        goal {
+            i32: Marker
+        } yields {
+            "Unique"
+        } yields_using {
+            impl 0, impl 1
+        }
+
+        goal {
+            u32: Marker
+        } yields {
+            "Unique"
+        } yields_using {
+            impl 1
+        }
+
+        goal {
+            f32: Marker
+        } yields {
+            "No possible solution"
+        } yields_using {
         }
 
         goal {
             forall<T> {
-                if (T: Foo; T: Bar) { T: Marker }
+                if (T: Foo) { T: Marker }
             }
         } yields {
             "Unique"
+        } yields_using {
+            impl 0
         }
 
+        // Proven from the hypothesis alone.
         goal {
-            i32: Marker
+            forall<T> {
+                if (T: Marker) { T: Marker }
+            }
         } yields {
             "Unique"
+        } yields_using {
         }
     }
 }
@@ -1717,7 +5410,6 @@ fn unselected_projection() {
             }
 
             struct Chars { }
-            struct char { }
             struct char2 { }
 
             impl Iterator for Chars {
@@ -1765,6 +5457,196 @@ fn unselected_projection() {
     }
 }
 
+#[test]
+fn goals_in_registered_scopes() {
+    let mut program = parse_and_lower_program(
+        "trait Iterator { type Item; }
+         trait Iterator2 { type Item; }
+         struct Chars { }
+         struct char2 { }
+         impl Iterator for Chars { type Item = char; }
+         impl Iterator2 for Chars { type Item = char2; }",
+        SolverChoice::slg(),
+    ).unwrap();
+    program.register_scope("iter", &["Iterator"], &[]).unwrap();
+    program.register_scope("iter2", &["Iterator2"], &["both"]).unwrap();
+    program.register_scope("prelude", &[], &["iter"]).unwrap();
+    program.register_scope("both", &[], &["iter", "iter2"]).unwrap();
+    assert!(program.register_scope("bad", &["Chars"], &[]).is_err());
+    let program = &Arc::new(program);
+    let env = &program.environment();
+
+    ir::tls::set_current_program(&program, || {
+        let solve_in = |scope: &str| {
+            let goal = parse_and_lower_goal(&program, "exists<T> { Chars::Item = T }").unwrap();
+            let goal = program.goal_in_scope(scope, goal).unwrap();
+            let options = SolveOptions::default().with_hypotheses(true);
+            let outcome = SolverChoice::slg()
+                .solve_root_goal(env, &goal.into_peeled_goal(), options)
+                .unwrap();
+            (outcome.solution, outcome.hypotheses.unwrap())
+        };
+
+        // Imported through a glob, `Iterator` is the only candidate.
+        let (solution, usage) = solve_in("prelude");
+        assert_eq!(
+            format!("{}", solution.unwrap()),
+            "Unique; substitution [?0 := char], lifetime constraints []"
+        );
+        assert_eq!(format!("{:?}", usage.selected_traits()), "[Iterator]");
+
+        // Globs may import each other in a cycle.
+        assert_eq!(
+            format!("{:?}", program.traits_in_scope("iter2").unwrap()),
+            "[Iterator, Iterator2]"
+        );
+        let (solution, _) = solve_in("both");
+        assert_eq!(
+            format!("{}", solution.unwrap()),
+            "Ambiguous; no inference guidance; unresolved associated type"
+        );
+
+        assert!(program.traits_in_scope("missing").is_err());
+    });
+}
+
+#[test]
+fn goal_rewrites() {
+    let program = &Arc::new(
+        parse_and_lower_program(
+            "trait Copy { }
+             trait Clone { }
+             struct Foo { }
+             struct Vec<T> { }
+             impl Clone for Foo { }
+             impl<T> Clone for Vec<T> where T: Clone { }",
+            SolverChoice::slg(),
+        ).unwrap(),
+    );
+    let env = &program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| *parse_and_lower_goal(&program, text).unwrap();
+        let rewrite = |pattern: &str, replacement: &str| GoalRewrite::new(goal(pattern), goal(replacement));
+
+        let mut solver = SolverChoice::slg().into_solver(env);
+        assert_result(&solver.solve(&goal("Vec<Foo>: Copy").into_peeled_goal()), "No possible solution");
+
+        // Prototype `Copy` as a mere alias for `Clone`.
+        let mut solver = SolverChoice::slg().into_solver(env);
+        solver.add_rewrite(rewrite("forall<T> { T: Copy }", "forall<T> { T: Clone }").unwrap());
+        let mut solve = |text: &str| solver.solve(&goal(text).into_closed_goal());
+        assert_result(&solve("Vec<Foo>: Copy"), "Unique");
+
+        // Goals beneath binders are rewritten too, with the pattern
+        // variable standing for a type that refers to those binders.
+        assert_result(&solve("forall<U> { if (U: Clone) { Vec<U>: Copy } }"), "Unique");
+        assert_result(&solve("forall<U> { Vec<U>: Copy }"), "No possible solution");
+
+        // Pattern variables must appear in the pattern, and be those of
+        // the replacement.
+        assert!(rewrite("forall<T> { Foo: Copy }", "forall<T> { Foo: Clone }").is_err());
+        assert!(rewrite("forall<T> { T: Copy }", "forall<'a> { Foo: Clone }").is_err());
+        assert!(rewrite("forall<T> { T: Copy }", "Foo: Clone").is_err());
+    });
+}
+
+#[test]
+fn trait_overrides() {
+    let program_text = "trait Unpin { }
+                        trait Cyclic { }
+                        trait Nat { }
+                        struct Foo { }
+                        struct Zero { }
+                        struct Succ<T> { }
+                        impl<T> Cyclic for T where T: Cyclic { }
+                        impl Nat for Zero { }
+                        impl<T> Nat for Succ<T> where T: Nat { }";
+    let solve = |overrides: &[(&str, ir::TraitOverrides)], goal_text: &str| {
+        let mut program = parse_and_lower_program(program_text, SolverChoice::slg()).unwrap();
+        for (trait_name, trait_overrides) in overrides {
+            program.override_trait(trait_name, trait_overrides.clone()).unwrap();
+        }
+        let program = Arc::new(program);
+        let env = program.environment();
+        ir::tls::set_current_program(&program, || {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            SolverChoice::slg()
+                .solve_root_goal(&env, &goal.into_peeled_goal(), SolveOptions::default())
+                .map(|outcome| outcome.solution)
+        })
+    };
+
+    assert_result(&solve(&[], "Foo: Unpin"), "No possible solution");
+    let always_implemented = ir::TraitOverrides {
+        always_implemented: true,
+        ..Default::default()
+    };
+    assert_result(&solve(&[("Unpin", always_implemented.clone())], "Foo: Unpin"), "Unique");
+
+    assert_result(&solve(&[], "Foo: Cyclic"), "No possible solution");
+    let coinductive = ir::TraitOverrides {
+        coinductive: true,
+        ..Default::default()
+    };
+    assert_result(&solve(&[("Cyclic", coinductive)], "Foo: Cyclic"), "Unique");
+
+    // Capping the answers makes the goal ambiguous, rather than false,
+    // if the answer it needs is not enumerated.
+    let max_answers = |max| ir::TraitOverrides {
+        max_answers: Some(max),
+        ..Default::default()
+    };
+    let goal = "Succ<Succ<Zero>>: Nat";
+    assert_result(&solve(&[("Nat", max_answers(1))], goal), "Unique");
+    assert_result(&solve(&[("Nat", max_answers(0))], goal), "Ambiguous");
+
+    let mut program = parse_and_lower_program(program_text, SolverChoice::slg()).unwrap();
+    assert!(program.override_trait("Foo", always_implemented).is_err());
+}
+
+#[test]
+fn inherent_assoc_ty() {
+    test! {
+        program {
+            trait Clone { }
+            struct Ref<'a, T> { }
+            struct Foo<T> { }
+            impl Clone for u32 { }
+
+            impl<T> Foo<T> where T: Clone {
+                type Bar<'a> = Ref<'a, T>;
+                type Baz = u32;
+            }
+        }
+
+        // Unlike trait associated types, no `InScope` is needed.
+        goal {
+            exists<U> {
+                Foo<u32>::Baz = U
+            }
+        } yields {
+            "Unique; substitution [?0 := u32], lifetime constraints []"
+        }
+
+        goal {
+            forall<'a> {
+                Foo<u32>::Bar<'a> = Ref<'a, u32>
+            }
+        } yields {
+            "Unique"
+        }
+
+        // The where clauses of the impl must hold.
+        goal {
+            exists<U> {
+                Foo<i32>::Baz = U
+            }
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
 #[test]
 fn unselected_projection_with_atc() {
     test! {
@@ -1774,7 +5656,6 @@ fn unselected_projection_with_atc() {
             }
 
             struct Ref<'a, T> { }
-            struct i32 { }
 
             impl Foo for i32 {
                 type Item<'a> = Ref<'a, i32>;
@@ -1800,8 +5681,6 @@ fn unselected_projection_with_parametric_trait() {
                 type Item;
             }
 
-            struct i32 { }
-
             impl Foo<i32> for i32 {
                 type Item = i32;
             }
@@ -1847,10 +5726,10 @@ fn overflow_universe() {
 fn projection_from_env() {
     test! {
         program {
+            #[lang_sized]
             trait Sized { }
 
             struct Slice<T> where T: Sized { }
-            impl<T> Sized for Slice<T> { }
 
             trait SliceExt
             {
@@ -1878,7 +5757,9 @@ fn projection_from_env() {
 }
 
 // This variant of the above test used to be achingly slow on SLG
-// solvers, before the "trivial answer" green cut was introduced.
+// solvers, before the "trivial answer" green cut was introduced (see
+// `ContextOps::trivial_answer_cut`, and the `trivial_answer_cut` test
+// for the effect of disabling it).
 //
 // The problem was that we wound up enumerating a goal like
 //
@@ -1894,14 +5775,12 @@ fn projection_from_env_slow() {
     test! {
         program {
             trait Clone { }
+            #[lang_sized]
             trait Sized { }
 
             struct Slice<T> where T: Sized { }
-            impl<T> Sized for Slice<T> { }
 
-            struct u32 { }
             impl Clone for u32 { }
-            impl Sized for u32 { }
 
             trait SliceExt
                 where <Self as SliceExt>::Item: Clone
@@ -1938,7 +5817,6 @@ fn clauses_in_if_goals() {
         program { 
             trait Foo { }
             struct Vec<T> { }
-            struct i32 { }
         }
 
         goal {
@@ -1986,22 +5864,22 @@ fn quantified_types() {
     test! {
         program {
             trait Foo { }
-            struct fn<'a> { }
-            impl Foo for for<'a> fn<'a> { }
+            struct Fun<'a> { }
+            impl Foo for for<'a> Fun<'a> { }
         }
 
         goal {
-            for<'a> fn<'a>: Foo
+            for<'a> Fun<'a>: Foo
         } yields {
             "Unique"
         }
 
         goal {
-            forall<'a> { fn<'a>: Foo }
+            forall<'a> { Fun<'a>: Foo }
         } yields {
             // Lifetime constraints are unsatisfiable
             "Unique; substitution [], \
-            lifetime constraints [InEnvironment { environment: Env([]), goal: '!2 == '!1 }]"
+            lifetime constraints ['!2 == '!1]"
         }
     }
 }
@@ -2047,6 +5925,103 @@ fn higher_ranked_implied_bounds() {
     }
 }
 
+#[test]
+fn user_defined_predicates() {
+    test! {
+        program {
+            predicate Edge(T, U);
+            predicate Reaches(T, U);
+
+            struct A { }
+            struct B { }
+            struct C { }
+            struct D { }
+
+            forall<> { Edge(A, B) }
+            forall<> { Edge(B, C) }
+
+            forall<T> { Reaches(T, T) }
+            forall<T, U, V> { Reaches(T, V) if Edge(T, U), Reaches(U, V) }
+        }
+
+        goal {
+            Reaches(A, C)
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            Reaches(C, A)
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<T> { Reaches(A, T) }
+        } yields {
+            "Ambiguous"
+        }
+
+        goal {
+            Reaches(A, D)
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            if (Edge(C, D)) {
+                Reaches(A, D)
+            }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn builtin_sized() {
+    test! {
+        program {
+            #[lang_sized]
+            trait Sized { }
+            struct Unit { }
+            struct Wrapper<T> { value: T }
+            struct Pair<T, U> { first: T, second: U }
+        }
+
+        goal {
+            Unit: Sized
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            Wrapper<Pair<u32, Wrapper<u32>>>: Sized
+        } yields {
+            "Unique"
+        }
+
+        // Only the last field matters.
+        goal {
+            forall<T> { Pair<T, u32>: Sized }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> { Wrapper<T>: Sized }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> { if (T: Sized) { Wrapper<Wrapper<T>>: Sized } }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
 #[test]
 fn deref_goal() {
     test! {
@@ -2077,8 +6052,6 @@ fn deref_goal() {
             #[lang_deref]
             trait Deref { type Target; }
             struct Arc<T> { }
-            struct i32 { }
-            struct u64 { }
             impl<T> Deref for Arc<T> { type Target = T; }
         }
 
@@ -2095,3 +6068,126 @@ fn deref_goal() {
         }
     }
 }
+
+#[test]
+fn const_generics() {
+    test! {
+        program {
+            struct Foo<const N: usize> { }
+            trait Baz<const N> { }
+            impl<const N> Baz<N> for Foo<N> { }
+        }
+
+        goal {
+            forall<const N> { Foo<N>: Baz<N> }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<const N> { exists<const M> { Foo<N>: Baz<M> } }
+        } yields {
+            "Unique; substitution [?0 := !1], lifetime constraints []"
+        }
+
+        goal {
+            forall<const N, const M> { Foo<N>: Baz<M> }
+        } yields {
+            "No possible solution"
+        }
+
+        // `M` cannot name the placeholder for `N`.
+        goal {
+            exists<const M> { forall<const N> { Foo<N>: Baz<M> } }
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn const_values() {
+    test! {
+        program {
+            struct Foo<const N: usize> { }
+            trait Baz<const N> { }
+            trait Small { }
+            impl<const N> Baz<N> for Foo<N> { }
+            impl Small for Foo<1> { }
+            impl Small for Foo<2> { }
+        }
+
+        goal {
+            Foo<3>: Baz<3>
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            Foo<3>: Baz<4>
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<const M> { Foo<3>: Baz<M> }
+        } yields {
+            "Unique; substitution [?0 := 3]"
+        }
+
+        goal {
+            exists<const N> { Foo<N>: Small }
+        } yields {
+            "Ambiguous"
+        }
+
+        goal {
+            forall<const N> { Foo<N>: Small }
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn assoc_consts() {
+    test! {
+        program {
+            struct Foo<const N: usize> { }
+            struct Bar { }
+            trait Len { const LEN: usize; }
+            impl Len for Bar { const LEN: usize = 3; }
+            impl<const N> Len for Foo<N> { const LEN: usize = N; }
+        }
+
+        goal {
+            NormalizeConst(<Bar as Len>::LEN -> 3)
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            NormalizeConst(<Bar as Len>::LEN -> 4)
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<const N> { NormalizeConst(<Bar as Len>::LEN -> N) }
+        } yields {
+            "Unique; substitution [?0 := 3]"
+        }
+
+        goal {
+            forall<const N> { NormalizeConst(<Foo<N> as Len>::LEN -> N) }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            exists<const N> { NormalizeConst(<Foo<5> as Len>::LEN -> N) }
+        } yields {
+            "Unique; substitution [?0 := 5]"
+        }
+    }
+}
@@ -3,8 +3,9 @@
 extern crate test;
 use self::test::Bencher;
 
+use generator::GeneratorConfig;
 use ir;
-use solve::SolverChoice;
+use solve::{SolveOptions, SolverChoice};
 use std::sync::Arc;
 
 use super::{parse_and_lower_program,
@@ -19,19 +20,23 @@ fn run_bench(
     expected: &str
 ) {
     let program = Arc::new(parse_and_lower_program(program_text, solver_choice).unwrap());
-    let env = Arc::new(program.environment());
+    let env = program.environment();
     ir::tls::set_current_program(&program, || {
         let goal = parse_and_lower_goal(&program, goal_text).unwrap();
         let peeled_goal = goal.into_peeled_goal();
 
         // Execute once to get an expected result.
-        let result = solver_choice.solve_root_goal(&env, &peeled_goal);
+        let result = solver_choice
+            .solve_root_goal(&env, &peeled_goal, SolveOptions::default())
+            .map(|outcome| outcome.solution);
 
         // Check expectation.
         assert_result(&result, expected);
 
         // Then do it many times to measure time.
-        bencher.iter(|| solver_choice.solve_root_goal(&env, &peeled_goal));
+        bencher.iter(|| {
+            solver_choice.solve_root_goal(&env, &peeled_goal, SolveOptions::default())
+        });
     });
 }
 
@@ -41,12 +46,10 @@ trait Clone { }
 trait Copy where Self: Clone { }
 trait Sized { }
 
-struct i32 { }
 impl Copy for i32 { }
 impl Clone for i32 { }
 impl Sized for i32 { }
 
-struct u32 { }
 impl Copy for u32 { }
 impl Clone for u32 { }
 impl Sized for u32 { }
@@ -101,11 +104,30 @@ forall<T> {
 fn cycley_slg(b: &mut Bencher) {
     run_bench(
         CYCLEY,
-        SolverChoice::SLG {
-            max_size: 20,
-        },
+        SolverChoice::slg().with_max_size(20),
         CYCLEY_GOAL,
         b,
         "Unique"
     );
 }
+
+#[bench]
+fn generated_program_slg(b: &mut Bencher) {
+    let config = GeneratorConfig {
+        num_traits: 40,
+        num_structs: 20,
+        impls_per_trait: 10,
+        where_clause_depth: 7,
+        cycles: true,
+        seed: 0,
+    };
+    let program = Arc::new(config.generate(SolverChoice::slg()).unwrap());
+    let env = program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Trait0 }").unwrap();
+        let peeled_goal = goal.into_peeled_goal();
+        b.iter(|| {
+            SolverChoice::slg().solve_root_goal(&env, &peeled_goal, SolveOptions::default())
+        });
+    });
+}
@@ -0,0 +1,193 @@
+//! A model of a slice of the standard library -- iterators, the
+//! `From`/`Into` blanket impls, `Deref`, `Borrow` and `ToOwned` -- with
+//! goals over it. The goals are a regression corpus for programs of a
+//! realistic shape, and the benchmarks time solving all of them, with
+//! and without sharing the work between goals.
+//!
+//! Only built with the `shared-prelude` feature.
+
+extern crate test;
+use self::test::Bencher;
+
+use ir;
+use solve::{SolveOptions, SolverChoice};
+use std::sync::Arc;
+
+use super::{parse_and_lower_goal, parse_and_lower_program, solve_goal};
+
+const STD_PRELUDE: &str = "
+#[auto] trait Send { }
+#[lang_deref] trait Deref { type Target; }
+trait Clone { }
+trait Copy where Self: Clone { }
+trait Iterator { type Item; }
+trait IntoIterator { type Item; type IntoIter; }
+trait From<T> { }
+trait Into<T> { }
+trait Borrow<T> { }
+trait ToOwned { type Owned; }
+
+struct str { }
+struct String { }
+struct Box<T> { }
+struct Rc<T> { }
+struct Vec<T> { }
+struct Slice<T> { }
+struct Option<T> { }
+struct IntoIter<T> { }
+struct Iter<T> { }
+struct Chars { }
+struct Peekable<I> { }
+
+impl Clone for u8 { }
+impl Copy for u8 { }
+impl Clone for u32 { }
+impl Copy for u32 { }
+impl Clone for i32 { }
+impl Copy for i32 { }
+impl Clone for char { }
+impl Copy for char { }
+impl Clone for String { }
+impl<T> Clone for Box<T> where T: Clone { }
+impl<T> Clone for Rc<T> { }
+impl<T> Clone for Vec<T> where T: Clone { }
+impl<T> Clone for Option<T> where T: Clone { }
+impl<T> !Send for Rc<T> { }
+
+impl<T> Deref for Box<T> { type Target = T; }
+impl<T> Deref for Rc<T> { type Target = T; }
+impl<T> Deref for Vec<T> { type Target = Slice<T>; }
+impl Deref for String { type Target = str; }
+
+impl<T> Iterator for IntoIter<T> { type Item = T; }
+impl<T> Iterator for Iter<T> { type Item = T; }
+impl Iterator for Chars { type Item = char; }
+impl<I> Iterator for Peekable<I> where I: Iterator { type Item = <I as Iterator>::Item; }
+impl<I> Iterator for Box<I> where I: Iterator { type Item = <I as Iterator>::Item; }
+
+impl<I> IntoIterator for I where I: Iterator {
+    type Item = <I as Iterator>::Item;
+    type IntoIter = I;
+}
+impl<T> IntoIterator for Vec<T> { type Item = T; type IntoIter = IntoIter<T>; }
+impl<T> IntoIterator for Slice<T> { type Item = T; type IntoIter = Iter<T>; }
+
+impl<T> From<T> for T { }
+impl<T, U> Into<U> for T where U: From<T> { }
+impl From<u8> for u32 { }
+impl From<u8> for i32 { }
+impl From<char> for u32 { }
+impl From<str> for String { }
+impl From<String> for Vec<u8> { }
+impl<T> From<T> for Box<T> { }
+impl<T> From<T> for Option<T> { }
+impl<T> From<Vec<T>> for Box<Slice<T>> { }
+
+impl<T> Borrow<T> for T { }
+impl<T> Borrow<T> for Box<T> { }
+impl<T> Borrow<Slice<T>> for Vec<T> { }
+impl Borrow<str> for String { }
+
+impl<T> ToOwned for T where T: Clone { type Owned = T; }
+impl<T> ToOwned for Slice<T> where T: Clone { type Owned = Vec<T>; }
+impl ToOwned for str { type Owned = String; }
+";
+
+/// Each goal, with the start of its expected solution.
+const GOALS: &[(&str, &str)] = &[
+    // Clone and Copy
+    ("Vec<Box<u32>>: Clone", "Unique"),
+    ("Option<Vec<Rc<String>>>: Clone", "Unique"),
+    ("u32: Copy", "Unique"),
+    ("Vec<String>: Copy", "No possible solution"),
+
+    // Auto traits
+    ("Box<String>: Send", "Unique"),
+    ("Rc<u32>: Send", "No possible solution"),
+
+    // Iterators
+    ("exists<U> { Normalize(<Vec<u32> as IntoIterator>::Item -> U) }",
+     "Unique; substitution [?0 := u32]"),
+    ("Vec<u32>: IntoIterator<Item = u32>", "Unique"),
+    ("Vec<u32>: IntoIterator<Item = char>", "No possible solution"),
+    ("Vec<u32>: Iterator", "No possible solution"),
+    ("IntoIter<u32>: IntoIterator", "Unique"),
+    ("Peekable<Box<Chars>>: Iterator<Item = char>", "Unique"),
+    ("Peekable<IntoIter<u32>>: IntoIterator<Item = u32>", "Unique"),
+    ("forall<I> { if (I: Iterator) { Peekable<I>: IntoIterator } }", "Unique"),
+
+    // From and Into
+    ("u8: Into<u32>", "Unique"),
+    ("u32: Into<u32>", "Unique"),
+    ("u32: Into<u8>", "No possible solution"),
+    ("exists<T> { u8: Into<T> }", "Ambiguous"),
+    ("forall<T> { T: Into<Option<T>> }", "Unique"),
+    ("Vec<u32>: Into<Box<Slice<u32>>>", "Unique"),
+
+    // Deref
+    ("Derefs(Box<String>, String)", "Unique"),
+    ("Derefs(Rc<Vec<u32>>, Vec<u32>)", "Unique"),
+    ("Derefs(Box<String>, str)", "No possible solution"),
+    ("exists<U> { Derefs(Box<String>, U), Derefs(U, str) }",
+     "Unique; substitution [?0 := String]"),
+
+    // Borrow and ToOwned
+    ("String: Borrow<str>", "Unique"),
+    ("Vec<u32>: Borrow<Slice<u32>>", "Unique"),
+    ("forall<T> { Box<T>: Borrow<T> }", "Unique"),
+    ("String: Borrow<Slice<u8>>", "No possible solution"),
+    ("exists<U> { Normalize(<str as ToOwned>::Owned -> U) }",
+     "Unique; substitution [?0 := String]"),
+    ("exists<U> { Normalize(<Slice<u32> as ToOwned>::Owned -> U) }",
+     "Unique; substitution [?0 := Vec<u32>]"),
+    ("exists<U> { Normalize(<u32 as ToOwned>::Owned -> U) }",
+     "Unique; substitution [?0 := u32]"),
+];
+
+#[test]
+fn std_prelude_goals() {
+    let goal_texts: Vec<String> = GOALS.iter().map(|&(goal, _)| format!("{{{}}}", goal)).collect();
+    let goals = goal_texts
+        .iter()
+        .zip(GOALS)
+        .map(|(goal, &(_, expected))| (&goal[..], SolverChoice::slg(), expected, None))
+        .collect();
+    solve_goal(&format!("{{{}}}", STD_PRELUDE), goals);
+}
+
+/// Solves every goal against `STD_PRELUDE`, each with a fresh solver
+/// or, if `shared`, with one solver for all of them.
+fn bench_goals(b: &mut Bencher, shared: bool) {
+    let program = Arc::new(parse_and_lower_program(STD_PRELUDE, SolverChoice::slg()).unwrap());
+    let env = program.environment();
+    ir::tls::set_current_program(&program, || {
+        let goals: Vec<_> = GOALS
+            .iter()
+            .map(|&(goal, _)| parse_and_lower_goal(&program, goal).unwrap().into_peeled_goal())
+            .collect();
+        b.iter(|| {
+            if shared {
+                let mut solver = SolverChoice::slg().into_solver(&env);
+                for goal in &goals {
+                    solver.solve(goal).unwrap();
+                }
+            } else {
+                for goal in &goals {
+                    SolverChoice::slg()
+                        .solve_root_goal(&env, goal, SolveOptions::default())
+                        .unwrap();
+                }
+            }
+        });
+    });
+}
+
+#[bench]
+fn std_prelude_fresh_solvers(b: &mut Bencher) {
+    bench_goals(b, false);
+}
+
+#[bench]
+fn std_prelude_shared_solver(b: &mut Bencher) {
+    bench_goals(b, true);
+}
@@ -0,0 +1,217 @@
+//! Recording what the solver proved while solving a goal, and
+//! comparing two such recordings. This is meant for debugging: when
+//! two runs disagree -- say, before and after an edit to the program
+//! -- the diff points at the subgoals where they first diverged.
+
+use ir::*;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A single answer found for a subgoal.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TracedAnswer {
+    pub subst: Canonical<ConstrainedSubst>,
+
+    /// True if the answer depends on delayed literals, and hence is
+    /// not known to be true.
+    pub ambiguous: bool,
+}
+
+/// A subgoal the solver considered, along with every answer it found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TracedGoal {
+    pub goal: UCanonical<InEnvironment<Goal>>,
+
+    /// The answers, sorted; the order in which the solver found them
+    /// is not considered significant.
+    pub answers: Vec<TracedAnswer>,
+}
+
+/// The estimated cost of working on a subgoal next; see
+/// `SubgoalOrder::CheapestFirst`. Costs are compared field by field,
+/// in order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubgoalCost {
+    /// True for a negative subgoal that still has unresolved inference
+    /// variables, which would flounder if selected now.
+    pub flounders: bool,
+
+    /// The number of clauses that might prove the subgoal. A subgoal
+    /// no clause matches fails at once, which makes it the cheapest.
+    pub candidates: usize,
+
+    /// The number of distinct unresolved inference variables in the
+    /// subgoal.
+    pub unbound_variables: usize,
+}
+
+/// The subgoal chosen among the pending subgoals of a clause, with
+/// the costs that led to the choice.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubgoalPlan {
+    /// The pending subgoals, with inference variables resolved as far
+    /// as they were at the time; negative subgoals are wrapped in
+    /// `Goal::Not`.
+    pub subgoals: Vec<(Goal, SubgoalCost)>,
+
+    /// The index of the selected subgoal in `subgoals`.
+    pub selected: usize,
+}
+
+/// The record of one solver run; see `SolveOutcome::trace`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofTrace {
+    /// Subgoals in the order the solver first considered them.
+    pub goals: Vec<TracedGoal>,
+
+    /// With `SubgoalOrder::CheapestFirst`, every choice between
+    /// several pending subgoals, in the order they were made.
+    /// Comparing traces with `diff` ignores these.
+    pub plans: Vec<SubgoalPlan>,
+}
+
+/// The differences between two `ProofTrace`s; see `ProofTrace::diff`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceDiff {
+    /// Subgoals considered only by the left-hand run.
+    pub only_in_left: Vec<TracedGoal>,
+
+    /// Subgoals considered only by the right-hand run.
+    pub only_in_right: Vec<TracedGoal>,
+
+    /// Subgoals considered by both runs, but with different answers.
+    /// Each entry holds the left and right version, in that order.
+    pub diverged: Vec<(TracedGoal, TracedGoal)>,
+}
+
+impl ProofTrace {
+    crate fn new(mut goals: Vec<TracedGoal>) -> Self {
+        for goal in &mut goals {
+            goal.answers.sort();
+        }
+        ProofTrace {
+            goals,
+            plans: vec![],
+        }
+    }
+
+    crate fn with_plans(self, plans: Vec<SubgoalPlan>) -> Self {
+        ProofTrace { plans, ..self }
+    }
+
+    /// Compares `self` (the "left" run) against `other` (the "right"
+    /// run). Subgoals are matched up by equality, so the two runs
+    /// should be against the same program, or at least programs whose
+    /// shared items were declared in the same order.
+    ///
+    /// Entries in the result are ordered by where they first appear in
+    /// the left trace, followed by those only in the right trace.
+    pub fn diff(&self, other: &ProofTrace) -> TraceDiff {
+        let right: BTreeMap<_, _> = other
+            .goals
+            .iter()
+            .map(|traced| (&traced.goal, traced))
+            .collect();
+
+        let mut diff = TraceDiff::default();
+        for left in &self.goals {
+            match right.get(&left.goal) {
+                None => diff.only_in_left.push(left.clone()),
+                Some(right) => if left.answers != right.answers {
+                    diff.diverged.push((left.clone(), (*right).clone()));
+                },
+            }
+        }
+
+        let left: BTreeMap<_, _> = self
+            .goals
+            .iter()
+            .map(|traced| (&traced.goal, traced))
+            .collect();
+        diff.only_in_right.extend(
+            other
+                .goals
+                .iter()
+                .filter(|traced| !left.contains_key(&traced.goal))
+                .cloned(),
+        );
+
+        diff
+    }
+}
+
+impl TraceDiff {
+    /// True if the two traces were equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_left.is_empty() && self.only_in_right.is_empty() && self.diverged.is_empty()
+    }
+}
+
+impl fmt::Display for TracedAnswer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.subst)?;
+        if self.ambiguous {
+            write!(f, " (ambiguous)")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TracedGoal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        writeln!(f, "{:?}", self.goal.canonical.value.goal)?;
+        if self.answers.is_empty() {
+            writeln!(f, "    no answers")?;
+        }
+        for answer in &self.answers {
+            writeln!(f, "    {}", answer)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SubgoalCost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "{} candidates, {} unbound",
+            self.candidates, self.unbound_variables
+        )?;
+        if self.flounders {
+            write!(f, ", flounders")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SubgoalPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (index, (goal, cost)) in self.subgoals.iter().enumerate() {
+            let marker = if index == self.selected { "*" } else { " " };
+            writeln!(f, "{} {:?} ({})", marker, goal, cost)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TraceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for traced in &self.only_in_left {
+            write!(f, "- {}", traced)?;
+        }
+        for traced in &self.only_in_right {
+            write!(f, "+ {}", traced)?;
+        }
+        for (left, right) in &self.diverged {
+            write!(f, "~ {}", left)?;
+            writeln!(f, "  became")?;
+            for answer in &right.answers {
+                writeln!(f, "    {}", answer)?;
+            }
+            if right.answers.is_empty() {
+                writeln!(f, "    no answers")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -3,10 +3,13 @@
 use diff;
 use chalk_parse;
 use itertools::Itertools;
+use lalrpop_intern::intern;
 use std::fmt::Write;
-use ir::lowering::{LowerProgram, LowerGoal};
-use ir::{Goal, Program};
+use ir::lowering::{LowerProgram, LowerGoal, LowerGoalWithHoles, PeeledBinderNames};
+use ir::{self, Goal, Identifier, InEnvironment, ItemId, ParameterKind, PlaceholderNames, Program,
+         UCanonical};
 use solve::SolverChoice;
+use std::sync::Arc;
 use errors::Result;
 
 pub fn parse_and_lower_program(text: &str, solver_choice: SolverChoice) -> Result<Program> {
@@ -17,6 +20,54 @@ pub fn parse_and_lower_goal(program: &Program, text: &str) -> Result<Box<Goal>>
     chalk_parse::parse_goal(text)?.lower(program)
 }
 
+/// Like `parse_and_lower_goal`, but also returns the names of the
+/// binders that peeling the goal will turn into placeholders.
+pub fn parse_and_lower_goal_with_names(
+    program: &Program,
+    text: &str,
+) -> Result<(Box<Goal>, Vec<ParameterKind<Identifier>>)> {
+    let goal = chalk_parse::parse_goal(text)?;
+    let binder_names = goal.peeled_binder_names();
+    Ok((goal.lower(program)?, binder_names))
+}
+
+/// Parses a goal that may contain type holes (`_`) and peels it, the
+/// way a REPL would.
+pub fn parse_and_peel_goal_with_holes(
+    program: &Program,
+    text: &str,
+) -> Result<(UCanonical<InEnvironment<Goal>>, PlaceholderNames)> {
+    let goal = chalk_parse::parse_goal(text)?;
+    let binder_names = goal.peeled_binder_names();
+    let (goal, num_holes) = goal.lower_with_holes(program)?;
+    Ok(goal.into_peeled_goal_with_holes(binder_names, num_holes))
+}
+
+/// The id of the one impl of `program` whose trait reference is
+/// formatted as `trait_ref`, like `i32 as Clone` or `Vec<?0> as Foo`
+/// (the parameters of the impl being `?0`, `?1`, ...). Tests use this
+/// rather than hard-coding ids, which depend on the order of items.
+pub fn impl_id(program: &Program, trait_ref: &str) -> ItemId {
+    let ids: Vec<_> = ir::tls::set_current_program(&Arc::new(program.clone()), || {
+        program
+            .impl_data
+            .iter()
+            .filter(|(_, impl_datum)| {
+                format!("{:?}", impl_datum.binders.value.trait_ref.trait_ref()) == trait_ref
+            })
+            .map(|(&id, _)| id)
+            .collect()
+    });
+    assert_eq!(ids.len(), 1, "expected one impl of `{}`, found {:?}", trait_ref, ids);
+    ids[0]
+}
+
+/// The id of the trait, struct or other type named `name` in
+/// `program`.
+pub fn type_id(program: &Program, name: &str) -> ItemId {
+    program.type_ids[&intern(name)]
+}
+
 macro_rules! lowering_success {
     (program $program:tt) => {
         let program_text = stringify!($program);
@@ -5,7 +5,7 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 /// When we zip types, we basically traverse the structure, ensuring
-/// that it matches.  When we come to types/lifetimes, we invoke the
+/// that it matches.  When we come to types/lifetimes/consts, we invoke the
 /// callback methods in the zipper to match them up. Primarily used
 /// during unification or similar operations.
 ///
@@ -13,7 +13,7 @@ use std::sync::Arc;
 /// would get two callbacks, one pairing `A` and `X`, and the other pairing
 /// `B` and `Y`.
 ///
-/// For things other than types/lifetimes, the zip impls will
+/// For things other than types/lifetimes/consts, the zip impls will
 /// guarantee equality. So e.g. if you have `A: Eq<B>` zipped with `X:
 /// Ord<Y>`, you would wind up with an error, no matter what zipper
 /// you are using. This is because the traits `Eq` and `Ord` are
@@ -29,6 +29,10 @@ crate trait Zipper {
     /// matching spots, beneath `binders` levels of binders.
     fn zip_lifetimes(&mut self, a: &Lifetime, b: &Lifetime) -> Fallible<()>;
 
+    /// Indicates that the two consts `a` and `b` were found in
+    /// matching spots, beneath `binders` levels of binders.
+    fn zip_consts(&mut self, a: &Const, b: &Const) -> Fallible<()>;
+
     /// Zips two values appearing beneath binders.
     fn zip_binders<T>(&mut self, a: &Binders<T>, b: &Binders<T>) -> Fallible<()>
     where
@@ -44,6 +48,10 @@ impl<'f, Z: Zipper> Zipper for &'f mut Z {
         (**self).zip_lifetimes(a, b)
     }
 
+    fn zip_consts(&mut self, a: &Const, b: &Const) -> Fallible<()> {
+        (**self).zip_consts(a, b)
+    }
+
     fn zip_binders<T>(&mut self, a: &Binders<T>, b: &Binders<T>) -> Fallible<()>
     where
         T: Zip + Fold<Result = T>,
@@ -101,6 +109,16 @@ impl<T: Zip> Zip for Box<T> {
     }
 }
 
+impl<T: Zip> Zip for Option<T> {
+    fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
+        match (a, b) {
+            (Some(a), Some(b)) => Zip::zip_with(zipper, a, b),
+            (None, None) => Ok(()),
+            (Some(_), None) | (None, Some(_)) => Err(NoSolution),
+        }
+    }
+}
+
 impl<T: Zip, U: Zip> Zip for (T, U) {
     fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
         Zip::zip_with(zipper, &a.0, &b.0)?;
@@ -121,6 +139,12 @@ impl Zip for Lifetime {
     }
 }
 
+impl Zip for Const {
+    fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
+        zipper.zip_consts(a, b)
+    }
+}
+
 impl<T: Zip + Fold<Result = T>> Zip for Binders<T> {
     fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
         zipper.zip_binders(a, b)
@@ -146,6 +170,7 @@ eq_zip!(ItemId);
 eq_zip!(TypeName);
 eq_zip!(Identifier);
 eq_zip!(QuantifierKind);
+eq_zip!(());
 
 macro_rules! struct_zip {
     ($t:ident$([$($param:tt)*])* { $($field:ident),* $(,)* } $($w:tt)*) => {
@@ -173,6 +198,15 @@ struct_zip!(ProjectionTy {
     associated_ty_id,
     parameters,
 });
+struct_zip!(DynTy { bounds, lifetime });
+struct_zip!(OpaqueTy {
+    opaque_ty_id,
+    parameters,
+});
+struct_zip!(DynBound {
+    trait_id,
+    parameters,
+});
 struct_zip!(UnselectedProjectionTy {
     type_name,
     parameters,
@@ -180,9 +214,20 @@ struct_zip!(UnselectedProjectionTy {
 struct_zip!(Normalize { projection, ty });
 struct_zip!(ProjectionEq { projection, ty });
 struct_zip!(UnselectedNormalize { projection, ty });
+struct_zip!(ConstProjection {
+    associated_const_id,
+    parameters,
+});
+struct_zip!(NormalizeConst { projection, value });
+struct_zip!(OpaqueEq { opaque, ty });
 struct_zip!(EqGoal { a, b });
 struct_zip!(ProgramClauseImplication { consequence, conditions });
 struct_zip!(Derefs { source, target });
+struct_zip!(Outlives { a, b });
+struct_zip!(PredicateApplication {
+    predicate_id,
+    parameters,
+});
 
 impl Zip for Environment {
     fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
@@ -223,10 +268,17 @@ enum_zip!(DomainGoal {
     FromEnv,
     Normalize,
     UnselectedNormalize,
+    NormalizeConst,
     WellFormedTy,
     FromEnvTy,
     InScope,
-    Derefs
+    Derefs,
+    Outlives,
+    NotImplemented,
+    Predicate,
+    OpaqueEq,
+    Reveal,
+    IsFullyVisible
 });
 enum_zip!(LeafGoal { DomainGoal, EqGoal });
 enum_zip!(ProgramClause { Implies, ForAll });
@@ -265,14 +317,19 @@ impl Zip for Goal {
 }
 
 // I'm too lazy to make `enum_zip` support type parameters.
-impl<T: Zip, L: Zip> Zip for ParameterKind<T, L> {
+impl<T: Zip, L: Zip, C: Zip> Zip for ParameterKind<T, L, C> {
     fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
         match (a, b) {
             (&ParameterKind::Ty(ref a), &ParameterKind::Ty(ref b)) => Zip::zip_with(zipper, a, b),
             (&ParameterKind::Lifetime(ref a), &ParameterKind::Lifetime(ref b)) => {
                 Zip::zip_with(zipper, a, b)
             }
-            (&ParameterKind::Ty(_), _) | (&ParameterKind::Lifetime(_), _) => {
+            (&ParameterKind::Const(ref a), &ParameterKind::Const(ref b)) => {
+                Zip::zip_with(zipper, a, b)
+            }
+            (&ParameterKind::Ty(_), _)
+            | (&ParameterKind::Lifetime(_), _)
+            | (&ParameterKind::Const(_), _) => {
                 panic!("zipping things of mixed kind")
             }
         }